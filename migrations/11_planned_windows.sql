@@ -0,0 +1,19 @@
+create table public.planned_windows
+(
+    id            bigserial primary key,
+    created_at    timestamptz not null default now(),
+    source        varchar     not null,
+    device_id     bigint,
+    starts_at     timestamptz not null,
+    ends_at       timestamptz not null,
+    average_price varchar     not null,
+    currency      varchar     not null,
+    inputs        jsonb       not null,
+    foreign key (device_id) references devices (id) on delete set null
+);
+
+-- the same window handed out for the same inputs is only stored once
+create unique index planned_windows_unique_idx
+    on public.planned_windows (source, coalesce(device_id, 0), starts_at, ends_at, md5(inputs::text));
+
+create index planned_windows_starts_at_idx on public.planned_windows (starts_at);
@@ -0,0 +1,19 @@
+create table public.notification_rules
+(
+    id           bigserial primary key,
+    event        varchar not null,
+    device_id    bigint,
+    lead_minutes integer not null default 0,
+    enabled      boolean not null default true,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table public.sent_notifications
+(
+    rule_id   bigint      not null,
+    device_id bigint      not null,
+    starts_at timestamptz not null,
+    sent_at   timestamptz not null default now(),
+    primary key (rule_id, device_id, starts_at),
+    foreign key (rule_id) references notification_rules (id) on delete cascade
+);
@@ -0,0 +1,12 @@
+create table public.webhooks
+(
+    id     bigserial primary key,
+    url    varchar   not null,
+    events varchar[] not null
+);
+
+create table public.webhook_events
+(
+    key           varchar primary key,
+    dispatched_at timestamptz not null default now()
+);
@@ -0,0 +1,8 @@
+alter table public.webhooks
+    add column secret varchar;
+
+update public.webhooks
+set secret = encode(sha256(gen_random_uuid()::text::bytea), 'hex');
+
+alter table public.webhooks
+    alter column secret set not null;
@@ -0,0 +1,28 @@
+create table public.webhook_deliveries
+(
+    id              bigserial primary key,
+    webhook_id      bigint,
+    url             varchar     not null,
+    event           varchar     not null,
+    payload         jsonb       not null,
+    status          varchar     not null default 'pending',
+    attempts        integer     not null default 0,
+    next_attempt_at timestamptz not null default now(),
+    last_error      varchar,
+    created_at      timestamptz not null default now(),
+    delivered_at    timestamptz,
+    foreign key (webhook_id) references webhooks (id) on delete cascade
+);
+
+create index webhook_deliveries_due_idx on public.webhook_deliveries (next_attempt_at) where status = 'pending';
+
+create table public.webhook_delivery_attempts
+(
+    delivery_id  bigint      not null,
+    attempted_at timestamptz not null default now(),
+    status_code  integer,
+    error        varchar,
+    foreign key (delivery_id) references webhook_deliveries (id) on delete cascade
+);
+
+create index webhook_delivery_attempts_delivery_id_idx on public.webhook_delivery_attempts (delivery_id);
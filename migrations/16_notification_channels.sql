@@ -0,0 +1,12 @@
+alter table public.notification_rules
+    add column channel   varchar not null default 'log',
+    add column hour      integer,
+    add column threshold double precision;
+
+-- daily summaries and price spikes are not about a device
+alter table public.sent_notifications
+    drop constraint sent_notifications_pkey,
+    alter column device_id drop not null;
+
+create unique index sent_notifications_key_idx
+    on public.sent_notifications (rule_id, coalesce(device_id, 0), starts_at);
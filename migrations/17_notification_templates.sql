@@ -0,0 +1,2 @@
+alter table public.notification_rules
+    add column template varchar;
@@ -0,0 +1,4 @@
+alter table public.notification_rules
+    add column level    varchar,
+    add column duration integer,
+    add column schedule varchar;
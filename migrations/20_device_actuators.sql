@@ -0,0 +1,21 @@
+alter table public.devices
+    add column actuator         varchar,
+    add column actuator_url     varchar,
+    add column actuator_channel integer;
+
+create table public.actuations
+(
+    device_id       bigint                   not null,
+    schedule_id     bigint                   not null,
+    date            date                     not null,
+    starts_at       timestamp with time zone not null,
+    ends_at         timestamp with time zone not null,
+    switched_on_at  timestamp with time zone,
+    switched_off_at timestamp with time zone,
+    error           varchar,
+    primary key (device_id, starts_at),
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
+
+create index actuations_ends_at_idx on public.actuations (ends_at);
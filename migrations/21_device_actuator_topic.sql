@@ -0,0 +1,2 @@
+alter table public.devices
+    add column actuator_topic varchar;
@@ -0,0 +1,12 @@
+create table public.calendar_events
+(
+    event_id    varchar primary key,
+    device_id   bigint                   not null,
+    schedule_id bigint                   not null,
+    date        date                     not null,
+    starts_at   timestamp with time zone not null,
+    ends_at     timestamp with time zone not null,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create index calendar_events_ends_at_idx on public.calendar_events (ends_at);
@@ -0,0 +1,10 @@
+create table public.carbon_intensity
+(
+    moment    timestamp with time zone not null,
+    zone      varchar                  not null,
+    intensity double precision         not null,
+    forecast  boolean                  not null,
+    primary key (moment, zone)
+);
+
+select create_hypertable('carbon_intensity', by_range('moment'));
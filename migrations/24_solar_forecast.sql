@@ -0,0 +1,7 @@
+create table public.solar_forecast
+(
+    moment     timestamp with time zone not null primary key,
+    energy_kwh double precision         not null
+);
+
+select create_hypertable('solar_forecast', by_range('moment'));
@@ -0,0 +1,11 @@
+create table public.renewable_generation
+(
+    moment   timestamp with time zone not null,
+    zone     varchar                  not null,
+    solar_mw double precision         not null,
+    wind_mw  double precision         not null,
+    load_mw  double precision         not null,
+    primary key (moment, zone)
+);
+
+select create_hypertable('renewable_generation', by_range('moment'));
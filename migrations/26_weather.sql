@@ -0,0 +1,9 @@
+create table public.weather
+(
+    moment              timestamp with time zone not null primary key,
+    temperature_celsius double precision         not null,
+    irradiance          double precision         not null,
+    tilted_irradiance   double precision
+);
+
+select create_hypertable('weather', by_range('moment'));
@@ -0,0 +1,8 @@
+create table public.consumption
+(
+    starts_at  timestamp with time zone not null primary key,
+    ends_at    timestamp with time zone not null,
+    energy_kwh double precision         not null
+);
+
+select create_hypertable('consumption', by_range('starts_at'));
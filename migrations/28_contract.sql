@@ -0,0 +1,10 @@
+create table public.contract
+(
+    -- there is only ever a single contract
+    id                      boolean                  not null primary key default true check (id),
+    standing_charge_per_day double precision         not null,
+    fixed_fee_per_month     double precision         not null,
+    tax_credit_per_month    double precision         not null,
+    feed_in_compensation    double precision,
+    updated_at              timestamp with time zone not null default now()
+);
@@ -0,0 +1,2 @@
+alter table public.consumption
+    add column exported_kwh double precision not null default 0;
@@ -0,0 +1,10 @@
+alter table public.providers
+    add column currency varchar(3) not null default 'EUR';
+
+create table public.exchange_rates
+(
+    date     date             not null,
+    currency varchar(3)       not null,
+    rate     double precision not null,
+    primary key (date, currency)
+);
@@ -0,0 +1,11 @@
+create table public.device_consumption
+(
+    device_id  bigint                   not null,
+    starts_at  timestamp with time zone not null,
+    ends_at    timestamp with time zone not null,
+    energy_kwh double precision         not null,
+    primary key (device_id, starts_at),
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create index device_consumption_starts_at_idx on device_consumption (starts_at);
@@ -0,0 +1,21 @@
+-- prices without a provider are never read, as every read joins them to their provider, and
+-- a unique index on the provider would not deduplicate them as nulls are distinct
+delete
+from prices
+where provider_id is null;
+
+alter table public.prices
+    alter column provider_id set not null;
+
+-- installs that stored prices before a price was unique per moment of a provider can hold several
+-- rows of a price. Prices do not record when they were written, so which row is the latest cannot
+-- be told, and the row with the highest price is kept instead, so that planning errs on the side
+-- of a price that is too high. Of rows with the same highest price, which one is kept is
+-- arbitrary.
+delete
+from prices dropped
+    using prices kept
+where dropped.moment = kept.moment
+  and dropped.provider_id = kept.provider_id
+  and (dropped.price < kept.price
+    or (dropped.price = kept.price and dropped.ctid < kept.ctid));
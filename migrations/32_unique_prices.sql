@@ -0,0 +1,3 @@
+-- a price is stored once for every moment of a provider, so fetching a day again replaces its
+-- prices rather than storing them twice
+create unique index prices_moment_provider_id_idx on prices (moment, provider_id);
@@ -0,0 +1,6 @@
+-- prices are partitioned by month, so retention drops whole chunks rather than deleting rows and
+-- queries of a date range only scan the months it covers. The partitions are the chunks of the
+-- TimescaleDB hypertable, of which only the chunks that are created from now on span a month:
+-- chunks that exist already keep the interval they were created with, seven days by default,
+-- until retention drops them.
+select set_chunk_time_interval('prices', interval '1 month');
@@ -0,0 +1,15 @@
+-- every price records what it is expressed in, so prices of providers that publish in another
+-- currency, per MWh or per quarter hour are never compared as if they were alike
+alter table public.prices
+    add column currency           varchar(3),
+    add column unit               varchar not null default 'kWh',
+    add column resolution_minutes integer not null default 60;
+
+-- the prices stored before were in the currency of their provider
+update prices
+set currency = providers.currency
+from providers
+where providers.id = prices.provider_id;
+
+alter table public.prices
+    alter column currency set not null;
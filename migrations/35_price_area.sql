@@ -0,0 +1,8 @@
+-- the prices of every bidding zone are kept apart, of which the area is part of the key of a
+-- price. Prices of which the area is not known have an empty area.
+alter table public.prices
+    add column area varchar not null default '';
+
+drop index prices_moment_provider_id_idx;
+
+create unique index prices_moment_provider_id_area_idx on prices (moment, provider_id, area);
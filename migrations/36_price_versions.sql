@@ -0,0 +1,33 @@
+-- providers occasionally republish corrected prices. The prices table holds the latest version of
+-- every price, which is what is read, and every version that was ingested is kept in
+-- price_versions to look back on what was corrected.
+alter table public.prices
+    add column version     integer                  not null default 1,
+    add column ingested_at timestamp with time zone not null default now();
+
+create table public.price_versions
+(
+    moment             timestamp with time zone not null,
+    provider_id        bigint                   not null,
+    area               varchar                  not null,
+    version            integer                  not null,
+    price              double precision         not null,
+    consumer_price     double precision,
+    supplier_fee       double precision,
+    energy_tax         double precision,
+    grid_fee           double precision,
+    vat                double precision,
+    currency           varchar(3)               not null,
+    unit               varchar                  not null,
+    resolution_minutes integer                  not null,
+    ingested_at        timestamp with time zone not null,
+    primary key (moment, provider_id, area, version),
+    foreign key (provider_id) references providers (id)
+);
+
+insert into price_versions (moment, provider_id, area, version, price, consumer_price,
+                            supplier_fee, energy_tax, grid_fee, vat, currency, unit,
+                            resolution_minutes, ingested_at)
+select moment, provider_id, area, version, price, consumer_price, supplier_fee, energy_tax,
+       grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+from prices;
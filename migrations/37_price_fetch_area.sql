@@ -0,0 +1,4 @@
+-- the fetches of every bidding zone are kept apart, now that the prices of several areas can
+-- be fetched. Fetches of which the area is not known have an empty area.
+alter table price_fetches
+    add column area varchar not null default '';
@@ -0,0 +1,66 @@
+-- prices are partitioned by calendar month with the native range partitioning of Postgres, so
+-- retention drops the partitions of months that ended rather than deleting rows and queries of a
+-- date range only scan the months it covers. The partitions replace the chunks of the TimescaleDB
+-- hypertable, which span 30 days rather than a month. Months are those of UTC.
+
+-- the partition of the month a moment falls in, created unless it exists. The partitions of the
+-- coming months are created ahead by the price partition job, those of other months before their
+-- prices are stored.
+create function create_price_partition(moment timestamp with time zone) returns void
+    language plpgsql as
+$$
+declare
+    month     timestamp := date_trunc('month', moment at time zone 'UTC');
+    partition text      := 'prices_' || to_char(month, 'YYYY_MM');
+begin
+    if to_regclass(partition) is null then
+        execute format('create table %I partition of prices for values from (%L) to (%L)',
+                       partition, month at time zone 'UTC',
+                       (month + interval '1 month') at time zone 'UTC');
+    end if;
+end
+$$;
+
+-- drop the partitions of the months that ended at or before a moment
+create function drop_price_partitions(before timestamp with time zone) returns void
+    language plpgsql as
+$$
+declare
+    partition regclass;
+begin
+    for partition in
+        select child.oid::regclass
+        from pg_inherits
+                 join pg_class child on child.oid = pg_inherits.inhrelid
+        where pg_inherits.inhparent = 'prices'::regclass
+          and (to_date(right(child.relname, 7), 'YYYY_MM') + interval '1 month') at time zone 'UTC' <= before
+        loop
+            execute format('drop table %s', partition);
+        end loop;
+end
+$$;
+
+alter table public.prices
+    rename to prices_hypertable;
+
+create table public.prices
+(
+    like prices_hypertable including defaults,
+    constraint prices_provider_id_fkey foreign key (provider_id) references providers (id)
+) partition by range (moment);
+
+select create_price_partition(month)
+from (select distinct date_trunc('month', moment, 'UTC') as month
+      from prices_hypertable
+      union
+      select date_trunc('month', now(), 'UTC')
+      union
+      select date_trunc('month', now(), 'UTC') + interval '1 month') as months;
+
+insert into prices
+select *
+from prices_hypertable;
+
+drop table prices_hypertable;
+
+create unique index prices_moment_provider_id_area_idx on prices (moment, provider_id, area);
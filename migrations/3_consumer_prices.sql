@@ -0,0 +1,2 @@
+alter table public.prices
+    add column consumer_price double precision;
@@ -0,0 +1,5 @@
+alter table public.prices
+    add column supplier_fee double precision,
+    add column energy_tax   double precision,
+    add column grid_fee     double precision,
+    add column vat          double precision;
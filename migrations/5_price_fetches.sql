@@ -0,0 +1,11 @@
+create table public.price_fetches
+(
+    id           bigserial primary key,
+    provider_id  bigint                   not null,
+    date         date                     not null,
+    attempted_at timestamp with time zone not null,
+    attempts     integer                  not null,
+    prices       integer                  not null,
+    error        varchar,
+    foreign key (provider_id) references providers (id)
+);
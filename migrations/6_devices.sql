@@ -0,0 +1,19 @@
+create table public.devices
+(
+    id       bigserial primary key,
+    name     varchar not null unique,
+    duration integer not null,
+    power_kw double precision,
+    strategy varchar not null default 'contiguous'
+);
+
+create table public.schedules
+(
+    id                  bigserial primary key,
+    device_id           bigint  not null,
+    recurrence          varchar not null default 'daily',
+    available_from_hour integer not null default 0,
+    finish_by_hour      integer not null default 24,
+    enabled             boolean not null default true,
+    foreign key (device_id) references devices (id) on delete cascade
+);
@@ -0,0 +1,10 @@
+create table public.schedule_overrides
+(
+    schedule_id         bigint  not null,
+    date                date    not null,
+    skip                boolean not null default false,
+    available_from_hour integer,
+    finish_by_hour      integer,
+    primary key (schedule_id, date),
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
@@ -0,0 +1,13 @@
+create table public.device_runs
+(
+    id          bigserial primary key,
+    device_id   bigint                   not null,
+    schedule_id bigint,
+    date        date,
+    started_at  timestamp with time zone not null,
+    finished_at timestamp with time zone,
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete set null
+);
+
+create index device_runs_device_id_started_at_idx on device_runs (device_id, started_at);
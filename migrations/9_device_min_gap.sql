@@ -0,0 +1,2 @@
+alter table public.devices
+    add column min_gap_hours integer;
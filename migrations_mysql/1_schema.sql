@@ -0,0 +1,277 @@
+-- the schema of the Postgres migrations up to 30_device_consumption.sql, of which moments are
+-- stored as UTC datetimes and arrays as json
+
+create table providers
+(
+    id       bigint primary key auto_increment,
+    name     varchar(255) not null,
+    currency varchar(3)   not null default 'EUR'
+);
+
+insert into providers (name)
+values ('tibber');
+
+create table prices
+(
+    moment         datetime(6) not null,
+    price          double      not null,
+    provider_id    bigint      not null,
+    consumer_price double,
+    supplier_fee   double,
+    energy_tax     double,
+    grid_fee       double,
+    vat            double,
+    unique key prices_moment_provider_id_idx (moment, provider_id),
+    foreign key (provider_id) references providers (id)
+);
+
+create table exchange_rates
+(
+    date     date       not null,
+    currency varchar(3) not null,
+    rate     double     not null,
+    primary key (date, currency)
+);
+
+create table price_fetches
+(
+    id           bigint primary key auto_increment,
+    provider_id  bigint      not null,
+    date         date        not null,
+    attempted_at datetime(6) not null,
+    attempts     int         not null,
+    prices       int         not null,
+    error        text,
+    foreign key (provider_id) references providers (id)
+);
+
+create table devices
+(
+    id               bigint primary key auto_increment,
+    name             varchar(255) not null unique,
+    duration         int          not null,
+    power_kw         double,
+    strategy         varchar(255) not null default 'contiguous',
+    min_gap_hours    int,
+    snoozed_until    datetime(6),
+    actuator         varchar(255),
+    actuator_url     text,
+    actuator_channel int,
+    actuator_topic   text
+);
+
+create table schedules
+(
+    id                  bigint primary key auto_increment,
+    device_id           bigint       not null,
+    recurrence          varchar(255) not null default 'daily',
+    available_from_hour int          not null default 0,
+    finish_by_hour      int          not null default 24,
+    enabled             boolean      not null default true,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table schedule_overrides
+(
+    schedule_id         bigint  not null,
+    date                date    not null,
+    skip                boolean not null default false,
+    available_from_hour int,
+    finish_by_hour      int,
+    primary key (schedule_id, date),
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
+
+create table device_runs
+(
+    id          bigint primary key auto_increment,
+    device_id   bigint      not null,
+    schedule_id bigint,
+    date        date,
+    started_at  datetime(6) not null,
+    finished_at datetime(6),
+    key device_runs_device_id_started_at_idx (device_id, started_at),
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete set null
+);
+
+-- the device is not a foreign key, as a column that a unique key is generated from cannot be
+-- set to null when the device is deleted
+create table planned_windows
+(
+    id            bigint primary key auto_increment,
+    created_at    datetime(6)  not null default current_timestamp(6),
+    source        varchar(64)  not null,
+    device_id     bigint,
+    starts_at     datetime(6)  not null,
+    ends_at       datetime(6)  not null,
+    average_price varchar(255) not null,
+    currency      varchar(255) not null,
+    inputs        json         not null,
+    device_key    bigint as (coalesce(device_id, 0)) stored,
+    inputs_hash   char(64) as (sha2(inputs, 256)) stored,
+    -- the same window handed out for the same inputs is only stored once
+    unique key planned_windows_unique_idx (source, device_key, starts_at, ends_at, inputs_hash),
+    key planned_windows_starts_at_idx (starts_at)
+);
+
+create table notification_rules
+(
+    id           bigint primary key auto_increment,
+    event        varchar(255) not null,
+    device_id    bigint,
+    lead_minutes int          not null default 0,
+    enabled      boolean      not null default true,
+    channel      varchar(255) not null default 'log',
+    hour         int,
+    threshold    double,
+    template     text,
+    level        varchar(255),
+    duration     int,
+    schedule     varchar(255),
+    durations    json,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table sent_notifications
+(
+    rule_id    bigint      not null,
+    device_id  bigint,
+    starts_at  datetime(6) not null,
+    sent_at    datetime(6) not null default current_timestamp(6),
+    device_key bigint as (coalesce(device_id, 0)) stored,
+    unique key sent_notifications_key_idx (rule_id, device_key, starts_at),
+    foreign key (rule_id) references notification_rules (id) on delete cascade
+);
+
+create table webhooks
+(
+    id     bigint primary key auto_increment,
+    url    text         not null,
+    events json         not null,
+    secret varchar(255) not null
+);
+
+create table webhook_events
+(
+    `key`         varchar(255) primary key,
+    dispatched_at datetime(6) not null default current_timestamp(6)
+);
+
+create table webhook_deliveries
+(
+    id              bigint primary key auto_increment,
+    webhook_id      bigint,
+    url             text         not null,
+    event           varchar(255) not null,
+    payload         json         not null,
+    status          varchar(16)  not null default 'pending',
+    attempts        int          not null default 0,
+    next_attempt_at datetime(6)  not null default current_timestamp(6),
+    last_error      text,
+    created_at      datetime(6)  not null default current_timestamp(6),
+    delivered_at    datetime(6),
+    key webhook_deliveries_due_idx (status, next_attempt_at),
+    foreign key (webhook_id) references webhooks (id) on delete cascade
+);
+
+create table webhook_delivery_attempts
+(
+    delivery_id  bigint      not null,
+    attempted_at datetime(6) not null,
+    status_code  int,
+    error        text,
+    key webhook_delivery_attempts_delivery_id_idx (delivery_id),
+    foreign key (delivery_id) references webhook_deliveries (id) on delete cascade
+);
+
+create table actuations
+(
+    device_id       bigint      not null,
+    schedule_id     bigint      not null,
+    date            date        not null,
+    starts_at       datetime(6) not null,
+    ends_at         datetime(6) not null,
+    switched_on_at  datetime(6),
+    switched_off_at datetime(6),
+    error           text,
+    primary key (device_id, starts_at),
+    key actuations_ends_at_idx (ends_at),
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
+
+create table calendar_events
+(
+    event_id    varchar(255) primary key,
+    device_id   bigint      not null,
+    schedule_id bigint      not null,
+    date        date        not null,
+    starts_at   datetime(6) not null,
+    ends_at     datetime(6) not null,
+    key calendar_events_ends_at_idx (ends_at),
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table carbon_intensity
+(
+    moment    datetime(6)  not null,
+    zone      varchar(64)  not null,
+    intensity double       not null,
+    forecast  boolean      not null,
+    primary key (moment, zone)
+);
+
+create table solar_forecast
+(
+    moment     datetime(6) not null primary key,
+    energy_kwh double      not null
+);
+
+create table renewable_generation
+(
+    moment   datetime(6) not null,
+    zone     varchar(64) not null,
+    solar_mw double      not null,
+    wind_mw  double      not null,
+    load_mw  double      not null,
+    primary key (moment, zone)
+);
+
+create table weather
+(
+    moment              datetime(6) not null primary key,
+    temperature_celsius double      not null,
+    irradiance          double      not null,
+    tilted_irradiance   double
+);
+
+create table consumption
+(
+    starts_at    datetime(6) not null primary key,
+    ends_at      datetime(6) not null,
+    energy_kwh   double      not null,
+    exported_kwh double      not null default 0
+);
+
+create table contract
+(
+    -- there is only ever a single contract
+    id                      tinyint     not null primary key default 1 check (id = 1),
+    standing_charge_per_day double      not null,
+    fixed_fee_per_month     double      not null,
+    tax_credit_per_month    double      not null,
+    feed_in_compensation    double,
+    updated_at              datetime(6) not null default current_timestamp(6)
+);
+
+create table device_consumption
+(
+    device_id  bigint      not null,
+    starts_at  datetime(6) not null,
+    ends_at    datetime(6) not null,
+    energy_kwh double      not null,
+    primary key (device_id, starts_at),
+    key device_consumption_starts_at_idx (starts_at),
+    foreign key (device_id) references devices (id) on delete cascade
+);
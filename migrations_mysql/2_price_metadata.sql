@@ -0,0 +1,10 @@
+-- every price records what it is expressed in, see 33_price_metadata.sql of the Postgres
+-- migrations
+alter table prices
+    add column currency           varchar(3) not null default 'EUR',
+    add column unit               varchar(3) not null default 'kWh',
+    add column resolution_minutes int        not null default 60;
+
+update prices
+    join providers on providers.id = prices.provider_id
+set prices.currency = providers.currency;
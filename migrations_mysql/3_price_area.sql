@@ -0,0 +1,6 @@
+-- the prices of every bidding zone are kept apart, see 34_price_area.sql of the Postgres
+-- migrations
+alter table prices
+    add column area varchar(64) not null default '',
+    drop index prices_moment_provider_id_idx,
+    add unique key prices_moment_provider_id_area_idx (moment, provider_id, area);
@@ -0,0 +1,32 @@
+-- every version of a price that was ingested is kept, see 35_price_versions.sql of the Postgres
+-- migrations
+alter table prices
+    add column version     int         not null default 1,
+    add column ingested_at datetime(6) not null default current_timestamp(6);
+
+create table price_versions
+(
+    moment             datetime(6) not null,
+    provider_id        bigint      not null,
+    area               varchar(64) not null,
+    version            int         not null,
+    price              double      not null,
+    consumer_price     double,
+    supplier_fee       double,
+    energy_tax         double,
+    grid_fee           double,
+    vat                double,
+    currency           varchar(3)  not null,
+    unit               varchar(3)  not null,
+    resolution_minutes int         not null,
+    ingested_at        datetime(6) not null,
+    primary key (moment, provider_id, area, version),
+    foreign key (provider_id) references providers (id)
+);
+
+insert into price_versions (moment, provider_id, area, version, price, consumer_price,
+                            supplier_fee, energy_tax, grid_fee, vat, currency, unit,
+                            resolution_minutes, ingested_at)
+select moment, provider_id, area, version, price, consumer_price, supplier_fee, energy_tax,
+       grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+from prices;
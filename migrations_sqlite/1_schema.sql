@@ -0,0 +1,285 @@
+-- the schema of the Postgres migrations up to 30_device_consumption.sql, of which moments are
+-- stored as RFC 3339 text, arrays and json as json text and booleans as integers
+
+create table providers
+(
+    id       integer primary key autoincrement,
+    name     varchar    not null,
+    currency varchar(3) not null default 'EUR'
+);
+
+insert into providers (name)
+values ('tibber');
+
+create table prices
+(
+    moment         text    not null,
+    price          real    not null,
+    provider_id    integer not null,
+    consumer_price real,
+    supplier_fee   real,
+    energy_tax     real,
+    grid_fee       real,
+    vat            real,
+    foreign key (provider_id) references providers (id)
+);
+
+create unique index prices_moment_provider_id_idx on prices (moment, provider_id);
+
+create table exchange_rates
+(
+    date     text       not null,
+    currency varchar(3) not null,
+    rate     real       not null,
+    primary key (date, currency)
+);
+
+create table price_fetches
+(
+    id           integer primary key autoincrement,
+    provider_id  integer not null,
+    date         text    not null,
+    attempted_at text    not null,
+    attempts     integer not null,
+    prices       integer not null,
+    error        varchar,
+    foreign key (provider_id) references providers (id)
+);
+
+create table devices
+(
+    id               integer primary key autoincrement,
+    name             varchar not null unique,
+    duration         integer not null,
+    power_kw         real,
+    strategy         varchar not null default 'contiguous',
+    min_gap_hours    integer,
+    snoozed_until    text,
+    actuator         varchar,
+    actuator_url     varchar,
+    actuator_channel integer,
+    actuator_topic   varchar
+);
+
+create table schedules
+(
+    id                  integer primary key autoincrement,
+    device_id           integer not null,
+    recurrence          varchar not null default 'daily',
+    available_from_hour integer not null default 0,
+    finish_by_hour      integer not null default 24,
+    enabled             boolean not null default true,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table schedule_overrides
+(
+    schedule_id         integer not null,
+    date                text    not null,
+    skip                boolean not null default false,
+    available_from_hour integer,
+    finish_by_hour      integer,
+    primary key (schedule_id, date),
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
+
+create table device_runs
+(
+    id          integer primary key autoincrement,
+    device_id   integer not null,
+    schedule_id integer,
+    date        text,
+    started_at  text    not null,
+    finished_at text,
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete set null
+);
+
+create index device_runs_device_id_started_at_idx on device_runs (device_id, started_at);
+
+create table planned_windows
+(
+    id            integer primary key autoincrement,
+    created_at    text    not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    source        varchar not null,
+    device_id     integer,
+    starts_at     text    not null,
+    ends_at       text    not null,
+    average_price varchar not null,
+    currency      varchar not null,
+    inputs        text    not null,
+    foreign key (device_id) references devices (id) on delete set null
+);
+
+-- the same window handed out for the same inputs is only stored once
+create unique index planned_windows_unique_idx
+    on planned_windows (source, coalesce(device_id, 0), starts_at, ends_at, inputs);
+
+create index planned_windows_starts_at_idx on planned_windows (starts_at);
+
+create table notification_rules
+(
+    id           integer primary key autoincrement,
+    event        varchar not null,
+    device_id    integer,
+    lead_minutes integer not null default 0,
+    enabled      boolean not null default true,
+    channel      varchar not null default 'log',
+    hour         integer,
+    threshold    real,
+    template     varchar,
+    level        varchar,
+    duration     integer,
+    schedule     varchar,
+    durations    text,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create table sent_notifications
+(
+    rule_id   integer not null,
+    device_id integer,
+    starts_at text    not null,
+    sent_at   text    not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    foreign key (rule_id) references notification_rules (id) on delete cascade
+);
+
+create unique index sent_notifications_key_idx
+    on sent_notifications (rule_id, coalesce(device_id, 0), starts_at);
+
+create table webhooks
+(
+    id     integer primary key autoincrement,
+    url    varchar not null,
+    events text    not null,
+    secret varchar not null
+);
+
+create table webhook_events
+(
+    key           varchar primary key,
+    dispatched_at text not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+create table webhook_deliveries
+(
+    id              integer primary key autoincrement,
+    webhook_id      integer,
+    url             varchar not null,
+    event           varchar not null,
+    payload         text    not null,
+    status          varchar not null default 'pending',
+    attempts        integer not null default 0,
+    next_attempt_at text    not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    last_error      varchar,
+    created_at      text    not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')),
+    delivered_at    text,
+    foreign key (webhook_id) references webhooks (id) on delete cascade
+);
+
+create index webhook_deliveries_due_idx on webhook_deliveries (next_attempt_at) where status = 'pending';
+
+create table webhook_delivery_attempts
+(
+    delivery_id  integer not null,
+    attempted_at text    not null,
+    status_code  integer,
+    error        varchar,
+    foreign key (delivery_id) references webhook_deliveries (id) on delete cascade
+);
+
+create index webhook_delivery_attempts_delivery_id_idx on webhook_delivery_attempts (delivery_id);
+
+create table actuations
+(
+    device_id       integer not null,
+    schedule_id     integer not null,
+    date            text    not null,
+    starts_at       text    not null,
+    ends_at         text    not null,
+    switched_on_at  text,
+    switched_off_at text,
+    error           varchar,
+    primary key (device_id, starts_at),
+    foreign key (device_id) references devices (id) on delete cascade,
+    foreign key (schedule_id) references schedules (id) on delete cascade
+);
+
+create index actuations_ends_at_idx on actuations (ends_at);
+
+create table calendar_events
+(
+    event_id    varchar primary key,
+    device_id   integer not null,
+    schedule_id integer not null,
+    date        text    not null,
+    starts_at   text    not null,
+    ends_at     text    not null,
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create index calendar_events_ends_at_idx on calendar_events (ends_at);
+
+create table carbon_intensity
+(
+    moment    text    not null,
+    zone      varchar not null,
+    intensity real    not null,
+    forecast  boolean not null,
+    primary key (moment, zone)
+);
+
+create table solar_forecast
+(
+    moment     text not null primary key,
+    energy_kwh real not null
+);
+
+create table renewable_generation
+(
+    moment   text    not null,
+    zone     varchar not null,
+    solar_mw real    not null,
+    wind_mw  real    not null,
+    load_mw  real    not null,
+    primary key (moment, zone)
+);
+
+create table weather
+(
+    moment              text not null primary key,
+    temperature_celsius real not null,
+    irradiance          real not null,
+    tilted_irradiance   real
+);
+
+create table consumption
+(
+    starts_at    text not null primary key,
+    ends_at      text not null,
+    energy_kwh   real not null,
+    exported_kwh real not null default 0
+);
+
+create table contract
+(
+    -- there is only ever a single contract
+    id                      integer not null primary key default 1 check (id = 1),
+    standing_charge_per_day real    not null,
+    fixed_fee_per_month     real    not null,
+    tax_credit_per_month    real    not null,
+    feed_in_compensation    real,
+    updated_at              text    not null default (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'))
+);
+
+create table device_consumption
+(
+    device_id  integer not null,
+    starts_at  text    not null,
+    ends_at    text    not null,
+    energy_kwh real    not null,
+    primary key (device_id, starts_at),
+    foreign key (device_id) references devices (id) on delete cascade
+);
+
+create index device_consumption_starts_at_idx on device_consumption (starts_at);
@@ -0,0 +1,8 @@
+-- every price records what it is expressed in, see 33_price_metadata.sql of the Postgres
+-- migrations
+alter table prices add column currency varchar(3) not null default 'EUR';
+alter table prices add column unit varchar(3) not null default 'kWh';
+alter table prices add column resolution_minutes integer not null default 60;
+
+update prices
+set currency = (select currency from providers where providers.id = prices.provider_id);
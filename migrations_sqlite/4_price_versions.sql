@@ -0,0 +1,61 @@
+-- every version of a price that was ingested is kept, see 35_price_versions.sql of the Postgres
+-- migrations. The prices table is created again, as a column that is added cannot default to the
+-- current time.
+create table prices_with_versions
+(
+    moment             text    not null,
+    price              real    not null,
+    provider_id        integer not null,
+    consumer_price     real,
+    supplier_fee       real,
+    energy_tax         real,
+    grid_fee           real,
+    vat                real,
+    currency           varchar(3)  not null default 'EUR',
+    unit               varchar(3)  not null default 'kWh',
+    resolution_minutes integer     not null default 60,
+    area               varchar(64) not null default '',
+    version            integer     not null default 1,
+    ingested_at        text        not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    foreign key (provider_id) references providers (id)
+);
+
+insert into prices_with_versions (moment, price, provider_id, consumer_price, supplier_fee,
+                                  energy_tax, grid_fee, vat, currency, unit, resolution_minutes,
+                                  area)
+select moment, price, provider_id, consumer_price, supplier_fee, energy_tax, grid_fee, vat,
+       currency, unit, resolution_minutes, area
+from prices;
+
+drop table prices;
+
+alter table prices_with_versions rename to prices;
+
+create unique index prices_moment_provider_id_area_idx on prices (moment, provider_id, area);
+
+create table price_versions
+(
+    moment             text        not null,
+    provider_id        integer     not null,
+    area               varchar(64) not null,
+    version            integer     not null,
+    price              real        not null,
+    consumer_price     real,
+    supplier_fee       real,
+    energy_tax         real,
+    grid_fee           real,
+    vat                real,
+    currency           varchar(3)  not null,
+    unit               varchar(3)  not null,
+    resolution_minutes integer     not null,
+    ingested_at        text        not null,
+    primary key (moment, provider_id, area, version),
+    foreign key (provider_id) references providers (id)
+);
+
+insert into price_versions (moment, provider_id, area, version, price, consumer_price,
+                            supplier_fee, energy_tax, grid_fee, vat, currency, unit,
+                            resolution_minutes, ingested_at)
+select moment, provider_id, area, version, price, consumer_price, supplier_fee, energy_tax,
+       grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+from prices;
@@ -0,0 +1,3 @@
+-- the fetches of every bidding zone are kept apart, see 36_price_fetch_area.sql of the Postgres
+-- migrations
+alter table price_fetches add column area varchar(64) not null default '';
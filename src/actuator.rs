@@ -0,0 +1,408 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::Client;
+use thiserror::Error;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    chat::describe_error,
+    device::{Actuation, Device},
+    planner::{plan_all_devices, record_run_start},
+    setup::AppState,
+};
+
+/// How long a switch may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long after its first window started a run is still switched on, e.g. after a restart
+const WINDOW_START_GRACE: TimeDelta = TimeDelta::minutes(5);
+
+/// How long switching a device off is retried after its window ended
+const SWITCH_OFF_RETRY_PERIOD: TimeDelta = TimeDelta::days(1);
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum ActuatorError {
+    #[error("unknown actuator \"{0}\", expected shelly or tasmota")]
+    UnknownKind(String),
+    #[error("a shelly actuator needs an actuator_url")]
+    MissingUrl,
+    #[error("a tasmota actuator needs either an actuator_url or an actuator_topic")]
+    MissingAddress,
+    #[error("an actuator_topic is only supported by tasmota actuators")]
+    UnsupportedTopic,
+    #[error("the actuator_url \"{0}\" is not a valid url")]
+    InvalidUrl(String),
+    #[error("actuator_channel must be at least {0}")]
+    InvalidChannel(i32),
+}
+
+/// A switch on the local network that turns a device on and off
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Actuator {
+    /// A Shelly Gen2 device, switched through its RPC over HTTP
+    Shelly { url: Url, channel: i32 },
+    /// A Tasmota device, switched through its HTTP command interface
+    TasmotaHttp { url: Url, relay: i32 },
+    /// A Tasmota device, switched through its command topic on the MQTT broker
+    TasmotaMqtt { topic: String, relay: i32 },
+}
+
+impl Actuator {
+    pub(crate) fn new(
+        kind: &str,
+        url: Option<&str>,
+        topic: Option<&str>,
+        channel: Option<i32>,
+    ) -> Result<Self, ActuatorError> {
+        let url = url
+            .map(|url| Url::parse(url).map_err(|_| ActuatorError::InvalidUrl(url.to_string())))
+            .transpose()?;
+
+        // Shelly numbers its switches from 0, Tasmota its relays from 1
+        let first_channel = match kind {
+            "shelly" => 0,
+            "tasmota" => 1,
+            _ => return Err(ActuatorError::UnknownKind(kind.to_string())),
+        };
+        let channel = channel.unwrap_or(first_channel);
+
+        if channel < first_channel {
+            return Err(ActuatorError::InvalidChannel(first_channel));
+        }
+
+        match (kind, url, topic) {
+            ("shelly", _, Some(_)) => Err(ActuatorError::UnsupportedTopic),
+            ("shelly", Some(url), None) => Ok(Actuator::Shelly { url, channel }),
+            ("shelly", None, None) => Err(ActuatorError::MissingUrl),
+            (_, Some(url), None) => Ok(Actuator::TasmotaHttp {
+                url,
+                relay: channel,
+            }),
+            (_, None, Some(topic)) => Ok(Actuator::TasmotaMqtt {
+                topic: topic.to_string(),
+                relay: channel,
+            }),
+            _ => Err(ActuatorError::MissingAddress),
+        }
+    }
+
+    /// The actuator of a device, `None` when electrack does not switch it
+    pub(crate) fn of(device: &Device) -> Option<Result<Self, ActuatorError>> {
+        device.actuator.as_ref().map(|kind| {
+            Actuator::new(
+                kind,
+                device.actuator_url.as_deref(),
+                device.actuator_topic.as_deref(),
+                device.actuator_channel,
+            )
+        })
+    }
+
+    async fn switch(&self, state: &AppState, on: bool) -> Result<(), String> {
+        match self {
+            Actuator::Shelly { url, channel } => {
+                let response = get(
+                    url,
+                    &["rpc", "Switch.Set"],
+                    &[("id", channel.to_string()), ("on", on.to_string())],
+                )
+                .await?;
+
+                let status = response.status();
+
+                match status.is_success() {
+                    true => Ok(()),
+                    false => Err(format!(
+                        "the shelly responded {}, {}",
+                        status,
+                        response.text().await.unwrap_or_default()
+                    )),
+                }
+            }
+            Actuator::TasmotaHttp { url, relay } => {
+                let command = format!("Power{} {}", relay, if on { "On" } else { "Off" });
+                let response = get(url, &["cm"], &[("cmnd", command)]).await?;
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+
+                // failed commands are answered with a success status, without the power state
+                let switched = serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|body| body.as_object().cloned())
+                    .is_some_and(|body| body.keys().any(|key| key.starts_with("POWER")));
+
+                match status.is_success() && switched {
+                    true => Ok(()),
+                    false => Err(format!("the tasmota responded {}, {}", status, body)),
+                }
+            }
+            Actuator::TasmotaMqtt { topic, relay } => {
+                let Some(mqtt) = &state.mqtt else {
+                    return Err("MQTT is not configured".to_string());
+                };
+
+                mqtt.client
+                    .publish(
+                        &format!("cmnd/{}/POWER{}", topic, relay),
+                        if on { "ON" } else { "OFF" },
+                        false,
+                    )
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Call an endpoint of a switch on the local network
+async fn get(
+    url: &Url,
+    path: &[&str],
+    parameters: &[(&str, String)],
+) -> Result<reqwest::Response, String> {
+    let mut url = url.clone();
+
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().extend(path);
+    }
+
+    url.query_pairs_mut().clear().extend_pairs(parameters);
+
+    Client::new()
+        .get(url)
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .map_err(describe_error)
+}
+
+/// Switch the devices that have an actuator on and off at the boundaries of their planned
+/// windows. Once the first window of a run starts its windows are recorded, and the run is
+/// reported as started and finished as the device is switched.
+pub(crate) async fn actuate_devices(state: &AppState) -> Result<(), String> {
+    let now = Utc::now();
+
+    let devices = state
+        .device_repository
+        .fetch_devices()
+        .await?
+        .into_iter()
+        .filter(|device| device.actuator.is_some())
+        .collect::<Vec<Device>>();
+
+    if devices.is_empty() {
+        return Ok(());
+    }
+
+    record_started_runs(state, now).await?;
+
+    let actuations = state
+        .device_repository
+        .fetch_due_actuations(now - SWITCH_OFF_RETRY_PERIOD, now)
+        .await?;
+
+    for actuation in actuations {
+        let Some(device) = devices
+            .iter()
+            .find(|device| device.id == actuation.device_id)
+        else {
+            continue;
+        };
+
+        let actuator = match Actuator::of(device) {
+            Some(Ok(actuator)) => actuator,
+            Some(Err(e)) => {
+                warn!("unable to switch {}, {}", device.name, e);
+                continue;
+            }
+            None => continue,
+        };
+
+        switch(state, device, &actuator, &actuation, now).await?;
+    }
+
+    Ok(())
+}
+
+/// Record the windows of the planned runs of which the first window just started
+async fn record_started_runs(state: &AppState, now: DateTime<Utc>) -> Result<(), String> {
+    // plans start at the next whole hour, so a run that just started is planned before it
+    for (device, _, next_run) in plan_all_devices(state, now - WINDOW_START_GRACE).await? {
+        let Some(next_run) = next_run.filter(|_| device.actuator.is_some()) else {
+            continue;
+        };
+
+        let Some(starts_at) = next_run
+            .windows
+            .first()
+            .map(|window| window.starts_at.to_utc())
+        else {
+            continue;
+        };
+
+        if now < starts_at || starts_at + WINDOW_START_GRACE <= now {
+            continue;
+        }
+
+        // the rest of an occurrence that already started is planned again, which is ignored
+        if !state
+            .device_repository
+            .fetch_occurrence_actuations(device.id, next_run.schedule_id, next_run.date)
+            .await?
+            .is_empty()
+        {
+            continue;
+        }
+
+        let actuations = next_run
+            .windows
+            .iter()
+            .map(|window| Actuation {
+                device_id: device.id,
+                schedule_id: next_run.schedule_id,
+                date: next_run.date,
+                starts_at: window.starts_at.to_utc(),
+                ends_at: window.ends_at.to_utc(),
+                switched_on_at: None,
+                switched_off_at: None,
+                error: None,
+            })
+            .collect::<Vec<Actuation>>();
+
+        state
+            .device_repository
+            .persist_actuations(&actuations)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Switch a device on at the start of an actuation, or off once it ended, and report the run
+/// as started with the first window of its occurrence and finished with the last
+async fn switch(
+    state: &AppState,
+    device: &Device,
+    actuator: &Actuator,
+    actuation: &Actuation,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let on = actuation.switched_on_at.is_none();
+    let outcome = actuator.switch(state, on).await;
+
+    state
+        .device_repository
+        .record_switch(
+            actuation,
+            on,
+            now,
+            outcome.as_ref().err().map(String::as_str),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Err(e) = outcome {
+        warn!(
+            "unable to switch {} {}, {}",
+            device.name,
+            if on { "on" } else { "off" },
+            e
+        );
+        return Ok(());
+    }
+
+    info!("switched {} {}", device.name, if on { "on" } else { "off" });
+
+    let occurrence = state
+        .device_repository
+        .fetch_occurrence_actuations(device.id, actuation.schedule_id, actuation.date)
+        .await?;
+
+    if on {
+        let first = !occurrence
+            .iter()
+            .any(|other| other.starts_at != actuation.starts_at && other.switched_on_at.is_some());
+
+        if first {
+            record_run_start(state, device, now).await?;
+        }
+    } else if occurrence
+        .last()
+        .is_some_and(|last| last.starts_at == actuation.starts_at)
+    {
+        state
+            .device_repository
+            .finish_run(device.id, now)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let url = Url::parse("http://192.168.1.20").unwrap();
+
+        assert_eq!(
+            Actuator::new("shelly", Some("http://192.168.1.20"), None, None),
+            Ok(Actuator::Shelly {
+                url: url.clone(),
+                channel: 0
+            })
+        );
+        assert_eq!(
+            Actuator::new("tasmota", Some("http://192.168.1.20"), None, None),
+            Ok(Actuator::TasmotaHttp {
+                url: url.clone(),
+                relay: 1
+            })
+        );
+        assert_eq!(
+            Actuator::new("tasmota", None, Some("tasmota_boiler"), Some(2)),
+            Ok(Actuator::TasmotaMqtt {
+                topic: "tasmota_boiler".to_string(),
+                relay: 2
+            })
+        );
+        assert_eq!(
+            Actuator::new("hue", Some("http://192.168.1.20"), None, None),
+            Err(ActuatorError::UnknownKind("hue".to_string()))
+        );
+        assert_eq!(
+            Actuator::new("shelly", None, None, None),
+            Err(ActuatorError::MissingUrl)
+        );
+        assert_eq!(
+            Actuator::new("shelly", None, Some("shelly_boiler"), None),
+            Err(ActuatorError::UnsupportedTopic)
+        );
+        assert_eq!(
+            Actuator::new(
+                "tasmota",
+                Some("http://192.168.1.20"),
+                Some("tasmota_boiler"),
+                None
+            ),
+            Err(ActuatorError::MissingAddress)
+        );
+        assert_eq!(
+            Actuator::new("shelly", Some("192.168.1.20"), None, None),
+            Err(ActuatorError::InvalidUrl("192.168.1.20".to_string()))
+        );
+        assert_eq!(
+            Actuator::new("shelly", Some("http://192.168.1.20"), None, Some(-1)),
+            Err(ActuatorError::InvalidChannel(0))
+        );
+        assert_eq!(
+            Actuator::new("tasmota", Some("http://192.168.1.20"), None, Some(0)),
+            Err(ActuatorError::InvalidChannel(1))
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use thiserror::Error;
+use tracing::info;
+
+use crate::{
+    cron::CronSchedule,
+    domain::PriceUnit,
+    s3::{S3Bucket, S3Object},
+    setup::AppState,
+};
+
+/// The version of the format of backups, raised when it changes incompatibly
+pub(crate) const BACKUP_VERSION: i32 = 1;
+
+/// What the names of backups start with, after the configured prefix
+const BACKUP_NAME: &str = "electrack-";
+
+/// Backups of the price history, devices and schedules to an S3-compatible bucket
+#[derive(Clone, Debug)]
+pub(crate) struct S3Backup {
+    pub(crate) bucket: S3Bucket,
+    /// What the keys of backups start with, e.g. `backups/`
+    pub(crate) prefix: String,
+    /// How many backups are kept, older ones are deleted
+    pub(crate) keep: usize,
+    pub(crate) schedule: CronSchedule,
+}
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum BackupError {
+    #[error("backups are not configured, set S3_BACKUP_BUCKET")]
+    NotConfigured,
+    #[error("no backup was found")]
+    NotFound,
+    #[error("the bucket could not be accessed, {0}")]
+    Storage(String),
+    #[error("the backup could not be read, {0}")]
+    Invalid(String),
+    #[error("the backup could not be restored, {0}")]
+    Restore(String),
+}
+
+/// Everything that cannot be fetched again from a provider, as stored in a bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Backup {
+    pub(crate) version: i32,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) providers: Vec<BackupProvider>,
+    pub(crate) prices: Vec<BackupPrice>,
+    pub(crate) devices: Vec<BackupDevice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub(crate) struct BackupProvider {
+    pub(crate) name: String,
+    /// The currency the provider was created with, which prices without a currency of their own
+    /// are taken to be in, as a provider can serve prices in several currencies
+    pub(crate) currency: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub(crate) struct BackupPrice {
+    /// The name of the provider of the price
+    pub(crate) provider: String,
+    pub(crate) moment: DateTime<Utc>,
+    pub(crate) price: f64,
+    pub(crate) consumer_price: Option<f64>,
+    pub(crate) supplier_fee: Option<f64>,
+    pub(crate) energy_tax: Option<f64>,
+    pub(crate) grid_fee: Option<f64>,
+    pub(crate) vat: Option<f64>,
+    /// Absent in backups made before prices had a currency of their own, in which a price is
+    /// in the currency of its provider
+    #[serde(default)]
+    pub(crate) currency: String,
+    #[serde(default = "default_unit")]
+    pub(crate) unit: String,
+    #[serde(default = "default_resolution_minutes")]
+    pub(crate) resolution_minutes: i32,
+    /// Empty when the area of the price is not known
+    #[serde(default)]
+    pub(crate) area: String,
+}
+
+/// What prices were expressed per before their unit was stored
+fn default_unit() -> String {
+    PriceUnit::KilowattHour.as_str().to_string()
+}
+
+/// How long prices lasted before their resolution was stored
+fn default_resolution_minutes() -> i32 {
+    60
+}
+
+impl Backup {
+    /// Give the prices without a currency, from backups made before prices had one of their
+    /// own, the currency of their provider
+    fn fill_price_currencies(&mut self) {
+        for price in self
+            .prices
+            .iter_mut()
+            .filter(|price| price.currency.is_empty())
+        {
+            if let Some(provider) = self
+                .providers
+                .iter()
+                .find(|provider| provider.name == price.provider)
+            {
+                price.currency = provider.currency.clone();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub(crate) struct BackupDevice {
+    pub(crate) name: String,
+    pub(crate) duration: i32,
+    pub(crate) power_kw: Option<f64>,
+    pub(crate) strategy: String,
+    pub(crate) min_gap_hours: Option<i32>,
+    pub(crate) actuator: Option<String>,
+    pub(crate) actuator_url: Option<String>,
+    pub(crate) actuator_topic: Option<String>,
+    pub(crate) actuator_channel: Option<i32>,
+    #[sqlx(skip)]
+    pub(crate) schedules: Vec<BackupSchedule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub(crate) struct BackupSchedule {
+    pub(crate) recurrence: String,
+    pub(crate) available_from_hour: i32,
+    pub(crate) finish_by_hour: i32,
+    pub(crate) enabled: bool,
+}
+
+/// What was restored from a backup. Prices that are stored already and devices with a name that
+/// exists already are left as they are.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct Restoration {
+    pub(crate) key: String,
+    pub(crate) providers: u64,
+    pub(crate) prices: u64,
+    pub(crate) devices: u64,
+    pub(crate) schedules: u64,
+}
+
+/// Store a backup in the bucket, and delete the backups beyond the number that is kept
+pub(crate) async fn back_up(state: &AppState) -> Result<(), String> {
+    let Some(configuration) = &state.backup else {
+        return Ok(());
+    };
+
+    let backup = state.backup_repository.fetch_backup().await?;
+    let body = serde_json::to_vec(&backup).map_err(|e| e.to_string())?;
+
+    let key = format!(
+        "{}{}{}.json",
+        configuration.prefix,
+        BACKUP_NAME,
+        backup.created_at.format("%Y%m%dT%H%M%SZ")
+    );
+
+    configuration.bucket.put_object(&key, body).await?;
+
+    info!(
+        "backed up {} prices and {} devices to {} in {}",
+        backup.prices.len(),
+        backup.devices.len(),
+        key,
+        configuration.bucket.name()
+    );
+
+    let backups = list_backups(configuration).await?;
+
+    for expired in backups
+        .iter()
+        .take(backups.len().saturating_sub(configuration.keep))
+    {
+        configuration.bucket.delete_object(&expired.key).await?;
+
+        info!("deleted the expired backup {}", expired.key);
+    }
+
+    Ok(())
+}
+
+/// The backups in the bucket, from oldest to newest
+pub(crate) async fn list_backups(configuration: &S3Backup) -> Result<Vec<S3Object>, String> {
+    let objects = configuration
+        .bucket
+        .list_objects(&configuration.prefix)
+        .await?;
+
+    // the key names the moment of the backup, so ordering by key orders by age
+    Ok(objects
+        .into_iter()
+        .filter(|object| is_backup(&configuration.prefix, &object.key))
+        .collect())
+}
+
+/// Restore a backup, the latest when no key is given
+pub(crate) async fn restore_backup(
+    state: &AppState,
+    key: Option<&str>,
+) -> Result<Restoration, BackupError> {
+    let Some(configuration) = &state.backup else {
+        return Err(BackupError::NotConfigured);
+    };
+
+    let backups = list_backups(configuration)
+        .await
+        .map_err(BackupError::Storage)?;
+
+    let object = match key {
+        Some(key) => backups.iter().find(|object| object.key == key),
+        None => backups.last(),
+    }
+    .ok_or(BackupError::NotFound)?;
+
+    let body = configuration
+        .bucket
+        .get_object(&object.key)
+        .await
+        .map_err(BackupError::Storage)?;
+
+    let mut backup =
+        serde_json::from_slice::<Backup>(&body).map_err(|e| BackupError::Invalid(e.to_string()))?;
+
+    if backup.version > BACKUP_VERSION {
+        return Err(BackupError::Invalid(format!(
+            "version {} is newer than this version of electrack supports",
+            backup.version
+        )));
+    }
+
+    backup.fill_price_currencies();
+
+    let restoration = state
+        .backup_repository
+        .restore_backup(&backup)
+        .await
+        .map_err(|e| BackupError::Restore(e.to_string()))?;
+
+    info!(
+        "restored {} prices and {} devices from {}",
+        restoration.prices, restoration.devices, object.key
+    );
+
+    Ok(Restoration {
+        key: object.key.clone(),
+        ..restoration
+    })
+}
+
+/// Whether the key of an object is that of a backup, rather than of anything else under the
+/// prefix
+fn is_backup(prefix: &str, key: &str) -> bool {
+    key.strip_prefix(prefix)
+        .is_some_and(|name| name.starts_with(BACKUP_NAME) && name.ends_with(".json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_backup() {
+        assert!(is_backup("", "electrack-20240630T030000Z.json"));
+        assert!(is_backup(
+            "backups/",
+            "backups/electrack-20240630T030000Z.json"
+        ));
+        assert!(!is_backup("backups/", "electrack-20240630T030000Z.json"));
+        assert!(!is_backup(
+            "backups/",
+            "backups/other/electrack-20240630T030000Z.json"
+        ));
+        assert!(!is_backup("", "photos/20240630.jpg"));
+    }
+
+    #[test]
+    fn test_fill_price_currencies() {
+        let mut backup = serde_json::from_str::<Backup>(
+            r#"{
+                "version": 1,
+                "created_at": "2024-06-30T03:00:00Z",
+                "providers": [{ "name": "tibber", "currency": "SEK" }],
+                "prices": [{ "provider": "tibber", "moment": "2024-06-30T00:00:00Z", "price": 0.1 }],
+                "devices": []
+            }"#,
+        )
+        .unwrap();
+
+        backup.fill_price_currencies();
+
+        assert_eq!(backup.prices[0].currency, "SEK");
+        assert_eq!(backup.prices[0].unit, "kWh");
+        assert_eq!(backup.prices[0].resolution_minutes, 60);
+    }
+}
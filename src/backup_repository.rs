@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool, QueryBuilder};
+use thiserror::Error;
+
+use crate::{
+    backup::{
+        Backup, BackupDevice, BackupPrice, BackupProvider, BackupSchedule, Restoration,
+        BACKUP_VERSION,
+    },
+    price_repository::create_partitions_of,
+};
+
+/// How many prices are inserted per statement, well within the limit of bound parameters
+pub(crate) const PRICE_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum BackupRepositoryError {
+    #[error("the backup could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait BackupRepository: Send + Sync {
+    /// Fetch the providers with all of their prices and the devices with their schedules
+    async fn fetch_backup(&self) -> Result<Backup, String>;
+
+    /// Fetch the prices starting within a period, optionally of a single provider and area, as
+    /// they are backed up
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        provider: Option<&str>,
+        area: Option<&str>,
+    ) -> Result<Vec<BackupPrice>, String>;
+
+    /// Persist what a backup holds in a single transaction, keeping the prices that are stored
+    /// already and the devices with a name that exists already
+    async fn restore_backup(&self, backup: &Backup) -> Result<Restoration, BackupRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresBackupRepository {
+    db: PgPool,
+    /// The replica that backups and exports read from, the primary unless one is configured
+    read_db: PgPool,
+}
+
+impl PostgresBackupRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self {
+            read_db: db.clone(),
+            db,
+        }
+    }
+
+    /// Read backups from a replica of the database, while they are restored to the primary
+    pub fn with_replica(self, read_db: PgPool) -> Self {
+        Self { read_db, ..self }
+    }
+}
+
+#[derive(FromRow)]
+pub(crate) struct ScheduleRow {
+    pub(crate) device: String,
+    #[sqlx(flatten)]
+    pub(crate) schedule: BackupSchedule,
+}
+
+#[async_trait]
+impl BackupRepository for PostgresBackupRepository {
+    async fn fetch_backup(&self) -> Result<Backup, String> {
+        let created_at = Utc::now();
+
+        let providers =
+            sqlx::query_as::<_, BackupProvider>("select name, currency from providers order by id")
+                .fetch_all(&self.read_db)
+                .await
+                .map_err(|e| e.to_string())?;
+
+        let prices = sqlx::query_as::<_, BackupPrice>(
+            r#"
+            select providers.name as provider, moment, price, consumer_price, supplier_fee,
+                   energy_tax, grid_fee, vat, prices.currency, unit, resolution_minutes,
+                   area
+            from prices
+            join providers on providers.id = prices.provider_id
+            order by providers.id, moment
+            "#,
+        )
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let mut devices = sqlx::query_as::<_, BackupDevice>(
+            r#"
+            select name, duration, power_kw, strategy, min_gap_hours, actuator, actuator_url,
+                   actuator_topic, actuator_channel
+            from devices
+            order by id
+            "#,
+        )
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let schedules = sqlx::query_as::<_, ScheduleRow>(
+            r#"
+            select devices.name as device, recurrence, available_from_hour, finish_by_hour, enabled
+            from schedules
+            join devices on devices.id = schedules.device_id
+            order by schedules.id
+            "#,
+        )
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for row in schedules {
+            if let Some(device) = devices.iter_mut().find(|device| device.name == row.device) {
+                device.schedules.push(row.schedule);
+            }
+        }
+
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            created_at,
+            providers,
+            prices,
+            devices,
+        })
+    }
+
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        provider: Option<&str>,
+        area: Option<&str>,
+    ) -> Result<Vec<BackupPrice>, String> {
+        sqlx::query_as::<_, BackupPrice>(
+            r#"
+            select providers.name as provider, moment, price, consumer_price, supplier_fee,
+                   energy_tax, grid_fee, vat, prices.currency, unit, resolution_minutes,
+                   area
+            from prices
+            join providers on providers.id = prices.provider_id
+            where moment >= $1 and moment < $2 and ($3 is null or providers.name = $3)
+              and ($4 is null or area = $4)
+            order by providers.id, area, moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(provider)
+        .bind(area)
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn restore_backup(&self, backup: &Backup) -> Result<Restoration, BackupRepositoryError> {
+        let error = |e: sqlx::Error| BackupRepositoryError::PersistenceError(e.to_string());
+
+        create_partitions_of(
+            &self.db,
+            &backup
+                .prices
+                .iter()
+                .map(|price| price.moment)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .map_err(error)?;
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+        let mut restoration = Restoration::default();
+        let mut provider_ids = HashMap::new();
+
+        for provider in &backup.providers {
+            let existing: Option<(i64,)> =
+                sqlx::query_as("select id from providers where name = $1 limit 1")
+                    .bind(&provider.name)
+                    .fetch_optional(&mut *transaction)
+                    .await
+                    .map_err(error)?;
+
+            let id = match existing {
+                Some((id,)) => id,
+                None => {
+                    restoration.providers += 1;
+
+                    let (id,): (i64,) = sqlx::query_as(
+                        "insert into providers (name, currency) values ($1, $2) returning id",
+                    )
+                    .bind(&provider.name)
+                    .bind(&provider.currency)
+                    .fetch_one(&mut *transaction)
+                    .await
+                    .map_err(error)?;
+
+                    id
+                }
+            };
+
+            provider_ids.insert(provider.name.as_str(), id);
+        }
+
+        let prices = backup
+            .prices
+            .iter()
+            .filter_map(|price| {
+                provider_ids
+                    .get(price.provider.as_str())
+                    .map(|id| (*id, price))
+            })
+            .collect::<Vec<(i64, &BackupPrice)>>();
+
+        for batch in prices.chunks(PRICE_BATCH_SIZE) {
+            let mut query_builder = QueryBuilder::new(
+                "insert into prices (moment, price, consumer_price, supplier_fee, energy_tax, grid_fee, vat, currency, unit, resolution_minutes, area, provider_id)",
+            );
+
+            query_builder.push_values(batch, |mut builder, (provider_id, price)| {
+                builder
+                    .push_bind(price.moment)
+                    .push_bind(price.price)
+                    .push_bind(price.consumer_price)
+                    .push_bind(price.supplier_fee)
+                    .push_bind(price.energy_tax)
+                    .push_bind(price.grid_fee)
+                    .push_bind(price.vat)
+                    .push_bind(&price.currency)
+                    .push_bind(&price.unit)
+                    .push_bind(price.resolution_minutes)
+                    .push_bind(&price.area)
+                    .push_bind(provider_id);
+            });
+
+            query_builder.push(" on conflict (moment, provider_id, area) do nothing");
+
+            restoration.prices += query_builder
+                .build()
+                .persistent(false)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?
+                .rows_affected();
+        }
+
+        // the restored prices are the first version of their moment
+        sqlx::query(
+            r#"
+            insert into price_versions (moment, provider_id, area, version, price, consumer_price,
+                                        supplier_fee, energy_tax, grid_fee, vat, currency, unit,
+                                        resolution_minutes, ingested_at)
+            select moment, provider_id, area, version, price, consumer_price, supplier_fee,
+                   energy_tax, grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+            from prices
+            on conflict do nothing
+            "#,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(error)?;
+
+        for device in &backup.devices {
+            let inserted: Option<(i64,)> = sqlx::query_as(
+                r#"
+                insert into devices (name, duration, power_kw, strategy, min_gap_hours, actuator,
+                                     actuator_url, actuator_topic, actuator_channel)
+                values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                on conflict (name) do nothing
+                returning id
+                "#,
+            )
+            .bind(&device.name)
+            .bind(device.duration)
+            .bind(device.power_kw)
+            .bind(&device.strategy)
+            .bind(device.min_gap_hours)
+            .bind(&device.actuator)
+            .bind(&device.actuator_url)
+            .bind(&device.actuator_topic)
+            .bind(device.actuator_channel)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(error)?;
+
+            let Some((device_id,)) = inserted else {
+                continue;
+            };
+
+            restoration.devices += 1;
+
+            for schedule in &device.schedules {
+                sqlx::query(
+                    r#"
+                    insert into schedules (device_id, recurrence, available_from_hour,
+                                           finish_by_hour, enabled)
+                    values ($1, $2, $3, $4, $5)
+                    "#,
+                )
+                .bind(device_id)
+                .bind(&schedule.recurrence)
+                .bind(schedule.available_from_hour)
+                .bind(schedule.finish_by_hour)
+                .bind(schedule.enabled)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+
+                restoration.schedules += 1;
+            }
+        }
+
+        transaction.commit().await.map_err(error)?;
+
+        Ok(restoration)
+    }
+}
@@ -0,0 +1,186 @@
+use serde::Serialize;
+
+/// The coarsest and finest number of steps the state of charge is divided into
+const MIN_LEVELS: usize = 20;
+const MAX_LEVELS: usize = 400;
+
+/// A home battery that can buy energy when it is cheap and deliver it when it is expensive
+#[derive(Debug, Clone)]
+pub(crate) struct Battery {
+    pub(crate) capacity_kwh: f64,
+    pub(crate) charge_power_kw: f64,
+    pub(crate) discharge_power_kw: f64,
+    /// The share of the energy that is left after charging and discharging it, e.g. 0.9
+    pub(crate) round_trip_efficiency: f64,
+    /// The stored energy at the start of the plan
+    pub(crate) initial_kwh: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatteryAction {
+    Charge,
+    Discharge,
+    Idle,
+}
+
+/// What the battery does in the hour of a price point
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BatterySlot {
+    pub(crate) action: BatteryAction,
+    /// The energy taken from the grid when charging or delivered when discharging
+    pub(crate) grid_kwh: f64,
+    /// The stored energy at the end of the hour
+    pub(crate) stored_kwh: f64,
+}
+
+impl Battery {
+    /// Plan when to charge and discharge over consecutive hours with the given costs, maximizing
+    /// the value of the energy that is delivered minus the cost of the energy that is bought.
+    /// Half of the round trip losses are taken when charging, half when discharging. Energy
+    /// that is left at the end of the plan has no value, so the battery is emptied when that
+    /// pays off.
+    ///
+    /// The state of charge is divided into steps, so the plan is an approximation for batteries
+    /// of which the power is small compared to their capacity.
+    pub(crate) fn plan_arbitrage(&self, costs: &[f64]) -> Vec<BatterySlot> {
+        let levels = ((self.capacity_kwh * 4.0 / self.charge_power_kw.min(self.discharge_power_kw))
+            .ceil() as usize)
+            .clamp(MIN_LEVELS, MAX_LEVELS);
+        let step = self.capacity_kwh / levels as f64;
+        let efficiency = self.round_trip_efficiency.sqrt();
+
+        let max_charge = (self.charge_power_kw / step + 1e-9).floor() as usize;
+        let max_discharge = (self.discharge_power_kw / step + 1e-9).floor() as usize;
+
+        let initial = ((self.initial_kwh / step).round() as usize).min(levels);
+
+        // the best value for every level after every hour, with the level it came from
+        let mut values = vec![f64::NEG_INFINITY; levels + 1];
+        values[initial] = 0.0;
+        let mut origins = Vec::with_capacity(costs.len());
+
+        for cost in costs {
+            let mut next = vec![f64::NEG_INFINITY; levels + 1];
+            let mut origin = vec![0usize; levels + 1];
+
+            for (from, value) in values.iter().enumerate() {
+                if value.is_infinite() {
+                    continue;
+                }
+
+                let lowest = from.saturating_sub(max_discharge);
+                let highest = (from + max_charge).min(levels);
+
+                // staying idle comes first, so cycling the battery has to gain something
+                for to in std::iter::once(from).chain(lowest..=highest) {
+                    let stored = (to as f64 - from as f64) * step;
+
+                    let gain = if stored > 0.0 {
+                        -stored / efficiency * cost
+                    } else {
+                        -stored * efficiency * cost
+                    };
+
+                    if value + gain > next[to] + 1e-9 {
+                        next[to] = value + gain;
+                        origin[to] = from;
+                    }
+                }
+            }
+
+            values = next;
+            origins.push(origin);
+        }
+
+        let Some((mut level, _)) = values
+            .iter()
+            .enumerate()
+            .max_by(|(a, a_value), (b, b_value)| a_value.total_cmp(b_value).then(b.cmp(a)))
+        else {
+            return Vec::new();
+        };
+
+        let mut levels_after = vec![0usize; costs.len()];
+
+        for (hour, origin) in origins.iter().enumerate().rev() {
+            levels_after[hour] = level;
+            level = origin[level];
+        }
+
+        let mut previous = initial;
+
+        levels_after
+            .into_iter()
+            .map(|level| {
+                let stored = (level as f64 - previous as f64) * step;
+                previous = level;
+
+                let (action, grid_kwh) = if stored > 0.0 {
+                    (BatteryAction::Charge, stored / efficiency)
+                } else if stored < 0.0 {
+                    (BatteryAction::Discharge, -stored * efficiency)
+                } else {
+                    (BatteryAction::Idle, 0.0)
+                };
+
+                BatterySlot {
+                    action,
+                    grid_kwh,
+                    stored_kwh: level as f64 * step,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_arbitrage() {
+        let battery = Battery {
+            capacity_kwh: 10.0,
+            charge_power_kw: 5.0,
+            discharge_power_kw: 5.0,
+            round_trip_efficiency: 1.0,
+            initial_kwh: 0.0,
+        };
+
+        let actions = battery
+            .plan_arbitrage(&[0.3, 0.1, 0.1, 0.35, 0.4, 0.4])
+            .iter()
+            .map(|slot| slot.action)
+            .collect::<Vec<BatteryAction>>();
+
+        assert_eq!(
+            actions,
+            vec![
+                BatteryAction::Idle,
+                BatteryAction::Charge,
+                BatteryAction::Charge,
+                BatteryAction::Idle,
+                BatteryAction::Discharge,
+                BatteryAction::Discharge,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_arbitrage_losses() {
+        let battery = Battery {
+            capacity_kwh: 10.0,
+            charge_power_kw: 5.0,
+            discharge_power_kw: 5.0,
+            round_trip_efficiency: 0.8,
+            initial_kwh: 0.0,
+        };
+
+        // the spread does not cover the losses
+        assert!(battery
+            .plan_arbitrage(&[0.30, 0.33, 0.30, 0.33])
+            .iter()
+            .all(|slot| slot.action == BatteryAction::Idle));
+    }
+}
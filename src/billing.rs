@@ -0,0 +1,248 @@
+use chrono::{DateTime, Days, Months, NaiveDate, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    consumption_forecast::learn_profile,
+    contract::{current_contract, FixedCharges},
+    cost_report::{fetch_cost_periods, CostPeriod, Granularity},
+    domain::{round, start_of_day},
+    setup::AppState,
+    tariff::PriceComponents,
+};
+
+/// The number of days before today of which the usage is typical for the rest of a month
+const TYPICAL_USAGE_DAYS: u64 = 28;
+
+/// What a month costs in consumption and fixed charges, as on an invoice
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BillingSummary {
+    #[serde(serialize_with = "serialize_month")]
+    pub(crate) month: NaiveDate,
+    pub(crate) currency: Option<String>,
+    pub(crate) energy_kwh: f64,
+    pub(crate) energy_cost: f64,
+    /// Consumption in hours of which no price is stored, which is left out of the cost
+    pub(crate) unpriced_kwh: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) components: Option<PriceComponents>,
+    pub(crate) exported_kwh: f64,
+    /// What the energy fed back into the grid earned, which is subtracted from the total
+    pub(crate) feed_in_revenue: f64,
+    pub(crate) fixed_charges: FixedCharges,
+    pub(crate) total: f64,
+    /// What the whole month is expected to cost, while it is not over yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) projection: Option<Projection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Projection {
+    pub(crate) energy_kwh: f64,
+    pub(crate) energy_cost: f64,
+    pub(crate) feed_in_revenue: f64,
+    pub(crate) total: f64,
+}
+
+/// The consumption of an average day and what it cost, and what feeding back into the grid
+/// earned on it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DailyUsage {
+    pub(crate) energy_kwh: f64,
+    pub(crate) cost: f64,
+    pub(crate) feed_in_revenue: f64,
+}
+
+impl DailyUsage {
+    /// The day with a different consumption, at the same price
+    pub(crate) fn with_energy(&self, energy_kwh: f64) -> DailyUsage {
+        DailyUsage {
+            energy_kwh,
+            cost: match self.energy_kwh {
+                typical_kwh if typical_kwh > 0.0 => self.cost * energy_kwh / typical_kwh,
+                _ => 0.0,
+            },
+            feed_in_revenue: self.feed_in_revenue,
+        }
+    }
+}
+
+/// The average consumption of the days with consumption, at the average price of the priced
+/// consumption. None without any priced consumption.
+pub(crate) fn typical_usage(days: &[CostPeriod]) -> Option<DailyUsage> {
+    let total = CostPeriod::total(days.first()?.starts_at, days);
+    let energy_kwh = total.energy_kwh / days.len() as f64;
+
+    total.average_price().map(|average_price| DailyUsage {
+        energy_kwh,
+        cost: energy_kwh * average_price,
+        feed_in_revenue: total.feed_in_revenue / days.len() as f64,
+    })
+}
+
+/// The usage so far extended by the typical usage over the remaining days
+pub(crate) fn project(so_far: &CostPeriod, typical: DailyUsage, remaining_days: f64) -> DailyUsage {
+    DailyUsage {
+        energy_kwh: so_far.energy_kwh + typical.energy_kwh * remaining_days,
+        cost: so_far.cost + typical.cost * remaining_days,
+        feed_in_revenue: so_far.feed_in_revenue + typical.feed_in_revenue * remaining_days,
+    }
+}
+
+/// Summarize what the month that starts on a date costs. While the month is not over yet, the
+/// rest of it is projected from the typical usage of the days before today, of which the
+/// consumption follows the profile of the hours of the week when there is enough history.
+pub(crate) async fn billing_summary(
+    state: &AppState,
+    month: NaiveDate,
+    now: DateTime<Utc>,
+    area: Option<&str>,
+) -> Result<BillingSummary, String> {
+    let start = start_of_day(&state.timezone, month);
+    let end = start_of_day(&state.timezone, month + Months::new(1));
+
+    let (days, currency) = fetch_cost_periods(state, start, end, Granularity::Day, area).await?;
+    let so_far = CostPeriod::total(start, &days);
+
+    let fixed_charges = current_contract(state)
+        .await?
+        .fixed_charges(month, month + Months::new(1));
+
+    let mut currency = currency;
+    let mut projection = None;
+
+    if now < end {
+        let today = start_of_day(
+            &state.timezone,
+            now.with_timezone(&state.timezone).date_naive(),
+        );
+        let history_start = start_of_day(
+            &state.timezone,
+            now.with_timezone(&state.timezone).date_naive() - Days::new(TYPICAL_USAGE_DAYS),
+        );
+
+        let (history, history_currency) =
+            fetch_cost_periods(state, history_start, today, Granularity::Day, area).await?;
+
+        currency = currency.or(history_currency);
+
+        let profile = learn_profile(state, now).await?;
+        let remaining_days = (end - now.max(start)).num_seconds() as f64 / 86_400.0;
+
+        projection = typical_usage(&history).map(|typical| {
+            let typical = match &profile {
+                Some(profile) => typical.with_energy(
+                    profile.expected_between(&state.timezone, now.max(start), end) / remaining_days,
+                ),
+                None => typical,
+            };
+            let projected = project(&so_far, typical, remaining_days);
+
+            Projection {
+                energy_kwh: round(projected.energy_kwh, 3),
+                energy_cost: round(projected.cost, 2),
+                feed_in_revenue: round(projected.feed_in_revenue, 2),
+                total: round(
+                    projected.cost - projected.feed_in_revenue + fixed_charges.total(),
+                    2,
+                ),
+            }
+        });
+    }
+
+    let net_cost = so_far.net_cost();
+
+    Ok(BillingSummary {
+        month,
+        currency,
+        energy_kwh: round(so_far.energy_kwh, 3),
+        energy_cost: round(so_far.cost, 2),
+        unpriced_kwh: round(so_far.unpriced_kwh, 3),
+        components: so_far.components.map(|components| PriceComponents {
+            energy: round(components.energy, 2),
+            supplier_fee: round(components.supplier_fee, 2),
+            energy_tax: round(components.energy_tax, 2),
+            grid_fee: round(components.grid_fee, 2),
+            vat: round(components.vat, 2),
+        }),
+        exported_kwh: round(so_far.exported_kwh, 3),
+        feed_in_revenue: round(so_far.feed_in_revenue, 2),
+        total: round(net_cost + fixed_charges.total(), 2),
+        fixed_charges: fixed_charges.rounded(),
+        projection,
+    })
+}
+
+/// A month as `2024-06`
+fn serialize_month<S: Serializer>(month: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&month.format("%Y-%m").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(energy_kwh: f64, cost: f64) -> CostPeriod {
+        CostPeriod {
+            starts_at: Utc::now(),
+            energy_kwh,
+            cost,
+            unpriced_kwh: 0.0,
+            components: None,
+            exported_kwh: 0.0,
+            feed_in_revenue: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_projection() {
+        assert_eq!(typical_usage(&[]), None);
+
+        let unpriced = CostPeriod {
+            unpriced_kwh: 4.0,
+            ..day(4.0, 0.0)
+        };
+        assert_eq!(typical_usage(std::slice::from_ref(&unpriced)), None);
+
+        // unpriced consumption counts at the average price
+        assert_eq!(
+            typical_usage(&[day(8.0, 2.0), unpriced]),
+            Some(DailyUsage {
+                energy_kwh: 6.0,
+                cost: 1.5,
+                feed_in_revenue: 0.0,
+            })
+        );
+
+        let exporting = CostPeriod {
+            exported_kwh: 5.0,
+            feed_in_revenue: 0.5,
+            ..day(12.0, 3.0)
+        };
+
+        let typical = typical_usage(&[day(8.0, 2.0), exporting]).unwrap();
+
+        assert_eq!(
+            typical,
+            DailyUsage {
+                energy_kwh: 10.0,
+                cost: 2.5,
+                feed_in_revenue: 0.25,
+            }
+        );
+
+        let projected = project(&day(50.0, 14.0), typical, 2.5);
+
+        assert_eq!(projected.energy_kwh, 75.0);
+        assert_eq!(projected.cost, 20.25);
+        assert_eq!(projected.feed_in_revenue, 0.625);
+
+        assert_eq!(
+            typical.with_energy(12.0),
+            DailyUsage {
+                energy_kwh: 12.0,
+                cost: 3.0,
+                feed_in_revenue: 0.25,
+            }
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+
+use crate::domain::{CarbonIntensity, PricePoint};
+
+/// The average carbon intensity of every hour, as providers publish it per hour or half hour
+pub(crate) fn hourly_intensities(intensities: &[CarbonIntensity]) -> BTreeMap<DateTime<Utc>, f64> {
+    let mut hours = BTreeMap::<DateTime<Utc>, (f64, usize)>::new();
+
+    for intensity in intensities {
+        let hour = intensity
+            .moment
+            .duration_trunc(TimeDelta::hours(1))
+            .unwrap_or(intensity.moment);
+
+        let (sum, count) = hours.entry(hour).or_default();
+        *sum += intensity.intensity;
+        *count += 1;
+    }
+
+    hours
+        .into_iter()
+        .map(|(hour, (sum, count))| (hour, sum / count as f64))
+        .collect()
+}
+
+/// The prices of the hours of which the carbon intensity is known, with the intensity of each
+pub(crate) fn with_carbon_intensity(
+    prices: Vec<PricePoint>,
+    intensities: &[CarbonIntensity],
+) -> (Vec<PricePoint>, Vec<f64>) {
+    let hourly = hourly_intensities(intensities);
+
+    prices
+        .into_iter()
+        .filter_map(|price| {
+            hourly
+                .get(&price.moment)
+                .map(|intensity| (price, *intensity))
+        })
+        .unzip()
+}
+
+/// The cost of every hour blending its price and carbon intensity, where a carbon weight of 0
+/// only counts the price and 1 only the carbon intensity. Both are scaled to between 0 and 1
+/// over the hours first, so the weight does not depend on their units.
+pub(crate) fn blended_costs(prices: &[f64], intensities: &[f64], carbon_weight: f64) -> Vec<f64> {
+    let scale = |values: &[f64]| -> Vec<f64> {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        values
+            .iter()
+            .map(|value| match max - min {
+                range if range > 0.0 => (value - min) / range,
+                _ => 0.0,
+            })
+            .collect()
+    };
+
+    scale(prices)
+        .into_iter()
+        .zip(scale(intensities))
+        .map(|(price, intensity)| (1.0 - carbon_weight) * price + carbon_weight * intensity)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::price_point;
+
+    fn moment(hour: i64, minute: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-06-30T00:00:00Z")
+            .unwrap()
+            .to_utc()
+            + TimeDelta::hours(hour)
+            + TimeDelta::minutes(minute)
+    }
+
+    fn intensity(hour: i64, minute: i64, intensity: f64) -> CarbonIntensity {
+        CarbonIntensity {
+            moment: moment(hour, minute),
+            zone: "GB".to_string(),
+            intensity,
+            forecast: false,
+        }
+    }
+
+    #[test]
+    fn test_hourly_intensities() {
+        let hourly = hourly_intensities(&[
+            intensity(0, 0, 100.0),
+            intensity(0, 30, 120.0),
+            intensity(1, 0, 90.0),
+        ]);
+
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[&moment(0, 0)], 110.0);
+        assert_eq!(hourly[&moment(1, 0)], 90.0);
+    }
+
+    #[test]
+    fn test_with_carbon_intensity() {
+        let prices = (0..3)
+            .map(|hour| price_point(moment(hour, 0), 0.2))
+            .collect();
+
+        let (prices, intensities) =
+            with_carbon_intensity(prices, &[intensity(0, 0, 100.0), intensity(2, 0, 80.0)]);
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[1].moment, moment(2, 0));
+        assert_eq!(intensities, vec![100.0, 80.0]);
+    }
+
+    #[test]
+    fn test_blended_costs() {
+        let prices = [0.25, 0.75, 0.5];
+        let intensities = [300.0, 100.0, 200.0];
+
+        assert_eq!(
+            blended_costs(&prices, &intensities, 0.0),
+            vec![0.0, 1.0, 0.5]
+        );
+        assert_eq!(
+            blended_costs(&prices, &intensities, 1.0),
+            vec![1.0, 0.0, 0.5]
+        );
+
+        let blended = blended_costs(&prices, &intensities, 0.3);
+        assert!((blended[0] - 0.3).abs() < 1e-9);
+        assert!((blended[1] - 0.7).abs() < 1e-9);
+
+        // a constant series does not count
+        assert_eq!(
+            blended_costs(&[0.2, 0.2], &[100.0, 50.0], 0.5),
+            vec![0.5, 0.0]
+        );
+    }
+}
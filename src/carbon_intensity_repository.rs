@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::domain::CarbonIntensity;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum CarbonIntensityRepositoryError {
+    #[error("the carbon intensity could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait CarbonIntensityRepository: Send + Sync {
+    /// Fetch the carbon intensity of a zone between two moments, ordered by moment
+    async fn fetch_carbon_intensity(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<CarbonIntensity>, String>;
+
+    /// Persist the carbon intensity, replacing what is stored for a moment unless a measured
+    /// intensity would be replaced by a forecast one
+    async fn persist_carbon_intensity(
+        &self,
+        intensities: &[CarbonIntensity],
+    ) -> Result<(), CarbonIntensityRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresCarbonIntensityRepository {
+    db: PgPool,
+}
+
+impl PostgresCarbonIntensityRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl CarbonIntensityRepository for PostgresCarbonIntensityRepository {
+    async fn fetch_carbon_intensity(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<CarbonIntensity>, String> {
+        sqlx::query_as::<_, CarbonIntensity>(
+            r#"
+            select moment, zone, intensity, forecast
+            from carbon_intensity
+            where zone = $1 and moment >= $2 and moment < $3
+            order by moment
+            "#,
+        )
+        .bind(zone)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_carbon_intensity(
+        &self,
+        intensities: &[CarbonIntensity],
+    ) -> Result<(), CarbonIntensityRepositoryError> {
+        if intensities.is_empty() {
+            return Ok(());
+        }
+
+        // a single statement cannot update a row twice, so a moment that is both measured and
+        // forecast keeps the measured intensity
+        let mut unique = BTreeMap::new();
+
+        for intensity in intensities {
+            unique
+                .entry((intensity.moment, &intensity.zone))
+                .and_modify(|kept: &mut &CarbonIntensity| {
+                    if kept.forecast {
+                        *kept = intensity;
+                    }
+                })
+                .or_insert(intensity);
+        }
+
+        info!("Persisting {} carbon intensities", unique.len());
+
+        let mut query_builder =
+            QueryBuilder::new("insert into carbon_intensity (moment, zone, intensity, forecast)");
+
+        query_builder.push_values(unique.values(), |mut builder, intensity| {
+            builder
+                .push_bind(intensity.moment)
+                .push_bind(&intensity.zone)
+                .push_bind(intensity.intensity)
+                .push_bind(intensity.forecast);
+        });
+
+        query_builder.push(
+            r#"
+            on conflict (moment, zone) do update
+            set intensity = excluded.intensity, forecast = excluded.forecast
+            where carbon_intensity.forecast or not excluded.forecast
+            "#,
+        );
+
+        query_builder
+            .build()
+            .persistent(false)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| CarbonIntensityRepositoryError::PersistenceError(e.to_string()))
+    }
+}
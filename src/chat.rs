@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde_json::json;
+
+/// How long a chat platform may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A chat platform that accepts messages through incoming webhooks
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Platform {
+    Slack,
+    Discord,
+}
+
+/// An incoming webhook of Slack or Discord, which posts messages to the channel it was created
+/// for
+#[derive(Clone, Debug)]
+pub(crate) struct IncomingWebhook {
+    platform: Platform,
+    /// Anyone with the URL can post messages, so it is kept out of errors
+    url: String,
+}
+
+impl IncomingWebhook {
+    pub(crate) fn new(platform: Platform, url: String) -> Self {
+        Self { platform, url }
+    }
+
+    pub(crate) async fn send_message(&self, text: &str) -> Result<(), String> {
+        let body = match self.platform {
+            Platform::Slack => json!({ "text": text }),
+            Platform::Discord => json!({ "content": text }),
+        };
+
+        Client::new()
+            .post(&self.url)
+            .timeout(TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(describe_error)
+    }
+}
+
+/// The error of a request without its URL, for URLs that carry a secret
+pub(crate) fn describe_error(error: reqwest::Error) -> String {
+    let error = error.without_url();
+
+    match std::error::Error::source(&error) {
+        Some(source) => format!("{}, {}", error, source),
+        None => error.to_string(),
+    }
+}
@@ -0,0 +1,277 @@
+use std::{io::Write, path::PathBuf};
+
+use chrono::{Days, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand};
+use tracing::info;
+use url::Url;
+
+use crate::{
+    client::ServerClient,
+    configuration_file::variable,
+    domain::start_of_day,
+    http::start_http_server,
+    price_export::{prices_csv, PriceFormat},
+    scheduler::{backfill_missing_days, fetch_prices_of_date},
+    setup::{migrate_database, setup_app_state},
+    APP_NAME,
+};
+
+/// Tracks electricity prices and finds the cheapest moments to use electricity
+#[derive(Debug, Parser)]
+#[command(name = APP_NAME, version)]
+pub(crate) struct Cli {
+    /// Make up the prices and keep everything in memory, without a provider or a database
+    #[arg(long, global = true)]
+    demo: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the API and run the scheduled jobs, which is the default
+    Serve,
+    /// Fetch the prices of a day from the provider and store them
+    Fetch {
+        /// The local day, today by default
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Fetch the prices of past days that are missing or incomplete
+    Backfill {
+        /// How many days to look back, `PRICE_CATCH_UP_DAYS` by default
+        #[arg(long)]
+        days: Option<i64>,
+    },
+    /// Write the stored prices of a range of days, to stdout unless an output is given
+    Export {
+        /// The first day to export
+        #[arg(long)]
+        from: NaiveDate,
+        /// The last day to export, defaults to `from`
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// The name of the provider of which to export the prices, all of them by default
+        #[arg(long)]
+        provider: Option<String>,
+        /// The bidding zone of which to export the prices, e.g. `NL`, all of them by default
+        #[arg(long)]
+        area: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        format: PriceFormat,
+        /// The file to write the prices to
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Run the database migrations and exit
+    Migrate,
+    /// Chart the prices of today and tomorrow of a running server
+    Prices {
+        #[command(flatten)]
+        server: ServerArguments,
+        /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+        #[arg(long)]
+        area: Option<String>,
+    },
+    /// List the cheapest upcoming windows of a running server
+    Windows {
+        #[command(flatten)]
+        server: ServerArguments,
+        /// The duration of a window in hours, which can be given more than once
+        #[arg(long = "duration", required = true)]
+        durations: Vec<i32>,
+        /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+        #[arg(long)]
+        area: Option<String>,
+    },
+}
+
+/// The URL of the running server when neither `--server` nor `ELECTRACK_URL` is given
+const DEFAULT_SERVER_URL: &str = "http://localhost:8080";
+
+#[derive(Debug, Args)]
+struct ServerArguments {
+    /// The URL of the running server, `ELECTRACK_URL` or http://localhost:8080 by default
+    #[arg(long)]
+    server: Option<Url>,
+}
+
+impl ServerArguments {
+    /// The URL of the argument, or else of `ELECTRACK_URL`, which is resolved once `.env`, the
+    /// secret files and the configuration file are read, as the arguments are parsed before
+    fn url(self) -> Result<Url, String> {
+        match self.server {
+            Some(url) => Ok(url),
+            None => {
+                let url = variable("ELECTRACK_URL").unwrap_or(DEFAULT_SERVER_URL.to_string());
+
+                Url::parse(&url).map_err(|e| format!("invalid ELECTRACK_URL {}, {}", url, e))
+            }
+        }
+    }
+}
+
+impl Cli {
+    /// Whether the command writes its result to stdout, which logs would get mixed into
+    pub(crate) fn writes_to_stdout(&self) -> bool {
+        matches!(
+            self.command,
+            Some(Command::Export { output: None, .. })
+                | Some(Command::Prices { .. })
+                | Some(Command::Windows { .. })
+        )
+    }
+
+    pub(crate) async fn run(self) -> Result<(), String> {
+        match self.command.unwrap_or(Command::Serve) {
+            Command::Serve => start_http_server(self.demo)
+                .await
+                .map_err(|e| e.to_string()),
+            Command::Fetch { date } => fetch(self.demo, date).await,
+            Command::Backfill { days } => backfill(self.demo, days).await,
+            Command::Export {
+                from,
+                to,
+                provider,
+                area,
+                format,
+                output,
+            } => export(self.demo, from, to, provider, area, format, output).await,
+            Command::Migrate => {
+                migrate_database().await;
+                info!("migrated the database");
+
+                Ok(())
+            }
+            Command::Prices { server, area } => {
+                print!("{}", ServerClient::new(server.url()?)?.prices(area).await?);
+
+                Ok(())
+            }
+            Command::Windows {
+                server,
+                durations,
+                area,
+            } => {
+                print!(
+                    "{}",
+                    ServerClient::new(server.url()?)?
+                        .windows(&durations, area)
+                        .await?
+                );
+
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn fetch(demo: bool, date: Option<NaiveDate>) -> Result<(), String> {
+    let state = setup_app_state(demo).await;
+    let date = date.unwrap_or_else(|| Utc::now().with_timezone(&state.timezone).date_naive());
+
+    let prices = fetch_prices_of_date(&state, date)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!("stored {} prices of {}", prices.len(), date);
+
+    Ok(())
+}
+
+async fn backfill(demo: bool, days: Option<i64>) -> Result<(), String> {
+    let state = setup_app_state(demo).await;
+    let days = days.unwrap_or(state.settings().scheduling.catch_up_days);
+
+    if days <= 0 {
+        return Err("the number of days must be positive".to_string());
+    }
+
+    let fetched = backfill_missing_days(&state, days).await?;
+
+    info!(
+        "fetched the prices of {} of the last {} days",
+        fetched, days
+    );
+
+    Ok(())
+}
+
+async fn export(
+    demo: bool,
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+    provider: Option<String>,
+    area: Option<String>,
+    format: PriceFormat,
+    output: Option<PathBuf>,
+) -> Result<(), String> {
+    let to = to.unwrap_or(from);
+
+    if to < from {
+        return Err("the last day must not be before the first".to_string());
+    }
+
+    let state = setup_app_state(demo).await;
+
+    let prices = state
+        .backup_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, from),
+            start_of_day(&state.timezone, to + Days::new(1)),
+            provider.as_deref(),
+            area.as_deref(),
+        )
+        .await?;
+
+    let contents = match format {
+        PriceFormat::Json => serde_json::to_string(&prices).map_err(|e| e.to_string())?,
+        PriceFormat::Csv => prices_csv(&prices),
+    };
+
+    match output {
+        Some(path) => std::fs::write(&path, contents)
+            .map_err(|e| format!("unable to write {}, {}", path.display(), e))?,
+        None => std::io::stdout()
+            .write_all(contents.as_bytes())
+            .map_err(|e| e.to_string())?,
+    }
+
+    info!("exported {} prices", prices.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        // the demo flag of before the subcommands still starts the server
+        let cli = Cli::try_parse_from([APP_NAME, "--demo"]).unwrap();
+        assert!(cli.demo && cli.command.is_none());
+
+        let cli = Cli::try_parse_from([
+            APP_NAME,
+            "export",
+            "--from",
+            "2024-06-30",
+            "--format",
+            "csv",
+        ])
+        .unwrap();
+        assert!(cli.writes_to_stdout());
+
+        assert!(Cli::try_parse_from([APP_NAME, "windows"]).is_err());
+        assert!(Cli::try_parse_from([APP_NAME, "prices", "--server", "localhost"]).is_err());
+        assert!(
+            Cli::try_parse_from([APP_NAME, "windows", "--duration", "3", "--duration", "4"])
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Local, TimeDelta, Utc};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize};
+use url::Url;
+
+/// How long the server may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of characters of the bar of the highest price
+const CHART_WIDTH: usize = 40;
+
+/// How far ahead windows are looked for, which covers tomorrow once its prices are published
+const WINDOW_HOURS: i64 = 36;
+
+/// The prices of today and tomorrow as the Home Assistant sensor serves them
+#[derive(Debug, Clone, Deserialize)]
+struct SensorPrices {
+    unit_of_measurement: String,
+    raw_today: Vec<RawPrice>,
+    raw_tomorrow: Vec<RawPrice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RawPrice {
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+    value: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Window {
+    starts_at: DateTime<FixedOffset>,
+    ends_at: DateTime<FixedOffset>,
+    average_price: String,
+    currency: String,
+}
+
+/// Queries a running server to show its prices and windows in a terminal
+pub(crate) struct ServerClient {
+    server: Url,
+    client: Client,
+}
+
+impl ServerClient {
+    pub(crate) fn new(server: Url) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { server, client })
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, String> {
+        let url = self.server.join(path).map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .get(url.clone())
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| format!("unable to reach {}, {}", self.server, e))?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{} responded with {}, {}", url, status, body));
+        }
+
+        response.json::<T>().await.map_err(|e| e.to_string())
+    }
+
+    /// A chart of the prices of today and tomorrow, of which the current hour is marked
+    pub(crate) async fn prices(&self, area: Option<String>) -> Result<String, String> {
+        let query = area.map(|area| vec![("area", area)]).unwrap_or_default();
+
+        let prices = self.get::<SensorPrices>("home-assistant", &query).await?;
+
+        Ok(price_chart(
+            &[prices.raw_today, prices.raw_tomorrow].concat(),
+            &prices.unit_of_measurement,
+            Utc::now(),
+        ))
+    }
+
+    /// A table of the cheapest upcoming window of every duration
+    pub(crate) async fn windows(
+        &self,
+        durations: &[i32],
+        area: Option<String>,
+    ) -> Result<String, String> {
+        let now = Local::now().fixed_offset();
+
+        let mut query = vec![
+            (
+                "durations",
+                durations
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<String>>()
+                    .join(","),
+            ),
+            ("moment_start", now.to_rfc3339()),
+            (
+                "moment_end",
+                (now + TimeDelta::hours(WINDOW_HOURS)).to_rfc3339(),
+            ),
+        ];
+        query.extend(area.map(|area| ("area", area)));
+
+        let windows = self.get::<Vec<Window>>("time-slots", &query).await?;
+
+        Ok(window_table(&windows))
+    }
+}
+
+/// A horizontal bar per price, grouped by day, with bars that start at zero or, when prices are
+/// negative, at the lowest price
+fn price_chart(prices: &[RawPrice], unit: &str, now: DateTime<Utc>) -> String {
+    if prices.is_empty() {
+        return "no prices are known\n".to_string();
+    }
+
+    let lowest = prices.iter().map(|price| price.value).fold(0.0, f64::min);
+    let highest = prices
+        .iter()
+        .map(|price| price.value)
+        .fold(f64::MIN, f64::max);
+    let range = (highest - lowest).max(f64::EPSILON);
+
+    let mut chart = format!("prices in {}\n", unit);
+    let mut day = None;
+
+    for price in prices {
+        if day != Some(price.start.date_naive()) {
+            day = Some(price.start.date_naive());
+            chart.push_str(&format!("\n{}\n", price.start.format("%A %-d %B")));
+        }
+
+        let current = price.start <= now && now < price.end;
+        let bar = ((price.value - lowest) / range * CHART_WIDTH as f64).round() as usize;
+
+        chart.push_str(&format!(
+            "{} {} {:<width$} {:.4}\n",
+            if current { ">" } else { " " },
+            price.start.format("%H:%M"),
+            "#".repeat(bar),
+            price.value,
+            width = CHART_WIDTH
+        ));
+    }
+
+    chart
+}
+
+/// A row per window with its duration, start, end and average price
+fn window_table(windows: &[Window]) -> String {
+    if windows.is_empty() {
+        return "no window fits in the known prices\n".to_string();
+    }
+
+    let mut table = format!(
+        "{:<9} {:<17} {:<17} {}\n",
+        "duration", "starts", "ends", "average"
+    );
+
+    for window in windows {
+        // a window ends at the last minute it covers
+        let minutes = (window.ends_at - window.starts_at).num_minutes() + 1;
+
+        table.push_str(&format!(
+            "{:<9} {:<17} {:<17} {} {}\n",
+            format!("{}h", minutes as f64 / 60.0),
+            window.starts_at.format("%a %d %H:%M"),
+            window.ends_at.format("%a %d %H:%M"),
+            window.average_price,
+            window.currency
+        ));
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{local_moment, moment};
+
+    #[test]
+    fn test_price_chart() {
+        let prices = [0.1, 0.2, -0.05]
+            .iter()
+            .enumerate()
+            .map(|(hour, value)| RawPrice {
+                start: local_moment("2024-06-30T22:00:00+02:00") + TimeDelta::hours(hour as i64),
+                end: local_moment("2024-06-30T23:00:00+02:00") + TimeDelta::hours(hour as i64),
+                value: *value,
+            })
+            .collect::<Vec<RawPrice>>();
+
+        let chart = price_chart(&prices, "EUR/kWh", moment("2024-06-30T23:30:00+02:00"));
+        let lines = chart.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], "prices in EUR/kWh");
+        assert_eq!(lines[2], "Sunday 30 June");
+        // the highest price has the longest bar, the negative price none
+        assert!(lines[4].starts_with(&format!("> 23:00 {}", "#".repeat(CHART_WIDTH))));
+        assert_eq!(lines[6], "Monday 1 July");
+        assert!(lines[7].starts_with("  00:00  "));
+        assert!(lines[7].ends_with("-0.0500"));
+    }
+}
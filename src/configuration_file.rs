@@ -0,0 +1,237 @@
+use std::{collections::BTreeMap, env::VarError, sync::RwLock};
+
+use toml::{Table, Value};
+
+/// The variable that holds the path of the configuration file
+const CONFIGURATION_FILE_VARIABLE: &str = "ELECTRACK_CONFIG";
+
+/// The suffix of the variables that hold the path of the file of which the contents are the
+/// value of the variable without it
+const SECRET_FILE_SUFFIX: &str = "_FILE";
+
+/// The values of the variables that were read from the configuration file and secret files.
+/// They are kept apart from the environment, which must not be changed once other threads run.
+static FILE_VARIABLES: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// The value of a configuration variable, of the environment or else of the configuration file
+/// and secret files that were read, to read the configuration from instead of `std::env::var`
+pub(crate) fn variable(name: &str) -> Result<String, VarError> {
+    match std::env::var(name) {
+        Err(VarError::NotPresent) => FILE_VARIABLES
+            .read()
+            .ok()
+            .and_then(|variables| variables.get(name).cloned())
+            .ok_or(VarError::NotPresent),
+        value => value,
+    }
+}
+
+/// Read the TOML file that `ELECTRACK_CONFIG` points to, if any. A key configures the variable
+/// it names in uppercase, of which the tables it is in are the prefix, e.g. `url` in `[mqtt]`
+/// configures `MQTT_URL`. Variables that are set in the environment or read from a secret file
+/// already are kept, so they override the file. Returns the path of the file that was read.
+pub(crate) fn load_configuration_file() -> Result<Option<String>, String> {
+    let mut variables = FILE_VARIABLES.write().map_err(|e| e.to_string())?;
+
+    read_configuration_file(&mut variables)
+}
+
+/// The variables of the secret files and the configuration file as they were read again
+pub(crate) struct FileVariables(BTreeMap<String, String>);
+
+/// Read the secret files and the configuration file again, so changes to them are picked up once
+/// they replace the variables that were read before
+pub(crate) fn reread_files() -> Result<FileVariables, String> {
+    let mut variables = BTreeMap::new();
+
+    read_secret_files(&mut variables)?;
+    read_configuration_file(&mut variables)?;
+
+    Ok(FileVariables(variables))
+}
+
+/// Replace the variables that were read from the files, returning those they replaced
+pub(crate) fn replace_file_variables(variables: FileVariables) -> Result<FileVariables, String> {
+    let mut stored = FILE_VARIABLES.write().map_err(|e| e.to_string())?;
+
+    Ok(FileVariables(std::mem::replace(&mut *stored, variables.0)))
+}
+
+fn read_configuration_file(
+    variables: &mut BTreeMap<String, String>,
+) -> Result<Option<String>, String> {
+    let Ok(path) = std::env::var(CONFIGURATION_FILE_VARIABLE) else {
+        return Ok(None);
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("unable to read the configuration file {}, {}", path, e))?;
+
+    let configured = configuration_variables(&contents)
+        .map_err(|e| format!("unable to parse the configuration file {}, {}", path, e))?;
+
+    for (name, value) in configured {
+        if std::env::var_os(&name).is_none() {
+            variables.entry(name).or_insert(value);
+        }
+    }
+
+    Ok(Some(path))
+}
+
+/// Read the value of every variable of which a `_FILE` variable holds the path, such as
+/// `DATABASE_URL_FILE`, unless the variable itself is set, so secrets can be mounted as files.
+/// A trailing newline is left out. Returns the names of the variables that were read.
+pub(crate) fn load_secret_files() -> Result<Vec<String>, String> {
+    let mut variables = FILE_VARIABLES.write().map_err(|e| e.to_string())?;
+
+    read_secret_files(&mut variables)
+}
+
+fn read_secret_files(variables: &mut BTreeMap<String, String>) -> Result<Vec<String>, String> {
+    // variables that are not unicode are left alone rather than failing
+    let secrets =
+        secret_files(std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }));
+
+    for (name, path) in &secrets {
+        let secret = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read {} from {}, {}", name, path, e))?;
+
+        variables.insert(
+            name.clone(),
+            secret.trim_end_matches(['\r', '\n']).to_string(),
+        );
+    }
+
+    Ok(secrets.into_iter().map(|(name, _)| name).collect())
+}
+
+/// The variables that are read from the file of their `_FILE` variable, with its path
+fn secret_files(variables: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
+    let variables = variables.collect::<Vec<(String, String)>>();
+
+    variables
+        .iter()
+        .filter_map(|(name, path)| Some((name.strip_suffix(SECRET_FILE_SUFFIX)?, path)))
+        .filter(|(name, _)| !name.is_empty() && !variables.iter().any(|(set, _)| set == name))
+        .map(|(name, path)| (name.to_string(), path.clone()))
+        .collect()
+}
+
+/// The variables a configuration file configures, of which lists are separated by commas
+fn configuration_variables(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let table = contents.parse::<Table>().map_err(|e| e.to_string())?;
+
+    let mut variables = vec![];
+    flatten("", &table, &mut variables)?;
+
+    Ok(variables)
+}
+
+fn flatten(
+    prefix: &str,
+    table: &Table,
+    variables: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        let name = format!("{}{}", prefix, key.to_uppercase().replace('-', "_"));
+
+        match value {
+            Value::Table(table) => flatten(&format!("{}_", name), table, variables)?,
+            Value::Array(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| {
+                        scalar(value).ok_or(format!("{} must be a list of plain values", name))
+                    })
+                    .collect::<Result<Vec<String>, String>>()?;
+
+                variables.push((name, values.join(",")));
+            }
+            value => variables.push((name, scalar(value).unwrap_or_default())),
+        }
+    }
+
+    Ok(())
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        Value::Datetime(value) => Some(value.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configuration_variables() {
+        let variables = configuration_variables(
+            r#"
+            timezone = "Europe/Amsterdam"
+            price-cache-seconds = 300
+
+            [mqtt]
+            url = "mqtt://localhost:1883"
+            window_durations = [1, 2, 3]
+            retain = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            variables,
+            vec![
+                ("MQTT_RETAIN".to_string(), "true".to_string()),
+                ("MQTT_URL".to_string(), "mqtt://localhost:1883".to_string()),
+                ("MQTT_WINDOW_DURATIONS".to_string(), "1,2,3".to_string()),
+                ("PRICE_CACHE_SECONDS".to_string(), "300".to_string()),
+                ("TIMEZONE".to_string(), "Europe/Amsterdam".to_string()),
+            ]
+        );
+        assert!(configuration_variables("webhook_urls = [[\"https://example.com\"]]").is_err());
+    }
+
+    #[test]
+    fn test_variable() {
+        FILE_VARIABLES.write().unwrap().extend([
+            (
+                "ELECTRACK_TEST_FILE_VARIABLE".to_string(),
+                "file".to_string(),
+            ),
+            ("PATH".to_string(), "file".to_string()),
+        ]);
+
+        assert_eq!(variable("ELECTRACK_TEST_FILE_VARIABLE").unwrap(), "file");
+        // the environment overrides the files
+        assert_ne!(variable("PATH").unwrap(), "file");
+        assert!(variable("ELECTRACK_TEST_UNSET_VARIABLE").is_err());
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let variables = [
+            ("DATABASE_URL_FILE", "/run/secrets/database_url"),
+            ("ADMIN_TOKEN_FILE", "/run/secrets/admin_token"),
+            ("ADMIN_TOKEN", "set"),
+            ("_FILE", "/run/secrets/nothing"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert_eq!(
+            secret_files(variables.into_iter()),
+            vec![(
+                "DATABASE_URL".to_string(),
+                "/run/secrets/database_url".to_string()
+            )]
+        );
+    }
+}
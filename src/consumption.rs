@@ -0,0 +1,79 @@
+use chrono::{DurationRound, TimeDelta};
+
+use crate::domain::Consumption;
+
+/// Check that a reading covers a slot within a single hour, so it can be priced at the price of
+/// that hour
+pub(crate) fn validate_reading(reading: &Consumption) -> Result<(), String> {
+    if reading.ends_at <= reading.starts_at {
+        return Err(format!(
+            "the reading starting at {} must end after it starts",
+            reading.starts_at
+        ));
+    }
+
+    let hour = reading
+        .starts_at
+        .duration_trunc(TimeDelta::hours(1))
+        .map_err(|e| e.to_string())?;
+
+    if reading.ends_at > hour + TimeDelta::hours(1) {
+        return Err(format!(
+            "the reading starting at {} must not extend into the next hour",
+            reading.starts_at
+        ));
+    }
+
+    if !reading.energy_kwh.is_finite() || reading.energy_kwh < 0.0 {
+        return Err(format!(
+            "the energy of the reading starting at {} must not be negative",
+            reading.starts_at
+        ));
+    }
+
+    if !reading.exported_kwh.is_finite() || reading.exported_kwh < 0.0 {
+        return Err(format!(
+            "the exported energy of the reading starting at {} must not be negative",
+            reading.starts_at
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn reading(start: &str, end: &str, energy_kwh: f64) -> Consumption {
+        let moment = |time: &str| {
+            DateTime::parse_from_rfc3339(&format!("2024-06-30T{}:00Z", time))
+                .unwrap()
+                .to_utc()
+        };
+
+        Consumption {
+            starts_at: moment(start),
+            ends_at: moment(end),
+            energy_kwh,
+            exported_kwh: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_validate_reading() {
+        assert!(validate_reading(&reading("10:00", "11:00", 0.4)).is_ok());
+        assert!(validate_reading(&reading("10:45", "11:00", 0.1)).is_ok());
+        assert!(validate_reading(&reading("10:00", "10:00", 0.1)).is_err());
+        assert!(validate_reading(&reading("10:30", "11:30", 0.4)).is_err());
+        assert!(validate_reading(&reading("10:00", "11:00", -0.1)).is_err());
+        assert!(validate_reading(&reading("10:00", "11:00", f64::NAN)).is_err());
+        assert!(validate_reading(&Consumption {
+            exported_kwh: -0.1,
+            ..reading("10:00", "11:00", 0.4)
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Days, DurationRound, TimeDelta, TimeZone, Timelike, Utc};
+
+use crate::{
+    domain::{start_of_day, Consumption},
+    setup::AppState,
+};
+
+/// The number of weeks before today of which the consumption is learned
+const PROFILE_WEEKS: u64 = 8;
+
+/// The typical consumption of every local hour of the week
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConsumptionProfile {
+    /// The average consumption by the day of the week from Monday and the hour of the day
+    hourly_kwh: HashMap<(u32, u32), f64>,
+    /// The average consumption of all hours, for the hours of the week without history
+    average_kwh: f64,
+}
+
+impl ConsumptionProfile {
+    /// Learn the average consumption of every local hour of the week from readings, counting
+    /// only the hours with readings. None without any readings.
+    pub(crate) fn learn<Tz: TimeZone>(readings: &[Consumption], timezone: &Tz) -> Option<Self> {
+        let mut hours = HashMap::<DateTime<Utc>, f64>::new();
+
+        for reading in readings {
+            let hour = reading
+                .starts_at
+                .duration_trunc(TimeDelta::hours(1))
+                .unwrap_or(reading.starts_at);
+
+            *hours.entry(hour).or_default() += reading.energy_kwh;
+        }
+
+        if hours.is_empty() {
+            return None;
+        }
+
+        let mut totals = HashMap::<(u32, u32), (f64, usize)>::new();
+
+        for (hour, energy_kwh) in &hours {
+            let (total, count) = totals.entry(hour_of_week(timezone, *hour)).or_default();
+
+            *total += energy_kwh;
+            *count += 1;
+        }
+
+        Some(Self {
+            hourly_kwh: totals
+                .into_iter()
+                .map(|(hour, (total, count))| (hour, total / count as f64))
+                .collect(),
+            average_kwh: hours.values().sum::<f64>() / hours.len() as f64,
+        })
+    }
+
+    /// The consumption expected in the hour that starts at a moment
+    pub(crate) fn expected_kwh<Tz: TimeZone>(&self, timezone: &Tz, hour: DateTime<Utc>) -> f64 {
+        self.hourly_kwh
+            .get(&hour_of_week(timezone, hour))
+            .copied()
+            .unwrap_or(self.average_kwh)
+    }
+
+    /// The consumption expected in the hours between two moments, where the hours the moments
+    /// fall in count in part
+    pub(crate) fn expected_between<Tz: TimeZone>(
+        &self,
+        timezone: &Tz,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> f64 {
+        let mut hour = start.duration_trunc(TimeDelta::hours(1)).unwrap_or(start);
+        let mut total = 0.0;
+
+        while hour < end {
+            let next = hour + TimeDelta::hours(1);
+            let covered = (next.min(end) - hour.max(start)).num_seconds() as f64 / 3600.0;
+
+            total += self.expected_kwh(timezone, hour) * covered;
+            hour = next;
+        }
+
+        total
+    }
+}
+
+/// The day of the week from Monday and the hour of the day a moment falls in locally
+fn hour_of_week<Tz: TimeZone>(timezone: &Tz, moment: DateTime<Utc>) -> (u32, u32) {
+    let local = moment.with_timezone(timezone);
+
+    (local.weekday().num_days_from_monday(), local.hour())
+}
+
+/// Learn the profile of the consumption of the weeks before today, None without readings
+pub(crate) async fn learn_profile(
+    state: &AppState,
+    now: DateTime<Utc>,
+) -> Result<Option<ConsumptionProfile>, String> {
+    let today = now.with_timezone(&state.timezone).date_naive();
+
+    let readings = state
+        .consumption_repository
+        .fetch_consumption(
+            start_of_day(&state.timezone, today - Days::new(PROFILE_WEEKS * 7)),
+            start_of_day(&state.timezone, today),
+        )
+        .await?;
+
+    Ok(ConsumptionProfile::learn(&readings, &state.timezone))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Amsterdam;
+
+    use super::*;
+    use crate::test_support::moment;
+
+    fn reading(start: &str, energy_kwh: f64) -> Consumption {
+        Consumption {
+            starts_at: moment(start),
+            ends_at: moment(start) + TimeDelta::minutes(30),
+            energy_kwh,
+            exported_kwh: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_consumption_profile() {
+        assert_eq!(ConsumptionProfile::learn(&[], &Amsterdam), None);
+
+        // Sunday 30 June 2024 and the Sunday before, from 20:00 local time
+        let profile = ConsumptionProfile::learn(
+            &[
+                reading("2024-06-23T18:00:00Z", 0.5),
+                reading("2024-06-23T18:30:00Z", 0.5),
+                reading("2024-06-30T18:00:00Z", 2.0),
+                reading("2024-06-30T19:00:00Z", 0.5),
+            ],
+            &Amsterdam,
+        )
+        .unwrap();
+
+        assert_eq!(
+            profile.expected_kwh(&Amsterdam, moment("2024-07-07T18:00:00Z")),
+            1.5
+        );
+        assert_eq!(
+            profile.expected_kwh(&Amsterdam, moment("2024-07-07T19:00:00Z")),
+            0.5
+        );
+        // a Monday without history is expected to use the average hour
+        assert_eq!(
+            profile.expected_kwh(&Amsterdam, moment("2024-07-08T18:00:00Z")),
+            3.5 / 3.0
+        );
+
+        assert_eq!(
+            profile.expected_between(
+                &Amsterdam,
+                moment("2024-07-07T18:30:00Z"),
+                moment("2024-07-07T20:00:00Z")
+            ),
+            1.25
+        );
+    }
+}
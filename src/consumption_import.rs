@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::domain::Consumption;
+
+/// The formats of local times that exports of grid operators use, besides RFC 3339
+const LOCAL_TIME_FORMATS: [&str; 6] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d-%m-%Y %H:%M:%S",
+    "%d-%m-%Y %H:%M",
+    "%d/%m/%Y %H:%M",
+];
+
+/// The names of the columns of a generic export, in lowercase
+const START_COLUMNS: [&str; 4] = ["starts_at", "from", "van", "start"];
+const END_COLUMNS: [&str; 4] = ["ends_at", "to", "tot", "end"];
+const ENERGY_COLUMNS: [&str; 5] = [
+    "energy_kwh",
+    "consumption",
+    "verbruik",
+    "afname",
+    "levering",
+];
+const EXPORTED_COLUMNS: [&str; 4] = ["exported_kwh", "export", "teruglevering", "injectie"];
+
+/// Parse the readings of an export of historical consumption, of which times without an offset
+/// are interpreted in a timezone. Either the export of Fluvius, with a row per register of a
+/// slot, or a generic export with a column for the start and end of every slot, for the energy
+/// drawn from the grid, and optionally for the energy fed back into it, such as those of
+/// Liander. Columns are separated by commas or semicolons and decimals may have a comma.
+pub(crate) fn parse_consumption_csv<Tz: TimeZone>(
+    csv: &str,
+    timezone: &Tz,
+) -> Result<Vec<Consumption>, String> {
+    let mut lines = csv
+        .lines()
+        .map(|line| line.trim_start_matches('\u{feff}'))
+        .filter(|line| !line.trim().is_empty());
+
+    let header = lines.next().ok_or("the export is empty".to_string())?;
+    let delimiter = if header.contains(';') { ';' } else { ',' };
+
+    let columns = split(header, delimiter)
+        .into_iter()
+        .map(|column| column.to_lowercase())
+        .collect::<Vec<String>>();
+
+    let column = |names: &[&str]| {
+        columns.iter().position(|column| {
+            names
+                .iter()
+                .any(|name| column == name || column.starts_with(&format!("{} ", name)))
+        })
+    };
+
+    let rows = lines.map(|line| split(line, delimiter));
+
+    match (column(&["register"]), column(&["volume"])) {
+        (Some(register), Some(volume)) => {
+            let required = |name: &str| {
+                columns
+                    .iter()
+                    .position(|column| column == name)
+                    .ok_or(format!("the Fluvius export has no column \"{}\"", name))
+            };
+
+            let fluvius = FluviusColumns {
+                start_date: required("van (datum)")?,
+                start_time: required("van (tijdstip)")?,
+                end_date: required("tot (datum)")?,
+                end_time: required("tot (tijdstip)")?,
+                register,
+                volume,
+            };
+
+            parse_fluvius(rows, &fluvius, timezone)
+        }
+        _ => {
+            let missing = |kind: &str| format!("the export has no column for the {}", kind);
+
+            let generic = GenericColumns {
+                start: column(&START_COLUMNS).ok_or(missing("start of a slot"))?,
+                end: column(&END_COLUMNS).ok_or(missing("end of a slot"))?,
+                energy: column(&ENERGY_COLUMNS).ok_or(missing("consumption"))?,
+                exported: column(&EXPORTED_COLUMNS),
+            };
+
+            parse_generic(rows, &generic, timezone)
+        }
+    }
+}
+
+struct GenericColumns {
+    start: usize,
+    end: usize,
+    energy: usize,
+    exported: Option<usize>,
+}
+
+struct FluviusColumns {
+    start_date: usize,
+    start_time: usize,
+    end_date: usize,
+    end_time: usize,
+    register: usize,
+    volume: usize,
+}
+
+fn parse_generic<Tz: TimeZone>(
+    rows: impl Iterator<Item = Vec<String>>,
+    columns: &GenericColumns,
+    timezone: &Tz,
+) -> Result<Vec<Consumption>, String> {
+    rows.enumerate()
+        .map(|(index, row)| {
+            let field = |column: usize| row.get(column).map(String::as_str).unwrap_or_default();
+            let row_error = |e: String| format!("row {}: {}", index + 1, e);
+
+            Ok(Consumption {
+                starts_at: parse_moment(field(columns.start), timezone).map_err(row_error)?,
+                ends_at: parse_moment(field(columns.end), timezone).map_err(row_error)?,
+                energy_kwh: parse_kwh(field(columns.energy)).map_err(row_error)?,
+                exported_kwh: match columns.exported.map(field) {
+                    Some(exported) if !exported.is_empty() => {
+                        parse_kwh(exported).map_err(row_error)?
+                    }
+                    _ => 0.0,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Fluvius has a row for every register of a slot, `Afname Dag` and `Afname Nacht` for the
+/// energy drawn from the grid and `Injectie Dag` and `Injectie Nacht` for the energy fed back,
+/// of which the volumes are added up. Rows without a volume are left out.
+fn parse_fluvius<Tz: TimeZone>(
+    rows: impl Iterator<Item = Vec<String>>,
+    columns: &FluviusColumns,
+    timezone: &Tz,
+) -> Result<Vec<Consumption>, String> {
+    let mut slots = BTreeMap::<DateTime<Utc>, Consumption>::new();
+
+    for (index, row) in rows.enumerate() {
+        let field = |column: usize| row.get(column).map(String::as_str).unwrap_or_default();
+        let row_error = |e: String| format!("row {}: {}", index + 1, e);
+
+        if field(columns.volume).is_empty() {
+            continue;
+        }
+
+        let starts_at = parse_moment(
+            &format!(
+                "{} {}",
+                field(columns.start_date),
+                field(columns.start_time)
+            ),
+            timezone,
+        )
+        .map_err(row_error)?;
+        let ends_at = parse_moment(
+            &format!("{} {}", field(columns.end_date), field(columns.end_time)),
+            timezone,
+        )
+        .map_err(row_error)?;
+        let volume = parse_kwh(field(columns.volume)).map_err(row_error)?;
+
+        let slot = slots.entry(starts_at).or_insert(Consumption {
+            starts_at,
+            ends_at,
+            energy_kwh: 0.0,
+            exported_kwh: 0.0,
+        });
+
+        let register = field(columns.register).to_lowercase();
+
+        if register.starts_with("afname") {
+            slot.energy_kwh += volume;
+        } else if register.starts_with("injectie") {
+            slot.exported_kwh += volume;
+        } else {
+            return Err(row_error(format!("unknown register \"{}\"", register)));
+        }
+    }
+
+    Ok(slots.into_values().collect())
+}
+
+/// Split a line into its fields, without the quotes around them
+fn split(line: &str, delimiter: char) -> Vec<String> {
+    line.split(delimiter)
+        .map(|field| field.trim().trim_matches('"').trim().to_string())
+        .collect()
+}
+
+fn parse_moment<Tz: TimeZone>(moment: &str, timezone: &Tz) -> Result<DateTime<Utc>, String> {
+    if let Ok(moment) = DateTime::parse_from_rfc3339(moment) {
+        return Ok(moment.to_utc());
+    }
+
+    LOCAL_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(moment, format).ok())
+        .and_then(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|moment| moment.to_utc())
+        .ok_or(format!("unable to parse the moment \"{}\"", moment))
+}
+
+fn parse_kwh(energy: &str) -> Result<f64, String> {
+    energy
+        .replace(',', ".")
+        .parse::<f64>()
+        .map_err(|_| format!("unable to parse the energy \"{}\"", energy))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::{Amsterdam, Brussels};
+
+    use super::*;
+    use crate::test_support::moment;
+
+    #[test]
+    fn test_parse_generic() {
+        let csv = "\u{feff}Van;Tot;Verbruik (kWh);Teruglevering (kWh)\n\
+            2024-06-30 10:00;2024-06-30 10:15;0,123;0,5\n\
+            \n\
+            2024-06-30 10:15;2024-06-30 10:30;0,2;\n";
+
+        let readings = parse_consumption_csv(csv, &Amsterdam).unwrap();
+
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].starts_at, moment("2024-06-30T08:00:00Z"));
+        assert_eq!(readings[0].ends_at, moment("2024-06-30T08:15:00Z"));
+        assert_eq!(readings[0].energy_kwh, 0.123);
+        assert_eq!(readings[0].exported_kwh, 0.5);
+        assert_eq!(readings[1].exported_kwh, 0.0);
+
+        let csv =
+            "starts_at,ends_at,energy_kwh\n2024-06-30T10:00:00+02:00,2024-06-30T11:00:00+02:00,1.5";
+
+        assert_eq!(
+            parse_consumption_csv(csv, &Amsterdam).unwrap()[0].energy_kwh,
+            1.5
+        );
+
+        assert_eq!(
+            parse_consumption_csv("starts_at,energy_kwh\n", &Amsterdam),
+            Err("the export has no column for the end of a slot".to_string())
+        );
+        assert_eq!(
+            parse_consumption_csv("from,to,energy_kwh\n30-06-2024,x,1", &Amsterdam),
+            Err("row 1: unable to parse the moment \"30-06-2024\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_fluvius() {
+        let csv = "Van (datum);Van (tijdstip);Tot (datum);Tot (tijdstip);EAN-code;Meter;Metertype;Register;Volume;Eenheid;Validatiestatus;Omschrijving\n\
+            30-06-2024;10:00:00;30-06-2024;10:15:00;=\"541448800000000000\";1SAG1100000000;Digitale Meter;Afname Dag;0,100;kWh;Gevalideerd;\n\
+            30-06-2024;10:00:00;30-06-2024;10:15:00;=\"541448800000000000\";1SAG1100000000;Digitale Meter;Afname Nacht;0,000;kWh;Gevalideerd;\n\
+            30-06-2024;10:00:00;30-06-2024;10:15:00;=\"541448800000000000\";1SAG1100000000;Digitale Meter;Injectie Dag;0,250;kWh;Gevalideerd;\n\
+            30-06-2024;10:15:00;30-06-2024;10:30:00;=\"541448800000000000\";1SAG1100000000;Digitale Meter;Afname Dag;;kWh;Geen verbruik;\n";
+
+        let readings = parse_consumption_csv(csv, &Brussels).unwrap();
+
+        assert_eq!(
+            readings,
+            vec![Consumption {
+                starts_at: moment("2024-06-30T08:00:00Z"),
+                ends_at: moment("2024-06-30T08:15:00Z"),
+                energy_kwh: 0.1,
+                exported_kwh: 0.25,
+            }]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::domain::{Consumption, DeviceConsumption};
+
+/// How many readings are inserted per statement, well within the limit of bound parameters
+pub(crate) const CONSUMPTION_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum ConsumptionRepositoryError {
+    #[error("the consumption could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait ConsumptionRepository: Send + Sync {
+    /// Fetch the readings of the slots that start between two moments
+    async fn fetch_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Consumption>, String>;
+
+    /// Persist meter readings in a single transaction, replacing those that start at the same
+    /// moment
+    async fn persist_consumption(
+        &self,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError>;
+
+    /// Fetch the readings of the devices of the slots that start between two moments, of a
+    /// single device or of all of them
+    async fn fetch_device_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        device_id: Option<i64>,
+    ) -> Result<Vec<DeviceConsumption>, String>;
+
+    /// Persist the readings of a device in a single transaction, replacing those of the device
+    /// that start at the same moment
+    async fn persist_device_consumption(
+        &self,
+        device_id: i64,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresConsumptionRepository {
+    db: PgPool,
+}
+
+impl PostgresConsumptionRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ConsumptionRepository for PostgresConsumptionRepository {
+    async fn fetch_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Consumption>, String> {
+        sqlx::query_as::<_, Consumption>(
+            r#"
+            select starts_at, ends_at, energy_kwh, exported_kwh
+            from consumption
+            where starts_at >= $1 and starts_at < $2
+            order by starts_at
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_consumption(
+        &self,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        if readings.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting {} consumption readings", readings.len());
+
+        let error = |e: sqlx::Error| ConsumptionRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in readings.chunks(CONSUMPTION_BATCH_SIZE) {
+            let mut query_builder = QueryBuilder::new(
+                "insert into consumption (starts_at, ends_at, energy_kwh, exported_kwh)",
+            );
+
+            query_builder.push_values(batch, |mut builder, reading| {
+                builder
+                    .push_bind(reading.starts_at)
+                    .push_bind(reading.ends_at)
+                    .push_bind(reading.energy_kwh)
+                    .push_bind(reading.exported_kwh);
+            });
+
+            query_builder.push(
+                r#"
+                on conflict (starts_at) do update
+                set ends_at = excluded.ends_at,
+                    energy_kwh = excluded.energy_kwh,
+                    exported_kwh = excluded.exported_kwh
+                "#,
+            );
+
+            query_builder
+                .build()
+                .persistent(false)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+
+    async fn fetch_device_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        device_id: Option<i64>,
+    ) -> Result<Vec<DeviceConsumption>, String> {
+        sqlx::query_as::<_, DeviceConsumption>(
+            r#"
+            select device_id, starts_at, ends_at, energy_kwh, 0::double precision as exported_kwh
+            from device_consumption
+            where starts_at >= $1 and starts_at < $2 and ($3::bigint is null or device_id = $3)
+            order by device_id, starts_at
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(device_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_device_consumption(
+        &self,
+        device_id: i64,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        if readings.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Persisting {} consumption readings of device {}",
+            readings.len(),
+            device_id
+        );
+
+        let error = |e: sqlx::Error| ConsumptionRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in readings.chunks(CONSUMPTION_BATCH_SIZE) {
+            let mut query_builder = QueryBuilder::new(
+                "insert into device_consumption (device_id, starts_at, ends_at, energy_kwh)",
+            );
+
+            query_builder.push_values(batch, |mut builder, reading| {
+                builder
+                    .push_bind(device_id)
+                    .push_bind(reading.starts_at)
+                    .push_bind(reading.ends_at)
+                    .push_bind(reading.energy_kwh);
+            });
+
+            query_builder.push(
+                r#"
+                on conflict (device_id, starts_at) do update
+                set ends_at = excluded.ends_at, energy_kwh = excluded.energy_kwh
+                "#,
+            );
+
+            query_builder
+                .build()
+                .persistent(false)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+}
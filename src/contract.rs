@@ -0,0 +1,116 @@
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::{domain::round, setup::AppState};
+
+/// The terms of the energy contract that do not depend on the price of the hour, all including
+/// VAT
+#[derive(Debug, Clone, Default, PartialEq, FromRow, Serialize, Deserialize)]
+pub(crate) struct Contract {
+    /// What the grid operator charges for the connection per day
+    pub(crate) standing_charge_per_day: f64,
+    /// What the supplier charges per month regardless of consumption
+    pub(crate) fixed_fee_per_month: f64,
+    /// A reduction of the energy tax per connection per month, such as the Dutch "vermindering
+    /// energiebelasting", which is subtracted
+    pub(crate) tax_credit_per_month: f64,
+    /// What is paid per kWh fed back into the grid, when it is not the price of the hour
+    pub(crate) feed_in_compensation: Option<f64>,
+}
+
+/// The charges of a contract over a period that do not depend on consumption
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct FixedCharges {
+    pub(crate) standing_charge: f64,
+    pub(crate) fixed_fee: f64,
+    pub(crate) tax_credit: f64,
+}
+
+impl FixedCharges {
+    pub(crate) fn total(&self) -> f64 {
+        self.standing_charge + self.fixed_fee - self.tax_credit
+    }
+
+    pub(crate) fn rounded(&self) -> FixedCharges {
+        FixedCharges {
+            standing_charge: round(self.standing_charge, 2),
+            fixed_fee: round(self.fixed_fee, 2),
+            tax_credit: round(self.tax_credit, 2),
+        }
+    }
+}
+
+impl Contract {
+    /// The fixed charges of the days from a date until another, of which every day carries
+    /// its share of the monthly charges of the month it falls in
+    pub(crate) fn fixed_charges(&self, from: NaiveDate, until: NaiveDate) -> FixedCharges {
+        from.iter_days().take_while(|date| *date < until).fold(
+            FixedCharges::default(),
+            |total, date| {
+                let month_days = days_in_month(date) as f64;
+
+                FixedCharges {
+                    standing_charge: total.standing_charge + self.standing_charge_per_day,
+                    fixed_fee: total.fixed_fee + self.fixed_fee_per_month / month_days,
+                    tax_credit: total.tax_credit + self.tax_credit_per_month / month_days,
+                }
+            },
+        )
+    }
+}
+
+/// The number of days of the month a date falls in
+fn days_in_month(date: NaiveDate) -> u64 {
+    let first = date.with_day(1).unwrap();
+
+    ((first + Months::new(1)) - first).num_days() as u64
+}
+
+/// The contract that is stored through the API, or else the configured one
+pub(crate) async fn current_contract(state: &AppState) -> Result<Contract, String> {
+    Ok(state
+        .contract_repository
+        .fetch_contract()
+        .await?
+        .unwrap_or_else(|| state.settings().pricing.contract.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_charges() {
+        let contract = Contract {
+            standing_charge_per_day: 1.0,
+            fixed_fee_per_month: 6.0,
+            tax_credit_per_month: 30.0,
+            feed_in_compensation: None,
+        };
+
+        let date = |date: &str| date.parse::<NaiveDate>().unwrap();
+
+        let june = contract.fixed_charges(date("2024-06-01"), date("2024-07-01"));
+
+        assert_eq!(
+            june.rounded(),
+            FixedCharges {
+                standing_charge: 30.0,
+                fixed_fee: 6.0,
+                tax_credit: 30.0,
+            }
+        );
+        assert!((june.total() - 6.0).abs() < 1e-9);
+
+        // a day carries the share of the month it falls in
+        let days = contract.fixed_charges(date("2024-02-29"), date("2024-03-01"));
+
+        assert!((days.tax_credit - 30.0 / 29.0).abs() < 1e-9);
+
+        assert_eq!(
+            contract.fixed_charges(date("2024-06-01"), date("2024-06-01")),
+            FixedCharges::default()
+        );
+    }
+}
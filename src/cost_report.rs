@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Datelike, DurationRound, TimeDelta, TimeZone, Utc};
+use serde::Deserialize;
+
+use crate::{
+    contract::current_contract,
+    domain::{start_of_day, Consumption},
+    formula::FormulaApplication,
+    setup::AppState,
+    tariff::PriceComponents,
+};
+
+/// The length of the periods consumption is reported in
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Granularity {
+    Hour,
+    Day,
+    /// A week from Monday
+    Week,
+}
+
+impl Granularity {
+    /// The start of the period a moment falls in, of which days and weeks are local
+    fn period_start<Tz: TimeZone>(&self, timezone: &Tz, moment: DateTime<Utc>) -> DateTime<Utc> {
+        let date = moment.with_timezone(timezone).date_naive();
+
+        match self {
+            Granularity::Hour => moment.duration_trunc(TimeDelta::hours(1)).unwrap_or(moment),
+            Granularity::Day => start_of_day(timezone, date),
+            Granularity::Week => start_of_day(
+                timezone,
+                date - TimeDelta::days(date.weekday().num_days_from_monday() as i64),
+            ),
+        }
+    }
+}
+
+/// The price a consumer pays in the hour that starts at a moment
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HourlyPrice {
+    pub(crate) moment: DateTime<Utc>,
+    pub(crate) price: f64,
+    /// What is paid per kWh fed back into the grid in the hour
+    pub(crate) export_price: f64,
+    pub(crate) components: Option<PriceComponents>,
+}
+
+/// What the consumption of a period cost
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CostPeriod {
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) energy_kwh: f64,
+    pub(crate) cost: f64,
+    /// Consumption in hours of which no price is stored, which is left out of the cost
+    pub(crate) unpriced_kwh: f64,
+    /// What each component of the price cost, when all priced consumption has components
+    pub(crate) components: Option<PriceComponents>,
+    pub(crate) exported_kwh: f64,
+    /// What the energy fed back into the grid earned, of which in hours without a stored price
+    /// nothing is counted
+    pub(crate) feed_in_revenue: f64,
+}
+
+impl CostPeriod {
+    /// The cost of the consumption less what feeding back into the grid earned
+    pub(crate) fn net_cost(&self) -> f64 {
+        self.cost - self.feed_in_revenue
+    }
+
+    /// The cost per kWh of the priced consumption
+    pub(crate) fn average_price(&self) -> Option<f64> {
+        let priced_kwh = self.energy_kwh - self.unpriced_kwh;
+
+        (priced_kwh > 0.0).then(|| self.cost / priced_kwh)
+    }
+
+    /// The periods taken together as one that starts at a moment, broken down into components
+    /// when all periods with priced consumption are
+    pub(crate) fn total(starts_at: DateTime<Utc>, periods: &[CostPeriod]) -> CostPeriod {
+        CostPeriod {
+            starts_at,
+            energy_kwh: periods
+                .iter()
+                .fold(0.0, |total, period| total + period.energy_kwh),
+            cost: periods
+                .iter()
+                .fold(0.0, |total, period| total + period.cost),
+            unpriced_kwh: periods
+                .iter()
+                .fold(0.0, |total, period| total + period.unpriced_kwh),
+            exported_kwh: periods
+                .iter()
+                .fold(0.0, |total, period| total + period.exported_kwh),
+            feed_in_revenue: periods
+                .iter()
+                .fold(0.0, |total, period| total + period.feed_in_revenue),
+            components: periods
+                .iter()
+                .filter(|period| period.unpriced_kwh < period.energy_kwh)
+                .map(|period| period.components.as_ref())
+                .collect::<Option<Vec<&PriceComponents>>>()
+                .filter(|components| !components.is_empty())
+                .map(|components| {
+                    components
+                        .into_iter()
+                        .fold(PriceComponents::default(), |total, components| {
+                            add_components(total, components, 1.0)
+                        })
+                }),
+        }
+    }
+}
+
+/// The price a consumer pays in every hour between two moments, and the currency of the prices.
+/// Energy fed back into the grid earns the compensation of the contract, or else the market
+/// price of the hour.
+pub(crate) async fn fetch_hourly_prices(
+    state: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    area: Option<&str>,
+) -> Result<(Vec<HourlyPrice>, Option<String>), String> {
+    let prices = state
+        .price_repository
+        .fetch_prices(start, end, area)
+        .await?;
+
+    let feed_in_compensation = current_contract(state).await?.feed_in_compensation;
+
+    let settings = state.settings();
+    let formula = settings
+        .pricing
+        .price_formula
+        .as_ref()
+        .filter(|formula| formula.application == FormulaApplication::Response);
+
+    let hourly_prices = prices
+        .iter()
+        .map(|price| HourlyPrice {
+            moment: price.moment,
+            price: match (price.consumer_amount, formula) {
+                (Some(consumer_amount), _) => consumer_amount,
+                (None, Some(formula)) => formula.apply(price.monetary_amount),
+                (None, None) => price.monetary_amount,
+            },
+            export_price: feed_in_compensation.unwrap_or(price.monetary_amount),
+            components: price.components.clone(),
+        })
+        .collect();
+
+    Ok((
+        hourly_prices,
+        prices.first().map(|price| price.currency.clone()),
+    ))
+}
+
+/// The stored consumption between two moments with what it cost per period, at the price a
+/// consumer pays, and the currency of the prices
+pub(crate) async fn fetch_cost_periods(
+    state: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    granularity: Granularity,
+    area: Option<&str>,
+) -> Result<(Vec<CostPeriod>, Option<String>), String> {
+    let consumption = state
+        .consumption_repository
+        .fetch_consumption(start, end)
+        .await?;
+
+    let (hourly_prices, currency) = fetch_hourly_prices(state, start, end, area).await?;
+
+    Ok((
+        cost_periods(&consumption, &hourly_prices, &state.timezone, granularity),
+        currency,
+    ))
+}
+
+/// What the stored consumption of every device with readings between two moments cost, at the
+/// price a consumer pays, by device id
+pub(crate) async fn fetch_device_costs(
+    state: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    area: Option<&str>,
+) -> Result<BTreeMap<i64, CostPeriod>, String> {
+    let consumption = state
+        .consumption_repository
+        .fetch_device_consumption(start, end, None)
+        .await?;
+
+    if consumption.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let (hourly_prices, _) = fetch_hourly_prices(state, start, end, area).await?;
+
+    let mut readings = BTreeMap::<i64, Vec<Consumption>>::new();
+
+    for reading in consumption {
+        readings
+            .entry(reading.device_id)
+            .or_default()
+            .push(reading.consumption);
+    }
+
+    Ok(readings
+        .into_iter()
+        .map(|(device_id, readings)| {
+            let days = cost_periods(&readings, &hourly_prices, &state.timezone, Granularity::Day);
+
+            (device_id, CostPeriod::total(start, &days))
+        })
+        .collect())
+}
+
+/// The cost of the consumption of every period, priced at the price of the hour every reading
+/// falls in, and what the energy fed back into the grid earned. Periods without readings are
+/// left out.
+pub(crate) fn cost_periods<Tz: TimeZone>(
+    consumption: &[Consumption],
+    prices: &[HourlyPrice],
+    timezone: &Tz,
+    granularity: Granularity,
+) -> Vec<CostPeriod> {
+    let prices = prices
+        .iter()
+        .map(|price| (price.moment, price))
+        .collect::<HashMap<DateTime<Utc>, &HourlyPrice>>();
+
+    let mut periods = BTreeMap::<DateTime<Utc>, CostPeriod>::new();
+
+    for reading in consumption {
+        let starts_at = granularity.period_start(timezone, reading.starts_at);
+
+        let period = periods.entry(starts_at).or_insert_with(|| CostPeriod {
+            starts_at,
+            energy_kwh: 0.0,
+            cost: 0.0,
+            unpriced_kwh: 0.0,
+            components: Some(PriceComponents::default()),
+            exported_kwh: 0.0,
+            feed_in_revenue: 0.0,
+        });
+
+        period.energy_kwh += reading.energy_kwh;
+        period.exported_kwh += reading.exported_kwh;
+
+        let hour = reading
+            .starts_at
+            .duration_trunc(TimeDelta::hours(1))
+            .unwrap_or(reading.starts_at);
+
+        let Some(price) = prices.get(&hour) else {
+            period.unpriced_kwh += reading.energy_kwh;
+            continue;
+        };
+
+        period.cost += reading.energy_kwh * price.price;
+        period.feed_in_revenue += reading.exported_kwh * price.export_price;
+
+        period.components = match (period.components.take(), &price.components) {
+            (Some(total), Some(components)) => {
+                Some(add_components(total, components, reading.energy_kwh))
+            }
+            _ => None,
+        };
+    }
+
+    periods
+        .into_values()
+        .map(|period| CostPeriod {
+            // without any priced consumption there is nothing to break down
+            components: period
+                .components
+                .filter(|_| period.unpriced_kwh < period.energy_kwh),
+            ..period
+        })
+        .collect()
+}
+
+/// Add every component times a factor to a total
+fn add_components(
+    total: PriceComponents,
+    components: &PriceComponents,
+    factor: f64,
+) -> PriceComponents {
+    PriceComponents {
+        energy: total.energy + factor * components.energy,
+        supplier_fee: total.supplier_fee + factor * components.supplier_fee,
+        energy_tax: total.energy_tax + factor * components.energy_tax,
+        grid_fee: total.grid_fee + factor * components.grid_fee,
+        vat: total.vat + factor * components.vat,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Amsterdam;
+
+    use super::*;
+    use crate::test_support::moment;
+
+    fn reading(start: &str, energy_kwh: f64) -> Consumption {
+        Consumption {
+            starts_at: moment(start),
+            ends_at: moment(start) + TimeDelta::minutes(15),
+            energy_kwh,
+            exported_kwh: 0.0,
+        }
+    }
+
+    fn price(hour: &str, price: f64, components: Option<PriceComponents>) -> HourlyPrice {
+        HourlyPrice {
+            moment: moment(hour),
+            price,
+            export_price: price / 2.0,
+            components,
+        }
+    }
+
+    #[test]
+    fn test_cost_periods() {
+        let consumption = [
+            reading("2024-06-30T21:15:00Z", 1.0),
+            reading("2024-06-30T21:30:00Z", 1.0),
+            reading("2024-06-30T22:00:00Z", 2.0),
+            reading("2024-06-30T23:00:00Z", 0.5),
+            Consumption {
+                energy_kwh: 0.0,
+                exported_kwh: 1.0,
+                ..reading("2024-06-30T22:45:00Z", 0.0)
+            },
+        ];
+
+        let components = PriceComponents {
+            energy: 0.1,
+            supplier_fee: 0.02,
+            energy_tax: 0.1,
+            grid_fee: 0.0,
+            vat: 0.0,
+        };
+
+        let prices = [
+            price("2024-06-30T21:00:00Z", 0.2, None),
+            price("2024-06-30T22:00:00Z", 0.22, Some(components)),
+        ];
+
+        let hours = cost_periods(&consumption, &prices, &Amsterdam, Granularity::Hour);
+
+        assert_eq!(hours.len(), 3);
+        assert_eq!(hours[0].energy_kwh, 2.0);
+        assert_eq!(hours[0].components, None);
+        assert!((hours[1].cost - 0.44).abs() < 1e-9);
+        assert!((hours[1].components.as_ref().unwrap().energy - 0.2).abs() < 1e-9);
+        assert_eq!(hours[1].exported_kwh, 1.0);
+        assert!((hours[1].net_cost() - 0.33).abs() < 1e-9);
+        assert_eq!(hours[2].unpriced_kwh, 0.5);
+        assert_eq!(hours[2].components, None);
+        assert_eq!(hours[2].average_price(), None);
+
+        // the local day starts at 22:00 UTC in summer
+        let days = cost_periods(&consumption, &prices, &Amsterdam, Granularity::Day);
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[1].starts_at, moment("2024-06-30T22:00:00Z"));
+        assert_eq!(days[1].energy_kwh, 2.5);
+        assert!((days[1].average_price().unwrap() - 0.22).abs() < 1e-9);
+
+        // 30 June 2024 is a Sunday
+        let weeks = cost_periods(&consumption, &prices, &Amsterdam, Granularity::Week);
+
+        assert_eq!(weeks.len(), 2);
+        assert_eq!(weeks[0].starts_at, moment("2024-06-23T22:00:00Z"));
+        assert_eq!(weeks[1].starts_at, moment("2024-06-30T22:00:00Z"));
+    }
+}
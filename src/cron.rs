@@ -0,0 +1,236 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Datelike, NaiveTime, TimeDelta, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+/// How many days ahead to look for a moment that matches a schedule
+const MAX_DAYS_AHEAD: i64 = 366 * 4;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum CronScheduleError {
+    #[error(
+        "cron schedule \"{0}\" must have 5 fields: minute hour day-of-month month day-of-week"
+    )]
+    FieldCount(String),
+    #[error("invalid {0} \"{1}\" in cron schedule")]
+    InvalidField(&'static str, String),
+}
+
+/// A recurring schedule in the format of a crontab,
+/// `minute hour day-of-month month day-of-week`, e.g. `5 * * * *` for five past every hour or
+/// `0 13-15 * * mon-fri` for the afternoons of working days. Fields support `*`, lists, ranges
+/// and steps. Times are interpreted in the given timezone.
+#[derive(Debug, Clone)]
+pub(crate) struct CronSchedule {
+    expression: String,
+    timezone: Tz,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day of month and day of week fields are `*`. Like cron, a day matches
+    /// either of the day fields when both are restricted.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+impl CronSchedule {
+    pub(crate) fn parse(schedule: &str, timezone: Tz) -> Result<Self, CronScheduleError> {
+        let fields = schedule.split_whitespace().collect::<Vec<&str>>();
+
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(CronScheduleError::FieldCount(schedule.to_string()));
+        };
+
+        Ok(Self {
+            expression: fields.join(" "),
+            timezone,
+            minutes: parse_field(minutes, "minute", 0, 59, &[])?,
+            hours: parse_field(hours, "hour", 0, 23, &[])?,
+            days_of_month: parse_field(days_of_month, "day of month", 1, 31, &[])?,
+            months: parse_field(months, "month", 1, 12, &MONTHS)?,
+            // 7 is an alias for sunday
+            days_of_week: parse_field(days_of_week, "day of week", 0, 7, &WEEKDAYS)
+                .map(|days| (days | days >> 7) & 0x7f)?,
+            any_day_of_month: days_of_month == "*",
+            any_day_of_week: days_of_week == "*",
+        })
+    }
+
+    /// The first moment after `moment` that matches the schedule. Local times that do not
+    /// exist because of a daylight saving time transition are skipped.
+    pub(crate) fn next_after(&self, moment: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = moment.with_timezone(&self.timezone);
+
+        (0..MAX_DAYS_AHEAD)
+            .map(|days| local.date_naive() + TimeDelta::days(days))
+            .filter(|date| {
+                self.matches_day(
+                    date.day(),
+                    date.month(),
+                    date.weekday().num_days_from_sunday(),
+                )
+            })
+            .flat_map(|date| {
+                (0..24u32)
+                    .filter(|hour| is_set(self.hours, *hour))
+                    .flat_map(move |hour| {
+                        (0..60u32)
+                            .filter(|minute| is_set(self.minutes, *minute))
+                            .filter_map(move |minute| NaiveTime::from_hms_opt(hour, minute, 0))
+                    })
+                    .map(move |time| date.and_time(time))
+            })
+            .filter_map(|local| self.timezone.from_local_datetime(&local).earliest())
+            .map(|candidate| candidate.to_utc())
+            .find(|candidate| *candidate > moment)
+    }
+
+    /// Whether the minute that contains `moment` matches the schedule
+    pub(crate) fn matches(&self, moment: DateTime<Utc>) -> bool {
+        let local = moment.with_timezone(&self.timezone);
+
+        self.matches_day(
+            local.day(),
+            local.month(),
+            local.weekday().num_days_from_sunday(),
+        ) && is_set(self.hours, local.hour())
+            && is_set(self.minutes, local.minute())
+    }
+
+    fn matches_day(&self, day_of_month: u32, month: u32, day_of_week: u32) -> bool {
+        let day_of_month = is_set(self.days_of_month, day_of_month);
+        let day_of_week = is_set(self.days_of_week, day_of_week);
+
+        let day = match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+
+        day && is_set(self.months, month)
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.expression, self.timezone)
+    }
+}
+
+fn is_set(field: u64, value: u32) -> bool {
+    field & (1 << value) != 0
+}
+
+/// Parse a field into a bit set of the values it matches
+fn parse_field(
+    field: &str,
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+) -> Result<u64, CronScheduleError> {
+    let invalid = || CronScheduleError::InvalidField(name, field.to_string());
+
+    let value = |value: &str| -> Result<u32, CronScheduleError> {
+        names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+            .map(|index| index as u32 + min)
+            .map_or_else(|| value.parse::<u32>().map_err(|_| invalid()), Ok)
+            .and_then(|value| {
+                if (min..=max).contains(&value) {
+                    Ok(value)
+                } else {
+                    Err(invalid())
+                }
+            })
+    };
+
+    field.split(',').try_fold(0u64, |set, part| {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(invalid)?,
+            ),
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+
+        if start > end {
+            return Err(invalid());
+        }
+
+        Ok((start..=end)
+            .step_by(step as usize)
+            .fold(set, |set, value| set | 1 << value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::moment;
+
+    #[test]
+    fn test_next_after() {
+        let schedule = CronSchedule::parse("5 * * * *", Tz::UTC).unwrap();
+
+        assert_eq!(
+            schedule.next_after(moment("2024-06-30T10:05:00Z")),
+            Some(moment("2024-06-30T11:05:00Z"))
+        );
+
+        let schedule = CronSchedule::parse("0 13-15/2 * * mon-fri", Tz::Europe__Amsterdam).unwrap();
+
+        // sunday afternoon, so the next run is on monday
+        assert_eq!(
+            schedule.next_after(moment("2024-06-30T12:30:00Z")),
+            Some(moment("2024-07-01T11:00:00Z"))
+        );
+        assert_eq!(
+            schedule.next_after(moment("2024-07-01T11:00:00Z")),
+            Some(moment("2024-07-01T13:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let schedule = CronSchedule::parse("* 7-21 * * *", Tz::Europe__Amsterdam).unwrap();
+
+        assert!(!schedule.matches(moment("2024-06-30T04:59:59Z")));
+        assert!(schedule.matches(moment("2024-06-30T05:00:00Z")));
+        assert!(schedule.matches(moment("2024-06-30T19:59:00Z")));
+        assert!(!schedule.matches(moment("2024-06-30T20:00:00Z")));
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        assert_eq!(
+            CronSchedule::parse("5 * * *", Tz::UTC).unwrap_err(),
+            CronScheduleError::FieldCount("5 * * *".to_string())
+        );
+        assert_eq!(
+            CronSchedule::parse("60 * * * *", Tz::UTC).unwrap_err(),
+            CronScheduleError::InvalidField("minute", "60".to_string())
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{NaiveDate, Utc};
+use thiserror::Error;
+use tracing::{info, instrument};
+
+use crate::{
+    domain::ExchangeRate,
+    ecb::{self, Feed},
+    exchange_rate_repository::ExchangeRateRepository,
+};
+
+/// The ECB publishes every rate relative to the euro
+const BASE_CURRENCY: &str = "EUR";
+
+/// Cached rates older than this (relative to the requested date) trigger a refresh from
+/// the ECB. It spans a weekend plus a bank holiday, during which no rates are published.
+const MAX_RATE_AGE_DAYS: i64 = 4;
+
+/// How long a feed is not fetched again for a currency, so rates that it does not hold, such as
+/// those of currencies the ECB does not publish, do not fetch it on every request
+const REFETCH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// When every feed was last fetched for a currency
+static FETCHED_AT: Mutex<BTreeMap<(Feed, String), Instant>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum CurrencyError {
+    #[error("unknown currency \"{0}\"")]
+    UnknownCurrency(String),
+    #[error("no exchange rate is available for {0}")]
+    MissingRate(String),
+    #[error("failed to resolve exchange rate: {0}")]
+    Lookup(String),
+}
+
+/// Normalize a user provided currency code and check that it is a known ISO 4217 currency
+pub(crate) fn parse_currency(code: &str) -> Result<String, CurrencyError> {
+    let code = code.trim().to_uppercase();
+
+    rusty_money::iso::find(&code)
+        .map(|currency| currency.iso_alpha_code.to_string())
+        .ok_or(CurrencyError::UnknownCurrency(code))
+}
+
+/// Resolve how much one unit of `from` is worth in `to` on the given date.
+/// Rates are read from the database and refreshed from the ECB when they are missing or stale.
+#[instrument(skip(repository))]
+pub(crate) async fn resolve_conversion_rate(
+    repository: &dyn ExchangeRateRepository,
+    from: &str,
+    to: &str,
+    date: NaiveDate,
+) -> Result<f64, CurrencyError> {
+    if from == to {
+        return Ok(1.0);
+    }
+
+    let from_rate = resolve_euro_rate(repository, from, date).await?;
+    let to_rate = resolve_euro_rate(repository, to, date).await?;
+
+    Ok(to_rate / from_rate)
+}
+
+async fn resolve_euro_rate(
+    repository: &dyn ExchangeRateRepository,
+    currency: &str,
+    date: NaiveDate,
+) -> Result<f64, CurrencyError> {
+    resolve_rate_of(repository, currency, date, |feed| {
+        fetch_feed(feed, currency)
+    })
+    .await
+}
+
+/// The rate of a currency on a date, or of the last working day before it when no rate was
+/// published on that date. The rates of a feed that covers the date are fetched when no recent
+/// enough rate is cached, of which those of the currency are kept. The feed of every rate since
+/// 1999 holds some 200,000 rates, too many to keep for a single conversion.
+async fn resolve_rate_of<F, R>(
+    repository: &dyn ExchangeRateRepository,
+    currency: &str,
+    date: NaiveDate,
+    fetch: F,
+) -> Result<f64, CurrencyError>
+where
+    F: FnOnce(Feed) -> R,
+    R: Future<Output = Result<Vec<ExchangeRate>, String>>,
+{
+    if currency == BASE_CURRENCY {
+        return Ok(1.0);
+    }
+
+    let cached = repository
+        .fetch_rate(currency, date)
+        .await
+        .map_err(CurrencyError::Lookup)?;
+
+    if let Some(rate) = cached.as_ref() {
+        if (date - rate.date).num_days() <= MAX_RATE_AGE_DAYS {
+            return Ok(rate.rate);
+        }
+    }
+
+    info!("exchange rate for {} on {} is not cached", currency, date);
+
+    let rates = fetch(Feed::covering(date, Utc::now().date_naive()))
+        .await
+        .map_err(CurrencyError::Lookup)?
+        .into_iter()
+        .filter(|rate| rate.currency == currency)
+        .collect::<Vec<ExchangeRate>>();
+
+    repository
+        .persist_rates(&rates)
+        .await
+        .map_err(|e| CurrencyError::Lookup(e.to_string()))?;
+
+    rates
+        .iter()
+        .filter(|rate| rate.date <= date)
+        .max_by_key(|rate| rate.date)
+        .or(cached.as_ref())
+        .map(|rate| rate.rate)
+        .ok_or(CurrencyError::MissingRate(currency.to_string()))
+}
+
+/// Fetch the rates of a feed, unless it was fetched recently for the currency
+async fn fetch_feed(feed: Feed, currency: &str) -> Result<Vec<ExchangeRate>, String> {
+    let key = (feed, currency.to_string());
+
+    let recently_fetched = FETCHED_AT
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&key)
+        .is_some_and(|fetched_at| fetched_at.elapsed() < REFETCH_INTERVAL);
+
+    if recently_fetched {
+        return Ok(vec![]);
+    }
+
+    let rates = ecb::fetch_rates(feed).await?;
+
+    FETCHED_AT
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(key, Instant::now());
+
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryExchangeRateRepository;
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn rates(rates: &[(&str, f64)]) -> Vec<ExchangeRate> {
+        rates
+            .iter()
+            .map(|(day, rate)| ExchangeRate {
+                date: date(day),
+                currency: "USD".to_string(),
+                rate: *rate,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rate_of_weekend() {
+        let repository = MemoryExchangeRateRepository::default();
+
+        // no rates are published on sundays, so the rate of the friday before applies
+        let rate = resolve_rate_of(&repository, "USD", date("2024-06-30"), |_| async {
+            Ok(rates(&[
+                ("2024-06-27", 1.0703),
+                ("2024-06-28", 1.0705),
+                ("2024-07-01", 1.0745),
+            ]))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(rate, 1.0705);
+
+        // every fetched day is kept, so days that were fetched are not fetched again
+        let rate = resolve_rate_of(&repository, "USD", date("2024-06-27"), |_| async {
+            Err("fetched again".to_string())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(rate, 1.0703);
+    }
+
+    #[tokio::test]
+    async fn test_historical_rate() {
+        let repository = MemoryExchangeRateRepository::default();
+
+        let rate = resolve_rate_of(&repository, "USD", date("2020-03-15"), |feed| async move {
+            assert_eq!(feed, Feed::All);
+            let mut all = rates(&[("2020-03-13", 1.1104), ("2024-07-01", 1.0745)]);
+            all.push(ExchangeRate {
+                date: date("2020-03-13"),
+                currency: "SEK".to_string(),
+                rate: 10.9203,
+            });
+            Ok(all)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(rate, 1.1104);
+
+        // of the history of every currency, only that of the requested one is kept
+        let other = repository
+            .fetch_rate("SEK", date("2020-03-15"))
+            .await
+            .unwrap();
+
+        assert!(other.is_none());
+
+        let missing = resolve_rate_of(&repository, "JPY", date("2020-03-15"), |_| async {
+            Ok(vec![])
+        })
+        .await;
+
+        assert!(matches!(missing, Err(CurrencyError::MissingRate(_))));
+    }
+}
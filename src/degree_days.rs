@@ -0,0 +1,87 @@
+use chrono::NaiveDate;
+
+/// The outdoor temperature in °C below which a building needs heating, as degree days in the
+/// Netherlands and Belgium are counted
+pub(crate) const DEFAULT_BASE_TEMPERATURE: f64 = 18.0;
+
+/// The weather and the prices of a day, as far as they bear on heating
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HeatingDay {
+    pub(crate) date: NaiveDate,
+    pub(crate) average_temperature: f64,
+    /// How far the average temperature of the day is below the base temperature
+    pub(crate) degree_days: f64,
+    /// The average price of the day, weighted by how much colder than the base temperature
+    /// every hour is, as more is heated in colder hours
+    pub(crate) average_price: f64,
+}
+
+/// The heating day of a date from the temperature and the price of each of its hours, none
+/// when no hour is known
+pub(crate) fn heating_day(
+    date: NaiveDate,
+    hours: &[(f64, f64)],
+    base_temperature: f64,
+) -> Option<HeatingDay> {
+    if hours.is_empty() {
+        return None;
+    }
+
+    let average_temperature = hours
+        .iter()
+        .map(|(temperature, _)| temperature)
+        .sum::<f64>()
+        / hours.len() as f64;
+
+    let weights = hours
+        .iter()
+        .map(|(temperature, _)| (base_temperature - temperature).max(0.0))
+        .collect::<Vec<f64>>();
+
+    let total_weight = weights.iter().sum::<f64>();
+
+    let average_price = match total_weight {
+        weight if weight > 0.0 => {
+            hours
+                .iter()
+                .zip(&weights)
+                .map(|((_, price), weight)| price * weight)
+                .sum::<f64>()
+                / weight
+        }
+        _ => hours.iter().map(|(_, price)| price).sum::<f64>() / hours.len() as f64,
+    };
+
+    Some(HeatingDay {
+        date,
+        average_temperature,
+        degree_days: (base_temperature - average_temperature).max(0.0),
+        average_price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heating_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+
+        let day = heating_day(date, &[(2.0, 0.3), (6.0, 0.1), (10.0, 0.2)], 18.0).unwrap();
+
+        assert_eq!(day.average_temperature, 6.0);
+        assert_eq!(day.degree_days, 12.0);
+        // 16 and 12 degrees below the base weigh the first two prices, 8 the last
+        assert_eq!(
+            day.average_price,
+            (0.3 * 16.0 + 0.1 * 12.0 + 0.2 * 8.0) / 36.0
+        );
+
+        let warm = heating_day(date, &[(20.0, 0.3), (22.0, 0.1)], 18.0).unwrap();
+        assert_eq!(warm.degree_days, 0.0);
+        assert_eq!(warm.average_price, 0.2);
+
+        assert_eq!(heating_day(date, &[], 18.0), None);
+    }
+}
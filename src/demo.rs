@@ -0,0 +1,148 @@
+use axum::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+use crate::domain::{ElectricityPriceProvider, ElectricityProviderError, PricePoint, PriceUnit};
+
+/// The market price in EUR per kWh of every hour of a day, cheap at night and around noon when
+/// the sun shines, expensive in the morning and evening peaks
+const DAILY_SHAPE: [f64; 24] = [
+    0.21, 0.19, 0.18, 0.17, 0.17, 0.19, 0.24, 0.31, 0.34, 0.28, 0.20, 0.13, //
+    0.09, 0.08, 0.11, 0.17, 0.24, 0.32, 0.38, 0.36, 0.30, 0.26, 0.24, 0.22,
+];
+
+/// Made up prices for running without an account at a provider, which are the same for the same
+/// hour every time they are generated
+#[derive(Clone, Debug)]
+pub(crate) struct DemoProvider {
+    /// The timezone of which the days are priced, like the market of a real provider
+    timezone: Tz,
+}
+
+impl DemoProvider {
+    pub(crate) fn new(timezone: Tz) -> Self {
+        Self { timezone }
+    }
+
+    /// The prices of every hour of the local days that start within a period
+    fn prices(&self, start_moment: DateTime<Utc>, end_moment: DateTime<Utc>) -> Vec<PricePoint> {
+        let first_day = start_moment.with_timezone(&self.timezone).date_naive();
+        let last_day = end_moment.with_timezone(&self.timezone).date_naive();
+
+        first_day
+            .iter_days()
+            .take_while(|day| *day <= last_day)
+            .flat_map(|day| self.day_prices(day))
+            .filter(|price| price.moment >= start_moment && price.moment < end_moment)
+            .collect()
+    }
+
+    fn day_prices(&self, day: NaiveDate) -> Vec<PricePoint> {
+        let Some(start) = self
+            .timezone
+            .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap_or_default())
+            .earliest()
+        else {
+            return vec![];
+        };
+
+        // a day lasts 23 or 25 hours when daylight saving time starts or ends
+        (0..25)
+            .map(|hour| (start + TimeDelta::hours(hour)).to_utc())
+            .take_while(|moment| moment.with_timezone(&self.timezone).date_naive() == day)
+            .map(|moment| {
+                let local_hour = moment.with_timezone(&self.timezone).hour() as usize;
+
+                PricePoint {
+                    moment,
+                    monetary_amount: demo_price(day, local_hour),
+                    currency: "EUR".to_string(),
+                    consumer_amount: None,
+                    components: None,
+                    unit: PriceUnit::KilowattHour,
+                    resolution_minutes: 60,
+                    area: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The price of an hour of the daily shape, which every day is a bit cheaper or more expensive
+/// with hours that stray from the shape
+fn demo_price(day: NaiveDate, hour: usize) -> f64 {
+    let day_number = day.num_days_from_ce() as u64;
+    let noise = |seed: u64| {
+        let mixed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+
+        ((mixed >> 33) % 1000) as f64 / 1000.0 - 0.5
+    };
+
+    let day_factor = 1.0 + 0.3 * noise(day_number);
+    let hour_offset = 0.04 * noise(day_number * 24 + hour as u64);
+
+    ((DAILY_SHAPE[hour] * day_factor + hour_offset) * 10_000.0).round() / 10_000.0
+}
+
+#[async_trait]
+impl ElectricityPriceProvider for DemoProvider {
+    fn name(&self) -> &'static str {
+        "demo"
+    }
+
+    /// The prices of today and tomorrow, as if those of tomorrow are always published
+    async fn fetch_prices(&self) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+        let today = Utc::now().with_timezone(&self.timezone).date_naive();
+        let tomorrow = today + TimeDelta::days(1);
+
+        Ok(self
+            .day_prices(today)
+            .into_iter()
+            .chain(self.day_prices(tomorrow))
+            .collect())
+    }
+
+    async fn fetch_historical_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+        Ok(self.prices(start_moment, end_moment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Amsterdam;
+
+    use super::*;
+    use crate::test_support::moment;
+
+    #[test]
+    fn test_prices() {
+        let provider = DemoProvider::new(Amsterdam);
+
+        // the day daylight saving time ends has 25 hours
+        let prices = provider.prices(
+            moment("2024-10-26T22:00:00Z"),
+            moment("2024-10-27T23:00:00Z"),
+        );
+
+        assert_eq!(prices.len(), 25);
+        assert_eq!(prices[0].moment, moment("2024-10-26T22:00:00Z"));
+        assert!(prices
+            .iter()
+            .all(|price| price.monetary_amount > 0.0 && price.currency == "EUR"));
+        // the same hour is priced the same every time
+        assert_eq!(
+            prices[8].monetary_amount,
+            provider.prices(
+                moment("2024-10-27T06:00:00Z"),
+                moment("2024-10-27T07:00:00Z"),
+            )[0]
+            .monetary_amount
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, DurationRound, NaiveDate, TimeDelta, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use thiserror::Error;
+
+use crate::{
+    domain::{start_of_day, PriceWindow},
+    optimizer::{Contiguous, Split, Strategy},
+};
+
+/// How many days ahead to look for the next occurrence of a schedule
+const MAX_DAYS_AHEAD: i64 = 7;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum DeviceError {
+    #[error("unknown recurrence \"{0}\", expected daily, weekdays or weekends")]
+    UnknownRecurrence(String),
+    #[error("unknown strategy \"{0}\", expected contiguous or split")]
+    UnknownStrategy(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// A device that electrack plans the runs of, e.g. a dishwasher or a boiler
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Device {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    /// The duration of a run in hours
+    pub(crate) duration: i32,
+    /// The power draw while running, to express the cost of a run
+    pub(crate) power_kw: Option<f64>,
+    /// How a run may be divided, either `contiguous` or `split`
+    pub(crate) strategy: String,
+    /// The minimum number of hours between the end of a run and the start of the next, e.g. for
+    /// a heat pump boost that must not run twice in a row
+    pub(crate) min_gap_hours: Option<i32>,
+    /// The next run is postponed until this moment
+    pub(crate) snoozed_until: Option<DateTime<Utc>>,
+    /// The kind of switch that electrack turns the device on and off with, e.g. `shelly`
+    pub(crate) actuator: Option<String>,
+    /// The address of the switch on the local network
+    pub(crate) actuator_url: Option<String>,
+    /// The topic of a switch that is commanded over MQTT instead
+    pub(crate) actuator_topic: Option<String>,
+    /// The switch of a device with several
+    pub(crate) actuator_channel: Option<i32>,
+}
+
+impl Device {
+    pub(crate) fn strategy(&self) -> Box<dyn Strategy> {
+        let duration = self.duration.max(1) as usize;
+
+        match self.strategy.as_str() {
+            "split" => Box::new(Split { duration }),
+            _ => Box::new(Contiguous { duration }),
+        }
+    }
+
+    /// The earliest moment the next run may start after a previous run, respecting the minimum
+    /// gap. A run that has not reported its finish is assumed to take its full duration.
+    pub(crate) fn next_start_after(&self, previous: &DeviceRun) -> Option<DateTime<Utc>> {
+        let gap = self.min_gap_hours?;
+        let end = previous
+            .finished_at
+            .unwrap_or(previous.started_at + TimeDelta::hours(self.duration as i64));
+
+        Some(end + TimeDelta::hours(gap as i64))
+    }
+}
+
+pub(crate) fn validate_strategy(strategy: &str) -> Result<(), DeviceError> {
+    match strategy {
+        "contiguous" | "split" => Ok(()),
+        _ => Err(DeviceError::UnknownStrategy(strategy.to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Recurrence {
+    Daily,
+    Weekdays,
+    Weekends,
+}
+
+impl Recurrence {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekdays => "weekdays",
+            Recurrence::Weekends => "weekends",
+        }
+    }
+
+    fn includes(&self, date: NaiveDate) -> bool {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+        match self {
+            Recurrence::Daily => true,
+            Recurrence::Weekdays => !weekend,
+            Recurrence::Weekends => weekend,
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = DeviceError;
+
+    fn from_str(recurrence: &str) -> Result<Self, Self::Err> {
+        match recurrence {
+            "daily" => Ok(Recurrence::Daily),
+            "weekdays" => Ok(Recurrence::Weekdays),
+            "weekends" => Ok(Recurrence::Weekends),
+            _ => Err(DeviceError::UnknownRecurrence(recurrence.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = DeviceError;
+
+    fn try_from(recurrence: String) -> Result<Self, Self::Error> {
+        recurrence.parse()
+    }
+}
+
+/// When a device runs. Every occurrence the device runs once, somewhere between
+/// `available_from_hour` and `finish_by_hour`. Hours are local, a `finish_by_hour` at or
+/// before `available_from_hour` falls on the next day, e.g. from 20 to 7.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Schedule {
+    pub(crate) id: i64,
+    pub(crate) device_id: i64,
+    #[sqlx(try_from = "String")]
+    pub(crate) recurrence: Recurrence,
+    pub(crate) available_from_hour: i32,
+    pub(crate) finish_by_hour: i32,
+    pub(crate) enabled: bool,
+}
+
+pub(crate) fn validate_hours(
+    available_from_hour: i32,
+    finish_by_hour: i32,
+) -> Result<(), DeviceError> {
+    if !(0..24).contains(&available_from_hour) || !(0..=24).contains(&finish_by_hour) {
+        return Err(DeviceError::Invalid(
+            "available_from_hour must be between 0 and 23, finish_by_hour between 0 and 24"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+impl Schedule {
+    /// The period of the occurrence starting on a date, as changed by an override
+    pub(crate) fn period_of<Tz: TimeZone>(
+        &self,
+        timezone: &Tz,
+        date: NaiveDate,
+        schedule_override: Option<&ScheduleOverride>,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        let available_from_hour = schedule_override
+            .and_then(|o| o.available_from_hour)
+            .unwrap_or(self.available_from_hour);
+        let finish_by_hour = schedule_override
+            .and_then(|o| o.finish_by_hour)
+            .unwrap_or(self.finish_by_hour);
+
+        let start = local_hour(timezone, date, available_from_hour);
+
+        let end = if finish_by_hour > available_from_hour {
+            local_hour(timezone, date, finish_by_hour)
+        } else {
+            local_hour(timezone, date + TimeDelta::days(1), finish_by_hour)
+        };
+
+        (start, end)
+    }
+
+    /// The remainder of the first occurrence after `now` that leaves room for a run of
+    /// `duration` hours, starting at a whole hour. Occurrences that are skipped by an override
+    /// are passed over.
+    pub(crate) fn next_period<Tz: TimeZone>(
+        &self,
+        timezone: &Tz,
+        now: DateTime<Utc>,
+        duration: i32,
+        overrides: &[ScheduleOverride],
+    ) -> Option<(NaiveDate, DateTime<Utc>, DateTime<Utc>)> {
+        let today = now.with_timezone(timezone).date_naive();
+        let next_hour = (now + TimeDelta::hours(1) - TimeDelta::nanoseconds(1))
+            .duration_trunc(TimeDelta::hours(1))
+            .unwrap_or(now);
+
+        // yesterday's occurrence may run past midnight
+        (-1..MAX_DAYS_AHEAD)
+            .map(|days| today + TimeDelta::days(days))
+            .filter(|date| self.recurrence.includes(*date))
+            .filter_map(|date| {
+                let schedule_override = overrides
+                    .iter()
+                    .find(|o| o.schedule_id == self.id && o.date == date);
+
+                if schedule_override.is_some_and(|o| o.skip) {
+                    return None;
+                }
+
+                let (start, end) = self.period_of(timezone, date, schedule_override);
+                Some((date, start.max(next_hour), end))
+            })
+            .find(|(_, start, end)| *end - *start >= TimeDelta::hours(duration as i64))
+    }
+}
+
+impl Schedule {
+    /// The occurrences that start within a period, with their period as changed by overrides.
+    /// Skipped occurrences are left out.
+    pub(crate) fn occurrences<Tz: TimeZone>(
+        &self,
+        timezone: &Tz,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        overrides: &[ScheduleOverride],
+    ) -> Vec<(NaiveDate, DateTime<Utc>, DateTime<Utc>)> {
+        let first = start.with_timezone(timezone).date_naive() - TimeDelta::days(1);
+        let last = end.with_timezone(timezone).date_naive();
+
+        first
+            .iter_days()
+            .take_while(|date| *date <= last)
+            .filter(|date| self.recurrence.includes(*date))
+            .filter_map(|date| {
+                let schedule_override = overrides
+                    .iter()
+                    .find(|o| o.schedule_id == self.id && o.date == date);
+
+                if schedule_override.is_some_and(|o| o.skip) {
+                    return None;
+                }
+
+                let (period_start, period_end) = self.period_of(timezone, date, schedule_override);
+                Some((date, period_start, period_end))
+            })
+            .filter(|(_, period_start, _)| *period_start >= start && *period_start < end)
+            .collect()
+    }
+}
+
+/// A run of a device as reported by the device itself
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct DeviceRun {
+    pub(crate) id: i64,
+    pub(crate) device_id: i64,
+    /// The schedule and date of the occurrence the run belongs to, absent for unplanned runs
+    pub(crate) schedule_id: Option<i64>,
+    pub(crate) date: Option<NaiveDate>,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) finished_at: Option<DateTime<Utc>>,
+}
+
+/// A window of a run that electrack switches a device on and off for. The windows of a run are
+/// recorded once its first window starts, so that planning the rest of the occurrence again
+/// does not move them.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct Actuation {
+    pub(crate) device_id: i64,
+    /// The schedule and date of the occurrence the run belongs to
+    pub(crate) schedule_id: i64,
+    pub(crate) date: NaiveDate,
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+    pub(crate) switched_on_at: Option<DateTime<Utc>>,
+    pub(crate) switched_off_at: Option<DateTime<Utc>>,
+    /// Why the latest attempt to switch the device failed
+    pub(crate) error: Option<String>,
+}
+
+/// A one-off change to a single occurrence of a schedule, which either skips it or moves its
+/// hours
+#[derive(Debug, Clone, FromRow, Serialize, PartialEq)]
+pub(crate) struct ScheduleOverride {
+    pub(crate) schedule_id: i64,
+    /// The date of the occurrence
+    pub(crate) date: NaiveDate,
+    pub(crate) skip: bool,
+    pub(crate) available_from_hour: Option<i32>,
+    pub(crate) finish_by_hour: Option<i32>,
+}
+
+/// The next run of a device that electrack has planned
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PlannedRun {
+    pub(crate) schedule_id: i64,
+    /// The date of the occurrence of the schedule
+    pub(crate) date: NaiveDate,
+    pub(crate) period_start: DateTime<Utc>,
+    pub(crate) period_end: DateTime<Utc>,
+    /// The windows to run in, empty while the prices of the period are not yet known
+    pub(crate) windows: Vec<PriceWindow>,
+}
+
+/// The moment an hour of a local date starts, 24 being the start of the next day
+fn local_hour<Tz: TimeZone>(timezone: &Tz, date: NaiveDate, hour: i32) -> DateTime<Utc> {
+    start_of_day(timezone, date)
+        .with_timezone(timezone)
+        .naive_local()
+        .checked_add_signed(TimeDelta::hours(hour as i64))
+        .and_then(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|moment| moment.to_utc())
+        .unwrap_or_else(|| start_of_day(timezone, date) + TimeDelta::hours(hour as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Tz;
+
+    use super::*;
+    use crate::test_support::moment;
+
+    fn schedule(recurrence: Recurrence, available_from_hour: i32, finish_by_hour: i32) -> Schedule {
+        Schedule {
+            id: 1,
+            device_id: 1,
+            recurrence,
+            available_from_hour,
+            finish_by_hour,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_next_period_over_midnight() {
+        let schedule = schedule(Recurrence::Daily, 20, 7);
+        let timezone = Tz::Europe__Amsterdam;
+
+        // in the middle of the night the occurrence of yesterday is still running
+        assert_eq!(
+            schedule.next_period(&timezone, moment("2024-06-30T01:30:00+02:00"), 3, &[]),
+            Some((
+                NaiveDate::from_ymd_opt(2024, 6, 29).unwrap(),
+                moment("2024-06-30T02:00:00+02:00"),
+                moment("2024-06-30T07:00:00+02:00"),
+            ))
+        );
+
+        // too little time is left, so the next occurrence is planned
+        assert_eq!(
+            schedule.next_period(&timezone, moment("2024-06-30T05:30:00+02:00"), 3, &[]),
+            Some((
+                NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(),
+                moment("2024-06-30T20:00:00+02:00"),
+                moment("2024-07-01T07:00:00+02:00"),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_next_period_on_weekdays() {
+        let schedule = schedule(Recurrence::Weekdays, 0, 24);
+
+        // sunday
+        assert_eq!(
+            schedule
+                .next_period(&Tz::UTC, moment("2024-06-30T12:00:00Z"), 2, &[])
+                .map(|(date, _, _)| date),
+            NaiveDate::from_ymd_opt(2024, 7, 1)
+        );
+    }
+
+    #[test]
+    fn test_next_period_with_overrides() {
+        let schedule = schedule(Recurrence::Daily, 0, 24);
+        let date = |day| NaiveDate::from_ymd_opt(2024, 6, day).unwrap();
+
+        let overrides = [
+            ScheduleOverride {
+                schedule_id: 1,
+                date: date(29),
+                skip: true,
+                available_from_hour: None,
+                finish_by_hour: None,
+            },
+            ScheduleOverride {
+                schedule_id: 1,
+                date: date(30),
+                skip: false,
+                available_from_hour: Some(22),
+                finish_by_hour: None,
+            },
+        ];
+
+        assert_eq!(
+            schedule.next_period(&Tz::UTC, moment("2024-06-29T12:00:00Z"), 1, &overrides),
+            Some((
+                date(30),
+                moment("2024-06-30T22:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_next_start_after() {
+        let device = Device {
+            id: 1,
+            name: "heat pump".to_string(),
+            duration: 2,
+            power_kw: None,
+            strategy: "contiguous".to_string(),
+            min_gap_hours: Some(20),
+            snoozed_until: None,
+            actuator: None,
+            actuator_url: None,
+            actuator_topic: None,
+            actuator_channel: None,
+        };
+
+        let run = DeviceRun {
+            id: 1,
+            device_id: 1,
+            schedule_id: Some(1),
+            date: NaiveDate::from_ymd_opt(2024, 6, 30),
+            started_at: moment("2024-06-30T22:00:00Z"),
+            finished_at: None,
+        };
+
+        // without a reported finish the run takes its full duration
+        assert_eq!(
+            device.next_start_after(&run),
+            Some(moment("2024-07-01T20:00:00Z"))
+        );
+
+        let finished = DeviceRun {
+            finished_at: Some(moment("2024-06-30T23:00:00Z")),
+            ..run
+        };
+
+        assert_eq!(
+            device.next_start_after(&finished),
+            Some(moment("2024-07-01T19:00:00Z"))
+        );
+    }
+}
@@ -1,42 +1,422 @@
+use std::str::FromStr;
+
 use axum::async_trait;
-use chrono::{DateTime, FixedOffset, TimeZone, Utc};
-use serde::Serialize;
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use thiserror::Error;
 
+use crate::{formula::PriceFormula, tariff::PriceComponents};
+
 /// A representation of a price starting at a certain moment in time.
-#[derive(Serialize, Debug, Clone, FromRow)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub(crate) struct PricePoint {
     pub(crate) moment: DateTime<Utc>,
     pub(crate) monetary_amount: f64,
+    /// ISO 4217 code of the currency `monetary_amount` is expressed in
+    pub(crate) currency: String,
+    /// The price including markup and taxes, when a price formula is applied at ingest
+    pub(crate) consumer_amount: Option<f64>,
+    /// The breakdown of the consumer price, when a tariff is configured
+    pub(crate) components: Option<PriceComponents>,
+    /// The energy `monetary_amount` is the price of
+    pub(crate) unit: PriceUnit,
+    /// How long the price lasts from its moment
+    pub(crate) resolution_minutes: i32,
+    /// The bidding zone the price applies to, e.g. `NL`, when the provider is configured with one
+    pub(crate) area: Option<String>,
+}
+
+/// The amount of energy a price is expressed per, as providers publish either retail prices per
+/// kWh or wholesale prices per MWh
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum PriceUnit {
+    #[serde(rename = "kWh")]
+    KilowattHour,
+    #[serde(rename = "MWh")]
+    MegawattHour,
+}
+
+impl PriceUnit {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PriceUnit::KilowattHour => "kWh",
+            PriceUnit::MegawattHour => "MWh",
+        }
+    }
 }
 
-#[derive(Debug, Clone, FromRow, Serialize)]
+impl FromStr for PriceUnit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        match unit {
+            "kWh" => Ok(PriceUnit::KilowattHour),
+            "MWh" => Ok(PriceUnit::MegawattHour),
+            _ => Err(format!("unknown price unit {}", unit)),
+        }
+    }
+}
+
+impl TryFrom<String> for PriceUnit {
+    type Error = String;
+
+    fn try_from(unit: String) -> Result<Self, Self::Error> {
+        unit.parse()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct PriceWindow {
     pub(crate) starts_at: DateTime<FixedOffset>,
     pub(crate) ends_at: DateTime<FixedOffset>,
     pub(crate) average_price: String,
+    /// The average price including markup and taxes, when a price formula is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) average_consumer_price: Option<String>,
+    /// The average price when a government price cap applies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) average_capped_price: Option<String>,
+    /// The average of every component of the consumer price, when a tariff is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) components: Option<PriceComponents>,
+    pub(crate) currency: String,
 }
 
 impl PriceWindow {
+    /// Summarize consecutive price points as a window, `None` when there are no prices
+    pub(crate) fn from_prices(prices: &[PricePoint], price_cap: Option<f64>) -> Option<Self> {
+        let first = prices.first()?;
+        let last = prices.last()?;
+        let count = prices.len() as f64;
+
+        let average = |amount: fn(&PricePoint) -> Option<f64>| -> Option<f64> {
+            prices
+                .iter()
+                .map(amount)
+                .sum::<Option<f64>>()
+                .map(|sum| sum / count)
+        };
+
+        let average_capped_price = price_cap.map(|cap| {
+            prices
+                .iter()
+                .map(|price| {
+                    price
+                        .consumer_amount
+                        .unwrap_or(price.monetary_amount)
+                        .min(cap)
+                })
+                .sum::<f64>()
+                / count
+        });
+
+        let components = prices
+            .iter()
+            .map(|price| price.components.clone())
+            .collect::<Option<Vec<PriceComponents>>>()
+            .map(|components| PriceComponents::average(&components));
+
+        Some(Self {
+            starts_at: first.moment.fixed_offset(),
+            ends_at: (last.moment + TimeDelta::minutes(last.resolution_minutes as i64)
+                - TimeDelta::seconds(1))
+            .fixed_offset(),
+            average_price: format!("{:.3}", average(|price| Some(price.monetary_amount))?),
+            average_consumer_price: average(|price| price.consumer_amount)
+                .map(|price| format!("{:.3}", price)),
+            average_capped_price: average_capped_price.map(|price| format!("{:.3}", price)),
+            components,
+            currency: first.currency.clone(),
+        })
+    }
+
     pub(crate) fn with_timezone<Tz: TimeZone>(&self, timezone: Tz) -> PriceWindow {
         PriceWindow {
             starts_at: self.starts_at.with_timezone(&timezone).fixed_offset(),
             ends_at: self.ends_at.with_timezone(&timezone).fixed_offset(),
             average_price: self.average_price.clone(),
+            average_consumer_price: self.average_consumer_price.clone(),
+            average_capped_price: self.average_capped_price.clone(),
+            components: self.components.clone(),
+            currency: self.currency.clone(),
         }
     }
+
+    /// The average price a consumer pays, falling back to the market price when no consumer
+    /// price is known
+    pub(crate) fn effective_average_price(&self) -> Option<f64> {
+        self.average_consumer_price
+            .as_ref()
+            .unwrap_or(&self.average_price)
+            .parse::<f64>()
+            .ok()
+    }
+
+    /// Derive the consumer price from the raw average price.
+    /// Averaging commutes with markup and VAT formulas, so this equals the average of the
+    /// consumer prices within the window.
+    pub(crate) fn with_price_formula(&self, formula: &PriceFormula) -> PriceWindow {
+        let average_consumer_price = self
+            .average_price
+            .parse::<f64>()
+            .map(|price| format!("{:.3}", formula.apply(price)))
+            .ok();
+
+        PriceWindow {
+            average_consumer_price,
+            ..self.clone()
+        }
+    }
+
+    /// Express the average price in another currency, using the rate of one unit of the
+    /// current currency in the target currency
+    pub(crate) fn with_exchange_rate(&self, rate: f64, currency: &str) -> PriceWindow {
+        let convert = |price: &String| {
+            price
+                .parse::<f64>()
+                .map(|price| format!("{:.3}", price * rate))
+                .unwrap_or_else(|_| price.clone())
+        };
+
+        PriceWindow {
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            average_price: convert(&self.average_price),
+            average_consumer_price: self.average_consumer_price.as_ref().map(convert),
+            average_capped_price: self.average_capped_price.as_ref().map(convert),
+            components: self
+                .components
+                .as_ref()
+                .map(|components| components.with_exchange_rate(rate)),
+            currency: currency.to_string(),
+        }
+    }
+}
+
+/// Aggregates over the prices within a period. The consumer price is used where it is stored.
+#[derive(Debug, Clone, Default, FromRow, Serialize)]
+pub(crate) struct PriceStatistics {
+    pub(crate) hours: i64,
+    pub(crate) minimum: Option<f64>,
+    pub(crate) maximum: Option<f64>,
+    pub(crate) average: Option<f64>,
+    pub(crate) sum: Option<f64>,
+    pub(crate) currency: Option<String>,
+}
+
+impl PriceStatistics {
+    /// The statistics of the prices of a single currency, out of those aggregated per currency.
+    /// Prices of several currencies can not be aggregated together, which happens when areas
+    /// with different currencies are stored and no area is chosen.
+    pub(crate) fn of_single_currency(
+        per_currency: Vec<PriceStatistics>,
+    ) -> Result<PriceStatistics, String> {
+        if per_currency.len() > 1 {
+            let currencies = per_currency
+                .iter()
+                .filter_map(|statistics| statistics.currency.as_deref())
+                .collect::<Vec<&str>>()
+                .join(", ");
+
+            return Err(format!(
+                "the prices within the period are in several currencies ({}), choose an area",
+                currencies
+            ));
+        }
+
+        Ok(per_currency.into_iter().next().unwrap_or_default())
+    }
+}
+
+/// What a period cost under dynamic prices compared to a fixed rate, assuming a flat
+/// consumption profile. Both costs include the fixed charges of the contract.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct TariffComparison {
+    pub(crate) hours: i64,
+    pub(crate) consumption_kwh: f64,
+    pub(crate) fixed_rate: f64,
+    pub(crate) fixed_charges: f64,
+    pub(crate) fixed_cost: f64,
+    pub(crate) dynamic_cost: f64,
+    /// Positive when the dynamic tariff was cheaper
+    pub(crate) savings: f64,
+    pub(crate) currency: Option<String>,
+}
+
+impl TariffComparison {
+    pub(crate) fn new(
+        statistics: &PriceStatistics,
+        fixed_rate: f64,
+        hourly_kwh: f64,
+        fixed_charges: f64,
+    ) -> Self {
+        let consumption_kwh = statistics.hours as f64 * hourly_kwh;
+        let fixed_cost = consumption_kwh * fixed_rate + fixed_charges;
+        let dynamic_cost = statistics.sum.unwrap_or_default() * hourly_kwh + fixed_charges;
+
+        Self {
+            hours: statistics.hours,
+            consumption_kwh: round(consumption_kwh, 3),
+            fixed_rate,
+            fixed_charges: round(fixed_charges, 2),
+            fixed_cost: round(fixed_cost, 2),
+            dynamic_cost: round(dynamic_cost, 2),
+            savings: round(fixed_cost - dynamic_cost, 2),
+            currency: statistics.currency.clone(),
+        }
+    }
+}
+
+/// The moment a local date starts in the given timezone
+pub(crate) fn start_of_day<Tz: TimeZone>(timezone: &Tz, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+
+    timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|moment| moment.to_utc())
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+pub(crate) fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+/// The outcome of fetching the prices of a date from the provider
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PriceFetch {
+    pub(crate) date: NaiveDate,
+    /// The bidding zone of which the prices were fetched, when the provider is configured with one
+    pub(crate) area: Option<String>,
+    pub(crate) attempted_at: DateTime<Utc>,
+    pub(crate) attempts: i32,
+    /// The number of prices of the date that were fetched
+    pub(crate) prices: i32,
+    pub(crate) error: Option<String>,
+}
+
+/// The value of one euro in another currency on a certain date
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct ExchangeRate {
+    pub(crate) date: NaiveDate,
+    pub(crate) currency: String,
+    pub(crate) rate: f64,
+}
+
+/// How much CO2 is emitted per kWh of the electricity of a grid zone, during the interval that
+/// starts at a moment
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub(crate) struct CarbonIntensity {
+    pub(crate) moment: DateTime<Utc>,
+    /// The grid zone, e.g. `NL` or `GB`
+    pub(crate) zone: String,
+    /// Grams of CO2 equivalent per kWh
+    pub(crate) intensity: f64,
+    /// Whether the intensity is forecast rather than measured
+    pub(crate) forecast: bool,
+}
+
+/// The forecast generation from wind and sun and the forecast load of a bidding zone, as the
+/// average power in MW during the hour that starts at a moment
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub(crate) struct RenewableGeneration {
+    pub(crate) moment: DateTime<Utc>,
+    /// The bidding zone, e.g. `NL`
+    pub(crate) zone: String,
+    pub(crate) solar_mw: f64,
+    pub(crate) wind_mw: f64,
+    pub(crate) load_mw: f64,
+}
+
+impl RenewableGeneration {
+    /// The share of the load that wind and sun generate, which exceeds 1 when the zone
+    /// exports their surplus
+    pub(crate) fn renewable_share(&self) -> f64 {
+        match self.load_mw {
+            load if load > 0.0 => (self.solar_mw + self.wind_mw) / load,
+            _ => 0.0,
+        }
+    }
+}
+
+/// The energy drawn from and fed back into the grid during a slot, as read from a meter
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, FromRow)]
+pub(crate) struct Consumption {
+    pub(crate) starts_at: DateTime<Utc>,
+    pub(crate) ends_at: DateTime<Utc>,
+    pub(crate) energy_kwh: f64,
+    /// The energy fed back into the grid, such as the surplus of solar panels
+    #[serde(default)]
+    pub(crate) exported_kwh: f64,
+}
+
+/// The energy a single device drew during a slot, as read from a smart plug
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub(crate) struct DeviceConsumption {
+    pub(crate) device_id: i64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub(crate) consumption: Consumption,
+}
+
+/// How much energy solar panels are forecast to produce during the hour that starts at a moment
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub(crate) struct SolarProduction {
+    pub(crate) moment: DateTime<Utc>,
+    pub(crate) energy_kwh: f64,
 }
 
 #[async_trait]
 pub(crate) trait ElectricityPriceProvider: Send + Sync {
     fn name(&self) -> &'static str;
 
+    /// The bidding zone of the prices the provider publishes, when it is configured with one
+    fn area(&self) -> Option<&str> {
+        None
+    }
+
+    /// Fetch the prices the provider currently publishes, those of today and, once they are
+    /// published, those of tomorrow
     async fn fetch_prices(&self) -> Result<Vec<PricePoint>, ElectricityProviderError>;
+
+    /// Fetch the prices of a period in the past, for providers that publish them
+    async fn fetch_historical_prices(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+        Err(ElectricityProviderError::HistoryUnavailable(
+            self.name().to_string(),
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Error)]
 pub enum ElectricityProviderError {
     #[error("failed to fetch prices: {0}")]
     FetchPrices(String),
+    #[error("{0} does not publish historical prices")]
+    HistoryUnavailable(String),
+}
+
+#[async_trait]
+pub(crate) trait CarbonIntensityProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The grid zone the carbon intensity is of, e.g. `NL`
+    fn zone(&self) -> &str;
+
+    /// Fetch the carbon intensity the provider publishes around the current moment, that of
+    /// the past day and, where the provider forecasts it, that of the coming day
+    async fn fetch_carbon_intensity(
+        &self,
+    ) -> Result<Vec<CarbonIntensity>, CarbonIntensityProviderError>;
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum CarbonIntensityProviderError {
+    #[error("failed to fetch the carbon intensity: {0}")]
+    FetchCarbonIntensity(String),
 }
@@ -0,0 +1,346 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, DurationRound, FixedOffset, NaiveDateTime, TimeDelta, Utc};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+use tracing::{info, warn};
+
+use crate::{
+    consumption_repository::ConsumptionRepository,
+    domain::{round, Consumption},
+};
+
+/// The slots the meter readings are normalized to, as smart meters settle per quarter hour
+const SLOT: TimeDelta = TimeDelta::minutes(15);
+
+/// The delay before reconnecting doubles up to this maximum
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// The longest period without a telegram before the connection is considered lost, meters send
+/// one every second or every ten seconds
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A P1 reader that serves the raw telegrams of a smart meter over TCP, such as ser2net or the
+/// raw port of an ESP based reader
+#[derive(Debug, Clone)]
+pub(crate) struct P1Meter {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+/// The energy delivered to and returned by the household according to the registers of the
+/// meter at a moment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct MeterReading {
+    pub(crate) moment: DateTime<Utc>,
+    /// The sum of the registers of all tariffs
+    pub(crate) delivered_kwh: f64,
+    /// The sum of the registers of all tariffs, zero on meters that do not count it
+    pub(crate) returned_kwh: f64,
+}
+
+/// Turns the ever increasing registers of a meter into the energy of every slot. The register
+/// at the boundary of a slot is interpolated between the readings around it, and slots that
+/// are not covered by consecutive readings, such as while the reader is unreachable, are
+/// skipped rather than guessed.
+#[derive(Debug, Default)]
+pub(crate) struct IntervalMeter {
+    last: Option<MeterReading>,
+    /// The start of the current slot with the delivered and returned registers at that moment
+    slot_start: Option<(DateTime<Utc>, f64, f64)>,
+}
+
+impl IntervalMeter {
+    /// Record a reading, returning the slot it completes
+    pub(crate) fn record(&mut self, reading: MeterReading) -> Option<Consumption> {
+        let Some(last) = self.last.filter(|last| last.moment < reading.moment) else {
+            if self.last.is_none() {
+                self.last = Some(reading);
+            }
+            return None;
+        };
+
+        self.last = Some(reading);
+
+        let slot = |moment: DateTime<Utc>| moment.duration_trunc(SLOT).unwrap_or(moment);
+        let (previous_slot, current_slot) = (slot(last.moment), slot(reading.moment));
+
+        if previous_slot == current_slot {
+            return None;
+        }
+
+        let interpolate = |moment: DateTime<Utc>, last_kwh: f64, kwh: f64| {
+            let elapsed = (moment - last.moment).num_milliseconds() as f64;
+            let period = (reading.moment - last.moment).num_milliseconds() as f64;
+
+            last_kwh + (kwh - last_kwh) * elapsed / period
+        };
+        let delivered_at = |moment| interpolate(moment, last.delivered_kwh, reading.delivered_kwh);
+        let returned_at = |moment| interpolate(moment, last.returned_kwh, reading.returned_kwh);
+
+        let completed = self
+            .slot_start
+            .filter(|(starts_at, _, _)| *starts_at == previous_slot)
+            .filter(|_| reading.moment - last.moment <= SLOT)
+            .map(|(starts_at, delivered, returned)| Consumption {
+                starts_at,
+                ends_at: starts_at + SLOT,
+                // the registers count watt-hours
+                energy_kwh: round(delivered_at(starts_at + SLOT) - delivered, 3),
+                exported_kwh: round(returned_at(starts_at + SLOT) - returned, 3),
+            })
+            // a decreasing register means the meter was replaced
+            .filter(|consumption| consumption.energy_kwh >= 0.0 && consumption.exported_kwh >= 0.0);
+
+        self.slot_start = Some((
+            current_slot,
+            delivered_at(current_slot),
+            returned_at(current_slot),
+        ));
+
+        completed
+    }
+}
+
+/// Remove the first complete telegram from the received bytes, discarding anything before it.
+/// A telegram starts with `/` and ends with the line of the `!`, which carries the checksum
+/// since DSMR 4.
+pub(crate) fn take_telegram(incoming: &mut Vec<u8>) -> Option<String> {
+    let start = incoming.iter().position(|byte| *byte == b'/')?;
+    incoming.drain(..start);
+
+    let end = incoming.iter().position(|byte| *byte == b'!')?;
+    let line_end = end + incoming[end..].iter().position(|byte| *byte == b'\n')?;
+
+    let telegram = incoming.drain(..=line_end).collect::<Vec<u8>>();
+
+    Some(String::from_utf8_lossy(&telegram).into_owned())
+}
+
+/// Parse the moment and the delivered and returned energy of a telegram of DSMR 4 or later, of
+/// which the checksum is verified
+pub(crate) fn parse_telegram(telegram: &str) -> Result<MeterReading, String> {
+    let end = telegram
+        .find('!')
+        .ok_or("the telegram has no end".to_string())?;
+
+    let checksum = telegram[end + 1..].trim();
+
+    if !checksum.is_empty() {
+        let expected = u16::from_str_radix(checksum, 16)
+            .map_err(|_| format!("the checksum {} of the telegram is invalid", checksum))?;
+
+        if crc16(&telegram.as_bytes()[..=end]) != expected {
+            return Err("the checksum of the telegram does not match".to_string());
+        }
+    }
+
+    let value = |obis: &str| {
+        telegram.lines().find_map(|line| {
+            line.strip_prefix(obis)
+                .and_then(|line| line.strip_prefix('('))
+                .and_then(|line| line.split(')').next())
+        })
+    };
+
+    let timestamp = value("0-0:1.0.0")
+        .ok_or("the telegram has no timestamp, DSMR 4 or later is required".to_string())?;
+    let moment = parse_timestamp(timestamp)?;
+
+    let register = |obis: &str| {
+        value(obis)
+            .map(|value| {
+                value
+                    .trim_end_matches("*kWh")
+                    .parse::<f64>()
+                    .map_err(|e| format!("unable to parse {} of the telegram, {}", obis, e))
+            })
+            .transpose()
+    };
+
+    // meters with a single tariff may only have the total register
+    let delivered_kwh = match (register("1-0:1.8.1")?, register("1-0:1.8.2")?) {
+        (None, None) => {
+            register("1-0:1.8.0")?.ok_or("the telegram has no delivered energy".to_string())?
+        }
+        (tariff_1, tariff_2) => tariff_1.unwrap_or(0.0) + tariff_2.unwrap_or(0.0),
+    };
+
+    let returned_kwh = match (register("1-0:2.8.1")?, register("1-0:2.8.2")?) {
+        (None, None) => register("1-0:2.8.0")?.unwrap_or(0.0),
+        (tariff_1, tariff_2) => tariff_1.unwrap_or(0.0) + tariff_2.unwrap_or(0.0),
+    };
+
+    Ok(MeterReading {
+        moment,
+        delivered_kwh,
+        returned_kwh,
+    })
+}
+
+/// A timestamp of the meter is the local time in the Netherlands and Belgium, `YYMMDDhhmmss`
+/// followed by `S` in summer and `W` in winter
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, String> {
+    let error = || {
+        format!(
+            "unable to parse the timestamp {} of the telegram",
+            timestamp
+        )
+    };
+
+    let (local, offset) = match timestamp.split_at_checked(12).ok_or_else(error)? {
+        (local, "S") => (local, 2),
+        (local, "W") => (local, 1),
+        _ => return Err(error()),
+    };
+
+    NaiveDateTime::parse_from_str(local, "%y%m%d%H%M%S")
+        .ok()
+        .and_then(|local| {
+            local
+                .and_local_timezone(FixedOffset::east_opt(offset * 3600)?)
+                .single()
+        })
+        .map(|moment| moment.to_utc())
+        .ok_or_else(error)
+}
+
+/// The CRC-16/ARC checksum of a telegram from its `/` up to and including its `!`
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xa001,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// Keep a connection to the P1 reader and store the consumption of every slot, reconnecting on
+/// a backoff
+pub(crate) async fn read_p1_meter(meter: P1Meter, repository: Arc<dyn ConsumptionRepository>) {
+    let mut interval_meter = IntervalMeter::default();
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        match TcpStream::connect((meter.host.as_str(), meter.port)).await {
+            Ok(stream) => {
+                info!("connected to the P1 meter at {}:{}", meter.host, meter.port);
+                delay = Duration::from_secs(1);
+
+                let e = read_telegrams(stream, &mut interval_meter, repository.as_ref()).await;
+                warn!("the connection to the P1 meter failed, {}", e);
+            }
+            Err(e) => warn!(
+                "unable to connect to the P1 meter, {}, retrying in {} seconds",
+                e,
+                delay.as_secs()
+            ),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Read telegrams until the connection fails
+async fn read_telegrams(
+    mut stream: TcpStream,
+    interval_meter: &mut IntervalMeter,
+    repository: &dyn ConsumptionRepository,
+) -> String {
+    let mut incoming = Vec::new();
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let read = match tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buffer)).await {
+            Ok(Ok(0)) => return "the reader closed the connection".to_string(),
+            Ok(Ok(read)) => read,
+            Ok(Err(e)) => return e.to_string(),
+            Err(_) => return "no telegram was received".to_string(),
+        };
+
+        incoming.extend_from_slice(&buffer[..read]);
+
+        while let Some(telegram) = take_telegram(&mut incoming) {
+            let reading = match parse_telegram(&telegram) {
+                Ok(reading) => reading,
+                Err(e) => {
+                    warn!("skipping a telegram of the P1 meter, {}", e);
+                    continue;
+                }
+            };
+
+            if let Some(consumption) = interval_meter.record(reading) {
+                if let Err(e) = repository.persist_consumption(&[consumption]).await {
+                    warn!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEGRAM: &str = "/ISk5\\2MT382-1000\r\n\r\n1-3:0.2.8(50)\r\n0-0:1.0.0(240630101502S)\r\n0-0:96.1.1(4B384547303034303436333935353037)\r\n1-0:1.8.1(001234.567*kWh)\r\n1-0:1.8.2(000765.433*kWh)\r\n1-0:2.8.1(000100.000*kWh)\r\n1-0:2.8.2(000050.000*kWh)\r\n0-0:96.14.0(0002)\r\n1-0:1.7.0(01.193*kW)\r\n!665E\r\n";
+
+    fn moment(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2024-06-30T{}Z", time))
+            .unwrap()
+            .to_utc()
+    }
+
+    #[test]
+    fn test_parse_telegram() {
+        assert_eq!(
+            parse_telegram(TELEGRAM),
+            Ok(MeterReading {
+                moment: moment("08:15:02"),
+                delivered_kwh: 2000.0,
+                returned_kwh: 150.0,
+            })
+        );
+
+        assert_eq!(
+            parse_telegram(&TELEGRAM.replace("01.193", "01.194")),
+            Err("the checksum of the telegram does not match".to_string())
+        );
+    }
+
+    #[test]
+    fn test_take_telegram() {
+        let mut incoming = format!("0*kW)\r\n!1234\r\n{}/ISk5", TELEGRAM).into_bytes();
+
+        assert_eq!(take_telegram(&mut incoming), Some(TELEGRAM.to_string()));
+        assert_eq!(take_telegram(&mut incoming), None);
+        assert_eq!(incoming, b"/ISk5");
+    }
+
+    #[test]
+    fn test_interval_meter() {
+        let mut meter = IntervalMeter::default();
+
+        let mut record = |time: &str, delivered_kwh: f64| {
+            meter.record(MeterReading {
+                moment: moment(time),
+                delivered_kwh,
+                returned_kwh: delivered_kwh / 2.0,
+            })
+        };
+
+        assert_eq!(record("08:14:50", 100.0), None);
+        // the start of the first slot is interpolated
+        assert_eq!(record("08:15:10", 100.2), None);
+        assert_eq!(record("08:29:50", 100.8), None);
+
+        let consumption = record("08:30:10", 101.0).unwrap();
+        assert_eq!(consumption.starts_at, moment("08:15:00"));
+        assert_eq!(consumption.ends_at, moment("08:30:00"));
+        assert_eq!(consumption.energy_kwh, 0.8);
+        assert_eq!(consumption.exported_kwh, 0.4);
+
+        // a gap longer than a slot is skipped
+        assert_eq!(record("09:00:10", 101.5), None);
+        assert!(record("09:15:10", 101.8).is_some());
+    }
+}
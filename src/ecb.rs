@@ -0,0 +1,154 @@
+use chrono::{NaiveDate, TimeDelta};
+use log::info;
+use reqwest::Client;
+use serde_derive::Deserialize;
+
+use crate::domain::ExchangeRate;
+
+const RECENT_RATES_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist-90d.xml";
+const ALL_RATES_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml";
+
+/// The number of days the recent rates are sure to cover, of the 90 they span
+const RECENT_RATES_DAYS: i64 = 85;
+
+/// The published rates of the ECB, of which the recent ones are a fraction of all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Feed {
+    /// The rates of the last 90 days
+    Recent,
+    /// The rates of every working day since 1999
+    All,
+}
+
+impl Feed {
+    /// The smallest feed that holds the rates of a date
+    pub(crate) fn covering(date: NaiveDate, today: NaiveDate) -> Self {
+        match today - date <= TimeDelta::days(RECENT_RATES_DAYS) {
+            true => Feed::Recent,
+            false => Feed::All,
+        }
+    }
+
+    fn url(&self) -> &'static str {
+        match self {
+            Feed::Recent => RECENT_RATES_URL,
+            Feed::All => ALL_RATES_URL,
+        }
+    }
+}
+
+/// Fetch the euro reference rates of every working day a feed of the European Central Bank
+/// holds. The ECB publishes once per working day, so the latest rates may be a few days old.
+pub(crate) async fn fetch_rates(feed: Feed) -> Result<Vec<ExchangeRate>, String> {
+    info!("Fetching exchange rates from the ECB");
+
+    let client = Client::new();
+
+    let body = client
+        .get(feed.url())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let rates = parse_rates_xml(&body)?;
+
+    info!("Fetched {} exchange rates from the ECB", rates.len());
+
+    Ok(rates)
+}
+
+fn parse_rates_xml(xml: &str) -> Result<Vec<ExchangeRate>, String> {
+    let envelope = quick_xml::de::from_str::<Envelope>(xml)
+        .map_err(|e| format!("failed to parse the ECB's response: {}", e))?;
+
+    Ok(envelope
+        .cube
+        .days
+        .into_iter()
+        .flat_map(|day| {
+            day.rates.into_iter().map(move |rate| ExchangeRate {
+                date: day.time,
+                currency: rate.currency,
+                rate: rate.rate,
+            })
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct Envelope {
+    #[serde(rename = "Cube")]
+    cube: OuterCube,
+}
+
+#[derive(Deserialize, Debug)]
+struct OuterCube {
+    #[serde(rename = "Cube", default)]
+    days: Vec<DayCube>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DayCube {
+    #[serde(rename = "@time")]
+    time: NaiveDate,
+    #[serde(rename = "Cube", default)]
+    rates: Vec<RateCube>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RateCube {
+    #[serde(rename = "@currency")]
+    currency: String,
+    #[serde(rename = "@rate")]
+    rate: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rates_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+                <gesmes:subject>Reference rates</gesmes:subject>
+                <gesmes:Sender>
+                    <gesmes:name>European Central Bank</gesmes:name>
+                </gesmes:Sender>
+                <Cube>
+                    <Cube time='2024-07-01'>
+                        <Cube currency='USD' rate='1.0745'/>
+                        <Cube currency='NOK' rate='11.4140'/>
+                        <Cube currency='SEK' rate='11.3655'/>
+                    </Cube>
+                </Cube>
+            </gesmes:Envelope>
+            "#;
+
+        let rates = parse_rates_xml(xml).unwrap();
+
+        assert_eq!(rates.len(), 3);
+        assert_eq!(rates[0].date, NaiveDate::from_ymd_opt(2024, 7, 1).unwrap());
+        assert_eq!(rates[0].currency, "USD");
+        assert_eq!(rates[0].rate, 1.0745);
+        assert_eq!(rates[2].currency, "SEK");
+        assert_eq!(rates[2].rate, 11.3655);
+    }
+
+    #[test]
+    fn test_feed_covering() {
+        let today = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+
+        assert_eq!(
+            Feed::covering(NaiveDate::from_ymd_opt(2024, 6, 29).unwrap(), today),
+            Feed::Recent
+        );
+        assert_eq!(
+            Feed::covering(NaiveDate::from_ymd_opt(2020, 3, 15).unwrap(), today),
+            Feed::All
+        );
+    }
+}
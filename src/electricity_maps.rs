@@ -0,0 +1,177 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::Client;
+use serde_derive::Deserialize;
+
+use crate::domain::{CarbonIntensity, CarbonIntensityProvider, CarbonIntensityProviderError};
+
+/// The carbon intensity of a zone according to Electricity Maps, which covers most grid zones
+/// of the world. The history of the past day is free, the forecast requires a plan that
+/// includes it.
+#[derive(Clone, Debug)]
+pub(crate) struct ElectricityMaps {
+    /// The host of the API, `api.electricitymaps.com`
+    host: String,
+    auth_token: String,
+    zone: String,
+}
+
+impl ElectricityMaps {
+    pub(crate) fn new(host: String, auth_token: String, zone: String) -> Self {
+        Self {
+            host,
+            auth_token,
+            zone,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<String, String> {
+        Client::new()
+            .get(format!(
+                "https://{}/v3/carbon-intensity/{}",
+                self.host, path
+            ))
+            .query(&[("zone", &self.zone)])
+            .header("auth-token", &self.auth_token)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl CarbonIntensityProvider for ElectricityMaps {
+    fn name(&self) -> &'static str {
+        "electricitymaps"
+    }
+
+    fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    async fn fetch_carbon_intensity(
+        &self,
+    ) -> Result<Vec<CarbonIntensity>, CarbonIntensityProviderError> {
+        info!(
+            "Fetching the carbon intensity of {} from electricity maps",
+            self.zone
+        );
+
+        let mut intensities = self
+            .get("history")
+            .await
+            .and_then(|history| parse_history_json(&history, &self.zone))
+            .map_err(CarbonIntensityProviderError::FetchCarbonIntensity)?;
+
+        // without a plan that includes the forecast, the history is all there is
+        match self
+            .get("forecast")
+            .await
+            .and_then(|forecast| parse_forecast_json(&forecast, &self.zone))
+        {
+            Ok(forecast) => intensities.extend(forecast),
+            Err(e) => warn!("unable to fetch the carbon intensity forecast, {}", e),
+        }
+
+        info!("Fetched {} carbon intensities", intensities.len());
+
+        Ok(intensities)
+    }
+}
+
+fn parse_history_json(json: &str, zone: &str) -> Result<Vec<CarbonIntensity>, String> {
+    let history = serde_json::from_str::<History>(json)
+        .map_err(|e| format!("failed to parse the history: {}", e))?;
+
+    Ok(history
+        .history
+        .into_iter()
+        .filter_map(|point| {
+            point.carbon_intensity.map(|intensity| CarbonIntensity {
+                moment: point.datetime,
+                zone: zone.to_string(),
+                intensity,
+                forecast: false,
+            })
+        })
+        .collect())
+}
+
+fn parse_forecast_json(json: &str, zone: &str) -> Result<Vec<CarbonIntensity>, String> {
+    let forecast = serde_json::from_str::<Forecast>(json)
+        .map_err(|e| format!("failed to parse the forecast: {}", e))?;
+
+    Ok(forecast
+        .forecast
+        .into_iter()
+        .filter_map(|point| {
+            point.carbon_intensity.map(|intensity| CarbonIntensity {
+                moment: point.datetime,
+                zone: zone.to_string(),
+                intensity,
+                forecast: true,
+            })
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct History {
+    history: Vec<IntensityPoint>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Forecast {
+    forecast: Vec<IntensityPoint>,
+}
+
+#[derive(Deserialize, Debug)]
+struct IntensityPoint {
+    /// Absent for hours without data
+    #[serde(rename = "carbonIntensity")]
+    carbon_intensity: Option<f64>,
+    datetime: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_history_json() {
+        let json = r#"
+            {"zone":"NL","history":[{"zone":"NL","carbonIntensity":312,"datetime":"2024-06-30T10:00:00.000Z","updatedAt":"2024-06-30T10:51:11.223Z","isEstimated":false},{"zone":"NL","carbonIntensity":null,"datetime":"2024-06-30T11:00:00.000Z","updatedAt":"2024-06-30T11:51:11.223Z","isEstimated":true},{"zone":"NL","carbonIntensity":287.5,"datetime":"2024-06-30T12:00:00.000Z","updatedAt":"2024-06-30T12:51:11.223Z","isEstimated":true}]}
+            "#;
+
+        let intensities = parse_history_json(json, "NL").unwrap();
+
+        assert_eq!(intensities.len(), 2);
+        assert_eq!(intensities[0].intensity, 312.0);
+        assert_eq!(
+            intensities[0].moment,
+            DateTime::parse_from_rfc3339("2024-06-30T10:00:00Z").unwrap()
+        );
+        assert!(!intensities[0].forecast);
+        assert_eq!(intensities[1].intensity, 287.5);
+    }
+
+    #[test]
+    fn test_parse_forecast_json() {
+        let json = r#"
+            {"zone":"NL","forecast":[{"carbonIntensity":254,"datetime":"2024-06-30T13:00:00.000Z"},{"carbonIntensity":241,"datetime":"2024-06-30T14:00:00.000Z"}],"updatedAt":"2024-06-30T12:51:11.223Z"}
+            "#;
+
+        let intensities = parse_forecast_json(json, "NL").unwrap();
+
+        assert_eq!(intensities.len(), 2);
+        assert_eq!(intensities[1].intensity, 241.0);
+        assert!(intensities[1].forecast);
+        assert_eq!(intensities[1].zone, "NL");
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_native_tls::TlsConnector;
+
+use crate::template::{Template, Value};
+
+/// How long sending an email may take, from connecting to the last reply
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the connection to the SMTP server is encrypted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Encryption {
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Upgraded to TLS when the server offers it, usually on port 587
+    StartTls,
+}
+
+/// Where, as whom and to whom to send emails
+#[derive(Debug, Clone)]
+pub(crate) struct SmtpOptions {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) encryption: Encryption,
+    pub(crate) tls: TlsConnector,
+    /// Credentials are only sent over an encrypted connection
+    pub(crate) credentials: Option<(String, String)>,
+    pub(crate) from: String,
+    pub(crate) to: Vec<String>,
+}
+
+/// Sends emails of which the subject and body are rendered from templates
+#[derive(Debug, Clone)]
+pub(crate) struct Mailer {
+    pub(crate) smtp: SmtpOptions,
+    pub(crate) subject: Template,
+    pub(crate) body: Template,
+}
+
+impl Mailer {
+    /// Render the templates with the values and send the email to every recipient
+    pub(crate) async fn send(&self, values: &[(&str, Value)]) -> Result<(), String> {
+        let render = |template: &Template| {
+            template
+                .render(values)
+                .map_err(|e| format!("unable to render the email, {}", e))
+        };
+
+        let message = format_message(
+            &self.smtp.from,
+            &self.smtp.to,
+            &render(&self.subject)?,
+            &render(&self.body)?,
+        );
+
+        tokio::time::timeout(TIMEOUT, send_message(&self.smtp, &message))
+            .await
+            .map_err(|_| "the SMTP server did not respond in time".to_string())?
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A conversation with an SMTP server
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Connection {
+    /// Read a reply, which spans multiple lines when its lines continue with a dash after the
+    /// code. Fails unless the code is the expected one.
+    async fn reply(&mut self, expected: u16) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+
+        loop {
+            let mut line = String::new();
+
+            if self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(|e| e.to_string())?
+                == 0
+            {
+                return Err("the SMTP server closed the connection".to_string());
+            }
+
+            let line = line.trim_end().to_string();
+            let last = line.as_bytes().get(3) != Some(&b'-');
+            lines.push(line);
+
+            if last {
+                break;
+            }
+        }
+
+        match lines.last().and_then(|line| line.get(..3)) {
+            Some(code) if code.parse() == Ok(expected) => Ok(lines),
+            _ => Err(format!("the SMTP server replied {}", lines.join(" "))),
+        }
+    }
+
+    async fn command(&mut self, command: &str, expected: u16) -> Result<Vec<String>, String> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        self.reply(expected).await
+    }
+
+    /// Introduce the client, returning the extensions the server supports
+    async fn hello(&mut self) -> Result<Vec<String>, String> {
+        Ok(self
+            .command(&format!("EHLO {}", crate::APP_NAME), 250)
+            .await?
+            .iter()
+            .skip(1)
+            .filter_map(|line| line.get(4..))
+            .map(str::to_uppercase)
+            .collect())
+    }
+}
+
+async fn send_message(options: &SmtpOptions, message: &str) -> Result<(), String> {
+    let tcp = TcpStream::connect((options.host.as_str(), options.port))
+        .await
+        .map_err(|e| format!("unable to connect to the SMTP server, {}", e))?;
+
+    let stream: Box<dyn Stream> = match options.encryption {
+        Encryption::Tls => Box::new(
+            options
+                .tls
+                .connect(&options.host, tcp)
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        Encryption::StartTls => Box::new(tcp),
+    };
+
+    let mut connection = Connection {
+        stream: BufReader::new(stream),
+    };
+    connection.reply(220).await?;
+
+    let mut extensions = connection.hello().await?;
+    let mut encrypted = options.encryption == Encryption::Tls;
+
+    if !encrypted && extensions.iter().any(|extension| extension == "STARTTLS") {
+        connection.command("STARTTLS", 220).await?;
+
+        let stream = options
+            .tls
+            .connect(&options.host, connection.stream.into_inner())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        connection = Connection {
+            stream: BufReader::new(Box::new(stream)),
+        };
+        extensions = connection.hello().await?;
+        encrypted = true;
+    }
+
+    if let Some((username, password)) = &options.credentials {
+        if !encrypted {
+            return Err(
+                "refusing to authenticate, the SMTP server does not support STARTTLS".to_string(),
+            );
+        }
+
+        let auth = extensions
+            .iter()
+            .find_map(|extension| extension.strip_prefix("AUTH "))
+            .unwrap_or_default();
+
+        if auth.split(' ').any(|mechanism| mechanism == "PLAIN") {
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            connection
+                .command(&format!("AUTH PLAIN {}", credentials), 235)
+                .await?;
+        } else {
+            connection.command("AUTH LOGIN", 334).await?;
+            connection.command(&STANDARD.encode(username), 334).await?;
+            connection.command(&STANDARD.encode(password), 235).await?;
+        }
+    }
+
+    connection
+        .command(&format!("MAIL FROM:<{}>", options.from), 250)
+        .await?;
+
+    for recipient in &options.to {
+        connection
+            .command(&format!("RCPT TO:<{}>", recipient), 250)
+            .await?;
+    }
+
+    connection.command("DATA", 354).await?;
+    connection
+        .command(&format!("{}\r\n.", message), 250)
+        .await?;
+
+    // the message is accepted, so a failing goodbye does not matter
+    let _ = connection.command("QUIT", 221).await;
+
+    Ok(())
+}
+
+/// An email with a plain text body. The body is base64 encoded, so it can contain any
+/// character and no line starts with a dot.
+fn format_message(from: &str, to: &[String], subject: &str, body: &str) -> String {
+    // a line break would end the header
+    let subject = subject.replace(['\r', '\n'], " ");
+    let subject = match subject.is_ascii() {
+        true => subject.to_string(),
+        false => format!("=?UTF-8?B?{}?=", STANDARD.encode(subject)),
+    };
+
+    let body = STANDARD
+        .encode(body.replace("\r\n", "\n").replace('\n', "\r\n"))
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<String>>()
+        .join("\r\n");
+
+    let domain = from
+        .rsplit_once('@')
+        .map_or("localhost", |(_, domain)| domain);
+
+    [
+        format!("From: {}", from),
+        format!("To: {}", to.join(", ")),
+        format!("Subject: {}", subject),
+        format!("Date: {}", Utc::now().to_rfc2822()),
+        format!(
+            "Message-ID: <{}.{}@{}>",
+            Utc::now().timestamp_micros(),
+            crate::APP_NAME,
+            domain
+        ),
+        "MIME-Version: 1.0".to_string(),
+        "Content-Type: text/plain; charset=utf-8".to_string(),
+        "Content-Transfer-Encoding: base64".to_string(),
+        String::new(),
+        body,
+    ]
+    .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message() {
+        let message = format_message(
+            "electrack@home.example",
+            &["me@home.example".to_string()],
+            "Prijs €",
+            "Price spike",
+        );
+
+        assert!(message.starts_with(
+            "From: electrack@home.example\r\nTo: me@home.example\r\nSubject: =?UTF-8?B?UHJpanMg4oKs?=\r\n"
+        ));
+        assert!(message.contains("@home.example>\r\n"));
+        assert!(message.ends_with("\r\n\r\nUHJpY2Ugc3Bpa2U="));
+    }
+}
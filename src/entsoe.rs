@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, DurationRound, NaiveDateTime, TimeDelta, Utc};
+use log::info;
+use reqwest::{Client, StatusCode};
+use serde_derive::Deserialize;
+
+use crate::domain::RenewableGeneration;
+
+/// The production types of the generation forecast that count as wind or solar
+const SOLAR: &str = "B16";
+const WIND_OFFSHORE: &str = "B18";
+const WIND_ONSHORE: &str = "B19";
+
+/// The EIC codes of the bidding zones by their usual name
+const BIDDING_ZONES: &[(&str, &str)] = &[
+    ("AT", "10YAT-APG------L"),
+    ("BE", "10YBE----------2"),
+    ("CZ", "10YCZ-CEPS-----N"),
+    ("DE_LU", "10Y1001A1001A82H"),
+    ("DK1", "10YDK-1--------W"),
+    ("DK2", "10YDK-2--------M"),
+    ("EE", "10Y1001A1001A39I"),
+    ("ES", "10YES-REE------0"),
+    ("FI", "10YFI-1--------U"),
+    ("FR", "10YFR-RTE------C"),
+    ("LT", "10YLT-1001A0008Q"),
+    ("LV", "10YLV-1001A00074"),
+    ("NL", "10YNL----------L"),
+    ("NO1", "10YNO-1--------2"),
+    ("NO2", "10YNO-2--------T"),
+    ("NO3", "10YNO-3--------J"),
+    ("NO4", "10YNO-4--------9"),
+    ("NO5", "10Y1001A1001A48H"),
+    ("PL", "10YPL-AREA-----S"),
+    ("PT", "10YPT-REN------W"),
+    ("SE1", "10Y1001A1001A44P"),
+    ("SE2", "10Y1001A1001A45N"),
+    ("SE3", "10Y1001A1001A46L"),
+    ("SE4", "10Y1001A1001A47J"),
+];
+
+/// The EIC code of a bidding zone, which is either its usual name, e.g. `NL` or `SE3`, or the
+/// code itself
+pub(crate) fn bidding_zone_code(zone: &str) -> Option<&str> {
+    BIDDING_ZONES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(zone))
+        .map(|(_, code)| *code)
+        .or(Some(zone).filter(|zone| zone.len() == 16 && zone.starts_with("10Y")))
+}
+
+/// The day-ahead forecasts of the generation from wind and sun and of the load of a bidding
+/// zone, published by the ENTSO-E Transparency Platform with the security token of an account
+#[derive(Debug, Clone)]
+pub(crate) struct Entsoe {
+    /// The host of the API, `web-api.tp.entsoe.eu`
+    host: String,
+    security_token: String,
+    /// The name of the bidding zone, e.g. `NL`
+    zone: String,
+    /// The EIC code of the bidding zone
+    code: String,
+}
+
+impl Entsoe {
+    pub(crate) fn new(host: String, security_token: String, zone: String, code: String) -> Self {
+        Self {
+            host,
+            security_token,
+            zone,
+            code,
+        }
+    }
+
+    pub(crate) fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    async fn get(
+        &self,
+        document_type: &str,
+        domain: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<TimeSeries>, String> {
+        let response = Client::new()
+            .get(format!("https://{}/api", self.host))
+            .query(&[
+                ("securityToken", self.security_token.as_str()),
+                ("documentType", document_type),
+                ("processType", "A01"),
+                (domain, self.code.as_str()),
+                ("periodStart", &start.format("%Y%m%d%H%M").to_string()),
+                ("periodEnd", &end.format("%Y%m%d%H%M").to_string()),
+            ])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+
+        parse_response(status, &body)
+    }
+
+    /// Fetch the forecast generation from wind and sun and the forecast load of the hours
+    /// between two moments. Hours of which either forecast is missing are left out.
+    pub(crate) async fn fetch_renewable_generation(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<RenewableGeneration>, String> {
+        info!(
+            "Fetching the wind and solar forecast of {} from entso-e",
+            self.zone
+        );
+
+        let generation = self.get("A69", "in_Domain", start, end).await?;
+
+        let load = self.get("A65", "outBiddingZone_Domain", start, end).await?;
+
+        let solar = hourly_energy(&generation, |psr_type| psr_type == Some(SOLAR))?;
+        let wind = hourly_energy(&generation, |psr_type| {
+            psr_type == Some(WIND_OFFSHORE) || psr_type == Some(WIND_ONSHORE)
+        })?;
+        let load = hourly_energy(&load, |_| true)?;
+
+        let hours = load
+            .into_iter()
+            .filter(|(moment, _)| solar.contains_key(moment) || wind.contains_key(moment))
+            .map(|(moment, load_mw)| RenewableGeneration {
+                moment,
+                zone: self.zone.clone(),
+                solar_mw: solar.get(&moment).copied().unwrap_or(0.0),
+                wind_mw: wind.get(&moment).copied().unwrap_or(0.0),
+                load_mw,
+            })
+            .collect::<Vec<RenewableGeneration>>();
+
+        info!("Fetched the renewable generation of {} hours", hours.len());
+
+        Ok(hours)
+    }
+}
+
+/// The time series of a market document, or the reason the platform gives for not returning
+/// one, e.g. that no data matches the request
+fn parse_response(status: StatusCode, xml: &str) -> Result<Vec<TimeSeries>, String> {
+    if let Ok(acknowledgement) = quick_xml::de::from_str::<Acknowledgement>(xml) {
+        return Err(format!(
+            "entso-e did not return a forecast: {}",
+            acknowledgement.reason.text
+        ));
+    }
+
+    if !status.is_success() {
+        return Err(format!("entso-e responded with {}", status));
+    }
+
+    quick_xml::de::from_str::<MarketDocument>(xml)
+        .map(|document| document.time_series)
+        .map_err(|e| format!("failed to parse entso-e's response: {}", e))
+}
+
+/// The average power in MW of every hour, summed over the time series of the matching
+/// production types. Points that are left out repeat the quantity of the point before them.
+fn hourly_energy(
+    time_series: &[TimeSeries],
+    matches: impl Fn(Option<&str>) -> bool,
+) -> Result<BTreeMap<DateTime<Utc>, f64>, String> {
+    let mut hours = BTreeMap::<DateTime<Utc>, f64>::new();
+
+    let parse_moment = |moment: &str| {
+        NaiveDateTime::parse_from_str(moment, "%Y-%m-%dT%H:%MZ")
+            .map(|moment| moment.and_utc())
+            .map_err(|e| format!("failed to parse the moment {}: {}", moment, e))
+    };
+
+    for series in time_series.iter().filter(|series| {
+        matches(
+            series
+                .psr_type
+                .as_ref()
+                .map(|psr_type| psr_type.psr_type.as_str()),
+        )
+    }) {
+        for period in &series.periods {
+            let start = parse_moment(&period.time_interval.start)?;
+            let end = parse_moment(&period.time_interval.end)?;
+            let resolution = parse_resolution(&period.resolution)?;
+
+            let quantities = period
+                .points
+                .iter()
+                .map(|point| (point.position, point.quantity))
+                .collect::<BTreeMap<u32, f64>>();
+
+            let mut quantity = None;
+            let mut moment = start;
+            let mut position = 1;
+
+            while moment < end {
+                quantity = quantities.get(&position).copied().or(quantity);
+
+                if let Some(quantity) = quantity {
+                    let hour = moment
+                        .duration_trunc(TimeDelta::hours(1))
+                        .map_err(|e| e.to_string())?;
+
+                    *hours.entry(hour).or_default() += quantity * resolution.num_minutes() as f64
+                        / TimeDelta::hours(1).num_minutes() as f64;
+                }
+
+                moment += resolution;
+                position += 1;
+            }
+        }
+    }
+
+    Ok(hours)
+}
+
+/// The duration of a point, e.g. `PT15M` or `PT60M`
+fn parse_resolution(resolution: &str) -> Result<TimeDelta, String> {
+    resolution
+        .strip_prefix("PT")
+        .and_then(
+            |duration| match duration.split_at(duration.len().saturating_sub(1)) {
+                (minutes, "M") => minutes.parse::<i64>().ok().map(TimeDelta::minutes),
+                (hours, "H") => hours.parse::<i64>().ok().map(TimeDelta::hours),
+                _ => None,
+            },
+        )
+        .filter(|resolution| *resolution > TimeDelta::zero())
+        .ok_or(format!("unsupported resolution {}", resolution))
+}
+
+#[derive(Deserialize, Debug)]
+struct MarketDocument {
+    #[serde(rename = "TimeSeries", default)]
+    time_series: Vec<TimeSeries>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TimeSeries {
+    /// The production type, absent from the load forecast
+    #[serde(rename = "MktPSRType")]
+    psr_type: Option<PsrType>,
+    #[serde(rename = "Period", default)]
+    periods: Vec<Period>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PsrType {
+    #[serde(rename = "psrType")]
+    psr_type: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Period {
+    #[serde(rename = "timeInterval")]
+    time_interval: TimeInterval,
+    resolution: String,
+    #[serde(rename = "Point", default)]
+    points: Vec<Point>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TimeInterval {
+    start: String,
+    end: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Point {
+    position: u32,
+    /// The power in MW
+    quantity: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Acknowledgement {
+    #[serde(rename = "Reason")]
+    reason: Reason,
+}
+
+#[derive(Deserialize, Debug)]
+struct Reason {
+    text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hourly_energy() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <GL_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-6:generationloaddocument:3:0">
+                <mRID>9a1e0e6f0c1b4b2f</mRID>
+                <type>A69</type>
+                <TimeSeries>
+                    <mRID>1</mRID>
+                    <businessType>A94</businessType>
+                    <inBiddingZone_Domain.mRID codingScheme="A01">10YNL----------L</inBiddingZone_Domain.mRID>
+                    <quantity_Measure_Unit.name>MAW</quantity_Measure_Unit.name>
+                    <curveType>A03</curveType>
+                    <MktPSRType><psrType>B16</psrType></MktPSRType>
+                    <Period>
+                        <timeInterval><start>2024-06-30T10:00Z</start><end>2024-06-30T12:00Z</end></timeInterval>
+                        <resolution>PT15M</resolution>
+                        <Point><position>1</position><quantity>4000</quantity></Point>
+                        <Point><position>2</position><quantity>4400</quantity></Point>
+                        <Point><position>5</position><quantity>5000</quantity></Point>
+                    </Period>
+                </TimeSeries>
+                <TimeSeries>
+                    <mRID>2</mRID>
+                    <businessType>A94</businessType>
+                    <curveType>A01</curveType>
+                    <MktPSRType><psrType>B19</psrType></MktPSRType>
+                    <Period>
+                        <timeInterval><start>2024-06-30T10:00Z</start><end>2024-06-30T12:00Z</end></timeInterval>
+                        <resolution>PT60M</resolution>
+                        <Point><position>1</position><quantity>1200</quantity></Point>
+                        <Point><position>2</position><quantity>900</quantity></Point>
+                    </Period>
+                </TimeSeries>
+            </GL_MarketDocument>
+            "#;
+
+        let time_series = parse_response(StatusCode::OK, xml).unwrap();
+
+        let moment = |hour: &str| {
+            DateTime::parse_from_rfc3339(&format!("2024-06-30T{}:00:00Z", hour))
+                .unwrap()
+                .to_utc()
+        };
+
+        let solar = hourly_energy(&time_series, |psr_type| psr_type == Some(SOLAR)).unwrap();
+        assert_eq!(solar[&moment("10")], 4300.0);
+        assert_eq!(solar[&moment("11")], 5000.0);
+
+        let wind = hourly_energy(&time_series, |psr_type| psr_type == Some(WIND_ONSHORE)).unwrap();
+        assert_eq!(wind[&moment("10")], 1200.0);
+        assert_eq!(wind[&moment("11")], 900.0);
+    }
+
+    #[test]
+    fn test_parse_response_without_forecast() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <Acknowledgement_MarketDocument xmlns="urn:iec62325.351:tc57wg16:451-1:acknowledgementdocument:7:0">
+                <mRID>1d0f8a3e</mRID>
+                <Reason>
+                    <code>999</code>
+                    <text>No matching data found for Data item Day-ahead Generation Forecasts for Wind and Solar [14.1.D]</text>
+                </Reason>
+            </Acknowledgement_MarketDocument>
+            "#;
+
+        assert!(parse_response(StatusCode::BAD_REQUEST, xml)
+            .unwrap_err()
+            .contains("No matching data found"));
+
+        assert_eq!(
+            parse_response(StatusCode::UNAUTHORIZED, "<html><body>401</body></html>").unwrap_err(),
+            "entso-e responded with 401 Unauthorized"
+        );
+    }
+
+    #[test]
+    fn test_bidding_zone_code() {
+        assert_eq!(bidding_zone_code("nl"), Some("10YNL----------L"));
+        assert_eq!(
+            bidding_zone_code("10YNL----------L"),
+            Some("10YNL----------L")
+        );
+        assert_eq!(bidding_zone_code("XX"), None);
+    }
+}
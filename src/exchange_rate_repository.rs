@@ -0,0 +1,99 @@
+use axum::async_trait;
+use chrono::NaiveDate;
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::domain::ExchangeRate;
+
+/// How many rates are inserted per statement, well within the limit of bound parameters
+pub(crate) const RATE_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum ExchangeRateRepositoryError {
+    #[error("the exchange rates could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait ExchangeRateRepository: Send + Sync {
+    /// Fetch the most recent rate of a currency that was published on or before the given date
+    async fn fetch_rate(
+        &self,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<ExchangeRate>, String>;
+
+    /// Persist rates in a single transaction, replacing those of a date that are stored already
+    async fn persist_rates(
+        &self,
+        rates: &[ExchangeRate],
+    ) -> Result<(), ExchangeRateRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresExchangeRateRepository {
+    db: PgPool,
+}
+
+impl PostgresExchangeRateRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ExchangeRateRepository for PostgresExchangeRateRepository {
+    async fn fetch_rate(
+        &self,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<ExchangeRate>, String> {
+        sqlx::query_as::<_, ExchangeRate>(
+            "select date, currency, rate from exchange_rates where currency = $1 and date <= $2 order by date desc limit 1",
+        )
+        .bind(currency)
+        .bind(date)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_rates(
+        &self,
+        rates: &[ExchangeRate],
+    ) -> Result<(), ExchangeRateRepositoryError> {
+        if rates.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting {} exchange rates", rates.len());
+
+        let error = |e: sqlx::Error| ExchangeRateRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in rates.chunks(RATE_BATCH_SIZE) {
+            let mut query_builder =
+                QueryBuilder::new("insert into exchange_rates (date, currency, rate)");
+
+            query_builder.push_values(batch, |mut builder, rate| {
+                builder
+                    .push_bind(rate.date)
+                    .push_bind(&rate.currency)
+                    .push_bind(rate.rate);
+            });
+
+            query_builder.push(" on conflict (date, currency) do update set rate = excluded.rate");
+
+            query_builder
+                .build()
+                .persistent(false)
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use log::info;
+use reqwest::Client;
+use serde_derive::Deserialize;
+
+use crate::{domain::SolarProduction, weather::WeatherLocation};
+
+/// The share of the energy of the sunlight at standard test conditions that panels deliver,
+/// after the losses of their temperature, wiring and inverter
+const PERFORMANCE_RATIO: f64 = 0.85;
+
+/// The orientation and size of the solar panels of the household
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SolarPanels {
+    /// The tilt of the panels in degrees, from 0 for flat to 90 for vertical
+    pub(crate) declination: f64,
+    /// The direction the panels face in degrees, from -180 to 180 where 0 is south, -90 east
+    /// and 90 west
+    pub(crate) azimuth: f64,
+    /// The power the panels produce under standard test conditions
+    pub(crate) peak_power_kw: f64,
+}
+
+impl SolarPanels {
+    /// The energy the panels produce in an hour with an average irradiance on their surface in
+    /// W/m², of which standard test conditions are 1000
+    pub(crate) fn production_kwh(&self, tilted_irradiance: f64) -> f64 {
+        self.peak_power_kw * tilted_irradiance / 1000.0 * PERFORMANCE_RATIO
+    }
+}
+
+/// The production of solar panels forecast by Forecast.Solar, which forecasts today and
+/// tomorrow without an API key and a few more days with one
+#[derive(Debug, Clone)]
+pub(crate) struct ForecastSolar {
+    /// The host of the API, `api.forecast.solar`
+    host: String,
+    api_key: Option<String>,
+    location: WeatherLocation,
+    panels: SolarPanels,
+}
+
+impl ForecastSolar {
+    pub(crate) fn new(
+        host: String,
+        api_key: Option<String>,
+        location: WeatherLocation,
+        panels: SolarPanels,
+    ) -> Self {
+        Self {
+            host,
+            api_key,
+            location,
+            panels,
+        }
+    }
+
+    /// Fetch the forecast production of every hour, from the first hour of today on
+    pub(crate) async fn fetch_solar_forecast(&self) -> Result<Vec<SolarProduction>, String> {
+        info!(
+            "Fetching the solar forecast of {} kWp at {}",
+            self.panels.peak_power_kw, self.location
+        );
+
+        let api_key = self
+            .api_key
+            .as_ref()
+            .map(|api_key| format!("{}/", api_key))
+            .unwrap_or_default();
+
+        let body = Client::new()
+            .get(format!(
+                "https://{}/{}estimate/watthours/period/{}/{}/{}/{}/{}",
+                self.host,
+                api_key,
+                self.location.latitude,
+                self.location.longitude,
+                self.panels.declination,
+                self.panels.azimuth,
+                self.panels.peak_power_kw
+            ))
+            .query(&[("time", "iso8601")])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let forecast = parse_estimate_json(&body)?;
+
+        info!("Fetched the solar forecast of {} hours", forecast.len());
+
+        Ok(forecast)
+    }
+}
+
+/// The energy of the periods that end at every moment, summed per hour. Periods last an hour,
+/// or 15 minutes with a paid plan, except those that start at sunrise or end at sunset.
+fn parse_estimate_json(json: &str) -> Result<Vec<SolarProduction>, String> {
+    let estimate = serde_json::from_str::<Estimate>(json)
+        .map_err(|e| format!("failed to parse the solar forecast: {}", e))?;
+
+    let mut hours = BTreeMap::<DateTime<Utc>, f64>::new();
+
+    for (end, watt_hours) in estimate.result {
+        let end = DateTime::parse_from_rfc3339(&end)
+            .map_err(|e| format!("failed to parse the solar forecast: {}", e))?
+            .to_utc();
+
+        // a period belongs to the hour it ends in, unless it ends at the start of an hour
+        let hour = (end - TimeDelta::seconds(1))
+            .duration_trunc(TimeDelta::hours(1))
+            .map_err(|e| e.to_string())?;
+
+        *hours.entry(hour).or_default() += watt_hours / 1000.0;
+    }
+
+    Ok(hours
+        .into_iter()
+        .map(|(moment, energy_kwh)| SolarProduction { moment, energy_kwh })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct Estimate {
+    /// The watt-hours produced in the period that ends at each moment
+    result: HashMap<String, f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_estimate_json() {
+        let json = r#"
+            {"result":{"2024-06-30T05:21:00+02:00":0,"2024-06-30T06:00:00+02:00":54,"2024-06-30T07:00:00+02:00":310,"2024-06-30T21:00:00+02:00":42,"2024-06-30T22:02:00+02:00":3},"message":{"code":0,"type":"success","text":"","info":{"latitude":52.37,"longitude":4.89,"distance":0,"place":"Amsterdam","timezone":"Europe/Amsterdam","time":"2024-06-30T04:41:12+02:00","time_utc":"2024-06-30T02:41:12+00:00"},"ratelimit":{"period":3600,"limit":12,"remaining":11}}}
+            "#;
+
+        let forecast = parse_estimate_json(json).unwrap();
+
+        let moment = |hour: &str| {
+            DateTime::parse_from_rfc3339(&format!("2024-06-30T{}:00:00Z", hour))
+                .unwrap()
+                .to_utc()
+        };
+
+        assert_eq!(
+            forecast,
+            vec![
+                SolarProduction {
+                    moment: moment("03"),
+                    energy_kwh: 0.054,
+                },
+                SolarProduction {
+                    moment: moment("04"),
+                    energy_kwh: 0.31,
+                },
+                SolarProduction {
+                    moment: moment("18"),
+                    energy_kwh: 0.042,
+                },
+                SolarProduction {
+                    moment: moment("20"),
+                    energy_kwh: 0.003,
+                },
+            ]
+        );
+    }
+}
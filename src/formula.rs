@@ -0,0 +1,253 @@
+use std::{fmt::Display, iter::Peekable, str::Chars, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum FormulaError {
+    #[error("unexpected character '{0}' in price formula")]
+    UnexpectedCharacter(char),
+    #[error("unexpected end of price formula")]
+    UnexpectedEnd,
+    #[error("invalid number \"{0}\" in price formula")]
+    InvalidNumber(String),
+    #[error("unknown variable \"{0}\" in price formula, only \"price\" is supported")]
+    UnknownVariable(String),
+}
+
+/// Where a configured price formula is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FormulaApplication {
+    /// The consumer price is calculated when prices are persisted and stored next to the raw price
+    Ingest,
+    /// The consumer price is calculated from the raw price whenever a response is built
+    Response,
+}
+
+impl FromStr for FormulaApplication {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ingest" => Ok(Self::Ingest),
+            "response" => Ok(Self::Response),
+            _ => Err(format!(
+                "\"{}\" is not a valid formula application, use \"ingest\" or \"response\"",
+                value
+            )),
+        }
+    }
+}
+
+/// A transformation from a bare market price to what a consumer pays,
+/// e.g. `(price + 0.02) * 1.21` to add a supplier markup and VAT
+#[derive(Debug, Clone)]
+pub(crate) struct PriceFormula {
+    expression: Expression,
+    pub(crate) application: FormulaApplication,
+}
+
+impl PriceFormula {
+    pub(crate) fn new(
+        formula: &str,
+        application: FormulaApplication,
+    ) -> Result<Self, FormulaError> {
+        Ok(Self {
+            expression: formula.parse()?,
+            application,
+        })
+    }
+
+    pub(crate) fn apply(&self, price: f64) -> f64 {
+        self.expression.evaluate(price)
+    }
+}
+
+impl Display for PriceFormula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Number(f64),
+    Price,
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    fn evaluate(&self, price: f64) -> f64 {
+        match self {
+            Expression::Number(number) => *number,
+            Expression::Price => price,
+            Expression::Negate(expression) => -expression.evaluate(price),
+            Expression::Add(left, right) => left.evaluate(price) + right.evaluate(price),
+            Expression::Subtract(left, right) => left.evaluate(price) - right.evaluate(price),
+            Expression::Multiply(left, right) => left.evaluate(price) * right.evaluate(price),
+            Expression::Divide(left, right) => left.evaluate(price) / right.evaluate(price),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Number(number) => write!(f, "{}", number),
+            Expression::Price => write!(f, "price"),
+            Expression::Negate(expression) => write!(f, "-{}", expression),
+            Expression::Add(left, right) => write!(f, "({} + {})", left, right),
+            Expression::Subtract(left, right) => write!(f, "({} - {})", left, right),
+            Expression::Multiply(left, right) => write!(f, "({} * {})", left, right),
+            Expression::Divide(left, right) => write!(f, "({} / {})", left, right),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = FormulaError;
+
+    fn from_str(formula: &str) -> Result<Self, Self::Err> {
+        let mut chars = formula.chars().peekable();
+
+        let expression = parse_sum(&mut chars)?;
+
+        skip_whitespace(&mut chars);
+
+        match chars.next() {
+            Some(c) => Err(FormulaError::UnexpectedCharacter(c)),
+            None => Ok(expression),
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// sum := product (('+' | '-') product)*
+fn parse_sum(chars: &mut Peekable<Chars>) -> Result<Expression, FormulaError> {
+    let mut expression = parse_product(chars)?;
+
+    loop {
+        skip_whitespace(chars);
+
+        expression = match chars.peek() {
+            Some('+') => {
+                chars.next();
+                Expression::Add(Box::new(expression), Box::new(parse_product(chars)?))
+            }
+            Some('-') => {
+                chars.next();
+                Expression::Subtract(Box::new(expression), Box::new(parse_product(chars)?))
+            }
+            _ => return Ok(expression),
+        }
+    }
+}
+
+/// product := factor (('*' | '/') factor)*
+fn parse_product(chars: &mut Peekable<Chars>) -> Result<Expression, FormulaError> {
+    let mut expression = parse_factor(chars)?;
+
+    loop {
+        skip_whitespace(chars);
+
+        expression = match chars.peek() {
+            Some('*') => {
+                chars.next();
+                Expression::Multiply(Box::new(expression), Box::new(parse_factor(chars)?))
+            }
+            Some('/') => {
+                chars.next();
+                Expression::Divide(Box::new(expression), Box::new(parse_factor(chars)?))
+            }
+            _ => return Ok(expression),
+        }
+    }
+}
+
+/// factor := number | 'price' | '-' factor | '(' sum ')'
+fn parse_factor(chars: &mut Peekable<Chars>) -> Result<Expression, FormulaError> {
+    skip_whitespace(chars);
+
+    match chars.peek().copied() {
+        None => Err(FormulaError::UnexpectedEnd),
+        Some('-') => {
+            chars.next();
+            Ok(Expression::Negate(Box::new(parse_factor(chars)?)))
+        }
+        Some('(') => {
+            chars.next();
+            let expression = parse_sum(chars)?;
+            skip_whitespace(chars);
+            match chars.next() {
+                Some(')') => Ok(expression),
+                Some(c) => Err(FormulaError::UnexpectedCharacter(c)),
+                None => Err(FormulaError::UnexpectedEnd),
+            }
+        }
+        Some(c) if c.is_ascii_digit() || c == '.' => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                number.push(c);
+            }
+            number
+                .parse::<f64>()
+                .map(Expression::Number)
+                .map_err(|_| FormulaError::InvalidNumber(number))
+        }
+        Some(c) if c.is_alphabetic() => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                name.push(c);
+            }
+            match name.as_str() {
+                "price" => Ok(Expression::Price),
+                _ => Err(FormulaError::UnknownVariable(name)),
+            }
+        }
+        Some(c) => Err(FormulaError::UnexpectedCharacter(c)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_markup_and_vat() {
+        let formula =
+            PriceFormula::new("(price + 0.02) * 1.21", FormulaApplication::Response).unwrap();
+
+        assert!((formula.apply(0.10) - 0.1452).abs() < 1e-9);
+        assert!((formula.apply(-0.02) - 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let formula =
+            PriceFormula::new("price + 0.02 * 2 - -1 / 4", FormulaApplication::Ingest).unwrap();
+
+        assert!((formula.apply(1.0) - 1.29).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_formulas() {
+        assert_eq!(
+            "(price + 1".parse::<Expression>(),
+            Err(FormulaError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "cost * 2".parse::<Expression>(),
+            Err(FormulaError::UnknownVariable("cost".to_string()))
+        );
+        assert_eq!(
+            "price ^ 2".parse::<Expression>(),
+            Err(FormulaError::UnexpectedCharacter('^'))
+        );
+    }
+}
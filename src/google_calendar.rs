@@ -0,0 +1,332 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeDelta, Utc};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    chat::describe_error, planned_window_repository::CalendarEvent, planner::plan_all_devices,
+    setup::AppState,
+};
+
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const CALENDARS_URL: &str = "https://www.googleapis.com/calendar/v3/calendars";
+
+/// How long Google may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long after they ended events are kept track of, so the occurrences they belong to are
+/// known to have started
+const STARTED_OCCURRENCE_PERIOD: TimeDelta = TimeDelta::days(2);
+
+/// A Google Calendar that the planned windows of devices are pushed to as events, accessed on
+/// behalf of its owner through an OAuth refresh token
+#[derive(Clone, Debug)]
+pub(crate) struct GoogleCalendar {
+    calendar_id: String,
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Event {
+    id: String,
+    summary: String,
+    description: String,
+    start: EventTime,
+    end: EventTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventTime {
+    date_time: DateTime<FixedOffset>,
+    time_zone: String,
+}
+
+impl GoogleCalendar {
+    pub(crate) fn new(
+        calendar_id: String,
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    ) -> Self {
+        Self {
+            calendar_id,
+            client_id,
+            client_secret,
+            refresh_token,
+        }
+    }
+
+    /// Exchange the refresh token for an access token
+    async fn access_token(&self) -> Result<String, String> {
+        let response = Client::new()
+            .post(TOKEN_URL)
+            .timeout(TIMEOUT)
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("refresh_token", self.refresh_token.as_str()),
+                ("grant_type", "refresh_token"),
+            ])
+            .send()
+            .await
+            .map_err(describe_error)?;
+
+        let status = response.status();
+
+        if !status.is_success() {
+            return Err(format!(
+                "google refused the refresh token, {}, {}",
+                status,
+                response.text().await.unwrap_or_default()
+            ));
+        }
+
+        response
+            .json::<AccessToken>()
+            .await
+            .map(|token| token.access_token)
+            .map_err(describe_error)
+    }
+
+    fn events_url(&self) -> String {
+        format!("{}/{}/events", CALENDARS_URL, urlencode(&self.calendar_id))
+    }
+
+    /// Create an event, or update it when an event with its id exists
+    async fn put_event(&self, token: &str, event: &Event) -> Result<(), String> {
+        let response = Client::new()
+            .post(self.events_url())
+            .timeout(TIMEOUT)
+            .bearer_auth(token)
+            .json(event)
+            .send()
+            .await
+            .map_err(describe_error)?;
+
+        let response = match response.status() {
+            StatusCode::CONFLICT => Client::new()
+                .put(format!("{}/{}", self.events_url(), event.id))
+                .timeout(TIMEOUT)
+                .bearer_auth(token)
+                .json(event)
+                .send()
+                .await
+                .map_err(describe_error)?,
+            _ => response,
+        };
+
+        let status = response.status();
+
+        match status.is_success() {
+            true => Ok(()),
+            false => Err(format!(
+                "google calendar responded {}, {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+
+    /// Delete an event, which may have been deleted from the calendar already
+    async fn delete_event(&self, token: &str, event_id: &str) -> Result<(), String> {
+        let response = Client::new()
+            .delete(format!("{}/{}", self.events_url(), event_id))
+            .timeout(TIMEOUT)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(describe_error)?;
+
+        let status = response.status();
+
+        match status.is_success() || [StatusCode::NOT_FOUND, StatusCode::GONE].contains(&status) {
+            true => Ok(()),
+            false => Err(format!(
+                "google calendar responded {}, {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Push the windows of the next planned run of every device to the Google Calendar, moving or
+/// deleting the events of windows that changed since. Once an event of an occurrence started,
+/// the events of that occurrence are left as they are.
+pub(crate) async fn sync_calendar(state: &AppState) -> Result<(), String> {
+    let Some(calendar) = &state.google_calendar else {
+        return Ok(());
+    };
+
+    let now = Utc::now();
+
+    let pushed = state
+        .planned_window_repository
+        .fetch_calendar_events(now - STARTED_OCCURRENCE_PERIOD)
+        .await?;
+
+    let started = pushed
+        .iter()
+        .filter(|event| event.starts_at <= now)
+        .map(|event| (event.device_id, event.schedule_id, event.date))
+        .collect::<HashSet<(i64, i64, NaiveDate)>>();
+
+    let mut planned = Vec::new();
+
+    for (device, _, next_run) in plan_all_devices(state, now).await? {
+        let Some(next_run) = next_run else {
+            continue;
+        };
+
+        if started.contains(&(device.id, next_run.schedule_id, next_run.date)) {
+            continue;
+        }
+
+        for window in next_run.windows {
+            let starts_at = window.starts_at.to_utc();
+            // windows end a second before the hour
+            let ends_at = window.ends_at.to_utc() + TimeDelta::seconds(1);
+
+            let event = CalendarEvent {
+                event_id: event_id(device.id, next_run.schedule_id, next_run.date, starts_at),
+                device_id: device.id,
+                schedule_id: next_run.schedule_id,
+                date: next_run.date,
+                starts_at,
+                ends_at,
+            };
+
+            let description = format!(
+                "{} is planned to run at an average price of {} {}/kWh",
+                device.name,
+                window
+                    .average_consumer_price
+                    .as_ref()
+                    .unwrap_or(&window.average_price),
+                window.currency
+            );
+
+            planned.push((event, device.name.clone(), description));
+        }
+    }
+
+    let mut token = None;
+
+    for (event, summary, description) in &planned {
+        if pushed.contains(event) {
+            continue;
+        }
+
+        let token = match &token {
+            Some(token) => token,
+            None => token.insert(calendar.access_token().await?),
+        };
+
+        let time = |moment: DateTime<Utc>| EventTime {
+            date_time: moment.with_timezone(&state.timezone).fixed_offset(),
+            time_zone: state.timezone.name().to_string(),
+        };
+
+        calendar
+            .put_event(
+                token,
+                &Event {
+                    id: event.event_id.clone(),
+                    summary: summary.clone(),
+                    description: description.clone(),
+                    start: time(event.starts_at),
+                    end: time(event.ends_at),
+                },
+            )
+            .await?;
+
+        state
+            .planned_window_repository
+            .persist_calendar_event(event)
+            .await?;
+
+        info!(
+            "pushed the {} window at {} to google calendar",
+            summary, event.starts_at
+        );
+    }
+
+    let stale = pushed.iter().filter(|event| {
+        event.starts_at > now
+            && !planned
+                .iter()
+                .any(|(planned, _, _)| planned.event_id == event.event_id)
+    });
+
+    for event in stale {
+        let token = match &token {
+            Some(token) => token,
+            None => token.insert(calendar.access_token().await?),
+        };
+
+        calendar.delete_event(token, &event.event_id).await?;
+
+        state
+            .planned_window_repository
+            .delete_calendar_event(&event.event_id)
+            .await?;
+
+        info!(
+            "deleted the window at {} from google calendar",
+            event.starts_at
+        );
+    }
+
+    Ok(())
+}
+
+/// The id of the event of a window, which Google requires to consist of the characters a-v and
+/// 0-9
+fn event_id(device_id: i64, schedule_id: i64, date: NaiveDate, starts_at: DateTime<Utc>) -> String {
+    hex::encode(format!(
+        "electrack:{}:{}:{}:{}",
+        device_id,
+        schedule_id,
+        date,
+        starts_at.timestamp()
+    ))
+}
+
+/// Encode a calendar id, e.g. an email address, as a segment of a path
+fn urlencode(segment: &str) -> String {
+    url::form_urlencoded::byte_serialize(segment.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_id() {
+        let id = event_id(
+            1,
+            2,
+            NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(),
+            DateTime::parse_from_rfc3339("2024-06-30T22:00:00Z")
+                .unwrap()
+                .to_utc(),
+        );
+
+        assert!((5..=1024).contains(&id.len()));
+        assert!(id
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='v').contains(&c)));
+    }
+}
@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum GridFeeScheduleError {
+    #[error("grid fee rule \"{0}\" has no fee, expected e.g. \"07-23=0.06\"")]
+    MissingFee(String),
+    #[error("invalid fee \"{0}\" in grid fee schedule")]
+    InvalidFee(String),
+    #[error("invalid hour range \"{0}\" in grid fee schedule, expected e.g. \"23-07\"")]
+    InvalidHours(String),
+    #[error("unknown month or weekday range \"{0}\" in grid fee schedule")]
+    InvalidRange(String),
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A recurring schedule of grid fees that vary by hour, weekday and season.
+///
+/// Rules are separated by `;` and have the shape `[months] [weekdays] <hours>=<fee>`, e.g.
+/// `oct-mar mon-fri 17-20=0.09; 07-23=0.06; 23-07=0.03`. Hour ranges exclude their end and
+/// may wrap around midnight. The first matching rule determines the fee.
+#[derive(Debug, Clone)]
+pub(crate) struct GridFeeSchedule {
+    timezone: Tz,
+    rules: Vec<GridFeeRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GridFeeRule {
+    /// Inclusive range of months, january being 0
+    months: Option<(u32, u32)>,
+    /// Inclusive range of weekdays, monday being 0
+    weekdays: Option<(u32, u32)>,
+    hours: (u32, u32),
+    fee: f64,
+}
+
+impl GridFeeSchedule {
+    pub(crate) fn parse(schedule: &str, timezone: Tz) -> Result<Self, GridFeeScheduleError> {
+        let rules = schedule
+            .split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(GridFeeRule::from_str)
+            .collect::<Result<Vec<GridFeeRule>, GridFeeScheduleError>>()?;
+
+        Ok(Self { timezone, rules })
+    }
+
+    /// The fee that applies to the hour starting at `moment`, if any rule matches
+    pub(crate) fn fee_at(&self, moment: DateTime<Utc>) -> Option<f64> {
+        let local = moment.with_timezone(&self.timezone);
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.months
+                    .is_none_or(|months| in_range(local.month0(), months))
+                    && rule.weekdays.is_none_or(|weekdays| {
+                        in_range(local.weekday().num_days_from_monday(), weekdays)
+                    })
+                    && in_hours(local.hour(), rule.hours)
+            })
+            .map(|rule| rule.fee)
+    }
+}
+
+impl FromStr for GridFeeRule {
+    type Err = GridFeeScheduleError;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (selectors, fee) = rule
+            .split_once('=')
+            .ok_or(GridFeeScheduleError::MissingFee(rule.to_string()))?;
+
+        let fee = fee
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| GridFeeScheduleError::InvalidFee(fee.trim().to_string()))?;
+
+        let mut selectors = selectors.split_whitespace().collect::<Vec<&str>>();
+
+        let hours = selectors
+            .pop()
+            .ok_or(GridFeeScheduleError::InvalidHours(rule.to_string()))?;
+        let hours = parse_range(hours, |hour| hour.parse::<u32>().ok().filter(|h| *h <= 24))
+            .ok_or(GridFeeScheduleError::InvalidHours(hours.to_string()))?;
+
+        let mut months = None;
+        let mut weekdays = None;
+
+        for selector in selectors {
+            if let Some(range) = parse_range(selector, |name| position(&MONTHS, name)) {
+                months = Some(range);
+            } else if let Some(range) = parse_range(selector, |name| position(&WEEKDAYS, name)) {
+                weekdays = Some(range);
+            } else {
+                return Err(GridFeeScheduleError::InvalidRange(selector.to_string()));
+            }
+        }
+
+        Ok(GridFeeRule {
+            months,
+            weekdays,
+            hours: (hours.0 % 24, hours.1 % 24),
+            fee,
+        })
+    }
+}
+
+fn position(names: &[&str], name: &str) -> Option<u32> {
+    names
+        .iter()
+        .position(|candidate| candidate.eq_ignore_ascii_case(name))
+        .map(|position| position as u32)
+}
+
+/// Parse either a single value or a `start-end` range
+fn parse_range(range: &str, parse: impl Fn(&str) -> Option<u32>) -> Option<(u32, u32)> {
+    match range.split_once('-') {
+        Some((start, end)) => Some((parse(start)?, parse(end)?)),
+        None => parse(range).map(|value| (value, value)),
+    }
+}
+
+fn in_range(value: u32, (start, end): (u32, u32)) -> bool {
+    if start <= end {
+        start <= value && value <= end
+    } else {
+        value >= start || value <= end
+    }
+}
+
+fn in_hours(hour: u32, (start, end): (u32, u32)) -> bool {
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= hour && hour < end,
+        std::cmp::Ordering::Greater => hour >= start || hour < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = "oct-mar mon-fri 17-20=0.09".parse::<GridFeeRule>().unwrap();
+
+        assert_eq!(
+            rule,
+            GridFeeRule {
+                months: Some((9, 2)),
+                weekdays: Some((0, 4)),
+                hours: (17, 20),
+                fee: 0.09,
+            }
+        );
+
+        assert_eq!(
+            "sat 0-24=0.01".parse::<GridFeeRule>(),
+            Ok(GridFeeRule {
+                months: None,
+                weekdays: Some((5, 5)),
+                hours: (0, 0),
+                fee: 0.01,
+            })
+        );
+        assert_eq!(
+            "winter 17-20=0.09".parse::<GridFeeRule>(),
+            Err(GridFeeScheduleError::InvalidRange("winter".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_fee_at() {
+        let schedule = GridFeeSchedule::parse(
+            "oct-mar mon-fri 17-20=0.09; 07-23=0.06; 23-07=0.03",
+            chrono_tz::Europe::Amsterdam,
+        )
+        .unwrap();
+
+        let fee_at =
+            |moment: &str| schedule.fee_at(DateTime::parse_from_rfc3339(moment).unwrap().to_utc());
+
+        // a winter weekday evening peak
+        assert_eq!(fee_at("2024-01-15T18:00:00+01:00"), Some(0.09));
+        // the same hour on a saturday
+        assert_eq!(fee_at("2024-01-13T18:00:00+01:00"), Some(0.06));
+        // a summer night, wrapping around midnight
+        assert_eq!(fee_at("2024-07-01T02:00:00+02:00"), Some(0.03));
+        assert_eq!(fee_at("2024-07-01T23:00:00+02:00"), Some(0.03));
+    }
+}
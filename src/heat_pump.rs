@@ -0,0 +1,35 @@
+/// The temperature of the water a heat pump heats for floor heating, in °C
+pub(crate) const DEFAULT_FLOW_TEMPERATURE: f64 = 35.0;
+
+/// The share of the theoretical Carnot efficiency that heat pumps achieve in practice
+const CARNOT_EFFICIENCY: f64 = 0.45;
+
+/// The range of coefficients of performance that heat pumps achieve in practice
+const MIN_COP: f64 = 1.0;
+const MAX_COP: f64 = 8.0;
+
+/// The expected coefficient of performance of a heat pump, the heat it delivers per kWh of
+/// electricity, at an outdoor and a flow temperature in °C. It is estimated as a share of the
+/// Carnot efficiency, so it drops as the outdoor temperature falls.
+pub(crate) fn coefficient_of_performance(outdoor_temperature: f64, flow_temperature: f64) -> f64 {
+    let lift = flow_temperature - outdoor_temperature;
+
+    if lift <= 0.0 {
+        return MAX_COP;
+    }
+
+    (CARNOT_EFFICIENCY * (flow_temperature + 273.15) / lift).clamp(MIN_COP, MAX_COP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coefficient_of_performance() {
+        // the common A7/W35 and A-7/W35 rating points
+        assert!((coefficient_of_performance(7.0, 35.0) - 4.95).abs() < 0.01);
+        assert!((coefficient_of_performance(-7.0, 35.0) - 3.30).abs() < 0.01);
+        assert_eq!(coefficient_of_performance(40.0, 35.0), MAX_COP);
+    }
+}
@@ -0,0 +1,137 @@
+use serde_json::{json, Value};
+
+/// The entities electrack announces to Home Assistant through MQTT discovery, so they appear
+/// without configuring them by hand
+#[derive(Debug, Clone)]
+pub(crate) struct Discovery<'a> {
+    /// The topic Home Assistant listens to for discovery messages, `homeassistant` by default
+    pub(crate) discovery_prefix: &'a str,
+    /// The topic under which the states are published
+    pub(crate) topic_prefix: &'a str,
+    /// Identifies this instance of electrack, so multiple instances do not collide
+    pub(crate) node_id: &'a str,
+    /// Marks the entities unavailable while electrack is disconnected
+    pub(crate) availability_topic: Option<&'a str>,
+    pub(crate) window_durations: &'a [usize],
+    pub(crate) currency: &'a str,
+}
+
+impl Discovery<'_> {
+    /// The topic and configuration of every entity: a current price sensor, a price level
+    /// sensor and a binary sensor per window duration that is on while the cheapest window is
+    /// active
+    pub(crate) fn messages(&self) -> Vec<(String, Value)> {
+        let node_id = self
+            .node_id
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+
+        let device = json!({
+            "identifiers": [node_id],
+            "name": crate::APP_NAME,
+            "model": crate::APP_NAME,
+        });
+
+        let topic = |component: &str, object_id: &str| {
+            format!(
+                "{}/{}/{}/{}/config",
+                self.discovery_prefix, component, node_id, object_id
+            )
+        };
+        let state_topic = |name: &str| format!("{}/{}", self.topic_prefix, name);
+
+        let mut messages = vec![
+            (
+                topic("sensor", "current_price"),
+                json!({
+                    "name": "Current price",
+                    "unique_id": format!("{}_current_price", node_id),
+                    "state_topic": state_topic("current_price"),
+                    "value_template": "{{ value_json.price }}",
+                    "json_attributes_topic": state_topic("current_price"),
+                    "unit_of_measurement": format!("{}/kWh", self.currency),
+                    "state_class": "measurement",
+                    "device": device,
+                }),
+            ),
+            (
+                topic("sensor", "price_level"),
+                json!({
+                    "name": "Price level",
+                    "unique_id": format!("{}_price_level", node_id),
+                    "state_topic": state_topic("price_level"),
+                    "device_class": "enum",
+                    "options": ["very_cheap", "cheap", "normal", "expensive", "very_expensive"],
+                    "device": device,
+                }),
+            ),
+        ];
+
+        for duration in self.window_durations {
+            let object_id = format!("cheap_window_{}h", duration);
+            let window_topic = state_topic(&format!("windows/{}h", duration));
+
+            messages.push((
+                topic("binary_sensor", &object_id),
+                json!({
+                    "name": format!("Cheap {}h window active", duration),
+                    "unique_id": format!("{}_{}", node_id, object_id),
+                    "state_topic": window_topic,
+                    "value_template": "{{ 'ON' if value_json and value_json.active else 'OFF' }}",
+                    "json_attributes_topic": window_topic,
+                    "device": device,
+                }),
+            ));
+        }
+
+        if let Some(topic) = self.availability_topic {
+            for (_, config) in &mut messages {
+                config["availability"] = json!([{ "topic": topic }]);
+            }
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovery_messages() {
+        let messages = Discovery {
+            discovery_prefix: "homeassistant",
+            topic_prefix: "electrack",
+            node_id: "electrack.home",
+            availability_topic: Some("electrack/availability"),
+            window_durations: &[3],
+            currency: "EUR",
+        }
+        .messages();
+
+        let topics = messages
+            .iter()
+            .map(|(topic, _)| topic.as_str())
+            .collect::<Vec<&str>>();
+
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/sensor/electrack_home/current_price/config",
+                "homeassistant/sensor/electrack_home/price_level/config",
+                "homeassistant/binary_sensor/electrack_home/cheap_window_3h/config",
+            ]
+        );
+        assert_eq!(messages[0].1["unit_of_measurement"], "EUR/kWh");
+        assert_eq!(messages[2].1["state_topic"], "electrack/windows/3h");
+        assert_eq!(
+            messages[2].1["availability"][0]["topic"],
+            "electrack/availability"
+        );
+    }
+}
@@ -1,41 +1,372 @@
+use std::{sync::Arc, time::Instant};
+
 use axum::{
-    extract::{Query, State},
-    routing::get,
+    body::{to_bytes, Body},
+    extract::{DefaultBodyLimit, MatchedPath, Query, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, patch, post, put},
     serve, Json, Router,
 };
 use axum_macros::debug_handler;
 
-use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime, Utc};
-use reqwest::StatusCode;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
+    StatusCode,
+};
 use serde::Deserialize;
-use sqlx::PgPool;
-use tokio::net::TcpListener;
-use tracing::{error, info, instrument};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tracing::{debug, field, info, info_span, instrument, Instrument};
+
+mod admin;
+mod available_dates;
+mod backtest;
+mod backups;
+mod battery;
+mod billing;
+mod charging;
+mod consumption;
+mod contract;
+mod costs;
+mod devices;
+mod grafana;
+mod greenest_slots;
+mod heating_costs;
+mod history;
+mod home_assistant;
+mod jobs;
+mod node_red;
+mod notification_rules;
+mod plan;
+mod planned_windows;
+mod price_corrections;
+mod recommendation;
+mod refresh;
+mod renewable_share;
+mod savings;
+mod schedules;
+mod sg_ready;
+mod solar_forecast;
+mod tariff_comparison;
+mod webhooks;
 
 use crate::{
-    domain::{ElectricityPriceProvider, PriceWindow},
-    price_repository::PriceRepository,
+    configuration_file::variable,
+    currency::{parse_currency, resolve_conversion_rate, CurrencyError},
+    domain::PriceWindow,
+    formula::FormulaApplication,
+    optimizer::{costs, optimize_costs, Contiguous},
+    planner::{price_inputs, record_planned_windows},
+    price_cache::WindowKey,
+    self_consumption::{forecast_production, self_consumption_costs, DEFAULT_LOAD_KW},
 };
 use crate::{
-    domain::{ElectricityProviderError, PricePoint},
-    setup::{setup_app_state, AppState},
+    reload::reload_on_hangup,
+    scheduler::start_scheduler,
+    setup::{resolve_tls, resolve_unix_socket, setup_app_state, AppState},
+    tls::serve_tls,
+    unix_socket::serve_unix_socket,
 };
 
+/// The header that carries the ID of a request, of both the request and the response
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longer IDs that clients send are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Longer error messages are not expected, and lose their message rather than being buffered
+const MAX_ERROR_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Longer durations of windows are ignored, as no period of prices covers them
+const MAX_DURATION_HOURS: i32 = 7 * 24;
+
 /// The main entry point for the http app.
 /// It creates the state that is passed to endpoints
-pub(crate) async fn start_http_server() -> Result<(), std::io::Error> {
+pub(crate) async fn start_http_server(demo: bool) -> Result<(), std::io::Error> {
+    let state = setup_app_state(demo).await;
+
+    start_scheduler(state.clone());
+
+    tokio::spawn(reload_on_hangup(state.clone(), demo));
+
     let router = Router::new()
         .route("/time-slots", get(get_time_slots))
-        .with_state(setup_app_state().await);
+        .route("/greenest-slots", get(greenest_slots::get_greenest_slots))
+        .route(
+            "/time-slots/history",
+            get(history::get_historical_time_slots),
+        )
+        .route("/plan", get(plan::get_plan))
+        .route("/charging-plan", get(charging::get_charging_plan))
+        .route("/battery-plan", get(battery::get_battery_plan))
+        .route("/jobs", get(jobs::get_jobs))
+        .route(
+            "/notification-rules",
+            get(notification_rules::get_notification_rules)
+                .post(notification_rules::post_notification_rule),
+        )
+        .route(
+            "/notification-rules/:id",
+            patch(notification_rules::patch_notification_rule)
+                .delete(notification_rules::delete_notification_rule),
+        )
+        .route(
+            "/webhooks",
+            get(webhooks::get_webhooks).post(webhooks::post_webhook),
+        )
+        .route("/webhooks/:id", delete(webhooks::delete_webhook))
+        .route("/webhooks/deliveries", get(webhooks::get_deliveries))
+        .route(
+            "/webhooks/deliveries/:id/attempts",
+            get(webhooks::get_delivery_attempts),
+        )
+        .route(
+            "/webhooks/deliveries/:id/retry",
+            post(webhooks::post_delivery_retry),
+        )
+        .route(
+            "/planned-windows",
+            get(planned_windows::get_planned_windows),
+        )
+        .route(
+            "/devices",
+            get(devices::get_devices).post(devices::post_device),
+        )
+        .route("/devices/:id", get(devices::get_device))
+        .route("/devices/:id/skip", post(devices::post_skip))
+        .route(
+            "/devices/:id/postpone",
+            post(devices::post_postpone).delete(devices::delete_postpone),
+        )
+        .route("/devices/:id/start", post(devices::post_run_start))
+        .route("/devices/:id/finish", post(devices::post_run_finish))
+        .route("/devices/:id/runs", get(devices::get_runs))
+        .route("/devices/:id/actuations", get(devices::get_actuations))
+        .route(
+            "/devices/:id/consumption",
+            post(consumption::post_device_consumption),
+        )
+        .route(
+            "/schedules",
+            get(schedules::get_schedules).post(schedules::post_schedule),
+        )
+        .route(
+            "/schedules/:id",
+            get(schedules::get_schedule)
+                .patch(schedules::patch_schedule)
+                .delete(schedules::delete_schedule),
+        )
+        .route(
+            "/schedules/:id/overrides/:date",
+            put(schedules::put_override).delete(schedules::delete_override),
+        )
+        .route("/backtest", get(backtest::get_backtest))
+        .route("/recommendation", get(recommendation::get_recommendation))
+        .route(
+            "/renewable-share",
+            get(renewable_share::get_renewable_share),
+        )
+        .route("/sg-ready", get(sg_ready::get_sg_ready))
+        .route("/solar-forecast", get(solar_forecast::get_solar_forecast))
+        .route("/node-red", get(node_red::get_node_red))
+        .route("/billing", get(billing::get_billing))
+        .route("/savings", get(savings::get_savings))
+        .route(
+            "/contract",
+            get(contract::get_contract).put(contract::put_contract),
+        )
+        .route("/consumption", post(consumption::post_consumption))
+        .route(
+            "/consumption/import",
+            post(consumption::post_consumption_import)
+                .layer(DefaultBodyLimit::max(consumption::MAX_IMPORT_BYTES)),
+        )
+        .route("/costs", get(costs::get_costs))
+        .route("/heating-costs", get(heating_costs::get_heating_costs))
+        .route("/home-assistant", get(home_assistant::get_home_assistant))
+        .route("/grafana", get(grafana::get_grafana))
+        .route("/grafana/search", post(grafana::post_search))
+        .route("/grafana/query", post(grafana::post_query))
+        .route("/grafana/annotations", post(grafana::post_annotations))
+        .route(
+            "/price-corrections",
+            get(price_corrections::get_price_corrections),
+        )
+        .route("/admin/prices/:provider", put(admin::put_prices))
+        .route("/admin/gaps", get(admin::get_gaps))
+        .route("/admin/export", get(admin::get_export))
+        .route(
+            "/admin/import",
+            post(admin::post_import).layer(DefaultBodyLimit::max(admin::MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/admin/prices/:provider/:date",
+            delete(admin::delete_prices),
+        )
+        .route(
+            "/available-dates",
+            get(available_dates::get_available_dates),
+        )
+        .route("/refresh", post(refresh::post_refresh))
+        .route("/backups", get(backups::get_backups))
+        .route("/backups/restore", post(backups::post_restore))
+        .route(
+            "/tariff-comparison",
+            get(tariff_comparison::get_tariff_comparison),
+        )
+        .route_layer(middleware::from_fn(trace_request))
+        .with_state(state);
 
-    let port = std::env::var("PORT").unwrap_or("8080".to_string());
+    if let Some(socket) = resolve_unix_socket() {
+        info!("now listening on {}", socket.path.display());
+
+        return serve_unix_socket(socket, router).await;
+    }
+
+    let port = variable("PORT").unwrap_or("8080".to_string());
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
         .unwrap();
 
-    info!("now listening on port {}", port);
+    match resolve_tls() {
+        Some(tls) => {
+            info!("now listening on port {} over HTTPS", port);
+
+            serve_tls(listener, router, tls).await
+        }
+        None => {
+            info!("now listening on port {}", port);
+
+            serve(listener, router).await
+        }
+    }
+}
 
-    serve(listener, router).await
+/// Serve the requests of a connection that was accepted outside of `axum::serve`, such as over
+/// TLS or a Unix socket
+pub(crate) async fn serve_connection<I>(io: I, router: Router)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    if let Err(e) = Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(io), TowerToHyperService::new(router))
+        .await
+    {
+        debug!("unable to serve a connection, {}", e);
+    }
+}
+
+/// Handle a request within a span named after its method and route, which is exported as the
+/// root of its trace when OTLP export is configured. Every request gets an ID, the one of the
+/// `x-request-id` header of the client if it sent one, which the response and the access log
+/// line of the request carry as well, and error messages end with.
+async fn trace_request(request: Request, next: Next) -> Response {
+    let started_at = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+
+    let span = info_span!(
+        "http request",
+        otel.name = %format!("{} {}", method, route),
+        otel.kind = "server",
+        otel.status_code = field::Empty,
+        http.request.id = request_id,
+        http.request.method = %method,
+        http.route = route,
+        url.path = path,
+        http.response.status_code = field::Empty,
+    );
+
+    let response = next.run(request).instrument(span.clone()).await;
+    let status = response.status();
+
+    span.record("http.response.status_code", status.as_u16());
+
+    if status.is_server_error() {
+        span.record("otel.status_code", "error");
+    }
+
+    info!(
+        target: "electrack::access",
+        request_id,
+        method = %method,
+        path,
+        status = status.as_u16(),
+        duration_ms = started_at.elapsed().as_millis() as u64,
+        "{} {} {}",
+        method,
+        path,
+        status.as_u16()
+    );
+
+    let mut response = with_request_id(response, &request_id).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// End the plain text message of an error response with the ID of the request, so the error a
+/// client logs can be found in the logs of the server
+async fn with_request_id(response: Response, request_id: &str) -> Response {
+    let plain_text = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain"));
+
+    if !(response.status().is_client_error() || response.status().is_server_error()) || !plain_text
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let Ok(message) = to_bytes(body, MAX_ERROR_MESSAGE_BYTES).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+
+    let message = format!(
+        "{} (request {})",
+        String::from_utf8_lossy(&message),
+        request_id
+    );
+
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::from(message))
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -43,17 +374,34 @@ struct TimeslotParameters {
     durations: String,
     moment_start: DateTime<FixedOffset>,
     moment_end: DateTime<FixedOffset>,
+    /// ISO 4217 code of the currency the prices should be expressed in
+    currency: Option<String>,
+    /// Whether to include the components of the consumer price
+    breakdown: Option<bool>,
+    /// The consumption in kWh so far in the current price cap period
+    consumed_kwh: Option<f64>,
+    /// The power the load draws, to know how much of it the solar panels cover
+    power_kw: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
 }
 
 impl TimeslotParameters {
     fn get_durations(&self) -> Vec<i32> {
-        self.durations
-            .split(',')
-            .filter_map(|s| s.parse::<i32>().ok())
-            .collect::<Vec<i32>>()
+        parse_durations(&self.durations)
     }
 }
 
+/// Parse a comma separated list of durations in hours, ignoring invalid entries and durations
+/// longer than a week
+fn parse_durations(durations: &str) -> Vec<i32> {
+    durations
+        .split(',')
+        .filter_map(|s| s.parse::<i32>().ok())
+        .filter(|duration| *duration <= MAX_DURATION_HOURS)
+        .collect::<Vec<i32>>()
+}
+
 impl Default for TimeslotParameters {
     fn default() -> Self {
         Self {
@@ -66,93 +414,349 @@ impl Default for TimeslotParameters {
                 .with_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap())
                 .unwrap()
                 .fixed_offset(),
+            currency: None,
+            breakdown: None,
+            consumed_kwh: None,
+            power_kw: None,
+            area: None,
         }
     }
 }
 
+/// The area of the prices, for the endpoints that take no other parameters
+#[derive(Debug, Clone, Deserialize)]
+struct AreaParameters {
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
 /// Fetch the timeslots between a start and end moment that are the cheapest for the given
-/// durations. Every duration results in a `PriceWindow`
+/// durations. Every duration results in a `PriceWindow`, of which all are optimized in memory
+/// on prices that are read in a single query. When a solar forecast is configured, hours are
+/// optimized on the cost of what the solar panels do not cover. The windows are cached along
+/// with the prices, for the same range, durations and everything else they are computed of.
 #[debug_handler(state = AppState)]
 #[instrument(skip(state))]
 async fn get_time_slots(
     State(state): State<AppState>,
     parameters: Query<TimeslotParameters>,
 ) -> axum::response::Result<(StatusCode, Json<Vec<PriceWindow>>)> {
-    if !has_prices_of_date(state.db.clone(), Local::now().date_naive())
-        .await
-        .unwrap()
-    {
-        let price_fetching_result = fetch_prices_of_today_from_provider(
-            &*state.electricity_provider,
-            &*state.price_repository,
-        )
-        .await;
-
-        if let Err(e) = price_fetching_result {
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into());
-        }
-    }
+    let area = state.price_area(parameters.area.as_deref());
 
     let durations = parameters.get_durations();
 
+    if parameters.power_kw.is_some_and(|power_kw| power_kw <= 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "power_kw must be positive".to_string(),
+        )
+            .into());
+    }
+
     let timezone_date_start = parameters.moment_start.timezone();
 
-    let optimal_windows: Vec<PriceWindow> = state
+    // taken before the prices are read, so windows of prices that change meanwhile are not cached
+    let generation = state.window_cache.generation();
+
+    let prices = state
         .price_repository
-        .fetch_optimal_price_window_of_window_for_durations(
+        .fetch_prices(
             parameters.moment_start.to_utc(),
             parameters.moment_end.to_utc(),
-            durations.as_slice(),
+            area.as_deref(),
         )
         .await
-        .map(|windows| {
-            windows
-                .into_iter()
-                .map(|window| window.with_timezone(timezone_date_start))
-                .collect::<Vec<PriceWindow>>()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(parameters.consumed_kwh));
+
+    let production = forecast_production(
+        &state,
+        parameters.moment_start.to_utc(),
+        parameters.moment_end.to_utc(),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let key = WindowKey {
+        start_moment: parameters.moment_start.to_utc(),
+        end_moment: parameters.moment_end.to_utc(),
+        durations: durations.clone(),
+        area,
+        inputs: json!({
+            "price_cap": price_cap,
+            "power_kw": parameters.power_kw,
+            "solar_forecast": production,
         })
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .to_string(),
+    };
+
+    let windows = match state.window_cache.cached(&key) {
+        Some(windows) => windows,
+        None => {
+            let costs = self_consumption_costs(
+                &prices,
+                &costs(&prices, price_cap),
+                &production,
+                parameters.power_kw.unwrap_or(DEFAULT_LOAD_KW),
+            );
+
+            let windows = Arc::new(
+                durations
+                    .iter()
+                    .filter_map(|duration| {
+                        optimize_costs(
+                            &Contiguous {
+                                duration: (*duration).max(1) as usize,
+                            },
+                            &prices,
+                            &costs,
+                            price_cap,
+                        )
+                    })
+                    .flatten()
+                    .collect::<Vec<PriceWindow>>(),
+            );
+
+            state.window_cache.cache(key, generation, windows.clone());
+            windows
+        }
+    };
+
+    let optimal_windows: Vec<PriceWindow> = windows
+        .iter()
+        .map(|window| window.with_timezone(timezone_date_start))
+        .collect();
+
+    let inputs = json!({
+        "durations": durations,
+        "moment_start": parameters.moment_start,
+        "moment_end": parameters.moment_end,
+        "price_cap": price_cap,
+        "power_kw": parameters.power_kw,
+        "prices": price_inputs(&prices),
+        "solar_forecast": production,
+    });
+
+    record_planned_windows(&state, "time-slots", None, &optimal_windows, &inputs).await;
+
+    let optimal_windows = with_consumer_pricing(
+        &state,
+        optimal_windows,
+        parameters.breakdown.unwrap_or(false),
+    );
+
+    let optimal_windows = match &parameters.currency {
+        Some(currency) => {
+            convert_windows(
+                &state,
+                optimal_windows,
+                currency,
+                parameters.moment_start.date_naive(),
+            )
+            .await?
+        }
+        None => optimal_windows,
+    };
 
     Ok((StatusCode::OK, Json(optimal_windows)))
 }
 
-async fn has_prices_of_date(db: PgPool, date: NaiveDate) -> Result<bool, String> {
-    let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM prices WHERE moment::date = $1")
-        .bind(date)
-        .fetch_one(&db)
-        .await
-        .map_err(|e| e.to_string())?;
+/// Derive the consumer prices of windows when they are calculated at response time, and
+/// only keep the component breakdown when it is requested
+fn with_consumer_pricing(
+    state: &AppState,
+    windows: Vec<PriceWindow>,
+    breakdown: bool,
+) -> Vec<PriceWindow> {
+    let settings = state.settings();
 
-    Ok(row.0 > 0)
+    windows
+        .into_iter()
+        .map(|window| match &settings.pricing.price_formula {
+            Some(formula) if formula.application == FormulaApplication::Response => {
+                window.with_price_formula(formula)
+            }
+            _ => window,
+        })
+        .map(|window| {
+            if breakdown {
+                window
+            } else {
+                PriceWindow {
+                    components: None,
+                    ..window
+                }
+            }
+        })
+        .collect()
 }
 
-/// Fetch the prices of the provider for the current day
-async fn fetch_prices_of_today_from_provider(
-    electricity_provider: &dyn ElectricityPriceProvider,
-    price_repository: &dyn PriceRepository,
-) -> Result<Vec<PricePoint>, ElectricityProviderError> {
-    info!("prices for today not yet fetched");
-    let fetch_result = electricity_provider.fetch_prices().await;
-
-    let persisting_result = match fetch_result {
-        Ok(fetched_prices) => {
-            info!("Fetched {} prices", fetched_prices.len());
-            price_repository
-                .persist_prices(&fetched_prices, electricity_provider.name())
-                .await
-                .and(Ok(fetched_prices))
-        }
-        Err(error) => {
-            error!("{}", error);
-            return Err(error.clone());
+/// Express the prices of the windows in the requested currency
+async fn convert_windows(
+    state: &AppState,
+    windows: Vec<PriceWindow>,
+    currency: &str,
+    date: NaiveDate,
+) -> Result<Vec<PriceWindow>, (StatusCode, String)> {
+    let currency_error = |e: CurrencyError| match e {
+        CurrencyError::UnknownCurrency(_) | CurrencyError::MissingRate(_) => {
+            (StatusCode::BAD_REQUEST, e.to_string())
         }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
     };
 
-    match persisting_result {
-        Ok(prices) => Ok(prices),
-        Err(error) => {
-            error!("{}", error);
-            Err(ElectricityProviderError::FetchPrices(error.to_string()))
-        }
+    let currency = parse_currency(currency).map_err(currency_error)?;
+
+    let mut converted = Vec::with_capacity(windows.len());
+
+    for window in windows {
+        let rate = resolve_conversion_rate(
+            &*state.exchange_rate_repository,
+            &window.currency,
+            &currency,
+            date,
+        )
+        .await
+        .map_err(currency_error)?;
+
+        converted.push(window.with_exchange_rate(rate, &currency));
+    }
+
+    Ok(converted)
+}
+
+/// Only allow requests that carry the configured admin token as a bearer token
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(admin_token) = &state.admin_token else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "administrative endpoints are disabled, set ADMIN_TOKEN".to_string(),
+        ));
+    };
+
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if tokens_match(token, admin_token) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "invalid admin token".to_string())),
+    }
+}
+
+/// Compare the digests of tokens in constant time, so neither the time a comparison takes nor
+/// the length of the token tells how much of a guessed token is right
+fn tokens_match(token: &str, admin_token: &str) -> bool {
+    Sha256::digest(token)
+        .as_slice()
+        .ct_eq(Sha256::digest(admin_token).as_slice())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::{
+        domain::PricePoint,
+        memory::MemoryPriceRepository,
+        price_repository::PriceRepository,
+        setup::test_app_state,
+        test_support::{local_moment, moment, price_point},
+    };
+
+    async fn state_with_prices(amounts: &[f64]) -> AppState {
+        let price_repository = MemoryPriceRepository::new();
+
+        let prices = amounts
+            .iter()
+            .enumerate()
+            .map(|(hour, amount)| {
+                price_point(
+                    moment("2024-06-30T00:00:00Z") + TimeDelta::hours(hour as i64),
+                    *amount,
+                )
+            })
+            .collect::<Vec<PricePoint>>();
+
+        price_repository
+            .persist_prices(&prices, "demo")
+            .await
+            .unwrap();
+
+        test_app_state(price_repository)
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret ", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_get_time_slots() {
+        let state = state_with_prices(&[0.3, 0.2, 0.1, 0.15, 0.4, 0.5]).await;
+
+        let (status, Json(windows)) = get_time_slots(
+            State(state),
+            Query(TimeslotParameters {
+                // a duration that no period covers is ignored
+                durations: "2,2147483647".to_string(),
+                moment_start: local_moment("2024-06-30T02:00:00+02:00"),
+                moment_end: local_moment("2024-06-30T08:00:00+02:00"),
+                ..TimeslotParameters::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(windows.len(), 1);
+        // the window is expressed in the offset of the requested period
+        assert_eq!(
+            windows[0].starts_at,
+            local_moment("2024-06-30T04:00:00+02:00")
+        );
+        assert_eq!(
+            windows[0].ends_at,
+            local_moment("2024-06-30T05:59:59+02:00")
+        );
+        assert_eq!(windows[0].currency, "EUR");
+    }
+
+    #[tokio::test]
+    async fn test_get_time_slots_rejects_power() {
+        let state = state_with_prices(&[0.3, 0.2]).await;
+
+        let response = get_time_slots(
+            State(state),
+            Query(TimeslotParameters {
+                durations: "1".to_string(),
+                power_kw: Some(0.0),
+                ..TimeslotParameters::default()
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = with_request_id(response, "f00d").await;
+        let message = to_bytes(response.into_body(), MAX_ERROR_MESSAGE_BYTES)
+            .await
+            .unwrap();
+
+        assert_eq!(message, "power_kw must be positive (request f00d)");
     }
 }
@@ -0,0 +1,411 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, FixedOffset, NaiveDate};
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use super::{require_admin, AreaParameters};
+use crate::{
+    backup::BackupPrice,
+    domain::{start_of_day, PricePoint, PriceUnit},
+    price_export::{import_backup, parse_prices_csv, prices_csv, PriceFormat},
+    price_repository::{unique_prices, PriceGap, PriceRepositoryError},
+    setup::AppState,
+};
+
+/// The most prices that are overwritten at once, a month of quarter hours
+const MAX_CORRECTIONS: usize = 31 * 24 * 4;
+
+/// The largest import that is accepted, which fits years of quarter hours of a few providers
+pub(super) const MAX_IMPORT_BYTES: usize = 256 * 1024 * 1024;
+
+/// The longest range of days that is checked for gaps at once
+const MAX_GAP_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct GapParameters {
+    /// The first day to check
+    from: NaiveDate,
+    /// The last day to check, defaults to `from`
+    to: Option<NaiveDate>,
+    /// The bidding zone of the prices, e.g. `NL`, of every area by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ExportParameters {
+    /// The first day to export the prices of
+    from: NaiveDate,
+    /// The last day to export the prices of, defaults to `from`
+    to: Option<NaiveDate>,
+    /// Only the prices of this provider
+    provider: Option<String>,
+    /// Only the prices of this bidding zone, e.g. `NL`
+    area: Option<String>,
+    /// `json` by default, or `csv`
+    format: Option<PriceFormat>,
+}
+
+/// A price that replaces the one a provider published for a moment
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PriceCorrection {
+    moment: DateTime<FixedOffset>,
+    price: f64,
+    consumer_price: Option<f64>,
+    currency: String,
+    /// Defaults to `kWh`
+    unit: Option<PriceUnit>,
+    /// Defaults to an hour
+    resolution_minutes: Option<i32>,
+    area: Option<String>,
+}
+
+impl From<PriceCorrection> for PricePoint {
+    fn from(correction: PriceCorrection) -> PricePoint {
+        PricePoint {
+            moment: correction.moment.to_utc(),
+            monetary_amount: correction.price,
+            currency: correction.currency,
+            consumer_amount: correction.consumer_price,
+            components: None,
+            unit: correction.unit.unwrap_or(PriceUnit::KilowattHour),
+            resolution_minutes: correction.resolution_minutes.unwrap_or(60),
+            area: correction.area,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PriceCorrectionRequest {
+    prices: Vec<PriceCorrection>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct PriceChanges {
+    /// The number of prices that were stored or deleted
+    prices: u64,
+}
+
+/// Delete the prices a provider published for a local day, optionally of a single area, after a
+/// bad ingest. Today and tomorrow are fetched again on the next fetch, earlier days when they are
+/// caught up on at startup. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_prices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((provider, date)): Path<(String, NaiveDate)>,
+    parameters: Query<AreaParameters>,
+) -> axum::response::Result<(StatusCode, Json<PriceChanges>)> {
+    require_admin(&state, &headers)?;
+
+    let deleted = state
+        .price_repository
+        .delete_prices(
+            &provider,
+            start_of_day(&state.timezone, date),
+            start_of_day(&state.timezone, date + Days::new(1)),
+            parameters.area.as_deref(),
+        )
+        .await
+        .map_err(price_error)?;
+
+    Ok((StatusCode::OK, Json(PriceChanges { prices: deleted })))
+}
+
+/// Overwrite the prices a provider published for specific moments, which are kept as the next
+/// version of the prices they replace. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers, request))]
+pub(super) async fn put_prices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Json(request): Json<PriceCorrectionRequest>,
+) -> axum::response::Result<(StatusCode, Json<PriceChanges>)> {
+    require_admin(&state, &headers)?;
+
+    if request.prices.len() > MAX_CORRECTIONS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "at most {} prices can be overwritten at once",
+                MAX_CORRECTIONS
+            ),
+        )
+            .into());
+    }
+
+    let prices = request
+        .prices
+        .into_iter()
+        .map(PricePoint::from)
+        .collect::<Vec<PricePoint>>();
+
+    if let Some(price) = prices.iter().find(|price| {
+        !price.monetary_amount.is_finite()
+            || price
+                .consumer_amount
+                .is_some_and(|amount| !amount.is_finite())
+    }) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the price of {} is not a finite number", price.moment),
+        )
+            .into());
+    }
+
+    state
+        .price_repository
+        .persist_prices(&prices, &provider)
+        .await
+        .map_err(price_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(PriceChanges {
+            prices: unique_prices(&prices).len() as u64,
+        }),
+    ))
+}
+
+/// The hours of the local days between two dates that a provider has no price for, of every
+/// provider that has stored prices, to backfill them. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_gaps(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Query<GapParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<PriceGap>>)> {
+    require_admin(&state, &headers)?;
+
+    let to = parameters.to.unwrap_or(parameters.from);
+    let days = (to - parameters.from).num_days();
+
+    if !(0..MAX_GAP_DAYS).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_GAP_DAYS),
+        )
+            .into());
+    }
+
+    let gaps = state
+        .price_repository
+        .fetch_price_gaps(
+            start_of_day(&state.timezone, parameters.from),
+            start_of_day(&state.timezone, to + Days::new(1)),
+            parameters.area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(gaps)))
+}
+
+/// Export the prices of the local days between two dates, tagged with their provider, as JSON or
+/// CSV to import them into another instance. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_export(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Query<ExportParameters>,
+) -> axum::response::Result<Response> {
+    require_admin(&state, &headers)?;
+
+    let to = parameters.to.unwrap_or(parameters.from);
+
+    if to < parameters.from {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "the last day must not be before the first".to_string(),
+        )
+            .into());
+    }
+
+    let prices = state
+        .backup_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, parameters.from),
+            start_of_day(&state.timezone, to + Days::new(1)),
+            parameters.provider.as_deref(),
+            parameters.area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(match parameters.format.unwrap_or_default() {
+        PriceFormat::Json => (StatusCode::OK, Json(prices)).into_response(),
+        PriceFormat::Csv => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, "text/csv")],
+            prices_csv(&prices),
+        )
+            .into_response(),
+    })
+}
+
+/// Import prices tagged with their provider, as exported by another instance, from a JSON array
+/// or from CSV when the content type is `text/csv`. Providers that are not stored yet are added,
+/// and prices that are stored already are kept. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers, body))]
+pub(super) async fn post_import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> axum::response::Result<(StatusCode, Json<PriceChanges>)> {
+    require_admin(&state, &headers)?;
+
+    let csv = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+
+    let prices = if csv {
+        parse_prices_csv(&body)
+    } else {
+        serde_json::from_str::<Vec<BackupPrice>>(&body).map_err(|e| e.to_string())
+    }
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let backup = import_backup(prices).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let restoration = state
+        .backup_repository
+        .restore_backup(&backup)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    info!(
+        "imported {} of {} prices",
+        restoration.prices,
+        backup.prices.len()
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PriceChanges {
+            prices: restoration.prices,
+        }),
+    ))
+}
+
+fn price_error(error: PriceRepositoryError) -> (StatusCode, String) {
+    match error {
+        PriceRepositoryError::UnknownProvider(_) => (StatusCode::NOT_FOUND, error.to_string()),
+        PriceRepositoryError::PersistenceError(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::AUTHORIZATION, HeaderValue};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{memory::MemoryPriceRepository, setup::test_app_state, test_support::moment};
+
+    fn admin_state() -> (AppState, HeaderMap) {
+        let mut state = test_app_state(MemoryPriceRepository::new());
+        state.admin_token = Some("secret".to_string());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+
+        (state, headers)
+    }
+
+    fn corrections(prices: serde_json::Value) -> Json<PriceCorrectionRequest> {
+        Json(serde_json::from_value(json!({ "prices": prices })).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_put_prices() {
+        let (state, headers) = admin_state();
+
+        let (status, Json(changes)) = put_prices(
+            State(state.clone()),
+            headers,
+            Path("tibber".to_string()),
+            corrections(json!([
+                {"moment": "2024-06-30T10:00:00+02:00", "price": 0.2, "consumer_price": 0.3, "currency": "EUR"},
+                {"moment": "2024-06-30T11:00:00+02:00", "price": -0.1, "currency": "EUR", "area": "NL"}
+            ])),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(changes.prices, 2);
+
+        let prices = state
+            .price_repository
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[0].moment, moment("2024-06-30T08:00:00Z"));
+        assert_eq!(prices[0].consumer_amount, Some(0.3));
+        assert_eq!(prices[0].unit, PriceUnit::KilowattHour);
+        assert_eq!(prices[1].area.as_deref(), Some("NL"));
+    }
+
+    #[tokio::test]
+    async fn test_put_prices_rejects_infinite_prices() {
+        let (state, headers) = admin_state();
+
+        let correction = |hour: &str, consumer_price: Option<f64>| PriceCorrection {
+            moment: DateTime::parse_from_rfc3339(hour).unwrap(),
+            price: 0.2,
+            consumer_price,
+            currency: "EUR".to_string(),
+            unit: None,
+            resolution_minutes: None,
+            area: None,
+        };
+
+        let response = put_prices(
+            State(state.clone()),
+            headers,
+            Path("tibber".to_string()),
+            Json(PriceCorrectionRequest {
+                prices: vec![
+                    correction("2024-06-30T10:00:00+02:00", None),
+                    correction("2024-06-30T11:00:00+02:00", Some(f64::INFINITY)),
+                ],
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // nothing of a rejected correction is stored
+        assert!(state
+            .price_repository
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
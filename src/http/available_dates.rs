@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashSet};
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, DurationRound, NaiveDate, TimeDelta, TimeZone, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{domain::start_of_day, setup::AppState};
+
+/// The longest range of days that is checked at once
+const MAX_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct AvailableDateParameters {
+    /// The first day to check
+    from: NaiveDate,
+    /// The last day to check, defaults to `from`
+    to: Option<NaiveDate>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// A day that providers have a price for every hour of
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct AvailableDate {
+    date: NaiveDate,
+    providers: Vec<String>,
+}
+
+/// The local days between two dates that have complete prices, with the providers that have
+/// them, so clients can leave out the days they cannot chart
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_available_dates(
+    State(state): State<AppState>,
+    parameters: Query<AvailableDateParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<AvailableDate>>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let to = parameters.to.unwrap_or(parameters.from);
+    let days = (to - parameters.from).num_days();
+
+    if !(0..MAX_DAYS).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_DAYS),
+        )
+            .into());
+    }
+
+    let moments = state
+        .price_repository
+        .fetch_price_moments(
+            start_of_day(&state.timezone, parameters.from),
+            start_of_day(&state.timezone, to + Days::new(1)),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(available_dates(
+            &state.timezone,
+            parameters.from,
+            to,
+            &moments,
+        )),
+    ))
+}
+
+/// The days between two dates of which a provider has a price within every hour, as a day lasts
+/// 23 or 25 hours when daylight saving time starts or ends
+fn available_dates<Tz: TimeZone>(
+    timezone: &Tz,
+    from: NaiveDate,
+    to: NaiveDate,
+    moments: &[(String, DateTime<Utc>)],
+) -> Vec<AvailableDate> {
+    let mut hours = BTreeMap::<(NaiveDate, &str), HashSet<DateTime<Utc>>>::new();
+
+    for (provider, moment) in moments {
+        let hour = moment
+            .duration_trunc(TimeDelta::hours(1))
+            .unwrap_or(*moment);
+        let date = hour.with_timezone(timezone).date_naive();
+
+        hours
+            .entry((date, provider.as_str()))
+            .or_default()
+            .insert(hour);
+    }
+
+    from.iter_days()
+        .take_while(|date| *date <= to)
+        .filter_map(|date| {
+            let next = date + Days::new(1);
+            let day_hours =
+                (start_of_day(timezone, next) - start_of_day(timezone, date)).num_hours();
+
+            let providers = hours
+                .range((date, "")..(next, ""))
+                .filter(|(_, priced)| priced.len() as i64 >= day_hours)
+                .map(|((_, provider), _)| provider.to_string())
+                .collect::<Vec<String>>();
+
+            (!providers.is_empty()).then_some(AvailableDate { date, providers })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Amsterdam;
+
+    use super::*;
+
+    #[test]
+    fn test_available_dates() {
+        let date = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        let hours = |provider: &str, start: &str, count: i64| {
+            let start = DateTime::parse_from_rfc3339(start).unwrap().to_utc();
+
+            (0..count)
+                .map(|hour| (provider.to_string(), start + TimeDelta::hours(hour)))
+                .collect::<Vec<(String, DateTime<Utc>)>>()
+        };
+
+        // the day daylight saving time ends has 25 hours
+        let moments = [
+            hours("tibber", "2024-10-26T22:00:00Z", 25),
+            hours("entsoe", "2024-10-26T22:00:00Z", 24),
+            hours("tibber", "2024-10-27T23:00:00Z", 23),
+        ]
+        .concat();
+
+        assert_eq!(
+            available_dates(&Amsterdam, date("2024-10-26"), date("2024-10-28"), &moments),
+            vec![AvailableDate {
+                date: date("2024-10-27"),
+                providers: vec!["tibber".to_string()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{round, start_of_day},
+    optimizer::{optimize, Contiguous},
+    setup::AppState,
+};
+
+const DEFAULT_DAYS: u64 = 30;
+const MAX_DAYS: u64 = 90;
+/// The local hour a device is assumed to be started at without electrack
+const DEFAULT_FIXED_START_HOUR: i64 = 19;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct BacktestParameters {
+    /// The duration of a run in hours
+    duration: i32,
+    /// The number of days before today to replay
+    days: Option<u64>,
+    /// The local hour a run would start at without electrack
+    fixed_start_hour: Option<i64>,
+    /// The power draw of the device in kW, to express the prices as costs
+    power_kw: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct BacktestDay {
+    date: NaiveDate,
+    window_starts_at: DateTime<FixedOffset>,
+    optimal_cost: f64,
+    fixed_time_cost: f64,
+    daily_average_cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Backtest {
+    days: Vec<BacktestDay>,
+    optimal_cost: f64,
+    fixed_time_cost: f64,
+    daily_average_cost: f64,
+    savings_versus_fixed_time: f64,
+    savings_versus_daily_average: f64,
+}
+
+impl Backtest {
+    fn new(days: Vec<BacktestDay>) -> Self {
+        let optimal_cost: f64 = days.iter().map(|day| day.optimal_cost).sum();
+        let fixed_time_cost: f64 = days.iter().map(|day| day.fixed_time_cost).sum();
+        let daily_average_cost: f64 = days.iter().map(|day| day.daily_average_cost).sum();
+
+        Self {
+            days,
+            optimal_cost: round(optimal_cost, 2),
+            fixed_time_cost: round(fixed_time_cost, 2),
+            daily_average_cost: round(daily_average_cost, 2),
+            savings_versus_fixed_time: round(fixed_time_cost - optimal_cost, 2),
+            savings_versus_daily_average: round(daily_average_cost - optimal_cost, 2),
+        }
+    }
+}
+
+/// Replay the last days and compare the cost of running a device in the windows electrack
+/// would have chosen to running it at a fixed time, or at the daily average price.
+/// Days without complete data are left out.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_backtest(
+    State(state): State<AppState>,
+    parameters: Query<BacktestParameters>,
+) -> axum::response::Result<(StatusCode, Json<Backtest>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let number_of_days = parameters.days.unwrap_or(DEFAULT_DAYS);
+
+    if !(1..=MAX_DAYS).contains(&number_of_days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("days must be between 1 and {}", MAX_DAYS),
+        )
+            .into());
+    }
+
+    let fixed_start_hour = parameters
+        .fixed_start_hour
+        .unwrap_or(DEFAULT_FIXED_START_HOUR);
+    let power_kw = parameters.power_kw.unwrap_or(1.0);
+    let energy_kwh = parameters.duration as f64 * power_kw;
+
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+    let first_day = today - Days::new(number_of_days);
+
+    let mut days = Vec::new();
+
+    for date in first_day.iter_days().take(number_of_days as usize) {
+        let day_start = start_of_day(&state.timezone, date);
+        let day_end = start_of_day(&state.timezone, date + Days::new(1));
+
+        let prices = state
+            .price_repository
+            .fetch_prices(day_start, day_end, area.as_deref())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        let window = optimize(
+            &Contiguous {
+                duration: parameters.duration.max(1) as usize,
+            },
+            &prices,
+            None,
+        )
+        .and_then(|windows| windows.into_iter().next());
+
+        let fixed_start = day_start + TimeDelta::hours(fixed_start_hour);
+        let fixed_time = state
+            .price_repository
+            .fetch_price_statistics(
+                fixed_start,
+                fixed_start + TimeDelta::hours(parameters.duration as i64),
+                area.as_deref(),
+            )
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        let daily = state
+            .price_repository
+            .fetch_price_statistics(day_start, day_end, area.as_deref())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        let (Some(window), Some(fixed_time_average), Some(daily_average)) =
+            (window, fixed_time.average, daily.average)
+        else {
+            continue;
+        };
+
+        let Some(window_average) = window.effective_average_price() else {
+            continue;
+        };
+
+        days.push(BacktestDay {
+            date,
+            window_starts_at: window.with_timezone(state.timezone).starts_at,
+            optimal_cost: round(window_average * energy_kwh, 2),
+            fixed_time_cost: round(fixed_time_average * energy_kwh, 2),
+            daily_average_cost: round(daily_average * energy_kwh, 2),
+        });
+    }
+
+    Ok((StatusCode::OK, Json(Backtest::new(days))))
+}
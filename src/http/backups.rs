@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    backup::{list_backups, restore_backup, BackupError, Restoration},
+    s3::S3Object,
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RestoreParameters {
+    /// The key of the backup to restore, the latest when absent
+    key: Option<String>,
+}
+
+/// List the backups in the bucket, from oldest to newest. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_backups(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> axum::response::Result<(StatusCode, Json<Vec<S3Object>>)> {
+    require_admin(&state, &headers)?;
+
+    let Some(configuration) = &state.backup else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            BackupError::NotConfigured.to_string(),
+        )
+            .into());
+    };
+
+    let backups = list_backups(configuration)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    Ok((StatusCode::OK, Json(backups)))
+}
+
+/// Restore the prices, devices and schedules of a backup into the database, e.g. after it was
+/// lost. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_restore(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Query<RestoreParameters>,
+) -> axum::response::Result<(StatusCode, Json<Restoration>)> {
+    require_admin(&state, &headers)?;
+
+    let restoration = restore_backup(&state, parameters.key.as_deref())
+        .await
+        .map_err(|e| {
+            let status = match e {
+                BackupError::NotConfigured | BackupError::NotFound => StatusCode::NOT_FOUND,
+                BackupError::Storage(_) => StatusCode::BAD_GATEWAY,
+                BackupError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                BackupError::Restore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+
+            (status, e.to_string())
+        })?;
+
+    Ok((StatusCode::OK, Json(restoration)))
+}
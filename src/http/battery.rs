@@ -0,0 +1,167 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, DurationRound, FixedOffset, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    battery::{Battery, BatteryAction},
+    optimizer::costs,
+    setup::AppState,
+};
+
+/// How far ahead to plan without an end moment, covering the prices of tomorrow
+const DEFAULT_HORIZON: TimeDelta = TimeDelta::hours(48);
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct BatteryParameters {
+    capacity_kwh: f64,
+    charge_power_kw: f64,
+    /// The maximum discharging power, the charging power by default
+    discharge_power_kw: Option<f64>,
+    /// The share of the energy that is left after charging and discharging it, 0.9 by default
+    round_trip_efficiency: Option<f64>,
+    /// The stored energy at the start, empty by default
+    initial_kwh: Option<f64>,
+    /// The start of the plan, the current hour by default
+    moment_start: Option<DateTime<FixedOffset>>,
+    /// The end of the plan, the last known price by default
+    moment_end: Option<DateTime<FixedOffset>>,
+    /// The consumption in kWh so far in the current price cap period
+    consumed_kwh: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct BatterySlot {
+    moment: DateTime<FixedOffset>,
+    action: BatteryAction,
+    /// The energy taken from the grid when charging or delivered when discharging
+    grid_kwh: f64,
+    /// The stored energy at the end of the hour
+    stored_kwh: f64,
+    price: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct BatteryPlan {
+    slots: Vec<BatterySlot>,
+    charged_kwh: f64,
+    discharged_kwh: f64,
+    /// The value of the delivered energy minus the cost of the charged energy
+    value: f64,
+}
+
+/// Plan when a battery charges and discharges over the known prices to make the most of the
+/// differences in price
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_battery_plan(
+    State(state): State<AppState>,
+    parameters: Query<BatteryParameters>,
+) -> axum::response::Result<(StatusCode, Json<BatteryPlan>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let battery = resolve_battery(&parameters).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let now = Utc::now();
+    let timezone = parameters
+        .moment_start
+        .map_or(now.fixed_offset().timezone(), |moment| moment.timezone());
+    let start = parameters.moment_start.map_or(
+        now.duration_trunc(TimeDelta::hours(1)).unwrap_or(now),
+        |moment| moment.to_utc(),
+    );
+    let end = parameters
+        .moment_end
+        .map_or(start + DEFAULT_HORIZON, |moment| moment.to_utc());
+
+    let prices = state
+        .price_repository
+        .fetch_prices(start, end, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(parameters.consumed_kwh));
+
+    let costs = costs(&prices, price_cap);
+
+    let slots = battery
+        .plan_arbitrage(&costs)
+        .into_iter()
+        .zip(prices.iter().zip(&costs))
+        .map(|(slot, (price, cost))| BatterySlot {
+            moment: price.moment.with_timezone(&timezone),
+            action: slot.action,
+            grid_kwh: slot.grid_kwh,
+            stored_kwh: slot.stored_kwh,
+            price: *cost,
+        })
+        .collect::<Vec<BatterySlot>>();
+
+    let total = |action: BatteryAction| -> f64 {
+        slots
+            .iter()
+            .filter(|slot| slot.action == action)
+            .map(|slot| slot.grid_kwh)
+            .sum()
+    };
+
+    let value = slots
+        .iter()
+        .map(|slot| match slot.action {
+            BatteryAction::Charge => -slot.grid_kwh * slot.price,
+            BatteryAction::Discharge => slot.grid_kwh * slot.price,
+            BatteryAction::Idle => 0.0,
+        })
+        .sum();
+
+    Ok((
+        StatusCode::OK,
+        Json(BatteryPlan {
+            charged_kwh: total(BatteryAction::Charge),
+            discharged_kwh: total(BatteryAction::Discharge),
+            value,
+            slots,
+        }),
+    ))
+}
+
+fn resolve_battery(parameters: &BatteryParameters) -> Result<Battery, String> {
+    let battery = Battery {
+        capacity_kwh: parameters.capacity_kwh,
+        charge_power_kw: parameters.charge_power_kw,
+        discharge_power_kw: parameters
+            .discharge_power_kw
+            .unwrap_or(parameters.charge_power_kw),
+        round_trip_efficiency: parameters.round_trip_efficiency.unwrap_or(0.9),
+        initial_kwh: parameters.initial_kwh.unwrap_or(0.0),
+    };
+
+    if battery.capacity_kwh <= 0.0
+        || battery.charge_power_kw <= 0.0
+        || battery.discharge_power_kw <= 0.0
+    {
+        return Err("capacity and power must be positive".to_string());
+    }
+
+    if battery.round_trip_efficiency <= 0.0 || battery.round_trip_efficiency > 1.0 {
+        return Err("round_trip_efficiency must be between 0 and 1".to_string());
+    }
+
+    if !(0.0..=battery.capacity_kwh).contains(&battery.initial_kwh) {
+        return Err("initial_kwh must be between 0 and the capacity".to_string());
+    }
+
+    Ok(battery)
+}
@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{Datelike, NaiveDate, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{
+    billing::{billing_summary, BillingSummary},
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct BillingParameters {
+    /// The month to summarize as `2024-06`, the current month by default
+    month: Option<String>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// Summarize what a month costs including the configured fixed charges, so the total matches
+/// the invoice. While the month is under way, the total of the whole month is projected from
+/// the typical usage of the last four weeks.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_billing(
+    State(state): State<AppState>,
+    parameters: Query<BillingParameters>,
+) -> axum::response::Result<(StatusCode, Json<BillingSummary>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let now = Utc::now();
+
+    let month = match &parameters.month {
+        Some(month) => {
+            NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("unable to parse month \"{}\", expected e.g. 2024-06", month),
+                )
+            })?
+        }
+        None => now
+            .with_timezone(&state.timezone)
+            .date_naive()
+            .with_day(1)
+            .unwrap(),
+    };
+
+    let summary = billing_summary(&state, month, now, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(summary)))
+}
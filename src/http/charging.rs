@@ -0,0 +1,160 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, DurationRound, FixedOffset, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::instrument;
+
+use super::with_consumer_pricing;
+use crate::{
+    domain::PriceWindow,
+    optimizer::{costs, plan_charging},
+    planner::{price_inputs, record_planned_windows},
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ChargingParameters {
+    /// The energy to charge in kWh
+    energy_kwh: f64,
+    /// The maximum charging power in kW
+    max_power_kw: f64,
+    /// The moment the car is plugged in, now by default
+    plugged_in_at: Option<DateTime<FixedOffset>>,
+    /// The moment the car has to be charged by
+    departure_at: DateTime<FixedOffset>,
+    /// The consumption in kWh so far in the current price cap period
+    consumed_kwh: Option<f64>,
+    /// Whether to include the components of the consumer price
+    breakdown: Option<bool>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct ChargingSlot {
+    #[serde(flatten)]
+    window: PriceWindow,
+    energy_kwh: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct ChargingPlan {
+    slots: Vec<ChargingSlot>,
+    /// The energy that is charged according to the plan
+    energy_kwh: f64,
+    /// The energy that does not fit before departure at the maximum power, or of which the
+    /// prices are not known yet
+    unmet_kwh: f64,
+    /// The cost of the charged energy at the optimized price
+    cost: f64,
+}
+
+/// Plan when to charge an electric vehicle between plugging in and departure, spreading the
+/// required energy over the cheapest hours rather than a single window
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_charging_plan(
+    State(state): State<AppState>,
+    parameters: Query<ChargingParameters>,
+) -> axum::response::Result<(StatusCode, Json<ChargingPlan>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    if parameters.energy_kwh <= 0.0 || parameters.max_power_kw <= 0.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "energy_kwh and max_power_kw must be positive".to_string(),
+        )
+            .into());
+    }
+
+    let timezone = parameters.departure_at.timezone();
+    let plugged_in_at = parameters
+        .plugged_in_at
+        .map_or(Utc::now(), |moment| moment.to_utc());
+    let departure_at = parameters.departure_at.to_utc();
+
+    if departure_at <= plugged_in_at {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "departure_at must be after plugged_in_at".to_string(),
+        )
+            .into());
+    }
+
+    // the hour the car is plugged in within is partly available
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            plugged_in_at
+                .duration_trunc(TimeDelta::hours(1))
+                .unwrap_or(plugged_in_at),
+            departure_at,
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(parameters.consumed_kwh));
+
+    let costs = costs(&prices, price_cap);
+
+    let planned = plan_charging(
+        &prices,
+        &costs,
+        parameters.energy_kwh,
+        parameters.max_power_kw,
+        plugged_in_at,
+        departure_at,
+    );
+
+    let windows = planned
+        .iter()
+        .filter_map(|slot| PriceWindow::from_prices(&prices[slot.index..=slot.index], price_cap))
+        .map(|window| window.with_timezone(timezone))
+        .collect::<Vec<PriceWindow>>();
+
+    let inputs = json!({
+        "energy_kwh": parameters.energy_kwh,
+        "max_power_kw": parameters.max_power_kw,
+        "plugged_in_at": plugged_in_at,
+        "departure_at": departure_at,
+        "price_cap": price_cap,
+        "prices": price_inputs(&prices),
+    });
+
+    record_planned_windows(&state, "charging-plan", None, &windows, &inputs).await;
+
+    let windows = with_consumer_pricing(&state, windows, parameters.breakdown.unwrap_or(false));
+
+    let energy_kwh = planned.iter().map(|slot| slot.energy_kwh).sum::<f64>();
+
+    Ok((
+        StatusCode::OK,
+        Json(ChargingPlan {
+            cost: planned
+                .iter()
+                .map(|slot| slot.energy_kwh * costs[slot.index])
+                .sum(),
+            slots: planned
+                .iter()
+                .zip(windows)
+                .map(|(slot, window)| ChargingSlot {
+                    window,
+                    energy_kwh: slot.energy_kwh,
+                })
+                .collect(),
+            energy_kwh,
+            unmet_kwh: (parameters.energy_kwh - energy_kwh).max(0.0),
+        }),
+    ))
+}
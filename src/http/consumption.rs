@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    consumption::validate_reading,
+    consumption_import::parse_consumption_csv,
+    domain::{round, Consumption},
+    setup::AppState,
+};
+
+/// The most readings that are accepted at once, a month of quarter hours
+const MAX_READINGS: usize = 31 * 24 * 4;
+
+/// The most readings that are imported at once, a year of quarter hours
+const MAX_IMPORTED_READINGS: usize = 366 * 24 * 4;
+
+/// The largest export that is imported, which fits a year of quarter hours with a row per
+/// register
+pub(super) const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ConsumptionRequest {
+    readings: Vec<Consumption>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct ConsumptionSummary {
+    /// The number of readings that were stored
+    stored: usize,
+    energy_kwh: f64,
+    exported_kwh: f64,
+}
+
+/// Store the energy drawn from and fed back into the grid in slots, as read from a meter. A
+/// slot must not extend into the next hour and replaces a stored slot that starts at the same
+/// moment. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers, request))]
+pub(super) async fn post_consumption(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ConsumptionRequest>,
+) -> axum::response::Result<(StatusCode, Json<ConsumptionSummary>)> {
+    require_admin(&state, &headers)?;
+
+    validate_readings(&request.readings, MAX_READINGS)?;
+
+    state
+        .consumption_repository
+        .persist_consumption(&request.readings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(summarize(&request.readings))))
+}
+
+/// Store the energy a single device drew in slots, as read from a smart plug, to break the
+/// costs down by device. The slots are validated like those of the household, and exported
+/// energy is ignored. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers, request))]
+pub(super) async fn post_device_consumption(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(request): Json<ConsumptionRequest>,
+) -> axum::response::Result<(StatusCode, Json<ConsumptionSummary>)> {
+    require_admin(&state, &headers)?;
+
+    state
+        .device_repository
+        .fetch_device(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::NOT_FOUND, format!("no device with id {}", id)))?;
+
+    let readings = request
+        .readings
+        .into_iter()
+        .map(|reading| Consumption {
+            exported_kwh: 0.0,
+            ..reading
+        })
+        .collect::<Vec<Consumption>>();
+
+    validate_readings(&readings, MAX_READINGS)?;
+
+    state
+        .consumption_repository
+        .persist_device_consumption(id, &readings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(summarize(&readings))))
+}
+
+/// Import the history of the consumption from the CSV export of a grid operator, such as
+/// Liander or Fluvius, of which local times are interpreted in the configured timezone. The
+/// readings replace those that start at the same moment, so an export can be imported again.
+/// Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers, csv))]
+pub(super) async fn post_consumption_import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    csv: String,
+) -> axum::response::Result<(StatusCode, Json<ConsumptionSummary>)> {
+    require_admin(&state, &headers)?;
+
+    let readings =
+        parse_consumption_csv(&csv, &state.timezone).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    validate_readings(&readings, MAX_IMPORTED_READINGS)?;
+
+    state
+        .consumption_repository
+        .persist_consumption(&readings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(summarize(&readings))))
+}
+
+/// Check every reading, and that no two readings start at the same moment
+fn validate_readings(readings: &[Consumption], max: usize) -> Result<(), (StatusCode, String)> {
+    if readings.len() > max {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("at most {} readings can be stored at once", max),
+        ));
+    }
+
+    let mut starts = HashSet::new();
+
+    for reading in readings {
+        validate_reading(reading).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+        if !starts.insert(reading.starts_at) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "there are several readings starting at {}",
+                    reading.starts_at
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn summarize(readings: &[Consumption]) -> ConsumptionSummary {
+    ConsumptionSummary {
+        stored: readings.len(),
+        energy_kwh: round(readings.iter().map(|reading| reading.energy_kwh).sum(), 3),
+        exported_kwh: round(readings.iter().map(|reading| reading.exported_kwh).sum(), 3),
+    }
+}
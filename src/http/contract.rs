@@ -0,0 +1,61 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    contract::{current_contract, Contract},
+    setup::AppState,
+};
+
+/// The contract the cost reports are based on, which is the configured one until one is
+/// stored
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_contract(
+    State(state): State<AppState>,
+) -> axum::response::Result<(StatusCode, Json<Contract>)> {
+    let contract = current_contract(&state)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(contract)))
+}
+
+/// Store the contract, which replaces the configured one. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn put_contract(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(contract): Json<Contract>,
+) -> axum::response::Result<(StatusCode, Json<Contract>)> {
+    require_admin(&state, &headers)?;
+
+    let charges = [
+        contract.standing_charge_per_day,
+        contract.fixed_fee_per_month,
+        contract.tax_credit_per_month,
+    ];
+
+    if charges
+        .iter()
+        .chain(contract.feed_in_compensation.iter())
+        .any(|charge| !charge.is_finite() || *charge < 0.0)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "the charges of a contract cannot be negative".to_string(),
+        )
+            .into());
+    }
+
+    state
+        .contract_repository
+        .persist_contract(&contract)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(contract)))
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, FixedOffset, NaiveDate};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    contract::{current_contract, FixedCharges},
+    cost_report::{fetch_cost_periods, fetch_device_costs, CostPeriod, Granularity},
+    domain::{round, start_of_day},
+    setup::AppState,
+    tariff::PriceComponents,
+};
+
+/// The longest range of days that can be reported in a single request
+const MAX_DAYS: u64 = 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct CostParameters {
+    /// The first day to report
+    from: NaiveDate,
+    /// The last day to report, defaults to `from`
+    to: Option<NaiveDate>,
+    /// Either `hour`, `day` (default) or `week`
+    granularity: Option<Granularity>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct CostReport {
+    currency: Option<String>,
+    periods: Vec<PeriodCost>,
+    energy_kwh: f64,
+    cost: f64,
+    average_price: Option<f64>,
+    unpriced_kwh: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<PriceComponents>,
+    exported_kwh: f64,
+    feed_in_revenue: f64,
+    /// What the contract charges over the days regardless of consumption
+    fixed_charges: FixedCharges,
+    /// The cost of the consumption less the feed-in revenue, plus the fixed charges
+    total: f64,
+    /// What the consumption of the devices with readings of their own cost, which is part of
+    /// the consumption of the household
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<DeviceCost>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct DeviceCost {
+    device_id: i64,
+    name: Option<String>,
+    energy_kwh: f64,
+    cost: f64,
+    average_price: Option<f64>,
+    unpriced_kwh: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct PeriodCost {
+    starts_at: DateTime<FixedOffset>,
+    energy_kwh: f64,
+    cost: f64,
+    /// The cost per kWh of the consumption of which the price is stored
+    average_price: Option<f64>,
+    /// Consumption in hours of which no price is stored, which is left out of the cost
+    unpriced_kwh: f64,
+    /// What each component of the price cost, with tariff components
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<PriceComponents>,
+    exported_kwh: f64,
+    /// What the energy fed back into the grid earned in hours of which the price is stored
+    feed_in_revenue: f64,
+}
+
+/// Report what the stored consumption cost on every hour, day or week between two dates, at
+/// the price a consumer pays in every hour, what feeding back into the grid earned, and what
+/// the contract charges over those days regardless of consumption. The cost of the devices
+/// with readings of their own is broken down by device. Days are interpreted in the configured
+/// timezone.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_costs(
+    State(state): State<AppState>,
+    parameters: Query<CostParameters>,
+) -> axum::response::Result<(StatusCode, Json<CostReport>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let to = parameters.to.unwrap_or(parameters.from);
+
+    let days = (to - parameters.from).num_days();
+
+    if days < 0 || days as u64 >= MAX_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_DAYS),
+        )
+            .into());
+    }
+
+    let start = start_of_day(&state.timezone, parameters.from);
+    let end = start_of_day(&state.timezone, to + Days::new(1));
+
+    let (periods, currency) = fetch_cost_periods(
+        &state,
+        start,
+        end,
+        parameters.granularity.unwrap_or(Granularity::Day),
+        area.as_deref(),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let total = CostPeriod::total(start, &periods);
+
+    let fixed_charges = current_contract(&state)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .fixed_charges(parameters.from, to + Days::new(1));
+
+    let net_cost = total.net_cost();
+
+    let device_costs = fetch_device_costs(&state, start, end, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let devices = match device_costs.is_empty() {
+        true => vec![],
+        false => state
+            .device_repository
+            .fetch_devices()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+    };
+
+    let round_components = |components: PriceComponents| PriceComponents {
+        energy: round(components.energy, 3),
+        supplier_fee: round(components.supplier_fee, 3),
+        energy_tax: round(components.energy_tax, 3),
+        grid_fee: round(components.grid_fee, 3),
+        vat: round(components.vat, 3),
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(CostReport {
+            currency,
+            energy_kwh: round(total.energy_kwh, 3),
+            cost: round(total.cost, 3),
+            average_price: total.average_price().map(|price| round(price, 3)),
+            unpriced_kwh: round(total.unpriced_kwh, 3),
+            components: total.components.map(round_components),
+            exported_kwh: round(total.exported_kwh, 3),
+            feed_in_revenue: round(total.feed_in_revenue, 3),
+            total: round(net_cost + fixed_charges.total(), 3),
+            fixed_charges: fixed_charges.rounded(),
+            devices: device_costs
+                .into_iter()
+                .map(|(device_id, cost)| DeviceCost {
+                    device_id,
+                    name: devices
+                        .iter()
+                        .find(|device| device.id == device_id)
+                        .map(|device| device.name.clone()),
+                    energy_kwh: round(cost.energy_kwh, 3),
+                    cost: round(cost.cost, 3),
+                    average_price: cost.average_price().map(|price| round(price, 3)),
+                    unpriced_kwh: round(cost.unpriced_kwh, 3),
+                })
+                .collect(),
+            periods: periods
+                .into_iter()
+                .map(|period| PeriodCost {
+                    starts_at: period
+                        .starts_at
+                        .with_timezone(&state.timezone)
+                        .fixed_offset(),
+                    energy_kwh: round(period.energy_kwh, 3),
+                    cost: round(period.cost, 3),
+                    average_price: period.average_price().map(|price| round(price, 3)),
+                    unpriced_kwh: round(period.unpriced_kwh, 3),
+                    components: period.components.map(round_components),
+                    exported_kwh: round(period.exported_kwh, 3),
+                    feed_in_revenue: round(period.feed_in_revenue, 3),
+                })
+                .collect(),
+        }),
+    ))
+}
@@ -0,0 +1,403 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    actuator::Actuator,
+    device::{
+        validate_hours, validate_strategy, Actuation, Device, DeviceRun, PlannedRun, Recurrence,
+        Schedule, ScheduleOverride,
+    },
+    device_repository::{NewDevice, NewSchedule},
+    planner::{plan_all_devices, record_run_start, run_reports, RunReport},
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct DeviceRequest {
+    name: String,
+    /// The duration of a run in hours
+    duration: i32,
+    power_kw: Option<f64>,
+    /// Either `contiguous` (default) or `split`
+    strategy: Option<String>,
+    /// The minimum number of hours between the end of a run and the start of the next
+    min_gap_hours: Option<i32>,
+    /// How often the device runs, `daily` by default
+    recurrence: Option<Recurrence>,
+    /// The local hour from which the device may start, 0 by default
+    available_from_hour: Option<i32>,
+    /// The local hour the device must be finished by, 24 by default
+    finish_by_hour: Option<i32>,
+    /// The kind of switch to turn the device on and off with, e.g. `shelly`
+    actuator: Option<String>,
+    /// The address of the switch on the local network
+    actuator_url: Option<String>,
+    /// The topic of a Tasmota switch to command over MQTT instead
+    actuator_topic: Option<String>,
+    /// The switch of a device with several, the first by default
+    actuator_channel: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RunParameters {
+    /// The moment the device started or finished, now by default
+    at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PostponeParameters {
+    /// The moment before which the next run may not start
+    until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RunsParameters {
+    /// The number of days to look back, 14 by default
+    days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct DeviceOverview {
+    #[serde(flatten)]
+    device: Device,
+    schedules: Vec<Schedule>,
+    next_run: Option<PlannedRun>,
+}
+
+/// Register a device with a recurring schedule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<DeviceRequest>,
+) -> axum::response::Result<(StatusCode, Json<DeviceOverview>)> {
+    require_admin(&state, &headers)?;
+
+    let strategy = request.strategy.unwrap_or("contiguous".to_string());
+    let available_from_hour = request.available_from_hour.unwrap_or(0);
+    let finish_by_hour = request.finish_by_hour.unwrap_or(24);
+
+    validate_strategy(&strategy)
+        .and_then(|_| validate_hours(available_from_hour, finish_by_hour))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    if request.duration <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "duration must be a positive number of hours".to_string(),
+        )
+            .into());
+    }
+
+    if request.min_gap_hours.is_some_and(|gap| gap < 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "min_gap_hours must not be negative".to_string(),
+        )
+            .into());
+    }
+
+    if let Some(kind) = &request.actuator {
+        let actuator = Actuator::new(
+            kind,
+            request.actuator_url.as_deref(),
+            request.actuator_topic.as_deref(),
+            request.actuator_channel,
+        )
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+        if matches!(actuator, Actuator::TasmotaMqtt { .. }) && state.mqtt.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "an actuator_topic requires MQTT to be configured".to_string(),
+            )
+                .into());
+        }
+    }
+
+    let device = state
+        .device_repository
+        .persist_device(&NewDevice {
+            name: request.name,
+            duration: request.duration,
+            power_kw: request.power_kw,
+            strategy,
+            min_gap_hours: request.min_gap_hours,
+            actuator: request.actuator,
+            actuator_url: request.actuator_url,
+            actuator_topic: request.actuator_topic,
+            actuator_channel: request.actuator_channel,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state
+        .device_repository
+        .persist_schedule(&NewSchedule {
+            device_id: device.id,
+            recurrence: request.recurrence.unwrap_or(Recurrence::Daily),
+            available_from_hour,
+            finish_by_hour,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(overview(&state, device.id).await?),
+    ))
+}
+
+/// List the registered devices with their next planned run
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_devices(
+    State(state): State<AppState>,
+) -> axum::response::Result<(StatusCode, Json<Vec<DeviceOverview>>)> {
+    Ok((StatusCode::OK, Json(overviews(&state).await?)))
+}
+
+/// A registered device with its next planned run
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_device(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<(StatusCode, Json<DeviceOverview>)> {
+    Ok((StatusCode::OK, Json(overview(&state, id).await?)))
+}
+
+/// Skip the next planned run of a device, e.g. "not tonight", by skipping its occurrence. The
+/// overview contains the run that is planned instead. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_skip(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<(StatusCode, Json<DeviceOverview>)> {
+    require_admin(&state, &headers)?;
+
+    let next_run = overview(&state, id).await?.next_run.ok_or((
+        StatusCode::CONFLICT,
+        format!("device {} has no planned run", id),
+    ))?;
+
+    state
+        .device_repository
+        .persist_override(&ScheduleOverride {
+            schedule_id: next_run.schedule_id,
+            date: next_run.date,
+            skip: true,
+            available_from_hour: None,
+            finish_by_hour: None,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::OK, Json(overview(&state, id).await?)))
+}
+
+/// Postpone the next run of a device until a moment. The run is planned again in the hours
+/// after it, or in a later occurrence when too little time is left. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_postpone(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    parameters: Query<PostponeParameters>,
+) -> axum::response::Result<(StatusCode, Json<DeviceOverview>)> {
+    require_admin(&state, &headers)?;
+
+    let found = state
+        .device_repository
+        .set_snoozed_until(id, Some(parameters.until))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !found {
+        return Err((StatusCode::NOT_FOUND, format!("no device with id {}", id)).into());
+    }
+
+    Ok((StatusCode::OK, Json(overview(&state, id).await?)))
+}
+
+/// Cancel the postponement of the next run of a device. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_postpone(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<(StatusCode, Json<DeviceOverview>)> {
+    require_admin(&state, &headers)?;
+
+    let found = state
+        .device_repository
+        .set_snoozed_until(id, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !found {
+        return Err((StatusCode::NOT_FOUND, format!("no device with id {}", id)).into());
+    }
+
+    Ok((StatusCode::OK, Json(overview(&state, id).await?)))
+}
+
+/// Report that a device started running. The run is attributed to the occurrence of the
+/// schedule it started in. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_run_start(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    parameters: Query<RunParameters>,
+) -> axum::response::Result<(StatusCode, Json<DeviceRun>)> {
+    require_admin(&state, &headers)?;
+
+    let device = find_device(&state, id).await?;
+
+    let run = record_run_start(&state, &device, parameters.at.unwrap_or(Utc::now()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::CREATED, Json(run)))
+}
+
+/// Report that a device finished its latest run. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_run_finish(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    parameters: Query<RunParameters>,
+) -> axum::response::Result<(StatusCode, Json<DeviceRun>)> {
+    require_admin(&state, &headers)?;
+
+    let device = find_device(&state, id).await?;
+
+    let run = state
+        .device_repository
+        .finish_run(device.id, parameters.at.unwrap_or(Utc::now()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::CONFLICT,
+            format!("device {} has no unfinished run", device.id),
+        ))?;
+
+    Ok((StatusCode::OK, Json(run)))
+}
+
+/// The runs of a device in the past days compared to what was planned, including the
+/// occurrences it missed
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_runs(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    parameters: Query<RunsParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<RunReport>>)> {
+    let days = parameters.days.unwrap_or(14);
+
+    if !(1..=366).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "days must be between 1 and 366".to_string(),
+        )
+            .into());
+    }
+
+    let device = find_device(&state, id).await?;
+    let now = Utc::now();
+
+    let reports = run_reports(&state, &device, now - TimeDelta::days(days), now)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(reports)))
+}
+
+/// The windows in the past days that a device with an actuator was switched on and off for,
+/// with the error of the latest attempt that failed
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_actuations(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    parameters: Query<RunsParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<Actuation>>)> {
+    let days = parameters.days.unwrap_or(14);
+
+    if !(1..=366).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "days must be between 1 and 366".to_string(),
+        )
+            .into());
+    }
+
+    let device = find_device(&state, id).await?;
+    let now = Utc::now();
+
+    // the later windows of a run that started lie ahead
+    let actuations = state
+        .device_repository
+        .fetch_actuations(
+            device.id,
+            now - TimeDelta::days(days),
+            now + TimeDelta::days(2),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(actuations)))
+}
+
+async fn find_device(state: &AppState, id: i64) -> Result<Device, (StatusCode, String)> {
+    state
+        .device_repository
+        .fetch_device(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::NOT_FOUND, format!("no device with id {}", id)))
+}
+
+/// The overview of a single device. The runs of all devices are planned, as they share the
+/// power cap of the household.
+async fn overview(state: &AppState, id: i64) -> Result<DeviceOverview, (StatusCode, String)> {
+    overviews(state)
+        .await?
+        .into_iter()
+        .find(|overview| overview.device.id == id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no device with id {}", id)))
+}
+
+async fn overviews(state: &AppState) -> Result<Vec<DeviceOverview>, (StatusCode, String)> {
+    let planned = plan_all_devices(state, Utc::now())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(planned
+        .into_iter()
+        .map(|(device, schedules, next_run)| DeviceOverview {
+            device,
+            schedules,
+            next_run,
+        })
+        .collect())
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use axum::{extract::State, Json};
+use axum_macros::debug_handler;
+use chrono::{DateTime, DurationRound, FixedOffset, NaiveDate, TimeDelta};
+use chrono_tz::Tz;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::{
+    domain::{start_of_day, PricePoint},
+    optimizer::costs,
+    setup::AppState,
+};
+
+/// The series a panel can query
+const TARGETS: [&str; 3] = ["price", "consumer_price", "average_price"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct Range {
+    from: DateTime<FixedOffset>,
+    to: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct Target {
+    /// Absent while a panel has no series selected
+    target: Option<String>,
+    /// The additional JSON of the target, of which the `area` is the bidding zone of the prices,
+    /// e.g. `{"area": "NL"}`, every area when it is absent
+    payload: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct QueryRequest {
+    range: Range,
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct TimeSeries {
+    target: String,
+    /// Pairs of a value and a moment in milliseconds since the epoch
+    datapoints: Vec<(f64, i64)>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct AnnotationQuery {
+    /// The id of the device to show the windows of, every device when empty
+    query: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct AnnotationRequest {
+    range: Range,
+    annotation: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Annotation {
+    /// The annotation of the request, which older versions of the datasource expect back
+    annotation: Value,
+    /// The start and end in milliseconds since the epoch
+    time: i64,
+    #[serde(rename = "timeEnd")]
+    time_end: i64,
+    title: String,
+    text: String,
+    tags: Vec<String>,
+}
+
+/// Let Grafana test the datasource
+#[debug_handler(state = AppState)]
+#[instrument]
+pub(super) async fn get_grafana() -> StatusCode {
+    StatusCode::OK
+}
+
+/// The series a panel can query
+#[debug_handler(state = AppState)]
+#[instrument]
+pub(super) async fn post_search() -> (StatusCode, Json<[&'static str; 3]>) {
+    (StatusCode::OK, Json(TARGETS))
+}
+
+/// The prices within the range of a panel as time series: the market `price`, the
+/// `consumer_price` a consumer pays and the `average_price` of its day
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn post_query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> axum::response::Result<(StatusCode, Json<Vec<TimeSeries>>)> {
+    let targets = request
+        .targets
+        .into_iter()
+        .filter_map(|target| {
+            let area = target
+                .payload
+                .as_ref()
+                .and_then(|payload| payload.get("area"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+
+            Some((target.target.filter(|target| !target.is_empty())?, area))
+        })
+        .collect::<Vec<(String, Option<String>)>>();
+
+    if let Some((target, _)) = targets
+        .iter()
+        .find(|(target, _)| !TARGETS.contains(&target.as_str()))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "unknown target \"{}\", expected one of {}",
+                target,
+                TARGETS.join(", ")
+            ),
+        )
+            .into());
+    }
+
+    // the price of the hour that the range starts in counts
+    let from = request
+        .range
+        .from
+        .to_utc()
+        .duration_trunc(TimeDelta::hours(1))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let to = request.range.to.to_utc();
+
+    // averages are of whole days, so the days the range starts and ends in are fetched whole
+    let mut prices_by_area = HashMap::<Option<String>, Vec<PricePoint>>::new();
+
+    for (_, area) in &targets {
+        if prices_by_area.contains_key(area) {
+            continue;
+        }
+
+        let prices = state
+            .price_repository
+            .fetch_prices(
+                start_of_day(
+                    &state.timezone,
+                    from.with_timezone(&state.timezone).date_naive(),
+                ),
+                start_of_day(
+                    &state.timezone,
+                    to.with_timezone(&state.timezone).date_naive() + TimeDelta::days(1),
+                ),
+                area.as_deref(),
+            )
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        prices_by_area.insert(area.clone(), prices);
+    }
+
+    let series = targets
+        .into_iter()
+        .map(|(target, area)| TimeSeries {
+            datapoints: datapoints(&target, &prices_by_area[&area], state.timezone)
+                .into_iter()
+                .filter(|(_, moment)| {
+                    from.timestamp_millis() <= *moment && *moment < to.timestamp_millis()
+                })
+                .collect(),
+            target,
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(series)))
+}
+
+/// The planned windows of devices within the range of a dashboard as regions, optionally of
+/// the device of which the id is the query of the annotation
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn post_annotations(
+    State(state): State<AppState>,
+    Json(request): Json<AnnotationRequest>,
+) -> axum::response::Result<(StatusCode, Json<Vec<Annotation>>)> {
+    let query = serde_json::from_value::<AnnotationQuery>(request.annotation.clone())
+        .ok()
+        .and_then(|annotation| annotation.query)
+        .filter(|query| !query.trim().is_empty());
+
+    let device_id = query
+        .map(|query| {
+            query.trim().parse::<i64>().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("the query \"{}\" is not the id of a device", query),
+                )
+            })
+        })
+        .transpose()?;
+
+    let devices = state
+        .device_repository
+        .fetch_devices()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_iter()
+        .map(|device| (device.id, device.name))
+        .collect::<HashMap<i64, String>>();
+
+    let windows = state
+        .planned_window_repository
+        .fetch_planned_windows(
+            device_id,
+            request.range.from.to_utc(),
+            request.range.to.to_utc(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let annotations = windows
+        .into_iter()
+        .filter_map(|window| {
+            let name = devices.get(&window.device_id?)?;
+
+            Some(Annotation {
+                annotation: request.annotation.clone(),
+                time: window.starts_at.timestamp_millis(),
+                time_end: window.ends_at.timestamp_millis(),
+                title: name.clone(),
+                text: format!(
+                    "{} window at {} {}/kWh",
+                    name, window.average_price, window.currency
+                ),
+                tags: vec![name.clone(), window.source],
+            })
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(annotations)))
+}
+
+/// The values of a target for every price
+fn datapoints(target: &str, prices: &[PricePoint], timezone: Tz) -> Vec<(f64, i64)> {
+    let costs = costs(prices, None);
+
+    let values = match target {
+        "price" => prices.iter().map(|price| price.monetary_amount).collect(),
+        "consumer_price" => costs,
+        _ => {
+            let date = |price: &PricePoint| price.moment.with_timezone(&timezone).date_naive();
+            let mut days = HashMap::<NaiveDate, (f64, usize)>::new();
+
+            for (price, cost) in prices.iter().zip(&costs) {
+                let (sum, count) = days.entry(date(price)).or_default();
+                *sum += cost;
+                *count += 1;
+            }
+
+            prices
+                .iter()
+                .map(|price| {
+                    let (sum, count) = days[&date(price)];
+                    sum / count as f64
+                })
+                .collect::<Vec<f64>>()
+        }
+    };
+
+    values
+        .into_iter()
+        .zip(prices)
+        .map(|(value, price)| (value, price.moment.timestamp_millis()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::price_point;
+
+    #[test]
+    fn test_datapoints() {
+        let start = DateTime::parse_from_rfc3339("2024-06-30T22:00:00+02:00")
+            .unwrap()
+            .to_utc();
+        let prices = [(0.10, Some(0.25)), (0.30, Some(0.75)), (0.50, Some(0.60))]
+            .iter()
+            .enumerate()
+            .map(|(hour, (amount, consumer_amount))| PricePoint {
+                consumer_amount: *consumer_amount,
+                ..price_point(start + TimeDelta::hours(hour as i64), *amount)
+            })
+            .collect::<Vec<PricePoint>>();
+        let moments = prices
+            .iter()
+            .map(|price| price.moment.timestamp_millis())
+            .collect::<Vec<i64>>();
+
+        assert_eq!(
+            datapoints("price", &prices, Tz::Europe__Amsterdam),
+            [(0.10, moments[0]), (0.30, moments[1]), (0.50, moments[2])]
+        );
+        assert_eq!(
+            datapoints("consumer_price", &prices, Tz::Europe__Amsterdam)[2],
+            (0.60, moments[2])
+        );
+        // 22:00 and 23:00 fall on the 30th, midnight on the 1st
+        assert_eq!(
+            datapoints("average_price", &prices, Tz::Europe__Amsterdam),
+            [(0.50, moments[0]), (0.50, moments[1]), (0.60, moments[2])]
+        );
+    }
+}
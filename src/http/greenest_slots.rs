@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{parse_durations, with_consumer_pricing};
+use crate::{
+    carbon_intensity::{blended_costs, with_carbon_intensity},
+    domain::{round, PriceWindow},
+    optimizer::{costs, Contiguous, Strategy},
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct GreenestSlotsParameters {
+    durations: String,
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+    /// How much the carbon intensity weighs against the price, from 0 for only the price to 1
+    /// for only the carbon intensity, which is the default
+    carbon_weight: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// The window with the lowest carbon intensity for a duration, with what electricity costs in it
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct GreenestWindow {
+    #[serde(flatten)]
+    window: PriceWindow,
+    /// Grams of CO2 equivalent per kWh
+    average_carbon_intensity: f64,
+    zone: String,
+    /// How the window compares to the cheapest and the greenest one, when a carbon weight is
+    /// given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tradeoff: Option<Tradeoff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Tradeoff {
+    carbon_weight: f64,
+    cheapest: Option<WindowSummary>,
+    greenest: Option<WindowSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct WindowSummary {
+    starts_at: DateTime<FixedOffset>,
+    /// The price a consumer pays, or the market price without a price formula or tariff
+    average_price: f64,
+    average_carbon_intensity: f64,
+}
+
+/// Fetch the timeslots between a start and end moment with the lowest average carbon intensity
+/// for the given durations, or with the lowest blend of price and carbon intensity when a carbon
+/// weight is given. Only hours of which both the price and the carbon intensity are known are
+/// considered.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_greenest_slots(
+    State(state): State<AppState>,
+    parameters: Query<GreenestSlotsParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<GreenestWindow>>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let Some(provider) = &state.carbon_intensity_provider else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "the carbon intensity is not fetched, set CARBON_INTENSITY_PROVIDER_DSN".to_string(),
+        )
+            .into());
+    };
+
+    if parameters
+        .carbon_weight
+        .is_some_and(|carbon_weight| !(0.0..=1.0).contains(&carbon_weight))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "carbon_weight must be between 0 and 1".to_string(),
+        )
+            .into());
+    }
+
+    let start = parameters.moment_start.to_utc();
+    let end = parameters.moment_end.to_utc();
+
+    let prices = state
+        .price_repository
+        .fetch_prices(start, end, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let intensities = state
+        .carbon_intensity_repository
+        .fetch_carbon_intensity(provider.zone(), start, end)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let (prices, intensities) = with_carbon_intensity(prices, &intensities);
+    let costs = costs(&prices, None);
+
+    let objective = match parameters.carbon_weight {
+        Some(carbon_weight) => blended_costs(&costs, &intensities, carbon_weight),
+        None => intensities.clone(),
+    };
+
+    let timezone = parameters.moment_start.timezone();
+    let average = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+
+    let summary = |range: Range<usize>| WindowSummary {
+        starts_at: prices[range.start].moment.with_timezone(&timezone),
+        average_price: round(average(&costs[range.clone()]), 3),
+        average_carbon_intensity: round(average(&intensities[range]), 1),
+    };
+
+    let (windows, details): (Vec<PriceWindow>, Vec<(f64, Option<Tradeoff>)>) =
+        parse_durations(&parameters.durations)
+            .iter()
+            .filter_map(|duration| {
+                let strategy = Contiguous {
+                    duration: (*duration).max(1) as usize,
+                };
+
+                let select = |costs: &[f64]| {
+                    strategy
+                        .select(&prices, costs)
+                        .and_then(|ranges| ranges.into_iter().next())
+                };
+
+                let range = select(&objective)?;
+                let window = PriceWindow::from_prices(&prices[range.clone()], None)?;
+
+                let tradeoff = parameters.carbon_weight.map(|carbon_weight| Tradeoff {
+                    carbon_weight,
+                    cheapest: select(&costs).map(summary),
+                    greenest: select(&intensities).map(summary),
+                });
+
+                Some((window, (average(&intensities[range]), tradeoff)))
+            })
+            .unzip();
+
+    let windows = with_consumer_pricing(&state, windows, false)
+        .into_iter()
+        .zip(details)
+        .map(|(window, (average, tradeoff))| GreenestWindow {
+            window: window.with_timezone(timezone),
+            average_carbon_intensity: round(average, 1),
+            zone: provider.zone().to_string(),
+            tradeoff,
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(windows)))
+}
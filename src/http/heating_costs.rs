@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    degree_days::{heating_day, DEFAULT_BASE_TEMPERATURE},
+    domain::{round, start_of_day},
+    optimizer::costs,
+    setup::AppState,
+};
+
+/// The longest range of days that can be analyzed in a single request
+const MAX_DAYS: u64 = 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct HeatingCostParameters {
+    /// The first day to analyze
+    from: NaiveDate,
+    /// The last day to analyze, defaults to `from`
+    to: Option<NaiveDate>,
+    /// The electricity heating takes per degree day
+    kwh_per_degree_day: f64,
+    /// The outdoor temperature below which is heated, 18 °C by default
+    base_temperature: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct HeatingCosts {
+    base_temperature: f64,
+    kwh_per_degree_day: f64,
+    currency: Option<String>,
+    days: Vec<HeatingCostDay>,
+    degree_days: f64,
+    estimated_kwh: f64,
+    estimated_cost: f64,
+    /// The cost of heating per degree day over the whole range, which only depends on the
+    /// prices
+    cost_per_degree_day: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct HeatingCostDay {
+    date: NaiveDate,
+    average_temperature: f64,
+    degree_days: f64,
+    /// The price a consumer pays, weighted by how much colder than the base temperature every
+    /// hour is
+    average_price: f64,
+    estimated_kwh: f64,
+    estimated_cost: f64,
+    /// What heating would have cost per degree day at the prices of the day, also on days
+    /// without degree days
+    cost_per_degree_day: f64,
+}
+
+/// Estimate the cost of heating on every day between two dates from the stored temperatures
+/// and prices, in heating degree days and in the cost per degree day. Where the degree days
+/// follow the weather, the cost per degree day follows the prices. Days are interpreted in the
+/// configured timezone, and only hours of which both the temperature and the price are stored
+/// are counted.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_heating_costs(
+    State(state): State<AppState>,
+    parameters: Query<HeatingCostParameters>,
+) -> axum::response::Result<(StatusCode, Json<HeatingCosts>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let to = parameters.to.unwrap_or(parameters.from);
+
+    let days = (to - parameters.from).num_days();
+
+    if days < 0 || days as u64 >= MAX_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_DAYS),
+        )
+            .into());
+    }
+
+    if parameters.kwh_per_degree_day <= 0.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "kwh_per_degree_day must be positive".to_string(),
+        )
+            .into());
+    }
+
+    let base_temperature = parameters
+        .base_temperature
+        .unwrap_or(DEFAULT_BASE_TEMPERATURE);
+
+    let start = start_of_day(&state.timezone, parameters.from);
+    let end = start_of_day(&state.timezone, to + Days::new(1));
+
+    let prices = state
+        .price_repository
+        .fetch_prices(start, end, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let weather = state
+        .weather_repository
+        .fetch_weather(start, end)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let temperatures = weather
+        .iter()
+        .map(|hour| (hour.moment, hour.temperature_celsius))
+        .collect::<HashMap<DateTime<Utc>, f64>>();
+
+    let mut hours_by_date = HashMap::<NaiveDate, Vec<(f64, f64)>>::new();
+
+    for (price, cost) in prices.iter().zip(costs(&prices, None)) {
+        if let Some(temperature) = temperatures.get(&price.moment) {
+            hours_by_date
+                .entry(price.moment.with_timezone(&state.timezone).date_naive())
+                .or_default()
+                .push((*temperature, cost));
+        }
+    }
+
+    let heating_days = parameters
+        .from
+        .iter_days()
+        .take(days as usize + 1)
+        .filter_map(|date| {
+            heating_day(
+                date,
+                hours_by_date.get(&date).map(Vec::as_slice).unwrap_or(&[]),
+                base_temperature,
+            )
+        })
+        .map(|day| {
+            let estimated_kwh = day.degree_days * parameters.kwh_per_degree_day;
+            let estimated_cost = estimated_kwh * day.average_price;
+
+            (day, estimated_kwh, estimated_cost)
+        })
+        .collect::<Vec<_>>();
+
+    let degree_days = heating_days.iter().map(|(day, _, _)| day.degree_days).sum();
+    let estimated_cost = heating_days.iter().map(|(_, _, cost)| cost).sum();
+
+    let days = heating_days
+        .into_iter()
+        .map(|(day, estimated_kwh, estimated_cost)| HeatingCostDay {
+            date: day.date,
+            average_temperature: round(day.average_temperature, 1),
+            degree_days: round(day.degree_days, 1),
+            average_price: round(day.average_price, 3),
+            estimated_kwh: round(estimated_kwh, 1),
+            estimated_cost: round(estimated_cost, 2),
+            cost_per_degree_day: round(parameters.kwh_per_degree_day * day.average_price, 3),
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(HeatingCosts {
+            base_temperature,
+            kwh_per_degree_day: parameters.kwh_per_degree_day,
+            currency: prices.first().map(|price| price.currency.clone()),
+            degree_days: round(degree_days, 1),
+            estimated_kwh: round(degree_days * parameters.kwh_per_degree_day, 1),
+            estimated_cost: round(estimated_cost, 2),
+            cost_per_degree_day: Some(degree_days)
+                .filter(|degree_days| round(*degree_days, 1) > 0.0)
+                .map(|degree_days| round(estimated_cost / degree_days, 3)),
+            days,
+        }),
+    ))
+}
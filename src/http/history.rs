@@ -0,0 +1,115 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{Days, NaiveDate};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{parse_durations, with_consumer_pricing};
+use crate::{
+    domain::{start_of_day, PricePoint, PriceWindow},
+    optimizer::{optimize, Contiguous},
+    setup::AppState,
+};
+
+/// The longest range of days that can be replayed in a single request
+const MAX_DAYS: u64 = 62;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct HistoricalTimeslotParameters {
+    durations: String,
+    /// The first day to compute windows for
+    from: NaiveDate,
+    /// The last day to compute windows for, defaults to `from`
+    to: Option<NaiveDate>,
+    /// Whether to include the components of the consumer price
+    breakdown: Option<bool>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct HistoricalTimeslots {
+    date: NaiveDate,
+    windows: Vec<PriceWindow>,
+}
+
+/// Compute the windows that would have been recommended on every day between two past
+/// dates, using the stored prices only. Days are interpreted in the configured timezone.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_historical_time_slots(
+    State(state): State<AppState>,
+    parameters: Query<HistoricalTimeslotParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<HistoricalTimeslots>>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let to = parameters.to.unwrap_or(parameters.from);
+
+    let days = (to - parameters.from).num_days();
+
+    if days < 0 || days as u64 >= MAX_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_DAYS),
+        )
+            .into());
+    }
+
+    let durations = parse_durations(&parameters.durations);
+
+    let mut history = Vec::with_capacity(days as usize + 1);
+
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, parameters.from),
+            start_of_day(&state.timezone, to + Days::new(1)),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .map(|cap| cap.rate);
+
+    for date in parameters.from.iter_days().take(days as usize + 1) {
+        let day_start = start_of_day(&state.timezone, date);
+        let day_end = start_of_day(&state.timezone, date + Days::new(1));
+
+        let prices_of_day = prices
+            .iter()
+            .filter(|price| price.moment >= day_start && price.moment < day_end)
+            .cloned()
+            .collect::<Vec<PricePoint>>();
+
+        let windows = durations
+            .iter()
+            .filter_map(|duration| {
+                optimize(
+                    &Contiguous {
+                        duration: (*duration).max(1) as usize,
+                    },
+                    &prices_of_day,
+                    price_cap,
+                )
+            })
+            .flatten()
+            .map(|window| window.with_timezone(state.timezone))
+            .collect();
+
+        history.push(HistoricalTimeslots {
+            date,
+            windows: with_consumer_pricing(&state, windows, parameters.breakdown.unwrap_or(false)),
+        });
+    }
+
+    Ok((StatusCode::OK, Json(history)))
+}
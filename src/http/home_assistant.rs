@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use chrono_tz::Tz;
+use reqwest::StatusCode;
+use serde::Serialize;
+use tracing::instrument;
+
+use super::AreaParameters;
+use crate::{
+    domain::{start_of_day, PricePoint},
+    optimizer::costs,
+    price_level::{current_price, PriceLevel},
+    setup::AppState,
+};
+
+/// The current price as the state of a Home Assistant REST sensor, with the prices of today and
+/// tomorrow as attributes in the shape of the Nordpool integration, which price cards such as
+/// the ApexCharts card are set up for
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct HomeAssistantSensor {
+    state: f64,
+    unit_of_measurement: String,
+    currency: String,
+    level: PriceLevel,
+    average: f64,
+    min: f64,
+    max: f64,
+    today: Vec<f64>,
+    tomorrow: Vec<f64>,
+    /// Whether the prices of tomorrow are known, usually from the early afternoon
+    tomorrow_valid: bool,
+    raw_today: Vec<RawPrice>,
+    raw_tomorrow: Vec<RawPrice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RawPrice {
+    start: DateTime<FixedOffset>,
+    end: DateTime<FixedOffset>,
+    value: f64,
+}
+
+/// The current price with the prices of today and tomorrow, for a single REST sensor in Home
+/// Assistant
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_home_assistant(
+    State(state): State<AppState>,
+    parameters: Query<AreaParameters>,
+) -> axum::response::Result<(StatusCode, Json<HomeAssistantSensor>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let now = Utc::now();
+
+    let current = current_price(&state, now, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "the price of the current hour is not known".to_string(),
+        ))?;
+
+    let today = now.with_timezone(&state.timezone).date_naive();
+    let start_of_tomorrow = start_of_day(&state.timezone, today + TimeDelta::days(1));
+
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, today),
+            start_of_day(&state.timezone, today + TimeDelta::days(2)),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let (prices_of_today, prices_of_tomorrow): (Vec<PricePoint>, Vec<PricePoint>) = prices
+        .into_iter()
+        .partition(|price| price.moment < start_of_tomorrow);
+
+    let raw_today = raw_prices(&prices_of_today, &state.timezone);
+    let raw_tomorrow = raw_prices(&prices_of_tomorrow, &state.timezone);
+    let today = raw_today
+        .iter()
+        .map(|price| price.value)
+        .collect::<Vec<f64>>();
+    let tomorrow = raw_tomorrow
+        .iter()
+        .map(|price| price.value)
+        .collect::<Vec<f64>>();
+
+    Ok((
+        StatusCode::OK,
+        Json(HomeAssistantSensor {
+            state: current.price,
+            unit_of_measurement: format!("{}/kWh", current.currency),
+            currency: current.currency,
+            level: current.level,
+            average: current.average_price,
+            min: today.iter().copied().fold(f64::INFINITY, f64::min),
+            max: today.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            tomorrow_valid: !tomorrow.is_empty(),
+            today,
+            tomorrow,
+            raw_today,
+            raw_tomorrow,
+        }),
+    ))
+}
+
+/// The hours of prices in local time with the price a consumer pays
+fn raw_prices(prices: &[PricePoint], timezone: &Tz) -> Vec<RawPrice> {
+    prices
+        .iter()
+        .zip(costs(prices, None))
+        .map(|(price, value)| RawPrice {
+            start: price.moment.with_timezone(timezone).fixed_offset(),
+            // in the offset of the end itself, in case daylight saving time changes
+            end: (price.moment + TimeDelta::hours(1))
+                .with_timezone(timezone)
+                .fixed_offset(),
+            value,
+        })
+        .collect()
+}
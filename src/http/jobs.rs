@@ -0,0 +1,16 @@
+use axum::{extract::State, Json};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use tracing::instrument;
+
+use crate::{scheduler::JobStatus, setup::AppState};
+
+/// List the background jobs with their last and next run, so the health of price fetching can
+/// be checked without reading logs
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_jobs(
+    State(state): State<AppState>,
+) -> axum::response::Result<(StatusCode, Json<Vec<JobStatus>>)> {
+    Ok((StatusCode::OK, Json(state.jobs.statuses())))
+}
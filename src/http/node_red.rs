@@ -0,0 +1,136 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, DurationRound, FixedOffset, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    optimizer::{optimize, Contiguous},
+    price_level::{current_price, PriceLevel},
+    setup::AppState,
+};
+
+/// How far ahead to look for the next window
+const UPCOMING_DAYS: i64 = 2;
+
+/// The duration of the window in hours, when none is given
+const DEFAULT_DURATION: i32 = 1;
+
+/// The longest window that can be looked for, in hours
+const MAX_DURATION: i32 = 24;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct NodeRedParameters {
+    /// The duration of the window in hours
+    duration: Option<i32>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// The current price and the next window as a flat object of numbers, booleans and moments
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct NodeRedState {
+    /// The start of the current hour
+    moment: DateTime<FixedOffset>,
+    price: f64,
+    average_price: f64,
+    currency: String,
+    level: PriceLevel,
+    window_duration: i32,
+    /// The window fields are null when no window of the duration fits in the known prices
+    window_starts_at: Option<DateTime<FixedOffset>>,
+    window_ends_at: Option<DateTime<FixedOffset>>,
+    window_average_price: Option<f64>,
+    window_active: bool,
+    /// 0 once the window has started
+    minutes_until_window: Option<i64>,
+}
+
+/// The current price with its level and the cheapest upcoming window of a duration, in a flat
+/// object that a Node-RED flow can use without parsing nested arrays or string prices
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_node_red(
+    State(state): State<AppState>,
+    parameters: Query<NodeRedParameters>,
+) -> axum::response::Result<(StatusCode, Json<NodeRedState>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let duration = parameters.duration.unwrap_or(DEFAULT_DURATION);
+
+    if !(1..=MAX_DURATION).contains(&duration) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("duration must be between 1 and {} hours", MAX_DURATION),
+        )
+            .into());
+    }
+
+    let now = Utc::now();
+
+    let current = current_price(&state, now, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "the price of the current hour is not known".to_string(),
+        ))?;
+
+    let current_hour = now.duration_trunc(TimeDelta::hours(1)).unwrap_or(now);
+
+    let upcoming_prices = state
+        .price_repository
+        .fetch_prices(
+            current_hour,
+            current_hour + TimeDelta::days(UPCOMING_DAYS),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(None));
+
+    let window = optimize(
+        &Contiguous {
+            duration: duration as usize,
+        },
+        &upcoming_prices,
+        price_cap,
+    )
+    .and_then(|windows| windows.into_iter().next())
+    .map(|window| window.with_timezone(state.timezone));
+
+    let window_active = window
+        .as_ref()
+        .is_some_and(|window| window.starts_at <= now && now < window.ends_at);
+
+    Ok((
+        StatusCode::OK,
+        Json(NodeRedState {
+            moment: current.moment.with_timezone(&state.timezone).fixed_offset(),
+            price: current.price,
+            average_price: current.average_price,
+            currency: current.currency,
+            level: current.level,
+            window_duration: duration,
+            window_starts_at: window.as_ref().map(|window| window.starts_at),
+            window_ends_at: window.as_ref().map(|window| window.ends_at),
+            window_average_price: window
+                .as_ref()
+                .and_then(|window| window.effective_average_price()),
+            window_active,
+            minutes_until_window: window
+                .as_ref()
+                .map(|window| (window.starts_at.to_utc() - now).num_minutes().max(0)),
+        }),
+    ))
+}
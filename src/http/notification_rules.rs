@@ -0,0 +1,306 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    cron::CronSchedule,
+    notification::{
+        NotificationChannel, NotificationEvent, NotificationRule, DEFAULT_DIGEST_DURATIONS,
+    },
+    notification_repository::NewNotificationRule,
+    price_level::PriceLevel,
+    setup::AppState,
+    template::Template,
+};
+
+/// How far ahead a notification may be sent, a day in minutes
+const MAX_LEAD_MINUTES: i32 = 24 * 60;
+
+/// The longest cheapest window to notify about, in hours
+const MAX_WINDOW_DURATION: i32 = 24;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct NotificationRuleRequest {
+    event: NotificationEvent,
+    /// Where to send the notifications, the log by default
+    channel: Option<NotificationChannel>,
+    /// The device a window start rule applies to, every device when absent
+    device_id: Option<i64>,
+    /// How many minutes before the window starts to notify, 15 by default
+    lead_minutes: Option<i32>,
+    /// The local hour to send the daily summary at, 7 by default
+    hour: Option<i32>,
+    /// The price above which a spike is notified, `PRICE_ALERT_THRESHOLD` when absent, below
+    /// which a low price is notified, or the monthly budget
+    threshold: Option<f64>,
+    /// The template of the notifications, with the fields of the event as placeholders
+    template: Option<String>,
+    /// The price level a level change is notified into, every level when absent
+    level: Option<PriceLevel>,
+    /// The number of hours of the cheapest window, 3 by default
+    duration: Option<i32>,
+    /// The numbers of hours of the cheapest windows in a digest, 1, 2 and 3 by default
+    durations: Option<Vec<i32>>,
+    /// A cron schedule of the minutes the rule may notify in, always when absent
+    schedule: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct NotificationRuleUpdate {
+    enabled: bool,
+}
+
+/// List the notification rules, which hold the webhook URLs and recipients they notify.
+/// Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_notification_rules(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> axum::response::Result<(StatusCode, Json<Vec<NotificationRule>>)> {
+    require_admin(&state, &headers)?;
+
+    let rules = state
+        .notification_repository
+        .fetch_rules()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(rules)))
+}
+
+/// Add a notification rule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_notification_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<NotificationRuleRequest>,
+) -> axum::response::Result<(StatusCode, Json<NotificationRule>)> {
+    require_admin(&state, &headers)?;
+
+    let channel = request.channel.unwrap_or(NotificationChannel::Log);
+
+    if !channel.is_configured(&state) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the {} channel is not configured", channel.as_str()),
+        )
+            .into());
+    }
+
+    let bad_request = |message: &str| (StatusCode::BAD_REQUEST, message.to_string());
+
+    let is_window_start = request.event == NotificationEvent::WindowStart;
+
+    if !is_window_start && (request.device_id.is_some() || request.lead_minutes.is_some()) {
+        return Err(bad_request("device_id and lead_minutes only apply to window_start").into());
+    }
+
+    if request.event != NotificationEvent::DailySummary && request.hour.is_some() {
+        return Err(bad_request("hour only applies to daily_summary").into());
+    }
+
+    let has_threshold = matches!(
+        request.event,
+        NotificationEvent::PriceSpike
+            | NotificationEvent::PriceBelow
+            | NotificationEvent::BudgetExceeded
+    );
+
+    if !has_threshold && request.threshold.is_some() {
+        return Err(bad_request(
+            "threshold only applies to price_spike, price_below and budget_exceeded",
+        )
+        .into());
+    }
+
+    if request.event != NotificationEvent::LevelChange && request.level.is_some() {
+        return Err(bad_request("level only applies to level_change").into());
+    }
+
+    let duration = match request.event {
+        NotificationEvent::CheapestWindowChange => Some(request.duration.unwrap_or(3)),
+        _ if request.duration.is_some() => {
+            return Err(bad_request("duration only applies to cheapest_window_change").into())
+        }
+        _ => None,
+    };
+
+    let durations = match request.event {
+        NotificationEvent::TomorrowDigest => Some(
+            request
+                .durations
+                .unwrap_or(DEFAULT_DIGEST_DURATIONS.to_vec()),
+        ),
+        _ if request.durations.is_some() => {
+            return Err(bad_request("durations only applies to tomorrow_digest").into())
+        }
+        _ => None,
+    };
+
+    if durations
+        .as_ref()
+        .is_some_and(|durations| durations.is_empty())
+    {
+        return Err(bad_request("durations must not be empty").into());
+    }
+
+    if duration
+        .iter()
+        .chain(durations.iter().flatten())
+        .any(|duration| !(1..=MAX_WINDOW_DURATION).contains(duration))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "durations must be between 1 and {} hours",
+                MAX_WINDOW_DURATION
+            ),
+        )
+            .into());
+    }
+
+    let lead_minutes = match is_window_start {
+        true => request.lead_minutes.unwrap_or(15),
+        false => 0,
+    };
+
+    // reminders are sent while a window is upcoming, so they need at least a minute
+    if is_window_start && !(1..=MAX_LEAD_MINUTES).contains(&lead_minutes) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("lead_minutes must be between 1 and {}", MAX_LEAD_MINUTES),
+        )
+            .into());
+    }
+
+    let hour = match request.event {
+        NotificationEvent::DailySummary => Some(request.hour.unwrap_or(7)),
+        _ => None,
+    };
+
+    if hour.is_some_and(|hour| !(0..24).contains(&hour)) {
+        return Err(bad_request("hour must be between 0 and 23").into());
+    }
+
+    if request.event == NotificationEvent::PriceSpike
+        && request.threshold.is_none()
+        && state.settings().pricing.price_alert_threshold.is_none()
+    {
+        return Err(bad_request(
+            "a price_spike rule needs a threshold when PRICE_ALERT_THRESHOLD is not configured",
+        )
+        .into());
+    }
+
+    if request.event == NotificationEvent::PriceBelow && request.threshold.is_none() {
+        return Err(bad_request("a price_below rule needs a threshold").into());
+    }
+
+    if request.event == NotificationEvent::BudgetExceeded
+        && !request.threshold.is_some_and(|budget| budget > 0.0)
+    {
+        return Err(bad_request("a budget_exceeded rule needs a positive threshold").into());
+    }
+
+    if let Some(schedule) = &request.schedule {
+        CronSchedule::parse(schedule, state.timezone)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    if let Some(template) = &request.template {
+        Template::parse(template)
+            .and_then(|template| template.check_fields(request.event.fields()))
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    if let Some(device_id) = request.device_id {
+        state
+            .device_repository
+            .fetch_device(device_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .ok_or((
+                StatusCode::BAD_REQUEST,
+                format!("no device with id {}", device_id),
+            ))?;
+    }
+
+    let rule = state
+        .notification_repository
+        .persist_rule(&NewNotificationRule {
+            event: request.event,
+            device_id: request.device_id,
+            lead_minutes,
+            channel,
+            hour,
+            threshold: request.threshold,
+            template: request.template,
+            level: request.level,
+            duration,
+            durations,
+            schedule: request.schedule,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Enable or disable a notification rule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn patch_notification_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(update): Json<NotificationRuleUpdate>,
+) -> axum::response::Result<(StatusCode, Json<NotificationRule>)> {
+    require_admin(&state, &headers)?;
+
+    let rule = state
+        .notification_repository
+        .set_rule_enabled(id, update.enabled)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("no notification rule with id {}", id),
+        ))?;
+
+    Ok((StatusCode::OK, Json(rule)))
+}
+
+/// Delete a notification rule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_notification_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let deleted = state
+        .notification_repository
+        .delete_rule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((
+            StatusCode::NOT_FOUND,
+            format!("no notification rule with id {}", id),
+        )
+            .into()),
+    }
+}
@@ -0,0 +1,255 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::instrument;
+
+use super::with_consumer_pricing;
+use crate::{
+    domain::PricePoint,
+    domain::PriceWindow,
+    heat_pump::{coefficient_of_performance, DEFAULT_FLOW_TEMPERATURE},
+    optimizer::{costs, optimize_costs, Contiguous, Deadline, Split, Strategy, Weighted},
+    planner::{price_inputs, record_planned_windows},
+    self_consumption::{forecast_production, self_consumption_costs, DEFAULT_LOAD_KW},
+    setup::AppState,
+    weather::fetch_weather,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PlanParameters {
+    /// One of `contiguous` (default), `split`, `weighted` or `deadline`
+    strategy: Option<String>,
+    /// The number of hours to run, for every strategy except `weighted`
+    duration: Option<i32>,
+    /// Comma separated share of the consumption per hour of a run, for `weighted`
+    weights: Option<String>,
+    /// The moment the run has to be finished by
+    deadline: Option<DateTime<FixedOffset>>,
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+    /// The consumption in kWh so far in the current price cap period
+    consumed_kwh: Option<f64>,
+    /// Whether to include the components of the consumer price
+    breakdown: Option<bool>,
+    /// Whether the device is a heat pump, to optimize on the cost per kWh of heat
+    heat_pump: Option<bool>,
+    /// The temperature in °C of the water the heat pump heats, 35 by default
+    flow_temperature: Option<f64>,
+    /// The power the device draws, to know how much of it the solar panels cover
+    power_kw: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Plan {
+    strategy: &'static str,
+    windows: Vec<PriceWindow>,
+}
+
+/// Plan when to run a device between a start and end moment using one of the optimizer
+/// strategies. Strategies that allow interruptions result in multiple windows. When a solar
+/// forecast is configured, hours are optimized on the cost of what the solar panels do not
+/// cover.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_plan(
+    State(state): State<AppState>,
+    parameters: Query<PlanParameters>,
+) -> axum::response::Result<(StatusCode, Json<Plan>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let strategy = resolve_strategy(&parameters).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if parameters.power_kw.is_some_and(|power_kw| power_kw <= 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "power_kw must be positive".to_string(),
+        )
+            .into());
+    }
+
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            parameters.moment_start.to_utc(),
+            parameters.moment_end.to_utc(),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(parameters.consumed_kwh));
+
+    let mut costs = costs(&prices, price_cap);
+
+    let coefficients = match parameters.heat_pump {
+        Some(true) => Some(coefficients_of_performance(&state, &prices, &parameters).await?),
+        _ => None,
+    };
+
+    if let Some(coefficients) = &coefficients {
+        for (cost, coefficient) in costs.iter_mut().zip(coefficients) {
+            *cost /= coefficient;
+        }
+    }
+
+    let production = forecast_production(
+        &state,
+        parameters.moment_start.to_utc(),
+        parameters.moment_end.to_utc(),
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let costs = self_consumption_costs(
+        &prices,
+        &costs,
+        &production,
+        parameters.power_kw.unwrap_or(DEFAULT_LOAD_KW),
+    );
+
+    let windows = optimize_costs(&*strategy, &prices, &costs, price_cap)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "no plan fits within the known prices".to_string(),
+        ))?
+        .into_iter()
+        .map(|window| window.with_timezone(parameters.moment_start.timezone()))
+        .collect::<Vec<PriceWindow>>();
+
+    let inputs = json!({
+        "strategy": strategy.name(),
+        "duration": parameters.duration,
+        "weights": parameters.weights,
+        "deadline": parameters.deadline,
+        "moment_start": parameters.moment_start,
+        "moment_end": parameters.moment_end,
+        "price_cap": price_cap,
+        "coefficients_of_performance": coefficients,
+        "power_kw": parameters.power_kw,
+        "prices": price_inputs(&prices),
+        "solar_forecast": production,
+    });
+
+    record_planned_windows(&state, "plan", None, &windows, &inputs).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(Plan {
+            strategy: strategy.name(),
+            windows: with_consumer_pricing(&state, windows, parameters.breakdown.unwrap_or(false)),
+        }),
+    ))
+}
+
+/// The expected coefficient of performance of a heat pump in the hour of every price, from the
+/// stored forecast outdoor temperature, or one that is fetched when none of the period is
+/// stored. Hours without a forecast get the average of the others.
+async fn coefficients_of_performance(
+    state: &AppState,
+    prices: &[PricePoint],
+    parameters: &PlanParameters,
+) -> Result<Vec<f64>, (StatusCode, String)> {
+    let location = state.weather_location.as_ref().ok_or((
+        StatusCode::BAD_REQUEST,
+        "heat pumps require WEATHER_LATITUDE and WEATHER_LONGITUDE".to_string(),
+    ))?;
+
+    let flow_temperature = parameters
+        .flow_temperature
+        .unwrap_or(DEFAULT_FLOW_TEMPERATURE);
+
+    let start = parameters.moment_start.to_utc();
+    let end = parameters.moment_end.to_utc();
+
+    let mut weather = state
+        .weather_repository
+        .fetch_weather(start, end)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if weather.is_empty() {
+        weather = fetch_weather(location, None, start, end)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    }
+
+    let coefficients = prices
+        .iter()
+        .map(|price| {
+            weather
+                .iter()
+                .find(|hour| hour.moment == price.moment)
+                .map(|hour| coefficient_of_performance(hour.temperature_celsius, flow_temperature))
+        })
+        .collect::<Vec<Option<f64>>>();
+
+    let known = coefficients.iter().flatten().collect::<Vec<&f64>>();
+
+    if known.is_empty() {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            "no temperature forecast of the period is available".to_string(),
+        ));
+    }
+
+    let average = known.iter().copied().sum::<f64>() / known.len() as f64;
+
+    Ok(coefficients
+        .into_iter()
+        .map(|coefficient| coefficient.unwrap_or(average))
+        .collect())
+}
+
+fn resolve_strategy(parameters: &PlanParameters) -> Result<Box<dyn Strategy>, String> {
+    let duration = || {
+        parameters
+            .duration
+            .filter(|duration| *duration > 0)
+            .map(|duration| duration as usize)
+            .ok_or("a positive duration is required".to_string())
+    };
+
+    let name = parameters.strategy.as_deref().unwrap_or("contiguous");
+
+    let strategy: Box<dyn Strategy> = match name {
+        "contiguous" | "deadline" => Box::new(Contiguous {
+            duration: duration()?,
+        }),
+        "split" => Box::new(Split {
+            duration: duration()?,
+        }),
+        "weighted" => Box::new(Weighted {
+            weights: parameters
+                .weights
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(|weight| weight.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| "weights must be a comma separated list of numbers".to_string())?,
+        }),
+        _ => return Err(format!("unknown strategy \"{}\"", name)),
+    };
+
+    match (name, parameters.deadline) {
+        (_, Some(deadline)) => Ok(Box::new(Deadline {
+            deadline: deadline.to_utc(),
+            strategy,
+        })),
+        ("deadline", None) => Err("the deadline strategy requires a deadline".to_string()),
+        _ => Ok(strategy),
+    }
+}
@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{planned_window_repository::PlannedWindow, setup::AppState};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PlannedWindowParameters {
+    /// Only the windows planned for this device
+    device_id: Option<i64>,
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+}
+
+/// The windows that were handed out and start between a start and end moment, with the inputs
+/// they were chosen from
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_planned_windows(
+    State(state): State<AppState>,
+    parameters: Query<PlannedWindowParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<PlannedWindow>>)> {
+    let windows = state
+        .planned_window_repository
+        .fetch_planned_windows(
+            parameters.device_id,
+            parameters.moment_start.to_utc(),
+            parameters.moment_end.to_utc(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(windows)))
+}
@@ -0,0 +1,143 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{price_repository::PriceVersion, setup::AppState};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct PriceCorrectionParameters {
+    /// The start of the hour to look back on
+    moment: DateTime<FixedOffset>,
+    /// The bidding zone of the prices, e.g. `NL`, of every area by default
+    area: Option<String>,
+}
+
+/// Every version of the prices of an hour that was ingested, which holds more than one version
+/// when the provider republished a corrected price
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_price_corrections(
+    State(state): State<AppState>,
+    parameters: Query<PriceCorrectionParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<PriceVersion>>)> {
+    let start_moment = parameters.moment.to_utc();
+
+    let versions = state
+        .price_repository
+        .fetch_price_versions(
+            start_moment,
+            start_moment + TimeDelta::hours(1),
+            parameters.area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(versions)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::PricePoint,
+        memory::MemoryPriceRepository,
+        price_repository::PriceRepository,
+        setup::test_app_state,
+        test_support::{local_moment, moment, price_point},
+    };
+
+    fn price(hour: &str, amount: f64, area: Option<&str>) -> PricePoint {
+        PricePoint {
+            area: area.map(str::to_string),
+            ..price_point(moment(hour), amount)
+        }
+    }
+
+    async fn corrected_state() -> AppState {
+        let price_repository = MemoryPriceRepository::new();
+
+        let published = [
+            price("2024-06-30T08:00:00Z", 0.2, Some("NL")),
+            price("2024-06-30T09:00:00Z", 0.3, Some("NL")),
+            price("2024-06-30T08:00:00Z", 0.4, Some("BE")),
+        ];
+        // the first hour of NL is corrected, the other prices are published again unchanged
+        let republished = [
+            price("2024-06-30T08:00:00Z", 0.25, Some("NL")),
+            published[1].clone(),
+            published[2].clone(),
+        ];
+
+        for prices in [&published, &republished] {
+            price_repository
+                .persist_prices(prices, "entsoe")
+                .await
+                .unwrap();
+        }
+
+        test_app_state(price_repository)
+    }
+
+    #[tokio::test]
+    async fn test_get_price_corrections() {
+        let state = corrected_state().await;
+
+        let (status, Json(versions)) = get_price_corrections(
+            State(state),
+            Query(PriceCorrectionParameters {
+                moment: local_moment("2024-06-30T10:00:00+02:00"),
+                area: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+
+        let versions = versions
+            .iter()
+            .map(|version| {
+                (
+                    version.price.area.as_deref(),
+                    version.version,
+                    version.price.monetary_amount,
+                )
+            })
+            .collect::<Vec<(Option<&str>, i32, f64)>>();
+
+        // an unchanged price keeps its single version, and only the requested hour is listed
+        assert_eq!(
+            versions,
+            vec![
+                (Some("BE"), 1, 0.4),
+                (Some("NL"), 1, 0.2),
+                (Some("NL"), 2, 0.25)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_price_corrections_of_area() {
+        let state = corrected_state().await;
+
+        let (_, Json(versions)) = get_price_corrections(
+            State(state),
+            Query(PriceCorrectionParameters {
+                moment: local_moment("2024-06-30T08:00:00Z"),
+                area: Some("BE".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].provider, "entsoe");
+        assert_eq!(versions[0].price.area.as_deref(), Some("BE"));
+    }
+}
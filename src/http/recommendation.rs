@@ -0,0 +1,104 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DurationRound, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{
+    optimizer::{optimize, Contiguous},
+    recommendation::Recommendation,
+    setup::AppState,
+};
+
+/// How far ahead to look for upcoming prices
+const UPCOMING_DAYS: i64 = 2;
+
+/// Starting now is recommended when it is at most this much more expensive, in percent
+const DEFAULT_TOLERANCE_PERCENTAGE: f64 = 5.0;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RecommendationParameters {
+    /// The duration of a run in hours
+    duration: i32,
+    /// How much more expensive, in percent, starting now may be than the best option
+    tolerance: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// Answer whether a device that runs for the given duration is best started immediately, or
+/// whether waiting for the best upcoming window is materially cheaper
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_recommendation(
+    State(state): State<AppState>,
+    parameters: Query<RecommendationParameters>,
+) -> axum::response::Result<(StatusCode, Json<Recommendation>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let now = Utc::now();
+    let current_hour = now.duration_trunc(TimeDelta::hours(1)).unwrap_or(now);
+
+    let run_now = state
+        .price_repository
+        .fetch_price_statistics(
+            current_hour,
+            current_hour + TimeDelta::hours(parameters.duration as i64),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let upcoming_prices = state
+        .price_repository
+        .fetch_prices(
+            current_hour,
+            current_hour + TimeDelta::days(UPCOMING_DAYS),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let best = optimize(
+        &Contiguous {
+            duration: parameters.duration.max(1) as usize,
+        },
+        &upcoming_prices,
+        None,
+    )
+    .and_then(|windows| windows.into_iter().next());
+
+    let run_now_average_price = run_now
+        .average
+        .filter(|_| run_now.hours >= parameters.duration as i64);
+
+    let (Some(run_now_average_price), Some(best)) = (run_now_average_price, best) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "not enough upcoming prices are known for the duration".to_string(),
+        )
+            .into());
+    };
+
+    let best = best.with_timezone(state.timezone);
+
+    let best_average_price = best.effective_average_price().ok_or((
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "invalid price".to_string(),
+    ))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(Recommendation::new(
+            now.with_timezone(&state.timezone).fixed_offset(),
+            run_now_average_price,
+            best.starts_at,
+            best_average_price,
+            parameters.tolerance.unwrap_or(DEFAULT_TOLERANCE_PERCENTAGE),
+        )),
+    ))
+}
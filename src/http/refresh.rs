@@ -0,0 +1,68 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{NaiveDate, TimeDelta, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{scheduler::refresh_prices, setup::AppState};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RefreshParameters {
+    /// One of `today`, `tomorrow` or `both` (default)
+    day: Option<String>,
+    /// The bidding zone of which to refresh the prices, e.g. `NL`, of every area by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct Refresh {
+    dates: Vec<NaiveDate>,
+    /// The number of prices of the dates that were stored
+    stored: usize,
+}
+
+/// Force a re-fetch of the prices of today and/or tomorrow from the providers, optionally of a
+/// single area, replacing the stored prices. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_refresh(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    parameters: Query<RefreshParameters>,
+) -> axum::response::Result<(StatusCode, Json<Refresh>)> {
+    require_admin(&state, &headers)?;
+
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+    let tomorrow = today + TimeDelta::days(1);
+
+    let dates = match parameters.day.as_deref().unwrap_or("both") {
+        "today" => vec![today],
+        "tomorrow" => vec![tomorrow],
+        "both" => vec![today, tomorrow],
+        day => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("unknown day \"{}\", expected today, tomorrow or both", day),
+            )
+                .into())
+        }
+    };
+
+    let prices = refresh_prices(&state, &dates, parameters.area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(Refresh {
+            dates,
+            stored: prices.len(),
+        }),
+    ))
+}
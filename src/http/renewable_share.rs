@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{domain::round, optimizer::costs, setup::AppState};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct RenewableShareParameters {
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct RenewableShareHour {
+    starts_at: DateTime<FixedOffset>,
+    zone: String,
+    /// The share of the load that wind and sun generate
+    renewable_share: f64,
+    solar_mw: f64,
+    wind_mw: f64,
+    load_mw: f64,
+    /// The price a consumer pays, or the market price without a price formula or tariff, when
+    /// it is known
+    price: Option<f64>,
+    currency: Option<String>,
+}
+
+/// Fetch the forecast renewable share of the bidding zone in the hours between a start and end
+/// moment, with the price of each hour
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_renewable_share(
+    State(state): State<AppState>,
+    parameters: Query<RenewableShareParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<RenewableShareHour>>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let Some(entsoe) = &state.entsoe else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "the renewable share is not fetched, set RENEWABLE_SHARE_DSN".to_string(),
+        )
+            .into());
+    };
+
+    let start = parameters.moment_start.to_utc();
+    let end = parameters.moment_end.to_utc();
+
+    let generation = state
+        .renewable_generation_repository
+        .fetch_renewable_generation(entsoe.zone(), start, end)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let prices = state
+        .price_repository
+        .fetch_prices(start, end, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let prices = prices
+        .iter()
+        .zip(costs(&prices, None))
+        .map(|(price, cost)| (price.moment, (cost, &price.currency)))
+        .collect::<HashMap<DateTime<Utc>, (f64, &String)>>();
+
+    let timezone = parameters.moment_start.timezone();
+
+    let hours = generation
+        .into_iter()
+        .map(|hour| {
+            let price = prices.get(&hour.moment);
+
+            RenewableShareHour {
+                starts_at: hour.moment.with_timezone(&timezone),
+                renewable_share: round(hour.renewable_share(), 3),
+                solar_mw: round(hour.solar_mw, 1),
+                wind_mw: round(hour.wind_mw, 1),
+                load_mw: round(hour.load_mw, 1),
+                price: price.map(|(cost, _)| round(*cost, 3)),
+                currency: price.map(|(_, currency)| currency.to_string()),
+                zone: hour.zone,
+            }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(hours)))
+}
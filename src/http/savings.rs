@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{Days, NaiveDate};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{round, start_of_day},
+    savings::{fetch_savings, DaySavings},
+    setup::AppState,
+};
+
+/// The longest range of days that can be reported in a single request
+const MAX_DAYS: u64 = 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct SavingsParameters {
+    /// The first day to report
+    from: NaiveDate,
+    /// The last day to report, defaults to `from`
+    to: Option<NaiveDate>,
+    /// Only report the readings of this device, to see whether shifting it pays off
+    device_id: Option<i64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SavingsReport {
+    currency: Option<String>,
+    days: Vec<SavingsDay>,
+    #[serde(flatten)]
+    total: SavingsDay,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SavingsDay {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<NaiveDate>,
+    energy_kwh: f64,
+    actual_cost: f64,
+    /// What the consumption would have cost at the average price of the day
+    average_cost: f64,
+    /// What the consumption would have cost in the most expensive hours of the day
+    worst_cost: f64,
+    /// What shifting saved compared to consuming at the average price
+    savings_vs_average: f64,
+    /// What shifting saved compared to the most expensive schedule
+    savings_vs_worst: f64,
+}
+
+impl SavingsDay {
+    fn new(date: Option<NaiveDate>, savings: &DaySavings) -> Self {
+        Self {
+            date,
+            energy_kwh: round(savings.energy_kwh, 3),
+            actual_cost: round(savings.actual_cost, 3),
+            average_cost: round(savings.average_cost, 3),
+            worst_cost: round(savings.worst_cost, 3),
+            savings_vs_average: round(savings.average_cost - savings.actual_cost, 3),
+            savings_vs_worst: round(savings.worst_cost - savings.actual_cost, 3),
+        }
+    }
+}
+
+/// Quantify what shifting consumption to cheap hours saved on every day between two dates:
+/// the actual cost of the stored consumption against what the same consumption would have
+/// cost at the average price of the day, and in the most expensive hours of the day. Days are
+/// interpreted in the configured timezone.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_savings(
+    State(state): State<AppState>,
+    parameters: Query<SavingsParameters>,
+) -> axum::response::Result<(StatusCode, Json<SavingsReport>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let to = parameters.to.unwrap_or(parameters.from);
+
+    let days = (to - parameters.from).num_days();
+
+    if days < 0 || days as u64 >= MAX_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("the range must span between 1 and {} days", MAX_DAYS),
+        )
+            .into());
+    }
+
+    let start = start_of_day(&state.timezone, parameters.from);
+    let end = start_of_day(&state.timezone, to + Days::new(1));
+
+    let (days, currency) = fetch_savings(&state, start, end, parameters.device_id, area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SavingsReport {
+            currency,
+            total: SavingsDay::new(None, &DaySavings::total(start, &days)),
+            days: days
+                .iter()
+                .map(|day| {
+                    SavingsDay::new(
+                        Some(day.starts_at.with_timezone(&state.timezone).date_naive()),
+                        day,
+                    )
+                })
+                .collect(),
+        }),
+    ))
+}
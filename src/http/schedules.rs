@@ -0,0 +1,268 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::NaiveDate;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    device::{validate_hours, Recurrence, Schedule, ScheduleOverride},
+    device_repository::NewSchedule,
+    setup::AppState,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ScheduleParameters {
+    /// Only list the schedules of this device
+    device_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ScheduleRequest {
+    device_id: i64,
+    /// How often the device runs, `daily` by default
+    recurrence: Option<Recurrence>,
+    /// The local hour from which the device may start, 0 by default
+    available_from_hour: Option<i32>,
+    /// The local hour the device must be finished by, 24 by default
+    finish_by_hour: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ScheduleUpdate {
+    enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct OverrideRequest {
+    /// Whether to skip the occurrence altogether
+    skip: Option<bool>,
+    available_from_hour: Option<i32>,
+    finish_by_hour: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct ScheduleDetails {
+    #[serde(flatten)]
+    schedule: Schedule,
+    overrides: Vec<ScheduleOverride>,
+}
+
+/// List the schedules with their overrides
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_schedules(
+    State(state): State<AppState>,
+    parameters: Query<ScheduleParameters>,
+) -> axum::response::Result<(StatusCode, Json<Vec<ScheduleDetails>>)> {
+    let schedules = match parameters.device_id {
+        Some(device_id) => state.device_repository.fetch_schedules(device_id).await,
+        None => state.device_repository.fetch_all_schedules().await,
+    }
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(with_overrides(&state, schedules).await?),
+    ))
+}
+
+/// A schedule with its overrides
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> axum::response::Result<(StatusCode, Json<ScheduleDetails>)> {
+    let schedule = find_schedule(&state, id).await?;
+
+    let details = with_overrides(&state, vec![schedule]).await?.remove(0);
+
+    Ok((StatusCode::OK, Json(details)))
+}
+
+/// Add a schedule to a device. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ScheduleRequest>,
+) -> axum::response::Result<(StatusCode, Json<Schedule>)> {
+    require_admin(&state, &headers)?;
+
+    let available_from_hour = request.available_from_hour.unwrap_or(0);
+    let finish_by_hour = request.finish_by_hour.unwrap_or(24);
+
+    validate_hours(available_from_hour, finish_by_hour)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    state
+        .device_repository
+        .fetch_device(request.device_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("no device with id {}", request.device_id),
+        ))?;
+
+    let schedule = state
+        .device_repository
+        .persist_schedule(&NewSchedule {
+            device_id: request.device_id,
+            recurrence: request.recurrence.unwrap_or(Recurrence::Daily),
+            available_from_hour,
+            finish_by_hour,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(schedule)))
+}
+
+/// Enable or disable a schedule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn patch_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(update): Json<ScheduleUpdate>,
+) -> axum::response::Result<(StatusCode, Json<Schedule>)> {
+    require_admin(&state, &headers)?;
+
+    let schedule = state
+        .device_repository
+        .set_schedule_enabled(id, update.enabled)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or(not_found(id))?;
+
+    Ok((StatusCode::OK, Json(schedule)))
+}
+
+/// Delete a schedule. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let deleted = state
+        .device_repository
+        .delete_schedule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(not_found(id).into()),
+    }
+}
+
+/// Skip a single occurrence of a schedule, or move its hours. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn put_override(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((id, date)): Path<(i64, NaiveDate)>,
+    Json(request): Json<OverrideRequest>,
+) -> axum::response::Result<(StatusCode, Json<ScheduleOverride>)> {
+    require_admin(&state, &headers)?;
+
+    let schedule = find_schedule(&state, id).await?;
+
+    validate_hours(
+        request
+            .available_from_hour
+            .unwrap_or(schedule.available_from_hour),
+        request.finish_by_hour.unwrap_or(schedule.finish_by_hour),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let schedule_override = state
+        .device_repository
+        .persist_override(&ScheduleOverride {
+            schedule_id: id,
+            date,
+            skip: request.skip.unwrap_or(false),
+            available_from_hour: request.available_from_hour,
+            finish_by_hour: request.finish_by_hour,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((StatusCode::OK, Json(schedule_override)))
+}
+
+/// Remove the override of an occurrence. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_override(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((id, date)): Path<(i64, NaiveDate)>,
+) -> axum::response::Result<StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let deleted = state
+        .device_repository
+        .delete_override(id, date)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((
+            StatusCode::NOT_FOUND,
+            format!("schedule {} has no override on {}", id, date),
+        )
+            .into()),
+    }
+}
+
+async fn find_schedule(state: &AppState, id: i64) -> Result<Schedule, (StatusCode, String)> {
+    state
+        .device_repository
+        .fetch_schedule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or(not_found(id))
+}
+
+async fn with_overrides(
+    state: &AppState,
+    schedules: Vec<Schedule>,
+) -> Result<Vec<ScheduleDetails>, (StatusCode, String)> {
+    let overrides = state
+        .device_repository
+        .fetch_overrides(&schedules.iter().map(|s| s.id).collect::<Vec<i64>>())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(schedules
+        .into_iter()
+        .map(|schedule| ScheduleDetails {
+            overrides: overrides
+                .iter()
+                .filter(|o| o.schedule_id == schedule.id)
+                .cloned()
+                .collect(),
+            schedule,
+        })
+        .collect())
+}
+
+fn not_found(id: i64) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("no schedule with id {}", id))
+}
@@ -0,0 +1,53 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Serialize;
+use tracing::instrument;
+
+use super::AreaParameters;
+use crate::{
+    price_level::{current_price, CurrentPrice},
+    setup::AppState,
+    sg_ready::SgReadyState,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SgReady {
+    state: SgReadyState,
+    /// The number of the SG-Ready operating state, 1 (blocked) to 4 (forced)
+    mode: u8,
+    current_price: CurrentPrice,
+}
+
+/// The SG-Ready state a heat pump should be in, derived from the level of the current price
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_sg_ready(
+    State(state): State<AppState>,
+    parameters: Query<AreaParameters>,
+) -> axum::response::Result<(StatusCode, Json<SgReady>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let current_price = current_price(&state, Utc::now(), area.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "the price of the current hour is not known".to_string(),
+        ))?;
+
+    let sg_ready_state = state.settings().sg_ready.state_of(current_price.level);
+
+    Ok((
+        StatusCode::OK,
+        Json(SgReady {
+            state: sg_ready_state,
+            mode: sg_ready_state.mode(),
+            current_price,
+        }),
+    ))
+}
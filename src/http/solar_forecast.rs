@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{domain::round, setup::AppState};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct SolarForecastParameters {
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SolarForecast {
+    total_kwh: f64,
+    hours: Vec<SolarHour>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct SolarHour {
+    starts_at: DateTime<FixedOffset>,
+    energy_kwh: f64,
+}
+
+/// Fetch the forecast production of the solar panels in the hours between a start and end
+/// moment
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_solar_forecast(
+    State(state): State<AppState>,
+    parameters: Query<SolarForecastParameters>,
+) -> axum::response::Result<(StatusCode, Json<SolarForecast>)> {
+    if state.solar_panels.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "the solar forecast is not fetched, set SOLAR_FORECAST_DSN".to_string(),
+        )
+            .into());
+    }
+
+    let forecast = state
+        .solar_forecast_repository
+        .fetch_solar_forecast(
+            parameters.moment_start.to_utc(),
+            parameters.moment_end.to_utc(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let timezone = parameters.moment_start.timezone();
+
+    Ok((
+        StatusCode::OK,
+        Json(SolarForecast {
+            total_kwh: round(forecast.iter().map(|hour| hour.energy_kwh).sum(), 3),
+            hours: forecast
+                .into_iter()
+                .map(|hour| SolarHour {
+                    starts_at: hour.moment.with_timezone(&timezone),
+                    energy_kwh: round(hour.energy_kwh, 3),
+                })
+                .collect(),
+        }),
+    ))
+}
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Days, FixedOffset};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{
+    contract::current_contract,
+    domain::{start_of_day, TariffComparison},
+    setup::AppState,
+};
+
+/// Without consumption data every hour is assumed to use this much energy
+const DEFAULT_HOURLY_KWH: f64 = 1.0;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct TariffComparisonParameters {
+    moment_start: DateTime<FixedOffset>,
+    moment_end: DateTime<FixedOffset>,
+    /// The average daily consumption, spread evenly over the hours of the day
+    daily_kwh: Option<f64>,
+    /// The bidding zone of the prices, e.g. `NL`, of the first provider by default
+    area: Option<String>,
+}
+
+/// Compare what the period between a start and end moment cost under dynamic prices to what
+/// it would have cost under the configured fixed tariff, including the fixed charges of the
+/// contract over the local days the period touches
+#[debug_handler(state = AppState)]
+#[instrument(skip(state))]
+pub(super) async fn get_tariff_comparison(
+    State(state): State<AppState>,
+    parameters: Query<TariffComparisonParameters>,
+) -> axum::response::Result<(StatusCode, Json<TariffComparison>)> {
+    let area = state.price_area(parameters.area.as_deref());
+
+    let fixed_rate = state.settings().pricing.fixed_tariff_rate.ok_or((
+        StatusCode::BAD_REQUEST,
+        "no fixed tariff is configured, set FIXED_TARIFF_RATE".to_string(),
+    ))?;
+
+    let statistics = state
+        .price_repository
+        .fetch_price_statistics(
+            parameters.moment_start.to_utc(),
+            parameters.moment_end.to_utc(),
+            area.as_deref(),
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let hourly_kwh = parameters
+        .daily_kwh
+        .map(|daily_kwh| daily_kwh / 24.0)
+        .unwrap_or(DEFAULT_HOURLY_KWH);
+
+    let from = parameters
+        .moment_start
+        .with_timezone(&state.timezone)
+        .date_naive();
+    let end = parameters
+        .moment_end
+        .with_timezone(&state.timezone)
+        .date_naive();
+
+    // a period that ends at midnight does not touch the day that starts then
+    let until = match start_of_day(&state.timezone, end) == parameters.moment_end.to_utc() {
+        true => end,
+        false => end + Days::new(1),
+    };
+
+    let fixed_charges = current_contract(&state)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .fixed_charges(from, until);
+
+    Ok((
+        StatusCode::OK,
+        Json(TariffComparison::new(
+            &statistics,
+            fixed_rate,
+            hourly_kwh,
+            fixed_charges.total(),
+        )),
+    ))
+}
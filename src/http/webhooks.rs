@@ -0,0 +1,220 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use axum_macros::debug_handler;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::require_admin;
+use crate::{
+    setup::AppState,
+    webhook::{
+        all_webhooks, generate_secret, DeliveryAttempt, DeliveryStatus, Webhook, WebhookDelivery,
+        WebhookEvent,
+    },
+    webhook_repository::NewWebhook,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct WebhookRequest {
+    url: String,
+    /// The events to call the webhook on, every event when absent
+    events: Option<Vec<WebhookEvent>>,
+    /// What to sign deliveries with, generated when absent
+    secret: Option<String>,
+}
+
+/// A registered webhook with its secret, which is only shown once
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct RegisteredWebhook {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct DeliveriesQuery {
+    /// Only the deliveries with this status, e.g. `failed` for the ones that were given up on
+    status: Option<DeliveryStatus>,
+}
+
+/// Secrets shorter than this are too easy to guess
+const MIN_SECRET_LENGTH: usize = 16;
+
+/// List the registered and configured webhooks. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_webhooks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> axum::response::Result<(StatusCode, Json<Vec<Webhook>>)> {
+    require_admin(&state, &headers)?;
+
+    let webhooks = all_webhooks(&state)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(webhooks)))
+}
+
+/// Register a webhook, of which the deliveries are signed with a secret. Requires the admin
+/// token.
+#[debug_handler(state = AppState)]
+// the request carries the secret, which must not end up in the logs
+#[instrument(skip(state, headers, request))]
+pub(super) async fn post_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<WebhookRequest>,
+) -> axum::response::Result<(StatusCode, Json<RegisteredWebhook>)> {
+    require_admin(&state, &headers)?;
+
+    match url::Url::parse(&request.url) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("{} is not an http(s) URL", request.url),
+            )
+                .into())
+        }
+    }
+
+    let events = request
+        .events
+        .unwrap_or(WebhookEvent::ALL.to_vec())
+        .into_iter()
+        .fold(Vec::new(), |mut events, event| {
+            if !events.contains(&event) {
+                events.push(event);
+            }
+            events
+        });
+
+    if events.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "a webhook needs at least one event".to_string(),
+        )
+            .into());
+    }
+
+    if request
+        .secret
+        .as_ref()
+        .is_some_and(|secret| secret.len() < MIN_SECRET_LENGTH)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("a secret needs at least {} characters", MIN_SECRET_LENGTH),
+        )
+            .into());
+    }
+
+    let secret = request.secret.unwrap_or_else(generate_secret);
+
+    let webhook = state
+        .webhook_repository
+        .persist_webhook(&NewWebhook {
+            url: request.url,
+            events,
+            secret: secret.clone(),
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisteredWebhook { webhook, secret }),
+    ))
+}
+
+/// Delete a registered webhook. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let deleted = state
+        .webhook_repository
+        .delete_webhook(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    match deleted {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((StatusCode::NOT_FOUND, format!("no webhook with id {}", id)).into()),
+    }
+}
+
+/// List the deliveries of webhooks, the most recent first. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DeliveriesQuery>,
+) -> axum::response::Result<(StatusCode, Json<Vec<WebhookDelivery>>)> {
+    require_admin(&state, &headers)?;
+
+    let deliveries = state
+        .webhook_repository
+        .fetch_deliveries(query.status)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(deliveries)))
+}
+
+/// List the attempts of a delivery. Requires the admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn get_delivery_attempts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<(StatusCode, Json<Vec<DeliveryAttempt>>)> {
+    require_admin(&state, &headers)?;
+
+    let attempts = state
+        .webhook_repository
+        .fetch_attempts(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok((StatusCode::OK, Json(attempts)))
+}
+
+/// Queue a delivery that was given up on again, it is attempted within a minute. Requires the
+/// admin token.
+#[debug_handler(state = AppState)]
+#[instrument(skip(state, headers))]
+pub(super) async fn post_delivery_retry(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> axum::response::Result<StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let retried = state
+        .webhook_repository
+        .retry_delivery(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    match retried {
+        true => Ok(StatusCode::ACCEPTED),
+        false => Err((
+            StatusCode::NOT_FOUND,
+            format!("no failed delivery with id {}", id),
+        )
+            .into()),
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::{NaiveDate, TimeDelta};
+use reqwest::Client;
+use url::Url;
+
+use crate::{
+    chat::describe_error,
+    domain::{start_of_day, PricePoint},
+    optimizer::costs,
+    price_level::PriceLevel,
+    setup::AppState,
+};
+
+/// How long InfluxDB may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A bucket of an InfluxDB v2 server that prices are written to
+#[derive(Clone, Debug)]
+pub(crate) struct InfluxDb {
+    /// The write endpoint, with the organization, bucket and precision as query parameters
+    write_url: Url,
+    token: String,
+    measurement: String,
+}
+
+impl InfluxDb {
+    pub(crate) fn new(
+        url: &Url,
+        token: String,
+        organization: &str,
+        bucket: &str,
+        measurement: String,
+    ) -> Self {
+        let mut write_url = url.clone();
+
+        // a server behind a path prefix keeps it
+        if let Ok(mut segments) = write_url.path_segments_mut() {
+            segments.pop_if_empty().extend(["api", "v2", "write"]);
+        }
+
+        write_url
+            .query_pairs_mut()
+            .clear()
+            .append_pair("org", organization)
+            .append_pair("bucket", bucket)
+            .append_pair("precision", "s");
+
+        Self {
+            write_url,
+            token,
+            measurement,
+        }
+    }
+
+    /// Write points in line protocol
+    async fn write(&self, lines: &[String]) -> Result<(), String> {
+        let response = Client::new()
+            .post(self.write_url.clone())
+            .timeout(TIMEOUT)
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(lines.join("\n"))
+            .send()
+            .await
+            .map_err(describe_error)?;
+
+        let status = response.status();
+
+        match status.is_success() {
+            true => Ok(()),
+            false => Err(format!(
+                "influxdb responded {}, {}",
+                status,
+                response.text().await.unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Write the prices of the days that ingested prices fall on to InfluxDB, with their levels.
+/// A level compares a price to the average of its day and area, so every price of those days
+/// and of the area of the ingested prices is written, replacing the points written before.
+pub(crate) async fn export_prices(
+    state: &AppState,
+    provider: &str,
+    ingested: &[PricePoint],
+) -> Result<(), String> {
+    let Some(influxdb) = &state.influxdb else {
+        return Ok(());
+    };
+
+    let area = ingested.first().and_then(|price| price.area.as_deref());
+
+    let dates = ingested
+        .iter()
+        .map(|price| price.moment.with_timezone(&state.timezone).date_naive())
+        .collect::<BTreeSet<NaiveDate>>();
+
+    let mut lines = Vec::new();
+
+    for date in dates {
+        let prices = state
+            .price_repository
+            .fetch_prices(
+                start_of_day(&state.timezone, date),
+                start_of_day(&state.timezone, date + TimeDelta::days(1)),
+                area,
+            )
+            .await?;
+
+        lines.extend(day_lines(&influxdb.measurement, provider, &prices));
+    }
+
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    influxdb.write(&lines).await
+}
+
+/// The prices of a day in line protocol, tagged with the provider, the area when it is known,
+/// and the currency. The level of a price compares what a consumer pays to the average of the
+/// day.
+fn day_lines(measurement: &str, provider: &str, prices: &[PricePoint]) -> Vec<String> {
+    let costs = costs(prices, None);
+    let average = costs.iter().sum::<f64>() / costs.len() as f64;
+
+    prices
+        .iter()
+        .zip(&costs)
+        .map(|(price, cost)| {
+            let mut fields = vec![format!("price={}", price.monetary_amount)];
+
+            if let Some(consumer_amount) = price.consumer_amount {
+                fields.push(format!("consumer_price={}", consumer_amount));
+            }
+
+            fields.push(format!(
+                "level=\"{}\"",
+                PriceLevel::of(*cost, average).as_str()
+            ));
+
+            let area = price
+                .area
+                .as_ref()
+                .map(|area| format!(",area={}", escape(area)))
+                .unwrap_or_default();
+
+            format!(
+                "{},provider={}{},currency={} {} {}",
+                escape(measurement),
+                escape(provider),
+                area,
+                escape(&price.currency),
+                fields.join(","),
+                price.moment.timestamp()
+            )
+        })
+        .collect()
+}
+
+/// Escape the characters that separate the parts of a line in a measurement or tag
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::test_support::price_point;
+
+    #[test]
+    fn test_day_lines() {
+        let start = DateTime::parse_from_rfc3339("2024-06-30T00:00:00+02:00")
+            .unwrap()
+            .to_utc();
+        let prices = [(0.10, Some(0.25)), (0.50, None)]
+            .iter()
+            .enumerate()
+            .map(|(hour, (amount, consumer_amount))| PricePoint {
+                consumer_amount: *consumer_amount,
+                area: Some("NL".to_string()),
+                ..price_point(start + TimeDelta::hours(hour as i64), *amount)
+            })
+            .collect::<Vec<PricePoint>>();
+
+        assert_eq!(
+            day_lines("electricity price", "tibber", &prices),
+            [
+                "electricity\\ price,provider=tibber,area=NL,currency=EUR price=0.1,consumer_price=0.25,level=\"cheap\" 1719698400",
+                "electricity\\ price,provider=tibber,area=NL,currency=EUR price=0.5,level=\"expensive\" 1719702000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_url() {
+        let influxdb = InfluxDb::new(
+            &Url::parse("https://influx.home.example/influx/").unwrap(),
+            "token".to_string(),
+            "home",
+            "energy prices",
+            "electricity_price".to_string(),
+        );
+
+        assert_eq!(
+            influxdb.write_url.as_str(),
+            "https://influx.home.example/influx/api/v2/write?org=home&bucket=energy+prices&precision=s"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use sqlx::{pool::PoolConnection, PgPool, Postgres};
+use tracing::error;
+
+/// Coordinates background jobs between instances that share a database through Postgres
+/// advisory locks, so only one instance runs a job at a time
+#[derive(Clone, Debug)]
+pub(crate) struct JobLock {
+    db: PgPool,
+}
+
+/// A held job lock, which must be released once the job is done. The lock is also released
+/// when the guard is dropped, by closing its connection.
+pub(crate) struct JobLockGuard {
+    connection: Option<PoolConnection<Postgres>>,
+    job: String,
+}
+
+impl JobLock {
+    pub(crate) fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+
+    /// Try to acquire the lock of a job, `None` when another instance holds it
+    pub(crate) async fn try_acquire(&self, job: &str) -> Result<Option<JobLockGuard>, String> {
+        let mut connection = self.db.acquire().await.map_err(|e| e.to_string())?;
+
+        let (acquired,): (bool,) = sqlx::query_as("select pg_try_advisory_lock(hashtext($1))")
+            .bind(job)
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(acquired.then(|| JobLockGuard {
+            connection: Some(connection),
+            job: job.to_string(),
+        }))
+    }
+}
+
+impl JobLockGuard {
+    pub(crate) async fn release(mut self) {
+        let Some(mut connection) = self.connection.take() else {
+            return;
+        };
+
+        let result = sqlx::query("select pg_advisory_unlock(hashtext($1))")
+            .bind(&self.job)
+            .execute(&mut *connection)
+            .await;
+
+        if let Err(e) = result {
+            error!("unable to release the lock of {}, {}", self.job, e);
+            // closing the connection releases its locks
+            drop(connection.detach());
+        }
+    }
+}
+
+impl Drop for JobLockGuard {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            drop(connection.detach());
+        }
+    }
+}
@@ -1,35 +1,134 @@
-use axum::async_trait;
-use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
-use domain::PricePoint;
+use std::process;
+
+use clap::Parser;
 use log::{error, info};
 use price_repository::PriceRepository;
-use serde_derive::{Deserialize, Serialize};
-use sqlx::PgPool;
-use thiserror::Error;
-use tracing_subscriber::fmt::format::FmtSpan;
 
-use crate::http::start_http_server;
+use crate::cli::Cli;
 
+mod actuator;
+mod backup;
+mod backup_repository;
+mod battery;
+mod billing;
+mod carbon_intensity;
+mod carbon_intensity_repository;
+mod chat;
+mod cli;
+mod client;
+mod configuration_file;
+mod consumption;
+mod consumption_forecast;
+mod consumption_import;
+mod consumption_repository;
+mod contract;
+mod contract_repository;
+mod cost_report;
+mod cron;
+mod currency;
+mod degree_days;
+mod demo;
+mod device;
+mod device_repository;
 mod domain;
+mod dsmr;
+mod ecb;
+mod electricity_maps;
+mod email;
+mod entsoe;
+mod exchange_rate_repository;
+mod forecast_solar;
+mod formula;
+mod google_calendar;
+mod grid_fee;
+mod heat_pump;
+mod home_assistant;
 mod http;
-mod nordpool;
+mod influxdb;
+mod job_lock;
+mod memory;
+mod mqtt;
+mod mqtt_publisher;
+#[cfg(feature = "mysql")]
+mod mysql;
+mod national_grid;
+mod notification;
+mod notification_repository;
+mod optimizer;
+mod planned_window_repository;
+mod planner;
+mod price_cache;
+mod price_cap;
+mod price_export;
+mod price_level;
 mod price_repository;
+mod recommendation;
+mod reload;
+mod renewable_generation_repository;
+mod s3;
+mod savings;
+mod scheduler;
+mod self_consumption;
+mod sentry;
 mod setup;
+mod sg_ready;
+mod single_flight;
+mod solar_forecast_repository;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod tariff;
+mod telegram;
+mod telemetry;
+mod template;
+#[cfg(test)]
+mod test_support;
 mod tibber;
+mod tls;
+mod unix_socket;
+mod weather;
+mod weather_repository;
+mod webhook;
+mod webhook_repository;
 
 const APP_NAME: &str = "electrack";
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::TRACE)
-        .with_span_events(FmtSpan::CLOSE)
-        .init();
+    let cli = Cli::parse();
+
+    dotenv::dotenv().ok();
+
+    // secrets and the configuration file may configure the telemetry, so they are read before
+    // tracing starts, of which the secrets go first as they override the file
+    let secret_files = configuration_file::load_secret_files();
+    let configuration_file = configuration_file::load_configuration_file();
+
+    telemetry::init_tracing(cli.writes_to_stdout());
 
     info!("starting {}", APP_NAME);
 
-    dotenv::dotenv().ok();
+    match secret_files {
+        Ok(secrets) if !secrets.is_empty() => info!("read {} from files", secrets.join(", ")),
+        Ok(_) => {}
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    match configuration_file {
+        Ok(Some(path)) => info!("read the configuration from {}", path),
+        Ok(None) => {}
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Err(e) = cli.run().await {
+        error!("{}", e);
+        process::exit(1);
+    }
 
-    start_http_server().await.unwrap();
     info!("shutting down {}", APP_NAME);
 }
@@ -0,0 +1,878 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+use crate::{
+    backup::{Backup, BackupPrice, Restoration},
+    backup_repository::{BackupRepository, BackupRepositoryError},
+    carbon_intensity_repository::{CarbonIntensityRepository, CarbonIntensityRepositoryError},
+    consumption_repository::{ConsumptionRepository, ConsumptionRepositoryError},
+    contract::Contract,
+    contract_repository::ContractRepository,
+    device::{Actuation, Device, DeviceRun, Schedule, ScheduleOverride},
+    device_repository::{DeviceRepository, DeviceRepositoryError, NewDevice, NewSchedule},
+    domain::{
+        CarbonIntensity, Consumption, DeviceConsumption, ExchangeRate, PriceFetch, PricePoint,
+        PriceStatistics, RenewableGeneration, SolarProduction,
+    },
+    exchange_rate_repository::{ExchangeRateRepository, ExchangeRateRepositoryError},
+    notification::NotificationRule,
+    notification_repository::{
+        NewNotificationRule, NotificationRepository, NotificationRepositoryError,
+    },
+    planned_window_repository::{
+        CalendarEvent, NewPlannedWindow, PlannedWindow, PlannedWindowRepository,
+    },
+    price_repository::{
+        price_gaps, unique_prices, DeletedPrices, IngestedPrices, PriceGap, PriceRepository,
+        PriceRepositoryError, PriceVersion,
+    },
+    renewable_generation_repository::{
+        RenewableGenerationRepository, RenewableGenerationRepositoryError,
+    },
+    setup::Repositories,
+    solar_forecast_repository::{SolarForecastRepository, SolarForecastRepositoryError},
+    weather::Weather,
+    weather_repository::{WeatherRepository, WeatherRepositoryError},
+    webhook::{DeliveryAttempt, DeliveryStatus, DueDelivery, Webhook, WebhookDelivery},
+    webhook_repository::{NewDelivery, NewWebhook, WebhookRepository, WebhookRepositoryError},
+};
+
+/// How many persisted batches of prices a slow listener may lag behind
+const INGESTED_PRICES_CAPACITY: usize = 16;
+
+/// Why something other than prices cannot be created in memory
+const UNSTORED: &str = "only prices are stored in memory";
+
+/// Storage that only lasts as long as the process, for demo mode and tests of the http layer.
+/// Prices and exchange rates are kept, everything else is discarded by [`Unstored`].
+pub(crate) fn repositories(price_repository: MemoryPriceRepository) -> Repositories {
+    Repositories {
+        price: Arc::new(price_repository),
+        exchange_rate: Arc::new(MemoryExchangeRateRepository::default()),
+        device: Arc::new(Unstored),
+        planned_window: Arc::new(Unstored),
+        notification: Arc::new(Unstored),
+        webhook: Arc::new(Unstored),
+        backup: Arc::new(Unstored),
+        carbon_intensity: Arc::new(Unstored),
+        solar_forecast: Arc::new(Unstored),
+        renewable_generation: Arc::new(Unstored),
+        weather: Arc::new(Unstored),
+        consumption: Arc::new(Unstored),
+        contract: Arc::new(Unstored),
+    }
+}
+
+/// Prices kept in memory by moment, provider and area, with every version that was persisted
+#[derive(Clone, Debug)]
+pub(crate) struct MemoryPriceRepository {
+    prices: Arc<RwLock<MemoryPrices>>,
+    /// Only the prices persisted by this process are announced, the only one that sees them
+    ingested: broadcast::Sender<IngestedPrices>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryPrices {
+    /// The versions of every price, of which the last is the one in use
+    prices: BTreeMap<(DateTime<Utc>, String, String), Vec<PriceVersion>>,
+    fetches: Vec<(String, PriceFetch)>,
+}
+
+impl Default for MemoryPriceRepository {
+    fn default() -> Self {
+        Self {
+            prices: Arc::default(),
+            ingested: broadcast::channel(INGESTED_PRICES_CAPACITY).0,
+        }
+    }
+}
+
+impl MemoryPriceRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The versions of the prices starting within a period, optionally of a single area,
+    /// ordered by moment
+    fn versions_between(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<Vec<PriceVersion>>, String> {
+        let prices = self.prices.read().map_err(|e| e.to_string())?;
+
+        Ok(prices
+            .prices
+            .range(
+                (start_moment, String::new(), String::new())
+                    ..(end_moment, String::new(), String::new()),
+            )
+            .filter(|((_, _, stored_area), _)| area.is_none() || Some(stored_area.as_str()) == area)
+            .map(|(_, versions)| versions.clone())
+            .collect())
+    }
+
+    /// The prices starting within a period, optionally of a single area, ordered by moment
+    fn prices_between(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String> {
+        Ok(self
+            .versions_between(start_moment, end_moment, area)?
+            .into_iter()
+            .filter_map(|versions| versions.last().map(|version| version.price.clone()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl PriceRepository for MemoryPriceRepository {
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String> {
+        self.prices_between(start_moment, end_moment, area)
+    }
+
+    async fn persist_prices(
+        &self,
+        prices: &[PricePoint],
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        let (Some(first), Some(last)) = (
+            prices.iter().map(|price| price.moment).min(),
+            prices.iter().map(|price| price.moment).max(),
+        ) else {
+            return Ok(());
+        };
+
+        {
+            let mut stored = self
+                .prices
+                .write()
+                .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+            let ingested_at = Utc::now();
+
+            for price in unique_prices(prices) {
+                let area = price.area.clone().unwrap_or_default();
+                let versions = stored
+                    .prices
+                    .entry((price.moment, provider_name.to_string(), area))
+                    .or_default();
+
+                // a price that is persisted again unchanged keeps its version
+                if versions.last().map(|version| &version.price) != Some(price) {
+                    versions.push(PriceVersion {
+                        provider: provider_name.to_string(),
+                        version: versions.len() as i32 + 1,
+                        ingested_at,
+                        price: price.clone(),
+                    });
+                }
+            }
+        }
+
+        // there is no one to notify while nothing listens
+        let _ = self.ingested.send(IngestedPrices {
+            provider: provider_name.to_string(),
+            prices: unique_prices(prices).len(),
+            first_moment: first,
+            last_moment: last,
+        });
+
+        Ok(())
+    }
+
+    async fn record_price_fetch(
+        &self,
+        fetch: &PriceFetch,
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        self.prices
+            .write()
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?
+            .fetches
+            .push((provider_name.to_string(), fetch.clone()));
+
+        Ok(())
+    }
+
+    async fn fetch_price_statistics(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<PriceStatistics, String> {
+        let mut amounts_per_currency = BTreeMap::<String, Vec<f64>>::new();
+        for price in self.prices_between(start_moment, end_moment, area)? {
+            amounts_per_currency
+                .entry(price.currency)
+                .or_default()
+                .push(price.consumer_amount.unwrap_or(price.monetary_amount));
+        }
+
+        PriceStatistics::of_single_currency(
+            amounts_per_currency
+                .into_iter()
+                .map(|(currency, amounts)| {
+                    let sum = amounts.iter().sum::<f64>();
+
+                    PriceStatistics {
+                        hours: amounts.len() as i64,
+                        minimum: amounts.iter().copied().reduce(f64::min),
+                        maximum: amounts.iter().copied().reduce(f64::max),
+                        average: Some(sum / amounts.len() as f64),
+                        sum: Some(sum),
+                        currency: Some(currency),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    async fn fetch_price_versions(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceVersion>, String> {
+        Ok(self
+            .versions_between(start_moment, end_moment, area)?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    async fn fetch_price_moments(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        let prices = self.prices.read().map_err(|e| e.to_string())?;
+
+        Ok(prices
+            .prices
+            .range(
+                (start_moment, String::new(), String::new())
+                    ..(end_moment, String::new(), String::new()),
+            )
+            .filter(|((_, _, stored_area), _)| area.is_none() || Some(stored_area.as_str()) == area)
+            .map(|((moment, provider, _), _)| (provider.clone(), *moment))
+            .collect())
+    }
+
+    async fn fetch_price_gaps(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceGap>, String> {
+        let mut providers = self
+            .prices
+            .read()
+            .map_err(|e| e.to_string())?
+            .prices
+            .keys()
+            .filter(|(_, _, stored_area)| area.is_none() || Some(stored_area.as_str()) == area)
+            .map(|(_, provider, _)| provider.clone())
+            .collect::<Vec<String>>();
+        providers.sort();
+        providers.dedup();
+
+        let stored = self
+            .fetch_price_moments(start_moment, end_moment, area)
+            .await?;
+
+        Ok(price_gaps(start_moment, end_moment, &providers, &stored))
+    }
+
+    async fn delete_prices(
+        &self,
+        provider_name: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<u64, PriceRepositoryError> {
+        let mut stored = self
+            .prices
+            .write()
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+        let count = stored.prices.len();
+        stored.prices.retain(|(moment, provider, stored_area), _| {
+            provider != provider_name
+                || *moment < start_moment
+                || *moment >= end_moment
+                || area.is_some_and(|area| area != stored_area)
+        });
+
+        Ok((count - stored.prices.len()) as u64)
+    }
+
+    async fn prune_prices(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        let mut stored = self.prices.write().map_err(|e| e.to_string())?;
+
+        let kept = stored
+            .prices
+            .split_off(&(before, String::new(), String::new()));
+        let pruned = std::mem::replace(&mut stored.prices, kept).len();
+
+        Ok(pruned as u64)
+    }
+
+    async fn prune_price_fetches(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        let mut stored = self.prices.write().map_err(|e| e.to_string())?;
+
+        let count = stored.fetches.len();
+        stored
+            .fetches
+            .retain(|(_, fetch)| fetch.attempted_at >= before);
+
+        Ok((count - stored.fetches.len()) as u64)
+    }
+
+    async fn create_price_partitions(&self, _moments: &[DateTime<Utc>]) -> Result<(), String> {
+        // prices are not partitioned
+        Ok(())
+    }
+
+    async fn listen_for_ingested_prices(&self) -> Result<mpsc::Receiver<IngestedPrices>, String> {
+        let mut ingestions = self.ingested.subscribe();
+
+        let (sender, receiver) = mpsc::channel(INGESTED_PRICES_CAPACITY);
+
+        tokio::spawn(async move {
+            loop {
+                let ingested = match ingestions.recv().await {
+                    Ok(ingested) => ingested,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("missed {} notifications of ingested prices", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                if sender.send(ingested).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    async fn listen_for_deleted_prices(&self) -> Result<mpsc::Receiver<DeletedPrices>, String> {
+        // the prices are only deleted by this instance, which forgets the cached prices itself
+        Ok(mpsc::channel(1).1)
+    }
+}
+
+/// Exchange rates kept in memory by currency and date, so a rate is fetched from the ECB once
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemoryExchangeRateRepository {
+    rates: Arc<RwLock<BTreeMap<(String, NaiveDate), f64>>>,
+}
+
+#[async_trait]
+impl ExchangeRateRepository for MemoryExchangeRateRepository {
+    async fn fetch_rate(
+        &self,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<ExchangeRate>, String> {
+        let rates = self.rates.read().map_err(|e| e.to_string())?;
+
+        Ok(rates
+            .range((currency.to_string(), NaiveDate::MIN)..=(currency.to_string(), date))
+            .next_back()
+            .map(|((currency, date), rate)| ExchangeRate {
+                date: *date,
+                currency: currency.clone(),
+                rate: *rate,
+            }))
+    }
+
+    async fn persist_rates(
+        &self,
+        rates: &[ExchangeRate],
+    ) -> Result<(), ExchangeRateRepositoryError> {
+        let mut stored = self
+            .rates
+            .write()
+            .map_err(|e| ExchangeRateRepositoryError::PersistenceError(e.to_string()))?;
+
+        for rate in rates {
+            stored.insert((rate.currency.clone(), rate.date), rate.rate);
+        }
+
+        Ok(())
+    }
+}
+
+/// The storage of everything but prices and exchange rates in memory, which holds nothing.
+/// Fetches find nothing and what is recorded along the way, such as forecasts and planned
+/// windows, is discarded. Creating devices, rules, webhooks or a contract is refused rather than
+/// pretending they were stored.
+#[derive(Clone, Debug)]
+pub(crate) struct Unstored;
+
+#[async_trait]
+impl DeviceRepository for Unstored {
+    async fn fetch_devices(&self) -> Result<Vec<Device>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_device(&self, _id: i64) -> Result<Option<Device>, String> {
+        Ok(None)
+    }
+
+    async fn fetch_schedules(&self, _device_id: i64) -> Result<Vec<Schedule>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_device(&self, _device: &NewDevice) -> Result<Device, DeviceRepositoryError> {
+        Err(DeviceRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn persist_schedule(
+        &self,
+        _schedule: &NewSchedule,
+    ) -> Result<Schedule, DeviceRepositoryError> {
+        Err(DeviceRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn fetch_all_schedules(&self) -> Result<Vec<Schedule>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_schedule(&self, _id: i64) -> Result<Option<Schedule>, String> {
+        Ok(None)
+    }
+
+    async fn set_schedule_enabled(
+        &self,
+        _id: i64,
+        _enabled: bool,
+    ) -> Result<Option<Schedule>, DeviceRepositoryError> {
+        Ok(None)
+    }
+
+    async fn delete_schedule(&self, _id: i64) -> Result<bool, DeviceRepositoryError> {
+        Ok(false)
+    }
+
+    async fn fetch_overrides(
+        &self,
+        _schedule_ids: &[i64],
+    ) -> Result<Vec<ScheduleOverride>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_override(
+        &self,
+        _schedule_override: &ScheduleOverride,
+    ) -> Result<ScheduleOverride, DeviceRepositoryError> {
+        Err(DeviceRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn delete_override(
+        &self,
+        _schedule_id: i64,
+        _date: NaiveDate,
+    ) -> Result<bool, DeviceRepositoryError> {
+        Ok(false)
+    }
+
+    async fn set_snoozed_until(
+        &self,
+        _device_id: i64,
+        _snoozed_until: Option<DateTime<Utc>>,
+    ) -> Result<bool, DeviceRepositoryError> {
+        Ok(false)
+    }
+
+    async fn persist_run_start(
+        &self,
+        _device_id: i64,
+        _occurrence: Option<(i64, NaiveDate)>,
+        _started_at: DateTime<Utc>,
+    ) -> Result<DeviceRun, DeviceRepositoryError> {
+        Err(DeviceRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn finish_run(
+        &self,
+        _device_id: i64,
+        _finished_at: DateTime<Utc>,
+    ) -> Result<Option<DeviceRun>, DeviceRepositoryError> {
+        Ok(None)
+    }
+
+    async fn fetch_runs(
+        &self,
+        _device_id: i64,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<DeviceRun>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_latest_run(
+        &self,
+        _device_id: i64,
+        _before: DateTime<Utc>,
+    ) -> Result<Option<DeviceRun>, String> {
+        Ok(None)
+    }
+
+    async fn persist_actuations(
+        &self,
+        _actuations: &[Actuation],
+    ) -> Result<(), DeviceRepositoryError> {
+        Ok(())
+    }
+
+    async fn fetch_occurrence_actuations(
+        &self,
+        _device_id: i64,
+        _schedule_id: i64,
+        _date: NaiveDate,
+    ) -> Result<Vec<Actuation>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_actuations(
+        &self,
+        _device_id: i64,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Actuation>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_due_actuations(
+        &self,
+        _since: DateTime<Utc>,
+        _now: DateTime<Utc>,
+    ) -> Result<Vec<Actuation>, String> {
+        Ok(vec![])
+    }
+
+    async fn record_switch(
+        &self,
+        _actuation: &Actuation,
+        _on: bool,
+        _switched_at: DateTime<Utc>,
+        _error: Option<&str>,
+    ) -> Result<(), DeviceRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PlannedWindowRepository for Unstored {
+    async fn persist_planned_windows(
+        &self,
+        _windows: &[NewPlannedWindow<'_>],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn fetch_planned_windows(
+        &self,
+        _device_id: Option<i64>,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<PlannedWindow>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_calendar_events(
+        &self,
+        _after: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_calendar_event(&self, _event: &CalendarEvent) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn delete_calendar_event(&self, _event_id: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn prune_planned_windows(&self, _before: DateTime<Utc>) -> Result<u64, String> {
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for Unstored {
+    async fn fetch_rules(&self) -> Result<Vec<NotificationRule>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_rule(
+        &self,
+        _rule: &NewNotificationRule,
+    ) -> Result<NotificationRule, NotificationRepositoryError> {
+        Err(NotificationRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn set_rule_enabled(
+        &self,
+        _id: i64,
+        _enabled: bool,
+    ) -> Result<Option<NotificationRule>, NotificationRepositoryError> {
+        Ok(None)
+    }
+
+    async fn delete_rule(&self, _id: i64) -> Result<bool, NotificationRepositoryError> {
+        Ok(false)
+    }
+
+    /// Nothing is notified about, as if it was before
+    async fn mark_sent(
+        &self,
+        _rule_id: i64,
+        _device_id: Option<i64>,
+        _starts_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    async fn fetch_last_sent(&self, _rule_id: i64) -> Result<Option<DateTime<Utc>>, String> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for Unstored {
+    async fn fetch_webhooks(&self) -> Result<Vec<Webhook>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_webhook(
+        &self,
+        _webhook: &NewWebhook,
+    ) -> Result<Webhook, WebhookRepositoryError> {
+        Err(WebhookRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+
+    async fn delete_webhook(&self, _id: i64) -> Result<bool, WebhookRepositoryError> {
+        Ok(false)
+    }
+
+    /// Nothing is dispatched, as if it was before
+    async fn mark_dispatched(&self, _key: &str) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    async fn enqueue_deliveries(&self, _deliveries: &[NewDelivery]) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        _limit: i64,
+        _lease: TimeDelta,
+    ) -> Result<Vec<DueDelivery>, String> {
+        Ok(vec![])
+    }
+
+    async fn record_attempt(
+        &self,
+        _id: i64,
+        _attempt: &DeliveryAttempt,
+        _status: DeliveryStatus,
+        _next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn fetch_deliveries(
+        &self,
+        _status: Option<DeliveryStatus>,
+    ) -> Result<Vec<WebhookDelivery>, String> {
+        Ok(vec![])
+    }
+
+    async fn fetch_attempts(&self, _delivery_id: i64) -> Result<Vec<DeliveryAttempt>, String> {
+        Ok(vec![])
+    }
+
+    async fn retry_delivery(&self, _id: i64) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    async fn prune_deliveries(&self, _delivered_before: DateTime<Utc>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BackupRepository for Unstored {
+    async fn fetch_backup(&self) -> Result<Backup, String> {
+        Err(format!("unable to back up, {}", UNSTORED))
+    }
+
+    async fn fetch_prices(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+        _provider: Option<&str>,
+        _area: Option<&str>,
+    ) -> Result<Vec<BackupPrice>, String> {
+        Err(format!("unable to export, {}", UNSTORED))
+    }
+
+    async fn restore_backup(&self, _backup: &Backup) -> Result<Restoration, BackupRepositoryError> {
+        Err(BackupRepositoryError::PersistenceError(
+            UNSTORED.to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl CarbonIntensityRepository for Unstored {
+    async fn fetch_carbon_intensity(
+        &self,
+        _zone: &str,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<CarbonIntensity>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_carbon_intensity(
+        &self,
+        _intensities: &[CarbonIntensity],
+    ) -> Result<(), CarbonIntensityRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SolarForecastRepository for Unstored {
+    async fn fetch_solar_forecast(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<SolarProduction>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_solar_forecast(
+        &self,
+        _forecast: &[SolarProduction],
+    ) -> Result<(), SolarForecastRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RenewableGenerationRepository for Unstored {
+    async fn fetch_renewable_generation(
+        &self,
+        _zone: &str,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<RenewableGeneration>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_renewable_generation(
+        &self,
+        _generation: &[RenewableGeneration],
+    ) -> Result<(), RenewableGenerationRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WeatherRepository for Unstored {
+    async fn fetch_weather(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Weather>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_weather(&self, _weather: &[Weather]) -> Result<(), WeatherRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConsumptionRepository for Unstored {
+    async fn fetch_consumption(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Consumption>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_consumption(
+        &self,
+        _readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        Ok(())
+    }
+
+    async fn fetch_device_consumption(
+        &self,
+        _start_moment: DateTime<Utc>,
+        _end_moment: DateTime<Utc>,
+        _device_id: Option<i64>,
+    ) -> Result<Vec<DeviceConsumption>, String> {
+        Ok(vec![])
+    }
+
+    async fn persist_device_consumption(
+        &self,
+        _device_id: i64,
+        _readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ContractRepository for Unstored {
+    async fn fetch_contract(&self) -> Result<Option<Contract>, String> {
+        Ok(None)
+    }
+
+    async fn persist_contract(&self, _contract: &Contract) -> Result<(), String> {
+        Err(UNSTORED.to_string())
+    }
+}
@@ -0,0 +1,510 @@
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+    time::Instant,
+};
+use tokio_native_tls::TlsConnector;
+use tracing::{info, warn};
+
+/// The number of messages that are kept while the connection to the broker is down
+const QUEUE_SIZE: usize = 256;
+
+/// The delay before reconnecting doubles up to this maximum
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// The payloads of the availability topic
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const PINGREQ: u8 = 0xc0;
+const DISCONNECT: u8 = 0xe0;
+
+/// Set on a publish that is sent again
+const DUP: u8 = 0x08;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum MqttError {
+    #[error("unable to connect to the MQTT broker, {0}")]
+    Connect(String),
+    #[error("the MQTT broker refused the connection, {0}")]
+    Refused(&'static str),
+    #[error("the connection to the MQTT broker failed, {0}")]
+    Connection(String),
+    #[error("the MQTT queue is full, the broker is unreachable")]
+    QueueFull,
+    #[error("unknown QoS \"{0}\", expected 0, 1 or 2")]
+    UnknownQos(String),
+}
+
+/// The delivery guarantee of a message
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum Qos {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl FromStr for Qos {
+    type Err = MqttError;
+
+    fn from_str(qos: &str) -> Result<Self, Self::Err> {
+        match qos {
+            "0" => Ok(Qos::AtMostOnce),
+            "1" => Ok(Qos::AtLeastOnce),
+            "2" => Ok(Qos::ExactlyOnce),
+            _ => Err(MqttError::UnknownQos(qos.to_string())),
+        }
+    }
+}
+
+/// Where and as whom to connect to an MQTT broker
+#[derive(Debug, Clone)]
+pub(crate) struct MqttOptions {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) client_id: String,
+    /// The longest period without any packet before the broker considers the client gone
+    pub(crate) keep_alive: Duration,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    /// Connect over TLS with this connector, which carries the trusted and client certificates
+    pub(crate) tls: Option<TlsConnector>,
+    /// The topic that reads `online` while connected and `offline` otherwise, the latter is
+    /// published by the broker as last will when the connection drops
+    pub(crate) availability_topic: Option<String>,
+    pub(crate) qos: Qos,
+}
+
+#[derive(Debug, Clone)]
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+/// A publish with QoS 1 or 2 that the broker has not yet completed
+#[derive(Debug, Clone)]
+struct Inflight {
+    message: Message,
+    /// Whether the broker received a publish with QoS 2, so only its release is outstanding
+    received: bool,
+}
+
+/// A minimal MQTT 3.1.1 client that only publishes. The connection is kept alive in the
+/// background and restored when it drops, unacknowledged messages are then sent again.
+/// Messages published while the broker is unreachable are queued up to a limit.
+#[derive(Debug, Clone)]
+pub(crate) struct MqttClient {
+    sender: mpsc::Sender<Message>,
+}
+
+impl MqttClient {
+    pub(crate) fn start(options: MqttOptions) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+
+        tokio::spawn(run_connection(options, receiver));
+
+        Self { sender }
+    }
+
+    pub(crate) fn publish(
+        &self,
+        topic: &str,
+        payload: impl Into<Vec<u8>>,
+        retain: bool,
+    ) -> Result<(), MqttError> {
+        self.sender
+            .try_send(Message {
+                topic: topic.to_string(),
+                payload: payload.into(),
+                retain,
+            })
+            .map_err(|_| MqttError::QueueFull)
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// The state of a connection that outlives reconnects
+struct Session {
+    inflight: BTreeMap<u16, Inflight>,
+    last_packet_id: u16,
+}
+
+impl Session {
+    fn next_packet_id(&mut self) -> u16 {
+        loop {
+            self.last_packet_id = self.last_packet_id.wrapping_add(1);
+
+            if self.last_packet_id != 0 && !self.inflight.contains_key(&self.last_packet_id) {
+                return self.last_packet_id;
+            }
+        }
+    }
+}
+
+/// Keep a connection to the broker and publish the queued messages, reconnecting on a backoff
+async fn run_connection(options: MqttOptions, mut receiver: mpsc::Receiver<Message>) {
+    let mut session = Session {
+        inflight: BTreeMap::new(),
+        last_packet_id: 0,
+    };
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        match connect(&options).await {
+            Ok(stream) => {
+                info!("connected to the MQTT broker at {}", options.host);
+                delay = Duration::from_secs(1);
+
+                match run_session(&options, stream, &mut receiver, &mut session).await {
+                    Ok(()) => return,
+                    Err(e) => warn!("{}", e),
+                }
+            }
+            Err(e) => warn!("{}, retrying in {} seconds", e, delay.as_secs()),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn connect(options: &MqttOptions) -> Result<Box<dyn Stream>, MqttError> {
+    let connect_error = |e: &dyn std::fmt::Display| MqttError::Connect(e.to_string());
+
+    let tcp = TcpStream::connect((options.host.as_str(), options.port))
+        .await
+        .map_err(|e| connect_error(&e))?;
+
+    let mut stream: Box<dyn Stream> = match &options.tls {
+        Some(tls) => Box::new(
+            tls.connect(&options.host, tcp)
+                .await
+                .map_err(|e| connect_error(&e))?,
+        ),
+        None => Box::new(tcp),
+    };
+
+    stream
+        .write_all(&encode_connect(options))
+        .await
+        .map_err(|e| connect_error(&e))?;
+
+    let mut connack = [0u8; 4];
+
+    tokio::time::timeout(options.keep_alive, stream.read_exact(&mut connack))
+        .await
+        .map_err(|_| connect_error(&"no acknowledgement of the broker"))?
+        .map_err(|e| connect_error(&e))?;
+
+    if connack[0] != CONNACK {
+        return Err(connect_error(&"unexpected response of the broker"));
+    }
+
+    match connack[3] {
+        0 => Ok(stream),
+        1 => Err(MqttError::Refused("unacceptable protocol version")),
+        2 => Err(MqttError::Refused("client identifier rejected")),
+        3 => Err(MqttError::Refused("server unavailable")),
+        4 => Err(MqttError::Refused("bad user name or password")),
+        5 => Err(MqttError::Refused("not authorized")),
+        _ => Err(MqttError::Refused("unknown reason")),
+    }
+}
+
+/// Publish queued messages until the connection fails, or return when the client is dropped.
+/// A ping is sent when nothing was sent for half the keep alive period, and the connection is
+/// considered lost when nothing was received for one and a half times the period.
+async fn run_session(
+    options: &MqttOptions,
+    mut stream: Box<dyn Stream>,
+    receiver: &mut mpsc::Receiver<Message>,
+    session: &mut Session,
+) -> Result<(), MqttError> {
+    let connection_error = |e: std::io::Error| MqttError::Connection(e.to_string());
+
+    // messages that were not acknowledged before the connection dropped
+    for (packet_id, inflight) in &session.inflight {
+        let packet = if inflight.received {
+            encode_acknowledgement(PUBREL, *packet_id)
+        } else {
+            encode_publish(&inflight.message, options.qos, Some(*packet_id), true)
+        };
+
+        stream.write_all(&packet).await.map_err(connection_error)?;
+    }
+
+    if let Some(topic) = &options.availability_topic {
+        let packet_id = (options.qos != Qos::AtMostOnce).then(|| session.next_packet_id());
+        let online = Message {
+            topic: topic.clone(),
+            payload: ONLINE.into(),
+            retain: true,
+        };
+
+        stream
+            .write_all(&encode_publish(&online, options.qos, packet_id, false))
+            .await
+            .map_err(connection_error)?;
+
+        if let Some(packet_id) = packet_id {
+            session.inflight.insert(
+                packet_id,
+                Inflight {
+                    message: online,
+                    received: false,
+                },
+            );
+        }
+    }
+
+    let mut last_sent = Instant::now();
+    let mut last_received = Instant::now();
+    let mut incoming = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    loop {
+        let ping_at = last_sent + options.keep_alive / 2;
+
+        if last_received.elapsed() > options.keep_alive * 3 / 2 {
+            return Err(MqttError::Connection(
+                "the broker stopped responding".to_string(),
+            ));
+        }
+
+        tokio::select! {
+            message = receiver.recv() => {
+                let Some(message) = message else {
+                    let _ = stream.write_all(&[DISCONNECT, 0]).await;
+                    return Ok(());
+                };
+
+                let packet_id = (options.qos != Qos::AtMostOnce).then(|| session.next_packet_id());
+
+                stream
+                    .write_all(&encode_publish(&message, options.qos, packet_id, false))
+                    .await
+                    .map_err(connection_error)?;
+                last_sent = Instant::now();
+
+                if let Some(packet_id) = packet_id {
+                    session.inflight.insert(packet_id, Inflight { message, received: false });
+                }
+            }
+            read = stream.read(&mut buffer) => {
+                match read.map_err(connection_error)? {
+                    0 => return Err(MqttError::Connection("closed by the broker".to_string())),
+                    read => {
+                        incoming.extend_from_slice(&buffer[..read]);
+                        last_received = Instant::now();
+                    }
+                }
+
+                while let Some((header, body)) = take_packet(&mut incoming) {
+                    let packet_id = body.get(..2).map(|id| u16::from_be_bytes([id[0], id[1]]));
+
+                    match (header & 0xf0, packet_id) {
+                        (PUBACK | PUBCOMP, Some(packet_id)) => {
+                            session.inflight.remove(&packet_id);
+                        }
+                        (PUBREC, Some(packet_id)) => {
+                            if let Some(inflight) = session.inflight.get_mut(&packet_id) {
+                                inflight.received = true;
+                            }
+
+                            stream
+                                .write_all(&encode_acknowledgement(PUBREL, packet_id))
+                                .await
+                                .map_err(connection_error)?;
+                            last_sent = Instant::now();
+                        }
+                        // ping responses only keep the connection alive
+                        _ => {}
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(ping_at) => {
+                stream.write_all(&[PINGREQ, 0]).await.map_err(connection_error)?;
+                last_sent = Instant::now();
+            }
+        }
+    }
+}
+
+fn encode_connect(options: &MqttOptions) -> Vec<u8> {
+    // a clean session unless the broker has to remember unacknowledged messages
+    let mut flags = if options.qos == Qos::AtMostOnce {
+        0x02
+    } else {
+        0x00
+    };
+
+    if options.availability_topic.is_some() {
+        flags |= 0x04 | (options.qos as u8) << 3 | 0x20;
+    }
+    if options.username.is_some() {
+        flags |= 0x80;
+    }
+    if options.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    encode_string(&mut body, "MQTT");
+    // protocol level 4 is MQTT 3.1.1
+    body.extend_from_slice(&[4, flags]);
+    body.extend_from_slice(
+        &(options.keep_alive.as_secs().min(u16::MAX as u64) as u16).to_be_bytes(),
+    );
+    encode_string(&mut body, &options.client_id);
+
+    if let Some(topic) = &options.availability_topic {
+        encode_string(&mut body, topic);
+        encode_string(&mut body, OFFLINE);
+    }
+    if let Some(username) = &options.username {
+        encode_string(&mut body, username);
+    }
+    if let Some(password) = &options.password {
+        encode_string(&mut body, password);
+    }
+
+    encode_packet(CONNECT, &body)
+}
+
+fn encode_publish(message: &Message, qos: Qos, packet_id: Option<u16>, dup: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, &message.topic);
+    if let Some(packet_id) = packet_id {
+        body.extend_from_slice(&packet_id.to_be_bytes());
+    }
+    body.extend_from_slice(&message.payload);
+
+    let mut header = PUBLISH | (qos as u8) << 1 | message.retain as u8;
+    if dup {
+        header |= DUP;
+    }
+
+    encode_packet(header, &body)
+}
+
+fn encode_acknowledgement(header: u8, packet_id: u16) -> Vec<u8> {
+    encode_packet(header, &packet_id.to_be_bytes())
+}
+
+fn encode_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+
+    // the remaining length is encoded in 7 bits per byte, the high bit marks continuation
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Remove the first packet from the received bytes, `None` until it is complete
+fn take_packet(incoming: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let mut length = 0;
+    let mut offset = 1;
+
+    loop {
+        let byte = *incoming.get(offset)?;
+        length |= ((byte & 0x7f) as usize) << (7 * (offset - 1));
+        offset += 1;
+
+        if byte & 0x80 == 0 || offset > 4 {
+            break;
+        }
+    }
+
+    if incoming.len() < offset + length {
+        return None;
+    }
+
+    let header = incoming[0];
+    let body = incoming[offset..offset + length].to_vec();
+    incoming.drain(..offset + length);
+
+    Some((header, body))
+}
+
+fn encode_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str, payload: &[u8], retain: bool) -> Message {
+        Message {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            retain,
+        }
+    }
+
+    #[test]
+    fn test_encode_publish() {
+        assert_eq!(
+            encode_publish(&message("a/b", b"on", true), Qos::AtMostOnce, None, false),
+            vec![0x31, 7, 0, 3, b'a', b'/', b'b', b'o', b'n']
+        );
+
+        assert_eq!(
+            encode_publish(&message("a", b"", false), Qos::AtLeastOnce, Some(5), true),
+            vec![0x3a, 5, 0, 1, b'a', 0, 5]
+        );
+
+        // a remaining length of 200 takes two bytes
+        let packet = encode_publish(
+            &message("t", &[0; 197], false),
+            Qos::AtMostOnce,
+            None,
+            false,
+        );
+        assert_eq!(&packet[..3], &[0x30, 0xc8, 0x01]);
+        assert_eq!(packet.len(), 203);
+    }
+
+    #[test]
+    fn test_take_packet() {
+        let mut incoming = vec![PUBACK, 2, 0, 7, 0xd0, 0, PUBREC, 2, 0];
+
+        assert_eq!(take_packet(&mut incoming), Some((PUBACK, vec![0, 7])));
+        assert_eq!(take_packet(&mut incoming), Some((0xd0, vec![])));
+        // incomplete
+        assert_eq!(take_packet(&mut incoming), None);
+
+        incoming.push(9);
+        assert_eq!(take_packet(&mut incoming), Some((PUBREC, vec![0, 9])));
+        assert!(incoming.is_empty());
+    }
+}
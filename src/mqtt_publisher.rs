@@ -0,0 +1,149 @@
+use chrono::{DateTime, DurationRound, FixedOffset, TimeDelta, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    domain::{start_of_day, PricePoint, PriceWindow},
+    home_assistant::Discovery,
+    optimizer::{costs, optimize, Contiguous},
+    price_level::current_price,
+    setup::AppState,
+};
+
+/// The price of an hour of the curve of a day
+#[derive(Debug, Clone, Serialize)]
+struct CurvePrice {
+    starts_at: DateTime<FixedOffset>,
+    price: f64,
+    currency: String,
+}
+
+/// The cheapest upcoming window of a duration
+#[derive(Debug, Clone, Serialize)]
+struct UpcomingWindow {
+    /// Whether the window has started
+    active: bool,
+    #[serde(flatten)]
+    window: PriceWindow,
+}
+
+/// Publish the current price with its level and SG-Ready state, the price curves of today and
+/// tomorrow and the cheapest upcoming window of every configured duration. Messages are
+/// retained unless configured otherwise, so subscribers receive the latest values when they
+/// connect. Home Assistant discovery messages are always retained and go first, so its
+/// entities exist before their states arrive.
+pub(crate) async fn publish_prices(state: &AppState) -> Result<(), String> {
+    let Some(mqtt) = &state.mqtt else {
+        return Ok(());
+    };
+
+    let topic = |name: &str| format!("{}/{}", mqtt.topic_prefix, name);
+    let publish = |name: &str, payload: String| {
+        mqtt.client
+            .publish(&topic(name), payload, mqtt.retain)
+            .map_err(|e| e.to_string())
+    };
+
+    let now = Utc::now();
+    let today = now.with_timezone(&state.timezone).date_naive();
+    let tomorrow = today + TimeDelta::days(1);
+
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, today),
+            start_of_day(&state.timezone, tomorrow + TimeDelta::days(1)),
+            state.price_area(None).as_deref(),
+        )
+        .await?;
+
+    // the unit of the price sensor needs the currency, which is only known with prices
+    if let (Some(discovery_prefix), Some(price)) = (&mqtt.discovery_prefix, prices.first()) {
+        let discovery = Discovery {
+            discovery_prefix,
+            topic_prefix: &mqtt.topic_prefix,
+            node_id: &mqtt.client_id,
+            availability_topic: mqtt.availability_topic.as_deref(),
+            window_durations: &mqtt.window_durations,
+            currency: &price.currency,
+        };
+
+        for (topic, config) in discovery.messages() {
+            mqtt.client
+                .publish(&topic, config.to_string(), true)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    if let Some(current_price) =
+        current_price(state, now, state.price_area(None).as_deref()).await?
+    {
+        let sg_ready_state = state.settings().sg_ready.state_of(current_price.level);
+
+        publish("price_level", current_price.level.as_str().to_string())?;
+        publish(
+            "sg_ready",
+            json!({ "state": sg_ready_state, "mode": sg_ready_state.mode() }).to_string(),
+        )?;
+        publish("current_price", to_json(&current_price)?)?;
+    }
+
+    let (prices_of_today, prices_of_tomorrow): (Vec<PricePoint>, Vec<PricePoint>) = prices
+        .iter()
+        .cloned()
+        .partition(|price| price.moment.with_timezone(&state.timezone).date_naive() == today);
+
+    publish("prices/today", to_json(&curve(state, &prices_of_today))?)?;
+    publish(
+        "prices/tomorrow",
+        to_json(&curve(state, &prices_of_tomorrow))?,
+    )?;
+
+    let current_hour = now.duration_trunc(TimeDelta::hours(1)).unwrap_or(now);
+    let upcoming_prices = prices
+        .into_iter()
+        .filter(|price| price.moment >= current_hour)
+        .collect::<Vec<PricePoint>>();
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(None));
+
+    for duration in &mqtt.window_durations {
+        let window = optimize(
+            &Contiguous {
+                duration: *duration,
+            },
+            &upcoming_prices,
+            price_cap,
+        )
+        .and_then(|windows| windows.into_iter().next())
+        .map(|window| UpcomingWindow {
+            active: window.starts_at <= now && now < window.ends_at,
+            window: window.with_timezone(state.timezone),
+        });
+
+        publish(&format!("windows/{}h", duration), to_json(&window)?)?;
+    }
+
+    Ok(())
+}
+
+fn curve(state: &AppState, prices: &[PricePoint]) -> Vec<CurvePrice> {
+    prices
+        .iter()
+        .zip(costs(prices, None))
+        .map(|(price, cost)| CurvePrice {
+            starts_at: price.moment.with_timezone(&state.timezone).fixed_offset(),
+            price: cost,
+            currency: price.currency.clone(),
+        })
+        .collect()
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
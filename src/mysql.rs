@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use sqlx::{migrate::Migrator, mysql::MySqlPoolOptions, MySqlPool};
+
+use crate::setup::{connect_with_retries, DatabaseConfiguration, Repositories};
+
+mod backup_repository;
+mod carbon_intensity_repository;
+mod consumption_repository;
+mod contract_repository;
+mod device_repository;
+mod exchange_rate_repository;
+mod notification_repository;
+mod planned_window_repository;
+mod price_repository;
+mod renewable_generation_repository;
+mod solar_forecast_repository;
+mod weather_repository;
+mod webhook_repository;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_mysql");
+
+/// Connect to the MySQL or MariaDB database of a `mysql:` DATABASE_URL and build the
+/// repositories on top of it
+pub(crate) async fn setup_repositories(
+    db_dsn: &str,
+    configuration: &DatabaseConfiguration,
+) -> Repositories {
+    let pool_options = MySqlPoolOptions::new()
+        .max_connections(configuration.max_connections.unwrap_or(16))
+        .acquire_timeout(configuration.acquire_timeout);
+
+    let pool = connect_with_retries(configuration, || pool_options.clone().connect(db_dsn))
+        .await
+        .expect("failed to create database pool");
+
+    MIGRATOR.run(&pool).await.expect("failed to run migrations");
+
+    repositories(pool)
+}
+
+fn repositories(db: MySqlPool) -> Repositories {
+    Repositories {
+        price: Arc::new(price_repository::MySqlPriceRepository::new(db.clone())),
+        exchange_rate: Arc::new(exchange_rate_repository::MySqlExchangeRateRepository::new(
+            db.clone(),
+        )),
+        device: Arc::new(device_repository::MySqlDeviceRepository::new(db.clone())),
+        planned_window: Arc::new(
+            planned_window_repository::MySqlPlannedWindowRepository::new(db.clone()),
+        ),
+        notification: Arc::new(notification_repository::MySqlNotificationRepository::new(
+            db.clone(),
+        )),
+        webhook: Arc::new(webhook_repository::MySqlWebhookRepository::new(db.clone())),
+        backup: Arc::new(backup_repository::MySqlBackupRepository::new(db.clone())),
+        carbon_intensity: Arc::new(
+            carbon_intensity_repository::MySqlCarbonIntensityRepository::new(db.clone()),
+        ),
+        solar_forecast: Arc::new(
+            solar_forecast_repository::MySqlSolarForecastRepository::new(db.clone()),
+        ),
+        renewable_generation: Arc::new(
+            renewable_generation_repository::MySqlRenewableGenerationRepository::new(db.clone()),
+        ),
+        weather: Arc::new(weather_repository::MySqlWeatherRepository::new(db.clone())),
+        consumption: Arc::new(consumption_repository::MySqlConsumptionRepository::new(
+            db.clone(),
+        )),
+        contract: Arc::new(contract_repository::MySqlContractRepository::new(db)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeDelta};
+
+    use super::*;
+    use crate::domain::{ExchangeRate, PricePoint};
+    use crate::test_support::{moment, price_point};
+
+    /// The repositories of the database at `MYSQL_TEST_DATABASE_URL`, of which the prices are
+    /// removed first, so it must not hold prices that are to be kept
+    async fn test_repositories() -> Repositories {
+        let url = std::env::var("MYSQL_TEST_DATABASE_URL")
+            .expect("MYSQL_TEST_DATABASE_URL must point to a MySQL or MariaDB database");
+        let pool = MySqlPoolOptions::new().connect(&url).await.unwrap();
+
+        MIGRATOR.run(&pool).await.unwrap();
+
+        for table in ["price_versions", "prices"] {
+            sqlx::query(&format!("delete from {}", table))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        repositories(pool)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MySQL or MariaDB database at MYSQL_TEST_DATABASE_URL"]
+    async fn test_persist_prices() {
+        let repositories = test_repositories().await;
+
+        let prices = (0..3)
+            .map(|hour| PricePoint {
+                currency: "SEK".to_string(),
+                ..price_point(
+                    moment("2024-06-30T08:00:00Z") + TimeDelta::hours(hour),
+                    0.1 * hour as f64,
+                )
+            })
+            .collect::<Vec<PricePoint>>();
+
+        repositories
+            .price
+            .persist_prices(&prices, "tibber")
+            .await
+            .unwrap();
+        // persisting again replaces the prices, of which a repeated moment keeps the last
+        let repeated = [
+            &prices[1..],
+            &[PricePoint {
+                monetary_amount: 0.5,
+                ..prices[2].clone()
+            }],
+        ]
+        .concat();
+
+        repositories
+            .price
+            .persist_prices(&repeated, "tibber")
+            .await
+            .unwrap();
+
+        let stored = repositories
+            .price
+            .fetch_prices(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].moment, moment("2024-06-30T09:00:00Z"));
+        assert_eq!(stored[0].currency, "SEK");
+        assert_eq!(stored[1].monetary_amount, 0.5);
+
+        // the unchanged price keeps its version, the corrected one gets another
+        let versions = repositories
+            .price
+            .fetch_price_versions(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-06-30T11:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|version| (version.version, version.price.monetary_amount))
+            .collect::<Vec<(i32, f64)>>();
+
+        assert_eq!(versions, vec![(1, 0.1), (1, 0.2), (2, 0.5)]);
+
+        // the moment, provider and area are the key of a price, so the prices of another area
+        // are kept apart
+        repositories
+            .price
+            .persist_prices(
+                &[PricePoint {
+                    area: Some("SE3".to_string()),
+                    ..prices[1].clone()
+                }],
+                "tibber",
+            )
+            .await
+            .unwrap();
+
+        let area_prices = repositories
+            .price
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                Some("SE3"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(area_prices.len(), 1);
+        assert_eq!(area_prices[0].area.as_deref(), Some("SE3"));
+        assert_eq!(
+            repositories
+                .price
+                .fetch_prices(
+                    moment("2024-06-30T00:00:00Z"),
+                    moment("2024-07-01T00:00:00Z"),
+                    None,
+                )
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MySQL or MariaDB database at MYSQL_TEST_DATABASE_URL"]
+    async fn test_persist_exchange_rates() {
+        let repositories = test_repositories().await;
+
+        // the history of a handful of currencies binds more parameters than a single statement
+        // may hold
+        let first = NaiveDate::from_ymd_opt(1999, 1, 4).unwrap();
+        let rates = ["USD", "SEK", "NOK", "DKK", "GBP"]
+            .iter()
+            .flat_map(|currency| {
+                (0..5000).map(move |day| ExchangeRate {
+                    date: first + TimeDelta::days(day),
+                    currency: currency.to_string(),
+                    rate: 1.0 + day as f64 / 10000.0,
+                })
+            })
+            .collect::<Vec<ExchangeRate>>();
+
+        assert!(rates.len() > 22_000);
+
+        repositories
+            .exchange_rate
+            .persist_rates(&rates)
+            .await
+            .unwrap();
+
+        let rate = repositories
+            .exchange_rate
+            .fetch_rate("NOK", first + TimeDelta::days(4999))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rate.rate, 1.4999);
+    }
+}
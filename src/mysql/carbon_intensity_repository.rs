@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, QueryBuilder};
+use tracing::info;
+
+use crate::{
+    carbon_intensity_repository::{CarbonIntensityRepository, CarbonIntensityRepositoryError},
+    domain::CarbonIntensity,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlCarbonIntensityRepository {
+    db: MySqlPool,
+}
+
+impl MySqlCarbonIntensityRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl CarbonIntensityRepository for MySqlCarbonIntensityRepository {
+    async fn fetch_carbon_intensity(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<CarbonIntensity>, String> {
+        sqlx::query_as::<_, CarbonIntensity>(
+            r#"
+            select moment, zone, intensity, forecast
+            from carbon_intensity
+            where zone = ? and moment >= ? and moment < ?
+            order by moment
+            "#,
+        )
+        .bind(zone)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_carbon_intensity(
+        &self,
+        intensities: &[CarbonIntensity],
+    ) -> Result<(), CarbonIntensityRepositoryError> {
+        if intensities.is_empty() {
+            return Ok(());
+        }
+
+        // a moment that is both measured and forecast keeps the measured intensity
+        let mut unique = BTreeMap::new();
+
+        for intensity in intensities {
+            unique
+                .entry((intensity.moment, &intensity.zone))
+                .and_modify(|kept: &mut &CarbonIntensity| {
+                    if kept.forecast {
+                        *kept = intensity;
+                    }
+                })
+                .or_insert(intensity);
+        }
+
+        info!("Persisting {} carbon intensities", unique.len());
+
+        let mut query_builder =
+            QueryBuilder::new("insert into carbon_intensity (moment, zone, intensity, forecast)");
+
+        query_builder.push_values(unique.values(), |mut builder, intensity| {
+            builder
+                .push_bind(intensity.moment)
+                .push_bind(&intensity.zone)
+                .push_bind(intensity.intensity)
+                .push_bind(intensity.forecast);
+        });
+
+        query_builder.push(
+            r#"
+            on duplicate key update
+            intensity = if(forecast or not values(forecast), values(intensity), intensity),
+            forecast  = forecast and values(forecast)
+            "#,
+        );
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| CarbonIntensityRepositoryError::PersistenceError(e.to_string()))
+    }
+}
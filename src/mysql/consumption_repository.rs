@@ -0,0 +1,163 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, QueryBuilder};
+use tracing::info;
+
+use crate::{
+    consumption_repository::{
+        ConsumptionRepository, ConsumptionRepositoryError, CONSUMPTION_BATCH_SIZE,
+    },
+    domain::{Consumption, DeviceConsumption},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlConsumptionRepository {
+    db: MySqlPool,
+}
+
+impl MySqlConsumptionRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ConsumptionRepository for MySqlConsumptionRepository {
+    async fn fetch_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Consumption>, String> {
+        sqlx::query_as::<_, Consumption>(
+            r#"
+            select starts_at, ends_at, energy_kwh, exported_kwh
+            from consumption
+            where starts_at >= ? and starts_at < ?
+            order by starts_at
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_consumption(
+        &self,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        if readings.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting {} consumption readings", readings.len());
+
+        let error = |e: sqlx::Error| ConsumptionRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in readings.chunks(CONSUMPTION_BATCH_SIZE) {
+            let mut query_builder = QueryBuilder::new(
+                "insert into consumption (starts_at, ends_at, energy_kwh, exported_kwh)",
+            );
+
+            query_builder.push_values(batch, |mut builder, reading| {
+                builder
+                    .push_bind(reading.starts_at)
+                    .push_bind(reading.ends_at)
+                    .push_bind(reading.energy_kwh)
+                    .push_bind(reading.exported_kwh);
+            });
+
+            query_builder.push(
+                r#"
+                on duplicate key update
+                ends_at = values(ends_at),
+                    energy_kwh = values(energy_kwh),
+                    exported_kwh = values(exported_kwh)
+                "#,
+            );
+
+            query_builder
+                .build()
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+
+    async fn fetch_device_consumption(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        device_id: Option<i64>,
+    ) -> Result<Vec<DeviceConsumption>, String> {
+        sqlx::query_as::<_, DeviceConsumption>(
+            r#"
+            select device_id, starts_at, ends_at, energy_kwh, 0e0 as exported_kwh
+            from device_consumption
+            where starts_at >= ? and starts_at < ? and (? is null or device_id = ?)
+            order by device_id, starts_at
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(device_id)
+        .bind(device_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_device_consumption(
+        &self,
+        device_id: i64,
+        readings: &[Consumption],
+    ) -> Result<(), ConsumptionRepositoryError> {
+        if readings.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Persisting {} consumption readings of device {}",
+            readings.len(),
+            device_id
+        );
+
+        let error = |e: sqlx::Error| ConsumptionRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in readings.chunks(CONSUMPTION_BATCH_SIZE) {
+            let mut query_builder = QueryBuilder::new(
+                "insert into device_consumption (device_id, starts_at, ends_at, energy_kwh)",
+            );
+
+            query_builder.push_values(batch, |mut builder, reading| {
+                builder
+                    .push_bind(device_id)
+                    .push_bind(reading.starts_at)
+                    .push_bind(reading.ends_at)
+                    .push_bind(reading.energy_kwh);
+            });
+
+            query_builder.push(
+                r#"
+                on duplicate key update
+                ends_at = values(ends_at), energy_kwh = values(energy_kwh)
+                "#,
+            );
+
+            query_builder
+                .build()
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+}
@@ -0,0 +1,77 @@
+use axum::async_trait;
+use chrono::NaiveDate;
+use sqlx::{MySqlPool, QueryBuilder};
+use tracing::info;
+
+use crate::{
+    domain::ExchangeRate,
+    exchange_rate_repository::{
+        ExchangeRateRepository, ExchangeRateRepositoryError, RATE_BATCH_SIZE,
+    },
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlExchangeRateRepository {
+    db: MySqlPool,
+}
+
+impl MySqlExchangeRateRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ExchangeRateRepository for MySqlExchangeRateRepository {
+    async fn fetch_rate(
+        &self,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<ExchangeRate>, String> {
+        sqlx::query_as::<_, ExchangeRate>(
+            "select date, currency, rate from exchange_rates where currency = ? and date <= ? order by date desc limit 1",
+        )
+        .bind(currency)
+        .bind(date)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_rates(
+        &self,
+        rates: &[ExchangeRate],
+    ) -> Result<(), ExchangeRateRepositoryError> {
+        if rates.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting {} exchange rates", rates.len());
+
+        let error = |e: sqlx::Error| ExchangeRateRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        for batch in rates.chunks(RATE_BATCH_SIZE) {
+            let mut query_builder =
+                QueryBuilder::new("insert into exchange_rates (date, currency, rate)");
+
+            query_builder.push_values(batch, |mut builder, rate| {
+                builder
+                    .push_bind(rate.date)
+                    .push_bind(&rate.currency)
+                    .push_bind(rate.rate);
+            });
+
+            query_builder.push(" on duplicate key update rate = values(rate)");
+
+            query_builder
+                .build()
+                .execute(&mut *transaction)
+                .await
+                .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)
+    }
+}
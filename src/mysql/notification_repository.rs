@@ -0,0 +1,196 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, FromRow, MySqlPool};
+
+use crate::{
+    notification::{NotificationChannel, NotificationEvent, NotificationRule},
+    notification_repository::{
+        NewNotificationRule, NotificationRepository, NotificationRepositoryError,
+    },
+};
+
+/// A notification rule as stored, of which the durations are a json array
+#[derive(Debug, FromRow)]
+struct NotificationRuleRow {
+    id: i64,
+    #[sqlx(try_from = "String")]
+    event: NotificationEvent,
+    device_id: Option<i64>,
+    lead_minutes: i32,
+    enabled: bool,
+    #[sqlx(try_from = "String")]
+    channel: NotificationChannel,
+    hour: Option<i32>,
+    threshold: Option<f64>,
+    template: Option<String>,
+    level: Option<String>,
+    duration: Option<i32>,
+    durations: Option<Json<Vec<i32>>>,
+    schedule: Option<String>,
+}
+
+impl From<NotificationRuleRow> for NotificationRule {
+    fn from(row: NotificationRuleRow) -> Self {
+        NotificationRule {
+            id: row.id,
+            event: row.event,
+            device_id: row.device_id,
+            lead_minutes: row.lead_minutes,
+            enabled: row.enabled,
+            channel: row.channel,
+            hour: row.hour,
+            threshold: row.threshold,
+            template: row.template,
+            level: row.level,
+            duration: row.duration,
+            durations: row.durations.map(|durations| durations.0),
+            schedule: row.schedule,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlNotificationRepository {
+    db: MySqlPool,
+}
+
+impl MySqlNotificationRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+
+    async fn fetch_rule(&self, id: i64) -> Result<Option<NotificationRule>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationRuleRow>(
+            r#"
+            select id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            from notification_rules
+            where id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+        .map(|row| row.map(NotificationRule::from))
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for MySqlNotificationRepository {
+    async fn fetch_rules(&self) -> Result<Vec<NotificationRule>, String> {
+        sqlx::query_as::<_, NotificationRuleRow>(
+            r#"
+            select id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            from notification_rules
+            order by id
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map(|rows| rows.into_iter().map(NotificationRule::from).collect())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_rule(
+        &self,
+        rule: &NewNotificationRule,
+    ) -> Result<NotificationRule, NotificationRepositoryError> {
+        let error = |e: sqlx::Error| NotificationRepositoryError::PersistenceError(e.to_string());
+
+        let id = sqlx::query(
+            r#"
+            insert into notification_rules
+                (event, device_id, lead_minutes, channel, hour, threshold, template, level,
+                 duration, durations, schedule)
+            values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(rule.event.as_str())
+        .bind(rule.device_id)
+        .bind(rule.lead_minutes)
+        .bind(rule.channel.as_str())
+        .bind(rule.hour)
+        .bind(rule.threshold)
+        .bind(&rule.template)
+        .bind(rule.level.map(|level| level.as_str()))
+        .bind(rule.duration)
+        .bind(rule.durations.as_ref().map(Json))
+        .bind(&rule.schedule)
+        .execute(&self.db)
+        .await
+        .map_err(error)?
+        .last_insert_id();
+
+        // MySQL cannot return the inserted row
+        self.fetch_rule(id as i64).await.map_err(error)?.ok_or(
+            NotificationRepositoryError::PersistenceError(format!(
+                "notification rule {} disappeared after it was inserted",
+                id
+            )),
+        )
+    }
+
+    async fn set_rule_enabled(
+        &self,
+        id: i64,
+        enabled: bool,
+    ) -> Result<Option<NotificationRule>, NotificationRepositoryError> {
+        let error = |e: sqlx::Error| NotificationRepositoryError::PersistenceError(e.to_string());
+
+        sqlx::query("update notification_rules set enabled = ? where id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map_err(error)?;
+
+        self.fetch_rule(id).await.map_err(error)
+    }
+
+    async fn delete_rule(&self, id: i64) -> Result<bool, NotificationRepositoryError> {
+        sqlx::query("delete from notification_rules where id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn mark_sent(
+        &self,
+        rule_id: i64,
+        device_id: Option<i64>,
+        starts_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        sqlx::query(
+            r#"
+            insert ignore into sent_notifications (rule_id, device_id, starts_at)
+            values (?, ?, ?)
+            "#,
+        )
+        .bind(rule_id)
+        .bind(device_id)
+        .bind(starts_at)
+        .execute(&self.db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_last_sent(&self, rule_id: i64) -> Result<Option<DateTime<Utc>>, String> {
+        sqlx::query_scalar(
+            r#"
+            select starts_at
+            from sent_notifications
+            where rule_id = ?
+            order by sent_at desc
+            limit 1
+            "#,
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,134 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, MySqlPool, QueryBuilder};
+
+use crate::planned_window_repository::{
+    CalendarEvent, NewPlannedWindow, PlannedWindow, PlannedWindowRepository,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlPlannedWindowRepository {
+    db: MySqlPool,
+}
+
+impl MySqlPlannedWindowRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl PlannedWindowRepository for MySqlPlannedWindowRepository {
+    async fn persist_planned_windows(
+        &self,
+        windows: &[NewPlannedWindow<'_>],
+    ) -> Result<(), String> {
+        if windows.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(
+            "insert ignore into planned_windows (source, device_id, starts_at, ends_at, average_price, currency, inputs)",
+        );
+
+        query_builder.push_values(windows, |mut builder, planned| {
+            builder
+                .push_bind(planned.source)
+                .push_bind(planned.device_id)
+                .push_bind(planned.window.starts_at.to_utc())
+                .push_bind(planned.window.ends_at.to_utc())
+                .push_bind(&planned.window.average_price)
+                .push_bind(&planned.window.currency)
+                .push_bind(Json(planned.inputs));
+        });
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_planned_windows(
+        &self,
+        device_id: Option<i64>,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<PlannedWindow>, String> {
+        sqlx::query_as::<_, PlannedWindow>(
+            r#"
+            select id, created_at, source, device_id, starts_at, ends_at, average_price, currency, inputs
+            from planned_windows
+            where (? is null or device_id = ?)
+              and starts_at >= ?
+              and starts_at < ?
+            order by starts_at, created_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(device_id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_calendar_events(
+        &self,
+        after: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>, String> {
+        sqlx::query_as::<_, CalendarEvent>(
+            r#"
+            select event_id, device_id, schedule_id, date, starts_at, ends_at
+            from calendar_events
+            where ends_at > ?
+            order by starts_at
+            "#,
+        )
+        .bind(after)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_calendar_event(&self, event: &CalendarEvent) -> Result<(), String> {
+        sqlx::query(
+            r#"
+            insert into calendar_events (event_id, device_id, schedule_id, date, starts_at, ends_at)
+            values (?, ?, ?, ?, ?, ?)
+            on duplicate key update
+            ends_at = values(ends_at)
+            "#,
+        )
+        .bind(&event.event_id)
+        .bind(event.device_id)
+        .bind(event.schedule_id)
+        .bind(event.date)
+        .bind(event.starts_at)
+        .bind(event.ends_at)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete_calendar_event(&self, event_id: &str) -> Result<(), String> {
+        sqlx::query("delete from calendar_events where event_id = ?")
+            .bind(event_id)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn prune_planned_windows(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        sqlx::query("delete from planned_windows where starts_at < ?")
+            .bind(before)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,450 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, QueryBuilder};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
+
+use crate::{
+    domain::{PriceFetch, PricePoint, PriceStatistics},
+    price_repository::{
+        areas_of, price_gaps, unique_prices, DeletedPrices, IngestedPrices, PriceGap,
+        PriceRepository, PriceRepositoryError, PriceRow, PriceVersion, PriceVersionRow, Provider,
+    },
+};
+
+/// How many persisted batches of prices a slow listener may lag behind
+const INGESTED_PRICES_CAPACITY: usize = 16;
+
+/// Whether a price that is persisted again is the same as the stored one
+const UNCHANGED_PRICE: &str = "price <=> values(price) \
+    and consumer_price <=> values(consumer_price) \
+    and supplier_fee <=> values(supplier_fee) \
+    and energy_tax <=> values(energy_tax) \
+    and grid_fee <=> values(grid_fee) \
+    and vat <=> values(vat) \
+    and currency <=> values(currency) \
+    and unit <=> values(unit) \
+    and resolution_minutes <=> values(resolution_minutes)";
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlPriceRepository {
+    db: MySqlPool,
+    /// MySQL cannot notify other connections, so only the prices persisted by this instance
+    /// are announced
+    ingested: broadcast::Sender<IngestedPrices>,
+}
+
+impl MySqlPriceRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self {
+            db,
+            ingested: broadcast::channel(INGESTED_PRICES_CAPACITY).0,
+        }
+    }
+
+    async fn fetch_provider(&self, provider_name: &str) -> Result<Provider, PriceRepositoryError> {
+        sqlx::query_as("select id, name from providers where name = ? limit 1")
+            .bind(provider_name)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?
+            .ok_or_else(|| PriceRepositoryError::UnknownProvider(provider_name.to_string()))
+    }
+}
+
+#[async_trait]
+impl PriceRepository for MySqlPriceRepository {
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String> {
+        let rows = sqlx::query_as::<_, PriceRow>(
+            r#"
+            select moment, price, consumer_price, supplier_fee, energy_tax, grid_fee, vat,
+                   prices.currency, unit, resolution_minutes, area
+            from prices
+            join providers on providers.id = prices.provider_id
+            where moment >= ? and moment < ? and (? is null or area = ?)
+            order by moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(rows.into_iter().map(PricePoint::from).collect())
+    }
+
+    async fn persist_prices(
+        &self,
+        prices: &[PricePoint],
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        if prices.is_empty() {
+            return Ok(());
+        }
+
+        let provider = self.fetch_provider(provider_name).await?;
+
+        let unique = unique_prices(prices);
+
+        info!("Persisting {} prices for {}", unique.len(), provider.name);
+
+        let error = |e: sqlx::Error| PriceRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        let ingested_at = Utc::now();
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into prices (moment, price, consumer_price, supplier_fee, energy_tax, grid_fee, vat, currency, unit, resolution_minutes, area, provider_id, ingested_at)",
+        );
+
+        query_builder.push_values(unique.iter(), |mut builder, price| {
+            builder
+                .push_bind(price.moment)
+                .push_bind(price.monetary_amount)
+                .push_bind(price.consumer_amount)
+                .push_bind(price.components.as_ref().map(|c| c.supplier_fee))
+                .push_bind(price.components.as_ref().map(|c| c.energy_tax))
+                .push_bind(price.components.as_ref().map(|c| c.grid_fee))
+                .push_bind(price.components.as_ref().map(|c| c.vat))
+                .push_bind(&price.currency)
+                .push_bind(price.unit.as_str())
+                .push_bind(price.resolution_minutes)
+                .push_bind(price.area.as_deref().unwrap_or_default())
+                .push_bind(provider.id)
+                .push_bind(ingested_at);
+        });
+
+        // a price that is fetched again unchanged keeps its version. The version is assigned
+        // before the columns it compares, as the assignments are applied in order.
+        query_builder.push(format!(
+            r#"
+            on duplicate key update
+                version            = if({UNCHANGED_PRICE}, version, version + 1),
+                ingested_at        = if({UNCHANGED_PRICE}, ingested_at, values(ingested_at)),
+                price              = values(price),
+                consumer_price     = values(consumer_price),
+                supplier_fee       = values(supplier_fee),
+                energy_tax         = values(energy_tax),
+                grid_fee           = values(grid_fee),
+                vat                = values(vat),
+                currency           = values(currency),
+                unit               = values(unit),
+                resolution_minutes = values(resolution_minutes)
+            "#,
+        ));
+
+        query_builder
+            .build()
+            .execute(&mut *transaction)
+            .await
+            .map_err(error)?;
+
+        let ingested = IngestedPrices {
+            provider: provider.name,
+            prices: unique.len(),
+            first_moment: prices
+                .iter()
+                .map(|price| price.moment)
+                .min()
+                .unwrap_or_default(),
+            last_moment: prices
+                .iter()
+                .map(|price| price.moment)
+                .max()
+                .unwrap_or_default(),
+        };
+
+        // the versions of the prices that are new or corrected are not kept yet, of the areas of
+        // the prices only as a provider can serve several
+        for area in areas_of(&unique) {
+            sqlx::query(
+                r#"
+                insert ignore into price_versions (moment, provider_id, area, version, price,
+                                                   consumer_price, supplier_fee, energy_tax,
+                                                   grid_fee, vat, currency, unit,
+                                                   resolution_minutes, ingested_at)
+                select moment, provider_id, area, version, price, consumer_price, supplier_fee,
+                       energy_tax, grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+                from prices
+                where provider_id = ? and moment >= ? and moment <= ? and area <=> ?
+                "#,
+            )
+            .bind(provider.id)
+            .bind(ingested.first_moment)
+            .bind(ingested.last_moment)
+            .bind(area)
+            .execute(&mut *transaction)
+            .await
+            .map_err(error)?;
+        }
+
+        transaction.commit().await.map_err(error)?;
+
+        // there is no one to notify while nothing listens
+        let _ = self.ingested.send(ingested);
+
+        Ok(())
+    }
+
+    async fn record_price_fetch(
+        &self,
+        fetch: &PriceFetch,
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        let provider = self.fetch_provider(provider_name).await?;
+
+        sqlx::query(
+            r#"
+            insert into price_fetches (provider_id, area, date, attempted_at, attempts, prices,
+                                       error)
+            values (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(fetch.area.as_deref().unwrap_or_default())
+        .bind(fetch.date)
+        .bind(fetch.attempted_at)
+        .bind(fetch.attempts)
+        .bind(fetch.prices)
+        .bind(&fetch.error)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_price_statistics(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<PriceStatistics, String> {
+        sqlx::query_as::<_, PriceStatistics>(
+            r#"
+            select count(*)                                      as hours,
+            min(coalesce(prices.consumer_price, prices.price)) as minimum,
+            max(coalesce(prices.consumer_price, prices.price)) as maximum,
+            avg(coalesce(prices.consumer_price, prices.price)) as average,
+            sum(coalesce(prices.consumer_price, prices.price)) as sum,
+            max(prices.currency)                               as currency
+            from prices
+            where moment >= ? and moment < ? and (? is null or area = ?)
+            group by prices.currency
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(PriceStatistics::of_single_currency)
+    }
+
+    async fn fetch_price_versions(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceVersion>, String> {
+        let rows = sqlx::query_as::<_, PriceVersionRow>(
+            r#"
+            select providers.name as provider, version, ingested_at, moment, price,
+                   consumer_price, supplier_fee, energy_tax, grid_fee, vat,
+                   price_versions.currency, unit, resolution_minutes, area
+            from price_versions
+            join providers on providers.id = price_versions.provider_id
+            where moment >= ? and moment < ? and (? is null or area = ?)
+            order by moment, providers.name, area, version
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(rows.into_iter().map(PriceVersion::from).collect())
+    }
+
+    async fn fetch_price_moments(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            r#"
+            select distinct providers.name, moment
+            from prices
+            join providers on providers.id = prices.provider_id
+            where moment >= ? and moment < ? and (? is null or area = ?)
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_price_gaps(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceGap>, String> {
+        let providers = sqlx::query_as::<_, (String,)>(
+            r#"
+            select name
+            from providers
+            where exists (select 1
+                          from prices
+                          where prices.provider_id = providers.id
+                            and (? is null or prices.area <=> ?))
+            "#,
+        )
+        .bind(area)
+        .bind(area)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(name,)| name)
+        .collect::<Vec<String>>();
+
+        let stored = self
+            .fetch_price_moments(start_moment, end_moment, area)
+            .await?;
+
+        Ok(price_gaps(start_moment, end_moment, &providers, &stored))
+    }
+
+    async fn delete_prices(
+        &self,
+        provider_name: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<u64, PriceRepositoryError> {
+        let provider = self.fetch_provider(provider_name).await?;
+
+        let error = |e: sqlx::Error| PriceRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        sqlx::query(
+            r#"
+            delete from price_versions
+            where provider_id = ? and moment >= ? and moment < ? and (? is null or area = ?)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .execute(&mut *transaction)
+        .await
+        .map_err(error)?;
+
+        let deleted = sqlx::query(
+            r#"
+            delete from prices
+            where provider_id = ? and moment >= ? and moment < ? and (? is null or area = ?)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .bind(area)
+        .execute(&mut *transaction)
+        .await
+        .map_err(error)?
+        .rows_affected();
+
+        transaction.commit().await.map_err(error)?;
+
+        Ok(deleted)
+    }
+
+    async fn prune_prices(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::query("delete from price_versions where moment < ?")
+            .bind(before)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let pruned = sqlx::query("delete from prices where moment < ?")
+            .bind(before)
+            .execute(&mut *transaction)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(pruned)
+    }
+
+    async fn prune_price_fetches(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        sqlx::query("delete from price_fetches where attempted_at < ?")
+            .bind(before)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn create_price_partitions(&self, _moments: &[DateTime<Utc>]) -> Result<(), String> {
+        // prices are not partitioned
+        Ok(())
+    }
+
+    async fn listen_for_ingested_prices(&self) -> Result<mpsc::Receiver<IngestedPrices>, String> {
+        let mut ingestions = self.ingested.subscribe();
+
+        let (sender, receiver) = mpsc::channel(INGESTED_PRICES_CAPACITY);
+
+        tokio::spawn(async move {
+            loop {
+                let ingested = match ingestions.recv().await {
+                    Ok(ingested) => ingested,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("missed {} notifications of ingested prices", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                if sender.send(ingested).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    async fn listen_for_deleted_prices(&self) -> Result<mpsc::Receiver<DeletedPrices>, String> {
+        // MySQL has no notifications, so prices deleted by other instances are not received
+        Ok(mpsc::channel(1).1)
+    }
+}
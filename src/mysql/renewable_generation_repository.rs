@@ -0,0 +1,88 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, QueryBuilder};
+use tracing::info;
+
+use crate::{
+    domain::RenewableGeneration,
+    renewable_generation_repository::{
+        RenewableGenerationRepository, RenewableGenerationRepositoryError,
+    },
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlRenewableGenerationRepository {
+    db: MySqlPool,
+}
+
+impl MySqlRenewableGenerationRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RenewableGenerationRepository for MySqlRenewableGenerationRepository {
+    async fn fetch_renewable_generation(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<RenewableGeneration>, String> {
+        sqlx::query_as::<_, RenewableGeneration>(
+            r#"
+            select moment, zone, solar_mw, wind_mw, load_mw
+            from renewable_generation
+            where zone = ? and moment >= ? and moment < ?
+            order by moment
+            "#,
+        )
+        .bind(zone)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_renewable_generation(
+        &self,
+        generation: &[RenewableGeneration],
+    ) -> Result<(), RenewableGenerationRepositoryError> {
+        if generation.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Persisting the renewable generation of {} hours",
+            generation.len()
+        );
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into renewable_generation (moment, zone, solar_mw, wind_mw, load_mw)",
+        );
+
+        query_builder.push_values(generation, |mut builder, hour| {
+            builder
+                .push_bind(hour.moment)
+                .push_bind(&hour.zone)
+                .push_bind(hour.solar_mw)
+                .push_bind(hour.wind_mw)
+                .push_bind(hour.load_mw);
+        });
+
+        query_builder.push(
+            r#"
+            on duplicate key update
+            solar_mw = values(solar_mw), wind_mw = values(wind_mw), load_mw = values(load_mw)
+            "#,
+        );
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| RenewableGenerationRepositoryError::PersistenceError(e.to_string()))
+    }
+}
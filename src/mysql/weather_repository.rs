@@ -0,0 +1,79 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{MySqlPool, QueryBuilder};
+use tracing::info;
+
+use crate::{
+    weather::Weather,
+    weather_repository::{WeatherRepository, WeatherRepositoryError},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlWeatherRepository {
+    db: MySqlPool,
+}
+
+impl MySqlWeatherRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl WeatherRepository for MySqlWeatherRepository {
+    async fn fetch_weather(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Weather>, String> {
+        sqlx::query_as::<_, Weather>(
+            r#"
+            select moment, temperature_celsius, irradiance, tilted_irradiance
+            from weather
+            where moment >= ? and moment < ?
+            order by moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_weather(&self, weather: &[Weather]) -> Result<(), WeatherRepositoryError> {
+        if weather.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting the weather of {} hours", weather.len());
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into weather (moment, temperature_celsius, irradiance, tilted_irradiance)",
+        );
+
+        query_builder.push_values(weather, |mut builder, hour| {
+            builder
+                .push_bind(hour.moment)
+                .push_bind(hour.temperature_celsius)
+                .push_bind(hour.irradiance)
+                .push_bind(hour.tilted_irradiance);
+        });
+
+        query_builder.push(
+            r#"
+            on duplicate key update
+            temperature_celsius = values(temperature_celsius),
+                irradiance = values(irradiance),
+                tilted_irradiance = values(tilted_irradiance)
+            "#,
+        );
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| WeatherRepositoryError::PersistenceError(e.to_string()))
+    }
+}
@@ -0,0 +1,294 @@
+use axum::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::{types::Json, FromRow, MySqlPool, QueryBuilder};
+
+use crate::{
+    webhook::{
+        DeliveryAttempt, DeliveryStatus, DueDelivery, Webhook, WebhookDelivery, WebhookEvent,
+    },
+    webhook_repository::{NewDelivery, NewWebhook, WebhookRepository, WebhookRepositoryError},
+};
+
+/// A webhook as stored, of which the events are a json array
+#[derive(Debug, FromRow)]
+struct WebhookRow {
+    id: i64,
+    url: String,
+    events: Json<Vec<String>>,
+}
+
+impl From<WebhookRow> for Webhook {
+    /// Events that are no longer known are left out
+    fn from(row: WebhookRow) -> Self {
+        Webhook {
+            id: Some(row.id),
+            url: row.url,
+            events: row
+                .events
+                .iter()
+                .filter_map(|event| event.parse().ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlWebhookRepository {
+    db: MySqlPool,
+}
+
+impl MySqlWebhookRepository {
+    pub fn new(db: MySqlPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MySqlWebhookRepository {
+    async fn fetch_webhooks(&self) -> Result<Vec<Webhook>, String> {
+        sqlx::query_as::<_, WebhookRow>("select id, url, events from webhooks order by id")
+            .fetch_all(&self.db)
+            .await
+            .map(|rows| rows.into_iter().map(Webhook::from).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn persist_webhook(
+        &self,
+        webhook: &NewWebhook,
+    ) -> Result<Webhook, WebhookRepositoryError> {
+        sqlx::query("insert into webhooks (url, events, secret) values (?, ?, ?)")
+            .bind(&webhook.url)
+            .bind(Json(
+                webhook
+                    .events
+                    .iter()
+                    .map(WebhookEvent::as_str)
+                    .collect::<Vec<&str>>(),
+            ))
+            .bind(&webhook.secret)
+            .execute(&self.db)
+            .await
+            .map(|result| Webhook {
+                id: Some(result.last_insert_id() as i64),
+                url: webhook.url.clone(),
+                events: webhook.events.clone(),
+            })
+            .map_err(|e| WebhookRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<bool, WebhookRepositoryError> {
+        sqlx::query("delete from webhooks where id = ?")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| WebhookRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn mark_dispatched(&self, key: &str) -> Result<bool, String> {
+        sqlx::query("insert ignore into webhook_events (`key`) values (?)")
+            .bind(key)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    async fn enqueue_deliveries(&self, deliveries: &[NewDelivery]) -> Result<(), String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        for delivery in deliveries {
+            sqlx::query(
+                "insert into webhook_deliveries (webhook_id, url, event, payload, next_attempt_at) values (?, ?, ?, ?, ?)",
+            )
+            .bind(delivery.webhook_id)
+            .bind(&delivery.url)
+            .bind(delivery.event.as_str())
+            .bind(&delivery.payload)
+            .bind(Utc::now())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+
+        transaction.commit().await.map_err(|e| e.to_string())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<DueDelivery>, String> {
+        let now = Utc::now();
+
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        // MySQL cannot return updated rows, so the due deliveries are locked and then leased
+        let due = sqlx::query_as::<_, DueDelivery>(
+            r#"
+            select webhook_deliveries.id,
+                webhook_deliveries.webhook_id,
+                webhook_deliveries.url,
+                webhook_deliveries.payload,
+                webhook_deliveries.attempts,
+                webhooks.secret
+            from webhook_deliveries
+            left join webhooks on webhooks.id = webhook_deliveries.webhook_id
+            where webhook_deliveries.status = 'pending'
+              and webhook_deliveries.next_attempt_at <= ?
+            order by webhook_deliveries.next_attempt_at
+            limit ?
+            for update skip locked
+            "#,
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if !due.is_empty() {
+            let mut query_builder =
+                QueryBuilder::new("update webhook_deliveries set next_attempt_at = ");
+
+            query_builder.push_bind(now + lease).push(" where id in (");
+
+            let mut ids = query_builder.separated(", ");
+
+            for delivery in &due {
+                ids.push_bind(delivery.id);
+            }
+
+            query_builder.push(")");
+
+            query_builder
+                .build()
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(due)
+    }
+
+    async fn record_attempt(
+        &self,
+        id: i64,
+        attempt: &DeliveryAttempt,
+        status: DeliveryStatus,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::query(
+            r#"
+            insert into webhook_delivery_attempts (delivery_id, attempted_at, status_code, error)
+            values (?, ?, ?, ?)
+            "#,
+        )
+        .bind(id)
+        .bind(attempt.attempted_at)
+        .bind(attempt.status_code)
+        .bind(&attempt.error)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        sqlx::query(
+            r#"
+            update webhook_deliveries
+            set status          = ?,
+                attempts        = attempts + 1,
+                next_attempt_at = ?,
+                last_error      = ?,
+                delivered_at    = case when ? = 'delivered' then ? end
+            where id = ?
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(next_attempt_at)
+        .bind(&attempt.error)
+        .bind(status.as_str())
+        .bind(attempt.attempted_at)
+        .bind(id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())
+    }
+
+    async fn fetch_deliveries(
+        &self,
+        status: Option<DeliveryStatus>,
+    ) -> Result<Vec<WebhookDelivery>, String> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            select id,
+                   webhook_id,
+                   url,
+                   event,
+                   payload,
+                   status,
+                   attempts,
+                   next_attempt_at,
+                   last_error,
+                   created_at,
+                   delivered_at
+            from webhook_deliveries
+            where ? is null or status = ?
+            order by id desc
+            "#,
+        )
+        .bind(status.map(|status| status.as_str()))
+        .bind(status.map(|status| status.as_str()))
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_attempts(&self, delivery_id: i64) -> Result<Vec<DeliveryAttempt>, String> {
+        sqlx::query_as::<_, DeliveryAttempt>(
+            r#"
+            select attempted_at, status_code, error
+            from webhook_delivery_attempts
+            where delivery_id = ?
+            order by attempted_at
+            "#,
+        )
+        .bind(delivery_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn retry_delivery(&self, id: i64) -> Result<bool, String> {
+        sqlx::query(
+            r#"
+            update webhook_deliveries
+            set status = 'pending', attempts = 0, next_attempt_at = ?
+            where id = ? and status = 'failed'
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn prune_deliveries(&self, delivered_before: DateTime<Utc>) -> Result<(), String> {
+        sqlx::query(
+            "delete from webhook_deliveries where status = 'delivered' and delivered_at < ?",
+        )
+        .bind(delivered_before)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
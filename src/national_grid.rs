@@ -0,0 +1,153 @@
+use axum::async_trait;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use log::info;
+use reqwest::Client;
+use serde_derive::Deserialize;
+
+use crate::domain::{CarbonIntensity, CarbonIntensityProvider, CarbonIntensityProviderError};
+
+/// The zone of the carbon intensity of the national grid of Great Britain
+const ZONE: &str = "GB";
+
+/// The carbon intensity of Great Britain according to the Carbon Intensity API of the National
+/// Energy System Operator, in half hours. It is free and does not require an API key.
+#[derive(Clone, Debug)]
+pub(crate) struct NationalGrid {
+    /// The host of the API, `api.carbonintensity.org.uk`
+    host: String,
+}
+
+impl NationalGrid {
+    pub(crate) fn new(host: String) -> Self {
+        Self { host }
+    }
+
+    /// The carbon intensity of the 48 hours from a moment
+    async fn get(&self, from: DateTime<Utc>) -> Result<String, String> {
+        Client::new()
+            .get(format!(
+                "https://{}/intensity/{}/fw48h",
+                self.host,
+                from.format("%Y-%m-%dT%H:%MZ")
+            ))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl CarbonIntensityProvider for NationalGrid {
+    fn name(&self) -> &'static str {
+        "nationalgrid"
+    }
+
+    fn zone(&self) -> &str {
+        ZONE
+    }
+
+    async fn fetch_carbon_intensity(
+        &self,
+    ) -> Result<Vec<CarbonIntensity>, CarbonIntensityProviderError> {
+        info!("Fetching the carbon intensity from the national grid");
+
+        // the 48 hours from a day ago hold the measured past day and the forecast coming day
+        let intensities = self
+            .get(Utc::now() - TimeDelta::days(1))
+            .await
+            .and_then(|body| parse_intensity_json(&body))
+            .map_err(CarbonIntensityProviderError::FetchCarbonIntensity)?;
+
+        info!("Fetched {} carbon intensities", intensities.len());
+
+        Ok(intensities)
+    }
+}
+
+fn parse_intensity_json(json: &str) -> Result<Vec<CarbonIntensity>, String> {
+    let response = serde_json::from_str::<Response>(json)
+        .map_err(|e| format!("failed to parse the carbon intensity: {}", e))?;
+
+    response
+        .data
+        .into_iter()
+        .filter_map(|period| {
+            let (intensity, forecast) = match period.intensity {
+                Intensity {
+                    actual: Some(actual),
+                    ..
+                } => (actual, false),
+                Intensity {
+                    forecast: Some(forecast),
+                    ..
+                } => (forecast, true),
+                _ => return None,
+            };
+
+            Some(
+                // the moments lack seconds, e.g. 2024-06-30T12:30Z
+                NaiveDateTime::parse_from_str(&period.from, "%Y-%m-%dT%H:%MZ")
+                    .map(|moment| CarbonIntensity {
+                        moment: moment.and_utc(),
+                        zone: ZONE.to_string(),
+                        intensity,
+                        forecast,
+                    })
+                    .map_err(|e| format!("failed to parse the carbon intensity: {}", e)),
+            )
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    data: Vec<Period>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Period {
+    from: String,
+    intensity: Intensity,
+}
+
+#[derive(Deserialize, Debug)]
+struct Intensity {
+    forecast: Option<f64>,
+    /// Absent for periods that have not passed yet
+    actual: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_intensity_json() {
+        let json = r#"
+            {"data":[{"from":"2024-06-30T12:00Z","to":"2024-06-30T12:30Z","intensity":{"forecast":120,"actual":114,"index":"low"}},{"from":"2024-06-30T12:30Z","to":"2024-06-30T13:00Z","intensity":{"forecast":126,"actual":null,"index":"low"}}]}
+            "#;
+
+        let intensities = parse_intensity_json(json).unwrap();
+
+        assert_eq!(intensities.len(), 2);
+        assert_eq!(
+            intensities[0].moment,
+            DateTime::parse_from_rfc3339("2024-06-30T12:00:00Z").unwrap()
+        );
+        assert_eq!(intensities[0].intensity, 114.0);
+        assert!(!intensities[0].forecast);
+        assert_eq!(
+            intensities[1].moment,
+            DateTime::parse_from_rfc3339("2024-06-30T12:30:00Z").unwrap()
+        );
+        assert_eq!(intensities[1].intensity, 126.0);
+        assert!(intensities[1].forecast);
+        assert_eq!(intensities[1].zone, "GB");
+    }
+}
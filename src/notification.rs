@@ -0,0 +1,1184 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, DurationRound, FixedOffset, NaiveDate, TimeDelta, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    billing::billing_summary,
+    cron::CronSchedule,
+    domain::{start_of_day, PricePoint},
+    optimizer::{costs, optimize, Contiguous},
+    planner::plan_all_devices,
+    price_level::current_price,
+    setup::AppState,
+    template::{Field, Template, Value},
+};
+
+/// The numbers of hours of the cheapest windows in a digest when its rule has none
+pub(crate) const DEFAULT_DIGEST_DURATIONS: [i32; 3] = [1, 2, 3];
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum NotificationError {
+    #[error(
+        "unknown event \"{0}\", expected window_start, daily_summary, price_spike, price_below, \
+         negative_price, level_change, tomorrow_published, tomorrow_digest, \
+         cheapest_window_change or budget_exceeded"
+    )]
+    UnknownEvent(String),
+    #[error("unknown channel \"{0}\", expected log, telegram, slack, discord or email")]
+    UnknownChannel(String),
+}
+
+/// What a notification rule fires on
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationEvent {
+    /// A planned window of a device is about to start
+    WindowStart,
+    /// The lowest, highest and average price of the day, at an hour of the day
+    DailySummary,
+    /// The price of the hour that started is above a threshold
+    PriceSpike,
+    /// The price of the hour that started is below a threshold
+    PriceBelow,
+    /// The price of the hour that started is negative
+    NegativePrice,
+    /// The price level of the hour that started differs from that of the hour before
+    LevelChange,
+    /// The prices of tomorrow are known
+    TomorrowPublished,
+    /// The lowest, highest and average price of tomorrow with its cheapest windows, once the
+    /// prices are known
+    TomorrowDigest,
+    /// Another window of the upcoming prices became the cheapest to run for a number of hours
+    CheapestWindowChange,
+    /// The projected cost of the current month exceeds a budget
+    BudgetExceeded,
+}
+
+impl NotificationEvent {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::WindowStart => "window_start",
+            NotificationEvent::DailySummary => "daily_summary",
+            NotificationEvent::PriceSpike => "price_spike",
+            NotificationEvent::PriceBelow => "price_below",
+            NotificationEvent::NegativePrice => "negative_price",
+            NotificationEvent::LevelChange => "level_change",
+            NotificationEvent::TomorrowPublished => "tomorrow_published",
+            NotificationEvent::TomorrowDigest => "tomorrow_digest",
+            NotificationEvent::CheapestWindowChange => "cheapest_window_change",
+            NotificationEvent::BudgetExceeded => "budget_exceeded",
+        }
+    }
+
+    /// What notifications of the event are about, e.g. the subject of an email
+    pub(crate) fn title(&self) -> &'static str {
+        match self {
+            NotificationEvent::WindowStart => "Window starting soon",
+            NotificationEvent::DailySummary => "Daily price summary",
+            NotificationEvent::PriceSpike => "Price spike",
+            NotificationEvent::PriceBelow => "Low price",
+            NotificationEvent::NegativePrice => "Negative price",
+            NotificationEvent::LevelChange => "Price level change",
+            NotificationEvent::TomorrowPublished => "Prices of tomorrow published",
+            NotificationEvent::TomorrowDigest => "Prices of tomorrow",
+            NotificationEvent::CheapestWindowChange => "Cheapest window changed",
+            NotificationEvent::BudgetExceeded => "Budget exceeded",
+        }
+    }
+
+    /// The fields a template of a notification of the event can use
+    pub(crate) fn fields(&self) -> &'static [(&'static str, Field)] {
+        match self {
+            NotificationEvent::WindowStart => &[
+                ("device", Field::Text),
+                ("starts_at", Field::Moment),
+                ("ends_at", Field::Moment),
+                ("minutes", Field::Number),
+                ("minutes_text", Field::Text),
+                ("average_price", Field::Number),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::DailySummary => &[
+                ("date", Field::Date),
+                ("average", Field::Number),
+                ("lowest", Field::Number),
+                ("lowest_at", Field::Moment),
+                ("highest", Field::Number),
+                ("highest_at", Field::Moment),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::PriceSpike | NotificationEvent::PriceBelow => &[
+                ("price", Field::Number),
+                ("threshold", Field::Number),
+                ("starts_at", Field::Moment),
+                ("average_price", Field::Number),
+                ("level", Field::Text),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::NegativePrice => &[
+                ("price", Field::Number),
+                ("starts_at", Field::Moment),
+                ("average_price", Field::Number),
+                ("level", Field::Text),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::LevelChange => &[
+                ("level", Field::Text),
+                ("previous_level", Field::Text),
+                ("price", Field::Number),
+                ("starts_at", Field::Moment),
+                ("average_price", Field::Number),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::TomorrowPublished => &[
+                ("date", Field::Date),
+                ("hours", Field::Number),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::TomorrowDigest => &[
+                ("date", Field::Date),
+                ("average", Field::Number),
+                ("lowest", Field::Number),
+                ("lowest_at", Field::Moment),
+                ("highest", Field::Number),
+                ("highest_at", Field::Moment),
+                ("windows", Field::Text),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::CheapestWindowChange => &[
+                ("starts_at", Field::Moment),
+                ("ends_at", Field::Moment),
+                ("duration", Field::Number),
+                ("average_price", Field::Number),
+                ("currency", Field::Text),
+            ],
+            NotificationEvent::BudgetExceeded => &[
+                ("month", Field::Date),
+                ("budget", Field::Number),
+                ("projected_total", Field::Number),
+                ("total", Field::Number),
+                ("currency", Field::Text),
+            ],
+        }
+    }
+
+    /// The template of a notification of the event when its rule has none
+    fn default_template(&self) -> &'static str {
+        match self {
+            NotificationEvent::WindowStart => {
+                "{{ device }} window starts in {{ minutes_text }} at {{ starts_at | date }}"
+            }
+            NotificationEvent::DailySummary => {
+                "Prices of {{ date | date(format=\"%a %-d %b\") }}: \
+                 average {{ average | decimals(n=4) }}, \
+                 lowest {{ lowest | decimals(n=4) }} at {{ lowest_at | date }}, \
+                 highest {{ highest | decimals(n=4) }} at {{ highest_at | date }} {{ currency }}/kWh"
+            }
+            NotificationEvent::PriceSpike => {
+                "Price spike: {{ price | decimals(n=4) }} {{ currency }}/kWh \
+                 from {{ starts_at | date }}, above {{ threshold | decimals(n=4) }}"
+            }
+            NotificationEvent::PriceBelow => {
+                "Low price: {{ price | decimals(n=4) }} {{ currency }}/kWh \
+                 from {{ starts_at | date }}, below {{ threshold | decimals(n=4) }}"
+            }
+            NotificationEvent::NegativePrice => {
+                "Negative price: {{ price | decimals(n=4) }} {{ currency }}/kWh \
+                 from {{ starts_at | date }}"
+            }
+            NotificationEvent::LevelChange => {
+                "Price level {{ level }} from {{ starts_at | date }}, was {{ previous_level }}: \
+                 {{ price | decimals(n=4) }} {{ currency }}/kWh"
+            }
+            NotificationEvent::TomorrowPublished => {
+                "The prices of {{ date | date(format=\"%a %-d %b\") }} are published"
+            }
+            NotificationEvent::TomorrowDigest => {
+                "Prices of {{ date | date(format=\"%a %-d %b\") }}: \
+                 average {{ average | decimals(n=4) }}, \
+                 lowest {{ lowest | decimals(n=4) }} at {{ lowest_at | date }}, \
+                 highest {{ highest | decimals(n=4) }} at {{ highest_at | date }} \
+                 {{ currency }}/kWh. Cheapest windows: {{ windows }}"
+            }
+            NotificationEvent::CheapestWindowChange => {
+                "The cheapest {{ duration }}h window now starts \
+                 {{ starts_at | date(format=\"%a %H:%M\") }}, \
+                 at {{ average_price | decimals(n=4) }} {{ currency }}/kWh"
+            }
+            NotificationEvent::BudgetExceeded => {
+                "The cost of {{ month | date(format=\"%B\") }} is projected at \
+                 {{ projected_total | decimals(n=2) }} {{ currency }}, \
+                 above the budget of {{ budget | decimals(n=2) }}"
+            }
+        }
+    }
+}
+
+impl FromStr for NotificationEvent {
+    type Err = NotificationError;
+
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        match event {
+            "window_start" => Ok(NotificationEvent::WindowStart),
+            "daily_summary" => Ok(NotificationEvent::DailySummary),
+            "price_spike" => Ok(NotificationEvent::PriceSpike),
+            "price_below" => Ok(NotificationEvent::PriceBelow),
+            "negative_price" => Ok(NotificationEvent::NegativePrice),
+            "level_change" => Ok(NotificationEvent::LevelChange),
+            "tomorrow_published" => Ok(NotificationEvent::TomorrowPublished),
+            "tomorrow_digest" => Ok(NotificationEvent::TomorrowDigest),
+            "cheapest_window_change" => Ok(NotificationEvent::CheapestWindowChange),
+            "budget_exceeded" => Ok(NotificationEvent::BudgetExceeded),
+            _ => Err(NotificationError::UnknownEvent(event.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationEvent {
+    type Error = NotificationError;
+
+    fn try_from(event: String) -> Result<Self, Self::Error> {
+        event.parse()
+    }
+}
+
+/// Where a notification is sent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NotificationChannel {
+    /// Only written to the log
+    Log,
+    /// A message from the configured Telegram bot
+    Telegram,
+    /// A message through the configured Slack incoming webhook
+    Slack,
+    /// A message through the configured Discord webhook
+    Discord,
+    /// An email to the configured recipients
+    Email,
+}
+
+impl NotificationChannel {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Log => "log",
+            NotificationChannel::Telegram => "telegram",
+            NotificationChannel::Slack => "slack",
+            NotificationChannel::Discord => "discord",
+            NotificationChannel::Email => "email",
+        }
+    }
+
+    /// Whether what the channel needs is configured
+    pub(crate) fn is_configured(&self, state: &AppState) -> bool {
+        match self {
+            NotificationChannel::Log => true,
+            NotificationChannel::Telegram => state.settings().notifications.telegram.is_some(),
+            NotificationChannel::Slack => state.settings().notifications.slack.is_some(),
+            NotificationChannel::Discord => state.settings().notifications.discord.is_some(),
+            NotificationChannel::Email => state.settings().notifications.email.is_some(),
+        }
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = NotificationError;
+
+    fn from_str(channel: &str) -> Result<Self, Self::Err> {
+        match channel {
+            "log" => Ok(NotificationChannel::Log),
+            "telegram" => Ok(NotificationChannel::Telegram),
+            "slack" => Ok(NotificationChannel::Slack),
+            "discord" => Ok(NotificationChannel::Discord),
+            "email" => Ok(NotificationChannel::Email),
+            _ => Err(NotificationError::UnknownChannel(channel.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationChannel {
+    type Error = NotificationError;
+
+    fn try_from(channel: String) -> Result<Self, Self::Error> {
+        channel.parse()
+    }
+}
+
+/// When to notify, e.g. 15 minutes before a window of the dishwasher starts, so there is time to
+/// load it
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct NotificationRule {
+    pub(crate) id: i64,
+    #[sqlx(try_from = "String")]
+    pub(crate) event: NotificationEvent,
+    /// The device the rule applies to, every device when absent
+    pub(crate) device_id: Option<i64>,
+    /// How many minutes before the window starts to notify
+    pub(crate) lead_minutes: i32,
+    pub(crate) enabled: bool,
+    #[sqlx(try_from = "String")]
+    pub(crate) channel: NotificationChannel,
+    /// The local hour to send the daily summary at
+    pub(crate) hour: Option<i32>,
+    /// The price above which a spike is notified, `PRICE_ALERT_THRESHOLD` when absent, below
+    /// which a low price is notified, or the monthly budget
+    pub(crate) threshold: Option<f64>,
+    /// The template of the notifications, a sentence in English when absent
+    pub(crate) template: Option<String>,
+    /// The price level a level change is notified into, every level when absent
+    pub(crate) level: Option<String>,
+    /// The number of hours of the cheapest window
+    pub(crate) duration: Option<i32>,
+    /// The numbers of hours of the cheapest windows in a digest
+    pub(crate) durations: Option<Vec<i32>>,
+    /// A cron schedule of the minutes the rule may notify in, always when absent
+    pub(crate) schedule: Option<String>,
+}
+
+impl NotificationRule {
+    /// Whether the schedule of the rule allows notifying in the minute of `now`
+    fn is_scheduled(&self, timezone: Tz, now: DateTime<Utc>) -> bool {
+        let Some(schedule) = &self.schedule else {
+            return true;
+        };
+
+        match CronSchedule::parse(schedule, timezone) {
+            Ok(schedule) => schedule.matches(now),
+            Err(e) => {
+                warn!("unable to parse the schedule of rule {}, {}", self.id, e);
+                true
+            }
+        }
+    }
+
+    /// Whether the rule fires for a window starting at `starts_at`, given the current moment.
+    /// It fires from its lead time before the start until the window starts.
+    fn is_due(&self, starts_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        starts_at - TimeDelta::minutes(self.lead_minutes as i64) <= now && now < starts_at
+    }
+
+    /// The message of a notification, rendered from the template of the rule or the default
+    /// template of its event
+    fn message(&self, values: &[(&str, Value)]) -> String {
+        let default =
+            || Template::parse(self.event.default_template()).expect("default templates are valid");
+
+        let template = match &self.template {
+            Some(template) => Template::parse(template).unwrap_or_else(|e| {
+                warn!("unable to parse the template of rule {}, {}", self.id, e);
+                default()
+            }),
+            None => default(),
+        };
+
+        template.render(values).unwrap_or_else(|e| {
+            warn!("unable to render the template of rule {}, {}", self.id, e);
+            default()
+                .render(values)
+                .unwrap_or_else(|_| self.event.title().to_string())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Notification {
+    pub(crate) rule_id: i64,
+    pub(crate) event: NotificationEvent,
+    pub(crate) device_id: Option<i64>,
+    pub(crate) message: String,
+}
+
+/// Send the notifications of the enabled rules that are due and of which the schedule allows
+/// notifying now: reminders of windows that are about to start, the daily summary at its hour,
+/// conditions on the price of the current hour, the prices of tomorrow being published,
+/// changes of the cheapest window and the projected cost of the month exceeding a budget.
+/// Every window, day, hour and month is notified once per rule.
+pub(crate) async fn send_notifications(state: &AppState) -> Result<(), String> {
+    let now = Utc::now();
+    let rules = state
+        .notification_repository
+        .fetch_rules()
+        .await?
+        .into_iter()
+        .filter(|rule| rule.enabled && rule.is_scheduled(state.timezone, now))
+        .collect::<Vec<NotificationRule>>();
+    let of_events = |events: &[NotificationEvent]| {
+        rules
+            .iter()
+            .filter(|rule| events.contains(&rule.event))
+            .collect::<Vec<&NotificationRule>>()
+    };
+
+    send_window_reminders(state, &of_events(&[NotificationEvent::WindowStart]), now).await?;
+    send_daily_summaries(state, &of_events(&[NotificationEvent::DailySummary]), now).await?;
+    send_price_conditions(
+        state,
+        &of_events(&[
+            NotificationEvent::PriceSpike,
+            NotificationEvent::PriceBelow,
+            NotificationEvent::NegativePrice,
+            NotificationEvent::LevelChange,
+        ]),
+        now,
+    )
+    .await?;
+    send_tomorrow_prices(
+        state,
+        &of_events(&[
+            NotificationEvent::TomorrowPublished,
+            NotificationEvent::TomorrowDigest,
+        ]),
+        now,
+    )
+    .await?;
+    send_cheapest_window_changes(
+        state,
+        &of_events(&[NotificationEvent::CheapestWindowChange]),
+        now,
+    )
+    .await?;
+    send_budget_alerts(state, &of_events(&[NotificationEvent::BudgetExceeded]), now).await?;
+
+    Ok(())
+}
+
+/// Send a notification for every window of a device that starts within the lead time of a
+/// window start rule
+async fn send_window_reminders(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    for (device, _, next_run) in plan_all_devices(state, now).await? {
+        let Some(next_run) = next_run else {
+            continue;
+        };
+
+        for rule in rules
+            .iter()
+            .filter(|rule| rule.device_id.is_none_or(|id| id == device.id))
+        {
+            for window in &next_run.windows {
+                let starts_at = window.starts_at.to_utc();
+
+                if !rule.is_due(starts_at, now) {
+                    continue;
+                }
+
+                if !state
+                    .notification_repository
+                    .mark_sent(rule.id, Some(device.id), starts_at)
+                    .await?
+                {
+                    continue;
+                }
+
+                let minutes = ((starts_at - now).num_seconds() + 59) / 60;
+
+                let message = rule.message(&[
+                    ("device", Value::Text(device.name.clone())),
+                    ("starts_at", Value::Moment(window.starts_at)),
+                    ("ends_at", Value::Moment(window.ends_at)),
+                    ("minutes", Value::Number(minutes as f64)),
+                    (
+                        "minutes_text",
+                        Value::Text(format!(
+                            "{} minute{}",
+                            minutes,
+                            if minutes == 1 { "" } else { "s" }
+                        )),
+                    ),
+                    (
+                        "average_price",
+                        Value::Number(window.average_price.parse().unwrap_or_default()),
+                    ),
+                    ("currency", Value::Text(window.currency.clone())),
+                ]);
+
+                deliver(
+                    state,
+                    rule,
+                    Notification {
+                        rule_id: rule.id,
+                        event: rule.event,
+                        device_id: Some(device.id),
+                        message,
+                    },
+                )
+                .await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Send the summary of the prices of today for every daily summary rule of which the hour
+/// started. A summary waits for the prices of today within its hour.
+async fn send_daily_summaries(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let local_now = now.with_timezone(&state.timezone);
+    let rules = rules
+        .iter()
+        .filter(|rule| rule.hour == Some(local_now.hour() as i32))
+        .collect::<Vec<&&NotificationRule>>();
+
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let today = local_now.date_naive();
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, today),
+            start_of_day(&state.timezone, today + TimeDelta::days(1)),
+            state.price_area(None).as_deref(),
+        )
+        .await?;
+
+    let Some(currency) = prices.first().map(|price| price.currency.clone()) else {
+        return Ok(());
+    };
+
+    let hourly_prices = prices
+        .iter()
+        .map(|price| price.moment.with_timezone(&state.timezone).fixed_offset())
+        .zip(costs(&prices, None))
+        .collect::<Vec<_>>();
+
+    let Some(values) = daily_summary(today, &hourly_prices, &currency) else {
+        return Ok(());
+    };
+
+    let hour = now
+        .duration_trunc(TimeDelta::hours(1))
+        .map_err(|e| e.to_string())?;
+
+    for rule in rules {
+        if !state
+            .notification_repository
+            .mark_sent(rule.id, None, hour)
+            .await?
+        {
+            continue;
+        }
+
+        deliver(
+            state,
+            rule,
+            Notification {
+                rule_id: rule.id,
+                event: rule.event,
+                device_id: None,
+                message: rule.message(&values),
+            },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// The lowest, highest and average of the prices of a day, with the hours of the lowest and
+/// highest price
+fn daily_summary(
+    date: NaiveDate,
+    prices: &[(DateTime<FixedOffset>, f64)],
+    currency: &str,
+) -> Option<Vec<(&'static str, Value)>> {
+    let (lowest_at, lowest) = prices.iter().min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let (highest_at, highest) = prices.iter().max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let average = prices.iter().map(|(_, price)| price).sum::<f64>() / prices.len() as f64;
+
+    Some(vec![
+        ("date", Value::Date(date)),
+        ("average", Value::Number(average)),
+        ("lowest", Value::Number(*lowest)),
+        ("lowest_at", Value::Moment(*lowest_at)),
+        ("highest", Value::Number(*highest)),
+        ("highest_at", Value::Moment(*highest_at)),
+        ("currency", Value::Text(currency.to_string())),
+    ])
+}
+
+/// Send a notification for every rule of which the condition holds for the price of the
+/// current hour: a spike above or a drop below its threshold, a negative price or a change of
+/// the price level since the hour before
+async fn send_price_conditions(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let area = state.price_area(None);
+
+    let Some(current) = current_price(state, now, area.as_deref()).await? else {
+        return Ok(());
+    };
+
+    let previous = match rules
+        .iter()
+        .any(|rule| rule.event == NotificationEvent::LevelChange)
+    {
+        true => current_price(state, current.moment - TimeDelta::hours(1), area.as_deref()).await?,
+        false => None,
+    };
+
+    for rule in rules {
+        let threshold = match rule.event {
+            NotificationEvent::PriceSpike => rule
+                .threshold
+                .or(state.settings().pricing.price_alert_threshold),
+            _ => rule.threshold,
+        };
+
+        let holds = match rule.event {
+            NotificationEvent::PriceSpike => {
+                threshold.is_some_and(|threshold| current.price > threshold)
+            }
+            NotificationEvent::PriceBelow => {
+                threshold.is_some_and(|threshold| current.price < threshold)
+            }
+            NotificationEvent::NegativePrice => current.price < 0.0,
+            NotificationEvent::LevelChange => {
+                previous
+                    .as_ref()
+                    .is_some_and(|previous| previous.level != current.level)
+                    && rule
+                        .level
+                        .as_deref()
+                        .is_none_or(|level| level == current.level.as_str())
+            }
+            _ => false,
+        };
+
+        if !holds
+            || !state
+                .notification_repository
+                .mark_sent(rule.id, None, current.moment)
+                .await?
+        {
+            continue;
+        }
+
+        let mut values = vec![
+            ("price", Value::Number(current.price)),
+            (
+                "starts_at",
+                Value::Moment(current.moment.with_timezone(&state.timezone).fixed_offset()),
+            ),
+            ("average_price", Value::Number(current.average_price)),
+            ("level", Value::from(current.level.as_str())),
+            ("currency", Value::Text(current.currency.clone())),
+        ];
+
+        if let Some(threshold) = threshold {
+            values.push(("threshold", Value::Number(threshold)));
+        }
+
+        if let Some(previous) = &previous {
+            values.push(("previous_level", Value::from(previous.level.as_str())));
+        }
+
+        deliver(
+            state,
+            rule,
+            Notification {
+                rule_id: rule.id,
+                event: rule.event,
+                device_id: None,
+                message: rule.message(&values),
+            },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Send a notification for every rule about the prices of tomorrow, once they are known: that
+/// they are published or a digest of them
+async fn send_tomorrow_prices(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let tomorrow = now.with_timezone(&state.timezone).date_naive() + TimeDelta::days(1);
+    let starts_at = start_of_day(&state.timezone, tomorrow);
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            starts_at,
+            start_of_day(&state.timezone, tomorrow + TimeDelta::days(1)),
+            state.price_area(None).as_deref(),
+        )
+        .await?;
+
+    let Some(currency) = prices.first().map(|price| price.currency.clone()) else {
+        return Ok(());
+    };
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(None));
+
+    let hourly_prices = prices
+        .iter()
+        .map(|price| price.moment.with_timezone(&state.timezone).fixed_offset())
+        .zip(costs(&prices, None))
+        .collect::<Vec<_>>();
+
+    for rule in rules {
+        if !state
+            .notification_repository
+            .mark_sent(rule.id, None, starts_at)
+            .await?
+        {
+            continue;
+        }
+
+        let values = match rule.event {
+            NotificationEvent::TomorrowDigest => {
+                let Some(mut values) = daily_summary(tomorrow, &hourly_prices, &currency) else {
+                    continue;
+                };
+
+                values.push((
+                    "windows",
+                    Value::Text(cheapest_windows(
+                        &prices,
+                        rule.durations
+                            .as_deref()
+                            .unwrap_or(&DEFAULT_DIGEST_DURATIONS),
+                        price_cap,
+                        state.timezone,
+                    )),
+                ));
+                values
+            }
+            _ => vec![
+                ("date", Value::Date(tomorrow)),
+                ("hours", Value::Number(prices.len() as f64)),
+                ("currency", Value::Text(currency.clone())),
+            ],
+        };
+
+        deliver(
+            state,
+            rule,
+            Notification {
+                rule_id: rule.id,
+                event: rule.event,
+                device_id: None,
+                message: rule.message(&values),
+            },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// The cheapest window of every duration with its average price, e.g.
+/// `1h at 03:00 (0.0950), 2h at 02:00 (0.1000)`. Durations longer than the prices are left out.
+fn cheapest_windows(
+    prices: &[PricePoint],
+    durations: &[i32],
+    price_cap: Option<f64>,
+    timezone: Tz,
+) -> String {
+    durations
+        .iter()
+        .filter_map(|duration| {
+            let window = optimize(
+                &Contiguous {
+                    duration: *duration as usize,
+                },
+                prices,
+                price_cap,
+            )?
+            .into_iter()
+            .next()?;
+
+            Some(format!(
+                "{}h at {} ({:.4})",
+                duration,
+                window.starts_at.with_timezone(&timezone).format("%H:%M"),
+                window.average_price.parse::<f64>().unwrap_or_default()
+            ))
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Send a notification for every rule of which the cheapest window of the upcoming prices
+/// starts at another moment than the window it notified about last. While that window runs,
+/// the windows after it are not a change.
+async fn send_cheapest_window_changes(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let current_hour = now
+        .duration_trunc(TimeDelta::hours(1))
+        .map_err(|e| e.to_string())?;
+    let today = now.with_timezone(&state.timezone).date_naive();
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            current_hour,
+            start_of_day(&state.timezone, today + TimeDelta::days(2)),
+            state.price_area(None).as_deref(),
+        )
+        .await?;
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .and_then(|cap| cap.rate_for(None));
+
+    for rule in rules {
+        let Some(duration) = rule.duration else {
+            continue;
+        };
+
+        let Some(window) = optimize(
+            &Contiguous {
+                duration: duration as usize,
+            },
+            &prices,
+            price_cap,
+        )
+        .and_then(|windows| windows.into_iter().next()) else {
+            continue;
+        };
+
+        let last_starts_at = state
+            .notification_repository
+            .fetch_last_sent(rule.id)
+            .await?;
+
+        if last_starts_at.is_some_and(|starts_at| {
+            starts_at <= now && now < starts_at + TimeDelta::hours(duration as i64)
+        }) {
+            continue;
+        }
+
+        if !state
+            .notification_repository
+            .mark_sent(rule.id, None, window.starts_at.to_utc())
+            .await?
+        {
+            continue;
+        }
+
+        let window = window.with_timezone(state.timezone);
+
+        deliver(
+            state,
+            rule,
+            Notification {
+                rule_id: rule.id,
+                event: rule.event,
+                device_id: None,
+                message: rule.message(&[
+                    ("starts_at", Value::Moment(window.starts_at)),
+                    ("ends_at", Value::Moment(window.ends_at)),
+                    ("duration", Value::Number(duration as f64)),
+                    (
+                        "average_price",
+                        Value::Number(window.average_price.parse().unwrap_or_default()),
+                    ),
+                    ("currency", Value::Text(window.currency.clone())),
+                ]),
+            },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Send a notification for every rule of which the budget is below the projected cost of the
+/// current month, including the fixed charges
+async fn send_budget_alerts(
+    state: &AppState,
+    rules: &[&NotificationRule],
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let month = now
+        .with_timezone(&state.timezone)
+        .date_naive()
+        .with_day(1)
+        .unwrap();
+    let starts_at = start_of_day(&state.timezone, month);
+    let mut summary = None;
+
+    for rule in rules {
+        let Some(budget) = rule.threshold else {
+            continue;
+        };
+
+        if state
+            .notification_repository
+            .fetch_last_sent(rule.id)
+            .await?
+            .is_some_and(|last_sent| last_sent >= starts_at)
+        {
+            continue;
+        }
+
+        // the summary is the same for every rule, so it is only made when a rule needs it
+        if summary.is_none() {
+            summary =
+                Some(billing_summary(state, month, now, state.price_area(None).as_deref()).await?);
+        }
+
+        let Some(summary) = &summary else {
+            continue;
+        };
+
+        // without typical usage the month cannot be projected
+        let Some(projection) = &summary.projection else {
+            return Ok(());
+        };
+
+        if projection.total <= budget
+            || !state
+                .notification_repository
+                .mark_sent(rule.id, None, starts_at)
+                .await?
+        {
+            continue;
+        }
+
+        deliver(
+            state,
+            rule,
+            Notification {
+                rule_id: rule.id,
+                event: rule.event,
+                device_id: None,
+                message: rule.message(&[
+                    ("month", Value::Date(month)),
+                    ("budget", Value::Number(budget)),
+                    ("projected_total", Value::Number(projection.total)),
+                    ("total", Value::Number(summary.total)),
+                    (
+                        "currency",
+                        Value::Text(summary.currency.clone().unwrap_or_default()),
+                    ),
+                ]),
+            },
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Send a notification through the channel of its rule. A notification that cannot be sent
+/// is not retried.
+async fn deliver(state: &AppState, rule: &NotificationRule, notification: Notification) {
+    let settings = state.settings();
+    let notifications = &settings.notifications;
+    let message = &notification.message;
+    let not_configured = || Err(format!("{} is not configured", rule.channel.as_str()));
+
+    let result = match rule.channel {
+        NotificationChannel::Log => Ok(()),
+        NotificationChannel::Telegram => match &notifications.telegram {
+            Some(telegram) => telegram.send_message(message).await,
+            None => not_configured(),
+        },
+        NotificationChannel::Slack => match &notifications.slack {
+            Some(slack) => slack.send_message(message).await,
+            None => not_configured(),
+        },
+        NotificationChannel::Discord => match &notifications.discord {
+            Some(discord) => discord.send_message(message).await,
+            None => not_configured(),
+        },
+        NotificationChannel::Email => match &notifications.email {
+            Some(mailer) => {
+                mailer
+                    .send(&[
+                        ("title", Value::from(notification.event.title())),
+                        ("event", Value::from(notification.event.as_str())),
+                        ("message", Value::from(message.as_str())),
+                    ])
+                    .await
+            }
+            None => not_configured(),
+        },
+    };
+
+    match result {
+        Ok(()) => info!(
+            "notification through {}: {}",
+            rule.channel.as_str(),
+            notification.message
+        ),
+        Err(e) => warn!(
+            "unable to send notification of rule {} through {}, {}",
+            notification.rule_id,
+            rule.channel.as_str(),
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{moment, price_point};
+
+    #[test]
+    fn test_is_due() {
+        let rule = NotificationRule {
+            id: 1,
+            event: NotificationEvent::WindowStart,
+            device_id: None,
+            lead_minutes: 15,
+            enabled: true,
+            channel: NotificationChannel::Log,
+            hour: None,
+            threshold: None,
+            template: None,
+            level: None,
+            duration: None,
+            durations: None,
+            schedule: None,
+        };
+        let starts_at = moment("2024-06-30T02:00:00Z");
+
+        assert!(!rule.is_due(starts_at, moment("2024-06-30T01:44:00Z")));
+        assert!(rule.is_due(starts_at, moment("2024-06-30T01:45:00Z")));
+        assert!(rule.is_due(starts_at, moment("2024-06-30T01:59:00Z")));
+        assert!(!rule.is_due(starts_at, moment("2024-06-30T02:00:00Z")));
+    }
+
+    #[test]
+    fn test_daily_summary() {
+        let prices = [("03:00", 0.10), ("12:00", 0.195), ("18:00", 0.35)].map(|(time, price)| {
+            (
+                DateTime::parse_from_rfc3339(&format!("2024-06-30T{}:00+02:00", time)).unwrap(),
+                price,
+            )
+        });
+        let rule = NotificationRule {
+            id: 1,
+            event: NotificationEvent::DailySummary,
+            device_id: None,
+            lead_minutes: 0,
+            enabled: true,
+            channel: NotificationChannel::Log,
+            hour: Some(7),
+            threshold: None,
+            template: None,
+            level: None,
+            duration: None,
+            durations: None,
+            schedule: None,
+        };
+        let values = daily_summary(
+            NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(),
+            &prices,
+            "EUR",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rule.message(&values),
+            "Prices of Sun 30 Jun: average 0.2150, lowest 0.1000 at 03:00, highest 0.3500 at \
+             18:00 EUR/kWh"
+        );
+        assert_eq!(
+            NotificationRule {
+                template: Some(
+                    "Goedkoopst om {{ lowest_at | date }}: \
+                     {{ lowest | decimals(n=2, separator=\",\") }} €"
+                        .to_string(),
+                ),
+                ..rule
+            }
+            .message(&values),
+            "Goedkoopst om 03:00: 0,10 €"
+        );
+        assert_eq!(
+            daily_summary(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(), &[], "EUR"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cheapest_windows() {
+        let start = moment("2024-06-29T22:00:00Z");
+        let prices = [0.20, 0.10, 0.12, 0.30]
+            .iter()
+            .enumerate()
+            .map(|(hour, amount)| price_point(start + TimeDelta::hours(hour as i64), *amount))
+            .collect::<Vec<PricePoint>>();
+
+        assert_eq!(
+            cheapest_windows(&prices, &[1, 2, 6], None, Tz::Europe__Amsterdam),
+            "1h at 01:00 (0.1000), 2h at 01:00 (0.1100)"
+        );
+    }
+
+    #[test]
+    fn test_default_templates() {
+        for event in [
+            NotificationEvent::WindowStart,
+            NotificationEvent::DailySummary,
+            NotificationEvent::PriceSpike,
+            NotificationEvent::PriceBelow,
+            NotificationEvent::NegativePrice,
+            NotificationEvent::LevelChange,
+            NotificationEvent::TomorrowPublished,
+            NotificationEvent::TomorrowDigest,
+            NotificationEvent::CheapestWindowChange,
+            NotificationEvent::BudgetExceeded,
+        ] {
+            assert_eq!(
+                Template::parse(event.default_template())
+                    .and_then(|template| template.check_fields(event.fields())),
+                Ok(())
+            );
+        }
+    }
+}
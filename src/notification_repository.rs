@@ -0,0 +1,190 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use thiserror::Error;
+
+use crate::{
+    notification::{NotificationChannel, NotificationEvent, NotificationRule},
+    price_level::PriceLevel,
+};
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum NotificationRepositoryError {
+    #[error("the notification rule could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+/// A notification rule that is yet to be stored
+#[derive(Debug, Clone)]
+pub(crate) struct NewNotificationRule {
+    pub(crate) event: NotificationEvent,
+    pub(crate) device_id: Option<i64>,
+    pub(crate) lead_minutes: i32,
+    pub(crate) channel: NotificationChannel,
+    pub(crate) hour: Option<i32>,
+    pub(crate) threshold: Option<f64>,
+    pub(crate) template: Option<String>,
+    pub(crate) level: Option<PriceLevel>,
+    pub(crate) duration: Option<i32>,
+    pub(crate) durations: Option<Vec<i32>>,
+    pub(crate) schedule: Option<String>,
+}
+
+#[async_trait]
+pub(crate) trait NotificationRepository: Send + Sync {
+    /// Fetch all notification rules, ordered by id
+    async fn fetch_rules(&self) -> Result<Vec<NotificationRule>, String>;
+
+    async fn persist_rule(
+        &self,
+        rule: &NewNotificationRule,
+    ) -> Result<NotificationRule, NotificationRepositoryError>;
+
+    /// Enable or disable a notification rule, `None` when it does not exist
+    async fn set_rule_enabled(
+        &self,
+        id: i64,
+        enabled: bool,
+    ) -> Result<Option<NotificationRule>, NotificationRepositoryError>;
+
+    /// Delete a notification rule, `false` when it does not exist
+    async fn delete_rule(&self, id: i64) -> Result<bool, NotificationRepositoryError>;
+
+    /// Record that a rule notified about something starting at a moment, such as the window of
+    /// a device or an hour, `false` when it did so before
+    async fn mark_sent(
+        &self,
+        rule_id: i64,
+        device_id: Option<i64>,
+        starts_at: DateTime<Utc>,
+    ) -> Result<bool, String>;
+
+    /// The moment of what a rule notified about last, `None` when it never notified
+    async fn fetch_last_sent(&self, rule_id: i64) -> Result<Option<DateTime<Utc>>, String>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresNotificationRepository {
+    db: PgPool,
+}
+
+impl PostgresNotificationRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for PostgresNotificationRepository {
+    async fn fetch_rules(&self) -> Result<Vec<NotificationRule>, String> {
+        sqlx::query_as::<_, NotificationRule>(
+            r#"
+            select id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            from notification_rules
+            order by id
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_rule(
+        &self,
+        rule: &NewNotificationRule,
+    ) -> Result<NotificationRule, NotificationRepositoryError> {
+        sqlx::query_as::<_, NotificationRule>(
+            r#"
+            insert into notification_rules
+                (event, device_id, lead_minutes, channel, hour, threshold, template, level,
+                 duration, durations, schedule)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            returning id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            "#,
+        )
+        .bind(rule.event.as_str())
+        .bind(rule.device_id)
+        .bind(rule.lead_minutes)
+        .bind(rule.channel.as_str())
+        .bind(rule.hour)
+        .bind(rule.threshold)
+        .bind(&rule.template)
+        .bind(rule.level.map(|level| level.as_str()))
+        .bind(rule.duration)
+        .bind(&rule.durations)
+        .bind(&rule.schedule)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn set_rule_enabled(
+        &self,
+        id: i64,
+        enabled: bool,
+    ) -> Result<Option<NotificationRule>, NotificationRepositoryError> {
+        sqlx::query_as::<_, NotificationRule>(
+            r#"
+            update notification_rules
+            set enabled = $2
+            where id = $1
+            returning id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            "#,
+        )
+        .bind(id)
+        .bind(enabled)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_rule(&self, id: i64) -> Result<bool, NotificationRepositoryError> {
+        sqlx::query("delete from notification_rules where id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn mark_sent(
+        &self,
+        rule_id: i64,
+        device_id: Option<i64>,
+        starts_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        sqlx::query(
+            r#"
+            insert into sent_notifications (rule_id, device_id, starts_at)
+            values ($1, $2, $3)
+            on conflict do nothing
+            "#,
+        )
+        .bind(rule_id)
+        .bind(device_id)
+        .bind(starts_at)
+        .execute(&self.db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_last_sent(&self, rule_id: i64) -> Result<Option<DateTime<Utc>>, String> {
+        sqlx::query_scalar(
+            r#"
+            select starts_at
+            from sent_notifications
+            where rule_id = $1
+            order by sent_at desc
+            limit 1
+            "#,
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,438 @@
+use std::{fmt::Debug, ops::Range};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::domain::{PricePoint, PriceWindow};
+
+/// The number of minutes of the hours that durations are given in
+const MINUTES_PER_HOUR: i64 = 60;
+
+/// A way of selecting the hours in which a device should run.
+///
+/// Strategies operate on the price points of a period, ordered by moment, and the price that
+/// is optimized for each of them. They return the ranges of price points to run in.
+pub(crate) trait Strategy: Send + Sync + Debug {
+    fn name(&self) -> &'static str;
+
+    fn select(&self, prices: &[PricePoint], costs: &[f64]) -> Option<Vec<Range<usize>>>;
+}
+
+/// Select the hours to run in for the prices of a period, returning a window per consecutive
+/// run of hours. Every hour is optimized on the price a consumer pays, capped by a price cap.
+pub(crate) fn optimize(
+    strategy: &dyn Strategy,
+    prices: &[PricePoint],
+    price_cap: Option<f64>,
+) -> Option<Vec<PriceWindow>> {
+    optimize_costs(strategy, prices, &costs(prices, price_cap), price_cap)
+}
+
+/// Select the hours to run in for the prices of a period, optimizing the given cost of every
+/// hour rather than its price
+pub(crate) fn optimize_costs(
+    strategy: &dyn Strategy,
+    prices: &[PricePoint],
+    costs: &[f64],
+    price_cap: Option<f64>,
+) -> Option<Vec<PriceWindow>> {
+    strategy.select(prices, costs).map(|ranges| {
+        ranges
+            .into_iter()
+            .filter_map(|range| PriceWindow::from_prices(&prices[range], price_cap))
+            .collect()
+    })
+}
+
+/// The price a consumer pays for every price point, capped by a price cap
+pub(crate) fn costs(prices: &[PricePoint], price_cap: Option<f64>) -> Vec<f64> {
+    prices
+        .iter()
+        .map(|price| {
+            let cost = price.consumer_amount.unwrap_or(price.monetary_amount);
+            price_cap.map_or(cost, |cap| cost.min(cap))
+        })
+        .collect()
+}
+
+/// The energy to charge in the hour of a price point
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChargingSlot {
+    /// The index of the price point
+    pub(crate) index: usize,
+    pub(crate) energy_kwh: f64,
+}
+
+/// Spread charging an amount of energy over the cheapest hours between plugging in and
+/// departing, e.g. for an electric vehicle, charging at most `max_power_kw`. Hours that are
+/// partly available, because the car is plugged in or departs within them, can take a
+/// proportional share. The slots are ordered by moment and fall short of the energy when it does
+/// not fit before departure.
+pub(crate) fn plan_charging(
+    prices: &[PricePoint],
+    costs: &[f64],
+    energy_kwh: f64,
+    max_power_kw: f64,
+    plugged_in_at: DateTime<Utc>,
+    departure_at: DateTime<Utc>,
+) -> Vec<ChargingSlot> {
+    let capacity = |price: &PricePoint| {
+        let start = price.moment.max(plugged_in_at);
+        let end = ends_at(price).min(departure_at);
+        let available = (end - start).num_seconds().max(0) as f64;
+
+        max_power_kw * available / TimeDelta::hours(1).num_seconds() as f64
+    };
+
+    let mut cheapest = (0..prices.len()).collect::<Vec<usize>>();
+    cheapest.sort_by(|a, b| costs[*a].total_cmp(&costs[*b]).then(a.cmp(b)));
+
+    let mut remaining = energy_kwh;
+    let mut slots = Vec::new();
+
+    for index in cheapest {
+        if remaining <= 0.0 {
+            break;
+        }
+
+        let energy = capacity(&prices[index]).min(remaining);
+
+        if energy > 0.0 {
+            slots.push(ChargingSlot {
+                index,
+                energy_kwh: energy,
+            });
+            remaining -= energy;
+        }
+    }
+
+    slots.sort_by_key(|slot| slot.index);
+    slots
+}
+
+/// Run uninterrupted for a number of hours
+#[derive(Debug, Clone)]
+pub(crate) struct Contiguous {
+    pub(crate) duration: usize,
+}
+
+impl Strategy for Contiguous {
+    fn name(&self) -> &'static str {
+        "contiguous"
+    }
+
+    fn select(&self, prices: &[PricePoint], costs: &[f64]) -> Option<Vec<Range<usize>>> {
+        cheapest_run(prices, costs, self.duration, |_| 1.0).map(|range| vec![range])
+    }
+}
+
+/// Run uninterrupted, where the power draw differs per hour of the run. Every hour of a run
+/// is weighted by its share of the consumption, e.g. `[1.0, 0.3, 0.3]` for a dishwasher
+/// that heats its water in the first hour.
+#[derive(Debug, Clone)]
+pub(crate) struct Weighted {
+    pub(crate) weights: Vec<f64>,
+}
+
+impl Strategy for Weighted {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn select(&self, prices: &[PricePoint], costs: &[f64]) -> Option<Vec<Range<usize>>> {
+        cheapest_run(prices, costs, self.weights.len(), |hour| self.weights[hour])
+            .map(|range| vec![range])
+    }
+}
+
+/// Run for a number of hours that do not need to be consecutive, e.g. a boiler that keeps its
+/// heat or a battery that can be charged in parts
+#[derive(Debug, Clone)]
+pub(crate) struct Split {
+    pub(crate) duration: usize,
+}
+
+impl Strategy for Split {
+    fn name(&self) -> &'static str {
+        "split"
+    }
+
+    fn select(&self, prices: &[PricePoint], costs: &[f64]) -> Option<Vec<Range<usize>>> {
+        let duration = duration_minutes(self.duration);
+
+        if duration == 0 || available_minutes(prices) < duration {
+            return None;
+        }
+
+        let mut cheapest = (0..costs.len()).collect::<Vec<usize>>();
+        cheapest.sort_by(|a, b| costs[*a].total_cmp(&costs[*b]).then(a.cmp(b)));
+
+        let mut selected = 0;
+        cheapest.retain(|index| {
+            let fits = selected < duration;
+            selected += prices[*index].resolution_minutes as i64;
+            fits
+        });
+        cheapest.sort();
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for index in cheapest {
+            match ranges.last_mut() {
+                Some(range)
+                    if range.end == index
+                        && are_consecutive(&prices[range.end - 1], &prices[index]) =>
+                {
+                    range.end = index + 1
+                }
+                _ => ranges.push(index..index + 1),
+            }
+        }
+
+        Some(ranges)
+    }
+}
+
+/// Apply another strategy, but only to hours that end before a deadline
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    pub(crate) deadline: DateTime<Utc>,
+    pub(crate) strategy: Box<dyn Strategy>,
+}
+
+impl Strategy for Deadline {
+    fn name(&self) -> &'static str {
+        "deadline"
+    }
+
+    fn select(&self, prices: &[PricePoint], costs: &[f64]) -> Option<Vec<Range<usize>>> {
+        let available = prices
+            .iter()
+            .take_while(|price| ends_at(price) <= self.deadline)
+            .count();
+
+        self.strategy
+            .select(&prices[..available], &costs[..available])
+    }
+}
+
+/// Find the consecutive price points that cover a number of hours with the lowest weighted cost,
+/// earliest first on equal costs. Every price point is weighted by the hour of the run it falls in
+/// and by its share of an hour, so quarter-hour prices count for a quarter.
+fn cheapest_run(
+    prices: &[PricePoint],
+    costs: &[f64],
+    hours: usize,
+    weight: impl Fn(usize) -> f64,
+) -> Option<Range<usize>> {
+    let duration = duration_minutes(hours);
+
+    if duration == 0 || available_minutes(prices) < duration {
+        return None;
+    }
+
+    (0..prices.len())
+        .filter_map(|start| {
+            let mut minutes = 0;
+            let mut cost = 0.0;
+
+            for index in start..prices.len() {
+                if index > start && !are_consecutive(&prices[index - 1], &prices[index]) {
+                    return None;
+                }
+
+                let resolution = prices[index].resolution_minutes as i64;
+                let hour = (minutes / MINUTES_PER_HOUR) as usize;
+
+                cost += costs[index] * weight(hour) * resolution as f64 / MINUTES_PER_HOUR as f64;
+                minutes += resolution;
+
+                if minutes >= duration {
+                    // a run that does not end at the end of a price point would overshoot
+                    return (minutes == duration).then_some((start..index + 1, cost));
+                }
+            }
+
+            None
+        })
+        .min_by(|(a_range, a), (b_range, b)| a.total_cmp(b).then(a_range.start.cmp(&b_range.start)))
+        .map(|(range, _)| range)
+}
+
+/// The number of minutes of a duration in hours
+fn duration_minutes(hours: usize) -> i64 {
+    (hours as i64).saturating_mul(MINUTES_PER_HOUR)
+}
+
+/// The number of minutes the prices cover together
+fn available_minutes(prices: &[PricePoint]) -> i64 {
+    prices
+        .iter()
+        .map(|price| price.resolution_minutes as i64)
+        .sum()
+}
+
+/// The moment a price point stops applying
+fn ends_at(price: &PricePoint) -> DateTime<Utc> {
+    price.moment + TimeDelta::minutes(price.resolution_minutes as i64)
+}
+
+fn are_consecutive(first: &PricePoint, second: &PricePoint) -> bool {
+    ends_at(first) == second.moment
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use crate::test_support::price_point;
+
+    fn prices(amounts: &[f64]) -> Vec<PricePoint> {
+        let start = DateTime::parse_from_rfc3339("2024-06-30T00:00:00+00:00")
+            .unwrap()
+            .to_utc();
+
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(hour, amount)| price_point(start + TimeDelta::hours(hour as i64), *amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_contiguous() {
+        let prices = prices(&[0.3, 0.1, 0.2, 0.05, 0.4, 0.1]);
+        let costs = prices
+            .iter()
+            .map(|p| p.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        assert_eq!(
+            Contiguous { duration: 2 }.select(&prices, &costs),
+            Some(vec![2..4])
+        );
+        assert_eq!(Contiguous { duration: 7 }.select(&prices, &costs), None);
+    }
+
+    #[test]
+    fn test_contiguous_skips_gaps() {
+        let mut prices = prices(&[0.3, 0.1, 0.1, 0.4]);
+        prices.remove(2);
+        let costs = prices
+            .iter()
+            .map(|p| p.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        assert_eq!(
+            Contiguous { duration: 2 }.select(&prices, &costs),
+            Some(vec![0..2])
+        );
+    }
+
+    #[test]
+    fn test_quarter_hours() {
+        let mut prices = prices(&[0.3, 0.1, 0.1, 0.2, 0.05, 0.05, 0.05, 0.4]);
+        for (quarter, price) in prices.iter_mut().enumerate() {
+            price.moment -= TimeDelta::minutes(45 * quarter as i64);
+            price.resolution_minutes = 15;
+        }
+        let costs = costs(&prices, None);
+
+        // an hour takes four quarters
+        assert_eq!(
+            Contiguous { duration: 1 }.select(&prices, &costs),
+            Some(vec![3..7])
+        );
+        assert_eq!(Contiguous { duration: 3 }.select(&prices, &costs), None);
+        assert_eq!(
+            Split { duration: 1 }.select(&prices, &costs),
+            Some(vec![1..2, 4..7])
+        );
+
+        let windows = optimize(&Contiguous { duration: 1 }, &prices, None).unwrap();
+        assert_eq!(
+            windows[0].ends_at,
+            DateTime::parse_from_rfc3339("2024-06-30T01:44:59+00:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let prices = prices(&[0.3, 0.1, 0.2, 0.05, 0.4]);
+        let costs = prices
+            .iter()
+            .map(|p| p.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        assert_eq!(
+            Weighted {
+                weights: vec![1.0, 0.1]
+            }
+            .select(&prices, &costs),
+            Some(vec![3..5])
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let prices = prices(&[0.3, 0.1, 0.2, 0.05, 0.04, 0.4]);
+        let costs = prices
+            .iter()
+            .map(|p| p.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        assert_eq!(
+            Split { duration: 3 }.select(&prices, &costs),
+            Some(vec![1..2, 3..5])
+        );
+    }
+
+    #[test]
+    fn test_deadline() {
+        let prices = prices(&[0.3, 0.1, 0.2, 0.05, 0.04, 0.4]);
+        let costs = prices
+            .iter()
+            .map(|p| p.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        let strategy = Deadline {
+            deadline: prices[3].moment,
+            strategy: Box::new(Contiguous { duration: 2 }),
+        };
+
+        assert_eq!(strategy.select(&prices, &costs), Some(vec![1..3]));
+    }
+
+    #[test]
+    fn test_plan_charging() {
+        let prices = prices(&[0.3, 0.1, 0.2, 0.05, 0.4]);
+        let costs = costs(&prices, None);
+
+        // departing halfway the cheapest hour, so it only takes half of the maximum power
+        let slots = plan_charging(
+            &prices,
+            &costs,
+            10.0,
+            4.0,
+            prices[0].moment,
+            prices[3].moment + TimeDelta::minutes(30),
+        );
+
+        assert_eq!(
+            slots,
+            vec![
+                ChargingSlot {
+                    index: 1,
+                    energy_kwh: 4.0
+                },
+                ChargingSlot {
+                    index: 2,
+                    energy_kwh: 4.0
+                },
+                ChargingSlot {
+                    index: 3,
+                    energy_kwh: 2.0
+                },
+            ]
+        );
+    }
+}
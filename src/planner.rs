@@ -0,0 +1,585 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, DurationRound, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    consumption_forecast::{learn_profile, ConsumptionProfile},
+    device::{Device, DeviceRun, PlannedRun, Schedule, ScheduleOverride},
+    domain::{PricePoint, PriceWindow, SolarProduction},
+    optimizer::{costs, optimize_costs},
+    planned_window_repository::NewPlannedWindow,
+    self_consumption::{forecast_production, self_consumption_costs, DEFAULT_LOAD_KW},
+    setup::AppState,
+};
+
+/// How long windows that were recorded are not recorded again for the same inputs, so clients that
+/// poll for windows do not write to the database on every request
+const RECORDED_WINDOWS_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// When the windows of every source and inputs were last recorded, by their hash
+static RECORDED_WINDOWS_AT: Mutex<BTreeMap<u64, Instant>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RunStatus {
+    /// The device ran and finished
+    Completed,
+    /// The device reported a start, but no finish yet
+    Running,
+    /// The occurrence has passed without the device reporting a start
+    Missed,
+    /// The occurrence has not passed yet
+    Pending,
+    /// The device ran outside any occurrence of its schedules
+    Unplanned,
+}
+
+/// How a run of a device compares to what was planned
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RunReport {
+    pub(crate) schedule_id: Option<i64>,
+    pub(crate) date: Option<NaiveDate>,
+    pub(crate) status: RunStatus,
+    pub(crate) planned_windows: Vec<PriceWindow>,
+    pub(crate) planned_average_price: Option<f64>,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+    pub(crate) finished_at: Option<DateTime<Utc>>,
+    /// The average price of the hours the device actually ran in
+    pub(crate) actual_average_price: Option<f64>,
+}
+
+/// The power that planned runs draw per hour on top of the baseline of the rest of the
+/// household, which must stay within the power cap of the household
+#[derive(Debug, Clone, Default)]
+struct HouseholdLoad {
+    cap_kw: Option<f64>,
+    planned_kw: HashMap<DateTime<Utc>, f64>,
+    /// The typical consumption of the household in the local hours of the week
+    baseline: Option<(ConsumptionProfile, Tz)>,
+}
+
+impl HouseholdLoad {
+    fn new(cap_kw: Option<f64>) -> Self {
+        Self {
+            cap_kw,
+            planned_kw: HashMap::new(),
+            baseline: None,
+        }
+    }
+
+    /// The load with the baseline that the rest of the household is expected to draw
+    fn with_baseline(self, profile: ConsumptionProfile, timezone: Tz) -> Self {
+        Self {
+            baseline: Some((profile, timezone)),
+            ..self
+        }
+    }
+
+    /// Whether a device drawing `power_kw` can run in the hour starting at `moment`. Devices of
+    /// which the power is unknown always fit.
+    fn fits(&self, moment: DateTime<Utc>, power_kw: Option<f64>) -> bool {
+        match (self.cap_kw, power_kw) {
+            (Some(cap), Some(power)) => self.planned(moment) + power <= cap + f64::EPSILON,
+            _ => true,
+        }
+    }
+
+    /// The power that planned runs and the baseline draw in the hour starting at `moment`
+    fn planned(&self, moment: DateTime<Utc>) -> f64 {
+        let baseline = self
+            .baseline
+            .as_ref()
+            .map(|(profile, timezone)| profile.expected_kwh(timezone, moment))
+            .unwrap_or(0.0);
+
+        self.planned_kw.get(&moment).copied().unwrap_or(0.0) + baseline
+    }
+
+    fn add(&mut self, windows: &[PriceWindow], power_kw: Option<f64>) {
+        let Some(power) = power_kw else {
+            return;
+        };
+
+        for window in windows {
+            let mut hour = window.starts_at.to_utc();
+
+            while hour < window.ends_at {
+                *self.planned_kw.entry(hour).or_default() += power;
+                hour += TimeDelta::hours(1);
+            }
+        }
+    }
+}
+
+/// Plan the next runs of all registered devices, returning every device with its schedules
+pub(crate) async fn plan_all_devices(
+    state: &AppState,
+    now: DateTime<Utc>,
+) -> Result<Vec<(Device, Vec<Schedule>, Option<PlannedRun>)>, String> {
+    let mut devices = Vec::new();
+
+    for device in state.device_repository.fetch_devices().await? {
+        let (schedules, overrides) = schedules_of(state, &device).await?;
+        devices.push((device, schedules, overrides));
+    }
+
+    let next_runs = plan_next_runs(state, &devices, now).await?;
+
+    Ok(devices
+        .into_iter()
+        .zip(next_runs)
+        .map(|((device, schedules, _), next_run)| (device, schedules, next_run))
+        .collect())
+}
+
+/// Plan the next runs of devices, each with its schedules and overrides, so that together with
+/// the typical consumption of the rest of the household they stay within its power cap. Devices
+/// that use the most energy per run are planned first, as they gain the most from cheap hours;
+/// the runs of lighter devices shift to other hours when they would exceed the cap.
+/// The planned runs are returned in the order of the devices.
+async fn plan_next_runs(
+    state: &AppState,
+    devices: &[(Device, Vec<Schedule>, Vec<ScheduleOverride>)],
+    now: DateTime<Utc>,
+) -> Result<Vec<Option<PlannedRun>>, String> {
+    let mut order = (0..devices.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| energy(&devices[*b].0).total_cmp(&energy(&devices[*a].0)));
+
+    let mut load = HouseholdLoad::new(state.settings().scheduling.household_power_cap_kw);
+
+    if let Some(profile) = learn_profile(state, now).await? {
+        load = load.with_baseline(profile, state.timezone);
+    }
+    let mut runs = vec![None; devices.len()];
+
+    for index in order {
+        let (device, schedules, overrides) = &devices[index];
+        let run = plan_next_run(state, device, schedules, overrides, &load, now).await?;
+
+        if let Some(run) = &run {
+            load.add(&run.windows, device.power_kw);
+        }
+
+        runs[index] = run;
+    }
+
+    Ok(runs)
+}
+
+/// The energy a run of a device uses in kWh, zero when its power is unknown
+fn energy(device: &Device) -> f64 {
+    device.power_kw.unwrap_or(0.0) * device.duration as f64
+}
+
+/// Plan the next run of a device, in the earliest upcoming occurrence of its enabled schedules
+/// that is not skipped by an override, leaves the minimum gap after the latest run and starts
+/// after the device's snooze.
+/// The run has no windows yet when the prices of the occurrence are not known.
+async fn plan_next_run(
+    state: &AppState,
+    device: &Device,
+    schedules: &[Schedule],
+    overrides: &[ScheduleOverride],
+    load: &HouseholdLoad,
+    now: DateTime<Utc>,
+) -> Result<Option<PlannedRun>, String> {
+    let not_before = next_start(state, device, now)
+        .await?
+        .max(device.snoozed_until.unwrap_or(now));
+
+    let next = schedules
+        .iter()
+        .filter(|schedule| schedule.enabled)
+        .filter_map(|schedule| {
+            schedule
+                .next_period(&state.timezone, not_before, device.duration, overrides)
+                .map(|(date, start, end)| (schedule, date, start, end))
+        })
+        .min_by_key(|(_, _, start, _)| *start);
+
+    let Some((schedule, date, period_start, period_end)) = next else {
+        return Ok(None);
+    };
+
+    let (run, prices, production) = plan_run(
+        state,
+        device,
+        load,
+        schedule.id,
+        date,
+        period_start,
+        period_end,
+    )
+    .await?;
+
+    let inputs = json!({
+        "device": device,
+        "schedule_id": run.schedule_id,
+        "date": run.date,
+        "period_start": run.period_start,
+        "period_end": run.period_end,
+        "price_cap": state.settings().pricing.price_cap.as_ref().map(|cap| cap.rate),
+        "household_power_cap_kw": state.settings().scheduling.household_power_cap_kw,
+        "prices": price_inputs(&prices),
+        "solar_forecast": production,
+    });
+
+    record_planned_windows(state, "device", Some(device.id), &run.windows, &inputs).await;
+
+    Ok(Some(run))
+}
+
+/// Plan a run of a device within the period of an occurrence, in the hours it fits within the
+/// power cap next to the load that is already planned. When a solar forecast is configured, the
+/// hours are optimized on the cost of what the solar panels do not cover, counting only the
+/// production that the planned load leaves. That production is returned with the run and its
+/// prices.
+async fn plan_run(
+    state: &AppState,
+    device: &Device,
+    load: &HouseholdLoad,
+    schedule_id: i64,
+    date: NaiveDate,
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+) -> Result<(PlannedRun, Vec<PricePoint>, Vec<SolarProduction>), String> {
+    let mut prices = state
+        .price_repository
+        .fetch_prices(period_start, period_end, state.price_area(None).as_deref())
+        .await?;
+
+    prices.retain(|price| load.fits(price.moment, device.power_kw));
+
+    let price_cap = state
+        .settings()
+        .pricing
+        .price_cap
+        .as_ref()
+        .map(|cap| cap.rate);
+
+    let production = forecast_production(state, period_start, period_end)
+        .await?
+        .into_iter()
+        .map(|hour| SolarProduction {
+            energy_kwh: (hour.energy_kwh - load.planned(hour.moment)).max(0.0),
+            ..hour
+        })
+        .collect::<Vec<SolarProduction>>();
+
+    let costs = self_consumption_costs(
+        &prices,
+        &costs(&prices, price_cap),
+        &production,
+        device.power_kw.unwrap_or(DEFAULT_LOAD_KW),
+    );
+
+    let windows = optimize_costs(&*device.strategy(), &prices, &costs, price_cap)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|window| window.with_timezone(state.timezone))
+        .collect();
+
+    let run = PlannedRun {
+        schedule_id,
+        date,
+        period_start,
+        period_end,
+        windows,
+    };
+
+    Ok((run, prices, production))
+}
+
+/// Store the windows that are handed out with the inputs they were chosen from, to explain
+/// later why a device ran when it did. Windows are only stored when they or their inputs changed
+/// since they were last recorded. Failing to store them does not fail the plan.
+pub(crate) async fn record_planned_windows(
+    state: &AppState,
+    source: &'static str,
+    device_id: Option<i64>,
+    windows: &[PriceWindow],
+    inputs: &serde_json::Value,
+) {
+    let mut hasher = DefaultHasher::new();
+    (source, device_id).hash(&mut hasher);
+    serde_json::to_string(windows)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    inputs.to_string().hash(&mut hasher);
+    let key = hasher.finish();
+
+    let now = Instant::now();
+
+    {
+        let mut recorded_at = RECORDED_WINDOWS_AT
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        recorded_at.retain(|_, at| now.duration_since(*at) < RECORDED_WINDOWS_INTERVAL);
+
+        if recorded_at.contains_key(&key) {
+            return;
+        }
+    }
+
+    let planned = windows
+        .iter()
+        .map(|window| NewPlannedWindow {
+            source,
+            device_id,
+            window,
+            inputs,
+        })
+        .collect::<Vec<NewPlannedWindow>>();
+
+    if let Err(e) = state
+        .planned_window_repository
+        .persist_planned_windows(&planned)
+        .await
+    {
+        warn!("unable to record the planned windows, {}", e);
+        return;
+    }
+
+    RECORDED_WINDOWS_AT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, now);
+}
+
+/// The prices a plan was chosen from, as inputs of planned windows
+pub(crate) fn price_inputs(prices: &[PricePoint]) -> serde_json::Value {
+    prices
+        .iter()
+        .map(|price| {
+            json!({
+                "moment": price.moment,
+                "price": price.monetary_amount,
+                "consumer_price": price.consumer_amount,
+            })
+        })
+        .collect()
+}
+
+/// Record the start of a run, within the occurrence of an enabled schedule that contains the
+/// moment it started
+pub(crate) async fn record_run_start(
+    state: &AppState,
+    device: &Device,
+    started_at: DateTime<Utc>,
+) -> Result<DeviceRun, String> {
+    let (schedules, overrides) = schedules_of(state, device).await?;
+
+    let occurrence = schedules
+        .iter()
+        .filter(|schedule| schedule.enabled)
+        .flat_map(|schedule| {
+            schedule
+                .occurrences(
+                    &state.timezone,
+                    started_at - TimeDelta::days(2),
+                    started_at + TimeDelta::seconds(1),
+                    &overrides,
+                )
+                .into_iter()
+                .map(|(date, start, end)| (schedule.id, date, start, end))
+        })
+        .find(|(_, _, start, end)| *start <= started_at && started_at < *end)
+        .map(|(schedule_id, date, _, _)| (schedule_id, date));
+
+    state
+        .device_repository
+        .persist_run_start(device.id, occurrence, started_at)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Compare the runs of a device within a period to the runs planned in the occurrences of its
+/// enabled schedules
+pub(crate) async fn run_reports(
+    state: &AppState,
+    device: &Device,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<RunReport>, String> {
+    let now = Utc::now();
+    let (schedules, overrides) = schedules_of(state, device).await?;
+
+    let runs = state
+        .device_repository
+        .fetch_runs(device.id, start, end)
+        .await?;
+
+    let mut reports = Vec::new();
+
+    for schedule in schedules.iter().filter(|schedule| schedule.enabled) {
+        for (date, period_start, period_end) in
+            schedule.occurrences(&state.timezone, start, end, &overrides)
+        {
+            let not_before = next_start(state, device, period_start).await?;
+
+            let (planned, _, _) = plan_run(
+                state,
+                device,
+                &HouseholdLoad::default(),
+                schedule.id,
+                date,
+                period_start.max(not_before),
+                period_end,
+            )
+            .await?;
+
+            let run = runs
+                .iter()
+                .find(|run| run.schedule_id == Some(schedule.id) && run.date == Some(date));
+
+            let status = match run {
+                Some(run) if run.finished_at.is_some() => RunStatus::Completed,
+                Some(_) => RunStatus::Running,
+                None if period_end <= now => RunStatus::Missed,
+                None => RunStatus::Pending,
+            };
+
+            reports.push(RunReport {
+                schedule_id: Some(schedule.id),
+                date: Some(date),
+                status,
+                planned_average_price: average_price(&planned.windows),
+                planned_windows: planned.windows,
+                started_at: run.map(|run| run.started_at),
+                finished_at: run.and_then(|run| run.finished_at),
+                actual_average_price: match run {
+                    Some(run) => actual_average_price(state, run).await?,
+                    None => None,
+                },
+            });
+        }
+    }
+
+    for run in runs.iter().filter(|run| run.schedule_id.is_none()) {
+        reports.push(RunReport {
+            schedule_id: None,
+            date: None,
+            status: RunStatus::Unplanned,
+            planned_windows: Vec::new(),
+            planned_average_price: None,
+            started_at: Some(run.started_at),
+            finished_at: run.finished_at,
+            actual_average_price: actual_average_price(state, run).await?,
+        });
+    }
+
+    reports.sort_by_key(|report| {
+        report
+            .started_at
+            .or(report.planned_windows.first().map(|w| w.starts_at.to_utc()))
+    });
+
+    Ok(reports)
+}
+
+/// The earliest moment a run that starts at or after `moment` may start, given the minimum gap
+/// after the latest run before it
+async fn next_start(
+    state: &AppState,
+    device: &Device,
+    moment: DateTime<Utc>,
+) -> Result<DateTime<Utc>, String> {
+    if device.min_gap_hours.is_none() {
+        return Ok(moment);
+    }
+
+    let previous = state
+        .device_repository
+        .fetch_latest_run(device.id, moment)
+        .await?;
+
+    Ok(previous
+        .and_then(|previous| device.next_start_after(&previous))
+        .map_or(moment, |start| start.max(moment)))
+}
+
+async fn schedules_of(
+    state: &AppState,
+    device: &Device,
+) -> Result<(Vec<Schedule>, Vec<ScheduleOverride>), String> {
+    let schedules = state.device_repository.fetch_schedules(device.id).await?;
+
+    let overrides = state
+        .device_repository
+        .fetch_overrides(&schedules.iter().map(|s| s.id).collect::<Vec<i64>>())
+        .await?;
+
+    Ok((schedules, overrides))
+}
+
+/// The average price over the hours of the windows
+fn average_price(windows: &[PriceWindow]) -> Option<f64> {
+    let (sum, hours) = windows
+        .iter()
+        .try_fold((0.0, 0.0), |(sum, hours), window| {
+            let window_hours =
+                ((window.ends_at - window.starts_at).num_seconds() + 1) as f64 / 3600.0;
+            window
+                .effective_average_price()
+                .map(|price| (sum + price * window_hours, hours + window_hours))
+        })?;
+
+    (hours > 0.0).then(|| sum / hours)
+}
+
+/// The average price of the hours a run spans, until now while it is running
+async fn actual_average_price(state: &AppState, run: &DeviceRun) -> Result<Option<f64>, String> {
+    let start = run
+        .started_at
+        .duration_trunc(TimeDelta::hours(1))
+        .unwrap_or(run.started_at);
+
+    let statistics = state
+        .price_repository
+        .fetch_price_statistics(
+            start,
+            run.finished_at.unwrap_or(Utc::now()),
+            state.price_area(None).as_deref(),
+        )
+        .await?;
+
+    Ok(statistics.average)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::moment;
+
+    #[test]
+    fn test_household_load() {
+        let mut load = HouseholdLoad::new(Some(5.0));
+
+        load.add(
+            &[PriceWindow {
+                starts_at: moment("2024-06-30T02:00:00Z").fixed_offset(),
+                ends_at: moment("2024-06-30T03:59:59Z").fixed_offset(),
+                average_price: "0.10".to_string(),
+                average_consumer_price: None,
+                average_capped_price: None,
+                components: None,
+                currency: "EUR".to_string(),
+            }],
+            Some(3.0),
+        );
+
+        assert!(!load.fits(moment("2024-06-30T03:00:00Z"), Some(2.5)));
+        assert!(load.fits(moment("2024-06-30T03:00:00Z"), Some(2.0)));
+        assert!(load.fits(moment("2024-06-30T04:00:00Z"), Some(2.5)));
+        assert!(load.fits(moment("2024-06-30T03:00:00Z"), None));
+    }
+}
@@ -0,0 +1,601 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+use crate::{
+    backup::{Backup, BackupPrice, Restoration},
+    backup_repository::{BackupRepository, BackupRepositoryError},
+    domain::{PriceFetch, PricePoint, PriceStatistics, PriceWindow},
+    price_repository::{
+        DeletedPrices, IngestedPrices, PriceGap, PriceRepository, PriceRepositoryError,
+        PriceVersion,
+    },
+};
+
+/// The most days of prices that are cached at once for a single range, longer ranges such as
+/// those of reports are always read from the repository
+const MAX_CACHED_DAYS: i64 = 7;
+
+/// The most ranges that are cached, beyond which the cache starts over
+const MAX_CACHED_RANGES: usize = 256;
+
+/// The first and last day of a range of prices, with their area
+type CacheKey = (DateTime<Utc>, DateTime<Utc>, Option<String>);
+
+/// The range, durations and area of computed windows, with everything else they were computed
+/// of besides the prices
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct WindowKey {
+    pub(crate) start_moment: DateTime<Utc>,
+    pub(crate) end_moment: DateTime<Utc>,
+    pub(crate) durations: Vec<i32>,
+    pub(crate) area: Option<String>,
+    /// The price cap, the power of the load and the solar forecast as JSON, so windows are
+    /// computed again when any of them changes
+    pub(crate) inputs: String,
+}
+
+struct Cached<V> {
+    cached_at: Instant,
+    value: Arc<V>,
+}
+
+struct Cache<K, V> {
+    entries: HashMap<K, Cached<V>>,
+    /// Raised on every invalidation, so what was read before it is not cached after it
+    generation: u64,
+}
+
+impl<K, V> Default for Cache<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            generation: 0,
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> Cache<K, V> {
+    fn invalidate(&mut self) {
+        self.entries.clear();
+        self.generation += 1;
+    }
+
+    fn cached(&self, key: &K, ttl: Duration) -> Option<Arc<V>> {
+        self.entries
+            .get(key)
+            .filter(|cached| cached.cached_at.elapsed() < ttl)
+            .map(|cached| cached.value.clone())
+    }
+
+    fn cache(&mut self, key: K, generation: u64, value: Arc<V>, ttl: Duration) {
+        if self.generation != generation {
+            return;
+        }
+
+        self.entries
+            .retain(|_, cached| cached.cached_at.elapsed() < ttl);
+
+        if self.entries.len() >= MAX_CACHED_RANGES {
+            self.entries.clear();
+        }
+
+        self.entries.insert(
+            key,
+            Cached {
+                cached_at: Instant::now(),
+                value,
+            },
+        );
+    }
+}
+
+/// Keeps the recent days of prices in memory for a while, so devices that poll every minute do
+/// not query the database every time. Prices are cached per whole UTC day, and forgotten when
+/// this instance persists, deletes, prunes or restores prices, or when another instance that
+/// shares a PostgreSQL database persists, deletes or prunes them.
+pub(crate) struct CachedPriceRepository {
+    repository: Arc<dyn PriceRepository>,
+    ttl: Duration,
+    cache: Mutex<Cache<CacheKey, Vec<PricePoint>>>,
+    windows: Arc<WindowCache>,
+}
+
+impl CachedPriceRepository {
+    pub(crate) fn new(repository: Arc<dyn PriceRepository>, ttl: Duration) -> Self {
+        Self {
+            repository,
+            ttl,
+            cache: Mutex::default(),
+            windows: Arc::new(WindowCache::new(ttl)),
+        }
+    }
+
+    /// The windows computed of the cached prices, which are forgotten along with them
+    pub(crate) fn windows(&self) -> Arc<WindowCache> {
+        self.windows.clone()
+    }
+
+    /// Forget the cached prices whenever any instance that shares the database persists or
+    /// deletes prices
+    pub(crate) async fn invalidate_on_changed_prices(self: Arc<Self>) {
+        let listening = tokio::try_join!(
+            self.repository.listen_for_ingested_prices(),
+            self.repository.listen_for_deleted_prices()
+        );
+
+        let (mut ingestions, mut deletions) = match listening {
+            Ok(receivers) => receivers,
+            Err(e) => {
+                error!(
+                    "unable to listen for changed prices to invalidate the cache, {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                Some(_) = ingestions.recv() => self.invalidate(),
+                Some(_) = deletions.recv() => self.invalidate(),
+                else => return,
+            }
+        }
+    }
+
+    fn invalidate(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.invalidate();
+        }
+
+        self.windows.invalidate();
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Arc<Vec<PricePoint>>> {
+        self.cache.lock().ok()?.cached(key, self.ttl)
+    }
+
+    fn cache(&self, key: CacheKey, generation: u64, prices: Arc<Vec<PricePoint>>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.cache(key, generation, prices, self.ttl);
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.cache
+            .lock()
+            .map(|cache| cache.generation)
+            .unwrap_or_default()
+    }
+}
+
+/// Keeps the windows that `/time-slots` computed for a while, so devices that poll every minute
+/// for the same range and durations do not optimize the prices every time. Windows are forgotten
+/// along with the cached prices, and are not cached at all without a price cache.
+pub(crate) struct WindowCache {
+    ttl: Option<Duration>,
+    cache: Mutex<Cache<WindowKey, Vec<PriceWindow>>>,
+}
+
+impl WindowCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            cache: Mutex::default(),
+        }
+    }
+
+    /// A cache that caches nothing, for when prices are not cached either
+    pub(crate) fn disabled() -> Self {
+        Self {
+            ttl: None,
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Changes when the windows are forgotten, to take before reading the prices they are
+    /// computed of, so windows of prices that changed in the meantime are not cached
+    pub(crate) fn generation(&self) -> u64 {
+        self.cache
+            .lock()
+            .map(|cache| cache.generation)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn cached(&self, key: &WindowKey) -> Option<Arc<Vec<PriceWindow>>> {
+        self.cache.lock().ok()?.cached(key, self.ttl?)
+    }
+
+    pub(crate) fn cache(&self, key: WindowKey, generation: u64, windows: Arc<Vec<PriceWindow>>) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.cache(key, generation, windows, ttl);
+        }
+    }
+
+    fn invalidate(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.invalidate();
+        }
+    }
+}
+
+#[async_trait]
+impl PriceRepository for CachedPriceRepository {
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String> {
+        let day = TimeDelta::days(1);
+        let (Ok(first_day), Ok(last_day)) = (
+            start_moment.duration_trunc(day),
+            (end_moment - TimeDelta::nanoseconds(1)).duration_trunc(day),
+        ) else {
+            return self
+                .repository
+                .fetch_prices(start_moment, end_moment, area)
+                .await;
+        };
+        let end_day = last_day + day;
+
+        if end_moment <= start_moment || end_day - first_day > TimeDelta::days(MAX_CACHED_DAYS) {
+            return self
+                .repository
+                .fetch_prices(start_moment, end_moment, area)
+                .await;
+        }
+
+        let key = (first_day, end_day, area.map(str::to_string));
+
+        let prices = match self.cached(&key) {
+            Some(prices) => prices,
+            None => {
+                debug!("caching the prices from {} until {}", first_day, end_day);
+
+                let generation = self.generation();
+                let prices = Arc::new(
+                    self.repository
+                        .fetch_prices(first_day, end_day, area)
+                        .await?,
+                );
+
+                self.cache(key, generation, prices.clone());
+                prices
+            }
+        };
+
+        Ok(prices
+            .iter()
+            .filter(|price| price.moment >= start_moment && price.moment < end_moment)
+            .cloned()
+            .collect())
+    }
+
+    async fn persist_prices(
+        &self,
+        prices: &[PricePoint],
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        let persisted = self.repository.persist_prices(prices, provider_name).await;
+        self.invalidate();
+
+        persisted
+    }
+
+    async fn record_price_fetch(
+        &self,
+        fetch: &PriceFetch,
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        self.repository
+            .record_price_fetch(fetch, provider_name)
+            .await
+    }
+
+    async fn fetch_price_statistics(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<PriceStatistics, String> {
+        self.repository
+            .fetch_price_statistics(start_moment, end_moment, area)
+            .await
+    }
+
+    async fn fetch_price_versions(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceVersion>, String> {
+        self.repository
+            .fetch_price_versions(start_moment, end_moment, area)
+            .await
+    }
+
+    async fn fetch_price_moments(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        self.repository
+            .fetch_price_moments(start_moment, end_moment, area)
+            .await
+    }
+
+    async fn fetch_price_gaps(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceGap>, String> {
+        self.repository
+            .fetch_price_gaps(start_moment, end_moment, area)
+            .await
+    }
+
+    async fn delete_prices(
+        &self,
+        provider_name: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<u64, PriceRepositoryError> {
+        let deleted = self
+            .repository
+            .delete_prices(provider_name, start_moment, end_moment, area)
+            .await;
+        self.invalidate();
+
+        deleted
+    }
+
+    async fn prune_prices(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        let pruned = self.repository.prune_prices(before).await;
+        self.invalidate();
+
+        pruned
+    }
+
+    async fn prune_price_fetches(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        self.repository.prune_price_fetches(before).await
+    }
+
+    async fn create_price_partitions(&self, moments: &[DateTime<Utc>]) -> Result<(), String> {
+        self.repository.create_price_partitions(moments).await
+    }
+
+    async fn listen_for_ingested_prices(&self) -> Result<mpsc::Receiver<IngestedPrices>, String> {
+        self.repository.listen_for_ingested_prices().await
+    }
+
+    async fn listen_for_deleted_prices(&self) -> Result<mpsc::Receiver<DeletedPrices>, String> {
+        self.repository.listen_for_deleted_prices().await
+    }
+}
+
+/// Restores backups and imports through another repository, after which the cached prices are
+/// forgotten, as the restored prices bypass the price repository
+pub(crate) struct InvalidatingBackupRepository {
+    repository: Arc<dyn BackupRepository>,
+    cache: Arc<CachedPriceRepository>,
+}
+
+impl InvalidatingBackupRepository {
+    pub(crate) fn new(
+        repository: Arc<dyn BackupRepository>,
+        cache: Arc<CachedPriceRepository>,
+    ) -> Self {
+        Self { repository, cache }
+    }
+}
+
+#[async_trait]
+impl BackupRepository for InvalidatingBackupRepository {
+    async fn fetch_backup(&self) -> Result<Backup, String> {
+        self.repository.fetch_backup().await
+    }
+
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        provider: Option<&str>,
+        area: Option<&str>,
+    ) -> Result<Vec<BackupPrice>, String> {
+        self.repository
+            .fetch_prices(start_moment, end_moment, provider, area)
+            .await
+    }
+
+    async fn restore_backup(&self, backup: &Backup) -> Result<Restoration, BackupRepositoryError> {
+        let restored = self.repository.restore_backup(backup).await;
+        self.cache.invalidate();
+
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::MemoryPriceRepository,
+        price_export::import_backup,
+        test_support::{moment, price_point},
+    };
+
+    fn price(time: &str, amount: f64) -> PricePoint {
+        price_point(moment(time), amount)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices() {
+        let repository = Arc::new(MemoryPriceRepository::new());
+        let cached = CachedPriceRepository::new(repository.clone(), Duration::from_secs(60));
+
+        cached
+            .persist_prices(
+                &[
+                    price("2024-06-30T08:00:00Z", 0.1),
+                    price("2024-06-30T09:00:00Z", 0.2),
+                ],
+                "tibber",
+            )
+            .await
+            .unwrap();
+
+        let fetch = || {
+            cached.fetch_prices(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-06-30T12:00:00Z"),
+                None,
+            )
+        };
+
+        assert_eq!(fetch().await.unwrap().len(), 1);
+
+        // prices persisted elsewhere are not seen until the cache is invalidated
+        repository
+            .persist_prices(&[price("2024-06-30T10:00:00Z", 0.3)], "tibber")
+            .await
+            .unwrap();
+
+        assert_eq!(fetch().await.unwrap().len(), 1);
+
+        cached
+            .persist_prices(&[price("2024-06-30T11:00:00Z", 0.4)], "tibber")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fetch()
+                .await
+                .unwrap()
+                .iter()
+                .map(|price| price.monetary_amount)
+                .collect::<Vec<f64>>(),
+            vec![0.2, 0.3, 0.4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_windows_are_forgotten_with_prices() {
+        let cached = CachedPriceRepository::new(
+            Arc::new(MemoryPriceRepository::new()),
+            Duration::from_secs(60),
+        );
+        let windows = cached.windows();
+        let key = WindowKey {
+            start_moment: moment("2024-06-30T00:00:00Z"),
+            end_moment: moment("2024-07-01T00:00:00Z"),
+            durations: vec![3],
+            area: None,
+            inputs: "{}".to_string(),
+        };
+
+        let generation = windows.generation();
+        windows.cache(key.clone(), generation, Arc::new(vec![]));
+
+        assert!(windows.cached(&key).is_some());
+        assert!(windows
+            .cached(&WindowKey {
+                durations: vec![2],
+                ..key.clone()
+            })
+            .is_none());
+
+        cached
+            .persist_prices(&[price("2024-06-30T10:00:00Z", 0.3)], "tibber")
+            .await
+            .unwrap();
+
+        assert!(windows.cached(&key).is_none());
+
+        // windows computed of prices read before they changed are not cached
+        windows.cache(key.clone(), generation, Arc::new(vec![]));
+        assert!(windows.cached(&key).is_none());
+
+        let disabled = WindowCache::disabled();
+        disabled.cache(key.clone(), disabled.generation(), Arc::new(vec![]));
+        assert!(disabled.cached(&key).is_none());
+    }
+
+    /// Restores by storing a price in the repository behind the cache, as a database would
+    struct RestoringRepository(Arc<MemoryPriceRepository>);
+
+    #[async_trait]
+    impl BackupRepository for RestoringRepository {
+        async fn fetch_backup(&self) -> Result<Backup, String> {
+            Err("not backed up".to_string())
+        }
+
+        async fn fetch_prices(
+            &self,
+            _start_moment: DateTime<Utc>,
+            _end_moment: DateTime<Utc>,
+            _provider: Option<&str>,
+            _area: Option<&str>,
+        ) -> Result<Vec<BackupPrice>, String> {
+            Ok(vec![])
+        }
+
+        async fn restore_backup(
+            &self,
+            _backup: &Backup,
+        ) -> Result<Restoration, BackupRepositoryError> {
+            self.0
+                .persist_prices(&[price("2024-06-30T10:00:00Z", 0.3)], "tibber")
+                .await
+                .map_err(|e| BackupRepositoryError::PersistenceError(e.to_string()))?;
+
+            Ok(Restoration::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_invalidates() {
+        let repository = Arc::new(MemoryPriceRepository::new());
+        let cached = Arc::new(CachedPriceRepository::new(
+            repository.clone(),
+            Duration::from_secs(60),
+        ));
+        let backups = InvalidatingBackupRepository::new(
+            Arc::new(RestoringRepository(repository)),
+            cached.clone(),
+        );
+
+        let fetch = || {
+            cached.fetch_prices(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-06-30T12:00:00Z"),
+                None,
+            )
+        };
+
+        assert!(fetch().await.unwrap().is_empty());
+
+        backups
+            .restore_backup(&import_backup(vec![]).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(fetch().await.unwrap().len(), 1);
+    }
+}
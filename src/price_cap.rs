@@ -0,0 +1,38 @@
+/// A government price cap scheme, where consumption up to a threshold is charged at most a
+/// capped rate, e.g. the Dutch "prijsplafond" of 2023
+#[derive(Debug, Clone)]
+pub(crate) struct PriceCap {
+    /// The maximum price per kWh a consumer pays while under the threshold
+    pub(crate) rate: f64,
+    /// The consumption in kWh per period up to which the cap applies, unlimited when absent
+    pub(crate) threshold_kwh: Option<f64>,
+}
+
+impl PriceCap {
+    /// The capped rate given the consumption so far in the current period, or `None` when the
+    /// threshold has been reached and market prices apply again.
+    /// Unknown consumption is assumed to be below the threshold.
+    pub(crate) fn rate_for(&self, consumed_kwh: Option<f64>) -> Option<f64> {
+        match (self.threshold_kwh, consumed_kwh) {
+            (Some(threshold), Some(consumed)) if consumed >= threshold => None,
+            _ => Some(self.rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_for_consumption() {
+        let cap = PriceCap {
+            rate: 0.40,
+            threshold_kwh: Some(2900.0),
+        };
+
+        assert_eq!(cap.rate_for(None), Some(0.40));
+        assert_eq!(cap.rate_for(Some(1200.0)), Some(0.40));
+        assert_eq!(cap.rate_for(Some(2900.0)), None);
+    }
+}
@@ -0,0 +1,264 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    backup::{Backup, BackupPrice, BackupProvider, BACKUP_VERSION},
+    domain::PriceUnit,
+};
+
+/// The columns of a CSV export of prices, in order
+const COLUMNS: [&str; 12] = [
+    "provider",
+    "moment",
+    "price",
+    "consumer_price",
+    "supplier_fee",
+    "energy_tax",
+    "grid_fee",
+    "vat",
+    "currency",
+    "unit",
+    "resolution_minutes",
+    "area",
+];
+
+/// What prices are exported as and imported from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PriceFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Write prices as CSV with a header, of which missing amounts are empty
+pub(crate) fn prices_csv(prices: &[BackupPrice]) -> String {
+    let amount = |amount: Option<f64>| amount.map(|amount| amount.to_string());
+
+    let rows = prices.iter().map(|price| {
+        [
+            Some(price.provider.clone()),
+            Some(price.moment.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            Some(price.price.to_string()),
+            amount(price.consumer_price),
+            amount(price.supplier_fee),
+            amount(price.energy_tax),
+            amount(price.grid_fee),
+            amount(price.vat),
+            Some(price.currency.clone()),
+            Some(price.unit.clone()),
+            Some(price.resolution_minutes.to_string()),
+            Some(price.area.clone()),
+        ]
+        .map(Option::unwrap_or_default)
+        .join(",")
+    });
+
+    std::iter::once(COLUMNS.join(","))
+        .chain(rows)
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Parse prices from CSV with a header that names its columns, as written by [`prices_csv`].
+/// The provider, moment, price and currency are required, the unit defaults to `kWh` and the
+/// resolution to an hour.
+pub(crate) fn parse_prices_csv(csv: &str) -> Result<Vec<BackupPrice>, String> {
+    let mut lines = csv
+        .lines()
+        .map(|line| line.trim_start_matches('\u{feff}'))
+        .filter(|line| !line.trim().is_empty());
+
+    let header = lines.next().ok_or("the CSV is empty".to_string())?;
+    let columns = header
+        .split(',')
+        .map(|column| column.trim().to_lowercase())
+        .collect::<Vec<String>>();
+
+    let column = |name: &str| columns.iter().position(|column| column == name);
+    let required = |name: &str| column(name).ok_or(format!("the CSV has no {} column", name));
+
+    let provider = required("provider")?;
+    let moment = required("moment")?;
+    let price = required("price")?;
+    let currency = required("currency")?;
+
+    lines
+        .enumerate()
+        .map(|(index, line)| {
+            // the header is the first line
+            let number = index + 2;
+            let values = line.split(',').map(str::trim).collect::<Vec<&str>>();
+
+            if values.len() != columns.len() {
+                return Err(format!(
+                    "line {} has {} values rather than {}",
+                    number,
+                    values.len(),
+                    columns.len()
+                ));
+            }
+
+            let value = |name: &str| {
+                column(name)
+                    .map(|index| values[index])
+                    .filter(|value| !value.is_empty())
+            };
+            let amount = |name: &str| {
+                value(name)
+                    .map(|value| {
+                        value
+                            .parse::<f64>()
+                            .map_err(|_| format!("the {} on line {} is not a number", name, number))
+                    })
+                    .transpose()
+            };
+
+            Ok(BackupPrice {
+                provider: values[provider].to_string(),
+                moment: DateTime::parse_from_rfc3339(values[moment])
+                    .map_err(|_| format!("the moment on line {} is not RFC 3339", number))?
+                    .to_utc(),
+                price: values[price]
+                    .parse()
+                    .map_err(|_| format!("the price on line {} is not a number", number))?,
+                consumer_price: amount("consumer_price")?,
+                supplier_fee: amount("supplier_fee")?,
+                energy_tax: amount("energy_tax")?,
+                grid_fee: amount("grid_fee")?,
+                vat: amount("vat")?,
+                currency: values[currency].to_string(),
+                unit: value("unit")
+                    .unwrap_or(PriceUnit::KilowattHour.as_str())
+                    .to_string(),
+                resolution_minutes: value("resolution_minutes")
+                    .map(|value| value.parse())
+                    .transpose()
+                    .map_err(|_| {
+                        format!("the resolution on line {} is not a whole number", number)
+                    })?
+                    .unwrap_or(60),
+                area: value("area").unwrap_or_default().to_uppercase(),
+            })
+        })
+        .collect()
+}
+
+/// A backup of nothing but imported prices, with every provider they are tagged with, of which
+/// providers that are not stored yet get the currency of their first price
+pub(crate) fn import_backup(prices: Vec<BackupPrice>) -> Result<Backup, String> {
+    let mut providers: Vec<BackupProvider> = vec![];
+
+    for price in &prices {
+        if price.provider.is_empty() {
+            return Err(format!("the price of {} has no provider", price.moment));
+        }
+
+        if !price.price.is_finite() || price.consumer_price.is_some_and(f64::is_nan) {
+            return Err(format!("the price of {} is not a number", price.moment));
+        }
+
+        if price.currency.len() != 3 {
+            return Err(format!(
+                "the currency of the price of {} is not an ISO 4217 code",
+                price.moment
+            ));
+        }
+
+        PriceUnit::from_str(&price.unit)?;
+
+        if price.resolution_minutes <= 0 {
+            return Err(format!(
+                "the resolution of the price of {} is not positive",
+                price.moment
+            ));
+        }
+
+        if !providers
+            .iter()
+            .any(|provider| provider.name == price.provider)
+        {
+            providers.push(BackupProvider {
+                name: price.provider.clone(),
+                currency: price.currency.clone(),
+            });
+        }
+    }
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        providers,
+        prices,
+        devices: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(provider: &str, moment: &str, amount: f64) -> BackupPrice {
+        BackupPrice {
+            provider: provider.to_string(),
+            moment: DateTime::parse_from_rfc3339(moment).unwrap().to_utc(),
+            price: amount,
+            consumer_price: Some(amount * 1.21),
+            supplier_fee: None,
+            energy_tax: None,
+            grid_fee: None,
+            vat: None,
+            currency: "EUR".to_string(),
+            unit: "kWh".to_string(),
+            resolution_minutes: 60,
+            area: "NL".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_prices_csv() {
+        let prices = vec![
+            price("tibber", "2024-06-25T08:00:00Z", 0.21),
+            price("entsoe", "2024-06-25T09:00:00Z", 0.18),
+        ];
+
+        let parsed = parse_prices_csv(&prices_csv(&prices)).unwrap();
+
+        assert_eq!(parsed, prices);
+        // only the required columns are needed
+        assert_eq!(
+            parse_prices_csv(
+                "provider,moment,price,currency\ntibber,2024-06-25T08:00:00Z,0.21,EUR"
+            )
+            .unwrap()[0]
+                .unit,
+            "kWh"
+        );
+        assert!(
+            parse_prices_csv("provider,moment,price\ntibber,2024-06-25T08:00:00Z,0.21").is_err()
+        );
+    }
+
+    #[test]
+    fn test_import_backup() {
+        let backup = import_backup(vec![
+            price("tibber", "2024-06-25T08:00:00Z", 0.21),
+            price("tibber", "2024-06-25T09:00:00Z", 0.18),
+            price("entsoe", "2024-06-25T08:00:00Z", 0.11),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            backup
+                .providers
+                .iter()
+                .map(|provider| provider.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["tibber", "entsoe"]
+        );
+        assert!(import_backup(vec![price("", "2024-06-25T08:00:00Z", 0.21)]).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{domain::start_of_day, optimizer::costs, setup::AppState};
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum PriceLevelError {
+    #[error(
+        "unknown price level \"{0}\", expected very_cheap, cheap, normal, expensive or very_expensive"
+    )]
+    UnknownLevel(String),
+}
+
+/// How a price compares to the average price of its day
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PriceLevel {
+    /// At most 60% of the average
+    VeryCheap,
+    /// At most 90% of the average
+    Cheap,
+    Normal,
+    /// At least 115% of the average
+    Expensive,
+    /// At least 140% of the average
+    VeryExpensive,
+}
+
+impl PriceLevel {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PriceLevel::VeryCheap => "very_cheap",
+            PriceLevel::Cheap => "cheap",
+            PriceLevel::Normal => "normal",
+            PriceLevel::Expensive => "expensive",
+            PriceLevel::VeryExpensive => "very_expensive",
+        }
+    }
+
+    /// The level of a price relative to an average. The difference is taken relative to the
+    /// magnitude of the average, so negative averages compare sensibly.
+    pub(crate) fn of(price: f64, average: f64) -> Self {
+        if average == 0.0 {
+            return match price {
+                price if price < 0.0 => PriceLevel::VeryCheap,
+                price if price > 0.0 => PriceLevel::VeryExpensive,
+                _ => PriceLevel::Normal,
+            };
+        }
+
+        let relative = 1.0 + (price - average) / average.abs();
+
+        match relative {
+            relative if relative <= 0.6 => PriceLevel::VeryCheap,
+            relative if relative <= 0.9 => PriceLevel::Cheap,
+            relative if relative >= 1.4 => PriceLevel::VeryExpensive,
+            relative if relative >= 1.15 => PriceLevel::Expensive,
+            _ => PriceLevel::Normal,
+        }
+    }
+}
+
+impl FromStr for PriceLevel {
+    type Err = PriceLevelError;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "very_cheap" => Ok(PriceLevel::VeryCheap),
+            "cheap" => Ok(PriceLevel::Cheap),
+            "normal" => Ok(PriceLevel::Normal),
+            "expensive" => Ok(PriceLevel::Expensive),
+            "very_expensive" => Ok(PriceLevel::VeryExpensive),
+            _ => Err(PriceLevelError::UnknownLevel(level.to_string())),
+        }
+    }
+}
+
+/// The price of the current hour compared to the average of its day
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CurrentPrice {
+    pub(crate) moment: DateTime<Utc>,
+    /// The price a consumer pays, or the market price without a price formula or tariff
+    pub(crate) price: f64,
+    pub(crate) average_price: f64,
+    pub(crate) currency: String,
+    pub(crate) level: PriceLevel,
+}
+
+/// The price of the hour that contains a moment with its level, `None` when it is not known
+pub(crate) async fn current_price(
+    state: &AppState,
+    now: DateTime<Utc>,
+    area: Option<&str>,
+) -> Result<Option<CurrentPrice>, String> {
+    let today = now.with_timezone(&state.timezone).date_naive();
+
+    let prices = state
+        .price_repository
+        .fetch_prices(
+            start_of_day(&state.timezone, today),
+            start_of_day(&state.timezone, today + TimeDelta::days(1)),
+            area,
+        )
+        .await?;
+
+    let costs = costs(&prices, None);
+
+    let Some((current, price)) = prices
+        .iter()
+        .zip(&costs)
+        .find(|(price, _)| price.moment <= now && now < price.moment + TimeDelta::hours(1))
+    else {
+        return Ok(None);
+    };
+
+    let average_price = costs.iter().sum::<f64>() / costs.len() as f64;
+
+    Ok(Some(CurrentPrice {
+        moment: current.moment,
+        price: *price,
+        average_price,
+        currency: current.currency.clone(),
+        level: PriceLevel::of(*price, average_price),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_level() {
+        assert_eq!(PriceLevel::of(0.05, 0.20), PriceLevel::VeryCheap);
+        assert_eq!(PriceLevel::of(0.17, 0.20), PriceLevel::Cheap);
+        assert_eq!(PriceLevel::of(0.20, 0.20), PriceLevel::Normal);
+        assert_eq!(PriceLevel::of(0.24, 0.20), PriceLevel::Expensive);
+        assert_eq!(PriceLevel::of(0.30, 0.20), PriceLevel::VeryExpensive);
+        // a negative average
+        assert_eq!(PriceLevel::of(-0.05, -0.01), PriceLevel::VeryCheap);
+    }
+}
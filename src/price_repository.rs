@@ -1,63 +1,303 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    time::Duration,
+};
+
 use axum::async_trait;
-use chrono::{DateTime, NaiveDate, Utc};
-use sqlx::{FromRow, PgPool, QueryBuilder};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{PgListener, PgTypeInfo, PgValueRef},
+    FromRow, PgPool, Postgres, QueryBuilder,
+};
 use thiserror::Error;
-use tracing::{error, info, instrument};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
 
-use crate::domain::{PricePoint, PriceWindow};
+use crate::{
+    domain::{PriceFetch, PricePoint, PriceStatistics, PriceUnit},
+    tariff::PriceComponents,
+};
 
 #[derive(Debug, Clone, Error)]
 pub(crate) enum PriceRepositoryError {
     #[error("the prices could not be persisted: {0}")]
     PersistenceError(String),
+    #[error("there is no provider named {0}")]
+    UnknownProvider(String),
+}
+
+/// The channel that every instance sharing the database is notified on of persisted prices
+const PRICES_INGESTED_CHANNEL: &str = "prices_ingested";
+/// The channel that every instance sharing the database is notified on of deleted prices
+const PRICES_DELETED_CHANNEL: &str = "prices_deleted";
+
+/// How long to wait before listening again when the connection to the database is lost
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Prices that were persisted, by this or another instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IngestedPrices {
+    pub(crate) provider: String,
+    pub(crate) prices: usize,
+    pub(crate) first_moment: DateTime<Utc>,
+    pub(crate) last_moment: DateTime<Utc>,
+}
+
+/// Prices that were deleted or pruned, by this or another instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DeletedPrices {
+    pub(crate) prices: u64,
+}
+
+/// A version of a price as it was ingested, of which the latest is the one in use
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PriceVersion {
+    pub(crate) provider: String,
+    /// Counts up from 1 every time the provider published a different price for the moment
+    pub(crate) version: i32,
+    pub(crate) ingested_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub(crate) price: PricePoint,
+}
+
+/// The areas of prices as they are stored, in which prices of which the area is not known have an
+/// empty area
+pub(crate) fn areas_of<'a>(prices: &[&'a PricePoint]) -> BTreeSet<&'a str> {
+    prices
+        .iter()
+        .map(|price| price.area.as_deref().unwrap_or_default())
+        .collect()
+}
+
+/// An hour a provider has no price for
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize)]
+pub(crate) struct PriceGap {
+    pub(crate) provider: String,
+    pub(crate) moment: DateTime<Utc>,
+}
+
+/// The hours within a period that providers have no price for, ordered by provider and moment,
+/// given the moments of the prices that are stored of every provider. The period starts at the
+/// start of an hour.
+pub(crate) fn price_gaps(
+    start_moment: DateTime<Utc>,
+    end_moment: DateTime<Utc>,
+    providers: &[String],
+    stored: &[(String, DateTime<Utc>)],
+) -> Vec<PriceGap> {
+    let hour = TimeDelta::hours(1);
+
+    // a price of a quarter hour covers the hour it falls in
+    let covered = stored
+        .iter()
+        .map(|(provider, moment)| {
+            (
+                provider.as_str(),
+                moment.duration_trunc(hour).unwrap_or(*moment),
+            )
+        })
+        .collect::<HashSet<(&str, DateTime<Utc>)>>();
+
+    let mut providers = providers.to_vec();
+    providers.sort();
+
+    providers
+        .into_iter()
+        .flat_map(|provider| {
+            let hours =
+                std::iter::successors(Some(start_moment), move |moment| Some(*moment + hour))
+                    .take_while(move |moment| *moment < end_moment);
+
+            hours
+                .filter(|moment| !covered.contains(&(provider.as_str(), *moment)))
+                .map(|moment| PriceGap {
+                    provider: provider.clone(),
+                    moment,
+                })
+                .collect::<Vec<PriceGap>>()
+        })
+        .collect()
+}
+
+/// A single price for every moment of an area, the last one given, as a fetch that is retried or
+/// a provider that repeats an hour would otherwise have a statement replace a price twice
+pub(crate) fn unique_prices(prices: &[PricePoint]) -> Vec<&PricePoint> {
+    prices
+        .iter()
+        .map(|price| ((price.moment, price.area.as_deref()), price))
+        .collect::<BTreeMap<(DateTime<Utc>, Option<&str>), &PricePoint>>()
+        .into_values()
+        .collect()
+}
+
+/// Create the partitions of the months that the moments fall in, unless they exist. Prices are
+/// partitioned by month, of which a partition must exist before prices of its month are stored.
+pub(crate) async fn create_partitions_of(
+    db: &PgPool,
+    moments: &[DateTime<Utc>],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        select create_price_partition(month)
+        from (select distinct date_trunc('month', moment, 'UTC') as month
+              from unnest($1::timestamptz[]) as moment) as months
+        "#,
+    )
+    .bind(moments)
+    .execute(db)
+    .await
+    .map(|_| ())
 }
 
 #[async_trait]
 pub(crate) trait PriceRepository: Send + Sync {
-    async fn fetch_prices_of_date(&self, date: NaiveDate) -> Result<Vec<PricePoint>, String>;
+    /// Fetch the prices starting within a period, optionally of a single area, ordered by moment
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String>;
 
+    /// Persist prices, replacing those of the provider that are already stored for a moment. A
+    /// price that differs from the stored one is kept as its next version.
     async fn persist_prices(
         &self,
         prices: &[PricePoint],
         provider_name: &str,
     ) -> Result<(), PriceRepositoryError>;
 
-    async fn fetch_optimal_price_window_of_window_for_durations(
+    async fn record_price_fetch(
+        &self,
+        fetch: &PriceFetch,
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError>;
+
+    async fn fetch_price_statistics(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<PriceStatistics, String>;
+
+    /// Fetch every version of the prices starting within a period, optionally of a single area,
+    /// ordered by moment and version
+    async fn fetch_price_versions(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceVersion>, String>;
+
+    /// Fetch the moments of the prices starting within a period, optionally of a single area,
+    /// with the name of their provider
+    async fn fetch_price_moments(
         &self,
         start_moment: DateTime<Utc>,
         end_moment: DateTime<Utc>,
-        durations: &[i32],
-    ) -> Result<Vec<PriceWindow>, String>;
+        area: Option<&str>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String>;
 
-    async fn fetch_optimal_upcoming_window(
+    /// Fetch the hours within a period that a provider has no price for, optionally of a single
+    /// area, of every provider that has stored prices, ordered by provider and moment. The period
+    /// starts at the start of an hour.
+    async fn fetch_price_gaps(
         &self,
-        duration: i32,
-    ) -> Result<Vec<PriceWindow>, String>;
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceGap>, String>;
+
+    /// Delete the prices of a provider and their versions starting within a period, optionally
+    /// of a single area, returning how many prices were deleted
+    async fn delete_prices(
+        &self,
+        provider_name: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<u64, PriceRepositoryError>;
+
+    /// Delete the prices and their versions of moments before a moment, returning how many
+    /// prices were deleted
+    async fn prune_prices(&self, before: DateTime<Utc>) -> Result<u64, String>;
+
+    /// Delete the fetches attempted before a moment, returning how many were deleted
+    async fn prune_price_fetches(&self, before: DateTime<Utc>) -> Result<u64, String>;
+
+    /// Create the partitions that the prices of the moments are stored in, ahead of storing
+    /// them. Storage that does not partition prices has nothing to create.
+    async fn create_price_partitions(&self, moments: &[DateTime<Utc>]) -> Result<(), String>;
+
+    /// Receive the prices persisted by any instance that shares the database, from now on
+    async fn listen_for_ingested_prices(&self) -> Result<mpsc::Receiver<IngestedPrices>, String>;
+
+    /// Receive the prices deleted by any other instance that shares the database, from now on.
+    /// Storage that is not shared between instances has nothing to receive.
+    async fn listen_for_deleted_prices(&self) -> Result<mpsc::Receiver<DeletedPrices>, String>;
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct PostgresPriceRepository {
     db: PgPool,
+    /// The replica that searches and statistics read from, the primary unless one is configured
+    read_db: PgPool,
 }
 
 impl PostgresPriceRepository {
     pub fn new(db: PgPool) -> Self {
-        Self { db }
+        Self {
+            read_db: db.clone(),
+            db,
+        }
+    }
+
+    /// Read prices from a replica of the database, while they are written to the primary
+    pub fn with_replica(self, read_db: PgPool) -> Self {
+        Self { read_db, ..self }
+    }
+
+    async fn fetch_provider(&self, provider_name: &str) -> Result<Provider, PriceRepositoryError> {
+        sqlx::query_as("select id, name from providers where name = $1 limit 1")
+            .bind(provider_name)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?
+            .ok_or_else(|| PriceRepositoryError::UnknownProvider(provider_name.to_string()))
     }
 }
 
 #[async_trait]
 impl PriceRepository for PostgresPriceRepository {
-    async fn fetch_prices_of_date(&self, date: NaiveDate) -> Result<Vec<PricePoint>, String> {
-        let rows = sqlx::query_as::<_, PricePoint>(
-            "SELECT moment, monetary_amount FROM prices WHERE moment::date = $1",
+    async fn fetch_prices(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PricePoint>, String> {
+        // every search for windows runs this query, of which the columns are checked against
+        // the schema at build time
+        let rows = sqlx::query_as!(
+            PriceRow,
+            r#"
+            select moment, price, consumer_price, supplier_fee, energy_tax, grid_fee, vat,
+                   prices.currency, unit as "unit: PriceUnit", resolution_minutes, area
+            from prices
+            join providers on providers.id = prices.provider_id
+            where moment >= $1 and moment < $2 and ($3::varchar is null or area = $3)
+            order by moment
+            "#,
+            start_moment,
+            end_moment,
+            area
         )
-        .bind(date)
-        .fetch_all(&self.db)
+        .fetch_all(&self.read_db)
         .await
         .map_err(|e| e.to_string())?;
 
-        Ok(rows)
+        Ok(rows.into_iter().map(PricePoint::from).collect())
     }
 
     async fn persist_prices(
@@ -65,102 +305,601 @@ impl PriceRepository for PostgresPriceRepository {
         prices: &[PricePoint],
         provider_name: &str,
     ) -> Result<(), PriceRepositoryError> {
-        let provider: Provider =
-            sqlx::query_as("select id, name from providers where name = $1 limit 1")
-                .bind(provider_name)
-                .fetch_one(&self.db)
-                .await
-                .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+        if prices.is_empty() {
+            return Ok(());
+        }
+
+        let provider = self.fetch_provider(provider_name).await?;
 
-        info!("Persisting {} prices for {}", prices.len(), provider.name);
+        let unique = unique_prices(prices);
 
-        let mut query_builder =
-            QueryBuilder::new("insert into prices (moment, price, provider_id)");
+        info!("Persisting {} prices for {}", unique.len(), provider.name);
 
-        query_builder.push_values(prices, |mut builder, price| {
+        create_partitions_of(
+            &self.db,
+            &unique.iter().map(|price| price.moment).collect::<Vec<_>>(),
+        )
+        .await
+        .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+        let mut transaction = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+        let ingested_at = Utc::now();
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into prices (moment, price, consumer_price, supplier_fee, energy_tax, grid_fee, vat, currency, unit, resolution_minutes, area, provider_id, ingested_at)",
+        );
+
+        query_builder.push_values(unique.iter(), |mut builder, price| {
             builder
                 .push_bind(price.moment)
                 .push_bind(price.monetary_amount)
-                .push_bind(provider.id);
+                .push_bind(price.consumer_amount)
+                .push_bind(price.components.as_ref().map(|c| c.supplier_fee))
+                .push_bind(price.components.as_ref().map(|c| c.energy_tax))
+                .push_bind(price.components.as_ref().map(|c| c.grid_fee))
+                .push_bind(price.components.as_ref().map(|c| c.vat))
+                .push_bind(&price.currency)
+                .push_bind(price.unit.as_str())
+                .push_bind(price.resolution_minutes)
+                .push_bind(price.area.as_deref().unwrap_or_default())
+                .push_bind(provider.id)
+                .push_bind(ingested_at);
         });
 
-        let query = query_builder.build();
+        // a price that is fetched again unchanged keeps its version
+        query_builder.push(
+            r#"
+            on conflict (moment, provider_id, area) do update
+            set price              = excluded.price,
+                consumer_price     = excluded.consumer_price,
+                supplier_fee       = excluded.supplier_fee,
+                energy_tax         = excluded.energy_tax,
+                grid_fee           = excluded.grid_fee,
+                vat                = excluded.vat,
+                currency           = excluded.currency,
+                unit               = excluded.unit,
+                resolution_minutes = excluded.resolution_minutes,
+                version            = prices.version + 1,
+                ingested_at        = excluded.ingested_at
+            where (prices.price, prices.consumer_price, prices.supplier_fee, prices.energy_tax,
+                   prices.grid_fee, prices.vat, prices.currency, prices.unit,
+                   prices.resolution_minutes)
+                  is distinct from
+                  (excluded.price, excluded.consumer_price, excluded.supplier_fee,
+                   excluded.energy_tax, excluded.grid_fee, excluded.vat, excluded.currency,
+                   excluded.unit, excluded.resolution_minutes)
+            "#,
+        );
+
+        let query = query_builder.build().persistent(false);
 
         query
-            .execute(&self.db)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+        let ingested = IngestedPrices {
+            provider: provider.name,
+            prices: unique.len(),
+            first_moment: prices
+                .iter()
+                .map(|price| price.moment)
+                .min()
+                .unwrap_or_default(),
+            last_moment: prices
+                .iter()
+                .map(|price| price.moment)
+                .max()
+                .unwrap_or_default(),
+        };
+
+        // the versions of the prices that are new or corrected are not kept yet, of the areas of
+        // the prices only as a provider can serve several
+        for area in areas_of(&unique) {
+            sqlx::query(
+                r#"
+                insert into price_versions (moment, provider_id, area, version, price,
+                                            consumer_price, supplier_fee, energy_tax, grid_fee,
+                                            vat, currency, unit, resolution_minutes, ingested_at)
+                select moment, provider_id, area, version, price, consumer_price, supplier_fee,
+                       energy_tax, grid_fee, vat, currency, unit, resolution_minutes, ingested_at
+                from prices
+                where provider_id = $1 and moment >= $2 and moment <= $3
+                  and area is not distinct from $4
+                on conflict do nothing
+                "#,
+            )
+            .bind(provider.id)
+            .bind(ingested.first_moment)
+            .bind(ingested.last_moment)
+            .bind(area)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+        }
+
+        // listeners are notified once the transaction commits
+        sqlx::query("select pg_notify($1, $2)")
+            .bind(PRICES_INGESTED_CHANNEL)
+            .bind(serde_json::to_string(&ingested).unwrap_or_default())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))?;
+
+        transaction
+            .commit()
             .await
-            .map(|_| ())
             .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))
     }
 
-    #[instrument(skip(self))]
-    async fn fetch_optimal_price_window_of_window_for_durations(
+    async fn record_price_fetch(
+        &self,
+        fetch: &PriceFetch,
+        provider_name: &str,
+    ) -> Result<(), PriceRepositoryError> {
+        let provider = self.fetch_provider(provider_name).await?;
+
+        sqlx::query(
+            r#"
+            insert into price_fetches (provider_id, area, date, attempted_at, attempts, prices,
+                                       error)
+            values ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(fetch.area.as_deref().unwrap_or_default())
+        .bind(fetch.date)
+        .bind(fetch.attempted_at)
+        .bind(fetch.attempts)
+        .bind(fetch.prices)
+        .bind(&fetch.error)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| PriceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_price_statistics(
         &self,
         start_moment: DateTime<Utc>,
         end_moment: DateTime<Utc>,
-        durations: &[i32],
-    ) -> Result<Vec<PriceWindow>, String> {
-        let mut windows: Vec<PriceWindow> = Vec::new();
+        area: Option<&str>,
+    ) -> Result<PriceStatistics, String> {
+        sqlx::query_as!(
+            PriceStatistics,
+            r#"
+            select count(*)                                      as "hours!",
+            min(coalesce(prices.consumer_price, prices.price)) as minimum,
+            max(coalesce(prices.consumer_price, prices.price)) as maximum,
+            avg(coalesce(prices.consumer_price, prices.price)) as average,
+            sum(coalesce(prices.consumer_price, prices.price)) as sum,
+            max(prices.currency)                               as currency
+            from prices
+            where moment >= $1 and moment < $2 and ($3::varchar is null or area = $3)
+            group by prices.currency
+            "#,
+            start_moment,
+            end_moment,
+            area
+        )
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(PriceStatistics::of_single_currency)
+    }
 
-        for duration in durations.iter() {
-            let mut duration: i32 = *duration;
+    async fn fetch_price_versions(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceVersion>, String> {
+        let rows = sqlx::query_as::<_, PriceVersionRow>(
+            r#"
+            select providers.name as provider, version, ingested_at, moment, price,
+                   consumer_price, supplier_fee, energy_tax, grid_fee, vat,
+                   price_versions.currency, unit, resolution_minutes, area
+            from price_versions
+            join providers on providers.id = price_versions.provider_id
+            where moment >= $1 and moment < $2 and ($3 is null or area = $3)
+            order by moment, providers.name, area, version
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())?;
 
-            duration -= 1;
-            duration = duration.clamp(0, 23);
+        Ok(rows.into_iter().map(PriceVersion::from).collect())
+    }
 
-            let row = sqlx::query_as::<_, PriceWindow>(r#"
-            select moment                                                                        as starts_at,
-            round((avg(prices.price) over price_window)::numeric, 3)::varchar                    as average_price,
-            ((max(moment) over price_window) + interval '59 minutes 59 seconds') as ends_at
+    async fn fetch_price_moments(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            r#"
+            select distinct providers.name, moment
             from prices
-            where moment::timestamptz >= $1 and moment::timestamptz <= $2
-            window price_window as ( partition by moment::date order by moment rows between current row and $3 following )
-            order by average_price
-            limit 1
-            "#
-            )
-                .bind(start_moment)
-                .bind(end_moment)
-                .bind(duration)
-                .fetch_one(&self.db)
-                .await
-                .map_err(|e| e.to_string())?;
-
-            windows.push(row)
-        }
+            join providers on providers.id = prices.provider_id
+            where moment >= $1 and moment < $2 and ($3 is null or area = $3)
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())
+    }
 
-        Ok(windows)
+    async fn fetch_price_gaps(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<Vec<PriceGap>, String> {
+        sqlx::query_as::<_, PriceGap>(
+            r#"
+            select providers.name as provider, hours.moment
+            from providers
+            cross join generate_series($1, $2 - interval '1 hour', interval '1 hour') as hours (moment)
+            where exists (select 1
+                          from prices
+                          where prices.provider_id = providers.id
+                            and ($3 is null or prices.area is not distinct from $3))
+              and not exists (
+                  select 1
+                  from prices
+                  where prices.provider_id = providers.id
+                    and prices.moment >= hours.moment
+                    and prices.moment < hours.moment + interval '1 hour'
+                    and ($3 is null or prices.area is not distinct from $3)
+              )
+            order by providers.name, hours.moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .fetch_all(&self.read_db)
+        .await
+        .map_err(|e| e.to_string())
     }
 
-    async fn fetch_optimal_upcoming_window(
+    async fn delete_prices(
         &self,
-        duration: i32,
-    ) -> Result<Vec<PriceWindow>, String> {
-        let duration = duration.clamp(0, 23);
+        provider_name: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+        area: Option<&str>,
+    ) -> Result<u64, PriceRepositoryError> {
+        let provider = self.fetch_provider(provider_name).await?;
 
-        let _row = sqlx::query_as::<_, PriceWindow>(r#"
-            select moment                                                                        as starts_at,
-            round((avg(prices.price) over price_window)::numeric, 3)::varchar                    as average_price,
-            ((max(moment) over price_window) + interval '59 minutes 59 seconds') as ends_at
-            from prices
-            where moment::timestamptz >= $1 and moment::timestamptz <= $2
-            window price_window as ( partition by moment::date order by moment rows between current row and $3 following )
-            order by average_price
-            limit 1
-            "#
-            )
-                .bind(Utc::now())
-                .bind(duration)
-                .fetch_one(&self.db)
-                .await
-                .map_err(|e| e.to_string())?;
+        let error = |e: sqlx::Error| PriceRepositoryError::PersistenceError(e.to_string());
+
+        let mut transaction = self.db.begin().await.map_err(error)?;
+
+        sqlx::query(
+            r#"
+            delete from price_versions
+            where provider_id = $1 and moment >= $2 and moment < $3 and ($4 is null or area = $4)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .execute(&mut *transaction)
+        .await
+        .map_err(error)?;
+
+        let deleted = sqlx::query(
+            r#"
+            delete from prices
+            where provider_id = $1 and moment >= $2 and moment < $3 and ($4 is null or area = $4)
+            "#,
+        )
+        .bind(provider.id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .bind(area)
+        .execute(&mut *transaction)
+        .await
+        .map_err(error)?
+        .rows_affected();
+
+        notify_deleted_prices(&mut transaction, deleted)
+            .await
+            .map_err(error)?;
 
-        return Ok(vec![]);
+        transaction.commit().await.map_err(error)?;
+
+        Ok(deleted)
+    }
+
+    async fn prune_prices(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::query("delete from price_versions where moment < $1")
+            .bind(before)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let (pruned,): (i64,) = sqlx::query_as("select count(*) from prices where moment < $1")
+            .bind(before)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // the partitions of the months that ended before the moment are dropped as a whole, the
+        // rows of the month the moment falls in are deleted
+        sqlx::query("select drop_price_partitions($1)")
+            .bind(before)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        sqlx::query("delete from prices where moment < $1")
+            .bind(before)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        notify_deleted_prices(&mut transaction, pruned as u64)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(pruned as u64)
+    }
+
+    async fn prune_price_fetches(&self, before: DateTime<Utc>) -> Result<u64, String> {
+        sqlx::query("delete from price_fetches where attempted_at < $1")
+            .bind(before)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn create_price_partitions(&self, moments: &[DateTime<Utc>]) -> Result<(), String> {
+        create_partitions_of(&self.db, moments)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn listen_for_ingested_prices(&self) -> Result<mpsc::Receiver<IngestedPrices>, String> {
+        listen(&self.db, PRICES_INGESTED_CHANNEL, "ingested prices").await
+    }
+
+    async fn listen_for_deleted_prices(&self) -> Result<mpsc::Receiver<DeletedPrices>, String> {
+        listen(&self.db, PRICES_DELETED_CHANNEL, "deleted prices").await
+    }
+}
+
+/// Notify the instances that share the database of prices that were deleted, once the
+/// transaction commits
+async fn notify_deleted_prices(
+    transaction: &mut sqlx::Transaction<'_, Postgres>,
+    prices: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("select pg_notify($1, $2)")
+        .bind(PRICES_DELETED_CHANNEL)
+        .bind(serde_json::to_string(&DeletedPrices { prices }).unwrap_or_default())
+        .execute(&mut **transaction)
+        .await
+        .map(|_| ())
+}
+
+/// Receive the notifications on a channel, of which the payload is JSON
+async fn listen<T>(
+    db: &PgPool,
+    channel: &str,
+    what: &'static str,
+) -> Result<mpsc::Receiver<T>, String>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut listener = PgListener::connect_with(db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    listener.listen(channel).await.map_err(|e| e.to_string())?;
+
+    let (sender, receiver) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        loop {
+            // the listener connects again on the next call after its connection is lost,
+            // notifications sent in between are missed
+            let notification = match listener.try_recv().await {
+                Ok(Some(notification)) => notification,
+                Ok(None) => {
+                    warn!("lost the connection listening for {}", what);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("unable to listen for {}, {}", what, e);
+                    tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            let payload = match serde_json::from_str(notification.payload()) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("ignoring a notification of {}, {}", what, e);
+                    continue;
+                }
+            };
+
+            if sender.send(payload).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+#[derive(FromRow)]
+pub(crate) struct Provider {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+}
+
+#[derive(FromRow)]
+pub(crate) struct PriceVersionRow {
+    provider: String,
+    version: i32,
+    ingested_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    price: PriceRow,
+}
+
+impl From<PriceVersionRow> for PriceVersion {
+    fn from(row: PriceVersionRow) -> PriceVersion {
+        PriceVersion {
+            provider: row.provider,
+            version: row.version,
+            ingested_at: row.ingested_at,
+            price: PricePoint::from(row.price),
+        }
+    }
+}
+
+/// Units are stored by their name, which the query macros decode directly
+impl sqlx::Type<Postgres> for PriceUnit {
+    fn type_info() -> PgTypeInfo {
+        <String as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for PriceUnit {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<String as sqlx::Decode<Postgres>>::decode(value)?.parse()?)
     }
 }
 
 #[derive(FromRow)]
-struct Provider {
-    id: i64,
-    name: String,
+pub(crate) struct PriceRow {
+    moment: DateTime<Utc>,
+    price: f64,
+    consumer_price: Option<f64>,
+    supplier_fee: Option<f64>,
+    energy_tax: Option<f64>,
+    grid_fee: Option<f64>,
+    vat: Option<f64>,
+    currency: String,
+    #[sqlx(try_from = "String")]
+    unit: PriceUnit,
+    resolution_minutes: i32,
+    /// Empty when the area of the price is not known
+    area: String,
+}
+
+impl From<PriceRow> for PricePoint {
+    fn from(row: PriceRow) -> PricePoint {
+        let components = match (row.supplier_fee, row.energy_tax, row.grid_fee, row.vat) {
+            (Some(supplier_fee), Some(energy_tax), Some(grid_fee), Some(vat)) => {
+                Some(PriceComponents {
+                    energy: row.price,
+                    supplier_fee,
+                    energy_tax,
+                    grid_fee,
+                    vat,
+                })
+            }
+            _ => None,
+        };
+
+        PricePoint {
+            moment: row.moment,
+            monetary_amount: row.price,
+            currency: row.currency,
+            consumer_amount: row.consumer_price,
+            components,
+            unit: row.unit,
+            resolution_minutes: row.resolution_minutes,
+            area: (!row.area.is_empty()).then_some(row.area),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{moment, price_point};
+
+    #[test]
+    fn test_unique_prices() {
+        let price = |hour: &str, amount: f64| price_point(moment(hour), amount);
+
+        let prices = [
+            price("2024-06-30T09:00:00Z", 0.2),
+            price("2024-06-30T08:00:00Z", 0.1),
+            price("2024-06-30T09:00:00Z", 0.3),
+        ];
+
+        let unique = unique_prices(&prices)
+            .into_iter()
+            .map(|price| price.monetary_amount)
+            .collect::<Vec<f64>>();
+
+        assert_eq!(unique, vec![0.1, 0.3]);
+    }
+
+    #[test]
+    fn test_price_gaps() {
+        let moment = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().to_utc();
+        let providers = ["tibber".to_string(), "entsoe".to_string()];
+
+        let stored = [
+            ("tibber".to_string(), moment("2024-06-30T08:00:00Z")),
+            // a quarter hour covers its hour
+            ("tibber".to_string(), moment("2024-06-30T10:45:00Z")),
+            ("entsoe".to_string(), moment("2024-06-30T09:00:00Z")),
+        ];
+
+        let gaps = price_gaps(
+            moment("2024-06-30T08:00:00Z"),
+            moment("2024-06-30T11:00:00Z"),
+            &providers,
+            &stored,
+        );
+
+        assert_eq!(
+            gaps,
+            vec![
+                PriceGap {
+                    provider: "entsoe".to_string(),
+                    moment: moment("2024-06-30T08:00:00Z"),
+                },
+                PriceGap {
+                    provider: "entsoe".to_string(),
+                    moment: moment("2024-06-30T10:00:00Z"),
+                },
+                PriceGap {
+                    provider: "tibber".to_string(),
+                    moment: moment("2024-06-30T09:00:00Z"),
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,106 @@
+use chrono::{DateTime, FixedOffset};
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    RunNow,
+    Wait,
+}
+
+/// Whether to start a device immediately or to wait for a cheaper moment
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Recommendation {
+    pub(crate) action: Action,
+    /// When to start the device
+    pub(crate) start_at: DateTime<FixedOffset>,
+    pub(crate) run_now_average_price: f64,
+    pub(crate) best_average_price: f64,
+    pub(crate) best_starts_at: DateTime<FixedOffset>,
+    /// How much more expensive starting now is than the best option, as a percentage
+    pub(crate) premium_percentage: f64,
+}
+
+impl Recommendation {
+    /// Recommend to run now when doing so is at most `tolerance_percentage` more expensive
+    /// than the best upcoming option
+    pub(crate) fn new(
+        now: DateTime<FixedOffset>,
+        run_now_average_price: f64,
+        best_starts_at: DateTime<FixedOffset>,
+        best_average_price: f64,
+        tolerance_percentage: f64,
+    ) -> Self {
+        let premium = run_now_average_price - best_average_price;
+
+        // relative to the magnitude of the best price, so negative prices compare sensibly
+        let premium_percentage = if best_average_price == 0.0 {
+            if premium > 0.0 {
+                f64::INFINITY
+            } else {
+                0.0
+            }
+        } else {
+            premium / best_average_price.abs() * 100.0
+        };
+
+        let action = if best_starts_at <= now || premium_percentage <= tolerance_percentage {
+            Action::RunNow
+        } else {
+            Action::Wait
+        };
+
+        Self {
+            action,
+            start_at: match action {
+                Action::RunNow => now,
+                Action::Wait => best_starts_at,
+            },
+            run_now_average_price,
+            best_average_price,
+            best_starts_at,
+            premium_percentage: (premium_percentage * 10.0).round() / 10.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::local_moment;
+
+    #[test]
+    fn test_run_now_within_tolerance() {
+        let recommendation = Recommendation::new(
+            local_moment("2024-06-30T14:10:00+02:00"),
+            0.21,
+            local_moment("2024-06-30T16:00:00+02:00"),
+            0.20,
+            10.0,
+        );
+
+        assert_eq!(recommendation.action, Action::RunNow);
+        assert_eq!(
+            recommendation.start_at,
+            local_moment("2024-06-30T14:10:00+02:00")
+        );
+        assert_eq!(recommendation.premium_percentage, 5.0);
+    }
+
+    #[test]
+    fn test_wait_when_materially_cheaper() {
+        let recommendation = Recommendation::new(
+            local_moment("2024-06-30T14:10:00+02:00"),
+            0.30,
+            local_moment("2024-06-30T16:00:00+02:00"),
+            -0.05,
+            10.0,
+        );
+
+        assert_eq!(recommendation.action, Action::Wait);
+        assert_eq!(
+            recommendation.start_at,
+            local_moment("2024-06-30T16:00:00+02:00")
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::{
+    configuration_file::{replace_file_variables, reread_files},
+    setup::{resolve_settings, AppState},
+};
+
+/// Reload the configuration every time electrack receives SIGHUP, keeping the current one when
+/// the new one is invalid
+pub(crate) async fn reload_on_hangup(state: AppState, demo: bool) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!(
+                "unable to listen for SIGHUP, the configuration cannot be reloaded, {}",
+                e
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        match reload_settings(&state, demo) {
+            Ok(()) => info!("reloaded the configuration"),
+            Err(e) => error!(
+                "unable to reload the configuration, keeping the current one, {}",
+                e
+            ),
+        }
+    }
+}
+
+/// Resolve the settings from the files as they are now, without changing the environment, which
+/// is not safe while other threads run. The variables of the files that were read before are
+/// put back when the settings are invalid.
+fn reload_settings(state: &AppState, demo: bool) -> Result<(), String> {
+    let previous = replace_file_variables(reread_files()?)?;
+
+    match resolve_settings(state.timezone, demo) {
+        Ok(settings) => {
+            state.replace_settings(settings);
+
+            Ok(())
+        }
+        Err(e) => {
+            replace_file_variables(previous)?;
+
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::domain::RenewableGeneration;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum RenewableGenerationRepositoryError {
+    #[error("the renewable generation could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait RenewableGenerationRepository: Send + Sync {
+    /// Fetch the renewable generation of a bidding zone between two moments, ordered by moment
+    async fn fetch_renewable_generation(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<RenewableGeneration>, String>;
+
+    /// Persist the renewable generation, replacing what was forecast before for the same hours
+    async fn persist_renewable_generation(
+        &self,
+        generation: &[RenewableGeneration],
+    ) -> Result<(), RenewableGenerationRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresRenewableGenerationRepository {
+    db: PgPool,
+}
+
+impl PostgresRenewableGenerationRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl RenewableGenerationRepository for PostgresRenewableGenerationRepository {
+    async fn fetch_renewable_generation(
+        &self,
+        zone: &str,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<RenewableGeneration>, String> {
+        sqlx::query_as::<_, RenewableGeneration>(
+            r#"
+            select moment, zone, solar_mw, wind_mw, load_mw
+            from renewable_generation
+            where zone = $1 and moment >= $2 and moment < $3
+            order by moment
+            "#,
+        )
+        .bind(zone)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_renewable_generation(
+        &self,
+        generation: &[RenewableGeneration],
+    ) -> Result<(), RenewableGenerationRepositoryError> {
+        if generation.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Persisting the renewable generation of {} hours",
+            generation.len()
+        );
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into renewable_generation (moment, zone, solar_mw, wind_mw, load_mw)",
+        );
+
+        query_builder.push_values(generation, |mut builder, hour| {
+            builder
+                .push_bind(hour.moment)
+                .push_bind(&hour.zone)
+                .push_bind(hour.solar_mw)
+                .push_bind(hour.wind_mw)
+                .push_bind(hour.load_mw);
+        });
+
+        query_builder.push(
+            r#"
+            on conflict (moment, zone) do update
+            set solar_mw = excluded.solar_mw, wind_mw = excluded.wind_mw, load_mw = excluded.load_mw
+            "#,
+        );
+
+        query_builder
+            .build()
+            .persistent(false)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| RenewableGenerationRepositoryError::PersistenceError(e.to_string()))
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, DurationRound, TimeDelta, TimeZone, Utc};
+
+use crate::{
+    cost_report::{fetch_hourly_prices, HourlyPrice},
+    domain::{start_of_day, Consumption},
+    setup::AppState,
+};
+
+/// What the consumption of a day cost, and what the same consumption would have cost without
+/// shifting it to cheap hours
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DaySavings {
+    pub(crate) starts_at: DateTime<Utc>,
+    /// The consumption in hours of which the price is stored
+    pub(crate) energy_kwh: f64,
+    /// What the consumption cost at the price of the hours it was used in
+    pub(crate) actual_cost: f64,
+    /// What the consumption would have cost at the average price of the day, as if it had been
+    /// spread without regard to prices
+    pub(crate) average_cost: f64,
+    /// What the consumption would have cost had the largest hourly consumption fallen in the
+    /// most expensive hours of the day
+    pub(crate) worst_cost: f64,
+}
+
+impl DaySavings {
+    /// The days taken together as one that starts at a moment
+    pub(crate) fn total(starts_at: DateTime<Utc>, days: &[DaySavings]) -> DaySavings {
+        days.iter().fold(
+            DaySavings {
+                starts_at,
+                energy_kwh: 0.0,
+                actual_cost: 0.0,
+                average_cost: 0.0,
+                worst_cost: 0.0,
+            },
+            |total, day| DaySavings {
+                energy_kwh: total.energy_kwh + day.energy_kwh,
+                actual_cost: total.actual_cost + day.actual_cost,
+                average_cost: total.average_cost + day.average_cost,
+                worst_cost: total.worst_cost + day.worst_cost,
+                ..total
+            },
+        )
+    }
+}
+
+/// The savings of every local day between two moments, of the household or of the readings of
+/// a single device, and the currency of the prices
+pub(crate) async fn fetch_savings(
+    state: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    device_id: Option<i64>,
+    area: Option<&str>,
+) -> Result<(Vec<DaySavings>, Option<String>), String> {
+    let consumption = match device_id {
+        Some(device_id) => state
+            .consumption_repository
+            .fetch_device_consumption(start, end, Some(device_id))
+            .await?
+            .into_iter()
+            .map(|reading| reading.consumption)
+            .collect(),
+        None => {
+            state
+                .consumption_repository
+                .fetch_consumption(start, end)
+                .await?
+        }
+    };
+
+    let (hourly_prices, currency) = fetch_hourly_prices(state, start, end, area).await?;
+
+    Ok((
+        day_savings(&consumption, &hourly_prices, &state.timezone),
+        currency,
+    ))
+}
+
+/// Compare the cost of the consumption of every local day to its baselines. Consumption in
+/// hours without a stored price is left out, as are days without priced consumption.
+pub(crate) fn day_savings<Tz: TimeZone>(
+    consumption: &[Consumption],
+    prices: &[HourlyPrice],
+    timezone: &Tz,
+) -> Vec<DaySavings> {
+    let day_of =
+        |moment: DateTime<Utc>| start_of_day(timezone, moment.with_timezone(timezone).date_naive());
+
+    let mut hourly_kwh = BTreeMap::<DateTime<Utc>, f64>::new();
+
+    for reading in consumption {
+        let hour = reading
+            .starts_at
+            .duration_trunc(TimeDelta::hours(1))
+            .unwrap_or(reading.starts_at);
+
+        *hourly_kwh.entry(hour).or_default() += reading.energy_kwh;
+    }
+
+    let mut days = BTreeMap::<DateTime<Utc>, (Vec<f64>, Vec<f64>, f64)>::new();
+
+    for price in prices {
+        let (day_prices, day_kwh, actual_cost) = days.entry(day_of(price.moment)).or_default();
+
+        day_prices.push(price.price);
+
+        if let Some(energy_kwh) = hourly_kwh.get(&price.moment) {
+            day_kwh.push(*energy_kwh);
+            *actual_cost += energy_kwh * price.price;
+        }
+    }
+
+    days.into_iter()
+        .filter(|(_, (_, day_kwh, _))| !day_kwh.is_empty())
+        .map(|(starts_at, (mut day_prices, mut day_kwh, actual_cost))| {
+            let energy_kwh = day_kwh.iter().sum::<f64>();
+            let average_price = day_prices.iter().sum::<f64>() / day_prices.len() as f64;
+
+            // pairing the largest consumption with the highest prices is the costliest schedule
+            day_prices.sort_by(|a, b| b.total_cmp(a));
+            day_kwh.sort_by(|a, b| b.total_cmp(a));
+
+            DaySavings {
+                starts_at,
+                energy_kwh,
+                actual_cost,
+                average_cost: energy_kwh * average_price,
+                worst_cost: day_kwh
+                    .iter()
+                    .zip(&day_prices)
+                    .map(|(energy_kwh, price)| energy_kwh * price)
+                    .sum(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Amsterdam;
+
+    use super::*;
+    use crate::test_support::moment;
+
+    fn reading(start: &str, energy_kwh: f64) -> Consumption {
+        Consumption {
+            starts_at: moment(start),
+            ends_at: moment(start) + TimeDelta::minutes(15),
+            energy_kwh,
+            exported_kwh: 0.0,
+        }
+    }
+
+    fn price(hour: &str, price: f64) -> HourlyPrice {
+        HourlyPrice {
+            moment: moment(hour),
+            price,
+            export_price: price,
+            components: None,
+        }
+    }
+
+    #[test]
+    fn test_day_savings() {
+        let consumption = [
+            reading("2024-06-30T22:00:00Z", 1.5),
+            reading("2024-06-30T22:15:00Z", 1.5),
+            reading("2024-06-30T23:00:00Z", 1.0),
+            // no price is stored for this hour
+            reading("2024-07-01T03:00:00Z", 5.0),
+        ];
+
+        let prices = [
+            price("2024-06-30T21:00:00Z", 0.5),
+            price("2024-06-30T22:00:00Z", 0.1),
+            price("2024-06-30T23:00:00Z", 0.2),
+            price("2024-07-01T00:00:00Z", 0.4),
+            price("2024-07-01T01:00:00Z", 0.3),
+        ];
+
+        let days = day_savings(&consumption, &prices, &Amsterdam);
+
+        // the 30th has prices but no consumption
+        assert_eq!(days.len(), 1);
+
+        let day = &days[0];
+
+        assert_eq!(day.starts_at, moment("2024-06-30T22:00:00Z"));
+        assert_eq!(day.energy_kwh, 4.0);
+        assert!((day.actual_cost - 0.5).abs() < 1e-9);
+        // at the average of 0.1, 0.2, 0.4 and 0.3
+        assert!((day.average_cost - 1.0).abs() < 1e-9);
+        // 3 kWh at 0.4 and 1 kWh at 0.3
+        assert!((day.worst_cost - 1.5).abs() < 1e-9);
+
+        let total = DaySavings::total(day.starts_at, &days);
+
+        assert_eq!(total, days[0]);
+    }
+}
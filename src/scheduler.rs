@@ -0,0 +1,1370 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Months, NaiveDate, TimeDelta, Utc};
+use serde::Serialize;
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    actuator::actuate_devices,
+    backup::back_up,
+    cron::CronSchedule,
+    domain::{
+        start_of_day, ElectricityPriceProvider, ElectricityProviderError, PriceFetch, PricePoint,
+        SolarProduction,
+    },
+    dsmr::read_p1_meter,
+    formula::FormulaApplication,
+    google_calendar::sync_calendar,
+    influxdb::export_prices,
+    job_lock::JobLock,
+    mqtt_publisher::publish_prices,
+    notification::send_notifications,
+    setup::{AppState, Settings},
+    weather::fetch_weather,
+    webhook::{deliver_due_deliveries, dispatch_prices_ingested, dispatch_scheduled_events},
+};
+
+/// The delay before the first retry when the prices of tomorrow are not yet published
+const INITIAL_RETRY_DELAY: TimeDelta = TimeDelta::minutes(5);
+
+/// The delay between retries doubles up to this maximum
+const MAX_RETRY_DELAY: TimeDelta = TimeDelta::hours(1);
+
+const ACTUATION_JOB: &str = "device actuation";
+const BACKUP_JOB: &str = "s3 backup";
+const CARBON_INTENSITY_FETCH_JOB: &str = "carbon intensity fetch";
+const CATCH_UP_JOB: &str = "catch up";
+const GOOGLE_CALENDAR_JOB: &str = "google calendar sync";
+const INFLUXDB_EXPORT_JOB: &str = "influxdb export";
+const MQTT_PUBLICATION_JOB: &str = "mqtt publication";
+const PRICE_FETCH_JOB: &str = "price fetch";
+const PRICE_PARTITION_JOB: &str = "price partitions";
+const PRICE_PUBLICATION_JOB: &str = "price publication";
+const RENEWABLE_SHARE_FETCH_JOB: &str = "renewable share fetch";
+const RETENTION_JOB: &str = "retention";
+const SOLAR_FORECAST_FETCH_JOB: &str = "solar forecast fetch";
+const TIBBER_CONSUMPTION_SYNC_JOB: &str = "tibber consumption sync";
+const WEATHER_FETCH_JOB: &str = "weather fetch";
+const WEBHOOK_DELIVERY_JOB: &str = "webhook deliveries";
+const WEBHOOK_EVENT_JOB: &str = "webhook events";
+const NOTIFICATION_JOB: &str = "notifications";
+
+/// How often to check for notifications that are due, such as windows that are about to start
+const NOTIFICATION_SCHEDULE: &str = "* * * * *";
+
+/// How often to create the partitions of the prices of the coming months
+const PRICE_PARTITION_SCHEDULE: &str = "0 2 * * *";
+
+/// How many months ahead of the current one prices are partitioned
+const PRICE_PARTITION_MONTHS_AHEAD: u32 = 2;
+
+/// How often to switch devices at the boundaries of their planned windows
+const ACTUATION_SCHEDULE: &str = "* * * * *";
+
+/// How often to push the planned windows of devices to Google Calendar
+const GOOGLE_CALENDAR_SCHEDULE: &str = "*/15 * * * *";
+
+/// Sync the consumption of Tibber every hour, once the past hour is measured
+const TIBBER_CONSUMPTION_SYNC_SCHEDULE: &str = "15 * * * *";
+
+/// How often to check for webhook events that depend on time
+const WEBHOOK_EVENT_SCHEDULE: &str = "* * * * *";
+
+/// How often to retry webhook deliveries that failed
+const WEBHOOK_DELIVERY_SCHEDULE: &str = "* * * * *";
+
+/// The state of a background job, as of its last run
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct JobStatus {
+    pub(crate) name: &'static str,
+    /// The crontab expression of the job, absent for jobs that only run at startup
+    pub(crate) schedule: Option<String>,
+    pub(crate) running: bool,
+    /// Whether the last run was skipped because another instance was running the job
+    pub(crate) skipped: bool,
+    pub(crate) last_run_at: Option<DateTime<Utc>>,
+    pub(crate) last_duration_ms: Option<i64>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) next_run_at: Option<DateTime<Utc>>,
+}
+
+/// The status of every background job, shared between the scheduler and the http server.
+/// With a lock, a job is skipped while another instance runs it.
+#[derive(Debug, Clone)]
+pub(crate) struct Jobs {
+    statuses: Arc<RwLock<BTreeMap<&'static str, JobStatus>>>,
+    lock: Option<JobLock>,
+}
+
+impl Jobs {
+    pub(crate) fn new(lock: Option<JobLock>) -> Self {
+        Self {
+            statuses: Arc::default(),
+            lock,
+        }
+    }
+
+    pub(crate) fn statuses(&self) -> Vec<JobStatus> {
+        self.statuses
+            .read()
+            .map(|jobs| jobs.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn register(&self, name: &'static str, schedule: Option<&CronSchedule>) {
+        if let Ok(mut jobs) = self.statuses.write() {
+            jobs.insert(
+                name,
+                JobStatus {
+                    name,
+                    schedule: schedule.map(|schedule| schedule.to_string()),
+                    running: false,
+                    skipped: false,
+                    last_run_at: None,
+                    last_duration_ms: None,
+                    last_error: None,
+                    next_run_at: None,
+                },
+            );
+        }
+    }
+
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut JobStatus)) {
+        if let Ok(mut jobs) = self.statuses.write() {
+            if let Some(job) = jobs.get_mut(name) {
+                update(job);
+            }
+        }
+    }
+
+    /// Run a job within a span of its own, keeping track of when it ran, how long it took and
+    /// whether it failed
+    async fn run<Fut>(&self, name: &'static str, job: Fut)
+    where
+        Fut: Future<Output = Result<(), String>>,
+    {
+        self.run_job(name, job)
+            .instrument(info_span!("job", otel.name = name, job = name))
+            .await
+    }
+
+    async fn run_job<Fut>(&self, name: &'static str, job: Fut)
+    where
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let started_at = Utc::now();
+
+        let guard = match &self.lock {
+            Some(lock) => match lock.try_acquire(name).await {
+                Ok(Some(guard)) => Some(guard),
+                Ok(None) => {
+                    info!("{} is running on another instance, skipping it", name);
+                    self.update(name, |status| {
+                        status.skipped = true;
+                        status.next_run_at = None;
+                    });
+                    return;
+                }
+                Err(e) => {
+                    error!("unable to acquire the lock of {}, {}", name, e);
+                    self.update(name, |status| {
+                        status.last_error = Some(e);
+                        status.next_run_at = None;
+                    });
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.update(name, |status| {
+            status.running = true;
+            status.skipped = false;
+            status.next_run_at = None;
+        });
+
+        let result = job.await;
+
+        if let Some(guard) = guard {
+            guard.release().await;
+        }
+
+        if let Err(e) = &result {
+            error!("{} failed, {}", name, e);
+        }
+
+        self.update(name, |status| {
+            status.running = false;
+            status.last_run_at = Some(started_at);
+            status.last_duration_ms = Some((Utc::now() - started_at).num_milliseconds());
+            status.last_error = result.err();
+        });
+    }
+}
+
+/// Fetch and persist the prices of the provider in the background. Missing days are caught up
+/// on at startup. Today's prices are fetched right away and then at every moment of the price
+/// fetch schedule, tomorrow's prices at every moment of the publication schedule.
+/// Every minute, the notifications that are due are sent and webhooks are called for windows
+/// that started and prices above the alert threshold. Webhook deliveries that failed are
+/// retried every minute as well, and devices with an actuator are switched on and off at the
+/// boundaries of their planned windows. When MQTT is configured, prices are published at every
+/// moment of its schedule. When Google Calendar is configured, the planned windows of devices
+/// are pushed to it every 15 minutes. When backups are configured, they are stored in the
+/// bucket at every moment of their schedule. When a carbon intensity provider is configured,
+/// the carbon intensity is fetched right away and then at every moment of the price fetch
+/// schedule, and so are the forecast production of solar panels, the forecast renewable share
+/// of the bidding zone and the weather forecast of the weather location when they are
+/// configured. When the consumption sync of Tibber is enabled, the consumption of the past
+/// days is synced right away and then every hour. History that is older than its retention is
+/// pruned at every moment of the retention schedule, and the partitions of the prices of the
+/// coming months are created right away and then every night.
+pub(crate) fn start_scheduler(state: AppState) {
+    let notification_schedule = CronSchedule::parse(NOTIFICATION_SCHEDULE, state.timezone)
+        .expect("the notification schedule is valid");
+
+    let settings = state.settings();
+    let jobs = &state.jobs;
+    jobs.register(CATCH_UP_JOB, None);
+    jobs.register(
+        PRICE_FETCH_JOB,
+        Some(&settings.scheduling.price_fetch_schedule),
+    );
+    jobs.register(
+        PRICE_PUBLICATION_JOB,
+        Some(&settings.scheduling.price_publication_schedule),
+    );
+    jobs.register(NOTIFICATION_JOB, Some(&notification_schedule));
+
+    let webhook_schedule = CronSchedule::parse(WEBHOOK_EVENT_SCHEDULE, state.timezone)
+        .expect("the webhook event schedule is valid");
+    jobs.register(WEBHOOK_EVENT_JOB, Some(&webhook_schedule));
+
+    let delivery_schedule = CronSchedule::parse(WEBHOOK_DELIVERY_SCHEDULE, state.timezone)
+        .expect("the webhook delivery schedule is valid");
+    jobs.register(WEBHOOK_DELIVERY_JOB, Some(&delivery_schedule));
+
+    let actuation_schedule = CronSchedule::parse(ACTUATION_SCHEDULE, state.timezone)
+        .expect("the actuation schedule is valid");
+    jobs.register(ACTUATION_JOB, Some(&actuation_schedule));
+    jobs.register(RETENTION_JOB, Some(&settings.scheduling.retention.schedule));
+
+    let partition_schedule = CronSchedule::parse(PRICE_PARTITION_SCHEDULE, state.timezone)
+        .expect("the price partition schedule is valid");
+    jobs.register(PRICE_PARTITION_JOB, Some(&partition_schedule));
+
+    let today_state = state.clone();
+    tokio::spawn(async move {
+        today_state
+            .jobs
+            .run(CATCH_UP_JOB, catch_up_missing_days(&today_state))
+            .await;
+
+        run_on_schedule(
+            PRICE_FETCH_JOB,
+            today_state.jobs.clone(),
+            JobSchedule::configured(&today_state, |settings| {
+                &settings.scheduling.price_fetch_schedule
+            }),
+            true,
+            move || {
+                let state = today_state.clone();
+                async move { fetch_missing_prices(&state).await }
+            },
+        )
+        .await
+    });
+
+    let tomorrow_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        PRICE_PUBLICATION_JOB,
+        state.jobs.clone(),
+        JobSchedule::configured(&state, |settings| {
+            &settings.scheduling.price_publication_schedule
+        }),
+        false,
+        move || {
+            let state = tomorrow_state.clone();
+            async move { fetch_prices_of_tomorrow(&state).await }
+        },
+    ));
+
+    let notification_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        NOTIFICATION_JOB,
+        state.jobs.clone(),
+        JobSchedule::Fixed(notification_schedule),
+        false,
+        move || {
+            let state = notification_state.clone();
+            async move { send_notifications(&state).await }
+        },
+    ));
+
+    let webhook_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        WEBHOOK_EVENT_JOB,
+        state.jobs.clone(),
+        JobSchedule::Fixed(webhook_schedule),
+        false,
+        move || {
+            let state = webhook_state.clone();
+            async move { dispatch_scheduled_events(&state).await }
+        },
+    ));
+
+    let delivery_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        WEBHOOK_DELIVERY_JOB,
+        state.jobs.clone(),
+        JobSchedule::Fixed(delivery_schedule),
+        true,
+        move || {
+            let state = delivery_state.clone();
+            async move { deliver_due_deliveries(&state).await }
+        },
+    ));
+
+    let actuation_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        ACTUATION_JOB,
+        state.jobs.clone(),
+        JobSchedule::Fixed(actuation_schedule),
+        false,
+        move || {
+            let state = actuation_state.clone();
+            async move { actuate_devices(&state).await }
+        },
+    ));
+
+    let retention_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        RETENTION_JOB,
+        state.jobs.clone(),
+        JobSchedule::configured(&state, |settings| &settings.scheduling.retention.schedule),
+        false,
+        move || {
+            let state = retention_state.clone();
+            async move { prune_history(&state).await }
+        },
+    ));
+
+    let partition_state = state.clone();
+    tokio::spawn(run_on_schedule(
+        PRICE_PARTITION_JOB,
+        state.jobs.clone(),
+        JobSchedule::Fixed(partition_schedule),
+        true,
+        move || {
+            let state = partition_state.clone();
+            async move { create_upcoming_price_partitions(&state).await }
+        },
+    ));
+
+    if state.influxdb.is_some() {
+        jobs.register(INFLUXDB_EXPORT_JOB, None);
+    }
+
+    if state.google_calendar.is_some() {
+        let calendar_schedule = CronSchedule::parse(GOOGLE_CALENDAR_SCHEDULE, state.timezone)
+            .expect("the google calendar schedule is valid");
+        jobs.register(GOOGLE_CALENDAR_JOB, Some(&calendar_schedule));
+
+        let calendar_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            GOOGLE_CALENDAR_JOB,
+            state.jobs.clone(),
+            JobSchedule::Fixed(calendar_schedule),
+            true,
+            move || {
+                let state = calendar_state.clone();
+                async move { sync_calendar(&state).await }
+            },
+        ));
+    }
+
+    if let Some(backup) = &state.backup {
+        jobs.register(BACKUP_JOB, Some(&backup.schedule));
+
+        let backup_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            BACKUP_JOB,
+            state.jobs.clone(),
+            JobSchedule::Fixed(backup.schedule.clone()),
+            false,
+            move || {
+                let state = backup_state.clone();
+                async move { back_up(&state).await }
+            },
+        ));
+    }
+
+    if state.carbon_intensity_provider.is_some() {
+        jobs.register(
+            CARBON_INTENSITY_FETCH_JOB,
+            Some(&settings.scheduling.price_fetch_schedule),
+        );
+
+        let carbon_intensity_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            CARBON_INTENSITY_FETCH_JOB,
+            state.jobs.clone(),
+            JobSchedule::configured(&state, |settings| &settings.scheduling.price_fetch_schedule),
+            true,
+            move || {
+                let state = carbon_intensity_state.clone();
+                async move { fetch_carbon_intensity(&state).await }
+            },
+        ));
+    }
+
+    if state.solar_forecast.is_some() {
+        jobs.register(
+            SOLAR_FORECAST_FETCH_JOB,
+            Some(&settings.scheduling.price_fetch_schedule),
+        );
+
+        let solar_forecast_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            SOLAR_FORECAST_FETCH_JOB,
+            state.jobs.clone(),
+            JobSchedule::configured(&state, |settings| &settings.scheduling.price_fetch_schedule),
+            true,
+            move || {
+                let state = solar_forecast_state.clone();
+                async move { fetch_solar_forecast(&state).await }
+            },
+        ));
+    }
+
+    if state.weather_location.is_some() {
+        jobs.register(
+            WEATHER_FETCH_JOB,
+            Some(&settings.scheduling.price_fetch_schedule),
+        );
+
+        let weather_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            WEATHER_FETCH_JOB,
+            state.jobs.clone(),
+            JobSchedule::configured(&state, |settings| &settings.scheduling.price_fetch_schedule),
+            true,
+            move || {
+                let state = weather_state.clone();
+                async move { fetch_weather_forecast(&state).await }
+            },
+        ));
+    }
+
+    if state.entsoe.is_some() {
+        jobs.register(
+            RENEWABLE_SHARE_FETCH_JOB,
+            Some(&settings.scheduling.price_fetch_schedule),
+        );
+
+        let renewable_share_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            RENEWABLE_SHARE_FETCH_JOB,
+            state.jobs.clone(),
+            JobSchedule::configured(&state, |settings| &settings.scheduling.price_fetch_schedule),
+            true,
+            move || {
+                let state = renewable_share_state.clone();
+                async move { fetch_renewable_generation(&state).await }
+            },
+        ));
+    }
+
+    if state.tibber_consumption_sync.is_some() {
+        let sync_schedule = CronSchedule::parse(TIBBER_CONSUMPTION_SYNC_SCHEDULE, state.timezone)
+            .expect("the tibber consumption sync schedule is valid");
+        jobs.register(TIBBER_CONSUMPTION_SYNC_JOB, Some(&sync_schedule));
+
+        let sync_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            TIBBER_CONSUMPTION_SYNC_JOB,
+            state.jobs.clone(),
+            JobSchedule::Fixed(sync_schedule),
+            true,
+            move || {
+                let state = sync_state.clone();
+                async move { sync_tibber_consumption(&state).await }
+            },
+        ));
+    }
+
+    if let Some(p1_meter) = &state.p1_meter {
+        tokio::spawn(read_p1_meter(
+            p1_meter.clone(),
+            state.consumption_repository.clone(),
+        ));
+    }
+
+    if let Some(mqtt) = &state.mqtt {
+        jobs.register(MQTT_PUBLICATION_JOB, Some(&mqtt.publish_schedule));
+
+        tokio::spawn(publish_on_ingested_prices(state.clone()));
+
+        let mqtt_state = state.clone();
+        tokio::spawn(run_on_schedule(
+            MQTT_PUBLICATION_JOB,
+            state.jobs.clone(),
+            JobSchedule::Fixed(mqtt.publish_schedule.clone()),
+            true,
+            move || {
+                let state = mqtt_state.clone();
+                async move { publish_prices(&state).await }
+            },
+        ));
+    }
+}
+
+/// When a job runs, either on a fixed schedule or on one of the settings, of which the job
+/// follows the changes when the settings are reloaded
+enum JobSchedule {
+    Fixed(CronSchedule),
+    Configured(
+        watch::Receiver<Arc<Settings>>,
+        fn(&Settings) -> &CronSchedule,
+    ),
+}
+
+impl JobSchedule {
+    fn configured(state: &AppState, schedule: fn(&Settings) -> &CronSchedule) -> Self {
+        Self::Configured(state.watch_settings(), schedule)
+    }
+
+    fn current(&mut self) -> CronSchedule {
+        match self {
+            Self::Fixed(schedule) => schedule.clone(),
+            Self::Configured(settings, schedule) => schedule(&settings.borrow_and_update()).clone(),
+        }
+    }
+
+    /// Completes once the settings are reloaded with another schedule than the current one,
+    /// which never happens to a fixed schedule
+    async fn changed(&mut self, current: &CronSchedule) {
+        if let Self::Configured(settings, schedule) = self {
+            let current = current.to_string();
+
+            while settings.changed().await.is_ok() {
+                if schedule(&settings.borrow_and_update()).to_string() != current {
+                    return;
+                }
+            }
+        }
+
+        std::future::pending().await
+    }
+}
+
+/// Run a job at every moment of a schedule, and optionally once right away
+async fn run_on_schedule<F, Fut>(
+    name: &'static str,
+    jobs: Jobs,
+    mut schedule: JobSchedule,
+    run_at_start: bool,
+    job: F,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    if run_at_start {
+        jobs.run(name, job()).await;
+    }
+
+    loop {
+        let current = schedule.current();
+        let now = Utc::now();
+
+        let Some(next) = current.next_after(now) else {
+            warn!("the {} schedule has no upcoming moments", name);
+            schedule.changed(&current).await;
+            continue;
+        };
+
+        info!("next {} scheduled at {}", name, next);
+        jobs.update(name, |status| {
+            status.schedule = Some(current.to_string());
+            status.next_run_at = Some(next);
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep((next - now).to_std().unwrap_or_default()) => {}
+            _ = schedule.changed(&current) => continue,
+        }
+
+        jobs.run(name, job()).await;
+    }
+}
+
+/// Backfill the prices of past days within the catch-up period that are missing or incomplete,
+/// for instance because electrack was not running
+async fn catch_up_missing_days(state: &AppState) -> Result<(), String> {
+    backfill_missing_days(state, state.settings().scheduling.catch_up_days)
+        .await
+        .map(|_| ())
+}
+
+/// Backfill the prices of the given number of past days that are missing or incomplete in any
+/// area, returning how many days were fetched. The outcome of every day is recorded.
+pub(crate) async fn backfill_missing_days(state: &AppState, days: i64) -> Result<usize, String> {
+    let mut fetched = 0;
+
+    for provider in &state.settings().electricity_providers {
+        fetched += backfill_missing_days_of(state, provider, days).await?;
+    }
+
+    Ok(fetched)
+}
+
+async fn backfill_missing_days_of(
+    state: &AppState,
+    provider: &Arc<dyn ElectricityPriceProvider>,
+    days: i64,
+) -> Result<usize, String> {
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+    let mut missing_days = Vec::new();
+
+    for days_ago in (1..=days).rev() {
+        let date = today - TimeDelta::days(days_ago);
+        let start = start_of_day(&state.timezone, date);
+        let end = start_of_day(&state.timezone, date + TimeDelta::days(1));
+
+        let statistics = state
+            .price_repository
+            .fetch_price_statistics(start, end, provider.area())
+            .await
+            .map_err(|e| format!("unable to check for prices of {}, {}", date, e))?;
+
+        // days with a daylight saving time transition have 23 or 25 hours
+        if statistics.hours < (end - start).num_hours() {
+            missing_days.push((date, start, end));
+        }
+    }
+
+    if missing_days.is_empty() {
+        return Ok(0);
+    }
+
+    let missing = missing_days.len();
+    info!(
+        "catching up on the prices of {} days{}",
+        missing,
+        of_area(provider.as_ref())
+    );
+
+    for (date, start, end) in missing_days {
+        let outcome = persist_fetched_prices(
+            state,
+            provider.as_ref(),
+            provider.fetch_historical_prices(start, end).await,
+        )
+        .await;
+
+        let fetch = PriceFetch {
+            date,
+            area: provider.area().map(str::to_string),
+            attempted_at: Utc::now(),
+            attempts: 1,
+            prices: outcome.as_ref().map_or(0, |prices| prices.len() as i32),
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+        };
+
+        if let Err(e) = state
+            .price_repository
+            .record_price_fetch(&fetch, provider.name())
+            .await
+        {
+            error!("unable to record the price fetch, {}", e);
+        }
+
+        // every day would fail the same way
+        if let Err(e @ ElectricityProviderError::HistoryUnavailable(_)) = outcome {
+            return Err(e.to_string());
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Fetch the prices of today from every provider, unless all of its prices are already stored
+async fn fetch_missing_prices(state: &AppState) -> Result<(), String> {
+    let mut errors = vec![];
+
+    for provider in &state.settings().electricity_providers {
+        if let Err(e) = fetch_missing_prices_of(state, provider).await {
+            errors.push(e);
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors.join(", ")),
+    }
+}
+
+async fn fetch_missing_prices_of(
+    state: &AppState,
+    provider: &Arc<dyn ElectricityPriceProvider>,
+) -> Result<(), String> {
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+    let start = start_of_day(&state.timezone, today);
+    let end = start_of_day(&state.timezone, today + TimeDelta::days(1));
+
+    let statistics = state
+        .price_repository
+        .fetch_price_statistics(start, end, provider.area())
+        .await
+        .map_err(|e| format!("unable to check for prices of today, {}", e))?;
+
+    if statistics.hours >= (end - start).num_hours() {
+        info!(
+            "prices for today{} already fetched",
+            of_area(provider.as_ref())
+        );
+        return Ok(());
+    }
+
+    info!(
+        "prices for today{} not yet fetched",
+        of_area(provider.as_ref())
+    );
+
+    // the next scheduled moment retries
+    fetch_prices_from_provider(state, provider)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the prices of tomorrow from every provider at the same time, as each of them retries
+/// until they are published
+async fn fetch_prices_of_tomorrow(state: &AppState) -> Result<(), String> {
+    let mut fetches = JoinSet::new();
+
+    for provider in &state.settings().electricity_providers {
+        let (state, provider) = (state.clone(), provider.clone());
+        fetches.spawn(
+            async move { fetch_prices_of_tomorrow_of(&state, &provider).await }.in_current_span(),
+        );
+    }
+
+    let mut errors = vec![];
+
+    while let Some(outcome) = fetches.join_next().await {
+        if let Err(e) = outcome
+            .map_err(|e| e.to_string())
+            .and_then(|outcome| outcome)
+        {
+            errors.push(e);
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors.join(", ")),
+    }
+}
+
+/// Fetch the prices of tomorrow, retrying on a backoff until they are published or tomorrow
+/// has started. The outcome is recorded.
+async fn fetch_prices_of_tomorrow_of(
+    state: &AppState,
+    provider: &Arc<dyn ElectricityPriceProvider>,
+) -> Result<(), String> {
+    let tomorrow = Utc::now().with_timezone(&state.timezone).date_naive() + TimeDelta::days(1);
+    let start = start_of_day(&state.timezone, tomorrow);
+    let end = start_of_day(&state.timezone, tomorrow + TimeDelta::days(1));
+
+    let statistics = state
+        .price_repository
+        .fetch_price_statistics(start, end, provider.area())
+        .await
+        .map_err(|e| format!("unable to check for prices of tomorrow, {}", e))?;
+
+    // another instance may have fetched them already
+    if statistics.hours >= (end - start).num_hours() {
+        info!(
+            "prices for tomorrow{} already fetched",
+            of_area(provider.as_ref())
+        );
+        return Ok(());
+    }
+
+    let mut attempts = 0;
+    let mut delay = INITIAL_RETRY_DELAY;
+
+    let outcome = loop {
+        attempts += 1;
+
+        let outcome = fetch_prices_from_provider(state, provider)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|prices| {
+                prices
+                    .iter()
+                    .filter(|price| price.moment >= start && price.moment < end)
+                    .count()
+            })
+            .and_then(|count| match count {
+                0 => Err(format!(
+                    "the prices of tomorrow{} are not yet published",
+                    of_area(provider.as_ref())
+                )),
+                count => Ok(count),
+            });
+
+        match outcome {
+            Ok(_) => break outcome,
+            Err(_) if Utc::now() + delay >= start => break outcome,
+            Err(e) => {
+                warn!("{}, retrying in {} minutes", e, delay.num_minutes());
+                tokio::time::sleep(delay.to_std().unwrap_or_default()).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    };
+
+    match &outcome {
+        Ok(count) => info!(
+            "fetched {} prices of {}{} in {} attempts",
+            count,
+            tomorrow,
+            of_area(provider.as_ref()),
+            attempts
+        ),
+        Err(_) => warn!(
+            "giving up on the prices of {}{} after {} attempts",
+            tomorrow,
+            of_area(provider.as_ref()),
+            attempts
+        ),
+    }
+
+    let fetch = PriceFetch {
+        date: tomorrow,
+        area: provider.area().map(str::to_string),
+        attempted_at: Utc::now(),
+        attempts,
+        prices: outcome.as_ref().map_or(0, |count| *count as i32),
+        error: outcome.as_ref().err().cloned(),
+    };
+
+    if let Err(e) = state
+        .price_repository
+        .record_price_fetch(&fetch, provider.name())
+        .await
+    {
+        error!("unable to record the price fetch, {}", e);
+    }
+
+    outcome.map(|_| ())
+}
+
+/// ` in {area}` when the provider is configured with an area, to tell the areas apart in logs
+fn of_area(provider: &dyn ElectricityPriceProvider) -> String {
+    provider
+        .area()
+        .map(|area| format!(" in {}", area))
+        .unwrap_or_default()
+}
+
+/// Fetch the prices of the provider and persist them
+/// When a price formula is configured to be applied at ingest, or a tariff is configured,
+/// the consumer prices are persisted as well
+/// Concurrent calls for the same provider and area are coalesced into a single fetch and
+/// insert.
+pub(crate) async fn fetch_prices_from_provider(
+    state: &AppState,
+    provider: &Arc<dyn ElectricityPriceProvider>,
+) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+    let flight_state = state.clone();
+    let flight_provider = provider.clone();
+
+    state
+        .price_fetches
+        .run(
+            (provider.name(), provider.area().map(str::to_string)),
+            async move {
+                let (state, provider) = (flight_state, flight_provider);
+                persist_fetched_prices(&state, provider.as_ref(), provider.fetch_prices().await)
+                    .await
+            }
+            // the fetch is spawned, so its span is attached to that of the caller
+            .instrument(info_span!(
+                "provider fetch",
+                provider = provider.name(),
+                area = provider.area()
+            )),
+        )
+        .await
+        .unwrap_or_else(|| {
+            Err(ElectricityProviderError::FetchPrices(
+                "fetching the prices was aborted".to_string(),
+            ))
+        })
+}
+
+/// Re-fetch the prices from the providers of an area, or from every provider, replacing those
+/// that are already stored, and return those of the given local dates. Only today and tomorrow
+/// can be fetched.
+pub(crate) async fn refresh_prices(
+    state: &AppState,
+    dates: &[NaiveDate],
+    area: Option<&str>,
+) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+    let providers = state
+        .settings()
+        .electricity_providers
+        .iter()
+        .filter(|provider| area.is_none() || provider.area() == area)
+        .cloned()
+        .collect::<Vec<Arc<dyn ElectricityPriceProvider>>>();
+
+    if providers.is_empty() {
+        return Err(ElectricityProviderError::FetchPrices(format!(
+            "no provider is configured for area {}",
+            area.unwrap_or_default()
+        )));
+    }
+
+    let mut refreshed = vec![];
+
+    for provider in &providers {
+        refreshed.extend(
+            fetch_prices_from_provider(state, provider)
+                .await?
+                .into_iter()
+                .filter(|price| {
+                    dates.contains(&price.moment.with_timezone(&state.timezone).date_naive())
+                }),
+        );
+    }
+
+    Ok(refreshed)
+}
+
+/// Fetch the prices of a local date from every provider and persist them, from the history of
+/// the providers unless the date is today or tomorrow
+pub(crate) async fn fetch_prices_of_date(
+    state: &AppState,
+    date: NaiveDate,
+) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+
+    if date == today || date == today + TimeDelta::days(1) {
+        return refresh_prices(state, &[date], None).await;
+    }
+
+    let start = start_of_day(&state.timezone, date);
+    let end = start_of_day(&state.timezone, date + TimeDelta::days(1));
+
+    let mut fetched = vec![];
+
+    for provider in &state.settings().electricity_providers {
+        fetched.extend(
+            persist_fetched_prices(
+                state,
+                provider.as_ref(),
+                provider.fetch_historical_prices(start, end).await,
+            )
+            .await?,
+        );
+    }
+
+    Ok(fetched)
+}
+
+/// Persist the prices fetched from the provider, logging any failure. Once they are persisted,
+/// the prices are exported to InfluxDB and webhooks are called. Publishing them over MQTT
+/// follows from the notification of the persisted prices.
+async fn persist_fetched_prices(
+    state: &AppState,
+    provider: &dyn ElectricityPriceProvider,
+    fetch_result: Result<Vec<PricePoint>, ElectricityProviderError>,
+) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+    let price_repository = &*state.price_repository;
+
+    let fetch_result = fetch_result.map(|prices| with_consumer_prices(state, prices));
+
+    let persisting_result = match fetch_result {
+        Ok(fetched_prices) => {
+            info!(
+                "Fetched {} prices{}",
+                fetched_prices.len(),
+                of_area(provider)
+            );
+            price_repository
+                .persist_prices(&fetched_prices, provider.name())
+                .await
+                .and(Ok(fetched_prices))
+                .inspect(|prices| {
+                    export_in_background(state, provider.name(), prices);
+                    dispatch_prices_ingested(state, provider, prices);
+                })
+        }
+        Err(error) => {
+            error!("{}", error);
+            return Err(error.clone());
+        }
+    };
+
+    match persisting_result {
+        Ok(prices) => Ok(prices),
+        Err(error) => {
+            error!("{}", error);
+            Err(ElectricityProviderError::FetchPrices(error.to_string()))
+        }
+    }
+}
+
+/// Fetch the carbon intensity from the provider and persist it
+async fn fetch_carbon_intensity(state: &AppState) -> Result<(), String> {
+    let Some(provider) = &state.carbon_intensity_provider else {
+        return Ok(());
+    };
+
+    let intensities = provider
+        .fetch_carbon_intensity()
+        .instrument(info_span!("provider fetch", provider = provider.name()))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .carbon_intensity_repository
+        .persist_carbon_intensity(&intensities)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the forecast production of the solar panels and persist it
+async fn fetch_solar_forecast(state: &AppState) -> Result<(), String> {
+    let Some(solar_forecast) = &state.solar_forecast else {
+        return Ok(());
+    };
+
+    let forecast = solar_forecast
+        .fetch_solar_forecast()
+        .instrument(info_span!("provider fetch", provider = "forecastsolar"))
+        .await?;
+
+    state
+        .solar_forecast_repository
+        .persist_solar_forecast(&forecast)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the weather forecast of today and tomorrow and persist it. Without a provider of the
+/// solar forecast, the production of the solar panels is derived from the irradiance on them.
+async fn fetch_weather_forecast(state: &AppState) -> Result<(), String> {
+    let Some(location) = &state.weather_location else {
+        return Ok(());
+    };
+
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+
+    let weather = fetch_weather(
+        location,
+        state.solar_panels.as_ref(),
+        start_of_day(&state.timezone, today),
+        start_of_day(&state.timezone, today + TimeDelta::days(2)),
+    )
+    .instrument(info_span!("provider fetch", provider = "openmeteo"))
+    .await?;
+
+    state
+        .weather_repository
+        .persist_weather(&weather)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (Some(panels), None) = (&state.solar_panels, &state.solar_forecast) else {
+        return Ok(());
+    };
+
+    let forecast = weather
+        .iter()
+        .filter_map(|hour| {
+            hour.tilted_irradiance
+                .map(|tilted_irradiance| SolarProduction {
+                    moment: hour.moment,
+                    energy_kwh: panels.production_kwh(tilted_irradiance),
+                })
+        })
+        .collect::<Vec<SolarProduction>>();
+
+    state
+        .solar_forecast_repository
+        .persist_solar_forecast(&forecast)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the forecast generation from wind and sun and the load of today and tomorrow, and
+/// persist it
+async fn fetch_renewable_generation(state: &AppState) -> Result<(), String> {
+    let Some(entsoe) = &state.entsoe else {
+        return Ok(());
+    };
+
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+
+    let generation = entsoe
+        .fetch_renewable_generation(
+            start_of_day(&state.timezone, today),
+            start_of_day(&state.timezone, today + TimeDelta::days(2)),
+        )
+        .instrument(info_span!("provider fetch", provider = "entsoe"))
+        .await?;
+
+    state
+        .renewable_generation_repository
+        .persist_renewable_generation(&generation)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Fetch the hourly consumption of the past days measured by Tibber and persist it, replacing
+/// the readings of the same hours
+async fn sync_tibber_consumption(state: &AppState) -> Result<(), String> {
+    let Some(sync) = &state.tibber_consumption_sync else {
+        return Ok(());
+    };
+
+    let consumption = sync
+        .tibber
+        .fetch_consumption(sync.days * 24)
+        .instrument(info_span!("provider fetch", provider = "tibber"))
+        .await?;
+
+    state
+        .consumption_repository
+        .persist_consumption(&consumption)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Delete the prices, the log of price fetches and the planned windows that are older than
+/// their retention
+async fn prune_history(state: &AppState) -> Result<(), String> {
+    let settings = state.settings();
+    let retention = &settings.scheduling.retention;
+    let now = Utc::now();
+
+    if let Some(prices) = retention.prices {
+        let pruned = state.price_repository.prune_prices(now - prices).await?;
+        info!("pruned {} prices", pruned);
+    }
+
+    if let Some(price_fetches) = retention.price_fetches {
+        let pruned = state
+            .price_repository
+            .prune_price_fetches(now - price_fetches)
+            .await?;
+        info!("pruned {} price fetches", pruned);
+    }
+
+    if let Some(planned_windows) = retention.planned_windows {
+        let pruned = state
+            .planned_window_repository
+            .prune_planned_windows(now - planned_windows)
+            .await?;
+        info!("pruned {} planned windows", pruned);
+    }
+
+    Ok(())
+}
+
+/// Create the partitions of the prices of the current and the coming months, so storing the
+/// prices of a new month never waits for its partition to be created
+async fn create_upcoming_price_partitions(state: &AppState) -> Result<(), String> {
+    let now = Utc::now();
+
+    let months = (0..=PRICE_PARTITION_MONTHS_AHEAD)
+        .filter_map(|months| now.checked_add_months(Months::new(months)))
+        .collect::<Vec<DateTime<Utc>>>();
+
+    state
+        .price_repository
+        .create_price_partitions(&months)
+        .await
+}
+
+/// Publish the prices over MQTT whenever prices are persisted, by this or any other instance
+/// that shares the database
+async fn publish_on_ingested_prices(state: AppState) {
+    let mut ingestions = match state.price_repository.listen_for_ingested_prices().await {
+        Ok(ingestions) => ingestions,
+        Err(e) => {
+            error!("unable to listen for ingested prices, {}", e);
+            return;
+        }
+    };
+
+    while let Some(ingested) = ingestions.recv().await {
+        info!(
+            "{} prices of {} from {} until {} were ingested",
+            ingested.prices, ingested.provider, ingested.first_moment, ingested.last_moment
+        );
+
+        state
+            .jobs
+            .run(MQTT_PUBLICATION_JOB, publish_prices(&state))
+            .await;
+    }
+}
+
+/// Export the ingested prices to InfluxDB without waiting for it, when InfluxDB is configured
+fn export_in_background(state: &AppState, provider: &'static str, prices: &[PricePoint]) {
+    if state.influxdb.is_none() {
+        return;
+    }
+
+    let state = state.clone();
+    let prices = prices.to_vec();
+    tokio::spawn(async move {
+        state
+            .jobs
+            .run(
+                INFLUXDB_EXPORT_JOB,
+                export_prices(&state, provider, &prices),
+            )
+            .await
+    });
+}
+
+/// Derive the consumer prices, and their components, according to the configuration
+fn with_consumer_prices(state: &AppState, prices: Vec<PricePoint>) -> Vec<PricePoint> {
+    let settings = state.settings();
+
+    prices
+        .into_iter()
+        .map(
+            |price| match (&settings.pricing.price_formula, &settings.pricing.tariff) {
+                (Some(formula), _) if formula.application == FormulaApplication::Ingest => {
+                    PricePoint {
+                        consumer_amount: Some(formula.apply(price.monetary_amount)),
+                        ..price
+                    }
+                }
+                (_, Some(tariff)) => {
+                    let components = tariff.components(price.monetary_amount, price.moment);
+                    PricePoint {
+                        consumer_amount: Some(components.total()),
+                        components: Some(components),
+                        ..price
+                    }
+                }
+                _ => price,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::async_trait;
+    use chrono_tz::Tz;
+
+    use crate::{memory::MemoryPriceRepository, setup::test_app_state, test_support::price_point};
+
+    use super::*;
+
+    /// Publishes a price of the start of today of its area, or fails without an area
+    struct AreaProvider(Option<&'static str>);
+
+    #[async_trait]
+    impl ElectricityPriceProvider for AreaProvider {
+        fn name(&self) -> &'static str {
+            "demo"
+        }
+
+        fn area(&self) -> Option<&str> {
+            self.0
+        }
+
+        async fn fetch_prices(&self) -> Result<Vec<PricePoint>, ElectricityProviderError> {
+            let area = self.0.ok_or(ElectricityProviderError::FetchPrices(
+                "the provider is down".to_string(),
+            ))?;
+
+            Ok(vec![PricePoint {
+                area: Some(area.to_string()),
+                ..price_point(start_of_day(&Tz::UTC, Utc::now().date_naive()), 0.1)
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices_of_several_areas() {
+        let state = test_app_state(MemoryPriceRepository::new());
+        let mut settings = state.settings().as_ref().clone();
+        settings.electricity_providers = vec![
+            Arc::new(AreaProvider(Some("NL"))),
+            Arc::new(AreaProvider(None)),
+            Arc::new(AreaProvider(Some("SE"))),
+        ];
+        state.replace_settings(settings);
+
+        // a provider that fails does not hold back the others
+        assert!(fetch_missing_prices(&state).await.is_err());
+
+        let today = Utc::now().date_naive();
+        let stored_areas = || async {
+            state
+                .price_repository
+                .fetch_prices(
+                    start_of_day(&Tz::UTC, today),
+                    start_of_day(&Tz::UTC, today + TimeDelta::days(1)),
+                    None,
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|price| price.area.unwrap_or_default())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(stored_areas().await, vec!["NL", "SE"]);
+
+        let refreshed = refresh_prices(&state, &[today], Some("SE")).await.unwrap();
+        assert_eq!(refreshed.len(), 1);
+        assert_eq!(refreshed[0].area.as_deref(), Some("SE"));
+
+        assert!(refresh_prices(&state, &[today], Some("BE")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_configured_schedule_follows_reloads() {
+        let state = test_app_state(MemoryPriceRepository::new());
+        let mut schedule =
+            JobSchedule::configured(&state, |settings| &settings.scheduling.price_fetch_schedule);
+
+        let current = schedule.current();
+        assert_eq!(current.to_string(), "5 * * * * (UTC)");
+
+        // a reload that keeps the schedule does not interrupt the job
+        state.replace_settings(state.settings().as_ref().clone());
+        let unchanged = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            schedule.changed(&current),
+        )
+        .await;
+        assert!(unchanged.is_err());
+
+        let mut settings = state.settings().as_ref().clone();
+        settings.scheduling.price_fetch_schedule =
+            CronSchedule::parse("*/15 * * * *", Tz::UTC).expect("the schedule is valid");
+        state.replace_settings(settings);
+
+        schedule.changed(&current).await;
+        assert_eq!(schedule.current().to_string(), "*/15 * * * * (UTC)");
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    domain::{PricePoint, SolarProduction},
+    setup::AppState,
+};
+
+/// The power a load is assumed to draw when it is not known, in kW
+pub(crate) const DEFAULT_LOAD_KW: f64 = 1.0;
+
+/// The forecast production of the solar panels in the hours between two moments, none when no
+/// solar forecast is configured
+pub(crate) async fn forecast_production(
+    state: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<SolarProduction>, String> {
+    match state.solar_panels {
+        Some(_) => {
+            state
+                .solar_forecast_repository
+                .fetch_solar_forecast(start, end)
+                .await
+        }
+        None => Ok(vec![]),
+    }
+}
+
+/// The cost of every hour for a load drawing `load_kw`, of which only the share that the
+/// forecast production of the solar panels does not cover is drawn from the grid. Hours in
+/// which the panels produce at least the load cost nothing.
+pub(crate) fn self_consumption_costs(
+    prices: &[PricePoint],
+    costs: &[f64],
+    production: &[SolarProduction],
+    load_kw: f64,
+) -> Vec<f64> {
+    let production = production
+        .iter()
+        .map(|hour| (hour.moment, hour.energy_kwh))
+        .collect::<HashMap<DateTime<Utc>, f64>>();
+
+    prices
+        .iter()
+        .zip(costs)
+        .map(|(price, cost)| {
+            let produced = production.get(&price.moment).copied().unwrap_or(0.0);
+            let grid_share = (load_kw - produced.max(0.0)).max(0.0) / load_kw;
+
+            cost * grid_share
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::test_support::price_point;
+
+    #[test]
+    fn test_self_consumption_costs() {
+        let start = DateTime::parse_from_rfc3339("2024-06-30T10:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let prices = (0..3)
+            .map(|hour| price_point(start + TimeDelta::hours(hour), 0.2))
+            .collect::<Vec<PricePoint>>();
+
+        let production = [
+            SolarProduction {
+                moment: start,
+                energy_kwh: 1.0,
+            },
+            SolarProduction {
+                moment: start + TimeDelta::hours(1),
+                energy_kwh: 3.0,
+            },
+        ];
+
+        assert_eq!(
+            self_consumption_costs(&prices, &[0.4, 0.4, 0.4], &production, 2.0),
+            vec![0.2, 0.0, 0.4]
+        );
+    }
+}
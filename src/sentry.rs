@@ -0,0 +1,399 @@
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    panic::{self, PanicHookInfo},
+    process,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::Client;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use tracing::{
+    field::{Field, Visit},
+    span, warn, Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+use url::Url;
+
+use crate::{configuration_file::variable, APP_NAME};
+
+/// How long Sentry may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the same error is not reported again, so a job that fails every minute does not
+/// use up the quota of the project
+const REPEAT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Events that occur while this many wait to be sent are dropped
+const QUEUE_SIZE: usize = 256;
+
+/// A Sentry project that errors and panics are reported to
+#[derive(Clone, Debug, PartialEq)]
+struct SentryConfiguration {
+    envelope_url: Url,
+    public_key: String,
+    environment: String,
+    release: String,
+}
+
+impl SentryConfiguration {
+    /// Parse a DSN, e.g. `https://{public_key}@o123.ingest.sentry.io/{project_id}`, where the
+    /// project id may be preceded by a path
+    fn from_dsn(dsn: &str, environment: String, release: String) -> Result<Self, String> {
+        let dsn = Url::parse(dsn).map_err(|e| e.to_string())?;
+
+        if !["http", "https"].contains(&dsn.scheme()) || dsn.username().is_empty() {
+            return Err("expected http(s)://{public_key}@{host}/{project_id}".to_string());
+        }
+
+        let mut segments = dsn
+            .path_segments()
+            .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+            .unwrap_or(Vec::new());
+
+        let project_id = segments
+            .pop()
+            .filter(|project_id| !project_id.is_empty())
+            .ok_or("the project id is missing".to_string())?;
+
+        let mut envelope_url = dsn.clone();
+        envelope_url
+            .set_username("")
+            .and_then(|_| envelope_url.set_password(None))
+            .map_err(|_| "the host is missing".to_string())?;
+
+        if let Ok(mut path) = envelope_url.path_segments_mut() {
+            path.clear()
+                .extend(segments)
+                .extend(["api", project_id, "envelope", ""]);
+        }
+
+        Ok(Self {
+            envelope_url,
+            public_key: dsn.username().to_string(),
+            environment,
+            release,
+        })
+    }
+}
+
+/// The Sentry project to report to, configured through `SENTRY_DSN`. Events are tagged with
+/// `SENTRY_ENVIRONMENT`, defaulting to `production`, and `SENTRY_RELEASE`, defaulting to the
+/// version of electrack. Logging is not set up yet, so configuration errors are printed.
+fn resolve_sentry() -> Option<SentryConfiguration> {
+    let dsn = variable("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty())?;
+
+    let configuration = SentryConfiguration::from_dsn(
+        &dsn,
+        variable("SENTRY_ENVIRONMENT").unwrap_or("production".to_string()),
+        variable("SENTRY_RELEASE").unwrap_or(format!("{}@{}", APP_NAME, env!("CARGO_PKG_VERSION"))),
+    );
+
+    Some(configuration.unwrap_or_else(|e| {
+        eprintln!("unable to parse SENTRY_DSN, {}", e);
+        process::exit(1);
+    }))
+}
+
+/// Report error-level events of electrack and panics to Sentry, when `SENTRY_DSN` is configured
+pub(crate) fn sentry_layer() -> Option<SentryLayer> {
+    let configuration = resolve_sentry()?;
+    let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+
+    tokio::spawn(send_events(configuration.clone(), receiver));
+
+    let layer = SentryLayer {
+        sender,
+        configuration,
+        reported: Arc::default(),
+    };
+
+    let panic_layer = layer.clone();
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        panic_layer.capture_panic(info);
+        previous_hook(info);
+    }));
+
+    Some(layer)
+}
+
+/// The name of a span as reported, which is the `otel.name` field when it has one
+struct SpanName(String);
+
+/// Reports the error-level events of electrack to Sentry, with the spans they occurred in
+#[derive(Clone)]
+pub(crate) struct SentryLayer {
+    sender: mpsc::Sender<Value>,
+    configuration: SentryConfiguration,
+    /// When errors were last reported, by logger and message
+    reported: Arc<Mutex<HashMap<(String, String), Instant>>>,
+}
+
+impl SentryLayer {
+    /// Queue an event for Sentry, unless the same error was reported recently
+    fn capture(&self, level: &str, logger: &str, message: String, mut event: Map<String, Value>) {
+        {
+            let Ok(mut reported) = self.reported.lock() else {
+                return;
+            };
+
+            let now = Instant::now();
+            reported.retain(|_, at| now.duration_since(*at) < REPEAT_INTERVAL);
+
+            let key = (logger.to_string(), message.clone());
+
+            if reported.contains_key(&key) {
+                return;
+            }
+
+            reported.insert(key, now);
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        event.extend([
+            (
+                "event_id".to_string(),
+                json!(hex::encode(rand::random::<[u8; 16]>())),
+            ),
+            ("timestamp".to_string(), json!(timestamp)),
+            ("platform".to_string(), json!("native")),
+            ("level".to_string(), json!(level)),
+            ("logger".to_string(), json!(logger)),
+            ("message".to_string(), json!({ "formatted": message })),
+            (
+                "environment".to_string(),
+                json!(self.configuration.environment),
+            ),
+            ("release".to_string(), json!(self.configuration.release)),
+        ]);
+
+        if let Ok(server_name) = variable("HOSTNAME") {
+            event.insert("server_name".to_string(), json!(server_name));
+        }
+
+        let _ = self.sender.try_send(Value::Object(event));
+    }
+
+    fn capture_panic(&self, info: &PanicHookInfo) {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or("panicked".to_string());
+
+        let location = info
+            .location()
+            .map(|location| format!("{}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+
+        let mut event = Map::new();
+        event.insert(
+            "exception".to_string(),
+            json!({ "values": [{ "type": "panic", "value": message }] }),
+        );
+        event.insert("culprit".to_string(), json!(location));
+        event.insert(
+            "extra".to_string(),
+            json!({
+                "thread": std::thread::current().name().unwrap_or_default(),
+                "backtrace": Backtrace::force_capture().to_string(),
+            }),
+        );
+
+        self.capture(
+            "fatal",
+            "panic",
+            format!("{} at {}", message, location),
+            event,
+        );
+    }
+}
+
+impl<S> Layer<S> for SentryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut fields = EventFields::default();
+        attributes.record(&mut fields);
+
+        let name = fields
+            .0
+            .remove("otel.name")
+            .and_then(|name| name.as_str().map(str::to_string))
+            .unwrap_or(span.name().to_string());
+
+        span.extensions_mut().insert(SpanName(name));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+
+        if *metadata.level() != Level::ERROR || !metadata.target().starts_with(APP_NAME) {
+            return;
+        }
+
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+
+        let message = fields
+            .0
+            .remove("message")
+            .and_then(|message| message.as_str().map(str::to_string))
+            .unwrap_or_default();
+
+        // from the outermost span, which is the request or job, to the innermost
+        let spans = ctx
+            .event_span(event)
+            .map(|span| {
+                span.scope()
+                    .from_root()
+                    .filter_map(|span| span.extensions().get::<SpanName>().map(|n| n.0.clone()))
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default();
+
+        let mut sentry_event = Map::new();
+
+        if let Some(transaction) = spans.first() {
+            sentry_event.insert("transaction".to_string(), json!(transaction));
+        }
+
+        sentry_event.insert(
+            "extra".to_string(),
+            json!({ "spans": spans, "fields": fields.0 }),
+        );
+
+        self.capture("error", metadata.target(), message, sentry_event);
+    }
+}
+
+/// Send the events as envelopes, one at a time
+async fn send_events(configuration: SentryConfiguration, mut receiver: mpsc::Receiver<Value>) {
+    while let Some(event) = receiver.recv().await {
+        if let Err(e) = send_event(&configuration, &event).await {
+            warn!("unable to report an error to sentry, {}", e);
+        }
+    }
+}
+
+async fn send_event(configuration: &SentryConfiguration, event: &Value) -> Result<(), String> {
+    let event = event.to_string();
+
+    let envelope = format!(
+        "{}\n{}\n{}\n",
+        json!({ "event_id": event_id(&event) }),
+        json!({ "type": "event", "length": event.len() }),
+        event
+    );
+
+    let response = Client::new()
+        .post(configuration.envelope_url.clone())
+        .timeout(TIMEOUT)
+        .header("Content-Type", "application/x-sentry-envelope")
+        .header(
+            "X-Sentry-Auth",
+            format!(
+                "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+                APP_NAME,
+                env!("CARGO_PKG_VERSION"),
+                configuration.public_key
+            ),
+        )
+        .body(envelope)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = response.status();
+
+    match status.is_success() {
+        true => Ok(()),
+        false => Err(format!(
+            "sentry responded {}, {}",
+            status,
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+fn event_id(event: &str) -> Value {
+    serde_json::from_str::<Value>(event)
+        .ok()
+        .and_then(|event| event.get("event_id").cloned())
+        .unwrap_or_default()
+}
+
+/// The fields of an event as JSON values
+#[derive(Debug, Default)]
+struct EventFields(Map<String, Value>);
+
+impl Visit for EventFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_dsn() {
+        let configuration =
+            |dsn| SentryConfiguration::from_dsn(dsn, "production".to_string(), "1.0".to_string());
+
+        let parsed = configuration("https://abc123@o42.ingest.sentry.io/1337").unwrap();
+        assert_eq!(
+            parsed.envelope_url.as_str(),
+            "https://o42.ingest.sentry.io/api/1337/envelope/"
+        );
+        assert_eq!(parsed.public_key, "abc123");
+
+        assert_eq!(
+            configuration("http://abc123@glitchtip:8000/sentry/7")
+                .unwrap()
+                .envelope_url
+                .as_str(),
+            "http://glitchtip:8000/sentry/api/7/envelope/"
+        );
+
+        assert!(configuration("https://o42.ingest.sentry.io/1337").is_err());
+        assert!(configuration("https://abc123@o42.ingest.sentry.io/").is_err());
+        assert!(configuration("sentry").is_err());
+    }
+}
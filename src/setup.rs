@@ -1,91 +1,1805 @@
-use core::panic;
-use log::debug;
+use chrono::TimeDelta;
+use chrono_tz::Tz;
+use log::{debug, info};
 use sqlx::migrate::Migrator;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPoolOptions};
+use sqlx::Connection;
+use std::fmt::Display;
+use std::future::Future;
 use std::process;
+use std::str::FromStr;
 use std::sync::Arc;
-use tracing::error;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{error, warn};
 
 use crate::{
-    domain::ElectricityPriceProvider, price_repository::PostgresPriceRepository, tibber,
+    backup::S3Backup,
+    backup_repository::{BackupRepository, PostgresBackupRepository},
+    carbon_intensity_repository::{CarbonIntensityRepository, PostgresCarbonIntensityRepository},
+    chat::{IncomingWebhook, Platform},
+    configuration_file::variable,
+    consumption_repository::{ConsumptionRepository, PostgresConsumptionRepository},
+    contract::Contract,
+    contract_repository::{ContractRepository, PostgresContractRepository},
+    cron::CronSchedule,
+    demo::DemoProvider,
+    device_repository::{DeviceRepository, PostgresDeviceRepository},
+    domain::{
+        CarbonIntensityProvider, ElectricityPriceProvider, ElectricityProviderError, PricePoint,
+    },
+    dsmr::P1Meter,
+    electricity_maps::ElectricityMaps,
+    email::{Encryption, Mailer, SmtpOptions},
+    entsoe::{bidding_zone_code, Entsoe},
+    exchange_rate_repository::{ExchangeRateRepository, PostgresExchangeRateRepository},
+    forecast_solar::{ForecastSolar, SolarPanels},
+    formula::{FormulaApplication, PriceFormula},
+    google_calendar::GoogleCalendar,
+    grid_fee::GridFeeSchedule,
+    influxdb::InfluxDb,
+    job_lock::JobLock,
+    memory::{self, MemoryPriceRepository},
+    mqtt::{MqttClient, MqttOptions, Qos},
+    national_grid::NationalGrid,
+    notification_repository::{NotificationRepository, PostgresNotificationRepository},
+    planned_window_repository::{PlannedWindowRepository, PostgresPlannedWindowRepository},
+    price_cache::{CachedPriceRepository, InvalidatingBackupRepository, WindowCache},
+    price_cap::PriceCap,
+    price_level::PriceLevel,
+    price_repository::PostgresPriceRepository,
+    renewable_generation_repository::{
+        PostgresRenewableGenerationRepository, RenewableGenerationRepository,
+    },
+    s3::S3Bucket,
+    scheduler::Jobs,
+    sg_ready::SgReadyThresholds,
+    single_flight::SingleFlight,
+    solar_forecast_repository::{PostgresSolarForecastRepository, SolarForecastRepository},
+    tariff::Tariff,
+    telegram::Telegram,
+    template::{Field, Template},
+    tibber::{self, TibberConsumptionSync},
+    tls::TlsConfiguration,
+    unix_socket::UnixSocket,
+    weather::WeatherLocation,
+    weather_repository::{PostgresWeatherRepository, WeatherRepository},
+    webhook_repository::{PostgresWebhookRepository, WebhookRepository},
     PriceRepository,
 };
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
+/// The longest wait between attempts to connect to the database at startup
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(10);
+
 /// Setup the app state that is given to every route handler
-/// Contains things such as the DB connection pool, ElectricityProvider instance
-/// and a price repository
-pub(crate) async fn setup_app_state() -> AppState {
-    let electricity_provider_dsn = std::env::var("ELECTRICITY_PRICE_PROVIDER_DSN")
-        .expect("ELECTRICITY_PRICE_PROVIDER_DSN is missing, you need to configure it");
+/// Contains things such as the ElectricityProvider instance
+/// and the price, exchange rate and device repositories
+/// In demo mode made up prices are kept in memory, which needs neither a provider nor a database
+pub(crate) async fn setup_app_state(demo: bool) -> AppState {
+    let timezone = resolve_timezone();
+
+    let (tibber_consumption_sync, repositories, jobs) = if demo {
+        info!("running in demo mode, prices are made up and kept in memory");
+
+        (
+            None,
+            memory::repositories(MemoryPriceRepository::new()),
+            Jobs::new(None),
+        )
+    } else {
+        let electricity_provider_dsn = variable("ELECTRICITY_PRICE_PROVIDER_DSN")
+            .expect("ELECTRICITY_PRICE_PROVIDER_DSN is missing, you need to configure it");
+
+        let db_dsn = variable("DATABASE_URL").expect("DATABASE_URL must be set");
+
+        let (repositories, jobs) =
+            setup_repositories(&db_dsn, &resolve_database_configuration()).await;
+
+        // the consumption is that of the household of the first provider
+        let first_dsn = provider_dsns(&electricity_provider_dsn)
+            .next()
+            .unwrap_or_default();
 
-    let db_dsn = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        (
+            resolve_tibber_consumption_sync(first_dsn),
+            repositories,
+            jobs,
+        )
+    };
+
+    let (repositories, window_cache) = with_price_cache(repositories, resolve_price_cache());
 
-    let db_pool = setup_db(&db_dsn).await;
+    let settings = resolve_settings(timezone, demo).unwrap_or_else(|e| {
+        error!("{}", e);
+        process::exit(1);
+    });
 
-    let price_repository = PostgresPriceRepository::new(db_pool.clone());
+    let weather_location = resolve_weather_location();
 
-    let electricity_provider = resolve_electricity_provider(electricity_provider_dsn.as_str());
+    let (solar_panels, solar_forecast) = resolve_solar_forecast(weather_location);
 
     AppState::new(
-        db_pool,
-        Arc::new(electricity_provider),
-        Arc::new(price_repository),
+        timezone,
+        repositories,
+        window_cache,
+        settings,
+        weather_location,
+        resolve_mqtt(),
+        resolve_webhooks(),
+        resolve_influxdb(),
+        resolve_google_calendar(),
+        resolve_backup(),
+        resolve_carbon_intensity_provider(),
+        solar_panels,
+        solar_forecast,
+        resolve_entsoe(),
+        resolve_p1_meter(),
+        tibber_consumption_sync,
+        jobs,
+        variable("ADMIN_TOKEN").ok(),
     )
 }
 
+/// Resolve the configuration that is read again on SIGHUP, of which any part that cannot be
+/// resolved fails all of it. In demo mode the prices are made up rather than fetched from
+/// `ELECTRICITY_PRICE_PROVIDER_DSN`.
+pub(crate) fn resolve_settings(timezone: Tz, demo: bool) -> Result<Settings, String> {
+    let electricity_providers: Vec<Arc<dyn ElectricityPriceProvider>> = if demo {
+        vec![Arc::new(DemoProvider::new(timezone))]
+    } else {
+        let dsns = variable("ELECTRICITY_PRICE_PROVIDER_DSN")
+            .map_err(|_| "ELECTRICITY_PRICE_PROVIDER_DSN is missing, you need to configure it")?;
+
+        resolve_electricity_providers(&dsns)?
+    };
+
+    let price_formula = resolve_price_formula()?;
+
+    let tariff = resolve_tariff(timezone)?;
+
+    if price_formula.is_some() && tariff.is_some() {
+        return Err(
+            "configure either PRICE_FORMULA or the TARIFF_* components, not both".to_string(),
+        );
+    }
+
+    Ok(Settings {
+        electricity_providers,
+        pricing: PricingConfiguration {
+            price_formula,
+            tariff,
+            price_cap: resolve_price_cap()?,
+            fixed_tariff_rate: parse_variable::<f64>("FIXED_TARIFF_RATE")?,
+            price_alert_threshold: parse_variable::<f64>("PRICE_ALERT_THRESHOLD")?,
+            contract: resolve_contract()?,
+        },
+        scheduling: resolve_scheduling(timezone)?,
+        sg_ready: resolve_sg_ready_thresholds()?,
+        notifications: resolve_notifications()?,
+    })
+}
+
+/// Run the migrations of the database of `DATABASE_URL`, which connecting to it does for every
+/// kind of database
+pub(crate) async fn migrate_database() {
+    let db_dsn = variable("DATABASE_URL").expect("DATABASE_URL must be set");
+
+    setup_repositories(&db_dsn, &resolve_database_configuration()).await;
+}
+
+/// Build a provider for every comma separated DSN of `ELECTRICITY_PRICE_PROVIDER_DSN`, to fetch
+/// the prices of several areas, e.g. `tibber://{api_key}?area=NL,tibber://{api_key}?area=SE`.
+/// A provider may be configured once per area, of which the area of the first provider is the
+/// default when several are configured.
+fn resolve_electricity_providers(
+    dsns: &str,
+) -> Result<Vec<Arc<dyn ElectricityPriceProvider>>, String> {
+    let mut providers: Vec<Arc<dyn ElectricityPriceProvider>> = vec![];
+
+    for dsn in provider_dsns(dsns) {
+        let provider = resolve_electricity_provider(dsn)?;
+
+        if providers
+            .iter()
+            .any(|other| (other.name(), other.area()) == (provider.name(), provider.area()))
+        {
+            return Err(format!(
+                "ELECTRICITY_PRICE_PROVIDER_DSN configures {} for area {} more than once",
+                provider.name(),
+                provider.area().unwrap_or("none")
+            ));
+        }
+
+        providers.push(Arc::new(provider));
+    }
+
+    if providers.is_empty() {
+        return Err(
+            "ELECTRICITY_PRICE_PROVIDER_DSN is empty, you need to configure it".to_string(),
+        );
+    }
+
+    Ok(providers)
+}
+
+fn provider_dsns(dsns: &str) -> impl Iterator<Item = &str> {
+    dsns.split(',').map(str::trim).filter(|dsn| !dsn.is_empty())
+}
+
 /// Build an `ElectricityProvider` instance from the provided instance
-/// Requires that a `ELECTRICITY_PRICE_PROVIDER_DSN` is present in the environment
+/// Requires that a `ELECTRICITY_PRICE_PROVIDER_DSN` is present in the environment, e.g.
+/// `tibber://{api_key}?area=NL` where the optional area is the bidding zone of the prices
 /// Currently only a tibber implementation exists
-fn resolve_electricity_provider(dsn: &str) -> impl ElectricityPriceProvider {
-    let dsn = dsn::parse(dsn).unwrap_or_else(|e| {
+fn resolve_electricity_provider(dsn: &str) -> Result<impl ElectricityPriceProvider, String> {
+    let dsn = parse_provider_dsn(dsn)
+        .map_err(|e| format!("unable to parse ELECTRICITY_PRICE_PROVIDER_DSN, {}", e))?;
+
+    debug!("trying to resolve provider \"{}\"", dsn.driver);
+    match dsn.driver.as_str() {
+        "tibber" => Ok(tibber::Tibber::new(
+            dsn.username
+                .ok_or("cannot create a tibber instance from the provided dsn")?,
+        )
+        .with_area(dsn.params.get("area").map(|area| area.to_uppercase()))),
+        _ => Err(
+            "the provided ELECTRICITY_PRICE_PROVIDER_DSN does not match any supported provider"
+                .to_string(),
+        ),
+    }
+}
+
+/// Parse the DSN of a provider, of which the parameters follow the API key, as the parser only
+/// reads parameters after a database and would take them for part of the key otherwise
+fn parse_provider_dsn(dsn: &str) -> Result<dsn::DSN, dsn::ParseError> {
+    let (dsn, parameters) = dsn.split_once('?').unwrap_or((dsn, ""));
+
+    let mut parsed = dsn::parse(dsn)?;
+    parsed.params = url::form_urlencoded::parse(parameters.as_bytes())
+        .into_owned()
+        .collect();
+
+    Ok(parsed)
+}
+
+/// Build the provider of the carbon intensity of the grid, configured through
+/// `CARBON_INTENSITY_PROVIDER_DSN`, either
+/// `electricitymaps://{auth_token}@api.electricitymaps.com?zone={zone}` or
+/// `nationalgrid://api.carbonintensity.org.uk` for Great Britain
+fn resolve_carbon_intensity_provider() -> Option<Arc<dyn CarbonIntensityProvider>> {
+    let dsn = variable("CARBON_INTENSITY_PROVIDER_DSN").ok()?;
+
+    let exit = |message: &str| -> ! {
+        error!("unable to parse CARBON_INTENSITY_PROVIDER_DSN, {}", message);
+        process::exit(1);
+    };
+
+    let dsn = url::Url::parse(&dsn).unwrap_or_else(|e| exit(&e.to_string()));
+
+    let host = match (dsn.host_str(), dsn.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => exit("the host of the API is missing"),
+    };
+
+    debug!(
+        "trying to resolve carbon intensity provider \"{}\"",
+        dsn.scheme()
+    );
+    match dsn.scheme() {
+        "electricitymaps" => {
+            let zone = dsn
+                .query_pairs()
+                .find(|(name, _)| name == "zone")
+                .map(|(_, zone)| zone.to_string())
+                .unwrap_or_else(|| exit("the zone is missing, e.g. ?zone=NL"));
+
+            if dsn.username().is_empty() {
+                exit("the auth token is missing");
+            }
+
+            Some(Arc::new(ElectricityMaps::new(
+                host,
+                dsn.username().to_string(),
+                zone,
+            )))
+        }
+        "nationalgrid" => Some(Arc::new(NationalGrid::new(host))),
+        _ => exit("expected an electricitymaps or nationalgrid provider"),
+    }
+}
+
+/// Build the forecast of the production of solar panels, configured through
+/// `SOLAR_FORECAST_DSN`, e.g.
+/// `forecastsolar://api.forecast.solar?declination=35&azimuth=0&peak_power_kw=4.2` with an
+/// optional `{api_key}@` before the host, or `openmeteo://?peak_power_kw=4.2` to derive it from
+/// the forecast irradiance of the weather. The panels are at the weather location.
+fn resolve_solar_forecast(
+    location: Option<WeatherLocation>,
+) -> (Option<SolarPanels>, Option<ForecastSolar>) {
+    let Ok(dsn) = variable("SOLAR_FORECAST_DSN") else {
+        return (None, None);
+    };
+
+    let exit = |message: &str| -> ! {
+        error!("unable to parse SOLAR_FORECAST_DSN, {}", message);
+        process::exit(1);
+    };
+
+    let dsn = url::Url::parse(&dsn).unwrap_or_else(|e| exit(&e.to_string()));
+
+    let parameter = |name: &str, default: Option<f64>| {
+        dsn.query_pairs()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, value)| {
+                value
+                    .parse::<f64>()
+                    .unwrap_or_else(|e| exit(&format!("{} is not a number, {}", name, e)))
+            })
+            .or(default)
+            .unwrap_or_else(|| exit(&format!("{} is missing", name)))
+    };
+
+    let panels = SolarPanels {
+        declination: parameter("declination", Some(35.0)),
+        azimuth: parameter("azimuth", Some(0.0)),
+        peak_power_kw: parameter("peak_power_kw", None),
+    };
+
+    let location = location.unwrap_or_else(|| {
+        exit("the panels are at the weather location, configure WEATHER_LATITUDE and WEATHER_LONGITUDE")
+    });
+
+    match dsn.scheme() {
+        "forecastsolar" => {
+            let host = match (dsn.host_str(), dsn.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (Some(host), None) => host.to_string(),
+                (None, _) => exit("the host of the API is missing"),
+            };
+
+            let api_key = Some(dsn.username())
+                .filter(|api_key| !api_key.is_empty())
+                .map(str::to_string);
+
+            (
+                Some(panels),
+                Some(ForecastSolar::new(host, api_key, location, panels)),
+            )
+        }
+        "openmeteo" => (Some(panels), None),
+        _ => exit("expected a forecastsolar or openmeteo provider"),
+    }
+}
+
+/// Build the forecast of the renewable share of a bidding zone, configured through
+/// `RENEWABLE_SHARE_DSN`, e.g. `entsoe://{security_token}@web-api.tp.entsoe.eu?zone=NL` where
+/// the zone is the name or the EIC code of the bidding zone
+fn resolve_entsoe() -> Option<Entsoe> {
+    let dsn = variable("RENEWABLE_SHARE_DSN").ok()?;
+
+    let exit = |message: &str| -> ! {
+        error!("unable to parse RENEWABLE_SHARE_DSN, {}", message);
+        process::exit(1);
+    };
+
+    let dsn = url::Url::parse(&dsn).unwrap_or_else(|e| exit(&e.to_string()));
+
+    if dsn.scheme() != "entsoe" {
+        exit("expected an entsoe provider");
+    }
+
+    let host = match (dsn.host_str(), dsn.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => exit("the host of the API is missing"),
+    };
+
+    if dsn.username().is_empty() {
+        exit("the security token is missing");
+    }
+
+    let zone = dsn
+        .query_pairs()
+        .find(|(name, _)| name == "zone")
+        .map(|(_, zone)| zone.to_string())
+        .unwrap_or_else(|| exit("the zone is missing, e.g. ?zone=NL"));
+
+    let code = bidding_zone_code(&zone)
+        .unwrap_or_else(|| exit(&format!("{} is not a known bidding zone or EIC code", zone)))
+        .to_string();
+
+    Some(Entsoe::new(
+        host,
+        dsn.username().to_string(),
+        zone.to_uppercase(),
+        code,
+    ))
+}
+
+/// Build the sync of the consumption measured by Tibber, enabled through
+/// `TIBBER_CONSUMPTION_SYNC=true` for households of which Tibber is the provider, which reuses
+/// the token of `ELECTRICITY_PRICE_PROVIDER_DSN`. `TIBBER_CONSUMPTION_SYNC_DAYS` is how many
+/// past days are synced every hour, 7 by default.
+fn resolve_tibber_consumption_sync(dsn: &str) -> Option<TibberConsumptionSync> {
+    let enabled = variable("TIBBER_CONSUMPTION_SYNC")
+        .ok()?
+        .parse::<bool>()
+        .unwrap_or_else(|e| {
+            error!("unable to parse TIBBER_CONSUMPTION_SYNC, {}", e);
+            process::exit(1);
+        });
+
+    if !enabled {
+        return None;
+    }
+
+    let dsn = parse_provider_dsn(dsn).unwrap_or_else(|e| {
         error!("unable to parse ELECTRICITY_PRICE_PROVIDER_DSN, {}", e);
         process::exit(1);
     });
 
-    debug!("trying to resolve provider \"{}\"", dsn.driver);
-    return match dsn.driver.as_str() {
-        "tibber" => tibber::Tibber::new(
-            dsn.username
-                .expect("cannot create a tibber instance from the provided dsn"),
+    let (true, Some(token)) = (dsn.driver == "tibber", dsn.username) else {
+        error!("TIBBER_CONSUMPTION_SYNC requires tibber to be the electricity price provider");
+        process::exit(1);
+    };
+
+    let days = variable("TIBBER_CONSUMPTION_SYNC_DAYS")
+        .map(|days| {
+            days.parse::<usize>().unwrap_or_else(|e| {
+                error!("unable to parse TIBBER_CONSUMPTION_SYNC_DAYS, {}", e);
+                process::exit(1);
+            })
+        })
+        .unwrap_or(7);
+
+    Some(TibberConsumptionSync {
+        tibber: tibber::Tibber::new(token),
+        days,
+    })
+}
+
+/// The P1 reader that serves the telegrams of the smart meter, `P1_DSN=tcp://host:port`
+fn resolve_p1_meter() -> Option<P1Meter> {
+    let dsn = variable("P1_DSN").ok()?;
+
+    let exit = |message: &str| -> ! {
+        error!("unable to parse P1_DSN, {}", message);
+        process::exit(1);
+    };
+
+    let dsn = url::Url::parse(&dsn).unwrap_or_else(|e| exit(&e.to_string()));
+
+    match (dsn.scheme(), dsn.host_str(), dsn.port()) {
+        ("tcp", Some(host), Some(port)) => Some(P1Meter {
+            host: host.to_string(),
+            port,
+        }),
+        _ => exit("expected tcp://host:port"),
+    }
+}
+
+/// Build the formula that turns market prices into consumer prices
+/// Configured through `PRICE_FORMULA`, e.g. `(price + 0.02) * 1.21`, and
+/// `PRICE_FORMULA_APPLIED_AT` which is either `response` (default) or `ingest`
+fn resolve_price_formula() -> Result<Option<PriceFormula>, String> {
+    let Ok(formula) = variable("PRICE_FORMULA") else {
+        return Ok(None);
+    };
+
+    let application = variable("PRICE_FORMULA_APPLIED_AT")
+        .unwrap_or("response".to_string())
+        .parse::<FormulaApplication>()
+        .map_err(|e| format!("unable to parse PRICE_FORMULA_APPLIED_AT, {}", e))?;
+
+    let formula = PriceFormula::new(&formula, application)
+        .map_err(|e| format!("unable to parse PRICE_FORMULA, {}", e))?;
+
+    info!("applying price formula {} at {:?}", formula, application);
+
+    Ok(Some(formula))
+}
+
+/// Build the tariff that splits consumer prices into components
+/// Configured through `TARIFF_SUPPLIER_FEE`, `TARIFF_ENERGY_TAX`, `TARIFF_GRID_FEE`
+/// (all per kWh) and `TARIFF_VAT_PERCENTAGE`. Components that are not set are zero.
+/// Time-of-use grid fees can be configured with `GRID_FEE_SCHEDULE`, whose hours are
+/// interpreted in `TIMEZONE`
+fn resolve_tariff(timezone: Tz) -> Result<Option<Tariff>, String> {
+    let supplier_fee = parse_variable::<f64>("TARIFF_SUPPLIER_FEE")?;
+    let energy_tax = parse_variable::<f64>("TARIFF_ENERGY_TAX")?;
+    let grid_fee = parse_variable::<f64>("TARIFF_GRID_FEE")?;
+    let vat_percentage = parse_variable::<f64>("TARIFF_VAT_PERCENTAGE")?;
+
+    let grid_fee_schedule = variable("GRID_FEE_SCHEDULE")
+        .ok()
+        .map(|schedule| {
+            GridFeeSchedule::parse(&schedule, timezone)
+                .map_err(|e| format!("unable to parse GRID_FEE_SCHEDULE, {}", e))
+        })
+        .transpose()?;
+
+    if [supplier_fee, energy_tax, grid_fee, vat_percentage]
+        .iter()
+        .all(Option::is_none)
+        && grid_fee_schedule.is_none()
+    {
+        return Ok(None);
+    }
+
+    let tariff = Tariff {
+        supplier_fee: supplier_fee.unwrap_or_default(),
+        energy_tax: energy_tax.unwrap_or_default(),
+        grid_fee: grid_fee.unwrap_or_default(),
+        grid_fee_schedule,
+        vat_percentage: vat_percentage.unwrap_or_default(),
+    };
+
+    info!("applying tariff {:?}", tariff);
+
+    Ok(Some(tariff))
+}
+
+/// The contract that applies until one is stored through the API, configured through
+/// `CONTRACT_STANDING_CHARGE_PER_DAY`, `CONTRACT_FIXED_FEE_PER_MONTH`,
+/// `CONTRACT_TAX_CREDIT_PER_MONTH` and `CONTRACT_FEED_IN_COMPENSATION`
+fn resolve_contract() -> Result<Contract, String> {
+    let charge = parse_variable::<f64>;
+
+    Ok(Contract {
+        standing_charge_per_day: charge("CONTRACT_STANDING_CHARGE_PER_DAY")?.unwrap_or_default(),
+        fixed_fee_per_month: charge("CONTRACT_FIXED_FEE_PER_MONTH")?.unwrap_or_default(),
+        tax_credit_per_month: charge("CONTRACT_TAX_CREDIT_PER_MONTH")?.unwrap_or_default(),
+        feed_in_compensation: charge("CONTRACT_FEED_IN_COMPENSATION")?,
+    })
+}
+
+/// Build the government price cap overlay
+/// Configured through `PRICE_CAP_RATE` and optionally `PRICE_CAP_THRESHOLD_KWH`
+fn resolve_price_cap() -> Result<Option<PriceCap>, String> {
+    let Some(rate) = parse_variable::<f64>("PRICE_CAP_RATE")? else {
+        return Ok(None);
+    };
+
+    Ok(Some(PriceCap {
+        rate,
+        threshold_kwh: parse_variable::<f64>("PRICE_CAP_THRESHOLD_KWH")?,
+    }))
+}
+
+/// The value of a variable, if it is set, of which a value that cannot be parsed is an error
+fn parse_variable<T>(name: &str) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    variable(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse::<T>()
+                .map_err(|e| format!("unable to parse {}, {}", name, e))
+        })
+        .transpose()
+}
+
+/// Build the schedules of the background price fetching
+/// Configured through `PRICE_FETCH_SCHEDULE`, defaulting to five past every hour, and
+/// `PRICE_PUBLICATION_SCHEDULE` for tomorrow's prices, defaulting to 13:15. Both are crontab
+/// expressions interpreted in `TIMEZONE`. `PRICE_CATCH_UP_DAYS` sets how many past days are
+/// checked for missing prices at startup, defaulting to 7.
+fn resolve_scheduling(timezone: Tz) -> Result<SchedulingConfiguration, String> {
+    let schedule = |name: &str, default: &str| {
+        let schedule = variable(name).unwrap_or(default.to_string());
+
+        CronSchedule::parse(&schedule, timezone)
+            .map_err(|e| format!("unable to parse {}, {}", name, e))
+    };
+
+    let catch_up_days = parse_variable::<i64>("PRICE_CATCH_UP_DAYS")?.unwrap_or(7);
+
+    let household_power_cap_kw = variable("HOUSEHOLD_POWER_CAP_KW")
+        .ok()
+        .map(|cap| {
+            cap.parse::<f64>().ok().filter(|cap| *cap > 0.0).ok_or(
+                "unable to parse HOUSEHOLD_POWER_CAP_KW, expected a positive number".to_string(),
+            )
+        })
+        .transpose()?;
+
+    Ok(SchedulingConfiguration {
+        catch_up_days,
+        price_fetch_schedule: schedule("PRICE_FETCH_SCHEDULE", "5 * * * *")?,
+        price_publication_schedule: schedule("PRICE_PUBLICATION_SCHEDULE", "15 13 * * *")?,
+        household_power_cap_kw,
+        retention: resolve_retention(catch_up_days, schedule("RETENTION_SCHEDULE", "30 3 * * *")?)?,
+    })
+}
+
+/// How long history is kept, in days. Prices are kept for `PRICE_RETENTION_DAYS` and planned
+/// windows for `PLANNED_WINDOW_RETENTION_DAYS`, both forever when unset, and the log of price
+/// fetches for `PRICE_FETCH_RETENTION_DAYS`, defaulting to 30. Prices must be kept longer than
+/// the catch-up period, or the days that are pruned would be fetched again at startup.
+fn resolve_retention(catch_up_days: i64, schedule: CronSchedule) -> Result<Retention, String> {
+    let days = |name: &str| {
+        variable(name)
+            .ok()
+            .map(|days| {
+                days.parse::<i64>()
+                    .ok()
+                    .filter(|days| *days > 0)
+                    .map(TimeDelta::days)
+                    .ok_or(format!(
+                        "unable to parse {}, expected a positive number of days",
+                        name
+                    ))
+            })
+            .transpose()
+    };
+
+    let prices = days("PRICE_RETENTION_DAYS")?;
+
+    if prices.is_some_and(|prices| prices <= TimeDelta::days(catch_up_days)) {
+        return Err("PRICE_RETENTION_DAYS must be more than PRICE_CATCH_UP_DAYS".to_string());
+    }
+
+    Ok(Retention {
+        prices,
+        price_fetches: Some(days("PRICE_FETCH_RETENTION_DAYS")?.unwrap_or(TimeDelta::days(30))),
+        planned_windows: days("PLANNED_WINDOW_RETENTION_DAYS")?,
+        schedule,
+    })
+}
+
+/// How long recent prices are cached in memory, `PRICE_CACHE_SECONDS` (60), of which 0 disables
+/// the cache
+fn resolve_price_cache() -> Option<Duration> {
+    let seconds = variable("PRICE_CACHE_SECONDS")
+        .ok()
+        .map(|seconds| {
+            seconds.parse::<u64>().unwrap_or_else(|e| {
+                error!("unable to parse PRICE_CACHE_SECONDS, {}", e);
+                process::exit(1);
+            })
+        })
+        .unwrap_or(60);
+
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Put the cache in front of the price repository, which forgets its prices and the windows
+/// computed of them whenever prices are ingested, deleted or restored
+fn with_price_cache(
+    repositories: Repositories,
+    ttl: Option<Duration>,
+) -> (Repositories, Arc<WindowCache>) {
+    let Some(ttl) = ttl else {
+        return (repositories, Arc::new(WindowCache::disabled()));
+    };
+
+    let price = Arc::new(CachedPriceRepository::new(repositories.price, ttl));
+    tokio::spawn(price.clone().invalidate_on_changed_prices());
+
+    (
+        Repositories {
+            backup: Arc::new(InvalidatingBackupRepository::new(
+                repositories.backup,
+                price.clone(),
+            )),
+            price: price.clone(),
+            ..repositories
+        },
+        price.windows(),
+    )
+}
+
+/// The pool of connections to the database, of `DATABASE_MAX_CONNECTIONS` connections, which
+/// wait up to `DATABASE_ACQUIRE_TIMEOUT_SECONDS` (30) for a free connection. Statements that run
+/// longer than `DATABASE_STATEMENT_TIMEOUT_SECONDS` are cancelled by Postgres. Connecting is
+/// retried for `DATABASE_CONNECT_TIMEOUT_SECONDS` (60) at startup.
+fn resolve_database_configuration() -> DatabaseConfiguration {
+    let positive = |name: &str| {
+        variable(name).ok().map(|value| {
+            value
+                .parse::<u64>()
+                .ok()
+                .filter(|value| *value > 0)
+                .unwrap_or_else(|| {
+                    error!("unable to parse {}, expected a positive number", name);
+                    process::exit(1);
+                })
+        })
+    };
+
+    DatabaseConfiguration {
+        max_connections: positive("DATABASE_MAX_CONNECTIONS")
+            .map(|connections| connections.min(u32::MAX as u64) as u32),
+        acquire_timeout: Duration::from_secs(
+            positive("DATABASE_ACQUIRE_TIMEOUT_SECONDS").unwrap_or(30),
         ),
-        _ => panic!(
-            "the provided ELECTRICITY_PRICE_PROVIDER_DSN does not match any supported provider"
+        statement_timeout: positive("DATABASE_STATEMENT_TIMEOUT_SECONDS").map(Duration::from_secs),
+        connect_timeout: Duration::from_secs(
+            positive("DATABASE_CONNECT_TIMEOUT_SECONDS").unwrap_or(60),
         ),
+    }
+}
+
+/// The URLs that are called on every webhook event besides the registered webhooks,
+/// configured as a comma separated list through `WEBHOOK_URLS`. Their deliveries are signed
+/// with `WEBHOOK_SECRET` when it is set.
+fn resolve_webhooks() -> WebhookConfiguration {
+    let urls = variable("WEBHOOK_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| {
+            match url::Url::parse(url) {
+                Ok(parsed) if ["http", "https"].contains(&parsed.scheme()) => {}
+                _ => {
+                    error!(
+                        "unable to parse WEBHOOK_URLS, {} is not an http(s) URL",
+                        url
+                    );
+                    process::exit(1);
+                }
+            }
+
+            url.to_string()
+        })
+        .collect();
+
+    WebhookConfiguration {
+        urls,
+        secret: variable("WEBHOOK_SECRET").ok(),
+    }
+}
+
+/// The channels notifications can be sent through besides the log. Telegram is configured
+/// through `TELEGRAM_BOT_TOKEN` and `TELEGRAM_CHAT_ID`, Slack and Discord through the URL of
+/// an incoming webhook in `SLACK_WEBHOOK_URL` and `DISCORD_WEBHOOK_URL`.
+fn resolve_notifications() -> Result<NotificationConfiguration, String> {
+    let telegram = match (variable("TELEGRAM_BOT_TOKEN"), variable("TELEGRAM_CHAT_ID")) {
+        (Ok(bot_token), Ok(chat_id)) => Some(Telegram::new(bot_token, chat_id)),
+        (Err(_), Err(_)) => None,
+        _ => {
+            return Err(
+                "configure both TELEGRAM_BOT_TOKEN and TELEGRAM_CHAT_ID, or neither".to_string(),
+            )
+        }
+    };
+
+    let incoming_webhook = |name: &str, platform: Platform| {
+        variable(name)
+            .ok()
+            .map(|url| match url::Url::parse(&url) {
+                Ok(parsed) if ["http", "https"].contains(&parsed.scheme()) => {
+                    Ok(IncomingWebhook::new(platform, url))
+                }
+                _ => Err(format!(
+                    "unable to parse {}, it is not an http(s) URL",
+                    name
+                )),
+            })
+            .transpose()
     };
+
+    Ok(NotificationConfiguration {
+        telegram,
+        slack: incoming_webhook("SLACK_WEBHOOK_URL", Platform::Slack)?,
+        discord: incoming_webhook("DISCORD_WEBHOOK_URL", Platform::Discord)?,
+        email: resolve_email()?,
+    })
 }
 
-async fn setup_db(db_dsn: &str) -> sqlx::PgPool {
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(db_dsn)
+/// The SMTP server to send emails through, configured through `SMTP_URL` as
+/// `smtp://host:port`, which is upgraded to TLS when the server supports it, or
+/// `smtps://host:port` for TLS from the start. `SMTP_USERNAME` and `SMTP_PASSWORD` are the
+/// credentials, `SMTP_FROM` the sender and `SMTP_TO` the comma separated recipients.
+/// `SMTP_SUBJECT_TEMPLATE` and `SMTP_BODY_TEMPLATE` are the templates of the subject and body,
+/// in which `{{ title }}`, `{{ event }}` and `{{ message }}` are replaced with those of the
+/// notification.
+fn resolve_email() -> Result<Option<Mailer>, String> {
+    let Ok(url) = variable("SMTP_URL") else {
+        return Ok(None);
+    };
+
+    let url = url::Url::parse(&url).map_err(|e| format!("unable to parse SMTP_URL, {}", e))?;
+
+    let (host, port, encryption) = match (url.host_str(), url.scheme()) {
+        (Some(host), "smtp") => (host, url.port().unwrap_or(587), Encryption::StartTls),
+        (Some(host), "smtps") => (host, url.port().unwrap_or(465), Encryption::Tls),
+        _ => {
+            return Err(
+                "unable to parse SMTP_URL, expected smtp://host:port or smtps://host:port"
+                    .to_string(),
+            )
+        }
+    };
+
+    let credentials = match (variable("SMTP_USERNAME"), variable("SMTP_PASSWORD")) {
+        (Ok(username), Ok(password)) => Some((username, password)),
+        (Err(_), Err(_)) => None,
+        _ => return Err("configure both SMTP_USERNAME and SMTP_PASSWORD, or neither".to_string()),
+    };
+
+    let is_address =
+        |address: &str| address.contains('@') && !address.contains(['<', '>', ',', '\r', '\n']);
+
+    let from = variable("SMTP_FROM").unwrap_or_default();
+
+    if !is_address(&from) {
+        return Err("unable to parse SMTP_FROM, expected an email address".to_string());
+    }
+
+    let to = variable("SMTP_TO")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|recipient| !recipient.is_empty())
+        .map(|recipient| {
+            if !is_address(recipient) {
+                return Err(format!(
+                    "unable to parse SMTP_TO, {} is not an email address",
+                    recipient
+                ));
+            }
+
+            Ok(recipient.to_string())
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+
+    if to.is_empty() {
+        return Err("SMTP_TO is missing, configure at least one recipient".to_string());
+    }
+
+    let tls = native_tls::TlsConnector::new()
+        .map(Into::into)
+        .map_err(|e| format!("unable to set up TLS for SMTP, {}", e))?;
+
+    let template = |name: &str, default: &str| {
+        // a line break is written as \n, as environment variables span a single line
+        let template = variable(name)
+            .unwrap_or(default.to_string())
+            .replace("\\n", "\n");
+
+        Template::parse(&template)
+            .and_then(|template| {
+                template
+                    .check_fields(&[
+                        ("title", Field::Text),
+                        ("event", Field::Text),
+                        ("message", Field::Text),
+                    ])
+                    .map(|_| template)
+            })
+            .map_err(|e| format!("unable to parse {}, {}", name, e))
+    };
+
+    Ok(Some(Mailer {
+        smtp: SmtpOptions {
+            host: host.to_string(),
+            port,
+            encryption,
+            tls,
+            credentials,
+            from,
+            to,
+        },
+        subject: template("SMTP_SUBJECT_TEMPLATE", "{{ title }}")?,
+        body: template(
+            "SMTP_BODY_TEMPLATE",
+            "{{ message }}\n\n-- \nSent by electrack for {{ event }} notifications",
+        )?,
+    }))
+}
+
+/// The price levels from which SG-Ready states apply, configured through
+/// `SG_READY_BLOCKED_FROM`, `SG_READY_RECOMMENDED_FROM` and `SG_READY_FORCED_FROM`
+fn resolve_sg_ready_thresholds() -> Result<SgReadyThresholds, String> {
+    let level = |name: &str, default: PriceLevel| {
+        parse_variable::<PriceLevel>(name).map(|level| level.unwrap_or(default))
+    };
+
+    let defaults = SgReadyThresholds::default();
+
+    Ok(SgReadyThresholds {
+        blocked_from: level("SG_READY_BLOCKED_FROM", defaults.blocked_from)?,
+        recommended_from: level("SG_READY_RECOMMENDED_FROM", defaults.recommended_from)?,
+        forced_from: level("SG_READY_FORCED_FROM", defaults.forced_from)?,
+    })
+}
+
+/// The InfluxDB v2 bucket to write ingested prices to, configured through `INFLUXDB_URL`, e.g.
+/// `http://influxdb:8086`, with `INFLUXDB_TOKEN`, `INFLUXDB_ORG` and `INFLUXDB_BUCKET`. Points
+/// are written to the `INFLUXDB_MEASUREMENT` measurement, defaulting to `electricity_price`.
+fn resolve_influxdb() -> Option<InfluxDb> {
+    let url = variable("INFLUXDB_URL").ok()?;
+
+    let url = match url::Url::parse(&url) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => url,
+        _ => {
+            error!("unable to parse INFLUXDB_URL, it is not an http(s) URL");
+            process::exit(1);
+        }
+    };
+
+    let required = |name: &str| {
+        variable(name).unwrap_or_else(|_| {
+            error!("{} is required with INFLUXDB_URL", name);
+            process::exit(1);
+        })
+    };
+
+    Some(InfluxDb::new(
+        &url,
+        required("INFLUXDB_TOKEN"),
+        &required("INFLUXDB_ORG"),
+        &required("INFLUXDB_BUCKET"),
+        variable("INFLUXDB_MEASUREMENT").unwrap_or("electricity_price".to_string()),
+    ))
+}
+
+/// The Google Calendar to push planned windows to, configured through
+/// `GOOGLE_CALENDAR_ID`, e.g. `primary`, with the `GOOGLE_CLIENT_ID` and
+/// `GOOGLE_CLIENT_SECRET` of an OAuth client and a `GOOGLE_REFRESH_TOKEN` that grants access to
+/// the calendar
+fn resolve_google_calendar() -> Option<GoogleCalendar> {
+    let calendar_id = variable("GOOGLE_CALENDAR_ID").ok()?;
+
+    let required = |name: &str| {
+        variable(name).unwrap_or_else(|_| {
+            error!("{} is required with GOOGLE_CALENDAR_ID", name);
+            process::exit(1);
+        })
+    };
+
+    Some(GoogleCalendar::new(
+        calendar_id,
+        required("GOOGLE_CLIENT_ID"),
+        required("GOOGLE_CLIENT_SECRET"),
+        required("GOOGLE_REFRESH_TOKEN"),
+    ))
+}
+
+/// Listen on the Unix socket of `UNIX_SOCKET_PATH` rather than on `PORT`, with the octal
+/// permissions of `UNIX_SOCKET_MODE`, e.g. `660`
+pub(crate) fn resolve_unix_socket() -> Option<UnixSocket> {
+    let path = variable("UNIX_SOCKET_PATH").ok()?;
+
+    if resolve_tls().is_some() {
+        error!("a Unix socket is served without TLS, configure either UNIX_SOCKET_PATH or TLS_*");
+        process::exit(1);
+    }
+
+    let mode = variable("UNIX_SOCKET_MODE").ok().map(|mode| {
+        u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+            .unwrap_or_else(|| {
+                error!("unable to parse UNIX_SOCKET_MODE, expected octal permissions such as 660");
+                process::exit(1);
+            })
+    });
+
+    Some(UnixSocket {
+        path: path.into(),
+        mode,
+    })
+}
+
+/// Serve HTTPS rather than HTTP with the PEM files of `TLS_CERTIFICATE_PATH`, the certificate
+/// with its chain, and `TLS_KEY_PATH`, the private key, which are both needed
+pub(crate) fn resolve_tls() -> Option<TlsConfiguration> {
+    let certificate_path = variable("TLS_CERTIFICATE_PATH").ok();
+    let key_path = variable("TLS_KEY_PATH").ok();
+
+    match (certificate_path, key_path) {
+        (Some(certificate_path), Some(key_path)) => Some(TlsConfiguration {
+            certificate_path: certificate_path.into(),
+            key_path: key_path.into(),
+        }),
+        (None, None) => None,
+        _ => {
+            error!("configure both TLS_CERTIFICATE_PATH and TLS_KEY_PATH to serve HTTPS");
+            process::exit(1);
+        }
+    }
+}
+
+/// The S3-compatible bucket to back up to, configured through `S3_BACKUP_BUCKET` with the
+/// `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY` of a key that may read, write, list and delete
+/// its objects. `S3_ENDPOINT` defaults to AWS in `S3_REGION`, which defaults to `us-east-1`.
+/// Backups are stored under `S3_BACKUP_PREFIX`, empty by default, according to
+/// `S3_BACKUP_SCHEDULE`, defaulting to 03:00 every day, and the latest `S3_BACKUP_KEEP`,
+/// defaulting to 30, are kept.
+fn resolve_backup() -> Option<S3Backup> {
+    let bucket = variable("S3_BACKUP_BUCKET").ok()?;
+
+    let required = |name: &str| {
+        variable(name).unwrap_or_else(|_| {
+            error!("{} is required with S3_BACKUP_BUCKET", name);
+            process::exit(1);
+        })
+    };
+
+    let region = variable("S3_REGION").unwrap_or("us-east-1".to_string());
+
+    let endpoint =
+        variable("S3_ENDPOINT").unwrap_or(format!("https://s3.{}.amazonaws.com", region));
+
+    let endpoint = match url::Url::parse(&endpoint) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) && url.host_str().is_some() => url,
+        _ => {
+            error!("unable to parse S3_ENDPOINT, it is not an http(s) URL");
+            process::exit(1);
+        }
+    };
+
+    let schedule = variable("S3_BACKUP_SCHEDULE").unwrap_or("0 3 * * *".to_string());
+    let schedule = CronSchedule::parse(&schedule, resolve_timezone()).unwrap_or_else(|e| {
+        error!("unable to parse S3_BACKUP_SCHEDULE, {}", e);
+        process::exit(1);
+    });
+
+    let keep = variable("S3_BACKUP_KEEP")
+        .map(|keep| match keep.parse::<usize>() {
+            Ok(keep) if keep > 0 => keep,
+            _ => {
+                error!("unable to parse S3_BACKUP_KEEP, expected a positive number");
+                process::exit(1);
+            }
+        })
+        .unwrap_or(30);
+
+    Some(S3Backup {
+        bucket: S3Bucket::new(
+            endpoint,
+            bucket,
+            region,
+            required("S3_ACCESS_KEY_ID"),
+            required("S3_SECRET_ACCESS_KEY"),
+        ),
+        prefix: variable("S3_BACKUP_PREFIX").unwrap_or_default(),
+        keep,
+        schedule,
+    })
+}
+
+/// The MQTT broker to publish prices to, configured through `MQTT_URL`, e.g.
+/// `mqtt://broker:1883`. `MQTT_CLIENT_ID` and `MQTT_TOPIC_PREFIX` both default to `electrack`.
+/// `MQTT_WINDOW_DURATIONS` lists the durations in hours of which the cheapest upcoming window is
+/// published, defaulting to `1,2,3`, and `MQTT_PUBLISH_SCHEDULE` when to publish, defaulting to
+/// the start of every hour. Home Assistant discovery messages are published under
+/// `MQTT_DISCOVERY_PREFIX`, defaulting to `homeassistant`, an empty prefix disables them.
+/// The broker is connected to over TLS with the `mqtts` scheme, and as `MQTT_USERNAME` with
+/// `MQTT_PASSWORD` when they are set. `MQTT_QOS` (default 0) and `MQTT_RETAIN` (default true)
+/// apply to published states. `MQTT_AVAILABILITY_TOPIC`, defaulting to `availability` under the
+/// topic prefix, reads `online` while connected and `offline` otherwise.
+fn resolve_mqtt() -> Option<MqttConfiguration> {
+    let url = variable("MQTT_URL").ok()?;
+
+    let url = url::Url::parse(&url).unwrap_or_else(|e| {
+        error!("unable to parse MQTT_URL, {}", e);
+        process::exit(1);
+    });
+
+    let (host, port, tls) = match (url.host_str(), url.scheme()) {
+        (Some(host), "mqtt") => (host, url.port().unwrap_or(1883), None),
+        (Some(host), "mqtts") => (host, url.port().unwrap_or(8883), Some(resolve_mqtt_tls())),
+        _ => {
+            error!("unable to parse MQTT_URL, expected mqtt://host:port or mqtts://host:port");
+            process::exit(1);
+        }
+    };
+
+    let window_durations = variable("MQTT_WINDOW_DURATIONS")
+        .unwrap_or("1,2,3".to_string())
+        .split(',')
+        .map(|duration| {
+            duration
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|duration| *duration > 0)
+                .unwrap_or_else(|| {
+                    error!(
+                        "unable to parse MQTT_WINDOW_DURATIONS, expected hours separated by commas"
+                    );
+                    process::exit(1);
+                })
+        })
+        .collect();
+
+    let publish_schedule = variable("MQTT_PUBLISH_SCHEDULE").unwrap_or("0 * * * *".to_string());
+    let publish_schedule = CronSchedule::parse(&publish_schedule, resolve_timezone())
+        .unwrap_or_else(|e| {
+            error!("unable to parse MQTT_PUBLISH_SCHEDULE, {}", e);
+            process::exit(1);
+        });
+
+    let qos = variable("MQTT_QOS")
+        .map(|qos| {
+            qos.parse::<Qos>().unwrap_or_else(|e| {
+                error!("unable to parse MQTT_QOS, {}", e);
+                process::exit(1);
+            })
+        })
+        .unwrap_or(Qos::AtMostOnce);
+
+    let retain = variable("MQTT_RETAIN")
+        .map(|retain| {
+            retain.parse::<bool>().unwrap_or_else(|e| {
+                error!("unable to parse MQTT_RETAIN, {}", e);
+                process::exit(1);
+            })
+        })
+        .unwrap_or(true);
+
+    let client_id = variable("MQTT_CLIENT_ID").unwrap_or(crate::APP_NAME.to_string());
+
+    let topic_prefix = variable("MQTT_TOPIC_PREFIX")
+        .unwrap_or(crate::APP_NAME.to_string())
+        .trim_end_matches('/')
+        .to_string();
+
+    let availability_topic = variable("MQTT_AVAILABILITY_TOPIC")
+        .map(Some)
+        .unwrap_or(Some(format!("{}/availability", topic_prefix)))
+        .filter(|topic| !topic.is_empty());
+
+    let client = MqttClient::start(MqttOptions {
+        host: host.to_string(),
+        port,
+        client_id: client_id.clone(),
+        keep_alive: Duration::from_secs(60),
+        username: variable("MQTT_USERNAME").ok(),
+        password: variable("MQTT_PASSWORD").ok(),
+        tls,
+        availability_topic: availability_topic.clone(),
+        qos,
+    });
+
+    let discovery_prefix = variable("MQTT_DISCOVERY_PREFIX")
+        .unwrap_or("homeassistant".to_string())
+        .trim_end_matches('/')
+        .to_string();
+
+    Some(MqttConfiguration {
+        client,
+        client_id,
+        discovery_prefix: Some(discovery_prefix).filter(|prefix| !prefix.is_empty()),
+        topic_prefix,
+        availability_topic,
+        retain,
+        window_durations,
+        publish_schedule,
+    })
+}
+
+/// The TLS settings of the MQTT connection. `MQTT_CA_CERTIFICATE` is the path of a PEM
+/// certificate to trust besides the system certificates, `MQTT_CLIENT_CERTIFICATE` and
+/// `MQTT_CLIENT_KEY` are the paths of the PEM certificate and PKCS #8 key to authenticate with
+fn resolve_mqtt_tls() -> tokio_native_tls::TlsConnector {
+    let read = |name: &str| {
+        variable(name).ok().map(|path| {
+            std::fs::read(&path).unwrap_or_else(|e| {
+                error!("unable to read {} from {}, {}", name, path, e);
+                process::exit(1);
+            })
+        })
+    };
+
+    let mut builder = native_tls::TlsConnector::builder();
+
+    if let Some(certificate) = read("MQTT_CA_CERTIFICATE") {
+        let certificate = native_tls::Certificate::from_pem(&certificate).unwrap_or_else(|e| {
+            error!("unable to parse MQTT_CA_CERTIFICATE, {}", e);
+            process::exit(1);
+        });
+        builder.add_root_certificate(certificate);
+    }
+
+    match (read("MQTT_CLIENT_CERTIFICATE"), read("MQTT_CLIENT_KEY")) {
+        (Some(certificate), Some(key)) => {
+            let identity =
+                native_tls::Identity::from_pkcs8(&certificate, &key).unwrap_or_else(|e| {
+                    error!(
+                        "unable to parse MQTT_CLIENT_CERTIFICATE or MQTT_CLIENT_KEY, {}",
+                        e
+                    );
+                    process::exit(1);
+                });
+            builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            error!("configure both MQTT_CLIENT_CERTIFICATE and MQTT_CLIENT_KEY");
+            process::exit(1);
+        }
+    }
+
+    builder.build().map(Into::into).unwrap_or_else(|e| {
+        error!("unable to set up TLS for MQTT, {}", e);
+        process::exit(1);
+    })
+}
+
+/// Where to forecast the outdoor temperature for heat pumps, configured through
+/// `WEATHER_LATITUDE` and `WEATHER_LONGITUDE`
+fn resolve_weather_location() -> Option<WeatherLocation> {
+    let coordinate = |name: &str| {
+        variable(name).ok().map(|coordinate| {
+            coordinate.parse::<f64>().unwrap_or_else(|e| {
+                error!("unable to parse {}, {}", name, e);
+                process::exit(1);
+            })
+        })
+    };
+
+    match (
+        coordinate("WEATHER_LATITUDE"),
+        coordinate("WEATHER_LONGITUDE"),
+    ) {
+        (Some(latitude), Some(longitude)) => Some(WeatherLocation {
+            latitude,
+            longitude,
+        }),
+        (None, None) => None,
+        _ => {
+            error!("configure both WEATHER_LATITUDE and WEATHER_LONGITUDE");
+            process::exit(1);
+        }
+    }
+}
+
+/// The timezone in which local times, such as the hours of schedules, are interpreted
+/// Configured through `TIMEZONE`, e.g. `Europe/Amsterdam`, defaults to UTC
+fn resolve_timezone() -> Tz {
+    variable("TIMEZONE")
+        .map(|timezone| {
+            timezone.parse::<Tz>().unwrap_or_else(|e| {
+                error!("unable to parse TIMEZONE, {}", e);
+                process::exit(1);
+            })
+        })
+        .unwrap_or(Tz::UTC)
+}
+
+/// Build the repositories on the database of `DATABASE_URL`, which is Postgres unless it starts
+/// with `sqlite:` or `mysql:` and the matching feature is enabled. Jobs are only coordinated
+/// between instances through Postgres, a SQLite or MySQL database is used by a single instance.
+/// Searches, statistics and exports of prices read from the Postgres replica of
+/// `DATABASE_READ_URL` when it is set.
+async fn setup_repositories(
+    db_dsn: &str,
+    configuration: &DatabaseConfiguration,
+) -> (Repositories, Jobs) {
+    let read_db_dsn = variable("DATABASE_READ_URL").ok();
+
+    if read_db_dsn.is_some() && (db_dsn.starts_with("sqlite:") || db_dsn.starts_with("mysql:")) {
+        error!("DATABASE_READ_URL is only supported for Postgres");
+        process::exit(1);
+    }
+
+    if db_dsn.starts_with("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return (
+            crate::sqlite::setup_repositories(db_dsn, configuration).await,
+            Jobs::new(None),
+        );
+
+        #[cfg(not(feature = "sqlite"))]
+        {
+            error!(
+                "DATABASE_URL points to SQLite, which requires building with the sqlite feature"
+            );
+            process::exit(1);
+        }
+    }
+
+    if db_dsn.starts_with("mysql:") {
+        #[cfg(feature = "mysql")]
+        return (
+            crate::mysql::setup_repositories(db_dsn, configuration).await,
+            Jobs::new(None),
+        );
+
+        #[cfg(not(feature = "mysql"))]
+        {
+            error!("DATABASE_URL points to MySQL, which requires building with the mysql feature");
+            process::exit(1);
+        }
+    }
+
+    let db_pool = setup_db(db_dsn, configuration).await;
+
+    let mut price_repository = PostgresPriceRepository::new(db_pool.clone());
+    let mut backup_repository = PostgresBackupRepository::new(db_pool.clone());
+
+    if let Some(read_db_dsn) = read_db_dsn {
+        let read_db_pool = setup_read_db(&read_db_dsn, configuration).await;
+
+        price_repository = price_repository.with_replica(read_db_pool.clone());
+        backup_repository = backup_repository.with_replica(read_db_pool);
+    }
+
+    let repositories = Repositories {
+        price: Arc::new(price_repository),
+        exchange_rate: Arc::new(PostgresExchangeRateRepository::new(db_pool.clone())),
+        device: Arc::new(PostgresDeviceRepository::new(db_pool.clone())),
+        planned_window: Arc::new(PostgresPlannedWindowRepository::new(db_pool.clone())),
+        notification: Arc::new(PostgresNotificationRepository::new(db_pool.clone())),
+        webhook: Arc::new(PostgresWebhookRepository::new(db_pool.clone())),
+        backup: Arc::new(backup_repository),
+        carbon_intensity: Arc::new(PostgresCarbonIntensityRepository::new(db_pool.clone())),
+        solar_forecast: Arc::new(PostgresSolarForecastRepository::new(db_pool.clone())),
+        renewable_generation: Arc::new(PostgresRenewableGenerationRepository::new(db_pool.clone())),
+        weather: Arc::new(PostgresWeatherRepository::new(db_pool.clone())),
+        consumption: Arc::new(PostgresConsumptionRepository::new(db_pool.clone())),
+        contract: Arc::new(PostgresContractRepository::new(db_pool.clone())),
+    };
+
+    (repositories, Jobs::new(Some(JobLock::new(db_pool))))
+}
+
+async fn setup_db(db_dsn: &str, configuration: &DatabaseConfiguration) -> sqlx::PgPool {
+    let options = postgres_options(db_dsn, configuration);
+
+    // migrations may run for longer than a statement is allowed to, so they run on a connection
+    // of their own that is closed afterwards
+    let mut connection =
+        connect_with_retries(configuration, || PgConnection::connect_with(&options))
+            .await
+            .expect("failed to connect to the database");
+
+    sqlx::query("set statement_timeout = 0")
+        .execute(&mut connection)
         .await
-        .expect("failed to create database pool");
+        .expect("failed to lift the statement timeout");
 
-    MIGRATOR.run(&pool).await.expect("failed to run migrations");
+    MIGRATOR
+        .run(&mut connection)
+        .await
+        .expect("failed to run migrations");
+
+    connection
+        .close()
+        .await
+        .expect("failed to close the migrated connection");
 
-    pool
+    connect_postgres(options, configuration)
+        .await
+        .expect("failed to create database pool")
+}
+
+/// Connect to a read replica, of which the schema follows the primary that is migrated
+async fn setup_read_db(db_dsn: &str, configuration: &DatabaseConfiguration) -> sqlx::PgPool {
+    connect_postgres(postgres_options(db_dsn, configuration), configuration)
+        .await
+        .expect("failed to create read replica pool")
+}
+
+fn postgres_options(db_dsn: &str, configuration: &DatabaseConfiguration) -> PgConnectOptions {
+    let options = PgConnectOptions::from_str(db_dsn).expect("failed to parse DATABASE_URL");
+
+    match configuration.statement_timeout {
+        Some(statement_timeout) => options.options([(
+            "statement_timeout",
+            statement_timeout.as_millis().to_string(),
+        )]),
+        None => options,
+    }
+}
+
+async fn connect_postgres(
+    options: PgConnectOptions,
+    configuration: &DatabaseConfiguration,
+) -> Result<sqlx::PgPool, sqlx::Error> {
+    // every running job holds a connection for its lock and the price listener holds one, which
+    // leaves room for the queries of the jobs that run at the same minute
+    let pool_options = PgPoolOptions::new()
+        .max_connections(configuration.max_connections.unwrap_or(16))
+        .acquire_timeout(configuration.acquire_timeout);
+
+    connect_with_retries(configuration, || {
+        pool_options.clone().connect_with(options.clone())
+    })
+    .await
+}
+
+/// Connect to the database, trying again with an increasing delay until the connect timeout
+/// passes, as the database may start a few seconds after electrack
+pub(crate) async fn connect_with_retries<T, F, Fut>(
+    configuration: &DatabaseConfiguration,
+    connect: F,
+) -> Result<T, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let deadline = Instant::now() + configuration.connect_timeout;
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        match connect().await {
+            Ok(connected) => return Ok(connected),
+            Err(e) if Instant::now() + delay < deadline => {
+                warn!(
+                    "unable to connect to the database, trying again in {} seconds, {}",
+                    delay.as_secs(),
+                    e
+                );
+
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_CONNECT_RETRY_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct AppState {
-    pub(crate) db: PgPool,
-    pub(crate) electricity_provider: Arc<dyn ElectricityPriceProvider>,
+    /// The timezone local dates and times are interpreted in
+    pub(crate) timezone: Tz,
+    /// The configuration that can be reloaded, of which the scheduler follows the changes
+    settings: Arc<watch::Sender<Arc<Settings>>>,
     pub(crate) price_repository: Arc<dyn PriceRepository>,
+    /// The windows computed of the cached prices, which caches nothing without a price cache
+    pub(crate) window_cache: Arc<WindowCache>,
+    pub(crate) exchange_rate_repository: Arc<dyn ExchangeRateRepository>,
+    pub(crate) device_repository: Arc<dyn DeviceRepository>,
+    pub(crate) planned_window_repository: Arc<dyn PlannedWindowRepository>,
+    pub(crate) notification_repository: Arc<dyn NotificationRepository>,
+    pub(crate) webhook_repository: Arc<dyn WebhookRepository>,
+    pub(crate) backup_repository: Arc<dyn BackupRepository>,
+    pub(crate) carbon_intensity_repository: Arc<dyn CarbonIntensityRepository>,
+    pub(crate) solar_forecast_repository: Arc<dyn SolarForecastRepository>,
+    pub(crate) renewable_generation_repository: Arc<dyn RenewableGenerationRepository>,
+    pub(crate) weather_repository: Arc<dyn WeatherRepository>,
+    pub(crate) consumption_repository: Arc<dyn ConsumptionRepository>,
+    pub(crate) contract_repository: Arc<dyn ContractRepository>,
+    pub(crate) weather_location: Option<WeatherLocation>,
+    pub(crate) mqtt: Option<MqttConfiguration>,
+    pub(crate) webhooks: WebhookConfiguration,
+    pub(crate) influxdb: Option<InfluxDb>,
+    pub(crate) google_calendar: Option<GoogleCalendar>,
+    pub(crate) backup: Option<S3Backup>,
+    /// Where the carbon intensity of the grid is fetched from, if anywhere
+    pub(crate) carbon_intensity_provider: Option<Arc<dyn CarbonIntensityProvider>>,
+    /// The solar panels of the household, if they are configured
+    pub(crate) solar_panels: Option<SolarPanels>,
+    /// Where the production of the solar panels is forecast, unless it is derived from the
+    /// weather
+    pub(crate) solar_forecast: Option<ForecastSolar>,
+    /// Where the generation from wind and sun in the bidding zone is forecast, if anywhere
+    pub(crate) entsoe: Option<Entsoe>,
+    /// Where the telegrams of the smart meter are read from, if anywhere
+    pub(crate) p1_meter: Option<P1Meter>,
+    /// Where the consumption measured by Tibber is synced from, when enabled
+    pub(crate) tibber_consumption_sync: Option<TibberConsumptionSync>,
+    /// The bearer token that grants access to administrative endpoints, which are disabled
+    /// without one
+    pub(crate) admin_token: Option<String>,
+    pub(crate) jobs: Jobs,
+    /// The fetches from the providers that are in progress, by provider name and area
+    pub(crate) price_fetches: PriceFetches,
+}
+
+/// The configuration that is read again on SIGHUP, so it changes without dropping the
+/// connections of clients and the MQTT broker that a restart would
+#[derive(Clone)]
+pub(crate) struct Settings {
+    /// Where the prices are fetched from, a provider per area of which the first is that of the
+    /// household
+    pub(crate) electricity_providers: Vec<Arc<dyn ElectricityPriceProvider>>,
+    pub(crate) pricing: PricingConfiguration,
+    pub(crate) scheduling: SchedulingConfiguration,
+    pub(crate) sg_ready: SgReadyThresholds,
+    pub(crate) notifications: NotificationConfiguration,
+}
+
+impl Settings {
+    /// The area of which the prices are used when none is asked for, that of the first
+    /// provider when several are configured, as the prices of the areas would mix otherwise
+    pub(crate) fn default_area(&self) -> Option<&str> {
+        match self.electricity_providers.as_slice() {
+            [first, _, ..] => first.area(),
+            _ => None,
+        }
+    }
+}
+
+/// Where everything is stored, in Postgres, SQLite, MySQL or, in demo mode, memory
+pub(crate) struct Repositories {
+    pub(crate) price: Arc<dyn PriceRepository>,
+    pub(crate) exchange_rate: Arc<dyn ExchangeRateRepository>,
+    pub(crate) device: Arc<dyn DeviceRepository>,
+    pub(crate) planned_window: Arc<dyn PlannedWindowRepository>,
+    pub(crate) notification: Arc<dyn NotificationRepository>,
+    pub(crate) webhook: Arc<dyn WebhookRepository>,
+    pub(crate) backup: Arc<dyn BackupRepository>,
+    pub(crate) carbon_intensity: Arc<dyn CarbonIntensityRepository>,
+    pub(crate) solar_forecast: Arc<dyn SolarForecastRepository>,
+    pub(crate) renewable_generation: Arc<dyn RenewableGenerationRepository>,
+    pub(crate) weather: Arc<dyn WeatherRepository>,
+    pub(crate) consumption: Arc<dyn ConsumptionRepository>,
+    pub(crate) contract: Arc<dyn ContractRepository>,
+}
+
+pub(crate) type PriceFetches = Arc<
+    SingleFlight<(&'static str, Option<String>), Result<Vec<PricePoint>, ElectricityProviderError>>,
+>;
+
+/// Configuration of how market prices translate to what a consumer pays
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PricingConfiguration {
+    pub(crate) price_formula: Option<PriceFormula>,
+    pub(crate) tariff: Option<Tariff>,
+    pub(crate) price_cap: Option<PriceCap>,
+    /// The all-in price per kWh of a fixed tariff, to compare dynamic prices against
+    pub(crate) fixed_tariff_rate: Option<f64>,
+    /// The price above which the `price_above_threshold` webhook event fires, and price spikes
+    /// are notified of unless their rule has a threshold
+    pub(crate) price_alert_threshold: Option<f64>,
+    /// The contract that applies until one is stored through the API
+    pub(crate) contract: Contract,
+}
+
+/// Configuration of the tasks that run in the background
+#[derive(Clone, Debug)]
+pub(crate) struct SchedulingConfiguration {
+    /// How many past days are checked for missing prices at startup
+    pub(crate) catch_up_days: i64,
+    pub(crate) price_fetch_schedule: CronSchedule,
+    /// When to fetch the prices of tomorrow, shortly after the market publishes them
+    pub(crate) price_publication_schedule: CronSchedule,
+    /// The maximum power the devices of the household may draw at the same time
+    pub(crate) household_power_cap_kw: Option<f64>,
+    pub(crate) retention: Retention,
+}
+
+/// How connections to the database are pooled and how long they may take
+#[derive(Clone, Debug)]
+pub(crate) struct DatabaseConfiguration {
+    /// The most connections that are open at once, which defaults per database
+    pub(crate) max_connections: Option<u32>,
+    /// How long a query waits for a connection when all of them are in use
+    pub(crate) acquire_timeout: Duration,
+    /// How long a statement may run before Postgres cancels it, without a limit when absent
+    pub(crate) statement_timeout: Option<Duration>,
+    /// How long connecting is retried at startup
+    pub(crate) connect_timeout: Duration,
+}
+
+/// How long history is kept before it is pruned, forever when absent
+#[derive(Clone, Debug)]
+pub(crate) struct Retention {
+    pub(crate) prices: Option<TimeDelta>,
+    pub(crate) price_fetches: Option<TimeDelta>,
+    pub(crate) planned_windows: Option<TimeDelta>,
+    /// When to prune the history that is older than its retention
+    pub(crate) schedule: CronSchedule,
+}
+
+/// The webhooks that are configured rather than registered through the API
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookConfiguration {
+    pub(crate) urls: Vec<String>,
+    /// What deliveries to the configured webhooks are signed with
+    pub(crate) secret: Option<String>,
+}
+
+/// The channels notifications can be sent through besides the log
+#[derive(Debug, Clone)]
+pub(crate) struct NotificationConfiguration {
+    pub(crate) telegram: Option<Telegram>,
+    pub(crate) slack: Option<IncomingWebhook>,
+    pub(crate) discord: Option<IncomingWebhook>,
+    pub(crate) email: Option<Mailer>,
+}
+
+/// Where and what to publish over MQTT
+#[derive(Debug, Clone)]
+pub(crate) struct MqttConfiguration {
+    pub(crate) client: MqttClient,
+    pub(crate) client_id: String,
+    /// Where to publish Home Assistant discovery messages, when they are enabled
+    pub(crate) discovery_prefix: Option<String>,
+    pub(crate) topic_prefix: String,
+    pub(crate) availability_topic: Option<String>,
+    /// Whether the broker retains the published states
+    pub(crate) retain: bool,
+    /// The durations in hours of which the cheapest upcoming window is published
+    pub(crate) window_durations: Vec<usize>,
+    pub(crate) publish_schedule: CronSchedule,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        db: PgPool,
-        electricity_provider: Arc<dyn ElectricityPriceProvider>,
-        price_repository: Arc<dyn PriceRepository>,
+        timezone: Tz,
+        repositories: Repositories,
+        window_cache: Arc<WindowCache>,
+        settings: Settings,
+        weather_location: Option<WeatherLocation>,
+        mqtt: Option<MqttConfiguration>,
+        webhooks: WebhookConfiguration,
+        influxdb: Option<InfluxDb>,
+        google_calendar: Option<GoogleCalendar>,
+        backup: Option<S3Backup>,
+        carbon_intensity_provider: Option<Arc<dyn CarbonIntensityProvider>>,
+        solar_panels: Option<SolarPanels>,
+        solar_forecast: Option<ForecastSolar>,
+        entsoe: Option<Entsoe>,
+        p1_meter: Option<P1Meter>,
+        tibber_consumption_sync: Option<TibberConsumptionSync>,
+        jobs: Jobs,
+        admin_token: Option<String>,
     ) -> Self {
         Self {
-            db,
-            electricity_provider,
-            price_repository,
+            timezone,
+            settings: Arc::new(watch::Sender::new(Arc::new(settings))),
+            price_repository: repositories.price,
+            window_cache,
+            exchange_rate_repository: repositories.exchange_rate,
+            device_repository: repositories.device,
+            planned_window_repository: repositories.planned_window,
+            notification_repository: repositories.notification,
+            webhook_repository: repositories.webhook,
+            backup_repository: repositories.backup,
+            carbon_intensity_repository: repositories.carbon_intensity,
+            solar_forecast_repository: repositories.solar_forecast,
+            renewable_generation_repository: repositories.renewable_generation,
+            weather_repository: repositories.weather,
+            consumption_repository: repositories.consumption,
+            contract_repository: repositories.contract,
+            weather_location,
+            mqtt,
+            webhooks,
+            influxdb,
+            google_calendar,
+            backup,
+            carbon_intensity_provider,
+            solar_panels,
+            solar_forecast,
+            entsoe,
+            p1_meter,
+            tibber_consumption_sync,
+            admin_token,
+            jobs,
+            price_fetches: PriceFetches::default(),
         }
     }
+
+    /// The current configuration, which a request or job keeps using while it is reloaded
+    pub(crate) fn settings(&self) -> Arc<Settings> {
+        self.settings.borrow().clone()
+    }
+
+    pub(crate) fn replace_settings(&self, settings: Settings) {
+        self.settings.send_replace(Arc::new(settings));
+    }
+
+    /// The area of which to use the prices, the one that is asked for or otherwise the default
+    /// area of the settings
+    pub(crate) fn price_area(&self, area: Option<&str>) -> Option<String> {
+        area.or(self.settings().default_area()).map(str::to_string)
+    }
+
+    /// Receives the configuration every time it is reloaded
+    pub(crate) fn watch_settings(&self) -> watch::Receiver<Arc<Settings>> {
+        self.settings.subscribe()
+    }
+}
+
+/// The state of the http layer in tests, of which prices are kept in memory and nothing else is
+/// configured
+#[cfg(test)]
+pub(crate) fn test_app_state(price_repository: MemoryPriceRepository) -> AppState {
+    let schedule = |schedule: &str| CronSchedule::parse(schedule, Tz::UTC).unwrap();
+
+    AppState::new(
+        Tz::UTC,
+        memory::repositories(price_repository),
+        Arc::new(WindowCache::disabled()),
+        Settings {
+            electricity_providers: vec![Arc::new(DemoProvider::new(Tz::UTC))],
+            pricing: PricingConfiguration::default(),
+            scheduling: SchedulingConfiguration {
+                catch_up_days: 7,
+                price_fetch_schedule: schedule("5 * * * *"),
+                price_publication_schedule: schedule("15 13 * * *"),
+                household_power_cap_kw: None,
+                retention: Retention {
+                    prices: None,
+                    price_fetches: None,
+                    planned_windows: None,
+                    schedule: schedule("30 3 * * *"),
+                },
+            },
+            sg_ready: SgReadyThresholds::default(),
+            notifications: NotificationConfiguration {
+                telegram: None,
+                slack: None,
+                discord: None,
+                email: None,
+            },
+        },
+        None,
+        None,
+        WebhookConfiguration {
+            urls: vec![],
+            secret: None,
+        },
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Jobs::new(None),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider_dsn() {
+        let dsn = parse_provider_dsn("tibber://token?area=NL").unwrap();
+
+        assert_eq!(dsn.driver, "tibber");
+        assert_eq!(dsn.username.as_deref(), Some("token"));
+        assert_eq!(dsn.params.get("area").map(String::as_str), Some("NL"));
+
+        let dsn = parse_provider_dsn("tibber://token").unwrap();
+
+        assert_eq!(dsn.username.as_deref(), Some("token"));
+        assert!(dsn.params.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_electricity_providers() {
+        let providers =
+            resolve_electricity_providers("tibber://token?area=nl, tibber://other?area=SE")
+                .unwrap();
+
+        assert_eq!(
+            providers
+                .iter()
+                .map(|provider| provider.area())
+                .collect::<Vec<Option<&str>>>(),
+            vec![Some("NL"), Some("SE")]
+        );
+
+        assert!(
+            resolve_electricity_providers("tibber://token?area=NL,tibber://other?area=nl").is_err()
+        );
+        assert!(resolve_electricity_providers(" , ").is_err());
+    }
+
+    #[test]
+    fn test_price_area() {
+        let state = test_app_state(MemoryPriceRepository::new());
+
+        // a single provider does not narrow the prices down to an area
+        assert_eq!(state.price_area(None), None);
+        assert_eq!(state.price_area(Some("SE")), Some("SE".to_string()));
+
+        let mut settings = state.settings().as_ref().clone();
+        settings.electricity_providers =
+            resolve_electricity_providers("tibber://token?area=NL,tibber://other?area=SE").unwrap();
+        state.replace_settings(settings);
+
+        assert_eq!(state.price_area(None), Some("NL".to_string()));
+        assert_eq!(state.price_area(Some("SE")), Some("SE".to_string()));
+    }
 }
@@ -0,0 +1,91 @@
+use serde::Serialize;
+
+use crate::price_level::PriceLevel;
+
+/// The operating states of the SG-Ready label, with which heat pumps are told to use more or
+/// less electricity
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SgReadyState {
+    /// The heat pump is switched off by the grid operator
+    Blocked,
+    Normal,
+    /// The heat pump is recommended to heat more, e.g. raise its buffer temperature
+    Recommended,
+    /// The heat pump is switched on
+    Forced,
+}
+
+impl SgReadyState {
+    /// The number of the operating state, 1 to 4
+    pub(crate) fn mode(&self) -> u8 {
+        match self {
+            SgReadyState::Blocked => 1,
+            SgReadyState::Normal => 2,
+            SgReadyState::Recommended => 3,
+            SgReadyState::Forced => 4,
+        }
+    }
+}
+
+/// The price levels from which the SG-Ready states apply
+#[derive(Debug, Clone)]
+pub(crate) struct SgReadyThresholds {
+    /// Blocked at this level and more expensive
+    pub(crate) blocked_from: PriceLevel,
+    /// Recommended at this level and cheaper
+    pub(crate) recommended_from: PriceLevel,
+    /// Forced at this level and cheaper
+    pub(crate) forced_from: PriceLevel,
+}
+
+impl Default for SgReadyThresholds {
+    fn default() -> Self {
+        Self {
+            blocked_from: PriceLevel::VeryExpensive,
+            recommended_from: PriceLevel::Cheap,
+            forced_from: PriceLevel::VeryCheap,
+        }
+    }
+}
+
+impl SgReadyThresholds {
+    pub(crate) fn state_of(&self, level: PriceLevel) -> SgReadyState {
+        if level >= self.blocked_from {
+            SgReadyState::Blocked
+        } else if level <= self.forced_from {
+            SgReadyState::Forced
+        } else if level <= self.recommended_from {
+            SgReadyState::Recommended
+        } else {
+            SgReadyState::Normal
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_of() {
+        let thresholds = SgReadyThresholds::default();
+
+        assert_eq!(
+            thresholds.state_of(PriceLevel::VeryCheap),
+            SgReadyState::Forced
+        );
+        assert_eq!(
+            thresholds.state_of(PriceLevel::Cheap),
+            SgReadyState::Recommended
+        );
+        assert_eq!(
+            thresholds.state_of(PriceLevel::Expensive),
+            SgReadyState::Normal
+        );
+        assert_eq!(
+            thresholds.state_of(PriceLevel::VeryExpensive),
+            SgReadyState::Blocked
+        );
+    }
+}
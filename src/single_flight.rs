@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::broadcast;
+
+/// Coalesces concurrent calls for the same key, so only the first caller performs the work and
+/// the others receive its result. The work is spawned, so it completes even when the first
+/// caller goes away.
+#[derive(Debug)]
+pub(crate) struct SingleFlight<K, T> {
+    in_flight: Mutex<HashMap<K, broadcast::Sender<T>>>,
+}
+
+impl<K, T> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::default(),
+        }
+    }
+}
+
+impl<K, T> SingleFlight<K, T>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    T: Clone + Send + 'static,
+{
+    /// Run `work` unless a call for the same key is already in flight, in which case its
+    /// result is awaited instead. `None` when the work panicked.
+    pub(crate) async fn run<F>(self: &Arc<Self>, key: K, work: F) -> Option<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let mut receiver = {
+            let mut in_flight = self.in_flight.lock().ok()?;
+
+            match in_flight.get(&key) {
+                Some(sender) => sender.subscribe(),
+                None => {
+                    let (sender, receiver) = broadcast::channel(1);
+                    in_flight.insert(key.clone(), sender.clone());
+
+                    let flight = Flight {
+                        single_flight: self.clone(),
+                        key,
+                    };
+
+                    tokio::spawn(async move {
+                        let result = work.await;
+                        // later callers start a new flight, as this result may be outdated
+                        drop(flight);
+                        let _ = sender.send(result);
+                    });
+
+                    receiver
+                }
+            }
+        };
+
+        receiver.recv().await.ok()
+    }
+}
+
+/// Removes a call from the calls in flight once it is done, also when its work panics
+struct Flight<K: Eq + Hash, T> {
+    single_flight: Arc<SingleFlight<K, T>>,
+    key: K,
+}
+
+impl<K: Eq + Hash, T> Drop for Flight<K, T> {
+    fn drop(&mut self) {
+        if let Ok(mut in_flight) = self.single_flight.in_flight.lock() {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_are_coalesced() {
+        let single_flight = Arc::new(SingleFlight::<&str, usize>::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let call = || {
+            let calls = calls.clone();
+            single_flight.run("tibber", async move {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                calls.fetch_add(1, Ordering::SeqCst) + 1
+            })
+        };
+
+        let results = tokio::join!(call(), call(), call());
+
+        assert_eq!(results, (Some(1), Some(1), Some(1)));
+        assert_eq!(call().await, Some(2));
+    }
+}
@@ -0,0 +1,98 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::domain::SolarProduction;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum SolarForecastRepositoryError {
+    #[error("the solar forecast could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait SolarForecastRepository: Send + Sync {
+    /// Fetch the forecast production of the hours between two moments, ordered by moment
+    async fn fetch_solar_forecast(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<SolarProduction>, String>;
+
+    /// Persist the forecast production, replacing what was forecast before for the same hours
+    async fn persist_solar_forecast(
+        &self,
+        forecast: &[SolarProduction],
+    ) -> Result<(), SolarForecastRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresSolarForecastRepository {
+    db: PgPool,
+}
+
+impl PostgresSolarForecastRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SolarForecastRepository for PostgresSolarForecastRepository {
+    async fn fetch_solar_forecast(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<SolarProduction>, String> {
+        sqlx::query_as::<_, SolarProduction>(
+            r#"
+            select moment, energy_kwh
+            from solar_forecast
+            where moment >= $1 and moment < $2
+            order by moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_solar_forecast(
+        &self,
+        forecast: &[SolarProduction],
+    ) -> Result<(), SolarForecastRepositoryError> {
+        if forecast.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting the solar forecast of {} hours", forecast.len());
+
+        let mut query_builder =
+            QueryBuilder::new("insert into solar_forecast (moment, energy_kwh)");
+
+        query_builder.push_values(forecast, |mut builder, production| {
+            builder
+                .push_bind(production.moment)
+                .push_bind(production.energy_kwh);
+        });
+
+        query_builder.push(
+            r#"
+            on conflict (moment) do update
+            set energy_kwh = excluded.energy_kwh
+            "#,
+        );
+
+        query_builder
+            .build()
+            .persistent(false)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| SolarForecastRepositoryError::PersistenceError(e.to_string()))
+    }
+}
@@ -0,0 +1,541 @@
+use std::{str::FromStr, sync::Arc};
+
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqlitePool,
+};
+
+use crate::setup::{DatabaseConfiguration, Repositories};
+
+mod backup_repository;
+mod carbon_intensity_repository;
+mod consumption_repository;
+mod contract_repository;
+mod device_repository;
+mod exchange_rate_repository;
+mod notification_repository;
+mod planned_window_repository;
+mod price_repository;
+mod renewable_generation_repository;
+mod solar_forecast_repository;
+mod weather_repository;
+mod webhook_repository;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+/// Open the SQLite database of a `sqlite:` DATABASE_URL, creating it when it does not exist,
+/// and build the repositories on top of it
+pub(crate) async fn setup_repositories(
+    db_dsn: &str,
+    configuration: &DatabaseConfiguration,
+) -> Repositories {
+    let options = SqliteConnectOptions::from_str(db_dsn)
+        .expect("failed to parse DATABASE_URL")
+        .create_if_missing(true)
+        .foreign_keys(true)
+        // readers do not wait for the single writer
+        .journal_mode(SqliteJournalMode::Wal);
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(configuration.max_connections.unwrap_or(4))
+        .acquire_timeout(configuration.acquire_timeout)
+        .connect_with(options)
+        .await
+        .expect("failed to open the database");
+
+    MIGRATOR.run(&pool).await.expect("failed to run migrations");
+
+    repositories(pool)
+}
+
+fn repositories(db: SqlitePool) -> Repositories {
+    Repositories {
+        price: Arc::new(price_repository::SqlitePriceRepository::new(db.clone())),
+        exchange_rate: Arc::new(exchange_rate_repository::SqliteExchangeRateRepository::new(
+            db.clone(),
+        )),
+        device: Arc::new(device_repository::SqliteDeviceRepository::new(db.clone())),
+        planned_window: Arc::new(
+            planned_window_repository::SqlitePlannedWindowRepository::new(db.clone()),
+        ),
+        notification: Arc::new(notification_repository::SqliteNotificationRepository::new(
+            db.clone(),
+        )),
+        webhook: Arc::new(webhook_repository::SqliteWebhookRepository::new(db.clone())),
+        backup: Arc::new(backup_repository::SqliteBackupRepository::new(db.clone())),
+        carbon_intensity: Arc::new(
+            carbon_intensity_repository::SqliteCarbonIntensityRepository::new(db.clone()),
+        ),
+        solar_forecast: Arc::new(
+            solar_forecast_repository::SqliteSolarForecastRepository::new(db.clone()),
+        ),
+        renewable_generation: Arc::new(
+            renewable_generation_repository::SqliteRenewableGenerationRepository::new(db.clone()),
+        ),
+        weather: Arc::new(weather_repository::SqliteWeatherRepository::new(db.clone())),
+        consumption: Arc::new(consumption_repository::SqliteConsumptionRepository::new(
+            db.clone(),
+        )),
+        contract: Arc::new(contract_repository::SqliteContractRepository::new(db)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        domain::{Consumption, ExchangeRate, PricePoint, PriceWindow},
+        planned_window_repository::NewPlannedWindow,
+        price_export::import_backup,
+        test_support::{moment, price_point},
+        webhook::WebhookEvent,
+        webhook_repository::{NewDelivery, NewWebhook},
+    };
+
+    async fn memory_repositories() -> Repositories {
+        // a single connection, as every connection to `:memory:` opens a database of its own
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        MIGRATOR.run(&pool).await.unwrap();
+
+        repositories(pool)
+    }
+
+    #[tokio::test]
+    async fn test_prices_and_consumption() {
+        let repositories = memory_repositories().await;
+
+        let prices = (0..3)
+            .map(|hour| PricePoint {
+                currency: "SEK".to_string(),
+                ..price_point(
+                    moment("2024-06-30T08:00:00Z") + TimeDelta::hours(hour),
+                    0.1 * hour as f64,
+                )
+            })
+            .collect::<Vec<PricePoint>>();
+
+        repositories
+            .price
+            .persist_prices(&prices, "tibber")
+            .await
+            .unwrap();
+        // persisting again replaces the prices, of which a repeated moment keeps the last
+        let repeated = [
+            &prices[1..],
+            &[PricePoint {
+                monetary_amount: 0.5,
+                ..prices[2].clone()
+            }],
+        ]
+        .concat();
+
+        repositories
+            .price
+            .persist_prices(&repeated, "tibber")
+            .await
+            .unwrap();
+
+        let stored = repositories
+            .price
+            .fetch_prices(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].moment, moment("2024-06-30T09:00:00Z"));
+        assert_eq!(stored[0].currency, "SEK");
+        assert_eq!(stored[1].monetary_amount, 0.5);
+
+        // the unchanged price keeps its version, the corrected one gets another
+        let versions = repositories
+            .price
+            .fetch_price_versions(
+                moment("2024-06-30T09:00:00Z"),
+                moment("2024-06-30T11:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|version| (version.version, version.price.monetary_amount))
+            .collect::<Vec<(i32, f64)>>();
+
+        assert_eq!(versions, vec![(1, 0.1), (1, 0.2), (2, 0.5)]);
+
+        let statistics = repositories
+            .price
+            .fetch_price_statistics(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(statistics.hours, 3);
+
+        // the prices of another area are kept apart
+        repositories
+            .price
+            .persist_prices(
+                &[PricePoint {
+                    area: Some("SE3".to_string()),
+                    ..prices[1].clone()
+                }],
+                "tibber",
+            )
+            .await
+            .unwrap();
+
+        let area_prices = repositories
+            .price
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                Some("SE3"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(area_prices.len(), 1);
+        assert_eq!(area_prices[0].area.as_deref(), Some("SE3"));
+
+        let area_export = repositories
+            .backup
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+                Some("SE3"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(area_export.len(), 1);
+        assert_eq!(area_export[0].area, "SE3");
+
+        // the prices of areas with another currency are not aggregated together
+        repositories
+            .price
+            .persist_prices(
+                &[PricePoint {
+                    moment: moment("2024-07-01T00:00:00Z"),
+                    currency: "EUR".to_string(),
+                    area: Some("DE-LU".to_string()),
+                    ..prices[0].clone()
+                }],
+                "tibber",
+            )
+            .await
+            .unwrap();
+
+        assert!(repositories
+            .price
+            .fetch_price_statistics(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-02T00:00:00Z"),
+                None,
+            )
+            .await
+            .is_err());
+
+        let area_statistics = repositories
+            .price
+            .fetch_price_statistics(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-02T00:00:00Z"),
+                Some("DE-LU"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(area_statistics.hours, 1);
+        assert_eq!(area_statistics.currency.as_deref(), Some("EUR"));
+
+        // only the providers with prices of an area miss the hours of that area
+        let area_gaps = repositories
+            .price
+            .fetch_price_gaps(
+                moment("2024-07-01T00:00:00Z"),
+                moment("2024-07-01T02:00:00Z"),
+                Some("DE-LU"),
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|gap| (gap.provider, gap.moment))
+            .collect::<Vec<(String, DateTime<Utc>)>>();
+
+        assert_eq!(
+            area_gaps,
+            vec![("tibber".to_string(), moment("2024-07-01T01:00:00Z"))]
+        );
+        assert!(repositories
+            .price
+            .fetch_price_gaps(
+                moment("2024-07-01T00:00:00Z"),
+                moment("2024-07-01T02:00:00Z"),
+                Some("NL"),
+            )
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            repositories
+                .price
+                .delete_prices(
+                    "tibber",
+                    moment("2024-06-30T00:00:00Z"),
+                    moment("2024-07-01T00:00:00Z"),
+                    Some("SE3"),
+                )
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(repositories
+            .price
+            .delete_prices(
+                "unknown",
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                None,
+            )
+            .await
+            .is_err());
+
+        assert_eq!(
+            repositories
+                .price
+                .prune_prices(moment("2024-06-30T09:00:00Z"))
+                .await
+                .unwrap(),
+            1
+        );
+
+        // imported prices are kept as the first version of their moment
+        let exported = repositories
+            .backup
+            .fetch_prices(
+                moment("2024-06-30T00:00:00Z"),
+                moment("2024-07-01T00:00:00Z"),
+                Some("tibber"),
+                None,
+            )
+            .await
+            .unwrap();
+        let imported = memory_repositories().await;
+
+        imported
+            .backup
+            .restore_backup(&import_backup(exported).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            imported
+                .price
+                .fetch_price_versions(
+                    moment("2024-06-30T00:00:00Z"),
+                    moment("2024-07-01T00:00:00Z"),
+                    None,
+                )
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let readings = vec![Consumption {
+            starts_at: moment("2024-06-30T08:00:00Z"),
+            ends_at: moment("2024-06-30T08:15:00Z"),
+            energy_kwh: 0.2,
+            exported_kwh: 0.0,
+        }];
+
+        repositories
+            .consumption
+            .persist_consumption(&readings)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repositories
+                .consumption
+                .fetch_consumption(
+                    moment("2024-06-30T00:00:00Z"),
+                    moment("2024-07-01T00:00:00Z")
+                )
+                .await
+                .unwrap(),
+            readings
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_and_planned_windows() {
+        let repositories = memory_repositories().await;
+
+        let webhook = repositories
+            .webhook
+            .persist_webhook(&NewWebhook {
+                url: "https://example.com/hook".to_string(),
+                events: WebhookEvent::ALL.to_vec(),
+                secret: "secret".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(webhook.events.len(), WebhookEvent::ALL.len());
+
+        repositories
+            .webhook
+            .enqueue_deliveries(&[NewDelivery {
+                webhook_id: webhook.id,
+                url: webhook.url.clone(),
+                event: WebhookEvent::ALL[0],
+                payload: json!({ "price": 0.1 }),
+            }])
+            .await
+            .unwrap();
+
+        let due = repositories
+            .webhook
+            .claim_due_deliveries(10, TimeDelta::minutes(5))
+            .await
+            .unwrap();
+
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].secret.as_deref(), Some("secret"));
+        // the claimed delivery is leased
+        assert!(repositories
+            .webhook
+            .claim_due_deliveries(10, TimeDelta::minutes(5))
+            .await
+            .unwrap()
+            .is_empty());
+
+        repositories
+            .webhook
+            .enqueue_deliveries(&[NewDelivery {
+                webhook_id: webhook.id,
+                url: webhook.url.clone(),
+                event: WebhookEvent::ALL[1],
+                payload: json!({ "price": 0.2 }),
+            }])
+            .await
+            .unwrap();
+
+        let leased = repositories
+            .webhook
+            .claim_due_deliveries(10, TimeDelta::milliseconds(10))
+            .await
+            .unwrap();
+
+        assert_eq!(leased.len(), 1);
+        assert_ne!(leased[0].id, due[0].id);
+        // a delivery is claimed again once its lease has run out
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(
+            repositories
+                .webhook
+                .claim_due_deliveries(10, TimeDelta::minutes(5))
+                .await
+                .unwrap()
+                .iter()
+                .map(|delivery| delivery.id)
+                .collect::<Vec<i64>>(),
+            vec![leased[0].id]
+        );
+
+        let window = PriceWindow {
+            starts_at: moment("2024-06-30T08:00:00Z").fixed_offset(),
+            ends_at: moment("2024-06-30T10:00:00Z").fixed_offset(),
+            average_price: "0.1".to_string(),
+            average_consumer_price: None,
+            average_capped_price: None,
+            components: None,
+            currency: "EUR".to_string(),
+        };
+        let inputs = json!({ "duration": 2 });
+        let planned = NewPlannedWindow {
+            source: "time-slots",
+            device_id: None,
+            window: &window,
+            inputs: &inputs,
+        };
+
+        // the same window for the same inputs is stored once
+        for _ in 0..2 {
+            repositories
+                .planned_window
+                .persist_planned_windows(std::slice::from_ref(&planned))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            repositories
+                .planned_window
+                .fetch_planned_windows(
+                    None,
+                    moment("2024-06-30T00:00:00Z"),
+                    moment("2024-07-01T00:00:00Z")
+                )
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_persist_exchange_rates() {
+        let repositories = memory_repositories().await;
+
+        // the history of a handful of currencies binds more parameters than a single statement
+        // may hold
+        let first = NaiveDate::from_ymd_opt(1999, 1, 4).unwrap();
+        let rates = ["USD", "SEK", "NOK", "DKK", "GBP"]
+            .iter()
+            .flat_map(|currency| {
+                (0..5000).map(move |day| ExchangeRate {
+                    date: first + TimeDelta::days(day),
+                    currency: currency.to_string(),
+                    rate: 1.0 + day as f64 / 10000.0,
+                })
+            })
+            .collect::<Vec<ExchangeRate>>();
+
+        assert!(rates.len() > 22_000);
+
+        repositories
+            .exchange_rate
+            .persist_rates(&rates)
+            .await
+            .unwrap();
+
+        let rate = repositories
+            .exchange_rate
+            .fetch_rate("NOK", first + TimeDelta::days(4999))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rate.rate, 1.4999);
+    }
+}
@@ -0,0 +1,58 @@
+use axum::async_trait;
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::{contract::Contract, contract_repository::ContractRepository};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteContractRepository {
+    db: SqlitePool,
+}
+
+impl SqliteContractRepository {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ContractRepository for SqliteContractRepository {
+    async fn fetch_contract(&self) -> Result<Option<Contract>, String> {
+        sqlx::query_as::<_, Contract>(
+            r#"
+            select standing_charge_per_day, fixed_fee_per_month, tax_credit_per_month,
+                feed_in_compensation
+            from contract
+            "#,
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_contract(&self, contract: &Contract) -> Result<(), String> {
+        info!("Persisting the contract");
+
+        sqlx::query(
+            r#"
+            insert into contract (standing_charge_per_day, fixed_fee_per_month,
+                tax_credit_per_month, feed_in_compensation)
+            values ($1, $2, $3, $4)
+            on conflict (id) do update
+            set standing_charge_per_day = excluded.standing_charge_per_day,
+                fixed_fee_per_month = excluded.fixed_fee_per_month,
+                tax_credit_per_month = excluded.tax_credit_per_month,
+                feed_in_compensation = excluded.feed_in_compensation,
+                updated_at = strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')
+            "#,
+        )
+        .bind(contract.standing_charge_per_day)
+        .bind(contract.fixed_fee_per_month)
+        .bind(contract.tax_credit_per_month)
+        .bind(contract.feed_in_compensation)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,470 @@
+use axum::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{QueryBuilder, SqlitePool};
+
+use crate::{
+    device::{Actuation, Device, DeviceRun, Schedule, ScheduleOverride},
+    device_repository::{DeviceRepository, DeviceRepositoryError, NewDevice, NewSchedule},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteDeviceRepository {
+    db: SqlitePool,
+}
+
+impl SqliteDeviceRepository {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl DeviceRepository for SqliteDeviceRepository {
+    async fn fetch_devices(&self) -> Result<Vec<Device>, String> {
+        sqlx::query_as::<_, Device>(
+            "select id, name, duration, power_kw, strategy, min_gap_hours, snoozed_until, actuator, actuator_url, actuator_topic, actuator_channel from devices order by name",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_device(&self, id: i64) -> Result<Option<Device>, String> {
+        sqlx::query_as::<_, Device>(
+            "select id, name, duration, power_kw, strategy, min_gap_hours, snoozed_until, actuator, actuator_url, actuator_topic, actuator_channel from devices where id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_schedules(&self, device_id: i64) -> Result<Vec<Schedule>, String> {
+        sqlx::query_as::<_, Schedule>(
+            r#"
+            select id, device_id, recurrence, available_from_hour, finish_by_hour, enabled
+            from schedules
+            where device_id = $1
+            order by id
+            "#,
+        )
+        .bind(device_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_device(&self, device: &NewDevice) -> Result<Device, DeviceRepositoryError> {
+        sqlx::query_as::<_, Device>(
+            r#"
+            insert into devices (name, duration, power_kw, strategy, min_gap_hours, actuator,
+                                 actuator_url, actuator_topic, actuator_channel)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            returning id, name, duration, power_kw, strategy, min_gap_hours, snoozed_until, actuator, actuator_url, actuator_topic, actuator_channel
+            "#,
+        )
+        .bind(&device.name)
+        .bind(device.duration)
+        .bind(device.power_kw)
+        .bind(&device.strategy)
+        .bind(device.min_gap_hours)
+        .bind(&device.actuator)
+        .bind(&device.actuator_url)
+        .bind(&device.actuator_topic)
+        .bind(device.actuator_channel)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn persist_schedule(
+        &self,
+        schedule: &NewSchedule,
+    ) -> Result<Schedule, DeviceRepositoryError> {
+        sqlx::query_as::<_, Schedule>(
+            r#"
+            insert into schedules (device_id, recurrence, available_from_hour, finish_by_hour)
+            values ($1, $2, $3, $4)
+            returning id, device_id, recurrence, available_from_hour, finish_by_hour, enabled
+            "#,
+        )
+        .bind(schedule.device_id)
+        .bind(schedule.recurrence.as_str())
+        .bind(schedule.available_from_hour)
+        .bind(schedule.finish_by_hour)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_all_schedules(&self) -> Result<Vec<Schedule>, String> {
+        sqlx::query_as::<_, Schedule>(
+            r#"
+            select id, device_id, recurrence, available_from_hour, finish_by_hour, enabled
+            from schedules
+            order by id
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_schedule(&self, id: i64) -> Result<Option<Schedule>, String> {
+        sqlx::query_as::<_, Schedule>(
+            r#"
+            select id, device_id, recurrence, available_from_hour, finish_by_hour, enabled
+            from schedules
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn set_schedule_enabled(
+        &self,
+        id: i64,
+        enabled: bool,
+    ) -> Result<Option<Schedule>, DeviceRepositoryError> {
+        sqlx::query_as::<_, Schedule>(
+            r#"
+            update schedules
+            set enabled = $2
+            where id = $1
+            returning id, device_id, recurrence, available_from_hour, finish_by_hour, enabled
+            "#,
+        )
+        .bind(id)
+        .bind(enabled)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_schedule(&self, id: i64) -> Result<bool, DeviceRepositoryError> {
+        sqlx::query("delete from schedules where id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_overrides(&self, schedule_ids: &[i64]) -> Result<Vec<ScheduleOverride>, String> {
+        if schedule_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // SQLite has no arrays to bind the ids as a single parameter
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            select schedule_id, date, skip, available_from_hour, finish_by_hour
+            from schedule_overrides
+            where schedule_id in (
+            "#,
+        );
+
+        let mut ids = query_builder.separated(", ");
+
+        for id in schedule_ids {
+            ids.push_bind(id);
+        }
+
+        query_builder.push(") order by date");
+
+        query_builder
+            .build_query_as::<ScheduleOverride>()
+            .fetch_all(&self.db)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn persist_override(
+        &self,
+        schedule_override: &ScheduleOverride,
+    ) -> Result<ScheduleOverride, DeviceRepositoryError> {
+        sqlx::query_as::<_, ScheduleOverride>(
+            r#"
+            insert into schedule_overrides (schedule_id, date, skip, available_from_hour, finish_by_hour)
+            values ($1, $2, $3, $4, $5)
+            on conflict (schedule_id, date) do update
+            set skip                = excluded.skip,
+                available_from_hour = excluded.available_from_hour,
+                finish_by_hour      = excluded.finish_by_hour
+            returning schedule_id, date, skip, available_from_hour, finish_by_hour
+            "#,
+        )
+        .bind(schedule_override.schedule_id)
+        .bind(schedule_override.date)
+        .bind(schedule_override.skip)
+        .bind(schedule_override.available_from_hour)
+        .bind(schedule_override.finish_by_hour)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_override(
+        &self,
+        schedule_id: i64,
+        date: NaiveDate,
+    ) -> Result<bool, DeviceRepositoryError> {
+        sqlx::query("delete from schedule_overrides where schedule_id = $1 and date = $2")
+            .bind(schedule_id)
+            .bind(date)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn set_snoozed_until(
+        &self,
+        device_id: i64,
+        snoozed_until: Option<DateTime<Utc>>,
+    ) -> Result<bool, DeviceRepositoryError> {
+        sqlx::query("update devices set snoozed_until = $2 where id = $1")
+            .bind(device_id)
+            .bind(snoozed_until)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn persist_run_start(
+        &self,
+        device_id: i64,
+        occurrence: Option<(i64, NaiveDate)>,
+        started_at: DateTime<Utc>,
+    ) -> Result<DeviceRun, DeviceRepositoryError> {
+        sqlx::query_as::<_, DeviceRun>(
+            r#"
+            insert into device_runs (device_id, schedule_id, date, started_at)
+            values ($1, $2, $3, $4)
+            returning id, device_id, schedule_id, date, started_at, finished_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(occurrence.map(|(schedule_id, _)| schedule_id))
+        .bind(occurrence.map(|(_, date)| date))
+        .bind(started_at)
+        .fetch_one(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn finish_run(
+        &self,
+        device_id: i64,
+        finished_at: DateTime<Utc>,
+    ) -> Result<Option<DeviceRun>, DeviceRepositoryError> {
+        sqlx::query_as::<_, DeviceRun>(
+            r#"
+            update device_runs
+            set finished_at = $2
+            where id = (select id
+                        from device_runs
+                        where device_id = $1
+                          and finished_at is null
+                        order by started_at desc
+                        limit 1)
+            returning id, device_id, schedule_id, date, started_at, finished_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(finished_at)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_runs(
+        &self,
+        device_id: i64,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<DeviceRun>, String> {
+        sqlx::query_as::<_, DeviceRun>(
+            r#"
+            select id, device_id, schedule_id, date, started_at, finished_at
+            from device_runs
+            where device_id = $1
+              and started_at >= $2
+              and started_at < $3
+            order by started_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_latest_run(
+        &self,
+        device_id: i64,
+        before: DateTime<Utc>,
+    ) -> Result<Option<DeviceRun>, String> {
+        sqlx::query_as::<_, DeviceRun>(
+            r#"
+            select id, device_id, schedule_id, date, started_at, finished_at
+            from device_runs
+            where device_id = $1
+              and started_at < $2
+            order by started_at desc
+            limit 1
+            "#,
+        )
+        .bind(device_id)
+        .bind(before)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_actuations(
+        &self,
+        actuations: &[Actuation],
+    ) -> Result<(), DeviceRepositoryError> {
+        if actuations.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into actuations (device_id, schedule_id, date, starts_at, ends_at)",
+        );
+
+        query_builder.push_values(actuations, |mut builder, actuation| {
+            builder
+                .push_bind(actuation.device_id)
+                .push_bind(actuation.schedule_id)
+                .push_bind(actuation.date)
+                .push_bind(actuation.starts_at)
+                .push_bind(actuation.ends_at);
+        });
+
+        query_builder.push(" on conflict (device_id, starts_at) do nothing");
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn fetch_occurrence_actuations(
+        &self,
+        device_id: i64,
+        schedule_id: i64,
+        date: NaiveDate,
+    ) -> Result<Vec<Actuation>, String> {
+        sqlx::query_as::<_, Actuation>(
+            r#"
+            select device_id, schedule_id, date, starts_at, ends_at, switched_on_at, switched_off_at, error
+            from actuations
+            where device_id = $1
+              and schedule_id = $2
+              and date = $3
+            order by starts_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(schedule_id)
+        .bind(date)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_actuations(
+        &self,
+        device_id: i64,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Actuation>, String> {
+        sqlx::query_as::<_, Actuation>(
+            r#"
+            select device_id, schedule_id, date, starts_at, ends_at, switched_on_at, switched_off_at, error
+            from actuations
+            where device_id = $1
+              and starts_at >= $2
+              and starts_at < $3
+            order by starts_at
+            "#,
+        )
+        .bind(device_id)
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_due_actuations(
+        &self,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Actuation>, String> {
+        sqlx::query_as::<_, Actuation>(
+            r#"
+            select device_id, schedule_id, date, starts_at, ends_at, switched_on_at, switched_off_at, error
+            from actuations
+            where (switched_on_at is null and starts_at <= $2 and ends_at > $2)
+               or (switched_on_at is not null and switched_off_at is null
+                   and ends_at <= $2 and ends_at > $1)
+            order by starts_at
+            "#,
+        )
+        .bind(since)
+        .bind(now)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn record_switch(
+        &self,
+        actuation: &Actuation,
+        on: bool,
+        switched_at: DateTime<Utc>,
+        error: Option<&str>,
+    ) -> Result<(), DeviceRepositoryError> {
+        let query = match on {
+            true => {
+                r#"
+                update actuations
+                set switched_on_at = case when $4 is null then $3 end,
+                    error          = $4
+                where device_id = $1
+                  and starts_at = $2
+                "#
+            }
+            false => {
+                r#"
+                update actuations
+                set switched_off_at = case when $4 is null then $3 end,
+                    error           = $4
+                where device_id = $1
+                  and starts_at = $2
+                "#
+            }
+        };
+
+        sqlx::query(query)
+            .bind(actuation.device_id)
+            .bind(actuation.starts_at)
+            .bind(switched_at)
+            .bind(error)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| DeviceRepositoryError::PersistenceError(e.to_string()))
+    }
+}
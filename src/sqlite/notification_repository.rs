@@ -0,0 +1,179 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, FromRow, SqlitePool};
+
+use crate::{
+    notification::{NotificationChannel, NotificationEvent, NotificationRule},
+    notification_repository::{
+        NewNotificationRule, NotificationRepository, NotificationRepositoryError,
+    },
+};
+
+/// A notification rule as stored, of which the durations are a json array
+#[derive(Debug, FromRow)]
+struct NotificationRuleRow {
+    id: i64,
+    #[sqlx(try_from = "String")]
+    event: NotificationEvent,
+    device_id: Option<i64>,
+    lead_minutes: i32,
+    enabled: bool,
+    #[sqlx(try_from = "String")]
+    channel: NotificationChannel,
+    hour: Option<i32>,
+    threshold: Option<f64>,
+    template: Option<String>,
+    level: Option<String>,
+    duration: Option<i32>,
+    durations: Option<Json<Vec<i32>>>,
+    schedule: Option<String>,
+}
+
+impl From<NotificationRuleRow> for NotificationRule {
+    fn from(row: NotificationRuleRow) -> Self {
+        NotificationRule {
+            id: row.id,
+            event: row.event,
+            device_id: row.device_id,
+            lead_minutes: row.lead_minutes,
+            enabled: row.enabled,
+            channel: row.channel,
+            hour: row.hour,
+            threshold: row.threshold,
+            template: row.template,
+            level: row.level,
+            duration: row.duration,
+            durations: row.durations.map(|durations| durations.0),
+            schedule: row.schedule,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteNotificationRepository {
+    db: SqlitePool,
+}
+
+impl SqliteNotificationRepository {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl NotificationRepository for SqliteNotificationRepository {
+    async fn fetch_rules(&self) -> Result<Vec<NotificationRule>, String> {
+        sqlx::query_as::<_, NotificationRuleRow>(
+            r#"
+            select id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            from notification_rules
+            order by id
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await
+        .map(|rows| rows.into_iter().map(NotificationRule::from).collect())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_rule(
+        &self,
+        rule: &NewNotificationRule,
+    ) -> Result<NotificationRule, NotificationRepositoryError> {
+        sqlx::query_as::<_, NotificationRuleRow>(
+            r#"
+            insert into notification_rules
+                (event, device_id, lead_minutes, channel, hour, threshold, template, level,
+                 duration, durations, schedule)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            returning id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            "#,
+        )
+        .bind(rule.event.as_str())
+        .bind(rule.device_id)
+        .bind(rule.lead_minutes)
+        .bind(rule.channel.as_str())
+        .bind(rule.hour)
+        .bind(rule.threshold)
+        .bind(&rule.template)
+        .bind(rule.level.map(|level| level.as_str()))
+        .bind(rule.duration)
+        .bind(rule.durations.as_ref().map(Json))
+        .bind(&rule.schedule)
+        .fetch_one(&self.db)
+        .await
+        .map(NotificationRule::from)
+        .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn set_rule_enabled(
+        &self,
+        id: i64,
+        enabled: bool,
+    ) -> Result<Option<NotificationRule>, NotificationRepositoryError> {
+        sqlx::query_as::<_, NotificationRuleRow>(
+            r#"
+            update notification_rules
+            set enabled = $2
+            where id = $1
+            returning id, event, device_id, lead_minutes, enabled, channel, hour, threshold, template,
+                level, duration, durations, schedule
+            "#,
+        )
+        .bind(id)
+        .bind(enabled)
+        .fetch_optional(&self.db)
+        .await
+        .map(|row| row.map(NotificationRule::from))
+        .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_rule(&self, id: i64) -> Result<bool, NotificationRepositoryError> {
+        sqlx::query("delete from notification_rules where id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| NotificationRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn mark_sent(
+        &self,
+        rule_id: i64,
+        device_id: Option<i64>,
+        starts_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        sqlx::query(
+            r#"
+            insert into sent_notifications (rule_id, device_id, starts_at)
+            values ($1, $2, $3)
+            on conflict do nothing
+            "#,
+        )
+        .bind(rule_id)
+        .bind(device_id)
+        .bind(starts_at)
+        .execute(&self.db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_last_sent(&self, rule_id: i64) -> Result<Option<DateTime<Utc>>, String> {
+        sqlx::query_scalar(
+            r#"
+            select starts_at
+            from sent_notifications
+            where rule_id = $1
+            order by sent_at desc
+            limit 1
+            "#,
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+}
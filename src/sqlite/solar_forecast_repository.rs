@@ -0,0 +1,77 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{QueryBuilder, SqlitePool};
+use tracing::info;
+
+use crate::{
+    domain::SolarProduction,
+    solar_forecast_repository::{SolarForecastRepository, SolarForecastRepositoryError},
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteSolarForecastRepository {
+    db: SqlitePool,
+}
+
+impl SqliteSolarForecastRepository {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SolarForecastRepository for SqliteSolarForecastRepository {
+    async fn fetch_solar_forecast(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<SolarProduction>, String> {
+        sqlx::query_as::<_, SolarProduction>(
+            r#"
+            select moment, energy_kwh
+            from solar_forecast
+            where moment >= $1 and moment < $2
+            order by moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_solar_forecast(
+        &self,
+        forecast: &[SolarProduction],
+    ) -> Result<(), SolarForecastRepositoryError> {
+        if forecast.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting the solar forecast of {} hours", forecast.len());
+
+        let mut query_builder =
+            QueryBuilder::new("insert into solar_forecast (moment, energy_kwh)");
+
+        query_builder.push_values(forecast, |mut builder, production| {
+            builder
+                .push_bind(production.moment)
+                .push_bind(production.energy_kwh);
+        });
+
+        query_builder.push(
+            r#"
+            on conflict (moment) do update
+            set energy_kwh = excluded.energy_kwh
+            "#,
+        );
+
+        query_builder
+            .build()
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| SolarForecastRepositoryError::PersistenceError(e.to_string()))
+    }
+}
@@ -0,0 +1,269 @@
+use axum::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::{types::Json, FromRow, SqlitePool};
+
+use crate::{
+    webhook::{
+        DeliveryAttempt, DeliveryStatus, DueDelivery, Webhook, WebhookDelivery, WebhookEvent,
+    },
+    webhook_repository::{NewDelivery, NewWebhook, WebhookRepository, WebhookRepositoryError},
+};
+
+/// A webhook as stored, of which the events are a json array
+#[derive(Debug, FromRow)]
+struct WebhookRow {
+    id: i64,
+    url: String,
+    events: Json<Vec<String>>,
+}
+
+impl From<WebhookRow> for Webhook {
+    /// Events that are no longer known are left out
+    fn from(row: WebhookRow) -> Self {
+        Webhook {
+            id: Some(row.id),
+            url: row.url,
+            events: row
+                .events
+                .iter()
+                .filter_map(|event| event.parse().ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteWebhookRepository {
+    db: SqlitePool,
+}
+
+impl SqliteWebhookRepository {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for SqliteWebhookRepository {
+    async fn fetch_webhooks(&self) -> Result<Vec<Webhook>, String> {
+        sqlx::query_as::<_, WebhookRow>("select id, url, events from webhooks order by id")
+            .fetch_all(&self.db)
+            .await
+            .map(|rows| rows.into_iter().map(Webhook::from).collect())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn persist_webhook(
+        &self,
+        webhook: &NewWebhook,
+    ) -> Result<Webhook, WebhookRepositoryError> {
+        sqlx::query_as::<_, WebhookRow>(
+            r#"
+            insert into webhooks (url, events, secret)
+            values ($1, $2, $3)
+            returning id, url, events
+            "#,
+        )
+        .bind(&webhook.url)
+        .bind(Json(
+            webhook
+                .events
+                .iter()
+                .map(WebhookEvent::as_str)
+                .collect::<Vec<&str>>(),
+        ))
+        .bind(&webhook.secret)
+        .fetch_one(&self.db)
+        .await
+        .map(Webhook::from)
+        .map_err(|e| WebhookRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<bool, WebhookRepositoryError> {
+        sqlx::query("delete from webhooks where id = $1")
+            .bind(id)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| WebhookRepositoryError::PersistenceError(e.to_string()))
+    }
+
+    async fn mark_dispatched(&self, key: &str) -> Result<bool, String> {
+        sqlx::query("insert into webhook_events (key) values ($1) on conflict do nothing")
+            .bind(key)
+            .execute(&self.db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    async fn enqueue_deliveries(&self, deliveries: &[NewDelivery]) -> Result<(), String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        for delivery in deliveries {
+            sqlx::query(
+                "insert into webhook_deliveries (webhook_id, url, event, payload, next_attempt_at) values ($1, $2, $3, $4, $5)",
+            )
+            .bind(delivery.webhook_id)
+            .bind(&delivery.url)
+            .bind(delivery.event.as_str())
+            .bind(&delivery.payload)
+            .bind(Utc::now())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+
+        transaction.commit().await.map_err(|e| e.to_string())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease: TimeDelta,
+    ) -> Result<Vec<DueDelivery>, String> {
+        let now = Utc::now();
+
+        // writes to SQLite are serialized, so there is nothing to skip
+        sqlx::query_as::<_, DueDelivery>(
+            r#"
+            update webhook_deliveries
+            set next_attempt_at = $3
+            where id in (select id
+                         from webhook_deliveries
+                         where status = 'pending'
+                           and next_attempt_at <= $2
+                         order by next_attempt_at
+                         limit $1)
+            returning id,
+                webhook_id,
+                url,
+                payload,
+                attempts,
+                (select secret from webhooks where webhooks.id = webhook_id) as secret
+            "#,
+        )
+        .bind(limit)
+        .bind(now)
+        .bind(now + lease)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn record_attempt(
+        &self,
+        id: i64,
+        attempt: &DeliveryAttempt,
+        status: DeliveryStatus,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let mut transaction = self.db.begin().await.map_err(|e| e.to_string())?;
+
+        sqlx::query(
+            r#"
+            insert into webhook_delivery_attempts (delivery_id, attempted_at, status_code, error)
+            values ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(id)
+        .bind(attempt.attempted_at)
+        .bind(attempt.status_code)
+        .bind(&attempt.error)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        sqlx::query(
+            r#"
+            update webhook_deliveries
+            set status          = $2,
+                attempts        = attempts + 1,
+                next_attempt_at = $3,
+                last_error      = $4,
+                delivered_at    = case when $2 = 'delivered' then $5 end
+            where id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(next_attempt_at)
+        .bind(&attempt.error)
+        .bind(attempt.attempted_at)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())
+    }
+
+    async fn fetch_deliveries(
+        &self,
+        status: Option<DeliveryStatus>,
+    ) -> Result<Vec<WebhookDelivery>, String> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            select id,
+                   webhook_id,
+                   url,
+                   event,
+                   payload,
+                   status,
+                   attempts,
+                   next_attempt_at,
+                   last_error,
+                   created_at,
+                   delivered_at
+            from webhook_deliveries
+            where $1 is null or status = $1
+            order by id desc
+            "#,
+        )
+        .bind(status.map(|status| status.as_str()))
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn fetch_attempts(&self, delivery_id: i64) -> Result<Vec<DeliveryAttempt>, String> {
+        sqlx::query_as::<_, DeliveryAttempt>(
+            r#"
+            select attempted_at, status_code, error
+            from webhook_delivery_attempts
+            where delivery_id = $1
+            order by attempted_at
+            "#,
+        )
+        .bind(delivery_id)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn retry_delivery(&self, id: i64) -> Result<bool, String> {
+        sqlx::query(
+            r#"
+            update webhook_deliveries
+            set status = 'pending', attempts = 0, next_attempt_at = $2
+            where id = $1 and status = 'failed'
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .execute(&self.db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn prune_deliveries(&self, delivered_before: DateTime<Utc>) -> Result<(), String> {
+        sqlx::query(
+            "delete from webhook_deliveries where status = 'delivered' and delivered_at < $1",
+        )
+        .bind(delivered_before)
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
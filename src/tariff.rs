@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::grid_fee::GridFeeSchedule;
+
+/// The parts a consumer price is made up of, all expressed per kWh
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PriceComponents {
+    /// The market price of the energy itself
+    pub(crate) energy: f64,
+    pub(crate) supplier_fee: f64,
+    pub(crate) energy_tax: f64,
+    pub(crate) grid_fee: f64,
+    pub(crate) vat: f64,
+}
+
+impl PriceComponents {
+    /// The all-in price a consumer pays
+    pub(crate) fn total(&self) -> f64 {
+        self.energy + self.supplier_fee + self.energy_tax + self.grid_fee + self.vat
+    }
+
+    /// The average of every component, rounded to 4 decimals
+    pub(crate) fn average(components: &[PriceComponents]) -> PriceComponents {
+        let count = components.len().max(1) as f64;
+        let average = |component: fn(&PriceComponents) -> f64| {
+            (components.iter().map(component).sum::<f64>() / count * 10_000.0).round() / 10_000.0
+        };
+
+        PriceComponents {
+            energy: average(|c| c.energy),
+            supplier_fee: average(|c| c.supplier_fee),
+            energy_tax: average(|c| c.energy_tax),
+            grid_fee: average(|c| c.grid_fee),
+            vat: average(|c| c.vat),
+        }
+    }
+
+    pub(crate) fn with_exchange_rate(&self, rate: f64) -> PriceComponents {
+        PriceComponents {
+            energy: self.energy * rate,
+            supplier_fee: self.supplier_fee * rate,
+            energy_tax: self.energy_tax * rate,
+            grid_fee: self.grid_fee * rate,
+            vat: self.vat * rate,
+        }
+    }
+}
+
+/// The tariff of a deployment, used to turn market prices into their components
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tariff {
+    pub(crate) supplier_fee: f64,
+    pub(crate) energy_tax: f64,
+    /// The grid fee for hours that are not covered by the grid fee schedule
+    pub(crate) grid_fee: f64,
+    /// Time-of-use grid fees, for grid operators that charge by hour or season
+    pub(crate) grid_fee_schedule: Option<GridFeeSchedule>,
+    /// VAT as a percentage, levied over the energy price, fees and tax
+    pub(crate) vat_percentage: f64,
+}
+
+impl Tariff {
+    /// Split the consumer price of the hour starting at `moment` into its components
+    pub(crate) fn components(&self, energy: f64, moment: DateTime<Utc>) -> PriceComponents {
+        let grid_fee = self
+            .grid_fee_schedule
+            .as_ref()
+            .and_then(|schedule| schedule.fee_at(moment))
+            .unwrap_or(self.grid_fee);
+
+        let taxable = energy + self.supplier_fee + self.energy_tax + grid_fee;
+
+        PriceComponents {
+            energy,
+            supplier_fee: self.supplier_fee,
+            energy_tax: self.energy_tax,
+            grid_fee,
+            vat: taxable * self.vat_percentage / 100.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components_of_price() {
+        let tariff = Tariff {
+            supplier_fee: 0.02,
+            energy_tax: 0.1,
+            grid_fee: 0.03,
+            grid_fee_schedule: None,
+            vat_percentage: 21.0,
+        };
+
+        let components = tariff.components(0.15, Utc::now());
+
+        assert_eq!(components.energy, 0.15);
+        assert!((components.vat - 0.063).abs() < 1e-9);
+        assert!((components.total() - 0.363).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::chat::describe_error;
+
+const API_URL: &str = "https://api.telegram.org";
+
+/// How long Telegram may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Telegram bot that sends messages to a single chat
+#[derive(Clone, Debug)]
+pub(crate) struct Telegram {
+    bot_token: String,
+    /// The id of a user, group or channel, or the `@username` of a channel
+    chat_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    ok: bool,
+    description: Option<String>,
+}
+
+impl Telegram {
+    pub(crate) fn new(bot_token: String, chat_id: String) -> Self {
+        Self { bot_token, chat_id }
+    }
+
+    pub(crate) async fn send_message(&self, text: &str) -> Result<(), String> {
+        let response = Client::new()
+            // the URL carries the bot token, which describing the error leaves out
+            .post(format!("{}/bot{}/sendMessage", API_URL, self.bot_token))
+            .timeout(TIMEOUT)
+            .json(&json!({ "chat_id": self.chat_id, "text": text }))
+            .send()
+            .await
+            .map_err(describe_error)?
+            .json::<Response>()
+            .await
+            .map_err(describe_error)?;
+
+        match response.ok {
+            true => Ok(()),
+            false => Err(format!(
+                "telegram refused the message, {}",
+                response.description.unwrap_or_default()
+            )),
+        }
+    }
+}
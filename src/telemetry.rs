@@ -0,0 +1,612 @@
+use std::{
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client,
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{
+    field::{Field, Visit},
+    span, warn, Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{format::FmtSpan, writer::BoxMakeWriter},
+    layer::Context,
+    prelude::*,
+    registry::LookupSpan,
+    Layer,
+};
+use url::Url;
+
+use crate::{configuration_file::variable, sentry::sentry_layer, APP_NAME};
+
+/// How long OTLP receivers may take to respond
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often finished spans are exported
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Spans are exported right away once this many finished
+const MAX_BATCH_SIZE: usize = 512;
+
+/// Spans that finish while this many wait to be exported are dropped
+const QUEUE_SIZE: usize = 4096;
+
+/// Events recorded on a span beyond this many are dropped
+const MAX_EVENTS: usize = 128;
+
+/// The kinds of spans as OTLP numbers them
+const SPAN_KIND_INTERNAL: i64 = 1;
+const SPAN_KIND_SERVER: i64 = 2;
+const SPAN_KIND_CLIENT: i64 = 3;
+
+const STATUS_CODE_ERROR: i64 = 2;
+
+/// Where and how to export traces, over OTLP/HTTP with JSON encoding
+#[derive(Clone, Debug)]
+struct OtlpConfiguration {
+    traces_url: Url,
+    headers: HeaderMap,
+    service_name: String,
+    /// The ratio of traces that are exported, from 0 to 1
+    sampling_ratio: f64,
+}
+
+/// Log to stdout, or to stderr when stdout is the output of a command, and, when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is configured, export the spans of electrack and its SQL queries
+/// to an OTLP receiver such as Jaeger or Tempo
+pub(crate) fn init_tracing(log_to_stderr: bool) {
+    let otlp = resolve_otlp().map(OtlpLayer::new);
+
+    let writer = if log_to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_span_events(FmtSpan::CLOSE),
+        )
+        .with(otlp)
+        .with(sentry_layer())
+        .init();
+}
+
+/// The OTLP receiver to export traces to, configured through the standard
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`, e.g. `http://tempo:4318`, with the optional
+/// `OTEL_EXPORTER_OTLP_HEADERS` as comma separated `name=value` pairs. `OTEL_SERVICE_NAME`
+/// defaults to `electrack` and `OTEL_TRACES_SAMPLER_ARG`, the ratio of traces that are
+/// exported, to 1. Logging is not set up yet, so configuration errors are printed.
+fn resolve_otlp() -> Option<OtlpConfiguration> {
+    let endpoint = variable("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+
+    let exit = |message: &str| -> ! {
+        eprintln!("{}", message);
+        process::exit(1);
+    };
+
+    let mut traces_url = match Url::parse(&endpoint) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => url,
+        _ => exit("unable to parse OTEL_EXPORTER_OTLP_ENDPOINT, it is not an http(s) URL"),
+    };
+
+    // a receiver behind a path prefix keeps it
+    if let Ok(mut segments) = traces_url.path_segments_mut() {
+        segments.pop_if_empty().extend(["v1", "traces"]);
+    }
+
+    let headers = variable("OTEL_EXPORTER_OTLP_HEADERS")
+        .map(|headers| {
+            parse_headers(&headers).unwrap_or_else(|e| {
+                exit(&format!(
+                    "unable to parse OTEL_EXPORTER_OTLP_HEADERS, {}",
+                    e
+                ))
+            })
+        })
+        .unwrap_or_default();
+
+    let sampling_ratio = variable("OTEL_TRACES_SAMPLER_ARG")
+        .map(|ratio| match ratio.parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => ratio,
+            _ => exit("unable to parse OTEL_TRACES_SAMPLER_ARG, expected a ratio from 0 to 1"),
+        })
+        .unwrap_or(1.0);
+
+    Some(OtlpConfiguration {
+        traces_url,
+        headers,
+        service_name: variable("OTEL_SERVICE_NAME").unwrap_or(APP_NAME.to_string()),
+        sampling_ratio,
+    })
+}
+
+/// Parse headers in the format of `OTEL_EXPORTER_OTLP_HEADERS`, e.g.
+/// `Authorization=Basic%20ZWxlY3RyYWNr,X-Scope-OrgID=home`, where values are percent-encoded
+fn parse_headers(headers: &str) -> Result<HeaderMap, String> {
+    let mut map = HeaderMap::new();
+
+    for pair in headers.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let Some((name, value)) = pair.split_once('=') else {
+            return Err(format!("\"{}\" is not a name=value pair", pair.trim()));
+        };
+
+        let value = percent_encoding::percent_decode_str(value.trim()).decode_utf8_lossy();
+
+        map.insert(
+            HeaderName::try_from(name.trim())
+                .map_err(|_| format!("\"{}\" is not a valid header name", name.trim()))?,
+            HeaderValue::try_from(value.as_ref())
+                .map_err(|_| format!("the value of {} is not valid", name.trim()))?,
+        );
+    }
+
+    Ok(map)
+}
+
+/// The identity of the trace a span belongs to. Spans that are not exported carry that of their
+/// closest exported ancestor, so their children are attached to it.
+#[derive(Debug, Clone, Copy)]
+struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    sampled: bool,
+}
+
+/// A span that is exported once it closes
+#[derive(Debug)]
+struct RecordedSpan {
+    parent_span_id: Option<[u8; 8]>,
+    name: String,
+    kind: i64,
+    start: SystemTime,
+    attributes: Vec<Value>,
+    events: Vec<Value>,
+    error: Option<String>,
+}
+
+impl RecordedSpan {
+    /// Take the fields of the span, of which those starting with `otel.` name the span, set its
+    /// kind or mark it as failed
+    fn record(&mut self, fields: Fields) {
+        for (name, value) in fields.0 {
+            match (name.as_str(), value) {
+                ("otel.name", FieldValue::Text(text)) => self.name = text,
+                ("otel.kind", FieldValue::Text(text)) => {
+                    self.kind = match text.as_str() {
+                        "server" => SPAN_KIND_SERVER,
+                        "client" => SPAN_KIND_CLIENT,
+                        _ => SPAN_KIND_INTERNAL,
+                    }
+                }
+                ("otel.status_code", FieldValue::Text(text)) if text == "error" => {
+                    self.error.get_or_insert(String::new());
+                }
+                (name, value) => self.attributes.push(attribute(name, value)),
+            }
+        }
+    }
+}
+
+/// Exports the spans of electrack, of which the target starts with its name, over OTLP/HTTP.
+/// SQL queries, which sqlx logs as events, are exported as spans of their own.
+struct OtlpLayer {
+    sender: mpsc::Sender<Value>,
+    sampling_ratio: f64,
+}
+
+impl OtlpLayer {
+    fn new(configuration: OtlpConfiguration) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let sampling_ratio = configuration.sampling_ratio;
+
+        tokio::spawn(export_spans(configuration, receiver));
+
+        Self {
+            sender,
+            sampling_ratio,
+        }
+    }
+
+    /// Queue a finished span for export, dropping it when the queue is full
+    fn export(&self, context: &TraceContext, span: RecordedSpan, end: SystemTime) {
+        let mut exported = json!({
+            "traceId": hex::encode(context.trace_id),
+            "spanId": hex::encode(context.span_id),
+            "name": span.name,
+            "kind": span.kind,
+            "startTimeUnixNano": unix_nanos(span.start),
+            "endTimeUnixNano": unix_nanos(end),
+            "attributes": span.attributes,
+            "events": span.events,
+        });
+
+        if let Some(parent_span_id) = span.parent_span_id {
+            exported["parentSpanId"] = json!(hex::encode(parent_span_id));
+        }
+
+        if let Some(message) = span.error {
+            exported["status"] = json!({ "code": STATUS_CODE_ERROR, "message": message });
+        }
+
+        let _ = self.sender.try_send(exported);
+    }
+
+    /// Export a query that sqlx logged as a span of its own, which ended when it was logged
+    fn export_query(&self, parent: &TraceContext, fields: Fields, end: SystemTime) {
+        let mut name = "query".to_string();
+        let mut statement = String::new();
+        let mut elapsed = Duration::ZERO;
+        let mut attributes = vec![attribute(
+            "db.system",
+            FieldValue::Text("postgresql".to_string()),
+        )];
+
+        for (field, value) in fields.0 {
+            match (field.as_str(), value) {
+                ("summary", FieldValue::Text(summary)) => name = summary,
+                ("db.statement", FieldValue::Text(text)) => statement = text.trim().to_string(),
+                ("elapsed_secs", FieldValue::Float(secs)) => {
+                    elapsed = Duration::try_from_secs_f64(secs).unwrap_or_default()
+                }
+                ("rows_affected" | "rows_returned", value) => {
+                    attributes.push(attribute(&format!("db.{}", field), value))
+                }
+                _ => {}
+            }
+        }
+
+        // sqlx leaves out the statement when the summary is all of it
+        if statement.is_empty() {
+            statement = name.clone();
+        }
+
+        attributes.push(attribute("db.statement", FieldValue::Text(statement)));
+
+        let context = TraceContext {
+            span_id: rand::random(),
+            ..*parent
+        };
+
+        self.export(
+            &context,
+            RecordedSpan {
+                parent_span_id: Some(parent.span_id),
+                name,
+                kind: SPAN_KIND_CLIENT,
+                start: end.checked_sub(elapsed).unwrap_or(end),
+                attributes,
+                events: Vec::new(),
+                error: None,
+            },
+            end,
+        );
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<TraceContext>().copied());
+
+        if !span.metadata().target().starts_with(APP_NAME) {
+            if let Some(parent) = parent {
+                span.extensions_mut().insert(parent);
+            }
+            return;
+        }
+
+        let context = TraceContext {
+            trace_id: parent
+                .map(|parent| parent.trace_id)
+                .unwrap_or_else(rand::random),
+            span_id: rand::random(),
+            sampled: parent
+                .map(|parent| parent.sampled)
+                .unwrap_or_else(|| rand::thread_rng().gen::<f64>() < self.sampling_ratio),
+        };
+
+        let mut extensions = span.extensions_mut();
+        extensions.insert(context);
+
+        if !context.sampled {
+            return;
+        }
+
+        let mut recorded = RecordedSpan {
+            parent_span_id: parent.map(|parent| parent.span_id),
+            name: span.name().to_string(),
+            kind: SPAN_KIND_INTERNAL,
+            start: SystemTime::now(),
+            attributes: Vec::new(),
+            events: Vec::new(),
+            error: None,
+        };
+
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        recorded.record(fields);
+
+        extensions.insert(recorded);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut extensions = span.extensions_mut();
+
+        if let Some(recorded) = extensions.get_mut::<RecordedSpan>() {
+            let mut fields = Fields::default();
+            values.record(&mut fields);
+            recorded.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+
+        let Some(context) = span.extensions().get::<TraceContext>().copied() else {
+            return;
+        };
+
+        if !context.sampled {
+            return;
+        }
+
+        let metadata = event.metadata();
+        let now = SystemTime::now();
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        if metadata.target() == "sqlx::query" {
+            self.export_query(&context, fields, now);
+            return;
+        }
+
+        if !metadata.target().starts_with(APP_NAME) || *metadata.level() > Level::INFO {
+            return;
+        }
+
+        // the event belongs to the closest span that is exported
+        let Some(owner) = span
+            .scope()
+            .find(|span| span.extensions().get::<RecordedSpan>().is_some())
+        else {
+            return;
+        };
+
+        let mut extensions = owner.extensions_mut();
+        let Some(recorded) = extensions.get_mut::<RecordedSpan>() else {
+            return;
+        };
+
+        let message = fields.take_message().unwrap_or_default();
+
+        if *metadata.level() == Level::ERROR {
+            recorded.error = Some(message.clone());
+        }
+
+        if recorded.events.len() < MAX_EVENTS {
+            let mut attributes = vec![attribute(
+                "level",
+                FieldValue::Text(metadata.level().to_string()),
+            )];
+            attributes.extend(
+                fields
+                    .0
+                    .into_iter()
+                    .map(|(name, value)| attribute(&name, value)),
+            );
+
+            recorded.events.push(json!({
+                "timeUnixNano": unix_nanos(now),
+                "name": message,
+                "attributes": attributes,
+            }));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+
+        let mut extensions = span.extensions_mut();
+
+        if let (Some(context), Some(recorded)) = (
+            extensions.get_mut::<TraceContext>().copied(),
+            extensions.remove::<RecordedSpan>(),
+        ) {
+            self.export(&context, recorded, SystemTime::now());
+        }
+    }
+}
+
+/// Send the spans in batches, every few seconds or once enough finished
+async fn export_spans(configuration: OtlpConfiguration, mut receiver: mpsc::Receiver<Value>) {
+    let mut batch = Vec::new();
+    let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            span = receiver.recv() => match span {
+                Some(span) => {
+                    batch.push(span);
+
+                    if batch.len() < MAX_BATCH_SIZE {
+                        continue;
+                    }
+                }
+                None => return,
+            },
+            _ = interval.tick() => {}
+        }
+
+        if batch.is_empty() {
+            continue;
+        }
+
+        let spans = std::mem::take(&mut batch);
+        let count = spans.len();
+
+        if let Err(e) = post_spans(&configuration, spans).await {
+            warn!("unable to export {} spans, {}", count, e);
+        }
+    }
+}
+
+async fn post_spans(configuration: &OtlpConfiguration, spans: Vec<Value>) -> Result<(), String> {
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    attribute("service.name", FieldValue::Text(configuration.service_name.clone())),
+                    attribute("service.version", FieldValue::Text(env!("CARGO_PKG_VERSION").to_string())),
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": APP_NAME },
+                "spans": spans,
+            }],
+        }],
+    });
+
+    let response = Client::new()
+        .post(configuration.traces_url.clone())
+        .timeout(TIMEOUT)
+        .headers(configuration.headers.clone())
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = response.status();
+
+    match status.is_success() {
+        true => Ok(()),
+        false => Err(format!(
+            "the receiver responded {}, {}",
+            status,
+            response.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FieldValue {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+/// The fields of a span or event, in the order they were recorded
+#[derive(Debug, Default)]
+struct Fields(Vec<(String, FieldValue)>);
+
+impl Fields {
+    fn take_message(&mut self) -> Option<String> {
+        let index = self.0.iter().position(|(name, _)| name == "message")?;
+
+        match self.0.remove(index).1 {
+            FieldValue::Text(message) => Some(message),
+            _ => None,
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0
+            .push((field.name().to_string(), FieldValue::Float(value)));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0
+            .push((field.name().to_string(), FieldValue::Integer(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        let value = i64::try_from(value)
+            .map(FieldValue::Integer)
+            .unwrap_or(FieldValue::Text(value.to_string()));
+        self.0.push((field.name().to_string(), value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0
+            .push((field.name().to_string(), FieldValue::Boolean(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((
+            field.name().to_string(),
+            FieldValue::Text(value.to_string()),
+        ));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((
+            field.name().to_string(),
+            FieldValue::Text(format!("{:?}", value)),
+        ));
+    }
+}
+
+/// An attribute as OTLP encodes it in JSON, where 64-bit integers are strings
+fn attribute(name: &str, value: FieldValue) -> Value {
+    let value = match value {
+        FieldValue::Text(text) => json!({ "stringValue": text }),
+        FieldValue::Integer(integer) => json!({ "intValue": integer.to_string() }),
+        FieldValue::Float(float) => json!({ "doubleValue": float }),
+        FieldValue::Boolean(boolean) => json!({ "boolValue": boolean }),
+    };
+
+    json!({ "key": name, "value": value })
+}
+
+fn unix_nanos(moment: SystemTime) -> String {
+    moment
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let headers =
+            parse_headers("Authorization=Basic%20ZWxlY3RyYWNr, X-Scope-OrgID=home").unwrap();
+
+        assert_eq!(headers["authorization"], "Basic ZWxlY3RyYWNr");
+        assert_eq!(headers["x-scope-orgid"], "home");
+        assert!(parse_headers("").unwrap().is_empty());
+        assert!(parse_headers("Authorization").is_err());
+        assert!(parse_headers("Bad Name=value").is_err());
+    }
+}
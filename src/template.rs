@@ -0,0 +1,286 @@
+use std::{collections::HashMap, error::Error as _, fmt::Write};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, NaiveDate,
+};
+use tera::{Context, Tera};
+use thiserror::Error;
+
+/// The name the template is known by to Tera, which holds only this one
+const NAME: &str = "template";
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum TemplateError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("unknown field \"{0}\", expected one of {1}")]
+    UnknownField(String, String),
+}
+
+impl From<tera::Error> for TemplateError {
+    fn from(error: tera::Error) -> Self {
+        TemplateError::Invalid(error_message(&error))
+    }
+}
+
+/// What a field is replaced with
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Text(String),
+    /// Formatted with the `decimals` filter
+    Number(f64),
+    /// Formatted with the `date` filter, `%H:%M` by default
+    Moment(DateTime<FixedOffset>),
+    /// Formatted with the `date` filter, `%Y-%m-%d` by default
+    Date(NaiveDate),
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+/// The kind of value of a field a template can use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Field {
+    Text,
+    Number,
+    Moment,
+    Date,
+}
+
+impl Field {
+    /// A value of the kind, to check a template with
+    fn sample(&self) -> Value {
+        match self {
+            Field::Text => Value::Text(String::new()),
+            Field::Number => Value::Number(0.0),
+            Field::Moment => Value::Moment(DateTime::UNIX_EPOCH.fixed_offset()),
+            Field::Date => Value::Date(NaiveDate::default()),
+        }
+    }
+}
+
+/// A [Tera](https://keats.github.io/tera/docs/#templates) template, in which fields are written
+/// as `{{ price }}`. Numbers take a fixed number of decimals with `{{ price | decimals(n=2) }}`,
+/// after a comma with `separator=","`, and moments and dates a strftime format with
+/// `{{ starts_at | date(format="%H:%M") }}`.
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    tera: Tera,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut tera = Tera::default();
+        tera.register_filter("decimals", decimals);
+        tera.register_filter("date", date);
+        tera.add_raw_template(NAME, template)?;
+
+        Ok(Self { tera })
+    }
+
+    /// Render the template with a value of every field, which fails on a field the template
+    /// uses that is not one of them or on a filter that does not take its value
+    pub(crate) fn check_fields(&self, fields: &[(&str, Field)]) -> Result<(), TemplateError> {
+        let values = fields
+            .iter()
+            .map(|(name, field)| (*name, field.sample()))
+            .collect::<Vec<(&str, Value)>>();
+
+        match self.render(&values) {
+            Err(TemplateError::Invalid(message)) => match missing_field(&message) {
+                Some(name) => Err(TemplateError::UnknownField(
+                    name,
+                    fields
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<&str>>()
+                        .join(", "),
+                )),
+                None => Err(TemplateError::Invalid(message)),
+            },
+            result => result.map(|_| ()),
+        }
+    }
+
+    pub(crate) fn render(&self, values: &[(&str, Value)]) -> Result<String, TemplateError> {
+        let mut context = Context::new();
+        for (field, value) in values {
+            match value {
+                Value::Text(text) => context.insert(*field, text),
+                // whole numbers such as hours are written without decimals
+                Value::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                    context.insert(*field, &(*number as i64))
+                }
+                Value::Number(number) => context.insert(*field, number),
+                Value::Moment(moment) => context.insert(*field, &moment.to_rfc3339()),
+                Value::Date(date) => context.insert(*field, &date.to_string()),
+            }
+        }
+
+        Ok(self.tera.render(NAME, &context)?)
+    }
+}
+
+/// The message of an error with the errors that caused it, as Tera tells where a template fails
+/// in the error it wraps
+fn error_message(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+
+    message
+}
+
+/// The field a template failed to render without, of the message of the error
+fn missing_field(message: &str) -> Option<String> {
+    let (_, after) = message.split_once("Variable `")?;
+    let (field, _) = after.split_once("` not found in context")?;
+
+    Some(field.to_string())
+}
+
+/// `decimals(n=2, separator=",")` formats a number with a fixed number of decimals, after a point
+/// unless another separator is given. Values other than numbers are kept.
+fn decimals(
+    value: &tera::Value,
+    arguments: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let decimals = arguments
+        .get("n")
+        .and_then(tera::Value::as_u64)
+        .filter(|decimals| *decimals <= 10)
+        .ok_or_else(|| tera::Error::msg("decimals takes a number of decimals n up to 10"))?;
+
+    let separator = match arguments.get("separator") {
+        Some(separator) => separator
+            .as_str()
+            .ok_or_else(|| tera::Error::msg("the separator of decimals is a text"))?,
+        None => ".",
+    };
+
+    Ok(match value.as_f64() {
+        Some(number) => format!("{:.*}", decimals as usize, number)
+            .replace('.', separator)
+            .into(),
+        None => value.clone(),
+    })
+}
+
+/// `date(format="%H:%M")` formats a moment or a date with a strftime format. Values other than
+/// moments and dates are kept.
+fn date(
+    value: &tera::Value,
+    arguments: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let format = match arguments.get("format") {
+        Some(format) => Some(
+            format
+                .as_str()
+                .filter(|format| is_date_format(format))
+                .ok_or_else(|| tera::Error::msg("the format of date is a strftime format"))?,
+        ),
+        None => None,
+    };
+
+    let Some(text) = value.as_str() else {
+        return Ok(value.clone());
+    };
+
+    if let Ok(moment) = DateTime::parse_from_rfc3339(text) {
+        return Ok(moment.format(format.unwrap_or("%H:%M")).to_string().into());
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let mut formatted = String::new();
+
+        // a date has no time or offset, so a format with those fails
+        return Ok(match format {
+            Some(format) if write!(formatted, "{}", date.format(format)).is_ok() => formatted,
+            _ => date.format("%Y-%m-%d").to_string(),
+        }
+        .into());
+    }
+
+    Ok(value.clone())
+}
+
+/// Whether a format is a strftime format, which would fail to format otherwise
+fn is_date_format(format: &str) -> bool {
+    format.contains('%') && !StrftimeItems::new(format).any(|item| item == Item::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(
+            "{{ device }} om {{ starts_at | date(format=\"%H.%M\") }}: \
+             {{ price | decimals(n=2, separator=\",\") }} {{ currency }} {{ price }}",
+        )
+        .unwrap();
+        let starts_at = DateTime::parse_from_rfc3339("2024-06-30T02:00:00+02:00").unwrap();
+
+        assert_eq!(
+            template.render(&[
+                ("device", "Vaatwasser".into()),
+                ("starts_at", Value::Moment(starts_at)),
+                ("price", Value::Number(0.1234)),
+                ("currency", "€".into()),
+            ]),
+            Ok("Vaatwasser om 02.00: 0,12 € 0.1234".to_string())
+        );
+        assert_eq!(
+            Template::parse("{{ date | date(format=\"%d-%m %H:%M\") }} {{ date | date }}")
+                .unwrap()
+                .render(&[(
+                    "date",
+                    Value::Date(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap())
+                )]),
+            Ok("2024-06-30 2024-06-30".to_string())
+        );
+        assert!(Template::parse("{{ price }}").unwrap().render(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("price {{ price").is_err());
+        assert!(Template::parse("{{ price | decimals(n=2) }}")
+            .unwrap()
+            .render(&[("price", Value::Number(0.1))])
+            .is_ok());
+        assert!(matches!(
+            Template::parse("{{ price | decimals }}")
+                .unwrap()
+                .check_fields(&[("price", Field::Number)]),
+            Err(TemplateError::Invalid(_))
+        ));
+        assert!(Template::parse("{{ starts_at | date(format=\"%Q\") }}")
+            .unwrap()
+            .check_fields(&[("starts_at", Field::Moment)])
+            .is_err());
+        assert!(Template::parse("{{ price * 100 }} {{ level | upper }}")
+            .unwrap()
+            .check_fields(&[("price", Field::Number), ("level", Field::Text)])
+            .is_ok());
+        assert_eq!(
+            Template::parse("{{ price }}")
+                .unwrap()
+                .check_fields(&[("level", Field::Text)]),
+            Err(TemplateError::UnknownField(
+                "price".to_string(),
+                "level".to_string()
+            ))
+        );
+    }
+}
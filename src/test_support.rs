@@ -0,0 +1,27 @@
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::domain::{PricePoint, PriceUnit};
+
+/// The moment of an RFC 3339 time
+pub(crate) fn moment(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+}
+
+/// An hourly price per kWh in EUR, without a consumer price, its components or an area
+pub(crate) fn price_point(moment: DateTime<Utc>, amount: f64) -> PricePoint {
+    PricePoint {
+        moment,
+        monetary_amount: amount,
+        currency: "EUR".to_string(),
+        consumer_amount: None,
+        components: None,
+        unit: PriceUnit::KilowattHour,
+        resolution_minutes: 60,
+        area: None,
+    }
+}
+
+/// The moment of an RFC 3339 time in the offset it is written in
+pub(crate) fn local_moment(time: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(time).unwrap()
+}
@@ -1,22 +1,79 @@
+use std::collections::BTreeMap;
+
 use axum::async_trait;
 use chrono::DateTime;
 use chrono::Utc;
 use log::info;
 use reqwest::Client;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
 
+use crate::domain::Consumption;
 use crate::domain::ElectricityPriceProvider;
 use crate::domain::ElectricityProviderError;
 use crate::domain::PricePoint;
+use crate::domain::PriceUnit;
+
+const API_URL: &str = "https://api.tibber.com/v1-beta/gql";
 
 #[derive(Clone, Debug)]
 pub(crate) struct Tibber {
     api_key: String,
+    /// The bidding zone of the home, which Tibber does not tell
+    area: Option<String>,
+}
+
+/// The sync of the consumption measured by Tibber into the consumption table
+#[derive(Clone, Debug)]
+pub(crate) struct TibberConsumptionSync {
+    pub(crate) tibber: Tibber,
+    /// How many past days are synced every time, which catches up on gaps
+    pub(crate) days: usize,
 }
 
 impl Tibber {
     pub(crate) fn new(api_key: String) -> Self {
-        Self { api_key }
+        Self {
+            api_key,
+            area: None,
+        }
+    }
+
+    pub(crate) fn with_area(self, area: Option<String>) -> Self {
+        Self { area, ..self }
+    }
+
+    /// Fetch the hourly consumption and production of the last hours as measured by Tibber,
+    /// leaving out the hours that are not measured yet
+    pub(crate) async fn fetch_consumption(&self, hours: usize) -> Result<Vec<Consumption>, String> {
+        info!(
+            "Fetching the consumption of the last {} hours from tibber",
+            hours
+        );
+
+        let query = format!(
+            "{{ viewer {{ homes {{ consumption(resolution: HOURLY, last: {hours}) {{ nodes {{ from to consumption }} }} production(resolution: HOURLY, last: {hours}) {{ nodes {{ from to production }} }} }} }} }}"
+        );
+
+        let response = Client::new()
+            .post(API_URL)
+            .header("Authorization", &self.api_key)
+            .json(&json!({ "query": query }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+
+        let body = response.text().await.map_err(|e| e.to_string())?;
+
+        let consumption = parse_consumption_json(&body)?;
+
+        info!(
+            "Fetched {} hours of consumption from tibber",
+            consumption.len()
+        );
+
+        Ok(consumption)
     }
 }
 
@@ -26,56 +83,162 @@ impl ElectricityPriceProvider for Tibber {
         "tibber"
     }
 
+    fn area(&self) -> Option<&str> {
+        self.area.as_deref()
+    }
+
     async fn fetch_prices(&self) -> Result<Vec<PricePoint>, ElectricityProviderError> {
         get_prices(&self.api_key)
             .await
-            .map_err(|e| ElectricityProviderError::FetchPrices(e.to_string()))
+            .map_err(ElectricityProviderError::FetchPrices)
             .map(|prices| {
                 prices
                     .into_iter()
-                    .map(PricePoint::from)
+                    .map(|price| PricePoint {
+                        area: self.area.clone(),
+                        ..PricePoint::from(price)
+                    })
                     .collect::<Vec<PricePoint>>()
             })
     }
 }
 
-async fn get_prices(api_key: &str) -> reqwest::Result<Vec<TibberPricePoint>> {
+async fn get_prices(api_key: &str) -> Result<Vec<TibberPricePoint>, String> {
     info!("Fetching prices from tibber");
 
-    let query = r#"{ "query": "{ viewer { homes { currentSubscription { priceInfo { today { total startsAt } }}}}}" }"#;
+    let query = r#"{ "query": "{ viewer { homes { currentSubscription { priceInfo { today { total startsAt currency } tomorrow { total startsAt currency } }}}}}" }"#;
 
     let client = Client::new();
 
     let response = client
-        .post("https://api.tibber.com/v1-beta/gql")
+        .post(API_URL)
         .header("Authorization", api_key)
         .header("Content-Type", "application/json")
         .body(query)
         .send()
-        .await?;
+        .await
+        .map_err(|e| e.to_string())?;
 
-    let body = response.text().await?;
+    let body = response.text().await.map_err(|e| e.to_string())?;
 
-    let prices = parse_prices_json(&body);
+    let prices = parse_prices_json(&body)?;
 
     info!("Fetched {} prices from tibber", prices.len());
 
     Ok(prices)
 }
 
-fn parse_prices_json(json: &str) -> Vec<TibberPricePoint> {
-    let data = serde_json::from_str::<Response>(json).expect("Failed to parse tibber's response");
+/// The prices of today and, once published, tomorrow of the first home
+fn parse_prices_json(json: &str) -> Result<Vec<TibberPricePoint>, String> {
+    let response = serde_json::from_str::<Response>(json)
+        .map_err(|e| format!("unable to parse the prices of tibber, {}", e))?;
+
+    let home = response
+        .data
+        .and_then(|data| data.viewer.homes.into_iter().next())
+        .ok_or(match response.errors.first() {
+            Some(error) => format!("tibber returned an error, {}", error.message),
+            None => "tibber has no home".to_string(),
+        })?;
 
-    return data.data.viewer.homes[0]
-        .current_subscription
-        .price_info
+    let price_info = home.current_subscription.price_info;
+
+    Ok(price_info
         .today
-        .clone();
+        .into_iter()
+        .chain(price_info.tomorrow)
+        .collect())
+}
+
+/// The consumption of every hour with the production of that hour as the exported energy
+fn parse_consumption_json(json: &str) -> Result<Vec<Consumption>, String> {
+    let response = serde_json::from_str::<ConsumptionResponse>(json)
+        .map_err(|e| format!("unable to parse the consumption of tibber, {}", e))?;
+
+    let home = response
+        .data
+        .and_then(|data| data.viewer.homes.into_iter().next())
+        .ok_or(match response.errors.first() {
+            Some(error) => format!("tibber returned an error, {}", error.message),
+            None => "tibber has no home".to_string(),
+        })?;
+
+    let production = home
+        .production
+        .map(|production| production.nodes)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|node| Some((node.from, node.production?)))
+        .collect::<BTreeMap<DateTime<Utc>, f64>>();
+
+    Ok(home
+        .consumption
+        .map(|consumption| consumption.nodes)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|node| {
+            Some(Consumption {
+                starts_at: node.from,
+                ends_at: node.to,
+                energy_kwh: node.consumption?,
+                exported_kwh: production.get(&node.from).copied().unwrap_or(0.0),
+            })
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+struct ConsumptionResponse {
+    data: Option<ConsumptionData>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConsumptionData {
+    viewer: ConsumptionViewer,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConsumptionViewer {
+    homes: Vec<ConsumptionHome>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConsumptionHome {
+    consumption: Option<Connection<ConsumptionNode>>,
+    production: Option<Connection<ProductionNode>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Connection<Node> {
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConsumptionNode {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    /// Absent for hours that are not measured yet
+    consumption: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductionNode {
+    from: DateTime<Utc>,
+    production: Option<f64>,
 }
 
 #[derive(Deserialize, Debug)]
 struct Response {
-    data: Data,
+    data: Option<Data>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -103,6 +266,9 @@ struct CurrentSubscription {
 #[derive(Deserialize, Debug)]
 struct PriceInfo {
     today: Vec<TibberPricePoint>,
+    /// Empty until the prices of tomorrow are published, around 13:00 CET
+    #[serde(default)]
+    tomorrow: Vec<TibberPricePoint>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -110,6 +276,7 @@ struct TibberPricePoint {
     total: f64,
     #[serde(rename = "startsAt")]
     starts_at: DateTime<Utc>,
+    currency: String,
 }
 
 impl From<TibberPricePoint> for PricePoint {
@@ -117,6 +284,13 @@ impl From<TibberPricePoint> for PricePoint {
         PricePoint {
             moment: value.starts_at.with_timezone(&Utc),
             monetary_amount: value.total,
+            currency: value.currency,
+            consumer_amount: None,
+            components: None,
+            // the price info of Tibber is hourly unless asked for quarter hours
+            unit: PriceUnit::KilowattHour,
+            resolution_minutes: 60,
+            area: None,
         }
     }
 }
@@ -128,13 +302,14 @@ mod tests {
     #[test]
     fn test_parse_prices_json() {
         let json = r#"
-            {"data":{"viewer":{"homes":[{"currentSubscription":{"priceInfo":{"today":[{"total":0.2821,"startsAt":"2024-06-15T00:00:00.000+02:00"},{"total":0.2787,"startsAt":"2024-06-15T01:00:00.000+02:00"},{"total":0.2666,"startsAt":"2024-06-15T02:00:00.000+02:00"},{"total":0.2581,"startsAt":"2024-06-15T03:00:00.000+02:00"},{"total":0.2213,"startsAt":"2024-06-15T04:00:00.000+02:00"},{"total":0.1769,"startsAt":"2024-06-15T05:00:00.000+02:00"},{"total":0.1547,"startsAt":"2024-06-15T06:00:00.000+02:00"},{"total":0.1529,"startsAt":"2024-06-15T07:00:00.000+02:00"},{"total":0.1528,"startsAt":"2024-06-15T08:00:00.000+02:00"},{"total":0.1528,"startsAt":"2024-06-15T09:00:00.000+02:00"},{"total":0.1406,"startsAt":"2024-06-15T10:00:00.000+02:00"},{"total":0.1177,"startsAt":"2024-06-15T11:00:00.000+02:00"},{"total":0.0985,"startsAt":"2024-06-15T12:00:00.000+02:00"},{"total":0.0736,"startsAt":"2024-06-15T13:00:00.000+02:00"},{"total":0.056,"startsAt":"2024-06-15T14:00:00.000+02:00"},{"total":0.0849,"startsAt":"2024-06-15T15:00:00.000+02:00"},{"total":0.1175,"startsAt":"2024-06-15T16:00:00.000+02:00"},{"total":0.1474,"startsAt":"2024-06-15T17:00:00.000+02:00"},{"total":0.1528,"startsAt":"2024-06-15T18:00:00.000+02:00"},{"total":0.1917,"startsAt":"2024-06-15T19:00:00.000+02:00"},{"total":0.2375,"startsAt":"2024-06-15T20:00:00.000+02:00"},{"total":0.2348,"startsAt":"2024-06-15T21:00:00.000+02:00"},{"total":0.2294,"startsAt":"2024-06-15T22:00:00.000+02:00"},{"total":0.2021,"startsAt":"2024-06-15T23:00:00.000+02:00"}]}}}]}}}
+            {"data":{"viewer":{"homes":[{"currentSubscription":{"priceInfo":{"today":[{"total":0.2821,"currency":"EUR","startsAt":"2024-06-15T00:00:00.000+02:00"},{"total":0.2787,"currency":"EUR","startsAt":"2024-06-15T01:00:00.000+02:00"},{"total":0.2666,"currency":"EUR","startsAt":"2024-06-15T02:00:00.000+02:00"},{"total":0.2581,"currency":"EUR","startsAt":"2024-06-15T03:00:00.000+02:00"},{"total":0.2213,"currency":"EUR","startsAt":"2024-06-15T04:00:00.000+02:00"},{"total":0.1769,"currency":"EUR","startsAt":"2024-06-15T05:00:00.000+02:00"},{"total":0.1547,"currency":"EUR","startsAt":"2024-06-15T06:00:00.000+02:00"},{"total":0.1529,"currency":"EUR","startsAt":"2024-06-15T07:00:00.000+02:00"},{"total":0.1528,"currency":"EUR","startsAt":"2024-06-15T08:00:00.000+02:00"},{"total":0.1528,"currency":"EUR","startsAt":"2024-06-15T09:00:00.000+02:00"},{"total":0.1406,"currency":"EUR","startsAt":"2024-06-15T10:00:00.000+02:00"},{"total":0.1177,"currency":"EUR","startsAt":"2024-06-15T11:00:00.000+02:00"},{"total":0.0985,"currency":"EUR","startsAt":"2024-06-15T12:00:00.000+02:00"},{"total":0.0736,"currency":"EUR","startsAt":"2024-06-15T13:00:00.000+02:00"},{"total":0.056,"currency":"EUR","startsAt":"2024-06-15T14:00:00.000+02:00"},{"total":0.0849,"currency":"EUR","startsAt":"2024-06-15T15:00:00.000+02:00"},{"total":0.1175,"currency":"EUR","startsAt":"2024-06-15T16:00:00.000+02:00"},{"total":0.1474,"currency":"EUR","startsAt":"2024-06-15T17:00:00.000+02:00"},{"total":0.1528,"currency":"EUR","startsAt":"2024-06-15T18:00:00.000+02:00"},{"total":0.1917,"currency":"EUR","startsAt":"2024-06-15T19:00:00.000+02:00"},{"total":0.2375,"currency":"EUR","startsAt":"2024-06-15T20:00:00.000+02:00"},{"total":0.2348,"currency":"EUR","startsAt":"2024-06-15T21:00:00.000+02:00"},{"total":0.2294,"currency":"EUR","startsAt":"2024-06-15T22:00:00.000+02:00"},{"total":0.2021,"currency":"EUR","startsAt":"2024-06-15T23:00:00.000+02:00"}]}}}]}}}
             "#;
 
-        let prices = parse_prices_json(json);
+        let prices = parse_prices_json(json).unwrap();
 
         assert_eq!(prices.len(), 24);
         assert_eq!(prices[0].total, 0.2821);
+        assert_eq!(prices[0].currency, "EUR");
         assert_eq!(
             prices[0].starts_at,
             DateTime::parse_from_rfc3339("2024-06-14T22:00:00.000+00:00").unwrap()
@@ -146,4 +321,52 @@ mod tests {
             DateTime::parse_from_rfc3339("2024-06-15T21:00:00.000+00:00").unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_consumption_json() {
+        let json = r#"
+            {"data":{"viewer":{"homes":[{"consumption":{"nodes":[{"from":"2024-06-15T10:00:00.000+02:00","to":"2024-06-15T11:00:00.000+02:00","consumption":0.412},{"from":"2024-06-15T11:00:00.000+02:00","to":"2024-06-15T12:00:00.000+02:00","consumption":null}]},"production":{"nodes":[{"from":"2024-06-15T10:00:00.000+02:00","to":"2024-06-15T11:00:00.000+02:00","production":1.2}]}}]}}}
+            "#;
+
+        let consumption = parse_consumption_json(json).unwrap();
+
+        assert_eq!(consumption.len(), 1);
+        assert_eq!(
+            consumption[0].starts_at,
+            DateTime::parse_from_rfc3339("2024-06-15T08:00:00Z").unwrap()
+        );
+        assert_eq!(consumption[0].energy_kwh, 0.412);
+        assert_eq!(consumption[0].exported_kwh, 1.2);
+
+        assert_eq!(
+            parse_consumption_json(r#"{"data":null,"errors":[{"message":"invalid token"}]}"#),
+            Err("tibber returned an error, invalid token".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_prices_json_with_tomorrow() {
+        let json = r#"
+            {"data":{"viewer":{"homes":[{"currentSubscription":{"priceInfo":{"today":[{"total":0.2021,"currency":"EUR","startsAt":"2024-06-15T23:00:00.000+02:00"}],"tomorrow":[{"total":0.1917,"currency":"EUR","startsAt":"2024-06-16T00:00:00.000+02:00"}]}}}]}}}
+            "#;
+
+        let prices = parse_prices_json(json).unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[1].total, 0.1917);
+    }
+
+    #[test]
+    fn test_parse_prices_json_without_home() {
+        assert_eq!(
+            parse_prices_json(r#"{"data":{"viewer":{"homes":[]}}}"#).unwrap_err(),
+            "tibber has no home"
+        );
+        assert_eq!(
+            parse_prices_json(r#"{"data":null,"errors":[{"message":"invalid token"}]}"#)
+                .unwrap_err(),
+            "tibber returned an error, invalid token"
+        );
+        assert!(parse_prices_json("not json").is_err());
+    }
 }
@@ -0,0 +1,140 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{crypto::ring::default_provider, ServerConfig},
+    TlsAcceptor,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::http::serve_connection;
+
+/// How often the certificate and key are checked for a renewal
+const RELOAD_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The PEM files of the certificate, with its chain, and the private key to serve HTTPS with
+#[derive(Clone, Debug)]
+pub(crate) struct TlsConfiguration {
+    pub(crate) certificate_path: PathBuf,
+    pub(crate) key_path: PathBuf,
+}
+
+impl TlsConfiguration {
+    fn load(&self) -> Result<Arc<ServerConfig>, String> {
+        let open = |path: &PathBuf| {
+            File::open(path)
+                .map(BufReader::new)
+                .map_err(|e| format!("unable to read {}, {}", path.display(), e))
+        };
+
+        let certificates = rustls_pemfile::certs(&mut open(&self.certificate_path)?)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("unable to parse the certificate, {}", e))?;
+
+        if certificates.is_empty() {
+            return Err(format!(
+                "{} holds no certificate",
+                self.certificate_path.display()
+            ));
+        }
+
+        let key = rustls_pemfile::private_key(&mut open(&self.key_path)?)
+            .map_err(|e| format!("unable to parse the private key, {}", e))?
+            .ok_or(format!("{} holds no private key", self.key_path.display()))?;
+
+        let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .map_err(|e| format!("the certificate does not match the private key, {}", e))?;
+
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+
+    /// When the certificate and key were last modified, which changes when they are renewed
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|file| file.modified());
+
+        Some((
+            modified(&self.certificate_path).ok()?,
+            modified(&self.key_path).ok()?,
+        ))
+    }
+}
+
+/// Serve the router over HTTPS, picking up a renewed certificate without restarting
+pub(crate) async fn serve_tls(
+    listener: TcpListener,
+    router: Router,
+    tls: TlsConfiguration,
+) -> io::Result<()> {
+    let config = Arc::new(RwLock::new(
+        tls.load()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+    ));
+
+    tokio::spawn(reload_on_renewal(tls, config.clone()));
+
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("unable to accept a connection, {}", e);
+                continue;
+            }
+        };
+
+        let Ok(acceptor) = config
+            .read()
+            .map(|config| TlsAcceptor::from(config.clone()))
+        else {
+            continue;
+        };
+        let router = router.clone();
+
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(stream) => serve_connection(stream, router).await,
+                Err(e) => debug!("the TLS handshake with {} failed, {}", address, e),
+            }
+        });
+    }
+}
+
+/// Load the certificate and key again once they are modified, keeping the previous ones when the
+/// new ones cannot be loaded, for instance while only one of them was written
+async fn reload_on_renewal(tls: TlsConfiguration, config: Arc<RwLock<Arc<ServerConfig>>>) {
+    let mut loaded = tls.modified();
+
+    loop {
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+
+        let modified = tls.modified();
+
+        if modified == loaded {
+            continue;
+        }
+
+        match tls.load() {
+            Ok(reloaded) => {
+                if let Ok(mut config) = config.write() {
+                    *config = reloaded;
+                }
+
+                loaded = modified;
+                info!("reloaded the renewed TLS certificate");
+            }
+            Err(e) => error!("unable to reload the TLS certificate, {}", e),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    fs::Permissions,
+    io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::PathBuf,
+};
+
+use axum::Router;
+use tokio::net::UnixListener;
+use tracing::warn;
+
+use crate::http::serve_connection;
+
+/// The Unix socket to listen on rather than a TCP port, for a reverse proxy on the same host
+#[derive(Clone, Debug)]
+pub(crate) struct UnixSocket {
+    pub(crate) path: PathBuf,
+    /// The permissions of the socket, e.g. `0o660` to let the group of the proxy connect, or the
+    /// ones the umask leaves when absent
+    pub(crate) mode: Option<u32>,
+}
+
+/// Serve the router on a Unix socket, replacing the socket a previous run left behind
+pub(crate) async fn serve_unix_socket(socket: UnixSocket, router: Router) -> io::Result<()> {
+    match std::fs::symlink_metadata(&socket.path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&socket.path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket.path.display()),
+            ))
+        }
+        Err(_) => {}
+    }
+
+    let listener = UnixListener::bind(&socket.path)?;
+
+    if let Some(mode) = socket.mode {
+        std::fs::set_permissions(&socket.path, Permissions::from_mode(mode))?;
+    }
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("unable to accept a connection, {}", e);
+                continue;
+            }
+        };
+
+        tokio::spawn(serve_connection(stream, router.clone()));
+    }
+}
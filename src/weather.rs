@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use log::info;
+use reqwest::Client;
+use serde_derive::Deserialize;
+use sqlx::FromRow;
+
+use crate::forecast_solar::SolarPanels;
+
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+/// Where the weather is forecast, e.g. for the outdoor temperature a heat pump works with
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WeatherLocation {
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+}
+
+impl Display for WeatherLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+/// The forecast weather of the hour that starts at a moment
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub(crate) struct Weather {
+    pub(crate) moment: DateTime<Utc>,
+    /// The outdoor temperature at the start of the hour
+    pub(crate) temperature_celsius: f64,
+    /// The average global horizontal irradiance during the hour in W/m²
+    pub(crate) irradiance: f64,
+    /// The average irradiance on the surface of the solar panels during the hour in W/m², when
+    /// they are configured
+    pub(crate) tilted_irradiance: Option<f64>,
+}
+
+/// Fetch the forecast weather of the hours between two moments from Open-Meteo, which
+/// forecasts up to 16 days ahead and does not require an API key. The irradiance on solar
+/// panels is only fetched when they are given.
+pub(crate) async fn fetch_weather(
+    location: &WeatherLocation,
+    panels: Option<&SolarPanels>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<Weather>, String> {
+    info!("Fetching the weather forecast of {}", location);
+
+    let mut query = vec![
+        ("latitude", location.latitude.to_string()),
+        ("longitude", location.longitude.to_string()),
+        ("timezone", "GMT".to_string()),
+        ("start_date", start.date_naive().to_string()),
+        // the irradiance of the last hour is published at its end
+        (
+            "end_date",
+            (end + TimeDelta::hours(1)).date_naive().to_string(),
+        ),
+    ];
+
+    match panels {
+        Some(panels) => query.extend([
+            (
+                "hourly",
+                "temperature_2m,shortwave_radiation,global_tilted_irradiance".to_string(),
+            ),
+            ("tilt", panels.declination.to_string()),
+            ("azimuth", panels.azimuth.to_string()),
+        ]),
+        None => query.push(("hourly", "temperature_2m,shortwave_radiation".to_string())),
+    }
+
+    let body = Client::new()
+        .get(FORECAST_URL)
+        .query(&query)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(parse_forecast(&body)?
+        .into_iter()
+        .filter(|weather| weather.moment >= start && weather.moment < end)
+        .collect())
+}
+
+/// The weather of every hour of which the temperature and the irradiance are forecast.
+/// Open-Meteo publishes the temperature at the start of an hour, but the average irradiance of
+/// the preceding hour, so the irradiance of an hour is that of the next.
+fn parse_forecast(json: &str) -> Result<Vec<Weather>, String> {
+    let forecast = serde_json::from_str::<Forecast>(json)
+        .map_err(|e| format!("failed to parse the forecast: {}", e))?;
+
+    let hourly = forecast.hourly;
+    let next = |values: &[Option<f64>], index: usize| values.get(index + 1).copied().flatten();
+
+    hourly
+        .time
+        .iter()
+        .enumerate()
+        .filter_map(|(index, time)| {
+            let temperature_celsius = hourly.temperature_2m.get(index).copied().flatten()?;
+            let irradiance = next(&hourly.shortwave_radiation, index)?;
+            let tilted_irradiance = hourly
+                .global_tilted_irradiance
+                .as_ref()
+                .and_then(|values| next(values, index));
+
+            Some(
+                NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+                    .map(|moment| Weather {
+                        moment: moment.and_utc(),
+                        temperature_celsius,
+                        irradiance,
+                        tilted_irradiance,
+                    })
+                    .map_err(|e| format!("failed to parse the forecast: {}", e)),
+            )
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct Forecast {
+    hourly: Hourly,
+}
+
+#[derive(Deserialize, Debug)]
+struct Hourly {
+    time: Vec<String>,
+    temperature_2m: Vec<Option<f64>>,
+    shortwave_radiation: Vec<Option<f64>>,
+    /// Only present when the irradiance on solar panels is requested
+    global_tilted_irradiance: Option<Vec<Option<f64>>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_forecast() {
+        let json = r#"{"latitude":52.38,"longitude":4.9,"hourly_units":{"time":"iso8601","temperature_2m":"°C","shortwave_radiation":"W/m²","global_tilted_irradiance":"W/m²"},"hourly":{"time":["2024-06-30T10:00","2024-06-30T11:00","2024-06-30T12:00","2024-06-30T13:00"],"temperature_2m":[14.2,15.8,null,17.1],"shortwave_radiation":[410.0,520.0,610.0,640.0],"global_tilted_irradiance":[450.0,580.0,690.0,null]}}"#;
+
+        let moment = |hour: &str| {
+            DateTime::parse_from_rfc3339(&format!("2024-06-30T{}:00:00Z", hour))
+                .unwrap()
+                .to_utc()
+        };
+
+        assert_eq!(
+            parse_forecast(json).unwrap(),
+            vec![
+                Weather {
+                    moment: moment("10"),
+                    temperature_celsius: 14.2,
+                    irradiance: 520.0,
+                    tilted_irradiance: Some(580.0),
+                },
+                Weather {
+                    moment: moment("11"),
+                    temperature_celsius: 15.8,
+                    irradiance: 610.0,
+                    tilted_irradiance: Some(690.0),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, QueryBuilder};
+use thiserror::Error;
+use tracing::info;
+
+use crate::weather::Weather;
+
+#[derive(Debug, Clone, Error)]
+pub(crate) enum WeatherRepositoryError {
+    #[error("the weather could not be persisted: {0}")]
+    PersistenceError(String),
+}
+
+#[async_trait]
+pub(crate) trait WeatherRepository: Send + Sync {
+    /// Fetch the forecast weather of the hours between two moments, ordered by moment
+    async fn fetch_weather(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Weather>, String>;
+
+    /// Persist the forecast weather, replacing what was forecast before for the same hours
+    async fn persist_weather(&self, weather: &[Weather]) -> Result<(), WeatherRepositoryError>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresWeatherRepository {
+    db: PgPool,
+}
+
+impl PostgresWeatherRepository {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl WeatherRepository for PostgresWeatherRepository {
+    async fn fetch_weather(
+        &self,
+        start_moment: DateTime<Utc>,
+        end_moment: DateTime<Utc>,
+    ) -> Result<Vec<Weather>, String> {
+        sqlx::query_as::<_, Weather>(
+            r#"
+            select moment, temperature_celsius, irradiance, tilted_irradiance
+            from weather
+            where moment >= $1 and moment < $2
+            order by moment
+            "#,
+        )
+        .bind(start_moment)
+        .bind(end_moment)
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn persist_weather(&self, weather: &[Weather]) -> Result<(), WeatherRepositoryError> {
+        if weather.is_empty() {
+            return Ok(());
+        }
+
+        info!("Persisting the weather of {} hours", weather.len());
+
+        let mut query_builder = QueryBuilder::new(
+            "insert into weather (moment, temperature_celsius, irradiance, tilted_irradiance)",
+        );
+
+        query_builder.push_values(weather, |mut builder, hour| {
+            builder
+                .push_bind(hour.moment)
+                .push_bind(hour.temperature_celsius)
+                .push_bind(hour.irradiance)
+                .push_bind(hour.tilted_irradiance);
+        });
+
+        query_builder.push(
+            r#"
+            on conflict (moment) do update
+            set temperature_celsius = excluded.temperature_celsius,
+                irradiance = excluded.irradiance,
+                tilted_irradiance = excluded.tilted_irradiance
+            "#,
+        );
+
+        query_builder
+            .build()
+            .persistent(false)
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| WeatherRepositoryError::PersistenceError(e.to_string()))
+    }
+}
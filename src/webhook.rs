@@ -0,0 +1,548 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::{types::Json, FromRow};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    domain::{ElectricityPriceProvider, PricePoint},
+    planner::plan_all_devices,
+    price_level::current_price,
+    setup::AppState,
+    webhook_repository::NewDelivery,
+};
+
+/// How long a receiver may take to respond
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The HMAC-SHA256 of the timestamp and body of a delivery, as `sha256=<hex>`
+const SIGNATURE_HEADER: &str = "X-Signature";
+/// When the delivery was signed, in seconds since the Unix epoch
+const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+/// Unique for every delivery and the same for its retries, so receivers can refuse deliveries
+/// they have seen before
+const DELIVERY_ID_HEADER: &str = "X-Delivery-Id";
+
+/// A delivery that keeps failing is given up on after this many attempts, about four hours
+/// after it was dispatched
+const MAX_DELIVERY_ATTEMPTS: i32 = 10;
+/// The delay before the first retry of a delivery, it doubles up to the maximum
+const INITIAL_DELIVERY_RETRY_DELAY: TimeDelta = TimeDelta::minutes(1);
+const MAX_DELIVERY_RETRY_DELAY: TimeDelta = TimeDelta::hours(1);
+/// How many due deliveries are claimed at once
+const DELIVERY_BATCH_SIZE: i64 = 50;
+/// How long deliveries are claimed by the instance attempting them. The deliveries of a batch
+/// are attempted one after another, so the lease lasts as long as every one of them timing out
+/// and a minute more, after which they are claimed again.
+const DELIVERY_LEASE: TimeDelta =
+    TimeDelta::seconds(DELIVERY_TIMEOUT.as_secs() as i64 * DELIVERY_BATCH_SIZE + 60);
+/// How long deliveries that succeeded are kept
+const DELIVERY_RETENTION: TimeDelta = TimeDelta::days(7);
+
+/// How long after a window starts its start is still dispatched, in case a check was missed
+const WINDOW_START_GRACE: TimeDelta = TimeDelta::minutes(5);
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub(crate) enum WebhookError {
+    #[error(
+        "unknown event \"{0}\", expected prices_ingested, window_start or price_above_threshold"
+    )]
+    UnknownEvent(String),
+    #[error("unknown delivery status \"{0}\", expected pending, delivered or failed")]
+    UnknownDeliveryStatus(String),
+}
+
+/// What a webhook is called on
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookEvent {
+    /// Prices were fetched from the provider and stored
+    PricesIngested,
+    /// A planned window of a device started
+    WindowStart,
+    /// The price of the hour that started is above the alert threshold
+    PriceAboveThreshold,
+}
+
+impl WebhookEvent {
+    pub(crate) const ALL: [WebhookEvent; 3] = [
+        WebhookEvent::PricesIngested,
+        WebhookEvent::WindowStart,
+        WebhookEvent::PriceAboveThreshold,
+    ];
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::PricesIngested => "prices_ingested",
+            WebhookEvent::WindowStart => "window_start",
+            WebhookEvent::PriceAboveThreshold => "price_above_threshold",
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = WebhookError;
+
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        match event {
+            "prices_ingested" => Ok(WebhookEvent::PricesIngested),
+            "window_start" => Ok(WebhookEvent::WindowStart),
+            "price_above_threshold" => Ok(WebhookEvent::PriceAboveThreshold),
+            _ => Err(WebhookError::UnknownEvent(event.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for WebhookEvent {
+    type Error = WebhookError;
+
+    fn try_from(event: String) -> Result<Self, Self::Error> {
+        event.parse()
+    }
+}
+
+/// Where a delivery is in its attempts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DeliveryStatus {
+    /// Not attempted yet, or to be retried
+    Pending,
+    Delivered,
+    /// Given up on after too many failed attempts, until it is retried by hand
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = WebhookError;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(WebhookError::UnknownDeliveryStatus(status.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = WebhookError;
+
+    fn try_from(status: String) -> Result<Self, Self::Error> {
+        status.parse()
+    }
+}
+
+/// A URL that is called with the payload of the events it subscribes to
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Webhook {
+    /// Absent for webhooks that are configured rather than registered through the API
+    pub(crate) id: Option<i64>,
+    pub(crate) url: String,
+    pub(crate) events: Vec<WebhookEvent>,
+}
+
+/// The body a webhook is called with
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WebhookPayload {
+    pub(crate) event: WebhookEvent,
+    pub(crate) occurred_at: DateTime<Utc>,
+    pub(crate) data: Value,
+}
+
+/// The payload of an event for a webhook, which is attempted until it is delivered or given up
+/// on
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct WebhookDelivery {
+    pub(crate) id: i64,
+    /// Absent for configured webhooks
+    pub(crate) webhook_id: Option<i64>,
+    pub(crate) url: String,
+    #[sqlx(try_from = "String")]
+    pub(crate) event: WebhookEvent,
+    pub(crate) payload: Json<Value>,
+    #[sqlx(try_from = "String")]
+    pub(crate) status: DeliveryStatus,
+    pub(crate) attempts: i32,
+    pub(crate) next_attempt_at: DateTime<Utc>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) delivered_at: Option<DateTime<Utc>>,
+}
+
+/// A delivery that is due, claimed to be attempted
+#[derive(Debug, Clone, FromRow)]
+pub(crate) struct DueDelivery {
+    pub(crate) id: i64,
+    pub(crate) webhook_id: Option<i64>,
+    pub(crate) url: String,
+    pub(crate) payload: Json<Value>,
+    pub(crate) attempts: i32,
+    /// The secret of the registered webhook, configured webhooks use the configured secret
+    pub(crate) secret: Option<String>,
+}
+
+/// The outcome of calling a webhook once
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub(crate) struct DeliveryAttempt {
+    pub(crate) attempted_at: DateTime<Utc>,
+    /// Absent when the receiver could not be reached
+    pub(crate) status_code: Option<i32>,
+    /// Absent when the attempt succeeded
+    pub(crate) error: Option<String>,
+}
+
+/// The registered webhooks followed by the configured ones, which subscribe to every event
+pub(crate) async fn all_webhooks(state: &AppState) -> Result<Vec<Webhook>, String> {
+    let mut webhooks = state.webhook_repository.fetch_webhooks().await?;
+
+    webhooks.extend(state.webhooks.urls.iter().map(|url| Webhook {
+        id: None,
+        url: url.clone(),
+        events: WebhookEvent::ALL.to_vec(),
+    }));
+
+    Ok(webhooks)
+}
+
+/// Queue a delivery of the payload of an event for every webhook that subscribes to it, and
+/// attempt the deliveries that are due. Deliveries that fail are retried later, so a receiver
+/// that is down for a while still gets the event.
+pub(crate) async fn dispatch(
+    state: &AppState,
+    event: WebhookEvent,
+    data: Value,
+) -> Result<(), String> {
+    let payload = json!(WebhookPayload {
+        event,
+        occurred_at: Utc::now(),
+        data,
+    });
+
+    let deliveries = all_webhooks(state)
+        .await?
+        .into_iter()
+        .filter(|webhook| webhook.events.contains(&event))
+        .map(|webhook| NewDelivery {
+            webhook_id: webhook.id,
+            url: webhook.url,
+            event,
+            payload: payload.clone(),
+        })
+        .collect::<Vec<NewDelivery>>();
+
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+
+    state
+        .webhook_repository
+        .enqueue_deliveries(&deliveries)
+        .await?;
+
+    deliver_due_deliveries(state).await
+}
+
+/// Attempt every delivery that is due. A delivery that fails is retried with a delay that
+/// doubles every attempt, and given up on after the maximum number of attempts. Deliveries are
+/// claimed for a while before they are attempted, so instances do not attempt the same one.
+pub(crate) async fn deliver_due_deliveries(state: &AppState) -> Result<(), String> {
+    loop {
+        let deliveries = state
+            .webhook_repository
+            .claim_due_deliveries(DELIVERY_BATCH_SIZE, DELIVERY_LEASE)
+            .await?;
+
+        for delivery in &deliveries {
+            let secret = match delivery.webhook_id {
+                Some(_) => delivery.secret.as_deref(),
+                None => state.webhooks.secret.as_deref(),
+            };
+
+            let attempt = deliver(delivery, secret).await;
+            let attempts = delivery.attempts + 1;
+
+            let (status, next_attempt_at) = match (&attempt.error, retry_delay(attempts)) {
+                (None, _) => {
+                    info!("delivered {} to webhook {}", delivery.id, delivery.url);
+                    (DeliveryStatus::Delivered, attempt.attempted_at)
+                }
+                (Some(e), Some(delay)) => {
+                    warn!(
+                        "unable to deliver {} to webhook {}, retrying in {} minutes, {}",
+                        delivery.id,
+                        delivery.url,
+                        delay.num_minutes(),
+                        e
+                    );
+                    (DeliveryStatus::Pending, attempt.attempted_at + delay)
+                }
+                (Some(e), None) => {
+                    warn!(
+                        "unable to deliver {} to webhook {} after {} attempts, giving up, {}",
+                        delivery.id, delivery.url, attempts, e
+                    );
+                    (DeliveryStatus::Failed, attempt.attempted_at)
+                }
+            };
+
+            state
+                .webhook_repository
+                .record_attempt(delivery.id, &attempt, status, next_attempt_at)
+                .await?;
+        }
+
+        if (deliveries.len() as i64) < DELIVERY_BATCH_SIZE {
+            break;
+        }
+    }
+
+    state
+        .webhook_repository
+        .prune_deliveries(Utc::now() - DELIVERY_RETENTION)
+        .await
+}
+
+/// The delay before retrying a delivery that failed for the given number of times, `None`
+/// when it is given up on
+fn retry_delay(attempts: i32) -> Option<TimeDelta> {
+    if attempts >= MAX_DELIVERY_ATTEMPTS {
+        return None;
+    }
+
+    Some(
+        (1..attempts)
+            .fold(INITIAL_DELIVERY_RETRY_DELAY, |delay, _| delay * 2)
+            .min(MAX_DELIVERY_RETRY_DELAY),
+    )
+}
+
+/// Dispatch that prices were ingested without waiting for the webhooks to respond
+pub(crate) fn dispatch_prices_ingested(
+    state: &AppState,
+    provider: &dyn ElectricityPriceProvider,
+    prices: &[PricePoint],
+) {
+    let (Some(first), Some(last)) = (prices.first(), prices.last()) else {
+        return;
+    };
+
+    let data = json!({
+        "provider": provider.name(),
+        "area": provider.area(),
+        "prices": prices.len(),
+        "starts_at": first.moment,
+        "ends_at": last.moment + TimeDelta::hours(1),
+    });
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = dispatch(&state, WebhookEvent::PricesIngested, data).await {
+            warn!("unable to dispatch ingested prices, {}", e);
+        }
+    });
+}
+
+/// Dispatch the events that depend on time: windows of devices that started and the price
+/// of the current hour exceeding the alert threshold. Every occurrence is dispatched once.
+pub(crate) async fn dispatch_scheduled_events(state: &AppState) -> Result<(), String> {
+    let webhooks = all_webhooks(state).await?;
+    let subscribed = |event: WebhookEvent| {
+        webhooks
+            .iter()
+            .any(|webhook| webhook.events.contains(&event))
+    };
+
+    let now = Utc::now();
+
+    if subscribed(WebhookEvent::WindowStart) {
+        dispatch_window_starts(state, now).await?;
+    }
+
+    if let (Some(threshold), true) = (
+        state.settings().pricing.price_alert_threshold,
+        subscribed(WebhookEvent::PriceAboveThreshold),
+    ) {
+        let Some(current_price) =
+            current_price(state, now, state.price_area(None).as_deref()).await?
+        else {
+            return Ok(());
+        };
+
+        if current_price.price > threshold
+            && state
+                .webhook_repository
+                .mark_dispatched(&format!(
+                    "{}:{}",
+                    WebhookEvent::PriceAboveThreshold.as_str(),
+                    current_price.moment.to_rfc3339()
+                ))
+                .await?
+        {
+            let mut data = json!(current_price);
+            data["threshold"] = json!(threshold);
+
+            dispatch(state, WebhookEvent::PriceAboveThreshold, data).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn dispatch_window_starts(state: &AppState, now: DateTime<Utc>) -> Result<(), String> {
+    // plans start at the next whole hour, so a window that just started is planned before it
+    for (device, _, next_run) in plan_all_devices(state, now - WINDOW_START_GRACE).await? {
+        let Some(next_run) = next_run else {
+            continue;
+        };
+
+        for window in next_run.windows {
+            let starts_at = window.starts_at.to_utc();
+
+            if now < starts_at || starts_at + WINDOW_START_GRACE <= now {
+                continue;
+            }
+
+            let key = format!(
+                "{}:{}:{}",
+                WebhookEvent::WindowStart.as_str(),
+                device.id,
+                starts_at.to_rfc3339()
+            );
+
+            if !state.webhook_repository.mark_dispatched(&key).await? {
+                continue;
+            }
+
+            dispatch(
+                state,
+                WebhookEvent::WindowStart,
+                json!({
+                    "device_id": device.id,
+                    "device_name": device.name,
+                    "window": window,
+                }),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Post the payload of a delivery to its webhook, signed with the secret
+async fn deliver(delivery: &DueDelivery, secret: Option<&str>) -> DeliveryAttempt {
+    let attempted_at = Utc::now();
+    let body = serde_json::to_vec(&delivery.payload.0).expect("a JSON value serializes");
+
+    let mut request = Client::new()
+        .post(&delivery.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(DELIVERY_ID_HEADER, delivery.id);
+
+    if let Some(secret) = secret {
+        let timestamp = attempted_at.timestamp();
+
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+    }
+
+    let response = request.body(body).send().await;
+
+    DeliveryAttempt {
+        attempted_at,
+        status_code: response
+            .as_ref()
+            .ok()
+            .map(|response| response.status().as_u16() as i32),
+        error: response
+            .and_then(|response| response.error_for_status())
+            .err()
+            .map(|e| e.to_string()),
+    }
+}
+
+/// The signature of a delivery: the HMAC-SHA256 of `<timestamp>.<body>` with the secret of the
+/// webhook. Signing the timestamp lets receivers refuse deliveries that are replayed later.
+fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// A secret to sign the deliveries of a webhook with
+pub(crate) fn generate_secret() -> String {
+    random_hex(32)
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut random = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut random);
+    hex::encode(random)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let delays = (1..=MAX_DELIVERY_ATTEMPTS)
+            .map(|attempts| retry_delay(attempts).map(|delay| delay.num_minutes()))
+            .collect::<Vec<Option<i64>>>();
+
+        assert_eq!(
+            delays,
+            vec![
+                Some(1),
+                Some(2),
+                Some(4),
+                Some(8),
+                Some(16),
+                Some(32),
+                Some(60),
+                Some(60),
+                Some(60),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delivery_lease_covers_batch() {
+        assert!(
+            DELIVERY_LEASE
+                > TimeDelta::from_std(DELIVERY_TIMEOUT * DELIVERY_BATCH_SIZE as u32).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("secret", 1718000000, br#"{"event":"window_start"}"#),
+            "sha256=caf622f9bd4b55755a514c67b4ad06f4c967de2ae86fa99b57c96b914fc6c247"
+        );
+    }
+}